@@ -0,0 +1,65 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hashing of multi-column keys, a column at a time and a row at a time
+
+#[macro_use]
+extern crate criterion;
+extern crate datafusion;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::rc::Rc;
+
+use criterion::Criterion;
+
+use datafusion::relations::join::encode_key;
+use datafusion::relations::row_hash::hash_rows;
+use datafusion::types::*;
+
+const NUM_ROWS: usize = 100_000;
+
+fn keys() -> Vec<Value> {
+    let ids: Vec<i64> = (0..NUM_ROWS as i64).collect();
+    let names: Vec<String> = (0..NUM_ROWS).map(|i| format!("customer-{}", i % 1000)).collect();
+    vec![
+        Value::Column(Rc::new(Array::from(ids))),
+        Value::Column(Rc::new(Array::from(names))),
+        Value::Scalar(Rc::new(ScalarValue::Int32(42))),
+    ]
+}
+
+fn hash_columns(c: &mut Criterion) {
+    let keys = keys();
+    c.bench_function("hash rows by column", move |b| {
+        b.iter(|| hash_rows(&keys, NUM_ROWS, false))
+    });
+}
+
+fn hash_encoded_rows(c: &mut Criterion) {
+    let keys = keys();
+    c.bench_function("hash encoded rows", move |b| {
+        b.iter(|| {
+            (0..NUM_ROWS)
+                .map(|i| {
+                    let mut hasher = DefaultHasher::new();
+                    hasher.write(&encode_key(&keys, i, false).unwrap());
+                    hasher.finish()
+                }).collect::<Vec<u64>>()
+        })
+    });
+}
+
+criterion_group!(benches, hash_columns, hash_encoded_rows);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares compiled and interpreted evaluation of an arithmetic-heavy projection
+
+#[macro_use]
+extern crate criterion;
+extern crate arrow;
+extern crate datafusion;
+
+use std::rc::Rc;
+
+use criterion::Criterion;
+
+use arrow::datatypes::*;
+use datafusion::datasources::common::*;
+use datafusion::exec::*;
+use datafusion::logical::*;
+use datafusion::types::*;
+
+const NUM_ROWS: usize = 1_000_000;
+
+fn binary(left: Expr, op: Operator, right: Expr) -> Expr {
+    Expr::BinaryExpr {
+        left: Rc::new(left),
+        op,
+        right: Rc::new(right),
+    }
+}
+
+/// (a * 2.0 + b) * (a - b) / 3.0
+fn expr() -> Expr {
+    let a = || Expr::Column(0);
+    let b = || Expr::Column(1);
+    let lit = |n| Expr::Literal(ScalarValue::Float64(n));
+    binary(
+        binary(
+            binary(binary(a(), Operator::Multiply, lit(2.0)), Operator::Plus, b()),
+            Operator::Multiply,
+            binary(a(), Operator::Minus, b()),
+        ),
+        Operator::Divide,
+        lit(3.0),
+    )
+}
+
+fn batch() -> DefaultRecordBatch {
+    let a: Vec<f64> = (0..NUM_ROWS).map(|i| i as f64).collect();
+    let b: Vec<f64> = (0..NUM_ROWS).map(|i| (i % 100) as f64).collect();
+    DefaultRecordBatch {
+        schema: Rc::new(schema()),
+        data: vec![
+            Value::Column(Rc::new(Array::from(a))),
+            Value::Column(Rc::new(Array::from(b))),
+        ],
+        row_count: NUM_ROWS,
+    }
+}
+
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("a", DataType::Float64, false),
+        Field::new("b", DataType::Float64, false),
+    ])
+}
+
+fn bench_projection(c: &mut Criterion, name: &str, jit: bool) {
+    let mut ctx = ExecutionContext::local();
+    ctx.set_jit(jit);
+    let f = compile_scalar_expr(&ctx, &expr(), &schema())
+        .unwrap()
+        .get_func();
+    let batch = batch();
+    c.bench_function(name, move |b| b.iter(|| f(&batch).unwrap()));
+}
+
+fn projection_interpreted(c: &mut Criterion) {
+    bench_projection(c, "arithmetic projection (interpreted)", false)
+}
+
+fn projection_compiled(c: &mut Criterion) {
+    bench_projection(c, "arithmetic projection (compiled)", true)
+}
+
+criterion_group!(benches, projection_interpreted, projection_compiled);
+criterion_main!(benches);
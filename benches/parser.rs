@@ -0,0 +1,130 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tokenizing and parsing small, medium and pathological queries. The parser doesn't support
+//! IN lists, so a 10k item IN list is only tokenized, and parsing is measured on the OR chain
+//! that it is equivalent to. Criterion reports a regression when a benchmark is slower than
+//! its previous run; to compare a change against master, run
+//! `cargo bench --bench parser -- --save-baseline master` on master and then
+//! `cargo bench --bench parser -- --baseline master` on the branch.
+
+#[macro_use]
+extern crate criterion;
+extern crate datafusion;
+
+use criterion::Criterion;
+
+use datafusion::sqlparser::Parser;
+use datafusion::sqltokenizer::Tokenizer;
+
+const SMALL: &'static str = "SELECT id, name FROM people WHERE id = 1";
+
+const MEDIUM: &'static str = "SELECT orders.order_id, customers.name, \
+                              CAST(orders.amount AS DOUBLE) * 1.2 \
+                              FROM orders JOIN customers ON orders.customer_id = customers.id \
+                              WHERE orders.amount > 100 AND customers.state = 'CO' \
+                              OR customers.state IS NULL \
+                              GROUP BY orders.order_id, customers.name \
+                              ORDER BY orders.order_id DESC LIMIT 100";
+
+const LIST_LEN: usize = 10_000;
+
+/// A 10k item IN list
+fn in_list() -> String {
+    let items: Vec<String> = (0..LIST_LEN).map(|i| i.to_string()).collect();
+    format!("SELECT id FROM t WHERE id IN ({})", items.join(", "))
+}
+
+/// The OR chain that a 10k item IN list is equivalent to
+fn or_chain() -> String {
+    let terms: Vec<String> = (0..LIST_LEN).map(|i| format!("id = {}", i)).collect();
+    format!("SELECT id FROM t WHERE {}", terms.join(" OR "))
+}
+
+/// About a megabyte of generated SQL, like the queries that reporting tools write out with
+/// every column quoted
+fn generated() -> String {
+    let columns: Vec<String> = (0..40_000)
+        .map(|i| format!("\"col_{}\" * 1.5 AS c{}", i, i))
+        .collect();
+    format!("SELECT {} FROM t WHERE name = 'generated'", columns.join(", "))
+}
+
+/// Function calls nested `depth` deep
+fn nested(depth: usize) -> String {
+    format!(
+        "SELECT {}id{} FROM t",
+        "f(".repeat(depth),
+        ")".repeat(depth)
+    )
+}
+
+/// An expression in `depth` levels of parentheses
+fn parenthesized(depth: usize) -> String {
+    format!(
+        "SELECT {}id + 1{} FROM t",
+        "(".repeat(depth),
+        ")".repeat(depth)
+    )
+}
+
+fn bench_tokenize(c: &mut Criterion, name: &str, sql: String) {
+    c.bench_function(name, move |b| {
+        b.iter(|| Tokenizer::new(&sql).tokenize().unwrap())
+    });
+}
+
+fn bench_parse(c: &mut Criterion, name: &str, sql: String) {
+    c.bench_function(name, move |b| {
+        b.iter(|| Parser::parse_sql(sql.clone()).unwrap())
+    });
+}
+
+fn bench_parse_iterative(c: &mut Criterion, name: &str, sql: String) {
+    c.bench_function(name, move |b| {
+        b.iter(|| Parser::parse_sql_iterative(sql.clone()).unwrap())
+    });
+}
+
+fn tokenize(c: &mut Criterion) {
+    bench_tokenize(c, "tokenize small", SMALL.to_string());
+    bench_tokenize(c, "tokenize medium", MEDIUM.to_string());
+    bench_tokenize(c, "tokenize 10k item IN list", in_list());
+    bench_tokenize(c, "tokenize 1MB generated query", generated());
+}
+
+fn parse(c: &mut Criterion) {
+    bench_parse(c, "parse small", SMALL.to_string());
+    bench_parse(c, "parse medium", MEDIUM.to_string());
+    bench_parse(c, "parse 10k term OR chain", or_chain());
+    // deeper nesting overflows the stack of the recursive parser
+    bench_parse(c, "parse 100 nested calls", nested(100));
+    bench_parse(c, "parse 100 nested parens", parenthesized(100));
+}
+
+fn parse_iterative(c: &mut Criterion) {
+    bench_parse_iterative(c, "parse iterative medium", MEDIUM.to_string());
+    bench_parse_iterative(c, "parse iterative 100 nested calls", nested(100));
+    bench_parse_iterative(c, "parse iterative 10k nested calls", nested(10_000));
+    bench_parse_iterative(c, "parse iterative 100 nested parens", parenthesized(100));
+    bench_parse_iterative(c, "parse iterative 10k nested parens", parenthesized(10_000));
+}
+
+criterion_group! {
+    name = benches;
+    // parsing is fast and stable enough that small changes are worth reporting
+    config = Criterion::default().noise_threshold(0.02);
+    targets = tokenize, parse, parse_iterative
+}
+criterion_main!(benches);
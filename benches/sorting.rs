@@ -0,0 +1,80 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sorting rows by several keys, compared as encoded rows and key by key
+
+#[macro_use]
+extern crate criterion;
+extern crate datafusion;
+
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use criterion::Criterion;
+
+use datafusion::exec::value_at;
+use datafusion::relations::row_format::encode_rows;
+use datafusion::types::*;
+
+const NUM_ROWS: usize = 100_000;
+
+fn keys() -> Vec<Value> {
+    let groups: Vec<i32> = (0..NUM_ROWS as i32).map(|i| i % 100).collect();
+    let names: Vec<String> = (0..NUM_ROWS).map(|i| format!("name-{}", i % 997)).collect();
+    let scores: Vec<f64> = (0..NUM_ROWS).map(|i| (i % 101) as f64 / 7.0).collect();
+    vec![
+        Value::Column(Rc::new(Array::from(groups))),
+        Value::Column(Rc::new(Array::from(names))),
+        Value::Column(Rc::new(Array::from(scores))),
+    ]
+}
+
+fn sort_encoded_rows(c: &mut Criterion) {
+    let keys = keys();
+    c.bench_function("sort by encoded rows", move |b| {
+        b.iter(|| {
+            let rows = encode_rows(&keys, &[true, false, true], NUM_ROWS);
+            let mut indices: Vec<usize> = (0..NUM_ROWS).collect();
+            indices.sort_by(|a, b| rows.row(*a).cmp(rows.row(*b)));
+            indices
+        })
+    });
+}
+
+fn sort_by_comparators(c: &mut Criterion) {
+    let keys = keys();
+    c.bench_function("sort by comparing each key", move |b| {
+        b.iter(|| {
+            let rows: Vec<Vec<ScalarValue>> = (0..NUM_ROWS)
+                .map(|i| keys.iter().map(|k| value_at(k, i)).collect())
+                .collect();
+            let mut indices: Vec<usize> = (0..NUM_ROWS).collect();
+            indices.sort_by(|a, b| {
+                for (k, asc) in [true, false, true].iter().enumerate() {
+                    let ordering = rows[*a][k]
+                        .partial_cmp(&rows[*b][k])
+                        .unwrap_or(Ordering::Equal);
+                    if ordering != Ordering::Equal {
+                        return if *asc { ordering } else { ordering.reverse() };
+                    }
+                }
+                Ordering::Equal
+            });
+            indices
+        })
+    });
+}
+
+criterion_group!(benches, sort_encoded_rows, sort_by_comparators);
+criterion_main!(benches);
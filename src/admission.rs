@@ -0,0 +1,226 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Admission control, which limits how many queries a server executes at once
+//!
+//! A server shares one `AdmissionController` between the sessions of all of its threads with
+//! `ExecutionContext::set_admission_controller`. Queries beyond the concurrency limit wait in
+//! a queue, highest priority first and then in the order they arrived. Queries that find the
+//! queue full, or that wait longer than the queue timeout, fail with
+//! `DataFusionError::ServerBusy` so that clients know to retry later.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::errors::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdmissionConfig {
+    /// Maximum number of queries that execute at once
+    pub max_concurrent: usize,
+    /// Maximum number of queries that wait for others to finish
+    pub max_queued: usize,
+    /// Longest time a query waits in the queue. Queries wait indefinitely by default.
+    pub queue_timeout: Option<Duration>,
+}
+
+/// Limits the queries that execute at once, queueing the rest by priority
+pub struct AdmissionController {
+    config: AdmissionConfig,
+    state: Mutex<AdmissionState>,
+    changed: Condvar,
+}
+
+struct AdmissionState {
+    running: usize,
+    /// Waiting queries as (negated priority, arrival), so the first is the next to run
+    queue: BTreeSet<(i64, u64)>,
+    arrivals: u64,
+}
+
+impl AdmissionController {
+    pub fn new(config: AdmissionConfig) -> Self {
+        AdmissionController {
+            config,
+            state: Mutex::new(AdmissionState {
+                running: 0,
+                queue: BTreeSet::new(),
+                arrivals: 0,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Wait until a query with the given priority can execute. Queries with higher priorities
+    /// execute before queries with lower ones. The query executes until the permit is dropped.
+    pub fn admit(controller: &Arc<AdmissionController>, priority: i32) -> Result<AdmissionPermit> {
+        let config = &controller.config;
+        let mut state = controller.state.lock().unwrap();
+        if state.running < config.max_concurrent && state.queue.is_empty() {
+            state.running += 1;
+            return Ok(AdmissionPermit {
+                controller: controller.clone(),
+            });
+        }
+        if state.queue.len() >= config.max_queued {
+            return Err(DataFusionError::ServerBusy(format!(
+                "{} queries are executing and {} are queued",
+                state.running,
+                state.queue.len()
+            )));
+        }
+
+        let ticket = (-(priority as i64), state.arrivals);
+        state.arrivals += 1;
+        state.queue.insert(ticket);
+        let deadline = config.queue_timeout.map(|t| Instant::now() + t);
+        loop {
+            if state.running < config.max_concurrent && state.queue.iter().next() == Some(&ticket)
+            {
+                state.queue.remove(&ticket);
+                state.running += 1;
+                // the next query in the queue may also be able to run
+                controller.changed.notify_all();
+                return Ok(AdmissionPermit {
+                    controller: controller.clone(),
+                });
+            }
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        state.queue.remove(&ticket);
+                        controller.changed.notify_all();
+                        return Err(DataFusionError::ServerBusy(format!(
+                            "query waited longer than {:?} to execute",
+                            config.queue_timeout.unwrap()
+                        )));
+                    }
+                    controller
+                        .changed
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0
+                }
+                None => controller.changed.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Get the number of queries that are executing
+    pub fn running(&self) -> usize {
+        self.state.lock().unwrap().running
+    }
+
+    /// Get the number of queries that are waiting to execute
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+}
+
+/// Permission for a query to execute, which is given back when dropped
+pub struct AdmissionPermit {
+    controller: Arc<AdmissionController>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.controller.state.lock().unwrap().running -= 1;
+        self.controller.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    fn controller(max_concurrent: usize, max_queued: usize) -> Arc<AdmissionController> {
+        Arc::new(AdmissionController::new(AdmissionConfig {
+            max_concurrent,
+            max_queued,
+            queue_timeout: None,
+        }))
+    }
+
+    fn wait_until_queued(controller: &AdmissionController, n: usize) {
+        while controller.queued() < n {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_rejects_queries_beyond_queue() {
+        let c = controller(1, 1);
+        let permit = AdmissionController::admit(&c, 0).unwrap();
+        assert_eq!(1, c.running());
+
+        let waiter = {
+            let c = c.clone();
+            thread::spawn(move || AdmissionController::admit(&c, 0).map(|_| ()))
+        };
+        wait_until_queued(&c, 1);
+        match AdmissionController::admit(&c, 0) {
+            Err(DataFusionError::ServerBusy(msg)) => {
+                assert_eq!("1 queries are executing and 1 are queued", msg)
+            }
+            _ => panic!("expected the server to be busy"),
+        }
+
+        drop(permit);
+        waiter.join().unwrap().unwrap();
+        assert_eq!(0, c.running());
+        assert_eq!(0, c.queued());
+    }
+
+    #[test]
+    fn test_admits_by_priority() {
+        let c = controller(1, 10);
+        let permit = AdmissionController::admit(&c, 0).unwrap();
+        let (tx, rx) = channel();
+        let mut waiters = vec![];
+        for (n, &priority) in [1, 5, 1].iter().enumerate() {
+            let c = c.clone();
+            let tx = tx.clone();
+            waiters.push(thread::spawn(move || {
+                let _permit = AdmissionController::admit(&c, priority).unwrap();
+                tx.send(n).unwrap();
+            }));
+            wait_until_queued(&c, n + 1);
+        }
+
+        drop(permit);
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+        assert_eq!(vec![1, 0, 2], rx.try_iter().collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_queue_timeout() {
+        let c = Arc::new(AdmissionController::new(AdmissionConfig {
+            max_concurrent: 1,
+            max_queued: 1,
+            queue_timeout: Some(Duration::from_millis(10)),
+        }));
+        let _permit = AdmissionController::admit(&c, 0).unwrap();
+        match AdmissionController::admit(&c, 0) {
+            Err(DataFusionError::ServerBusy(_)) => {}
+            _ => panic!("expected the query to time out"),
+        }
+        assert_eq!(0, c.queued());
+    }
+}
@@ -0,0 +1,674 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Analyzer passes check and rewrite a logical plan after it has been planned from SQL and
+//! before it is optimized. The built-in passes reject plans that are invalid but that the
+//! planner let through, such as non-boolean filter predicates, so the error is reported before
+//! any data is read. Applications can register their own passes on the execution context.
+
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::errors::*;
+use super::logical::*;
+use super::types::*;
+
+/// A stage that checks or rewrites a logical plan before it is optimized
+pub trait AnalyzerPass {
+    /// Name of the pass, used in error messages
+    fn name(&self) -> &str;
+
+    /// Check the plan, returning it unchanged or rewritten, or fail if it is invalid
+    fn analyze(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>>;
+}
+
+/// Runs the analyzer passes over a plan in the order they were added
+pub struct Analyzer {
+    passes: Vec<Rc<AnalyzerPass>>,
+}
+
+impl Analyzer {
+    /// Create an analyzer with the built-in passes
+    pub fn new() -> Self {
+        Analyzer {
+            passes: vec![Rc::new(TypeCheck), Rc::new(SubqueryValidation)],
+        }
+    }
+
+    /// Add a pass that runs after the passes already added
+    pub fn add_pass(&mut self, pass: Rc<AnalyzerPass>) {
+        self.passes.push(pass);
+    }
+
+    /// Get the names of the passes in the order they run
+    pub fn pass_names(&self) -> Vec<String> {
+        self.passes.iter().map(|p| p.name().to_string()).collect()
+    }
+
+    pub fn analyze(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        let mut plan = plan.clone();
+        for pass in &self.passes {
+            plan = pass.analyze(&plan).map_err(|e| match e {
+                DataFusionError::Plan(msg) => {
+                    DataFusionError::Plan(format!("{} (in analyzer pass '{}')", msg, pass.name()))
+                }
+                other => other,
+            })?;
+        }
+        Ok(plan)
+    }
+}
+
+/// Checks that column references are in range and that predicates are boolean
+pub struct TypeCheck;
+
+impl AnalyzerPass for TypeCheck {
+    fn name(&self) -> &str {
+        "type_check"
+    }
+
+    fn analyze(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        check_types(plan)?;
+        Ok(plan.clone())
+    }
+}
+
+fn check_types(plan: &LogicalPlan) -> Result<()> {
+    for input in plan.inputs() {
+        check_types(input)?;
+    }
+    match *plan {
+        LogicalPlan::Selection {
+            ref expr,
+            ref input,
+        } => {
+            check_expr(expr, input.schema())?;
+            check_predicate("Filter predicate", expr, input.schema())
+        }
+        LogicalPlan::Projection {
+            ref expr,
+            ref input,
+            ..
+        }
+        | LogicalPlan::Sort {
+            ref expr,
+            ref input,
+            ..
+        } => {
+            for e in expr {
+                check_expr(e, input.schema())?;
+            }
+            Ok(())
+        }
+        LogicalPlan::Aggregate {
+            ref input,
+            ref group_expr,
+            ref aggr_expr,
+            ..
+        } => {
+            for e in group_expr.iter().chain(aggr_expr.iter()) {
+                check_expr(e, input.schema())?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_expr(expr: &Expr, schema: &Schema) -> Result<()> {
+    match *expr {
+        Expr::Column(i) if i >= schema.columns().len() => Err(DataFusionError::Plan(format!(
+            "Column index {} is out of range for a relation with {} columns",
+            i,
+            schema.columns().len()
+        ))),
+        Expr::Column(_) | Expr::Literal(_) | Expr::OuterColumn { .. } => Ok(()),
+        Expr::BinaryExpr {
+            ref left,
+            ref op,
+            ref right,
+        } => {
+            check_expr(left, schema)?;
+            check_expr(right, schema)?;
+            match *op {
+                Operator::And | Operator::Or => {
+                    check_predicate(&format!("Operand of {:?}", op), left, schema)?;
+                    check_predicate(&format!("Operand of {:?}", op), right, schema)
+                }
+                _ => Ok(()),
+            }
+        }
+        Expr::Not(ref e) => {
+            check_expr(e, schema)?;
+            check_predicate("Operand of NOT", e, schema)
+        }
+        Expr::IsNull(ref e)
+        | Expr::IsNotNull(ref e)
+        | Expr::Cast { expr: ref e, .. }
+        | Expr::Sort { expr: ref e, .. }
+        | Expr::QuantifiedComparison { expr: ref e, .. } => check_expr(e, schema),
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            for &(ref when, ref then) in when_then {
+                check_expr(when, schema)?;
+                check_predicate("CASE condition", when, schema)?;
+                check_expr(then, schema)?;
+            }
+            match *else_expr {
+                Some(ref e) => check_expr(e, schema),
+                None => Ok(()),
+            }
+        }
+        Expr::ScalarFunction { ref args, .. } | Expr::AggregateFunction { ref args, .. } => {
+            for arg in args {
+                check_expr(arg, schema)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Check that an expression is boolean, where a null literal is an unknown boolean
+fn check_predicate(what: &str, expr: &Expr, schema: &Schema) -> Result<()> {
+    if let Expr::Literal(ScalarValue::Null) = *expr {
+        return Ok(());
+    }
+    match expr.get_type(schema) {
+        DataType::Boolean => Ok(()),
+        other => Err(DataFusionError::Plan(format!(
+            "{} must be Boolean but was {:?}",
+            what, other
+        ))),
+    }
+}
+
+/// Checks that subqueries used as expressions return a single column and only reference
+/// columns that exist in the outer query
+pub struct SubqueryValidation;
+
+impl AnalyzerPass for SubqueryValidation {
+    fn name(&self) -> &str {
+        "subquery_validation"
+    }
+
+    fn analyze(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+        check_subqueries(plan)?;
+        Ok(plan.clone())
+    }
+}
+
+fn check_subqueries(plan: &LogicalPlan) -> Result<()> {
+    for input in plan.inputs() {
+        check_subqueries(input)?;
+    }
+    let (exprs, input): (Vec<&Expr>, &Rc<LogicalPlan>) = match *plan {
+        LogicalPlan::Selection {
+            ref expr,
+            ref input,
+        } => (vec![expr], input),
+        LogicalPlan::Projection {
+            ref expr,
+            ref input,
+            ..
+        } => (expr.iter().collect(), input),
+        _ => return Ok(()),
+    };
+    for expr in exprs {
+        check_subquery_expr(expr, input.schema())?;
+    }
+    Ok(())
+}
+
+fn check_subquery_expr(expr: &Expr, schema: &Schema) -> Result<()> {
+    match *expr {
+        Expr::QuantifiedComparison {
+            ref expr,
+            ref subquery,
+            ..
+        } => {
+            check_subquery_expr(expr, schema)?;
+            let columns = subquery.plan.schema().columns().len();
+            if columns != 1 {
+                return Err(DataFusionError::Plan(format!(
+                    "Subquery must return exactly one column but returns {}",
+                    columns
+                )));
+            }
+            if let Some(i) = subquery
+                .outer_columns
+                .iter()
+                .find(|&&i| i >= schema.columns().len())
+            {
+                return Err(DataFusionError::Plan(format!(
+                    "Subquery references outer column {} but the outer query has {} columns",
+                    i,
+                    schema.columns().len()
+                )));
+            }
+            check_subqueries(&subquery.plan)
+        }
+        Expr::BinaryExpr {
+            ref left,
+            ref right,
+            ..
+        } => {
+            check_subquery_expr(left, schema)?;
+            check_subquery_expr(right, schema)
+        }
+        Expr::IsNull(ref e)
+        | Expr::IsNotNull(ref e)
+        | Expr::Not(ref e)
+        | Expr::Cast { expr: ref e, .. } => check_subquery_expr(e, schema),
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            for &(ref when, ref then) in when_then {
+                check_subquery_expr(when, schema)?;
+                check_subquery_expr(then, schema)?;
+            }
+            match *else_expr {
+                Some(ref e) => check_subquery_expr(e, schema),
+                None => Ok(()),
+            }
+        }
+        Expr::ScalarFunction { ref args, .. } => {
+            for arg in args {
+                check_subquery_expr(arg, schema)?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check that a plan that was built outside the query planner, such as one submitted by a
+/// client, is consistent: every expression only references columns that its input has, and
+/// every relation has the column types that its expressions and inputs produce. The physical
+/// planner relies on both, so an inconsistent plan would otherwise panic when it is executed.
+pub fn validate_plan(plan: &LogicalPlan) -> Result<()> {
+    validate_node(plan, None)
+}
+
+/// Validate a relation, where `outer` is the schema of the row that outer columns refer to
+fn validate_node(plan: &LogicalPlan, outer: Option<&Schema>) -> Result<()> {
+    match *plan {
+        LogicalPlan::LateralJoin {
+            ref left,
+            ref subquery,
+            ..
+        } => {
+            validate_node(left, outer)?;
+            validate_node(subquery, Some(left.schema()))?;
+        }
+        _ => {
+            for input in plan.inputs() {
+                validate_node(input, outer)?;
+            }
+        }
+    }
+    let expected: Vec<Option<DataType>> = match *plan {
+        LogicalPlan::Selection {
+            ref expr,
+            ref input,
+        } => {
+            validate_expr(expr, input.schema(), outer)?;
+            return check_predicate("Filter predicate", expr, input.schema());
+        }
+        LogicalPlan::Projection {
+            ref expr,
+            ref input,
+            ..
+        } => {
+            for e in expr {
+                validate_expr(e, input.schema(), outer)?;
+            }
+            expr_types(expr.iter(), input.schema())
+        }
+        LogicalPlan::Aggregate {
+            ref input,
+            ref group_expr,
+            ref aggr_expr,
+            ..
+        } => {
+            for e in group_expr.iter().chain(aggr_expr.iter()) {
+                validate_expr(e, input.schema(), outer)?;
+            }
+            expr_types(group_expr.iter().chain(aggr_expr.iter()), input.schema())
+        }
+        LogicalPlan::Sort {
+            ref expr,
+            ref input,
+            ..
+        } => {
+            for e in expr {
+                validate_expr(e, input.schema(), outer)?;
+            }
+            column_types(input.schema())
+        }
+        LogicalPlan::Deduplicate {
+            ref input,
+            ref keys,
+            ref order_by,
+            ..
+        } => {
+            for e in keys.iter().chain(order_by.iter()) {
+                validate_expr(e, input.schema(), outer)?;
+            }
+            column_types(input.schema())
+        }
+        LogicalPlan::Window {
+            ref input,
+            ref window_expr,
+            ..
+        } => {
+            for w in window_expr {
+                for e in w.args.iter().chain(&w.partition_by).chain(&w.order_by) {
+                    validate_expr(e, input.schema(), outer)?;
+                }
+            }
+            let mut types = column_types(input.schema());
+            types.extend(window_expr.iter().map(|w| Some(w.return_type.clone())));
+            types
+        }
+        LogicalPlan::Limit { ref input, .. } | LogicalPlan::Sample { ref input, .. } => {
+            column_types(input.schema())
+        }
+        LogicalPlan::Join {
+            ref left,
+            ref right,
+            ref join_type,
+            ref on,
+            ..
+        } => {
+            for &(l, r) in on {
+                check_column(l, left.schema())?;
+                check_column(r, right.schema())?;
+            }
+            let mut types = column_types(left.schema());
+            if *join_type == JoinType::Inner {
+                types.extend(column_types(right.schema()));
+            }
+            types
+        }
+        LogicalPlan::LateralJoin {
+            ref left,
+            ref subquery,
+            ..
+        } => {
+            let mut types = column_types(left.schema());
+            types.extend(column_types(subquery.schema()));
+            types
+        }
+        LogicalPlan::Union {
+            ref inputs,
+            ref schema,
+        } => {
+            for input in inputs {
+                check_schema("Union input", input.schema(), &column_types(schema))?;
+            }
+            return Ok(());
+        }
+        LogicalPlan::TableScan {
+            ref schema,
+            ref projection,
+            ..
+        }
+        | LogicalPlan::CsvFile {
+            ref schema,
+            ref projection,
+            ..
+        }
+        | LogicalPlan::NdJsonFile {
+            ref schema,
+            ref projection,
+            ..
+        }
+        | LogicalPlan::ParquetFile {
+            ref schema,
+            ref projection,
+            ..
+        } => {
+            if let Some(ref projection) = *projection {
+                for &i in projection {
+                    check_column(i, schema)?;
+                }
+            }
+            return Ok(());
+        }
+        LogicalPlan::EmptyRelation { .. }
+        | LogicalPlan::MemTable { .. }
+        | LogicalPlan::StreamScan { .. }
+        | LogicalPlan::GenerateSeries { .. } => return Ok(()),
+    };
+    check_schema("Relation", plan.schema(), &expected)
+}
+
+fn check_column(index: usize, schema: &Schema) -> Result<()> {
+    if index < schema.columns().len() {
+        Ok(())
+    } else {
+        Err(DataFusionError::Plan(format!(
+            "Column index {} is out of range for a relation with {} columns",
+            index,
+            schema.columns().len()
+        )))
+    }
+}
+
+/// Check that a schema has the expected column types, where None is the type of a null
+/// literal, which can have any type
+fn check_schema(what: &str, schema: &Schema, expected: &[Option<DataType>]) -> Result<()> {
+    let columns = schema.columns();
+    if columns.len() != expected.len() {
+        return Err(DataFusionError::Plan(format!(
+            "{} has {} columns but its inputs produce {}",
+            what,
+            columns.len(),
+            expected.len()
+        )));
+    }
+    for (field, data_type) in columns.iter().zip(expected) {
+        match *data_type {
+            Some(ref data_type) if data_type != field.data_type() => {
+                return Err(DataFusionError::Plan(format!(
+                    "{} declares column '{}' as {:?} but it is {:?}",
+                    what,
+                    field.name(),
+                    field.data_type(),
+                    data_type
+                )))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn column_types(schema: &Schema) -> Vec<Option<DataType>> {
+    schema
+        .columns()
+        .iter()
+        .map(|f| Some(f.data_type().clone()))
+        .collect()
+}
+
+fn expr_types<'a, I>(expr: I, schema: &Schema) -> Vec<Option<DataType>>
+where
+    I: Iterator<Item = &'a Expr>,
+{
+    expr.map(|e| match *e {
+        Expr::Literal(ScalarValue::Null) => None,
+        ref e => Some(e.get_type(schema)),
+    }).collect()
+}
+
+/// Check the column references of an expression and of any subqueries it contains
+fn validate_expr(expr: &Expr, schema: &Schema, outer: Option<&Schema>) -> Result<()> {
+    match *expr {
+        Expr::Column(i) => check_column(i, schema),
+        Expr::OuterColumn {
+            index,
+            ref data_type,
+        } => match outer {
+            Some(outer) => {
+                check_column(index, outer)?;
+                let column = outer.column(index);
+                if column.data_type() == data_type {
+                    Ok(())
+                } else {
+                    Err(DataFusionError::Plan(format!(
+                        "Outer column {} is {:?} but is referenced as {:?}",
+                        index,
+                        column.data_type(),
+                        data_type
+                    )))
+                }
+            }
+            None => Err(DataFusionError::Plan(format!(
+                "Outer column {} is not in a subquery",
+                index
+            ))),
+        },
+        Expr::Literal(_) => Ok(()),
+        Expr::BinaryExpr {
+            ref left,
+            ref right,
+            ..
+        } => {
+            validate_expr(left, schema, outer)?;
+            validate_expr(right, schema, outer)
+        }
+        Expr::IsNull(ref e)
+        | Expr::IsNotNull(ref e)
+        | Expr::Not(ref e)
+        | Expr::Cast { expr: ref e, .. }
+        | Expr::Sort { expr: ref e, .. } => validate_expr(e, schema, outer),
+        Expr::QuantifiedComparison {
+            ref expr,
+            ref subquery,
+            ..
+        } => {
+            validate_expr(expr, schema, outer)?;
+            for &i in &subquery.outer_columns {
+                check_column(i, schema)?;
+            }
+            validate_node(&subquery.plan, Some(schema))
+        }
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            if when_then.is_empty() {
+                return Err(DataFusionError::Plan(
+                    "CASE must have at least one WHEN clause".to_string(),
+                ));
+            }
+            for &(ref when, ref then) in when_then {
+                validate_expr(when, schema, outer)?;
+                validate_expr(then, schema, outer)?;
+            }
+            match *else_expr {
+                Some(ref e) => validate_expr(e, schema, outer),
+                None => Ok(()),
+            }
+        }
+        Expr::ScalarFunction { ref args, .. } | Expr::AggregateFunction { ref args, .. } => {
+            for arg in args {
+                validate_expr(arg, schema, outer)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> Rc<LogicalPlan> {
+        Rc::new(LogicalPlan::EmptyRelation {
+            schema: Rc::new(Schema::new(vec![
+                Field::new("id", DataType::Int32, false),
+                Field::new("active", DataType::Boolean, true),
+            ])),
+        })
+    }
+
+    fn selection(expr: Expr) -> Rc<LogicalPlan> {
+        Rc::new(LogicalPlan::Selection {
+            expr,
+            input: input(),
+        })
+    }
+
+    /// Rejects every plan that contains a limit
+    struct NoLimits;
+
+    impl AnalyzerPass for NoLimits {
+        fn name(&self) -> &str {
+            "no_limits"
+        }
+
+        fn analyze(&self, plan: &Rc<LogicalPlan>) -> Result<Rc<LogicalPlan>> {
+            match **plan {
+                LogicalPlan::Limit { .. } => {
+                    Err(DataFusionError::Plan("LIMIT is not allowed".to_string()))
+                }
+                _ => Ok(plan.clone()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_type_check() {
+        let analyzer = Analyzer::new();
+        assert!(analyzer.analyze(&selection(Expr::Column(1))).is_ok());
+        assert!(analyzer
+            .analyze(&selection(
+                Expr::Column(0).gt(&Expr::Literal(ScalarValue::Int32(1)))
+            ))
+            .is_ok());
+
+        match analyzer.analyze(&selection(Expr::Column(0))) {
+            Err(DataFusionError::Plan(msg)) => assert_eq!(
+                "Filter predicate must be Boolean but was Int32 (in analyzer pass 'type_check')",
+                msg
+            ),
+            _ => panic!("Expected a plan error"),
+        }
+        assert!(analyzer.analyze(&selection(Expr::Column(2))).is_err());
+    }
+
+    #[test]
+    fn test_custom_pass() {
+        let mut analyzer = Analyzer::new();
+        analyzer.add_pass(Rc::new(NoLimits));
+        assert_eq!(
+            vec!["type_check", "subquery_validation", "no_limits"],
+            analyzer.pass_names()
+        );
+
+        let plan = Rc::new(LogicalPlan::Limit {
+            limit: 1,
+            schema: input().schema().clone(),
+            input: input(),
+        });
+        assert!(analyzer.analyze(&plan).is_err());
+        assert!(analyzer.analyze(&input()).is_ok());
+    }
+}
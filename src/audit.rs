@@ -0,0 +1,359 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Audit log of executed statements
+//!
+//! When an audit hook is set on an `ExecutionContext` it is called once for every statement
+//! that is executed, whether or not the statement succeeds, with the user, role and session
+//! that executed it. Statements are recorded in normalized form with their literals replaced
+//! by `?`, so the log doesn't hold the values that queries filter on.
+//!
+//! Queries are recorded when their results have been read, so that the event has the number
+//! of rows they returned and any error they failed with during execution.
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use arrow::datatypes::Schema;
+use json::JsonValue;
+
+use super::datasources::common::RecordBatch;
+use super::errors::*;
+use super::exec::SimpleRelation;
+use super::sqlast::ASTNode;
+
+/// Who statements are executed by, which is recorded with each audit event
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditSession {
+    pub user: String,
+    /// The role the user is acting in, if roles are used
+    pub role: Option<String>,
+    pub session_id: String,
+}
+
+/// A statement that was executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    /// None when no audit session has been set
+    pub session: Option<AuditSession>,
+    /// The normalized statement, or the statement as written if it couldn't be parsed
+    pub sql: String,
+    /// A hash of the normalized statement, for grouping events of the same query
+    pub fingerprint: Option<u64>,
+    /// Tables that the statement reads, creates or changes, in order of first reference
+    pub tables: Vec<String>,
+    /// Rows returned or written, when they are known. The rows of a query that failed are not
+    /// known, and a query whose results were not read to the end has the rows that were read.
+    pub rows: Option<usize>,
+    /// Time spent planning and executing the statement, which doesn't include the time between
+    /// planning a query and reading its results
+    pub duration: Duration,
+    /// The error the statement failed with
+    pub error: Option<String>,
+}
+
+impl AuditEvent {
+    /// Get the event as a JSON object, e.g. to write as one line of a log file
+    pub fn to_json(&self) -> JsonValue {
+        let mut event = JsonValue::new_object();
+        match self.session {
+            Some(ref session) => {
+                event["user"] = session.user.clone().into();
+                event["role"] = session.role.clone().into();
+                event["session_id"] = session.session_id.clone().into();
+            }
+            None => {
+                event["user"] = JsonValue::Null;
+                event["role"] = JsonValue::Null;
+                event["session_id"] = JsonValue::Null;
+            }
+        }
+        event["sql"] = self.sql.clone().into();
+        // as a string, because JSON numbers can't hold every 64 bit integer exactly
+        event["fingerprint"] = self.fingerprint.map(|f| format!("{:016x}", f)).into();
+        event["tables"] = JsonValue::Array(self.tables.iter().map(|t| t.clone().into()).collect());
+        event["rows"] = self.rows.into();
+        event["duration_ms"] = (self.duration.as_secs() as f64 * 1000.0
+            + self.duration.subsec_nanos() as f64 / 1e6)
+            .into();
+        event["error"] = self.error.clone().into();
+        event
+    }
+}
+
+/// Called with each statement that is executed
+pub type AuditHook = Rc<Fn(&AuditEvent)>;
+
+/// Records a query with the audit hook, given the rows it returned, the time it took to plan
+/// and execute and the error it failed with
+pub type AuditRecorder = Box<Fn(Option<usize>, Duration, Option<String>)>;
+
+/// Relation that records the query it executes once its results have been read to the end or
+/// it fails, or when it is dropped before then
+pub struct AuditingRelation {
+    input: Box<SimpleRelation>,
+    state: AuditState,
+}
+
+struct AuditState {
+    /// None once the query has been recorded
+    record: Option<AuditRecorder>,
+    planning: Duration,
+    start: Instant,
+    rows: usize,
+    error: Option<String>,
+}
+
+impl AuditState {
+    fn finish(&mut self) {
+        if let Some(record) = self.record.take() {
+            let rows = match self.error {
+                Some(_) => None,
+                None => Some(self.rows),
+            };
+            record(rows, self.planning + self.start.elapsed(), self.error.take());
+        }
+    }
+}
+
+impl AuditingRelation {
+    /// `planning` is the time it took to plan the query and `start` is when its execution
+    /// started
+    pub fn new(
+        input: Box<SimpleRelation>,
+        planning: Duration,
+        start: Instant,
+        record: AuditRecorder,
+    ) -> Self {
+        AuditingRelation {
+            input,
+            state: AuditState {
+                record: Some(record),
+                planning,
+                start,
+                rows: 0,
+                error: None,
+            },
+        }
+    }
+}
+
+struct AuditingIterator<'a> {
+    it: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    state: &'a mut AuditState,
+}
+
+impl<'a> Iterator for AuditingIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.it.next() {
+            Some(Ok(batch)) => {
+                self.state.rows += batch.num_rows();
+                Some(Ok(batch))
+            }
+            Some(Err(e)) => {
+                self.state.error = Some(e.to_string());
+                self.state.finish();
+                Some(Err(e))
+            }
+            None => {
+                self.state.finish();
+                None
+            }
+        }
+    }
+}
+
+impl SimpleRelation for AuditingRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        Box::new(AuditingIterator {
+            it: self.input.scan(),
+            state: &mut self.state,
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+impl Drop for AuditingRelation {
+    fn drop(&mut self) {
+        self.state.finish();
+    }
+}
+
+/// Get the names of the tables that a statement reads, creates or changes, in order of first
+/// reference. Qualified names are joined with periods.
+pub fn referenced_tables(ast: &ASTNode) -> Vec<String> {
+    let mut tables = vec![];
+    collect_tables(ast, false, &mut tables);
+    tables
+}
+
+fn add_table(tables: &mut Vec<String>, name: String) {
+    if !tables.contains(&name) {
+        tables.push(name);
+    }
+}
+
+/// `relation` is true where a name refers to a table rather than a column
+fn collect_tables(ast: &ASTNode, relation: bool, tables: &mut Vec<String>) {
+    match *ast {
+        ASTNode::SQLIdentifier(ref name) if relation => add_table(tables, name.clone()),
+        ASTNode::SQLCompoundIdentifier(ref parts) if relation => {
+            add_table(tables, parts.join("."))
+        }
+        ASTNode::SQLNested(ref expr) => collect_tables(expr, relation, tables),
+        ASTNode::SQLIsNull(ref expr)
+        | ASTNode::SQLIsNotNull(ref expr)
+        | ASTNode::SQLCast { ref expr, .. }
+        | ASTNode::SQLUnary { rex: ref expr, .. }
+        | ASTNode::SQLOrderBy { ref expr, .. } => collect_tables(expr, false, tables),
+        ASTNode::SQLBinaryExpr {
+            ref left,
+            ref right,
+            ..
+        } => {
+            collect_tables(left, false, tables);
+            collect_tables(right, false, tables);
+        }
+        ASTNode::SQLFunction { ref args, .. } => {
+            for arg in args {
+                collect_tables(arg, false, tables);
+            }
+        }
+        ASTNode::SQLWindowFunction {
+            ref args,
+            ref partition_by,
+            ref order_by,
+            ..
+        } => {
+            for expr in args.iter().chain(partition_by).chain(order_by) {
+                collect_tables(expr, false, tables);
+            }
+        }
+        ASTNode::SQLQuantifiedComparison {
+            ref expr,
+            ref subquery,
+            ..
+        } => {
+            collect_tables(expr, false, tables);
+            collect_tables(subquery, false, tables);
+        }
+        ASTNode::SQLSelect {
+            ref projection,
+            relation: ref from,
+            ref selection,
+            ref order_by,
+            ref group_by,
+            ref having,
+            ref qualify,
+            ref limit,
+            ..
+        } => {
+            if let Some(ref from) = *from {
+                collect_tables(from, true, tables);
+            }
+            let lists = [Some(projection), order_by.as_ref(), group_by.as_ref()];
+            for expr in lists.iter().filter_map(|l| *l).flat_map(|l| l.iter()) {
+                collect_tables(expr, false, tables);
+            }
+            for expr in [selection, having, qualify, limit].iter() {
+                if let Some(ref expr) = **expr {
+                    collect_tables(expr, false, tables);
+                }
+            }
+        }
+        ASTNode::SQLJoin {
+            ref left,
+            ref right,
+            ref on,
+            ..
+        } => {
+            collect_tables(left, true, tables);
+            collect_tables(right, true, tables);
+            collect_tables(on, false, tables);
+        }
+        ASTNode::SQLLateralJoin {
+            ref left,
+            ref subquery,
+            ref on,
+            ..
+        } => {
+            collect_tables(left, true, tables);
+            collect_tables(subquery, false, tables);
+            if let Some(ref on) = *on {
+                collect_tables(on, false, tables);
+            }
+        }
+        ASTNode::SQLTableSample { ref relation, .. }
+        | ASTNode::SQLTableSnapshot { ref relation, .. }
+        | ASTNode::SQLTableAlias { ref relation, .. } => collect_tables(relation, true, tables),
+        // `DESCRIBE t` describes a table
+        ASTNode::SQLDescribe(ref statement) => collect_tables(statement, true, tables),
+        ASTNode::SQLExplain { ref statement, .. } => collect_tables(statement, false, tables),
+        ASTNode::SQLCreateTable { ref name, .. } | ASTNode::SQLDropTable { ref name, .. } => {
+            add_table(tables, name.clone())
+        }
+        ASTNode::SQLCreateTableAs {
+            ref name,
+            ref query,
+            ..
+        } => {
+            collect_tables(query, false, tables);
+            add_table(tables, name.clone());
+        }
+        ASTNode::SQLCopyTo { ref query, .. } => collect_tables(query, false, tables),
+        ASTNode::SQLAlterTableRename {
+            ref name,
+            ref new_name,
+        } => {
+            add_table(tables, name.clone());
+            add_table(tables, new_name.clone());
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sqlparser::Parser;
+    use super::*;
+
+    fn tables(sql: &str) -> Vec<String> {
+        referenced_tables(&Parser::parse_sql(sql.to_string()).unwrap())
+    }
+
+    #[test]
+    fn referenced_tables_of_statements() {
+        assert_eq!(
+            vec!["people", "orders", "s.refunds"],
+            tables(
+                "SELECT name FROM people JOIN orders ON id = person_id \
+                 WHERE id = ANY (SELECT person_id FROM s.refunds)"
+            )
+        );
+        assert_eq!(
+            vec!["people", "people_copy"],
+            tables("CREATE TABLE people_copy AS SELECT * FROM people")
+        );
+        assert_eq!(vec!["a", "b"], tables("ALTER TABLE a RENAME TO b"));
+        assert_eq!(
+            Vec::<String>::new(),
+            tables("CREATE FUNCTION twice(x INT) RETURNS INT AS 'x * 2'")
+        );
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tab completion and syntax highlighting for the console, both driven by the SQL tokenizer
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[cfg(target_family = "unix")]
+use liner::Completer;
+
+use datafusion::exec::ExecutionContext;
+use datafusion::sqltokenizer::*;
+
+const KEYWORD_COLOR: &'static str = "\x1b[1;34m";
+const STRING_COLOR: &'static str = "\x1b[32m";
+const NUMBER_COLOR: &'static str = "\x1b[33m";
+const COMMENT_COLOR: &'static str = "\x1b[90m";
+const RESET: &'static str = "\x1b[0m";
+
+/// Add ANSI colors to keywords, literals and comments in a line of SQL. Lines that can't be
+/// tokenized are returned unchanged.
+pub fn highlight(line: &str) -> String {
+    let tokens = match Tokenizer::new(line).tokenize_with_spans() {
+        Ok(tokens) => tokens,
+        Err(_) => return line.to_string(),
+    };
+    let mut out = String::with_capacity(line.len() * 2);
+    for (token, span) in tokens {
+        let text = &line[span.range()];
+        let color = match token {
+            Token::Keyword(_) | Token::Boolean(_) | Token::Null => KEYWORD_COLOR,
+            Token::String(_) => STRING_COLOR,
+            Token::Integer(_) | Token::Decimal(_) | Token::Float(_) | Token::Binary(_) => {
+                NUMBER_COLOR
+            }
+            Token::Comment(_) | Token::Hint(_) => COMMENT_COLOR,
+            _ => {
+                out.push_str(text);
+                continue;
+            }
+        };
+        out.push_str(color);
+        out.push_str(text);
+        out.push_str(RESET);
+    }
+    out
+}
+
+/// Completes keywords, table names, column names and function names
+pub struct SqlCompleter {
+    ctx: ExecutionContext,
+    /// The text before the cursor, which is kept up to date by the line reader
+    line: Rc<RefCell<String>>,
+}
+
+impl SqlCompleter {
+    pub fn new(ctx: ExecutionContext, line: Rc<RefCell<String>>) -> Self {
+        SqlCompleter { ctx, line }
+    }
+
+    /// Get the candidates for the word being typed, based on the tokens before it. Table names
+    /// are offered after FROM, JOIN and TABLE, the columns of a table after `table.`, and
+    /// otherwise keywords, functions and the columns of the tables that the statement uses.
+    fn candidates(&self, line: &str, word: &str) -> Vec<String> {
+        let tokens: Vec<Token> = Tokenizer::new(line)
+            .tokenize_with_spans()
+            .map(|tokens| {
+                tokens
+                    .into_iter()
+                    .map(|(token, _)| token)
+                    .filter(|t| match *t {
+                        Token::Whitespace | Token::Comment(_) | Token::Hint(_) => false,
+                        _ => true,
+                    })
+                    .collect()
+            }).unwrap_or_default();
+
+        // the word being completed is the last token unless the line ends with whitespace
+        let before: &[Token] = if word.is_empty() || tokens.is_empty() {
+            &tokens
+        } else {
+            &tokens[..tokens.len() - 1]
+        };
+
+        if let Some(dot) = word.rfind('.') {
+            let table = &word[..dot];
+            return match self.ctx.table_schema(table) {
+                Some(schema) => schema
+                    .columns()
+                    .iter()
+                    .map(|c| format!("{}.{}", table, c.name()))
+                    .collect(),
+                None => vec![],
+            };
+        }
+
+        match before.last() {
+            Some(&Token::Keyword(ref k)) if k == "FROM" || k == "JOIN" || k == "TABLE" => {
+                return self.ctx.table_names();
+            }
+            _ => {}
+        }
+
+        let lowercase = word.chars().all(|c| !c.is_uppercase());
+        let mut candidates: Vec<String> = keywords()
+            .iter()
+            .map(|k| {
+                if lowercase {
+                    k.to_lowercase()
+                } else {
+                    k.to_string()
+                }
+            }).collect();
+        candidates.extend(self.ctx.function_names());
+
+        // columns of the tables named in the statement, or of every table when there are none
+        let mut tables: Vec<String> = tokens
+            .windows(2)
+            .filter_map(|w| match (&w[0], &w[1]) {
+                (&Token::Keyword(ref k), &Token::Identifier(ref t))
+                    if k == "FROM" || k == "JOIN" =>
+                {
+                    Some(t.to_string())
+                }
+                _ => None,
+            }).collect();
+        if tables.is_empty() {
+            tables = self.ctx.table_names();
+        }
+        for table in tables {
+            if let Some(schema) = self.ctx.table_schema(&table) {
+                candidates.extend(schema.columns().iter().map(|c| c.name().clone()));
+            }
+        }
+        candidates
+    }
+}
+
+#[cfg(target_family = "unix")]
+impl Completer for SqlCompleter {
+    fn completions(&self, start: &str) -> Vec<String> {
+        let line = self.line.borrow();
+        let prefix = start.to_lowercase();
+        let mut completions: Vec<String> = self
+            .candidates(&line, start)
+            .into_iter()
+            .filter(|c| c.to_lowercase().starts_with(&prefix))
+            .collect();
+        completions.sort();
+        completions.dedup();
+        completions
+    }
+}
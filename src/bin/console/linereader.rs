@@ -13,11 +13,20 @@
 // limitations under the License.
 
 #[cfg(target_family = "unix")]
-use liner::Context;
+use liner::{Context, Event};
+use std::cell::RefCell;
+use std::env;
 use std::io;
+use std::rc::Rc;
+
+use datafusion::exec::ExecutionContext;
+
+use completion::{highlight, SqlCompleter};
+use split_output;
 
 const DEFAULT_PROMPT: &'static str = "datafusion> ";
 const CONTINUE_PROMPT: &'static str = "> ";
+const HISTORY_FILE: &'static str = ".datafusion_history";
 
 #[cfg(target_family = "unix")]
 pub enum LineResult {
@@ -29,14 +38,30 @@ pub enum LineResult {
 pub struct LineReader<'a> {
     reader: Context,
     prompt: &'a str,
+    /// The text before the cursor, shared with the completer
+    line: Rc<RefCell<String>>,
 }
 
 #[cfg(target_family = "unix")]
 impl<'a> LineReader<'a> {
-    pub fn new() -> Self {
+    pub fn new(ctx: ExecutionContext) -> Self {
+        let line = Rc::new(RefCell::new(String::new()));
+        let mut reader = Context::new();
+        reader.completer = Some(Box::new(SqlCompleter::new(ctx, line.clone())));
+        // keep history across sessions in the user's home directory
+        if let Some(home) = env::home_dir() {
+            let path = home.join(HISTORY_FILE);
+            reader.history.set_file_name(path.to_str().map(|p| p.to_string()));
+            if path.exists() {
+                if let Err(e) = reader.history.load_history() {
+                    println!("Could not load history from {:?}: {}", path, e);
+                }
+            }
+        }
         LineReader {
-            reader: Context::new(),
+            reader,
             prompt: DEFAULT_PROMPT,
+            line,
         }
     }
 
@@ -47,7 +72,18 @@ impl<'a> LineReader<'a> {
     pub fn read_lines(&mut self) -> Option<LineResult> {
         let mut result = String::new();
         loop {
-            let line = self.reader.read_line(self.prompt, &mut |_| {});
+            // earlier lines of a statement that spans several lines give context for completion
+            let previous = result.clone();
+            let current = self.line.clone();
+            let line = self.reader.read_line(
+                self.prompt,
+                Some(Box::new(|s: &str| highlight(s))),
+                &mut |e: Event<_>| {
+                    let buffer = e.editor.current_buffer();
+                    *current.borrow_mut() =
+                        format!("{}{}", previous, buffer.range(0, e.editor.cursor()));
+                },
+            );
 
             match line {
                 Ok(i) => {
@@ -56,6 +92,7 @@ impl<'a> LineReader<'a> {
 
                     match j {
                         "quit" | "exit" => {
+                            self.reader.history.commit_history();
                             return Some(LineResult::Break);
                         }
                         _ => {
@@ -63,7 +100,12 @@ impl<'a> LineReader<'a> {
                             // CONTINUE: are statements that don't end with a semicolon
                             // DEFAULT: are statements that end with a semicolon
                             // and can be returned to being executed.
-                            if j.ends_with(';') {
+                            // Console commands start with a backslash, and `\g` also ends a
+                            // statement.
+                            if j.ends_with(';')
+                                || j.starts_with('\\')
+                                || split_output(&result).is_some()
+                            {
                                 self.set_prompt(DEFAULT_PROMPT);
                                 break;
                             } else {
@@ -80,6 +122,7 @@ impl<'a> LineReader<'a> {
                         io::ErrorKind::Interrupted => {}
                         // ctrl-d pressed
                         io::ErrorKind::UnexpectedEof => {
+                            self.reader.history.commit_history();
                             return Some(LineResult::Break);
                         }
                         _ => {
@@ -98,6 +141,9 @@ impl<'a> LineReader<'a> {
         }
 
         // Return the command without semicolon
-        Some(LineResult::Input(result[..result.len() - 1].to_string()))
+        if result.ends_with(';') {
+            result.pop();
+        }
+        Some(LineResult::Input(result))
     }
 }
@@ -19,55 +19,82 @@ extern crate datafusion;
 extern crate liner;
 
 use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
 use std::rc::Rc;
 use std::str;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use clap::{App, Arg};
 use datafusion::exec::*;
 use datafusion::functions::geospatial::st_astext::*;
 use datafusion::functions::geospatial::st_point::*;
 use datafusion::functions::math::*;
-use datafusion::sqlast::ASTNode::SQLCreateTable;
+use datafusion::progress::Progress;
+use datafusion::sqlast::ASTNode::{
+    SQLAlterTableRename, SQLCopyTo, SQLCreateFunction, SQLCreateTable, SQLCreateTableAs,
+    SQLDropTable,
+};
 use datafusion::sqlparser::*;
+use datafusion::sqltokenizer::Tokenizer;
 
+mod completion;
 mod linereader;
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
+/// Find the `\g` that ends a statement, returning the statement before it and the file name
+/// after it, if there is one. A `\g` in a string literal or comment is part of the statement,
+/// and only a file name may follow the one that ends it.
+fn split_output(input: &str) -> Option<(&str, Option<&str>)> {
+    // a backslash outside of string literals and comments can't be tokenized, so the tokenizer
+    // reports where each of them is
+    let (_, errors) = Tokenizer::new(input).tokenize_with_recovery();
+    let start = errors
+        .iter()
+        .map(|e| e.span.start)
+        .filter(|&i| input[i..].starts_with("\\g"))
+        .last()?;
+    let rest = &input[start + 2..];
+    let filename = rest.trim();
+    if !(rest.is_empty() || rest.starts_with(char::is_whitespace))
+        || filename.contains(char::is_whitespace)
+    {
+        return None;
+    }
+    if filename.is_empty() {
+        Some((&input[..start], None))
+    } else {
+        Some((&input[..start], Some(filename)))
+    }
+}
+
 #[cfg(target_family = "unix")]
 fn setup_console(cmdline: clap::ArgMatches) {
     //parse args
     //let etcd_endpoints = cmdline.value_of("ETCD").unwrap();
     let mut console = Console::new(/*etcd_endpoints.to_string()*/);
+    if let Some(path) = cmdline.value_of("CATALOG") {
+        console.use_catalog(path);
+    }
 
     match cmdline.value_of("SCRIPT") {
         Some(filename) => match File::open(filename) {
-            Ok(f) => {
-                let mut cmd_buffer = String::new();
-                let mut reader = BufReader::new(&f);
-                for line in reader.lines() {
-                    match line {
-                        Ok(cmd) => {
-                            cmd_buffer.push_str(&cmd);
-                            if cmd_buffer.as_str().ends_with(";") {
-                                console.execute(&cmd_buffer[0..cmd_buffer.len() - 2]);
-                                cmd_buffer = String::new();
-                            }
+            Ok(mut f) => {
+                let mut script = String::new();
+                match f.read_to_string(&mut script) {
+                    Ok(_) => {
+                        for statement in Parser::split_statements(&script) {
+                            console.execute(&statement);
                         }
-                        Err(e) => println!("Error: {}", e),
                     }
-                }
-                if cmd_buffer.as_str().ends_with(";") {
-                    console.execute(&cmd_buffer[0..cmd_buffer.len() - 2]);
+                    Err(e) => println!("Error: {}", e),
                 }
             }
             Err(e) => println!("Could not open file {}: {}", filename, e),
         },
         _ => {
-            let mut reader = linereader::LineReader::new();
+            let mut reader = linereader::LineReader::new(console.ctx.clone());
             loop {
                 let result = reader.read_lines();
                 match result {
@@ -112,6 +139,14 @@ fn main() {
                     .required(false)
                     .takes_value(true),
             )
+            .arg(
+                Arg::with_name("CATALOG")
+                    .help("JSON file that tables are loaded from at startup and saved to when changed")
+                    .short("c")
+                    .long("catalog")
+                    .required(false)
+                    .takes_value(true),
+            )
             .get_matches();
     setup_console(cmdline);
 }
@@ -119,6 +154,9 @@ fn main() {
 /// Interactive SQL console
 struct Console {
     ctx: ExecutionContext,
+    catalog_path: Option<String>,
+    /// Show how long parsing, planning and execution took for each query
+    timing: bool,
 }
 
 impl Console {
@@ -128,59 +166,181 @@ impl Console {
         ctx.register_scalar_function(Rc::new(STPointFunc {}));
         ctx.register_scalar_function(Rc::new(STAsText {}));
         ctx.register_scalar_function(Rc::new(SqrtFunction {}));
-        Console { ctx }
+        ctx.set_progress_callback(Some(Rc::new(|p: &Progress| render_progress(p))));
+        Console {
+            ctx,
+            catalog_path: None,
+            timing: false,
+        }
+    }
+
+    /// Load the tables from a catalog file if it exists, and save the catalog to it whenever a
+    /// table is created, dropped or renamed
+    fn use_catalog(&mut self, path: &str) {
+        if Path::new(path).exists() {
+            match self.ctx.load_catalog(path) {
+                Ok(missing) => {
+                    for name in missing {
+                        println!("Warning: function '{}' in the catalog is not registered", name);
+                    }
+                }
+                Err(e) => println!("Could not load catalog {}: {}", path, e),
+            }
+        }
+        self.catalog_path = Some(path.to_string());
     }
 
     /// Execute a SQL statement or console command
-    fn execute(&mut self, sql: &str) {
+    fn execute(&mut self, input: &str) {
+        let input = input.trim();
+        if input == "\\timing" {
+            self.timing = !self.timing;
+            println!("Timing is {}.", if self.timing { "on" } else { "off" });
+            return;
+        }
+
+        // `query \g filename` writes the results of the query to a file instead of the screen
+        let (sql, output) = split_output(input).unwrap_or((input, None));
+        if sql.starts_with('\\') {
+            println!("Unknown command {}", sql);
+            return;
+        }
+
         println!("Executing query ...");
 
         let timer = Instant::now();
 
         // parse the SQL
-        match Parser::parse_sql(String::from(sql)) {
-            Ok(ast) => match ast {
-                SQLCreateTable { .. } => {
-                    self.ctx.sql(&sql).unwrap();
-                    //println!("Registered schema with execution context");
-                    ()
+        let ast = match Parser::parse_sql(String::from(sql)) {
+            Ok(ast) => ast,
+            Err(e) => {
+                println!("Error: {}", e);
+                return;
+            }
+        };
+        let parse_time = timer.elapsed();
+
+        match ast {
+            SQLCreateTable { .. }
+            | SQLCreateTableAs { .. }
+            | SQLDropTable { .. }
+            | SQLAlterTableRename { .. }
+            | SQLCreateFunction { .. } => {
+                if let Err(e) = self.ctx.sql(&sql) {
+                    println!("Error: {}", e);
+                    return;
                 }
-                _ => match self.ctx.create_logical_plan(sql) {
-                    Ok(logical_plan) => {
-                        let physical_plan = PhysicalPlan::Interactive {
-                            plan: logical_plan.clone(),
-                        };
+                //println!("Registered schema with execution context");
+                if let Some(ref path) = self.catalog_path {
+                    if let Err(e) = self.ctx.save_catalog(path) {
+                        println!("Could not save catalog {}: {}", path, e);
+                    }
+                }
+            }
+            SQLCopyTo { ref location, .. } => match self.ctx.sql(&sql) {
+                Ok(df) => {
+                    let count = self
+                        .ctx
+                        .write_string(df)
+                        .map(|s| s.split(',').next().unwrap_or("0").to_string());
+                    match count {
+                        Ok(n) => println!(
+                            "Query executed in {} seconds and wrote {} rows to {}",
+                            seconds(timer.elapsed()),
+                            n,
+                            location
+                        ),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                }
+                Err(e) => println!("Error: {}", e),
+            },
+            _ => {
+                let plan_timer = Instant::now();
+                let logical_plan = match self.ctx.create_logical_plan(sql) {
+                    Ok(plan) => plan,
+                    Err(e) => {
+                        println!("Error: {}", e);
+                        return;
+                    }
+                };
+                let plan_time = plan_timer.elapsed();
+
+                let execute_timer = Instant::now();
+                let physical_plan = match output {
+                    Some(filename) => PhysicalPlan::Write {
+                        plan: logical_plan.clone(),
+                        filename: filename.to_string(),
+                        kind: "csv".to_string(),
+                    },
+                    None => PhysicalPlan::Interactive {
+                        plan: logical_plan.clone(),
+                    },
+                };
+                let result = self.ctx.execute(&physical_plan);
+                let execute_time = execute_timer.elapsed();
+                clear_progress();
 
-                        let result = self.ctx.execute(&physical_plan);
+                match result {
+                    Ok(result) => {
+                        let elapsed_seconds = seconds(timer.elapsed());
 
                         match result {
-                            Ok(result) => {
-                                let elapsed = timer.elapsed();
-                                let elapsed_seconds = elapsed.as_secs() as f64
-                                    + elapsed.subsec_nanos() as f64 / 1000000000.0;
-
-                                match result {
-                                    ExecutionResult::Unit => {
-                                        println!("Query executed in {} seconds", elapsed_seconds);
-                                    }
-                                    ExecutionResult::Count(n) => {
-                                        println!(
-                                            "Query executed in {} seconds and updated {} rows",
-                                            elapsed_seconds, n
-                                        );
-                                    }
-                                    ExecutionResult::Str(_) => {
-                                        println!("Query executed in {} seconds", elapsed_seconds);
-                                    }
-                                }
+                            ExecutionResult::Unit => {
+                                println!("Query executed in {} seconds", elapsed_seconds);
+                            }
+                            ExecutionResult::Count(n) => match output {
+                                Some(filename) => println!(
+                                    "Query executed in {} seconds and wrote {} rows to {}",
+                                    elapsed_seconds, n, filename
+                                ),
+                                None => println!(
+                                    "Query executed in {} seconds and updated {} rows",
+                                    elapsed_seconds, n
+                                ),
+                            },
+                            ExecutionResult::Str(_) => {
+                                println!("Query executed in {} seconds", elapsed_seconds);
                             }
-                            Err(e) => println!("Error: {:?}", e),
+                        }
+                        if self.timing {
+                            println!(
+                                "Time: parse {:.3} ms, plan {:.3} ms, execute {:.3} ms",
+                                seconds(parse_time) * 1000.0,
+                                seconds(plan_time) * 1000.0,
+                                seconds(execute_time) * 1000.0
+                            );
                         }
                     }
-                    Err(e) => println!("Error: {:?}", e),
-                },
-            },
-            Err(e) => println!("Error: {:?}", e),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
         }
     }
 }
+
+fn seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1000000000.0
+}
+
+const PROGRESS_WIDTH: usize = 40;
+
+/// Draw a progress bar on stderr, overwriting the previous one
+fn render_progress(progress: &Progress) {
+    if let Some(fraction) = progress.fraction() {
+        let filled = (fraction * PROGRESS_WIDTH as f64) as usize;
+        eprint!(
+            "\r[{}{}] {:>3}% ({}/{} files, {} rows)",
+            "=".repeat(filled),
+            " ".repeat(PROGRESS_WIDTH - filled),
+            (fraction * 100.0) as usize,
+            progress.files_completed,
+            progress.files_total,
+            progress.rows_scanned
+        );
+    }
+}
+
+fn clear_progress() {
+    eprint!("\r{}\r", " ".repeat(PROGRESS_WIDTH + 40));
+}
@@ -0,0 +1,318 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache of materialized query results
+//!
+//! Results are keyed by a fingerprint of the logical plan and are only returned while the
+//! modification times and lengths of the underlying files are unchanged and the entry is
+//! younger than the configured time-to-live. The cache holds at most a configured number of
+//! bytes of results, evicting the least recently used results to make room for new ones.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::datasources::common::*;
+use super::errors::*;
+use super::exec::*;
+use super::resource::batch_memory_size;
+
+use arrow::datatypes::Schema;
+
+/// Paths, modification times and lengths of the files that a query reads from
+pub type SourceSnapshot = Vec<(String, SystemTime, u64)>;
+
+struct CacheEntry {
+    snapshot: SourceSnapshot,
+    /// Names of the tables that the query reads from
+    tables: Vec<String>,
+    created: Instant,
+    /// Value of the cache's clock when the entry was last inserted or returned
+    last_used: u64,
+    /// Estimated memory used by the batches
+    bytes: usize,
+    schema: Rc<Schema>,
+    batches: Rc<Vec<Rc<RecordBatch>>>,
+}
+
+pub struct ResultCache {
+    ttl: Duration,
+    max_bytes: usize,
+    /// Total estimated memory used by the cached batches
+    bytes: usize,
+    /// Counter that orders the uses of entries, for finding the least recently used one
+    clock: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl ResultCache {
+    /// Create a cache whose entries expire after `ttl` and whose batches use at most
+    /// `max_bytes` of memory
+    pub fn new(ttl: Duration, max_bytes: usize) -> Self {
+        ResultCache {
+            ttl,
+            max_bytes,
+            bytes: 0,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Get cached batches for a plan fingerprint, provided the entry has not expired and the
+    /// source files have not changed since the results were cached
+    pub fn get(
+        &mut self,
+        key: &str,
+        snapshot: &SourceSnapshot,
+    ) -> Option<(Rc<Schema>, Rc<Vec<Rc<RecordBatch>>>)> {
+        let valid = match self.entries.get(key) {
+            Some(entry) => entry.created.elapsed() < self.ttl && entry.snapshot == *snapshot,
+            None => return None,
+        };
+        if valid {
+            self.clock += 1;
+            let clock = self.clock;
+            self.entries.get_mut(key).map(|entry| {
+                entry.last_used = clock;
+                (entry.schema.clone(), entry.batches.clone())
+            })
+        } else {
+            self.remove(key);
+            None
+        }
+    }
+
+    /// Cache the results of a query, discarding expired results and then the least recently
+    /// used results until they fit. Results larger than the whole cache aren't cached.
+    pub fn insert(
+        &mut self,
+        key: String,
+        snapshot: SourceSnapshot,
+        tables: Vec<String>,
+        schema: Rc<Schema>,
+        batches: Vec<Rc<RecordBatch>>,
+    ) {
+        self.remove(&key);
+        let ttl = self.ttl;
+        self.retain(|entry| entry.created.elapsed() < ttl);
+
+        let bytes: usize = batches.iter().map(|b| batch_memory_size(b)).sum();
+        if bytes > self.max_bytes {
+            return;
+        }
+        while self.bytes + bytes > self.max_bytes {
+            // the cache holds few enough entries that a linear search is cheap
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|&(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+
+        self.clock += 1;
+        self.bytes += bytes;
+        self.entries.insert(
+            key,
+            CacheEntry {
+                snapshot,
+                tables,
+                created: Instant::now(),
+                last_used: self.clock,
+                bytes,
+                schema,
+                batches: Rc::new(batches),
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    fn retain<F: Fn(&CacheEntry) -> bool>(&mut self, f: F) {
+        self.entries.retain(|_, entry| f(entry));
+        self.bytes = self.entries.values().map(|entry| entry.bytes).sum();
+    }
+
+    /// Number of cached results
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Estimated memory used by the cached results
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Remove all cached results
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    /// Remove cached results of queries that read from a table
+    pub fn invalidate_table(&mut self, table_name: &str) {
+        self.retain(|entry| !entry.tables.iter().any(|t| t == table_name));
+    }
+}
+
+/// Relation that stores the batches produced by its input in the result cache, once the input
+/// has been read to completion without errors
+pub struct CachingRelation {
+    input: Box<SimpleRelation>,
+    cache: Rc<RefCell<Option<ResultCache>>>,
+    key: String,
+    snapshot: SourceSnapshot,
+    tables: Vec<String>,
+}
+
+impl CachingRelation {
+    pub fn new(
+        input: Box<SimpleRelation>,
+        cache: Rc<RefCell<Option<ResultCache>>>,
+        key: String,
+        snapshot: SourceSnapshot,
+        tables: Vec<String>,
+    ) -> Self {
+        CachingRelation {
+            input,
+            cache,
+            key,
+            snapshot,
+            tables,
+        }
+    }
+}
+
+struct CachingIterator<'a> {
+    it: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    batches: Vec<Rc<RecordBatch>>,
+    failed: bool,
+    done: bool,
+    cache: Rc<RefCell<Option<ResultCache>>>,
+    key: String,
+    snapshot: SourceSnapshot,
+    tables: Vec<String>,
+    schema: Rc<Schema>,
+}
+
+impl<'a> Iterator for CachingIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.it.next() {
+            Some(Ok(batch)) => {
+                self.batches.push(batch.clone());
+                Some(Ok(batch))
+            }
+            Some(Err(e)) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+            None => {
+                if !self.failed && !self.done {
+                    self.done = true;
+                    if let Some(ref mut cache) = *self.cache.borrow_mut() {
+                        cache.insert(
+                            self.key.clone(),
+                            self.snapshot.clone(),
+                            self.tables.clone(),
+                            self.schema.clone(),
+                            self.batches.drain(..).collect(),
+                        );
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+impl SimpleRelation for CachingRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let schema = Rc::new(self.input.schema().clone());
+        Box::new(CachingIterator {
+            it: self.input.scan(),
+            batches: vec![],
+            failed: false,
+            done: false,
+            cache: self.cache.clone(),
+            key: self.key.clone(),
+            snapshot: self.snapshot.clone(),
+            tables: self.tables.clone(),
+            schema,
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::types::*;
+    use super::*;
+
+    fn batches(values: Vec<i64>) -> Vec<Rc<RecordBatch>> {
+        let schema = Rc::new(Schema::new(vec![]));
+        vec![Rc::new(DefaultRecordBatch {
+            schema,
+            row_count: values.len(),
+            data: vec![Value::Column(Rc::new(Array::from(values)))],
+        })]
+    }
+
+    fn insert(cache: &mut ResultCache, key: &str, values: Vec<i64>) {
+        let schema = Rc::new(Schema::new(vec![]));
+        cache.insert(key.to_string(), vec![], vec![], schema, batches(values));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = ResultCache::new(Duration::from_secs(60), 48);
+        insert(&mut cache, "a", vec![1, 2]);
+        insert(&mut cache, "b", vec![3, 4]);
+        insert(&mut cache, "c", vec![5, 6]);
+        assert_eq!(48, cache.bytes());
+        assert!(cache.get("a", &vec![]).is_some());
+
+        insert(&mut cache, "d", vec![7, 8]);
+        assert_eq!(3, cache.len());
+        assert_eq!(48, cache.bytes());
+        assert!(cache.get("b", &vec![]).is_none());
+        assert!(cache.get("a", &vec![]).is_some());
+
+        // results larger than the whole cache aren't cached
+        insert(&mut cache, "e", vec![0; 7]);
+        assert!(cache.get("e", &vec![]).is_none());
+        assert_eq!(48, cache.bytes());
+    }
+
+    #[test]
+    fn purges_expired_entries_on_insert() {
+        let mut cache = ResultCache::new(Duration::from_millis(0), 1024);
+        insert(&mut cache, "a", vec![1]);
+        insert(&mut cache, "b", vec![2]);
+        assert_eq!(1, cache.len());
+        assert_eq!(8, cache.bytes());
+    }
+}
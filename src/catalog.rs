@@ -0,0 +1,266 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A description of the tables registered with an execution context that can be saved to a JSON
+//! file and loaded in a later session. Only tables backed by files can be described, since the
+//! contents of in-memory tables are not persisted.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+use json;
+use json::JsonValue;
+
+use super::errors::*;
+
+/// The file that a table reads from
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableSource {
+    Csv { filename: String, has_header: bool },
+    NdJson { filename: String },
+    Parquet { filename: String },
+}
+
+/// The definition of a file-backed table
+#[derive(Debug, Clone)]
+pub struct TableDef {
+    pub name: String,
+    pub source: TableSource,
+    pub schema: Rc<Schema>,
+    /// Declared sort order as column names and whether each is ascending
+    pub ordering: Vec<(String, bool)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    pub tables: Vec<TableDef>,
+    /// Names of the scalar functions that were registered. The functions themselves are code
+    /// so they have to be registered again before the catalog is loaded.
+    pub functions: Vec<String>,
+}
+
+impl Catalog {
+    pub fn to_json(&self) -> Result<JsonValue> {
+        let mut tables = JsonValue::new_array();
+        for table in &self.tables {
+            let mut node = JsonValue::new_object();
+            node["name"] = table.name.as_str().into();
+            match table.source {
+                TableSource::Csv {
+                    ref filename,
+                    has_header,
+                } => {
+                    node["format"] = "csv".into();
+                    node["filename"] = filename.as_str().into();
+                    node["has_header"] = has_header.into();
+                }
+                TableSource::NdJson { ref filename } => {
+                    node["format"] = "ndjson".into();
+                    node["filename"] = filename.as_str().into();
+                }
+                TableSource::Parquet { ref filename } => {
+                    node["format"] = "parquet".into();
+                    node["filename"] = filename.as_str().into();
+                }
+            }
+            let mut columns = JsonValue::new_array();
+            for field in table.schema.columns() {
+                let mut column = JsonValue::new_object();
+                column["name"] = field.name().as_str().into();
+                column["data_type"] = data_type_name(field.data_type())?.into();
+                column["nullable"] = field.is_nullable().into();
+                push(&mut columns, column);
+            }
+            node["columns"] = columns;
+            let mut ordering = JsonValue::new_array();
+            for &(ref column, asc) in &table.ordering {
+                let mut key = JsonValue::new_object();
+                key["column"] = column.as_str().into();
+                key["asc"] = asc.into();
+                push(&mut ordering, key);
+            }
+            node["ordering"] = ordering;
+            push(&mut tables, node);
+        }
+        let mut functions = JsonValue::new_array();
+        for name in &self.functions {
+            push(&mut functions, name.as_str().into());
+        }
+        let mut catalog = JsonValue::new_object();
+        catalog["tables"] = tables;
+        catalog["functions"] = functions;
+        Ok(catalog)
+    }
+
+    pub fn from_json(value: &JsonValue) -> Result<Self> {
+        let mut tables = vec![];
+        for node in value["tables"].members() {
+            let name = string_field(node, "name")?;
+            let filename = string_field(node, "filename")?;
+            let source = match string_field(node, "format")?.as_str() {
+                "csv" => TableSource::Csv {
+                    filename,
+                    has_header: node["has_header"].as_bool().unwrap_or(true),
+                },
+                "ndjson" => TableSource::NdJson { filename },
+                "parquet" => TableSource::Parquet { filename },
+                other => {
+                    return Err(DataFusionError::Execution(format!(
+                        "Unsupported format '{}' for table '{}' in catalog",
+                        other, name
+                    )))
+                }
+            };
+            let columns = node["columns"]
+                .members()
+                .map(|c| {
+                    Ok(Field::new(
+                        &string_field(c, "name")?,
+                        parse_data_type(&string_field(c, "data_type")?)?,
+                        c["nullable"].as_bool().unwrap_or(true),
+                    ))
+                }).collect::<Result<Vec<Field>>>()?;
+            let ordering = node["ordering"]
+                .members()
+                .map(|k| Ok((string_field(k, "column")?, k["asc"].as_bool().unwrap_or(true))))
+                .collect::<Result<Vec<(String, bool)>>>()?;
+            tables.push(TableDef {
+                name,
+                source,
+                schema: Rc::new(Schema::new(columns)),
+                ordering,
+            });
+        }
+        let functions = value["functions"]
+            .members()
+            .filter_map(|f| f.as_str().map(|s| s.to_string()))
+            .collect();
+        Ok(Catalog { tables, functions })
+    }
+
+    /// Write the catalog to a JSON file
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(self.to_json()?.pretty(2).as_bytes())?;
+        Ok(())
+    }
+
+    /// Read a catalog from a JSON file
+    pub fn load(path: &str) -> Result<Self> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        let value = json::parse(&text).map_err(|e| {
+            DataFusionError::Execution(format!("Invalid catalog file '{}': {}", path, e))
+        })?;
+        Catalog::from_json(&value)
+    }
+}
+
+fn push(array: &mut JsonValue, value: JsonValue) {
+    // pushing only fails when the target is not an array
+    array.push(value).unwrap();
+}
+
+fn string_field(node: &JsonValue, name: &str) -> Result<String> {
+    match node[name].as_str() {
+        Some(s) => Ok(s.to_string()),
+        None => Err(DataFusionError::Execution(format!(
+            "Catalog entry is missing '{}'",
+            name
+        ))),
+    }
+}
+
+fn data_type_name(data_type: &DataType) -> Result<&'static str> {
+    Ok(match *data_type {
+        DataType::Boolean => "Boolean",
+        DataType::Int8 => "Int8",
+        DataType::Int16 => "Int16",
+        DataType::Int32 => "Int32",
+        DataType::Int64 => "Int64",
+        DataType::UInt8 => "UInt8",
+        DataType::UInt16 => "UInt16",
+        DataType::UInt32 => "UInt32",
+        DataType::UInt64 => "UInt64",
+        DataType::Float32 => "Float32",
+        DataType::Float64 => "Float64",
+        DataType::Utf8 => "Utf8",
+        ref other => {
+            return Err(DataFusionError::Execution(format!(
+                "Cannot save columns of type {:?} in a catalog",
+                other
+            )))
+        }
+    })
+}
+
+fn parse_data_type(name: &str) -> Result<DataType> {
+    Ok(match name {
+        "Boolean" => DataType::Boolean,
+        "Int8" => DataType::Int8,
+        "Int16" => DataType::Int16,
+        "Int32" => DataType::Int32,
+        "Int64" => DataType::Int64,
+        "UInt8" => DataType::UInt8,
+        "UInt16" => DataType::UInt16,
+        "UInt32" => DataType::UInt32,
+        "UInt64" => DataType::UInt64,
+        "Float32" => DataType::Float32,
+        "Float64" => DataType::Float64,
+        "Utf8" => DataType::Utf8,
+        other => {
+            return Err(DataFusionError::Execution(format!(
+                "Unsupported data type '{}' in catalog",
+                other
+            )))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_json_roundtrip() {
+        let catalog = Catalog {
+            tables: vec![TableDef {
+                name: "orders".to_string(),
+                source: TableSource::Csv {
+                    filename: "orders.csv".to_string(),
+                    has_header: true,
+                },
+                schema: Rc::new(Schema::new(vec![
+                    Field::new("order_id", DataType::Int64, false),
+                    Field::new("note", DataType::Utf8, true),
+                ])),
+                ordering: vec![("order_id".to_string(), true)],
+            }],
+            functions: vec!["sqrt".to_string()],
+        };
+        let json = catalog.to_json().unwrap();
+        assert_eq!(json, Catalog::from_json(&json).unwrap().to_json().unwrap());
+    }
+
+    #[test]
+    fn test_catalog_invalid_data_type() {
+        let value = json::parse(
+            r#"{"tables": [{"name": "t", "format": "csv", "filename": "t.csv",
+                "columns": [{"name": "a", "data_type": "Decimal"}]}]}"#,
+        ).unwrap();
+        assert!(Catalog::from_json(&value).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checkpoints of completed query stages
+//!
+//! A stage is a part of a plan that reads all of its input before producing output, such as an
+//! aggregation. When checkpoints are enabled the output of each stage is written to a file in
+//! the checkpoint directory as it is produced, and the file is kept once the stage completes.
+//! If a long query crashes or is cancelled and is then run again, the stages that completed
+//! are read back from their checkpoints rather than being computed again.
+//!
+//! Checkpoints are keyed by a fingerprint of the stage's logical plan and the modification
+//! times of the files it reads, so they are not used once the data has changed. They are kept
+//! until they are cleared, so that they survive the process that wrote them.
+
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use fnv::FnvHasher;
+
+use arrow::datatypes::Schema;
+
+use super::cache::SourceSnapshot;
+use super::datasources::common::*;
+use super::errors::*;
+use super::exec::*;
+use super::spill::{read_batch, write_batch};
+
+/// Precedes each batch in a checkpoint file
+const TAG_BATCH: u8 = 1;
+/// Marks the end of a checkpoint file
+const TAG_END: u8 = 0;
+
+pub struct CheckpointStore {
+    directory: PathBuf,
+}
+
+impl CheckpointStore {
+    pub fn new(directory: PathBuf) -> Self {
+        CheckpointStore { directory }
+    }
+
+    pub fn directory(&self) -> &PathBuf {
+        &self.directory
+    }
+
+    /// The path of the checkpoint of a stage
+    fn path(&self, key: &str, snapshot: &SourceSnapshot) -> PathBuf {
+        // FNV-1a is used rather than the standard library's hasher, whose output may change
+        // between releases and would then not find the checkpoints written by earlier ones
+        let mut hasher = FnvHasher::default();
+        hasher.write(key.as_bytes());
+        for &(ref path, modified, len) in snapshot {
+            hasher.write(path.as_bytes());
+            hasher.write(format!("{:?}", modified).as_bytes());
+            hasher.write_u64(len);
+        }
+        self.directory
+            .join(format!("stage-{:016x}.bin", hasher.finish()))
+    }
+
+    /// Get a relation that reads the checkpoint of a stage, if the stage has completed
+    pub fn get(
+        &self,
+        key: &str,
+        snapshot: &SourceSnapshot,
+        schema: Rc<Schema>,
+    ) -> Option<Box<SimpleRelation>> {
+        let path = self.path(key, snapshot);
+        if path.is_file() {
+            Some(Box::new(CheckpointRelation { path, schema }))
+        } else {
+            None
+        }
+    }
+
+    /// Wrap the relation for a stage so that its output is checkpointed once it completes
+    pub fn checkpoint(
+        &self,
+        input: Box<SimpleRelation>,
+        key: &str,
+        snapshot: &SourceSnapshot,
+    ) -> Box<SimpleRelation> {
+        Box::new(CheckpointingRelation {
+            input,
+            path: self.path(key, snapshot),
+        })
+    }
+
+    /// Delete all checkpoints, including any left incomplete by a crash
+    pub fn clear(&self) -> Result<()> {
+        if self.directory.is_dir() {
+            for entry in fs::read_dir(&self.directory)? {
+                let path = entry?.path();
+                let is_checkpoint = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .map(|name| name.starts_with("stage-"))
+                    .unwrap_or(false);
+                if is_checkpoint {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the batches of a completed checkpoint
+struct CheckpointRelation {
+    path: PathBuf,
+    schema: Rc<Schema>,
+}
+
+struct CheckpointIterator {
+    /// None once the end of the file or an error has been reached
+    input: Option<Box<Read>>,
+    schema: Rc<Schema>,
+}
+
+impl Iterator for CheckpointIterator {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = match self.input {
+            Some(ref mut input) => match input.read_u8() {
+                Ok(TAG_BATCH) => match read_batch(input, &self.schema) {
+                    Ok(batch) => return Some(Ok(batch)),
+                    Err(e) => Some(Err(e)),
+                },
+                Ok(TAG_END) => None,
+                Ok(tag) => Some(Err(DataFusionError::Execution(format!(
+                    "Invalid tag {} in checkpoint file",
+                    tag
+                )))),
+                Err(e) => Some(Err(e.into())),
+            },
+            None => return None,
+        };
+        self.input = None;
+        result
+    }
+}
+
+impl SimpleRelation for CheckpointRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        match File::open(&self.path) {
+            Ok(file) => Box::new(CheckpointIterator {
+                input: Some(Box::new(BufReader::new(file))),
+                schema: self.schema.clone(),
+            }),
+            Err(e) => Box::new(vec![Err(e.into())].into_iter()),
+        }
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+/// Relation that writes the batches produced by its input to a checkpoint file as they are
+/// produced. The file is written under a temporary name and renamed once the input has been
+/// read to completion, so an incomplete checkpoint is never read.
+struct CheckpointingRelation {
+    input: Box<SimpleRelation>,
+    path: PathBuf,
+}
+
+struct CheckpointingIterator<'a> {
+    it: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    /// None once the checkpoint has been completed or abandoned
+    output: Option<BufWriter<File>>,
+    path: PathBuf,
+    temp_path: PathBuf,
+}
+
+impl<'a> CheckpointingIterator<'a> {
+    /// Delete an incomplete checkpoint. Failing to write a checkpoint doesn't fail the query.
+    fn abandon(&mut self) {
+        if self.output.take().is_some() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        if let Some(ref mut w) = self.output {
+            w.write_u8(TAG_BATCH)?;
+            write_batch(w, batch)?;
+        }
+        Ok(())
+    }
+
+    fn complete(&mut self) -> Result<()> {
+        if let Some(mut w) = self.output.take() {
+            w.write_u8(TAG_END)?;
+            w.flush()?;
+            fs::rename(&self.temp_path, &self.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for CheckpointingIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.it.next() {
+            Some(Ok(batch)) => {
+                if self.write(batch.as_ref()).is_err() {
+                    self.abandon();
+                }
+                Some(Ok(batch))
+            }
+            Some(Err(e)) => {
+                self.abandon();
+                Some(Err(e))
+            }
+            None => {
+                if self.complete().is_err() {
+                    let _ = fs::remove_file(&self.temp_path);
+                }
+                None
+            }
+        }
+    }
+}
+
+impl<'a> Drop for CheckpointingIterator<'a> {
+    fn drop(&mut self) {
+        // a stage that was not read to completion, e.g. because the query was cancelled
+        self.abandon();
+    }
+}
+
+impl SimpleRelation for CheckpointingRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let temp_path = self.path.with_extension("tmp");
+        let output = self
+            .path
+            .parent()
+            .map(|dir| fs::create_dir_all(dir))
+            .unwrap_or(Ok(()))
+            .and_then(|_| File::create(&temp_path))
+            .map(BufWriter::new)
+            .ok();
+        Box::new(CheckpointingIterator {
+            it: self.input.scan(),
+            output,
+            path: self.path.clone(),
+            temp_path,
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cursor for paging through query results
+//!
+//! Relations are pulled lazily so upstream operators only run when the cursor needs more rows
+//! to satisfy a fetch. Not calling `fetch_next` pauses the whole pipeline. Cursors are opened
+//! with `ExecutionContext::open_cursor`.
+
+use std::rc::Rc;
+
+use super::admission::AdmissionPermit;
+use super::datasources::common::*;
+use super::errors::*;
+use super::exec::*;
+use super::types::*;
+
+/// A row of results
+pub type Row = Vec<Rc<ScalarValue>>;
+
+pub struct ResultCursor {
+    /// Borrows from `relation`, so it is dropped first
+    it: Option<Box<Iterator<Item = Result<Rc<RecordBatch>>>>>,
+    relation: *mut Box<SimpleRelation>,
+    /// Holds the query's admission slot until the cursor is dropped
+    _permit: Option<AdmissionPermit>,
+    /// Batch that has only been partially returned to the caller
+    current: Option<Rc<RecordBatch>>,
+    /// Index of the next row to return from the current batch
+    offset: usize,
+    exhausted: bool,
+}
+
+impl ResultCursor {
+    /// Create a cursor over the results of an execution plan. The plan is dropped with the cursor.
+    pub fn new(relation: Box<SimpleRelation>, permit: Option<AdmissionPermit>) -> Self {
+        let relation = Box::into_raw(Box::new(relation));
+        // the iterator borrows the relation, which lives until the cursor is dropped
+        let it = unsafe { (*relation).scan() };
+        ResultCursor {
+            it: Some(it),
+            relation,
+            _permit: permit,
+            current: None,
+            offset: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Fetch up to `n` rows. Fewer than `n` rows are only returned once the results are
+    /// exhausted, and an empty vector means there are no more rows. Fetching 0 rows is an error,
+    /// as the result couldn't be told apart from the end of the results.
+    pub fn fetch_next(&mut self, n: usize) -> Result<Vec<Row>> {
+        if n == 0 {
+            return Err(DataFusionError::Execution(
+                "Cursors must fetch at least one row".to_string(),
+            ));
+        }
+        let mut rows: Vec<Row> = Vec::with_capacity(n);
+        while rows.len() < n {
+            let has_rows = match self.current {
+                Some(ref batch) => self.offset < batch.num_rows(),
+                None => false,
+            };
+            let batch = if has_rows {
+                self.current.clone().unwrap()
+            } else {
+                match self.next_batch()? {
+                    Some(batch) => batch,
+                    None => break,
+                }
+            };
+            while rows.len() < n && self.offset < batch.num_rows() {
+                rows.push(batch.row_slice(self.offset));
+                self.offset += 1;
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Returns true once all rows have been fetched
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted && match self.current {
+            Some(ref batch) => self.offset >= batch.num_rows(),
+            None => true,
+        }
+    }
+
+    /// Pull the next non-empty batch from the relation
+    fn next_batch(&mut self) -> Result<Option<Rc<RecordBatch>>> {
+        if self.exhausted {
+            return Ok(None);
+        }
+        loop {
+            match self.it.as_mut().unwrap().next() {
+                Some(Ok(batch)) => {
+                    if batch.num_rows() > 0 {
+                        self.current = Some(batch.clone());
+                        self.offset = 0;
+                        return Ok(Some(batch));
+                    }
+                }
+                Some(Err(e)) => return Err(e),
+                None => {
+                    self.exhausted = true;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ResultCursor {
+    fn drop(&mut self) {
+        self.it = None;
+        unsafe { drop(Box::from_raw(self.relation)) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::*;
+
+    #[test]
+    fn test_fetch_next() {
+        let mut ctx = ExecutionContext::local();
+        let uk_cities = ctx
+            .load_csv(
+                "./test/data/uk_cities.csv",
+                &Schema::new(vec![
+                    Field::new("city", DataType::Utf8, false),
+                    Field::new("lat", DataType::Float64, false),
+                    Field::new("lng", DataType::Float64, false),
+                ]),
+                false,
+                None,
+            )
+            .unwrap();
+        ctx.register("uk_cities", uk_cities);
+
+        let df = ctx.sql("SELECT city, lat FROM uk_cities").unwrap();
+        let mut cursor = ctx.open_cursor(df).unwrap();
+
+        assert!(cursor.fetch_next(0).is_err());
+        assert_eq!(10, cursor.fetch_next(10).unwrap().len());
+        assert_eq!(25, cursor.fetch_next(25).unwrap().len());
+        assert!(!cursor.is_exhausted());
+        let last = cursor.fetch_next(10).unwrap();
+        assert_eq!(2, last.len());
+        assert_eq!(2, last[0].len());
+        assert!(cursor.is_exhausted());
+        assert_eq!(0, cursor.fetch_next(10).unwrap().len());
+    }
+}
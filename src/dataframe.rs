@@ -32,6 +32,21 @@ pub trait DataFrame {
     /// Selection
     fn filter(&self, expr: Expr) -> Result<Rc<DataFrame>>;
 
+    /// Random sample of rows, where `fraction` is between 0.0 and 1.0. Providing a seed makes
+    /// the sample reproducible.
+    fn sample(&self, fraction: f64, seed: Option<u64>) -> Result<Rc<DataFrame>>;
+
+    /// Keep one row for each distinct value of the key expressions. The sort expressions decide
+    /// which row of each set of duplicates is kept, e.g. the latest row by a timestamp column
+    /// with `DeduplicateKeep::Last`. Without sort expressions the first or last row in input
+    /// order is kept.
+    fn deduplicate(
+        &self,
+        keys: Vec<Expr>,
+        keep: DeduplicateKeep,
+        order_by: Vec<Expr>,
+    ) -> Result<Rc<DataFrame>>;
+
     /// Return an expression representing the specified column
     fn col(&self, column_name: &str) -> Result<Expr>;
 
@@ -91,10 +106,50 @@ impl DataFrame for DF {
         Ok(Rc::new(self.with_plan(Rc::new(plan))))
     }
 
+    fn sample(&self, fraction: f64, seed: Option<u64>) -> Result<Rc<DataFrame>> {
+        // NaN compares false with both bounds, so it is rejected explicitly
+        if fraction.is_nan() || fraction < 0.0 || fraction > 1.0 {
+            return Err(DataFusionError::Execution(format!(
+                "Sample fraction must be between 0.0 and 1.0 but was {}",
+                fraction
+            )));
+        }
+
+        let plan = LogicalPlan::Sample {
+            method: SampleMethod::Bernoulli,
+            fraction,
+            seed,
+            input: self.plan.clone(),
+            schema: self.plan.schema().clone(),
+        };
+
+        Ok(Rc::new(self.with_plan(Rc::new(plan))))
+    }
+
+    fn deduplicate(
+        &self,
+        keys: Vec<Expr>,
+        keep: DeduplicateKeep,
+        order_by: Vec<Expr>,
+    ) -> Result<Rc<DataFrame>> {
+        let plan = LogicalPlan::Deduplicate {
+            input: self.plan.clone(),
+            keys,
+            order_by,
+            keep,
+            schema: self.plan.schema().clone(),
+        };
+
+        Ok(Rc::new(self.with_plan(Rc::new(plan))))
+    }
+
     fn col(&self, column_name: &str) -> Result<Expr> {
         match self.plan.schema().column_with_name(column_name) {
             Some((i, _)) => Ok(Expr::Column(i)),
-            _ => Err(ExecutionError::InvalidColumn(column_name.to_string())),
+            _ => Err(DataFusionError::Schema(format!(
+                "Invalid column '{}'",
+                column_name
+            ))),
         }
     }
 
@@ -16,7 +16,6 @@
 
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::str;
 
 //use arrow::array::*;
 use arrow::datatypes::*;
@@ -57,9 +56,10 @@ pub fn get_value(column: &Array, index: usize) -> ScalarValue {
         ArrayData::UInt16(ref v) => ScalarValue::UInt16(*v.get(index)),
         ArrayData::UInt32(ref v) => ScalarValue::UInt32(*v.get(index)),
         ArrayData::UInt64(ref v) => ScalarValue::UInt64(*v.get(index)),
-        ArrayData::Utf8(ref data) => ScalarValue::Utf8(Rc::new(String::from(
-            str::from_utf8(data.get(index)).unwrap(),
-        ))),
+        // binary columns are stored as Utf8 so the bytes are not always valid utf8
+        ArrayData::Utf8(ref data) => ScalarValue::Utf8(Rc::new(
+            String::from_utf8_lossy(data.get(index)).into_owned(),
+        )),
         ArrayData::Struct(ref v) => {
             // v is Vec<ArrayData>
             // each field has its own ArrayData e.g. lat, lon so we want to get a value from each (but it's recursive)
@@ -123,3 +123,54 @@ impl Iterator for DataSourceIterator {
         self.ds.borrow_mut().next()
     }
 }
+
+/// What a scan should do with rows that are malformed or contain values that can't be parsed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanErrorPolicy {
+    /// Fail the query on the first bad row
+    Fail,
+    /// Drop bad rows and count them
+    Skip,
+    /// Replace values that cannot be parsed with nulls. Rows that cannot be read at all are
+    /// skipped.
+    ReplaceWithNull,
+}
+
+/// Maximum number of warnings that are kept, so a badly formatted file doesn't use unbounded
+/// memory. Counts are always accurate.
+const MAX_SCAN_WARNINGS: usize = 100;
+
+/// Counts of bad data encountered by scans
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanMetrics {
+    pub rows_skipped: usize,
+    pub values_replaced: usize,
+    pub warnings: Vec<String>,
+}
+
+impl ScanMetrics {
+    pub fn skip_row(&mut self, warning: String) {
+        self.rows_skipped += 1;
+        self.add_warning(warning);
+    }
+
+    pub fn replace_value(&mut self, warning: String) {
+        self.values_replaced += 1;
+        self.add_warning(warning);
+    }
+
+    /// Add the counts and warnings of another scan
+    pub fn merge(&mut self, other: ScanMetrics) {
+        self.rows_skipped += other.rows_skipped;
+        self.values_replaced += other.values_replaced;
+        for warning in other.warnings {
+            self.add_warning(warning);
+        }
+    }
+
+    fn add_warning(&mut self, warning: String) {
+        if self.warnings.len() < MAX_SCAN_WARNINGS {
+            self.warnings.push(warning);
+        }
+    }
+}
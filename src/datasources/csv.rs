@@ -14,8 +14,9 @@
 
 //! CSV Support
 
+use std::cell::RefCell;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufReader, Write};
 use std::rc::Rc;
 
 use arrow::array::ListArray;
@@ -30,12 +31,17 @@ use csv::{StringRecord, StringRecordsIntoIter};
 use super::super::errors::*;
 use super::super::types::*;
 use super::common::*;
+use super::infer::ValueFormats;
+use super::output::FileOutput;
 
 pub struct CsvFile {
     schema: Rc<Schema>,
     projection: Option<Vec<usize>>,
     record_iter: StringRecordsIntoIter<BufReader<File>>,
     batch_size: usize,
+    error_policy: ScanErrorPolicy,
+    metrics: Rc<RefCell<ScanMetrics>>,
+    formats: Rc<ValueFormats>,
 }
 
 impl CsvFile {
@@ -55,60 +61,284 @@ impl CsvFile {
             projection,
             record_iter,
             batch_size: 1024,
+            error_policy: ScanErrorPolicy::Fail,
+            metrics: Rc::new(RefCell::new(ScanMetrics::default())),
+            formats: Rc::new(ValueFormats::default()),
         })
     }
 
     pub fn set_batch_size(&mut self, batch_size: usize) {
         self.batch_size = batch_size
     }
+
+    /// Set the policy for rows that are malformed or contain values that cannot be parsed, and
+    /// the metrics that skipped rows and replaced values are reported to
+    pub fn set_error_policy(&mut self, policy: ScanErrorPolicy, metrics: Rc<RefCell<ScanMetrics>>) {
+        self.error_policy = policy;
+        self.metrics = metrics;
+    }
+
+    /// Set the tokens that Boolean columns accept and the formats that Int64 columns accept
+    /// timestamps in
+    pub fn set_value_formats(&mut self, formats: Rc<ValueFormats>) {
+        self.formats = formats;
+    }
+
+    /// Get the value that a boolean token or timestamp should be parsed as instead, for values
+    /// that aren't already in the form that their column's type is parsed from
+    fn format_value(&self, i: usize, s: &str) -> Option<String> {
+        if s.is_empty() {
+            return None;
+        }
+        match *self.schema.column(i).data_type() {
+            DataType::Boolean if s.parse::<bool>().is_err() => {
+                self.formats.parse_bool(s).map(|b| b.to_string())
+            }
+            DataType::Int64 if s.parse::<i64>().is_err() => {
+                self.formats.parse_timestamp(s).map(|ts| ts.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Rewrite the boolean tokens and timestamps in the projected values of a row
+    fn apply_formats(&self, row: StringRecord, projection: &[usize]) -> StringRecord {
+        let formatted: Vec<Option<String>> = (0..row.len())
+            .map(|i| match row.get(i) {
+                Some(s) if projection.contains(&i) => self.format_value(i, s),
+                _ => None,
+            })
+            .collect();
+        if formatted.iter().all(|v| v.is_none()) {
+            return row;
+        }
+        let mut record = StringRecord::with_capacity(row.as_slice().len(), row.len());
+        for (s, v) in row.iter().zip(formatted.iter()) {
+            match *v {
+                Some(ref v) => record.push_field(v),
+                None => record.push_field(s),
+            }
+        }
+        record.set_position(row.position().cloned());
+        record
+    }
+
+    /// Check that every projected value in a row can be parsed as the type of its column
+    fn check_row(&self, row: &StringRecord, projection: &[usize]) -> ::std::result::Result<(), String> {
+        for &i in projection {
+            let s = match row.get(i) {
+                Some(s) => s,
+                None => return Err(format!("{} missing value for column {}", position(row), i)),
+            };
+            if s.is_empty() {
+                continue;
+            }
+            let valid = match self.schema.column(i).data_type() {
+                DataType::Boolean => s.parse::<bool>().is_ok(),
+                DataType::Int8 => s.parse::<i8>().is_ok(),
+                DataType::Int16 => s.parse::<i16>().is_ok(),
+                DataType::Int32 => s.parse::<i32>().is_ok(),
+                DataType::Int64 => s.parse::<i64>().is_ok(),
+                DataType::UInt8 => s.parse::<u8>().is_ok(),
+                DataType::UInt16 => s.parse::<u16>().is_ok(),
+                DataType::UInt32 => s.parse::<u32>().is_ok(),
+                DataType::UInt64 => s.parse::<u64>().is_ok(),
+                DataType::Float16 | DataType::Float32 => s.parse::<f32>().is_ok(),
+                DataType::Float64 => s.parse::<f64>().is_ok(),
+                _ => true,
+            };
+            if !valid {
+                return Err(format!(
+                    "{} failed to parse value '{}' as {:?} for column {}",
+                    position(row),
+                    s,
+                    self.schema.column(i).data_type(),
+                    i
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
-/// Built an Arrow array from one column in a batch of CSV records
+/// Describe the location of a record for error messages
+fn position(row: &StringRecord) -> String {
+    match row.position() {
+        Some(p) => format!("CSV line {}", p.line()),
+        None => "CSV record".to_string(),
+    }
+}
+
+/// Built an Arrow array from one column in a batch of CSV records. Values that cannot be parsed
+/// are an error unless the error policy replaces them with nulls.
 macro_rules! collect_column {
-    ($ROWS:expr, $COL_INDEX:expr, $TY:ty, $LEN:expr, $DEFAULT_VALUE:expr) => {{
+    ($ROWS:expr, $COL_INDEX:expr, $TY:ty, $LEN:expr, $DEFAULT_VALUE:expr, $POLICY:expr,
+     $WARNINGS:expr) => {{
         let mut bitmap = Bitmap::new($LEN);
         let mut null_count = 0;
         let mut b: Builder<$TY> = Builder::with_capacity($LEN);
         for row_index in 0..$LEN {
-            b.push(match $ROWS[row_index].get($COL_INDEX) {
+            let parsed = match $ROWS[row_index].get($COL_INDEX) {
                 Some(s) => if s.len() == 0 {
-                    null_count += 1;
-                    bitmap.clear(row_index);
-                    $DEFAULT_VALUE
+                    None
                 } else {
                     match s.parse::<$TY>() {
-                        Ok(v) => v,
-                        Err(e) => panic!(
-                            "Failed to parse value '{}' as {} at batch row {} column {}: {:?}",
-                            s,
-                            stringify!($TY),
-                            row_index,
-                            $COL_INDEX,
-                            e
-                        ),
+                        Ok(v) => Some(v),
+                        Err(e) => {
+                            let msg = format!(
+                                "{} failed to parse value '{}' as {} for column {}: {:?}",
+                                position(&$ROWS[row_index]),
+                                s,
+                                stringify!($TY),
+                                $COL_INDEX,
+                                e
+                            );
+                            if $POLICY != ScanErrorPolicy::ReplaceWithNull {
+                                return Err(DataFusionError::Schema(msg));
+                            }
+                            $WARNINGS.push(msg);
+                            None
+                        }
                     }
                 },
-                None => panic!(
-                    "CSV file missing value at batch  row {}, column {}",
-                    row_index, $COL_INDEX
-                ),
+                None => {
+                    let msg = format!(
+                        "{} missing value for column {}",
+                        position(&$ROWS[row_index]),
+                        $COL_INDEX
+                    );
+                    if $POLICY != ScanErrorPolicy::ReplaceWithNull {
+                        return Err(DataFusionError::Schema(msg));
+                    }
+                    $WARNINGS.push(msg);
+                    None
+                }
+            };
+            b.push(match parsed {
+                Some(v) => v,
+                None => {
+                    null_count += 1;
+                    bitmap.clear(row_index);
+                    $DEFAULT_VALUE
+                }
             })
         }
         let data = ArrayData::from(b.finish());
-        Value::Column(Rc::new(Array::with_nulls($LEN, data, null_count, bitmap)))
+        Ok(Value::Column(Rc::new(Array::with_nulls(
+            $LEN,
+            data,
+            null_count,
+            bitmap,
+        ))))
     }};
 }
 
+impl CsvFile {
+    /// Build the projected columns for a batch of rows
+    fn build_columns(
+        &self,
+        rows: &Vec<StringRecord>,
+        projection: &[usize],
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<Value>> {
+        let policy = self.error_policy;
+        let mut columns = Vec::with_capacity(self.schema.columns().len());
+        for (i, c) in self.schema.columns().iter().enumerate() {
+            if !projection.contains(&i) {
+                // not in the projection
+                columns.push(Value::Scalar(Rc::new(ScalarValue::Null)));
+                continue;
+            }
+            let column: Result<Value> = match c.data_type() {
+                DataType::Boolean => {
+                    collect_column!(rows, i, bool, rows.len(), false, policy, warnings)
+                }
+                DataType::Int8 => collect_column!(rows, i, i8, rows.len(), 0, policy, warnings),
+                DataType::Int16 => collect_column!(rows, i, i16, rows.len(), 0, policy, warnings),
+                DataType::Int32 => collect_column!(rows, i, i32, rows.len(), 0, policy, warnings),
+                DataType::Int64 => collect_column!(rows, i, i64, rows.len(), 0, policy, warnings),
+                DataType::UInt8 => collect_column!(rows, i, u8, rows.len(), 0, policy, warnings),
+                DataType::UInt16 => collect_column!(rows, i, u16, rows.len(), 0, policy, warnings),
+                DataType::UInt32 => collect_column!(rows, i, u32, rows.len(), 0, policy, warnings),
+                DataType::UInt64 => collect_column!(rows, i, u64, rows.len(), 0, policy, warnings),
+                DataType::Float16 => {
+                    collect_column!(rows, i, f32, rows.len(), 0_f32, policy, warnings)
+                }
+                DataType::Float32 => {
+                    collect_column!(rows, i, f32, rows.len(), 0_f32, policy, warnings)
+                }
+                DataType::Float64 => {
+                    collect_column!(rows, i, f64, rows.len(), 0_f64, policy, warnings)
+                }
+                DataType::Utf8 => {
+                    let mut builder: ListBuilder<u8> = ListBuilder::with_capacity(rows.len());
+                    let mut bitmap = Bitmap::new(rows.len());
+                    let mut null_count = 0;
+                    for j in 0..rows.len() {
+                        let row = &rows[j];
+                        match row.get(i) {
+                            Some(s) => {
+                                builder.push(s.as_bytes());
+                            }
+                            _ => {
+                                //NOTE this never happens because the csv crate treats missing strings as empty strings
+                                bitmap.clear(j);
+                                null_count += 1;
+                            }
+                        }
+                    }
+                    let buffer = builder.finish();
+                    Ok(Value::Column(Rc::new(Array::with_nulls(
+                        rows.len(),
+                        ArrayData::Utf8(ListArray::from(buffer)),
+                        null_count,
+                        bitmap,
+                    ))))
+                }
+                _ => unimplemented!("CSV does not support data type {:?}", c.data_type()),
+            };
+            columns.push(column?);
+        }
+        Ok(columns)
+    }
+}
+
 impl DataSource for CsvFile {
     fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
+        let projection = match self.projection {
+            Some(ref v) => v.clone(),
+            None => self
+                .schema
+                .columns()
+                .iter()
+                .enumerate()
+                .map(|(i, _)| i)
+                .collect(),
+        };
+
         // read a batch of rows into memory
         let mut rows: Vec<StringRecord> = Vec::with_capacity(self.batch_size);
-        for _ in 0..self.batch_size {
+        while rows.len() < self.batch_size {
             match self.record_iter.next() {
                 Some(Ok(r)) => {
+                    let r = self.apply_formats(r, &projection);
+                    // rows are only checked up front when bad rows are being skipped
+                    if self.error_policy == ScanErrorPolicy::Skip {
+                        if let Err(msg) = self.check_row(&r, &projection) {
+                            self.metrics.borrow_mut().skip_row(msg);
+                            continue;
+                        }
+                    }
                     rows.push(r);
                 }
-                Some(Err(e)) => panic!("{:?}", e),
+                Some(Err(e)) => {
+                    // malformed records and invalid utf8 can't be partially recovered
+                    let msg = format!("Failed to read CSV record: {}", e);
+                    if self.error_policy == ScanErrorPolicy::Fail {
+                        return Some(Err(DataFusionError::Execution(msg)));
+                    }
+                    self.metrics.borrow_mut().skip_row(msg);
+                }
                 None => break,
             }
         }
@@ -119,70 +349,17 @@ impl DataSource for CsvFile {
 
         //println!("Loaded {} rows", rows.len());
 
-        let column_with_index = self.schema.columns().iter().enumerate();
-
-        let projection = match self.projection {
-            Some(ref v) => v.clone(),
-            None => self
-                .schema
-                .columns()
-                .iter()
-                .enumerate()
-                .map(|(i, _)| i)
-                .collect(),
+        let mut warnings = vec![];
+        let columns = match self.build_columns(&rows, &projection, &mut warnings) {
+            Ok(columns) => columns,
+            Err(e) => return Some(Err(e)),
         };
-
-        let columns: Vec<Value> = column_with_index
-            .map(|(i, c)| {
-                if projection.contains(&i) {
-                    match c.data_type() {
-                        DataType::Boolean => collect_column!(rows, i, bool, rows.len(), false),
-                        DataType::Int8 => collect_column!(rows, i, i8, rows.len(), 0),
-                        DataType::Int16 => collect_column!(rows, i, i16, rows.len(), 0),
-                        DataType::Int32 => collect_column!(rows, i, i32, rows.len(), 0),
-                        DataType::Int64 => collect_column!(rows, i, i64, rows.len(), 0),
-                        DataType::UInt8 => collect_column!(rows, i, u8, rows.len(), 0),
-                        DataType::UInt16 => collect_column!(rows, i, u16, rows.len(), 0),
-                        DataType::UInt32 => collect_column!(rows, i, u32, rows.len(), 0),
-                        DataType::UInt64 => collect_column!(rows, i, u64, rows.len(), 0),
-                        DataType::Float16 => collect_column!(rows, i, f32, rows.len(), 0_f32),
-                        DataType::Float32 => collect_column!(rows, i, f32, rows.len(), 0_f32),
-                        DataType::Float64 => collect_column!(rows, i, f64, rows.len(), 0_f64),
-                        DataType::Utf8 => {
-                            let mut builder: ListBuilder<u8> =
-                                ListBuilder::with_capacity(rows.len());
-                            let mut bitmap = Bitmap::new(rows.len());
-                            let mut null_count = 0;
-                            for j in 0..rows.len() {
-                                let row = &rows[j];
-                                match row.get(i) {
-                                    Some(s) => {
-                                        builder.push(s.as_bytes());
-                                    }
-                                    _ => {
-                                        //NOTE this never happens because the csv crate treats missing strings as empty strings
-                                        bitmap.clear(j);
-                                        null_count += 1;
-                                    }
-                                }
-                            }
-                            let buffer = builder.finish();
-                            Value::Column(Rc::new(Array::with_nulls(
-                                rows.len(),
-                                ArrayData::Utf8(ListArray::from(buffer)),
-                                null_count,
-                                bitmap,
-                            )))
-                        }
-                        _ => unimplemented!("CSV does not support data type {:?}", c.data_type()),
-                    }
-                } else {
-                    // not in the projection
-                    //println!("Not loading column {} at index {}", c.name(), i);
-                    Value::Scalar(Rc::new(ScalarValue::Null))
-                }
-            })
-            .collect();
+        if !warnings.is_empty() {
+            let mut metrics = self.metrics.borrow_mut();
+            for msg in warnings {
+                metrics.replace_value(msg);
+            }
+        }
 
         Some(Ok(Rc::new(DefaultRecordBatch {
             schema: self.schema.clone(),
@@ -196,11 +373,95 @@ impl DataSource for CsvFile {
     }
 }
 
+/// Options for writing CSV files
+#[derive(Debug, Clone)]
+pub struct CsvWriteOptions {
+    /// Whether the first line of each file has the column names
+    pub has_header: bool,
+    pub delimiter: u8,
+    /// Written in place of null values. Strings that are equal to it are quoted.
+    pub null_string: String,
+}
+
+impl Default for CsvWriteOptions {
+    fn default() -> Self {
+        CsvWriteOptions {
+            has_header: false,
+            delimiter: b',',
+            null_string: String::new(),
+        }
+    }
+}
+
 pub struct CsvWriter {
-    pub w: BufWriter<File>,
+    w: FileOutput,
+    options: CsvWriteOptions,
 }
 
 impl CsvWriter {
+    pub fn new(w: FileOutput, options: CsvWriteOptions) -> Self {
+        CsvWriter { w, options }
+    }
+
+    /// Write a line with the names of the columns
+    pub fn write_header(&mut self, schema: &Schema) {
+        for (j, field) in schema.columns().iter().enumerate() {
+            if j > 0 {
+                self.write_delimiter();
+            }
+            self.write_string(field.name().as_bytes());
+        }
+        self.write_bytes(b"\n");
+    }
+
+    /// Write the rows of a batch and return the number of rows written
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> usize {
+        for i in 0..batch.num_rows() {
+            for j in 0..batch.num_columns() {
+                if j > 0 {
+                    self.write_delimiter();
+                }
+                match *batch.column(j) {
+                    Value::Scalar(ref v) => self.write_scalar(v),
+                    Value::Column(ref v) if !is_set(v, i) => self.write_null(),
+                    Value::Column(ref v) => match v.data() {
+                        ArrayData::Boolean(ref v) => self.write_bool(v.get(i)),
+                        ArrayData::Float32(ref v) => self.write_f32(v.get(i)),
+                        ArrayData::Float64(ref v) => self.write_f64(v.get(i)),
+                        ArrayData::Int8(ref v) => self.write_i8(v.get(i)),
+                        ArrayData::Int16(ref v) => self.write_i16(v.get(i)),
+                        ArrayData::Int32(ref v) => self.write_i32(v.get(i)),
+                        ArrayData::Int64(ref v) => self.write_i64(v.get(i)),
+                        ArrayData::UInt8(ref v) => self.write_u8(v.get(i)),
+                        ArrayData::UInt16(ref v) => self.write_u16(v.get(i)),
+                        ArrayData::UInt32(ref v) => self.write_u32(v.get(i)),
+                        ArrayData::UInt64(ref v) => self.write_u64(v.get(i)),
+                        ArrayData::Utf8(ref data) => self.write_string(data.get(i)),
+                        ArrayData::Struct(ref v) => {
+                            let fields = v.iter().map(|arr| get_value(&arr, i)).collect();
+                            self.write_bytes(
+                                format!("{}", ScalarValue::Struct(fields)).as_bytes(),
+                            );
+                        }
+                    },
+                }
+            }
+            self.write_bytes(b"\n");
+        }
+        batch.num_rows()
+    }
+
+    /// Write any buffered rows to the file
+    pub fn flush(&mut self) -> Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+
+    /// Write any buffered rows and finish the file
+    pub fn close(self) -> Result<()> {
+        self.w.finish()
+    }
+
     pub fn write_scalar(&mut self, v: &ScalarValue) {
         match *v {
             ScalarValue::Int8(vv) => self.write_bytes(format!("{}", vv).as_bytes()),
@@ -213,6 +474,8 @@ impl CsvWriter {
             ScalarValue::UInt64(vv) => self.write_bytes(format!("{}", vv).as_bytes()),
             ScalarValue::Float32(vv) => self.write_bytes(format!("{}", vv).as_bytes()),
             ScalarValue::Float64(vv) => self.write_bytes(format!("{}", vv).as_bytes()),
+            ScalarValue::Utf8(ref vv) => self.write_string(vv.as_bytes()),
+            ScalarValue::Null => self.write_null(),
             _ => self.write_bytes(format!("{:?}", v).as_bytes()),
         }
     }
@@ -253,13 +516,48 @@ impl CsvWriter {
     pub fn write_bytes(&mut self, s: &[u8]) {
         self.w.write(s).unwrap();
     }
+
+    /// Write a string, in quotes when it contains the delimiter, a quote or a line break, or
+    /// would otherwise be read back as null
+    pub fn write_string(&mut self, s: &[u8]) {
+        let delimiter = self.options.delimiter;
+        let quoted = s == self.options.null_string.as_bytes()
+            || s
+                .iter()
+                .any(|&b| b == delimiter || b == b'"' || b == b'\n' || b == b'\r');
+        if quoted {
+            let escaped = String::from_utf8_lossy(s).replace('"', "\"\"");
+            self.write_bytes(format!("\"{}\"", escaped).as_bytes());
+        } else {
+            self.write_bytes(s);
+        }
+    }
+
+    fn write_null(&mut self) {
+        self.w.write(self.options.null_string.as_bytes()).unwrap();
+    }
+
+    fn write_delimiter(&mut self) {
+        let delimiter = [self.options.delimiter];
+        self.write_bytes(&delimiter);
+    }
+}
+
+/// Check whether the value at an index of an array is non-null
+fn is_set(array: &Array, index: usize) -> bool {
+    match *array.validity_bitmap() {
+        Some(ref bitmap) => bitmap.is_set(index),
+        None => true,
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use super::super::output::FileCompression;
     use super::*;
     use std::cell::RefCell;
+    use std::io::Read;
 
     #[test]
     fn test_csv() {
@@ -325,4 +623,86 @@ mod tests {
         });
         assert_eq!(37, row_count);
     }
+
+    fn open_bad_rows(policy: ScanErrorPolicy) -> (CsvFile, Rc<RefCell<ScanMetrics>>) {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("score", DataType::Float64, false),
+        ]);
+        let file = File::open("test/data/bad_rows.csv").unwrap();
+        let mut csv = CsvFile::open(file, Rc::new(schema), true, None).unwrap();
+        let metrics = Rc::new(RefCell::new(ScanMetrics::default()));
+        csv.set_error_policy(policy, metrics.clone());
+        (csv, metrics)
+    }
+
+    #[test]
+    fn test_error_policy_fail() {
+        let (mut csv, _) = open_bad_rows(ScanErrorPolicy::Fail);
+        assert!(csv.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_error_policy_skip() {
+        let (mut csv, metrics) = open_bad_rows(ScanErrorPolicy::Skip);
+        let batch = csv.next().unwrap().unwrap();
+        assert_eq!(2, batch.num_rows());
+        assert_eq!(2, metrics.borrow().rows_skipped);
+        assert_eq!(0, metrics.borrow().values_replaced);
+        assert_eq!(2, metrics.borrow().warnings.len());
+    }
+
+    #[test]
+    fn test_error_policy_replace_with_null() {
+        let (mut csv, metrics) = open_bad_rows(ScanErrorPolicy::ReplaceWithNull);
+        let batch = csv.next().unwrap().unwrap();
+        assert_eq!(3, batch.num_rows());
+        assert_eq!(1, metrics.borrow().rows_skipped);
+        assert_eq!(1, metrics.borrow().values_replaced);
+        match batch.column(1) {
+            Value::Column(ref array) => assert_eq!(1, array.null_count()),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_write_options() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let batch = DefaultRecordBatch {
+            schema: schema.clone(),
+            data: vec![
+                Value::Column(Rc::new(Array::from(vec![1_i64, 2, 3]))),
+                Value::Column(Rc::new(Array::from(vec![
+                    "a;b".to_string(),
+                    "-".to_string(),
+                    "say \"hi\"".to_string(),
+                ]))),
+            ],
+            row_count: 3,
+        };
+        let path = "./target/test_csv_write_options.csv";
+        let options = CsvWriteOptions {
+            has_header: true,
+            delimiter: b';',
+            null_string: "-".to_string(),
+        };
+        let output = FileOutput::create(path, FileCompression::Uncompressed).unwrap();
+        let mut w = CsvWriter::new(output, options);
+        w.write_header(&schema);
+        assert_eq!(3, w.write_batch(&batch));
+        w.close().unwrap();
+
+        let mut contents = String::new();
+        File::open(path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(
+            "id;name\n1;\"a;b\"\n2;\"-\"\n3;\"say \"\"hi\"\"\"\n",
+            contents
+        );
+    }
 }
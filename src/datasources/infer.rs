@@ -0,0 +1,468 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Schema inference for CSV and ndjson files
+//!
+//! A sample of rows is read and each column gets the most specific type that every sampled
+//! value can be read as. Each column also gets a confidence between 0 and 1, which is low when
+//! few values were sampled or when a column falls back to `Utf8` although most of its values
+//! look like another type, so that misinferred columns can be caught before running a query.
+
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+use arrow::datatypes::*;
+
+use csv;
+use json;
+
+use super::super::errors::*;
+use super::super::functions::datetime::parse_timestamp;
+
+/// Columns with fewer non-null sampled values than this have a proportionally lower confidence
+const MIN_CONFIDENT_VALUES: usize = 10;
+
+/// How text values are read as booleans and timestamps, both when inferring the schema of a CSV
+/// file and when reading one. Timestamps are read as Int64 milliseconds since the epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueFormats {
+    /// Values that are read as true, ignoring case
+    pub true_tokens: Vec<String>,
+    /// Values that are read as false, ignoring case
+    pub false_tokens: Vec<String>,
+    /// Timestamp formats in the order they are tried, see `parse_timestamp`
+    pub timestamp_formats: Vec<String>,
+}
+
+impl Default for ValueFormats {
+    fn default() -> Self {
+        ValueFormats {
+            true_tokens: vec!["true".to_string()],
+            false_tokens: vec!["false".to_string()],
+            timestamp_formats: vec![
+                "%Y-%m-%d %H:%M:%S".to_string(),
+                "%Y-%m-%dT%H:%M:%S".to_string(),
+                "%Y-%m-%d".to_string(),
+            ],
+        }
+    }
+}
+
+impl ValueFormats {
+    pub fn parse_bool(&self, s: &str) -> Option<bool> {
+        if self.true_tokens.iter().any(|t| t.eq_ignore_ascii_case(s)) {
+            Some(true)
+        } else if self.false_tokens.iter().any(|t| t.eq_ignore_ascii_case(s)) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Parse a timestamp with the first format that matches it
+    pub fn parse_timestamp(&self, s: &str) -> Option<i64> {
+        self.timestamp_formats
+            .iter()
+            .filter_map(|f| parse_timestamp(s, f))
+            .next()
+    }
+}
+
+/// Options for schema inference
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferenceOptions {
+    /// Maximum number of rows to read
+    pub sample_rows: usize,
+    /// Infer Float64 for columns that mix integers and floats, rather than Utf8
+    pub promote_integers: bool,
+    /// Boolean tokens and timestamp formats, which only apply to CSV files because JSON has its
+    /// own booleans and JSON strings are always read as text
+    pub formats: ValueFormats,
+}
+
+impl Default for InferenceOptions {
+    fn default() -> Self {
+        InferenceOptions {
+            sample_rows: 1000,
+            promote_integers: true,
+            formats: ValueFormats::default(),
+        }
+    }
+}
+
+/// The inferred type of one column
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredColumn {
+    pub name: String,
+    pub data_type: DataType,
+    /// For a timestamp column, the format that read the most sampled values
+    pub timestamp_format: Option<String>,
+    /// How likely the type is to be right, from 0 to 1
+    pub confidence: f64,
+    /// Number of sampled values that were not null
+    pub values: usize,
+    /// Number of sampled values that were null or missing
+    pub nulls: usize,
+}
+
+/// The result of schema inference
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredSchema {
+    pub columns: Vec<InferredColumn>,
+    pub rows_sampled: usize,
+}
+
+impl InferredSchema {
+    /// The schema to read the file with. Every column is nullable because the sample may have
+    /// missed null values.
+    pub fn schema(&self) -> Schema {
+        Schema::new(
+            self.columns
+                .iter()
+                .map(|c| Field::new(&c.name, c.data_type.clone(), true))
+                .collect(),
+        )
+    }
+
+    /// The columns with a confidence below the threshold
+    pub fn low_confidence(&self, threshold: f64) -> Vec<&InferredColumn> {
+        self.columns
+            .iter()
+            .filter(|c| c.confidence < threshold)
+            .collect()
+    }
+}
+
+impl fmt::Display for InferredSchema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Inferred from {} rows:", self.rows_sampled)?;
+        for c in &self.columns {
+            write!(f, "  {}: {:?}", c.name, c.data_type)?;
+            if let Some(ref format) = c.timestamp_format {
+                write!(f, " (timestamp '{}')", format)?;
+            }
+            writeln!(
+                f,
+                ", confidence {:.2}, {} values, {} nulls",
+                c.confidence, c.values, c.nulls
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts of the types that the sampled values of a column could be read as
+#[derive(Debug, Default)]
+struct ColumnStats {
+    values: usize,
+    booleans: usize,
+    integers: usize,
+    /// Numbers that are not integers
+    floats: usize,
+    /// Values that at least one timestamp format can read
+    timestamps: usize,
+    /// Number of values that each timestamp format can read
+    format_matches: Vec<usize>,
+}
+
+impl ColumnStats {
+    fn add_text(&mut self, s: &str, formats: &ValueFormats) {
+        self.values += 1;
+        if formats.parse_bool(s).is_some() {
+            self.booleans += 1;
+        }
+        if s.parse::<i64>().is_ok() {
+            self.integers += 1;
+        } else if s.parse::<f64>().is_ok() {
+            self.floats += 1;
+        }
+        self.format_matches
+            .resize(formats.timestamp_formats.len(), 0);
+        let mut timestamp = false;
+        for (i, format) in formats.timestamp_formats.iter().enumerate() {
+            if parse_timestamp(s, format).is_some() {
+                self.format_matches[i] += 1;
+                timestamp = true;
+            }
+        }
+        if timestamp {
+            self.timestamps += 1;
+        }
+    }
+
+    fn add_json(&mut self, value: &json::JsonValue) {
+        self.values += 1;
+        if value.is_boolean() {
+            self.booleans += 1;
+        } else if value.as_i64().is_some() {
+            self.integers += 1;
+        } else if value.is_number() {
+            self.floats += 1;
+        }
+    }
+
+    fn infer(&self, name: &str, rows: usize, options: &InferenceOptions) -> InferredColumn {
+        let numbers = self.integers + self.floats;
+        let mut timestamp_format = None;
+        let (data_type, agreeing) = if self.values == 0 {
+            (DataType::Utf8, 0)
+        } else if self.booleans == self.values {
+            (DataType::Boolean, self.values)
+        } else if self.integers == self.values {
+            (DataType::Int64, self.values)
+        } else if numbers == self.values && options.promote_integers {
+            (DataType::Float64, self.values)
+        } else if self.floats == self.values {
+            (DataType::Float64, self.values)
+        } else if self.timestamps == self.values {
+            // ties go to the format that is tried first
+            let (_, i) = self
+                .format_matches
+                .iter()
+                .enumerate()
+                .map(|(i, n)| (*n, ::std::cmp::Reverse(i)))
+                .max()
+                .unwrap();
+            timestamp_format = Some(options.formats.timestamp_formats[i.0].clone());
+            (DataType::Int64, self.values)
+        } else {
+            // values that don't look like any other type support falling back to text
+            let best = *[self.booleans, numbers, self.timestamps]
+                .iter()
+                .max()
+                .unwrap();
+            (DataType::Utf8, self.values - best)
+        };
+
+        let confidence = if self.values == 0 {
+            0.0
+        } else {
+            let evidence =
+                self.values.min(MIN_CONFIDENT_VALUES) as f64 / MIN_CONFIDENT_VALUES as f64;
+            agreeing as f64 / self.values as f64 * evidence
+        };
+        InferredColumn {
+            name: name.to_string(),
+            data_type,
+            timestamp_format,
+            confidence,
+            values: self.values,
+            nulls: rows - self.values,
+        }
+    }
+}
+
+/// Infer the schema of CSV data. Without a header row the columns are named `column_1`,
+/// `column_2` and so on. Empty values are nulls.
+pub fn infer_csv_schema<R: Read>(
+    reader: R,
+    has_header: bool,
+    options: &InferenceOptions,
+) -> Result<InferredSchema> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .has_headers(has_header)
+        .flexible(true)
+        .from_reader(reader);
+    let mut names: Vec<String> = if has_header {
+        csv_reader
+            .headers()
+            .map_err(|e| DataFusionError::Execution(format!("Failed to read CSV header: {}", e)))?
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let mut stats: Vec<ColumnStats> = vec![];
+    let mut rows = 0;
+    for record in csv_reader.records().take(options.sample_rows) {
+        let record = record
+            .map_err(|e| DataFusionError::Execution(format!("Failed to read CSV record: {}", e)))?;
+        rows += 1;
+        for (i, value) in record.iter().enumerate() {
+            if i >= stats.len() {
+                stats.resize_with(i + 1, ColumnStats::default);
+            }
+            if !value.is_empty() {
+                stats[i].add_text(value, &options.formats);
+            }
+        }
+    }
+    while names.len() < stats.len() {
+        let n = names.len() + 1;
+        names.push(format!("column_{}", n));
+    }
+
+    let columns = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| match stats.get(i) {
+            Some(s) => s.infer(name, rows, options),
+            None => ColumnStats::default().infer(name, rows, options),
+        })
+        .collect();
+    Ok(InferredSchema {
+        columns,
+        rows_sampled: rows,
+    })
+}
+
+/// Infer the schema of newline-delimited JSON data. Columns are ordered by where their field
+/// first appears, and records without a field are null for that column.
+pub fn infer_ndjson_schema<R: Read>(
+    reader: R,
+    options: &InferenceOptions,
+) -> Result<InferredSchema> {
+    let mut names: Vec<String> = vec![];
+    let mut stats: Vec<ColumnStats> = vec![];
+    let mut rows = 0;
+    for line in BufReader::new(reader).lines() {
+        if rows == options.sample_rows {
+            break;
+        }
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = json::parse(&line)
+            .map_err(|e| DataFusionError::Execution(format!("Failed to parse JSON: {}", e)))?;
+        rows += 1;
+        for (name, value) in record.entries() {
+            let i = match names.iter().position(|n| n == name) {
+                Some(i) => i,
+                None => {
+                    names.push(name.to_string());
+                    stats.push(ColumnStats::default());
+                    names.len() - 1
+                }
+            };
+            if !value.is_null() {
+                stats[i].add_json(value);
+            }
+        }
+    }
+
+    let columns = names
+        .iter()
+        .zip(stats.iter())
+        .map(|(name, s)| s.infer(name, rows, options))
+        .collect();
+    Ok(InferredSchema {
+        columns,
+        rows_sampled: rows,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_csv_schema() {
+        let data = "id,price,active,created,name\n\
+                    1,1.5,true,2018-05-20,a\n\
+                    2,2,false,2018-05-21 10:30:00,b\n\
+                    3,,TRUE,2018-05-22,c\n";
+        let options = InferenceOptions::default();
+        let inferred = infer_csv_schema(data.as_bytes(), true, &options).unwrap();
+        assert_eq!(3, inferred.rows_sampled);
+        let types: Vec<DataType> = inferred
+            .columns
+            .iter()
+            .map(|c| c.data_type.clone())
+            .collect();
+        assert_eq!(
+            vec![
+                DataType::Int64,
+                DataType::Float64,
+                DataType::Boolean,
+                DataType::Int64,
+                DataType::Utf8,
+            ],
+            types
+        );
+        assert_eq!(1, inferred.columns[1].nulls);
+        assert_eq!(
+            Some("%Y-%m-%d".to_string()),
+            inferred.columns[3].timestamp_format
+        );
+        // confidence is lower when few values were sampled
+        assert_eq!(0.3, inferred.columns[4].confidence);
+
+        let options = InferenceOptions {
+            promote_integers: false,
+            formats: ValueFormats {
+                timestamp_formats: vec!["%Y-%m-%d %H:%M:%S".to_string(), "%Y-%m-%d".to_string()],
+                ..ValueFormats::default()
+            },
+            ..InferenceOptions::default()
+        };
+        let data = "2018-05-20,1.5\n2018-05-21,2\n";
+        let inferred = infer_csv_schema(data.as_bytes(), false, &options).unwrap();
+        assert_eq!("column_1", inferred.columns[0].name);
+        assert_eq!(DataType::Int64, inferred.columns[0].data_type);
+        // without promotion the mix of integers and floats falls back to text, which none of
+        // the values support
+        assert_eq!(DataType::Utf8, inferred.columns[1].data_type);
+        let low = inferred.low_confidence(0.01);
+        assert_eq!(1, low.len());
+        assert_eq!("column_2", low[0].name);
+    }
+
+    #[test]
+    fn test_infer_csv_schema_options() {
+        let options = InferenceOptions {
+            sample_rows: 2,
+            formats: ValueFormats {
+                true_tokens: vec!["yes".to_string()],
+                false_tokens: vec!["no".to_string()],
+                ..ValueFormats::default()
+            },
+            ..InferenceOptions::default()
+        };
+        // the third row is not sampled
+        let data = "flag\nYes\nno\nmaybe\n";
+        let inferred = infer_csv_schema(data.as_bytes(), true, &options).unwrap();
+        assert_eq!(2, inferred.rows_sampled);
+        assert_eq!(DataType::Boolean, inferred.columns[0].data_type);
+        assert_eq!(
+            "Inferred from 2 rows:\n  flag: Boolean, confidence 0.20, 2 values, 0 nulls\n",
+            inferred.to_string()
+        );
+    }
+
+    #[test]
+    fn test_infer_ndjson_schema() {
+        let data = "{\"a\": 1, \"b\": \"x\", \"c\": 1.5}\n\
+                    {\"a\": 2, \"c\": 2, \"d\": true}\n";
+        let inferred = infer_ndjson_schema(data.as_bytes(), &InferenceOptions::default()).unwrap();
+        let schema = inferred.schema();
+        let columns: Vec<(&str, &DataType)> = schema
+            .columns()
+            .iter()
+            .map(|f| (f.name().as_ref(), f.data_type()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a", &DataType::Int64),
+                ("b", &DataType::Utf8),
+                ("c", &DataType::Float64),
+                ("d", &DataType::Boolean),
+            ],
+            columns
+        );
+        assert_eq!(1, inferred.columns[1].nulls);
+    }
+}
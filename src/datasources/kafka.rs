@@ -0,0 +1,343 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Kafka Support
+//!
+//! Reads the messages of a Kafka topic as a stream of rows. Message payloads are either JSON
+//! objects or Avro records in the schema registry wire format, where each payload starts with
+//! a zero byte and the big endian id of the schema it was written with.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::rc::Rc;
+use std::str;
+use std::time::{Duration, Instant};
+
+use arrow::datatypes::Schema;
+use avro_rs;
+use avro_rs::types::Value as AvroValue;
+use byteorder::{BigEndian, ReadBytesExt};
+use json;
+use json::JsonValue;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::message::Message;
+use reqwest;
+
+use super::super::errors::*;
+use super::common::*;
+use super::ndjson::json_batch;
+use super::stream::StreamSource;
+
+/// How the payloads of messages are encoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayloadFormat {
+    /// A JSON object per message, with a field for each column
+    Json,
+    /// Avro records whose schemas are fetched from the schema registry at this URL
+    Avro { schema_registry_url: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct KafkaOptions {
+    /// Comma separated `host:port` pairs of the brokers to connect to
+    pub brokers: String,
+    pub topic: String,
+    /// Consumer group that reads the topic. Each scan continues from the offsets committed by
+    /// the group.
+    pub group_id: String,
+    pub format: PayloadFormat,
+    /// Longest time to wait for more messages before producing a partial batch
+    pub poll_timeout: Duration,
+    /// End scans when no messages arrive for this long. Scans never end by default.
+    pub idle_timeout: Option<Duration>,
+    /// Other consumer properties, as documented by librdkafka
+    pub properties: HashMap<String, String>,
+}
+
+impl KafkaOptions {
+    pub fn new(brokers: &str, topic: &str, group_id: &str, format: PayloadFormat) -> Self {
+        KafkaOptions {
+            brokers: brokers.to_string(),
+            topic: topic.to_string(),
+            group_id: group_id.to_string(),
+            format,
+            poll_timeout: Duration::from_millis(100),
+            idle_timeout: None,
+            properties: HashMap::new(),
+        }
+    }
+}
+
+/// A stream source that consumes a Kafka topic. Each column is read from the payload field of
+/// the same name, and missing fields are null.
+pub struct KafkaSource {
+    schema: Rc<Schema>,
+    options: KafkaOptions,
+    decoder: Rc<PayloadDecoder>,
+}
+
+impl KafkaSource {
+    pub fn new(schema: Rc<Schema>, options: KafkaOptions) -> Self {
+        let decoder = match options.format {
+            PayloadFormat::Json => PayloadDecoder::Json,
+            PayloadFormat::Avro {
+                ref schema_registry_url,
+            } => PayloadDecoder::Avro(SchemaRegistry::new(schema_registry_url)),
+        };
+        KafkaSource {
+            schema,
+            options,
+            decoder: Rc::new(decoder),
+        }
+    }
+}
+
+impl StreamSource for KafkaSource {
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn open(&self, batch_size: usize) -> Result<Rc<RefCell<DataSource>>> {
+        let mut config = ClientConfig::new();
+        for (key, value) in &self.options.properties {
+            config.set(key, value);
+        }
+        config
+            .set("bootstrap.servers", &self.options.brokers)
+            .set("group.id", &self.options.group_id);
+        let consumer: BaseConsumer = config
+            .create()
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        consumer
+            .subscribe(&[self.options.topic.as_str()])
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        Ok(Rc::new(RefCell::new(KafkaDataSource {
+            schema: self.schema.clone(),
+            consumer,
+            decoder: self.decoder.clone(),
+            batch_size,
+            poll_timeout: self.options.poll_timeout,
+            idle_timeout: self.options.idle_timeout,
+            last_message: Instant::now(),
+        })))
+    }
+}
+
+struct KafkaDataSource {
+    schema: Rc<Schema>,
+    consumer: BaseConsumer,
+    decoder: Rc<PayloadDecoder>,
+    batch_size: usize,
+    poll_timeout: Duration,
+    idle_timeout: Option<Duration>,
+    last_message: Instant,
+}
+
+impl DataSource for KafkaDataSource {
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
+        let mut rows: Vec<JsonValue> = Vec::with_capacity(self.batch_size);
+        while rows.len() < self.batch_size {
+            match self.consumer.poll(self.poll_timeout) {
+                Some(Ok(message)) => {
+                    self.last_message = Instant::now();
+                    // messages without a payload are deletions of their key, which have no row
+                    if let Some(payload) = message.payload() {
+                        match self.decoder.decode(payload) {
+                            Ok(row) => rows.push(row),
+                            Err(e) => return Some(Err(e)),
+                        }
+                    }
+                }
+                Some(Err(e)) => return Some(Err(DataFusionError::External(Box::new(e)))),
+                None if !rows.is_empty() => break,
+                None => match self.idle_timeout {
+                    Some(timeout) if self.last_message.elapsed() >= timeout => return None,
+                    _ => {}
+                },
+            }
+        }
+        Some(Ok(Rc::new(json_batch(&self.schema, &None, &rows))))
+    }
+}
+
+enum PayloadDecoder {
+    Json,
+    Avro(SchemaRegistry),
+}
+
+impl PayloadDecoder {
+    /// Decode a payload to a JSON object with a field for each column
+    fn decode(&self, payload: &[u8]) -> Result<JsonValue> {
+        let row = match *self {
+            PayloadDecoder::Json => {
+                let text = str::from_utf8(payload)
+                    .map_err(|e| DataFusionError::External(Box::new(e)))?;
+                json::parse(text).map_err(|e| DataFusionError::External(Box::new(e)))?
+            }
+            PayloadDecoder::Avro(ref registry) => {
+                let (schema_id, datum) = split_schema_id(payload)?;
+                let schema = registry.schema(schema_id)?;
+                let value = avro_rs::from_avro_datum(&schema, &mut Cursor::new(datum), None)
+                    .map_err(|e| {
+                        DataFusionError::Execution(format!(
+                            "Could not decode Avro record with schema {}: {}",
+                            schema_id, e
+                        ))
+                    })?;
+                avro_to_json(value)
+            }
+        };
+        if row.is_object() {
+            Ok(row)
+        } else {
+            Err(DataFusionError::Execution(format!(
+                "Expected a message to be a record but found {}",
+                row.dump()
+            )))
+        }
+    }
+}
+
+/// Avro schemas fetched from a schema registry by id. Schemas never change once registered,
+/// so each is only fetched once.
+struct SchemaRegistry {
+    url: String,
+    schemas: RefCell<HashMap<u32, Rc<avro_rs::Schema>>>,
+}
+
+impl SchemaRegistry {
+    fn new(url: &str) -> Self {
+        SchemaRegistry {
+            url: url.trim_right_matches('/').to_string(),
+            schemas: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn schema(&self, id: u32) -> Result<Rc<avro_rs::Schema>> {
+        if let Some(schema) = self.schemas.borrow().get(&id) {
+            return Ok(schema.clone());
+        }
+        let body = reqwest::get(&format!("{}/schemas/ids/{}", self.url, id))
+            .and_then(|mut response| response.error_for_status()?.text())
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let response = json::parse(&body).map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let schema = match response["schema"].as_str() {
+            Some(s) => avro_rs::Schema::parse_str(s).map_err(|e| {
+                DataFusionError::Execution(format!("Invalid Avro schema {}: {}", id, e))
+            })?,
+            None => {
+                return Err(DataFusionError::Execution(format!(
+                    "Schema registry has no schema with id {}",
+                    id
+                )))
+            }
+        };
+        let schema = Rc::new(schema);
+        self.schemas.borrow_mut().insert(id, schema.clone());
+        Ok(schema)
+    }
+}
+
+/// Split a payload in the schema registry wire format into its schema id and Avro datum
+fn split_schema_id(payload: &[u8]) -> Result<(u32, &[u8])> {
+    if payload.len() < 5 || payload[0] != 0 {
+        return Err(DataFusionError::Execution(
+            "Expected an Avro payload to start with a zero byte and a schema id".to_string(),
+        ));
+    }
+    let id = Cursor::new(&payload[1..5]).read_u32::<BigEndian>()?;
+    Ok((id, &payload[5..]))
+}
+
+fn avro_to_json(value: AvroValue) -> JsonValue {
+    match value {
+        AvroValue::Null => JsonValue::Null,
+        AvroValue::Boolean(b) => b.into(),
+        AvroValue::Int(n) => n.into(),
+        AvroValue::Long(n) => n.into(),
+        AvroValue::Float(n) => n.into(),
+        AvroValue::Double(n) => n.into(),
+        AvroValue::String(s) | AvroValue::Enum(_, s) => s.into(),
+        AvroValue::Bytes(bytes) | AvroValue::Fixed(_, bytes) => {
+            String::from_utf8_lossy(&bytes).into_owned().into()
+        }
+        AvroValue::Union(value) => avro_to_json(*value),
+        AvroValue::Array(values) => {
+            JsonValue::Array(values.into_iter().map(avro_to_json).collect())
+        }
+        AvroValue::Map(entries) => {
+            let mut object = JsonValue::new_object();
+            for (key, value) in entries {
+                object[key.as_str()] = avro_to_json(value);
+            }
+            object
+        }
+        AvroValue::Record(fields) => {
+            let mut object = JsonValue::new_object();
+            for (name, value) in fields {
+                object[name.as_str()] = avro_to_json(value);
+            }
+            object
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_schema_id() {
+        let (id, datum) = split_schema_id(&[0, 0, 0, 1, 2, 42]).unwrap();
+        assert_eq!(258, id);
+        assert_eq!(&[42], datum);
+        assert!(split_schema_id(&[1, 0, 0, 1, 2, 42]).is_err());
+        assert!(split_schema_id(&[0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_avro_record() {
+        let schema = avro_rs::Schema::parse_str(
+            r#"{"type": "record", "name": "click", "fields": [
+                {"name": "ts", "type": "long"},
+                {"name": "page", "type": ["null", "string"]}
+            ]}"#,
+        ).unwrap();
+        let mut record = avro_rs::types::Record::new(&schema).unwrap();
+        record.put("ts", 61000_i64);
+        record.put("page", AvroValue::Union(Box::new(AvroValue::Null)));
+        let datum = avro_rs::to_avro_datum(&schema, record).unwrap();
+
+        let value = avro_rs::from_avro_datum(&schema, &mut Cursor::new(datum), None).unwrap();
+        let row = avro_to_json(value);
+        assert_eq!(Some(61000), row["ts"].as_i64());
+        assert!(row["page"].is_null());
+    }
+
+    #[test]
+    fn test_decode_json_payload() {
+        let row = PayloadDecoder::Json
+            .decode(br#"{"ts": 1000, "user_id": 1}"#)
+            .unwrap();
+        assert_eq!(Some(1), row["user_id"].as_i32());
+        assert!(PayloadDecoder::Json.decode(b"[1, 2]").is_err());
+        assert!(PayloadDecoder::Json.decode(b"{").is_err());
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshots of the files in a directory. A table backed by a directory reads the files that
+//! were listed when it was registered or last refreshed, so files added while a query runs are
+//! not partially visible to it, and files that change after being listed are reported as errors
+//! rather than being read in an inconsistent state.
+
+use std::fs::{metadata, read_dir};
+use std::time::SystemTime;
+
+use super::super::errors::*;
+
+/// The state of a file when it was listed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSnapshot {
+    pub path: String,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl FileSnapshot {
+    pub fn new(path: &str) -> Result<Self> {
+        let m = metadata(path)?;
+        Ok(FileSnapshot {
+            path: path.to_string(),
+            size: m.len(),
+            modified: m.modified()?,
+        })
+    }
+
+    /// Check that the file has not been modified since it was listed
+    pub fn verify(&self) -> Result<()> {
+        let current = FileSnapshot::new(&self.path)?;
+        if current.size != self.size || current.modified != self.modified {
+            return Err(DataFusionError::Execution(format!(
+                "File '{}' has changed since its table was last refreshed",
+                self.path
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Check whether a location is a directory rather than a single file
+pub fn is_directory(location: &str) -> bool {
+    metadata(location).map(|m| m.is_dir()).unwrap_or(false)
+}
+
+/// List the files directly inside a directory in name order, skipping hidden files and
+/// subdirectories
+pub fn list_files(directory: &str) -> Result<Vec<FileSnapshot>> {
+    let mut paths = vec![];
+    for entry in read_dir(directory)? {
+        let entry = entry?;
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if hidden || !entry.file_type()?.is_file() {
+            continue;
+        }
+        match entry.path().to_str() {
+            Some(path) => paths.push(path.to_string()),
+            None => {
+                return Err(DataFusionError::Execution(format!(
+                    "File name {:?} is not valid UTF-8",
+                    entry.path()
+                )))
+            }
+        }
+    }
+    paths.sort();
+    paths.iter().map(|p| FileSnapshot::new(p)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, File, OpenOptions};
+    use std::io::Write;
+
+    #[test]
+    fn test_list_files() {
+        let dir = "./target/test_list_files";
+        create_dir_all(dir).unwrap();
+        File::create(format!("{}/b.csv", dir)).unwrap();
+        File::create(format!("{}/a.csv", dir)).unwrap();
+        File::create(format!("{}/.hidden", dir)).unwrap();
+
+        let files = list_files(dir).unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            vec!["./target/test_list_files/a.csv", "./target/test_list_files/b.csv"],
+            names
+        );
+        assert!(is_directory(dir));
+        assert!(!is_directory(&names[0]));
+
+        files[0].verify().unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&files[0].path)
+            .unwrap()
+            .write_all(b"1\n")
+            .unwrap();
+        assert!(files[0].verify().is_err());
+    }
+}
@@ -0,0 +1,54 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory data source
+
+use std::rc::Rc;
+
+use super::super::errors::*;
+use super::common::*;
+
+use arrow::datatypes::*;
+
+/// Data source that returns batches that have already been loaded into memory
+pub struct MemoryDataSource {
+    schema: Rc<Schema>,
+    batches: Rc<Vec<Rc<RecordBatch>>>,
+    index: usize,
+}
+
+impl MemoryDataSource {
+    pub fn new(schema: Rc<Schema>, batches: Rc<Vec<Rc<RecordBatch>>>) -> Self {
+        MemoryDataSource {
+            schema,
+            batches,
+            index: 0,
+        }
+    }
+}
+
+impl DataSource for MemoryDataSource {
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
+        if self.index < self.batches.len() {
+            self.index += 1;
+            Some(Ok(self.batches[self.index - 1].clone()))
+        } else {
+            None
+        }
+    }
+}
@@ -17,6 +17,15 @@
 pub mod common;
 pub mod csv;
 pub mod empty;
+pub mod infer;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod listing;
+pub mod memory;
 pub mod ndjson;
+pub mod output;
 pub mod parquet;
+pub mod profile;
+pub mod series;
+pub mod stream;
 //pub mod quiver;
@@ -16,7 +16,7 @@
 
 use std::fs::File;
 use std::io;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 use std::rc::Rc;
 
 use arrow::array::ListArray;
@@ -30,6 +30,7 @@ use json;
 use super::super::errors::*;
 use super::super::types::*;
 use super::common::*;
+use super::output::FileOutput;
 
 pub struct NdJsonFile {
     schema: Rc<Schema>,
@@ -49,6 +50,10 @@ impl NdJsonFile {
             projection,
         })
     }
+
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size
+    }
 }
 
 /// Built an Arrow array from one column in a batch of JSON records
@@ -95,105 +100,174 @@ impl DataSource for NdJsonFile {
             return None;
         }
 
-        //println!("Loaded {} rows", rows.len());
-
-        // now convert to Arrow arrays based on provided schema
-
-        let column_with_index = self.schema.columns().iter().enumerate();
-
-        let projection = match self.projection {
-            Some(ref v) => v.clone(),
-            None => self
-                .schema
-                .columns()
-                .iter()
-                .enumerate()
-                .map(|(i, _)| i)
-                .collect(),
-        };
-
-        let columns: Vec<Value> = column_with_index
-            .map(|(i, c)| {
-                if projection.contains(&i) {
-                    match c.data_type() {
-                        DataType::Boolean => {
-                            collect_column!(rows, c.name(), bool, as_bool, rows.len(), false)
-                        }
-                        DataType::Int8 => collect_column!(rows, c.name(), i8, as_i8, rows.len(), 0),
-                        DataType::Int16 => {
-                            collect_column!(rows, c.name(), i16, as_i16, rows.len(), 0)
-                        }
-                        DataType::Int32 => {
-                            collect_column!(rows, c.name(), i32, as_i32, rows.len(), 0)
-                        }
-                        DataType::Int64 => {
-                            collect_column!(rows, c.name(), i64, as_i64, rows.len(), 0)
-                        }
-                        DataType::UInt8 => {
-                            collect_column!(rows, c.name(), u8, as_u8, rows.len(), 0)
-                        }
-                        DataType::UInt16 => {
-                            collect_column!(rows, c.name(), u16, as_u16, rows.len(), 0)
-                        }
-                        DataType::UInt32 => {
-                            collect_column!(rows, c.name(), u32, as_u32, rows.len(), 0)
-                        }
-                        DataType::UInt64 => {
-                            collect_column!(rows, c.name(), u64, as_u64, rows.len(), 0)
-                        }
-                        DataType::Float16 => {
-                            collect_column!(rows, c.name(), f32, as_f32, rows.len(), 0_f32)
-                        }
-                        DataType::Float32 => {
-                            collect_column!(rows, c.name(), f32, as_f32, rows.len(), 0_f32)
-                        }
-                        DataType::Float64 => {
-                            collect_column!(rows, c.name(), f64, as_f64, rows.len(), 0_f64)
-                        }
-                        DataType::Utf8 => {
-                            let mut b: ListBuilder<u8> = ListBuilder::with_capacity(rows.len());
-                            let mut bitmap = Bitmap::new(rows.len());
-                            let mut null_count = 0;
-                            for row_index in 0..rows.len() {
-                                let field_name: &str = c.name().as_ref();
-                                let json_value: &json::JsonValue = &rows[row_index][field_name];
-                                if json_value.is_null() {
-                                    null_count += 1;
-                                    bitmap.clear(row_index);
-                                    b.push(b"");
-                                } else {
-                                    b.push(json_value.as_str().unwrap().as_bytes());
-                                }
+        Some(Ok(Rc::new(json_batch(&self.schema, &self.projection, &rows))))
+    }
+}
+
+/// Convert JSON objects to a batch with the given schema, reading each column from the field
+/// of the same name. Columns that are not in the projection are left null.
+pub fn json_batch(
+    schema: &Rc<Schema>,
+    projection: &Option<Vec<usize>>,
+    rows: &[json::JsonValue],
+) -> DefaultRecordBatch {
+    let column_with_index = schema.columns().iter().enumerate();
+
+    let projection = match *projection {
+        Some(ref v) => v.clone(),
+        None => schema
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(i, _)| i)
+            .collect(),
+    };
+
+    let columns: Vec<Value> = column_with_index
+        .map(|(i, c)| {
+            if projection.contains(&i) {
+                match c.data_type() {
+                    DataType::Boolean => {
+                        collect_column!(rows, c.name(), bool, as_bool, rows.len(), false)
+                    }
+                    DataType::Int8 => collect_column!(rows, c.name(), i8, as_i8, rows.len(), 0),
+                    DataType::Int16 => collect_column!(rows, c.name(), i16, as_i16, rows.len(), 0),
+                    DataType::Int32 => collect_column!(rows, c.name(), i32, as_i32, rows.len(), 0),
+                    DataType::Int64 => collect_column!(rows, c.name(), i64, as_i64, rows.len(), 0),
+                    DataType::UInt8 => collect_column!(rows, c.name(), u8, as_u8, rows.len(), 0),
+                    DataType::UInt16 => collect_column!(rows, c.name(), u16, as_u16, rows.len(), 0),
+                    DataType::UInt32 => collect_column!(rows, c.name(), u32, as_u32, rows.len(), 0),
+                    DataType::UInt64 => collect_column!(rows, c.name(), u64, as_u64, rows.len(), 0),
+                    DataType::Float16 => {
+                        collect_column!(rows, c.name(), f32, as_f32, rows.len(), 0_f32)
+                    }
+                    DataType::Float32 => {
+                        collect_column!(rows, c.name(), f32, as_f32, rows.len(), 0_f32)
+                    }
+                    DataType::Float64 => {
+                        collect_column!(rows, c.name(), f64, as_f64, rows.len(), 0_f64)
+                    }
+                    DataType::Utf8 => {
+                        let mut b: ListBuilder<u8> = ListBuilder::with_capacity(rows.len());
+                        let mut bitmap = Bitmap::new(rows.len());
+                        let mut null_count = 0;
+                        for row_index in 0..rows.len() {
+                            let field_name: &str = c.name().as_ref();
+                            let json_value: &json::JsonValue = &rows[row_index][field_name];
+                            if json_value.is_null() {
+                                null_count += 1;
+                                bitmap.clear(row_index);
+                                b.push(b"");
+                            } else {
+                                b.push(json_value.as_str().unwrap().as_bytes());
                             }
-                            let buffer = b.finish();
-                            Value::Column(Rc::new(Array::with_nulls(
-                                rows.len(),
-                                ArrayData::Utf8(ListArray::from(buffer)),
-                                null_count,
-                                bitmap,
-                            )))
                         }
-                        _ => unimplemented!(
-                            "ndjson reader does not support data type {:?}",
-                            c.data_type()
-                        ),
+                        let buffer = b.finish();
+                        Value::Column(Rc::new(Array::with_nulls(
+                            rows.len(),
+                            ArrayData::Utf8(ListArray::from(buffer)),
+                            null_count,
+                            bitmap,
+                        )))
                     }
-                } else {
-                    // not in the projection
-                    //println!("Not loading column {} at index {}", c.name(), i);
-                    Value::Scalar(Rc::new(ScalarValue::Null))
+                    _ => unimplemented!(
+                        "ndjson reader does not support data type {:?}",
+                        c.data_type()
+                    ),
                 }
-            })
-            .collect();
-
-        Some(Ok(Rc::new(DefaultRecordBatch {
-            schema: self.schema.clone(),
-            data: columns,
-            row_count: rows.len(),
-        })))
+            } else {
+                // not in the projection
+                //println!("Not loading column {} at index {}", c.name(), i);
+                Value::Scalar(Rc::new(ScalarValue::Null))
+            }
+        })
+        .collect();
+
+    DefaultRecordBatch {
+        schema: schema.clone(),
+        data: columns,
+        row_count: rows.len(),
+    }
+}
+
+/// Writes record batches as newline-delimited JSON, with one object per row
+pub struct NdJsonWriter {
+    w: FileOutput,
+}
+
+impl NdJsonWriter {
+    pub fn new(w: FileOutput) -> Self {
+        NdJsonWriter { w }
+    }
+
+    /// Write the rows of a batch and return the number of rows written
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<usize> {
+        let schema = batch.schema();
+        for i in 0..batch.num_rows() {
+            let mut row = json::JsonValue::new_object();
+            for (j, field) in schema.columns().iter().enumerate() {
+                let value = match *batch.column(j) {
+                    Value::Scalar(ref v) => v.as_ref().clone(),
+                    Value::Column(ref array) => match *array.validity_bitmap() {
+                        Some(ref bitmap) if !bitmap.is_set(i) => ScalarValue::Null,
+                        _ => get_value(array, i),
+                    },
+                };
+                row[field.name().as_str()] = to_json(&value, field.data_type())?;
+            }
+            self.w.write_all(row.dump().as_bytes())?;
+            self.w.write_all(b"\n")?;
+        }
+        Ok(batch.num_rows())
+    }
+
+    /// Write any buffered rows to the file
+    pub fn flush(&mut self) -> Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+
+    /// Write any buffered rows and finish the file
+    pub fn close(self) -> Result<()> {
+        self.w.finish()
     }
 }
 
+/// Convert a value to JSON, with the fields of structs named as in their data type
+fn to_json(value: &ScalarValue, data_type: &DataType) -> Result<json::JsonValue> {
+    Ok(match *value {
+        ScalarValue::Null => json::JsonValue::Null,
+        ScalarValue::Boolean(v) => v.into(),
+        ScalarValue::Int8(v) => v.into(),
+        ScalarValue::Int16(v) => v.into(),
+        ScalarValue::Int32(v) => v.into(),
+        ScalarValue::Int64(v) => v.into(),
+        ScalarValue::UInt8(v) => v.into(),
+        ScalarValue::UInt16(v) => v.into(),
+        ScalarValue::UInt32(v) => v.into(),
+        ScalarValue::UInt64(v) => v.into(),
+        ScalarValue::Float32(v) => v.into(),
+        ScalarValue::Float64(v) => v.into(),
+        ScalarValue::Utf8(ref v) => v.as_str().into(),
+        ScalarValue::Struct(ref values) => {
+            let fields = match *data_type {
+                DataType::Struct(ref fields) => fields,
+                ref other => {
+                    return Err(DataFusionError::Execution(format!(
+                        "Struct value in a column of type {:?}",
+                        other
+                    )))
+                }
+            };
+            let mut object = json::JsonValue::new_object();
+            for (v, field) in values.iter().zip(fields) {
+                object[field.name().as_str()] = to_json(v, field.data_type())?;
+            }
+            object
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
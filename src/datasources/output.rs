@@ -0,0 +1,133 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Files that query results are written to, optionally compressed
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::super::errors::*;
+
+/// The compression codec of written files. CSV and JSON files are compressed as a whole and
+/// only support gzip, while Parquet files compress each page with any of the codecs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileCompression {
+    Uncompressed,
+    Gzip,
+    Snappy,
+    Zstd,
+}
+
+impl FileCompression {
+    /// Get a codec from its name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_ref() {
+            "none" | "uncompressed" => Some(FileCompression::Uncompressed),
+            "gzip" => Some(FileCompression::Gzip),
+            "snappy" => Some(FileCompression::Snappy),
+            "zstd" => Some(FileCompression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// The suffix added to the names of whole files compressed with this codec
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            FileCompression::Gzip => ".gz",
+            _ => "",
+        }
+    }
+}
+
+/// A file being written, that compresses what is written to it as a whole
+pub enum FileOutput {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl FileOutput {
+    /// Create the file at `path`
+    pub fn create(path: &str, compression: FileCompression) -> Result<Self> {
+        let gzip = match compression {
+            FileCompression::Uncompressed => false,
+            FileCompression::Gzip => true,
+            other => {
+                return Err(DataFusionError::Execution(format!(
+                    "{:?} compression is only supported for Parquet files",
+                    other
+                )))
+            }
+        };
+        let w = BufWriter::with_capacity(8 * 1024 * 1024, File::create(path)?);
+        if gzip {
+            Ok(FileOutput::Gzip(GzEncoder::new(w, Compression::default())))
+        } else {
+            Ok(FileOutput::Plain(w))
+        }
+    }
+
+    /// Write any buffered data and the end of the compressed stream to the file
+    pub fn finish(self) -> Result<()> {
+        let mut w = match self {
+            FileOutput::Plain(w) => w,
+            FileOutput::Gzip(w) => w.finish()?,
+        };
+        w.flush()?;
+        Ok(())
+    }
+}
+
+impl Write for FileOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            FileOutput::Plain(ref mut w) => w.write(buf),
+            FileOutput::Gzip(ref mut w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            FileOutput::Plain(ref mut w) => w.flush(),
+            FileOutput::Gzip(ref mut w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_gzip_output() {
+        let path = "./target/test_gzip_output.txt.gz";
+        let mut output = FileOutput::create(path, FileCompression::Gzip).unwrap();
+        output.write_all(b"hello\n").unwrap();
+        output.finish().unwrap();
+
+        let mut s = String::new();
+        GzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!("hello\n", s);
+
+        assert_eq!(Some(FileCompression::Zstd), FileCompression::from_name("ZSTD"));
+        assert!(FileOutput::create(path, FileCompression::Snappy).is_err());
+    }
+}
@@ -24,17 +24,43 @@ use arrow::list_builder::*;
 
 use parquet::basic;
 use parquet::column::reader::*;
+use parquet::column::writer::ColumnWriter;
 use parquet::data_type::{ByteArray, Int96};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
 use parquet::file::reader::*;
+use parquet::file::statistics::Statistics;
+use parquet::file::writer::{FileWriter, RowGroupWriter, SerializedFileWriter};
+use parquet::schema::parser::parse_message_type;
 use parquet::schema::types::Type;
 
 use super::super::errors::*;
 use super::super::types::*;
 use super::common::*;
+use super::output::FileCompression;
+
+/// What the statistics of every row group of a Parquet file say about the values of a column
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSummary {
+    pub null_count: u64,
+    /// Number of distinct values, which Parquet only records per row group, so this is only
+    /// known for files with a single row group
+    pub distinct_count: Option<u64>,
+    /// Smallest value, which is null when every value is null and `None` when it isn't known
+    pub min: Option<ScalarValue>,
+    /// Largest value, which is null when every value is null and `None` when it isn't known
+    pub max: Option<ScalarValue>,
+}
 
 pub struct ParquetFile {
     reader: SerializedFileReader,
+    /// Row groups to read, in order
+    row_groups: Vec<usize>,
+    /// Position in `row_groups` of the next row group to load
     row_group_index: usize,
+    /// Decides whether to read each row group when it is reached, so that the ones it
+    /// rejects are never loaded
+    row_group_filter: Option<Box<FnMut(usize) -> bool>>,
     schema: Rc<Schema>,
     projection: Option<Vec<usize>>,
     batch_size: usize,
@@ -53,9 +79,12 @@ impl ParquetFile {
             DataType::Struct(fields) => {
                 let schema = Schema::new(fields.clone());
                 //println!("Parquet schema: {:?}", schema);
+                let row_groups = (0..reader.num_row_groups()).collect();
                 Ok(ParquetFile {
                     reader: reader,
+                    row_groups,
                     row_group_index: 0,
+                    row_group_filter: None,
                     schema: Rc::new(schema),
                     projection,
                     batch_size: 64 * 1024,
@@ -63,7 +92,7 @@ impl ParquetFile {
                     column_readers: vec![],
                 })
             }
-            _ => Err(ExecutionError::General(
+            _ => Err(DataFusionError::Execution(
                 "Failed to read Parquet schema".to_string(),
             )),
         }
@@ -73,10 +102,88 @@ impl ParquetFile {
         self.batch_size = batch_size
     }
 
+    pub fn num_row_groups(&self) -> usize {
+        self.reader.num_row_groups()
+    }
+
+    /// Get the number of rows in the file from its metadata
+    pub fn num_rows(&self) -> usize {
+        let metadata = self.reader.metadata();
+        (0..self.reader.num_row_groups())
+            .map(|i| metadata.row_group(i).num_rows() as usize)
+            .sum()
+    }
+
+    /// Summarize the values of a column from the statistics of each row group, without
+    /// reading any data. Returns `None` when a row group has no statistics for the column.
+    pub fn column_summary(&self, column: usize) -> Option<ColumnSummary> {
+        let metadata = self.reader.metadata();
+        let row_groups = self.reader.num_row_groups();
+        let mut summary = ColumnSummary {
+            null_count: 0,
+            distinct_count: None,
+            min: Some(ScalarValue::Null),
+            max: Some(ScalarValue::Null),
+        };
+        for i in 0..row_groups {
+            let row_group = metadata.row_group(i);
+            let statistics = row_group.column(column).statistics()?;
+            summary.null_count += statistics.null_count();
+            if row_groups == 1 {
+                summary.distinct_count = statistics.distinct_count();
+            }
+            if statistics.null_count() == row_group.num_rows() as u64 {
+                // a row group of nulls has no min or max
+                continue;
+            }
+            let (min, max) = match min_max(statistics) {
+                Some(min_max) => min_max,
+                None => {
+                    summary.min = None;
+                    summary.max = None;
+                    continue;
+                }
+            };
+            summary.min = summary
+                .min
+                .map(|m| if m == ScalarValue::Null || min < m { min } else { m });
+            summary.max = summary
+                .max
+                .map(|m| if m == ScalarValue::Null || max > m { max } else { m });
+        }
+        Some(summary)
+    }
+
+    /// Only read the given row groups, in the given order
+    pub fn set_row_groups(&mut self, row_groups: Vec<usize>) {
+        self.row_groups = row_groups;
+        self.row_group_index = 0;
+    }
+
+    /// Only read the row groups that a filter accepts, such as a random sample of them. The
+    /// filter is called with the index of each row group just before it would be loaded.
+    pub fn set_row_group_filter(&mut self, filter: Box<FnMut(usize) -> bool>) {
+        self.row_group_filter = Some(filter);
+    }
+
+    /// Move past the row groups that the filter rejects
+    fn skip_filtered_row_groups(&mut self) {
+        if let Some(ref mut filter) = self.row_group_filter {
+            while self.row_group_index < self.row_groups.len()
+                && !filter(self.row_groups[self.row_group_index])
+            {
+                self.row_group_index += 1;
+            }
+        }
+    }
+
     fn load_next_row_group(&mut self) {
-        if self.row_group_index < self.reader.num_row_groups() {
+        if self.row_group_index < self.row_groups.len() {
             //println!("Loading row group {} of {}", self.row_group_index, self.reader.num_row_groups());
-            let reader = self.reader.get_row_group(self.row_group_index).unwrap();
+            let reader = self
+                .reader
+                .get_row_group(self.row_groups[self.row_group_index])
+                .unwrap();
 
             self.column_readers = vec![];
 
@@ -276,20 +383,18 @@ impl ParquetFile {
 
 impl DataSource for ParquetFile {
     fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
-        // advance the row group reader if necessary
-        if self.current_row_group.is_none() {
-            self.load_next_row_group();
-            self.load_batch()
-        } else {
-            match self.load_batch() {
-                Some(b) => Some(b),
-                None => if self.row_group_index < self.reader.num_row_groups() {
-                    self.load_next_row_group();
-                    self.load_batch()
-                } else {
-                    None
-                },
+        loop {
+            if self.current_row_group.is_some() {
+                if let Some(b) = self.load_batch() {
+                    return Some(b);
+                }
             }
+            // advance the row group reader
+            self.skip_filtered_row_groups();
+            if self.row_group_index >= self.row_groups.len() {
+                return None;
+            }
+            self.load_next_row_group();
         }
     }
 
@@ -298,6 +403,230 @@ impl DataSource for ParquetFile {
     }
 }
 
+/// Writes record batches to a Parquet file as they are produced, with one row group per batch
+pub struct ParquetWriter {
+    writer: SerializedFileWriter,
+    schema: Rc<Schema>,
+}
+
+impl ParquetWriter {
+    /// Create the file at `path` for batches with the given schema, compressing its pages with
+    /// the given codec
+    pub fn create(path: &str, schema: Rc<Schema>, compression: FileCompression) -> Result<Self> {
+        let message = parquet_message_type(&schema)?;
+        let parquet_schema = parse_message_type(&message).map_err(parquet_error)?;
+        let codec = match compression {
+            FileCompression::Uncompressed => basic::Compression::UNCOMPRESSED,
+            FileCompression::Gzip => basic::Compression::GZIP,
+            FileCompression::Snappy => basic::Compression::SNAPPY,
+            FileCompression::Zstd => basic::Compression::ZSTD,
+        };
+        let writer = SerializedFileWriter::new(
+            File::create(path)?,
+            Rc::new(parquet_schema),
+            Rc::new(WriterProperties::builder().set_compression(codec).build()),
+        ).map_err(parquet_error)?;
+        Ok(ParquetWriter { writer, schema })
+    }
+
+    /// Write the rows of a batch as a row group and return the number of rows written
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<usize> {
+        if batch.num_rows() == 0 {
+            return Ok(0);
+        }
+        let mut row_group = self.writer.next_row_group().map_err(parquet_error)?;
+        let mut i = 0;
+        while let Some(mut column) = row_group.next_column().map_err(parquet_error)? {
+            let values = column_values(batch, i, self.schema.column(i).name())?;
+            match column {
+                ColumnWriter::BoolColumnWriter(ref mut w) => {
+                    let values: Vec<bool> = values
+                        .iter()
+                        .map(|v| match *v {
+                            ScalarValue::Boolean(b) => b,
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    w.write_batch(&values, None, None).map_err(parquet_error)?;
+                }
+                ColumnWriter::Int32ColumnWriter(ref mut w) => {
+                    let values: Vec<i32> = values
+                        .iter()
+                        .map(|v| match *v {
+                            ScalarValue::Int8(n) => n as i32,
+                            ScalarValue::Int16(n) => n as i32,
+                            ScalarValue::Int32(n) => n,
+                            ScalarValue::UInt8(n) => n as i32,
+                            ScalarValue::UInt16(n) => n as i32,
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    w.write_batch(&values, None, None).map_err(parquet_error)?;
+                }
+                ColumnWriter::Int64ColumnWriter(ref mut w) => {
+                    let values: Vec<i64> = values
+                        .iter()
+                        .map(|v| match *v {
+                            ScalarValue::Int64(n) => n,
+                            ScalarValue::UInt32(n) => n as i64,
+                            // stored as the same bits and read back as signed
+                            ScalarValue::UInt64(n) => n as i64,
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    w.write_batch(&values, None, None).map_err(parquet_error)?;
+                }
+                ColumnWriter::FloatColumnWriter(ref mut w) => {
+                    let values: Vec<f32> = values
+                        .iter()
+                        .map(|v| match *v {
+                            ScalarValue::Float32(n) => n,
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    w.write_batch(&values, None, None).map_err(parquet_error)?;
+                }
+                ColumnWriter::DoubleColumnWriter(ref mut w) => {
+                    let values: Vec<f64> = values
+                        .iter()
+                        .map(|v| match *v {
+                            ScalarValue::Float64(n) => n,
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    w.write_batch(&values, None, None).map_err(parquet_error)?;
+                }
+                ColumnWriter::ByteArrayColumnWriter(ref mut w) => {
+                    let values: Vec<ByteArray> = values
+                        .iter()
+                        .map(|v| match *v {
+                            ScalarValue::Utf8(ref s) => ByteArray::from(s.as_bytes().to_vec()),
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    w.write_batch(&values, None, None).map_err(parquet_error)?;
+                }
+                _ => unreachable!(),
+            }
+            row_group.close_column(column).map_err(parquet_error)?;
+            i += 1;
+        }
+        self.writer
+            .close_row_group(row_group)
+            .map_err(parquet_error)?;
+        Ok(batch.num_rows())
+    }
+
+    /// Write the file footer. The file is not readable until it has been closed.
+    pub fn close(mut self) -> Result<()> {
+        self.writer.close().map_err(parquet_error)
+    }
+}
+
+/// Get the min and max of the values in a row group as the types they are read as. The
+/// statistics of byte arrays are left out because older writers compared them as signed bytes,
+/// and INT96 timestamps because they are converted to milliseconds when read.
+fn min_max(statistics: &Statistics) -> Option<(ScalarValue, ScalarValue)> {
+    if !statistics.has_min_max_set() {
+        return None;
+    }
+    match *statistics {
+        Statistics::Boolean(ref s) => Some((
+            ScalarValue::Boolean(*s.min()),
+            ScalarValue::Boolean(*s.max()),
+        )),
+        Statistics::Int32(ref s) => {
+            Some((ScalarValue::Int32(*s.min()), ScalarValue::Int32(*s.max())))
+        }
+        Statistics::Int64(ref s) => {
+            Some((ScalarValue::Int64(*s.min()), ScalarValue::Int64(*s.max())))
+        }
+        Statistics::Float(ref s) => Some((
+            ScalarValue::Float32(*s.min()),
+            ScalarValue::Float32(*s.max()),
+        )),
+        Statistics::Double(ref s) => Some((
+            ScalarValue::Float64(*s.min()),
+            ScalarValue::Float64(*s.max()),
+        )),
+        _ => None,
+    }
+}
+
+fn parquet_error(e: ParquetError) -> DataFusionError {
+    DataFusionError::Execution(format!("Parquet error: {}", e))
+}
+
+/// Describe a schema as a Parquet message type. Small integer types are stored as INT32 and
+/// UInt32 as INT64 so that they are read back as signed types that can hold every value.
+fn parquet_message_type(schema: &Schema) -> Result<String> {
+    let mut message = String::from("message schema {");
+    for field in schema.columns() {
+        let column_type = match *field.data_type() {
+            DataType::Boolean => "BOOLEAN",
+            DataType::Int8 | DataType::Int16 | DataType::Int32 => "INT32",
+            DataType::UInt8 | DataType::UInt16 => "INT32",
+            DataType::Int64 | DataType::UInt32 | DataType::UInt64 => "INT64",
+            DataType::Float32 => "FLOAT",
+            DataType::Float64 => "DOUBLE",
+            DataType::Utf8 => "BYTE_ARRAY",
+            ref other => {
+                return Err(DataFusionError::Execution(format!(
+                    "Cannot write column '{}' of type {:?} to Parquet",
+                    field.name(),
+                    other
+                )))
+            }
+        };
+        let annotation = if *field.data_type() == DataType::Utf8 {
+            " (UTF8)"
+        } else {
+            ""
+        };
+        message.push_str(&format!(
+            " REQUIRED {} {}{};",
+            column_type,
+            field.name(),
+            annotation
+        ));
+    }
+    message.push_str(" }");
+    Ok(message)
+}
+
+/// Get the values of a column of a batch. Columns are written as REQUIRED because the Parquet
+/// reader does not read definition levels, so null values can't be written.
+fn column_values(batch: &RecordBatch, i: usize, name: &str) -> Result<Vec<ScalarValue>> {
+    let mut values = Vec::with_capacity(batch.num_rows());
+    match *batch.column(i) {
+        Value::Scalar(ref v) => {
+            if **v == ScalarValue::Null {
+                return Err(DataFusionError::Execution(format!(
+                    "Cannot write null values in column '{}' to Parquet",
+                    name
+                )));
+            }
+            for _ in 0..batch.num_rows() {
+                values.push(v.as_ref().clone());
+            }
+        }
+        Value::Column(ref array) => {
+            if let Some(ref bitmap) = *array.validity_bitmap() {
+                if (0..batch.num_rows()).any(|j| !bitmap.is_set(j)) {
+                    return Err(DataFusionError::Execution(format!(
+                        "Cannot write null values in column '{}' to Parquet",
+                        name
+                    )));
+                }
+            }
+            for j in 0..batch.num_rows() {
+                values.push(get_value(array, j));
+            }
+        }
+    }
+    Ok(values)
+}
+
 fn to_arrow(t: &Type) -> Field {
     match t {
         Type::PrimitiveType {
@@ -319,6 +648,11 @@ fn to_arrow(t: &Type) -> Field {
                 basic::Type::DOUBLE => DataType::Float64,
                 basic::Type::BYTE_ARRAY => match basic_info.logical_type() {
                     basic::LogicalType::UTF8 => DataType::Utf8,
+                    // raw bytes are read into the same list array representation as utf8
+                    basic::LogicalType::NONE
+                    | basic::LogicalType::JSON
+                    | basic::LogicalType::BSON
+                    | basic::LogicalType::ENUM => DataType::Utf8,
                     _ => unimplemented!("No support for Parquet BYTE_ARRAY yet"),
                 }
                 basic::Type::FIXED_LEN_BYTE_ARRAY => unimplemented!("No support for Parquet FIXED_LEN_BYTE_ARRAY yet")
@@ -355,6 +689,64 @@ mod tests {
         println!("First row: {:?}", batch.row_slice(0));
     }
 
+    #[test]
+    fn test_parquet_row_groups() {
+        let file = File::open("test/data/uk_cities.parquet").unwrap();
+        let mut parquet = ParquetFile::open(file, None).unwrap();
+        let row_groups = parquet.num_row_groups();
+        assert!(row_groups > 0);
+        parquet.set_row_groups(vec![]);
+        assert!(parquet.next().is_none());
+
+        let file = File::open("test/data/uk_cities.parquet").unwrap();
+        let mut parquet = ParquetFile::open(file, None).unwrap();
+        parquet.set_row_groups((0..row_groups).rev().collect());
+        assert!(parquet.next().is_some());
+    }
+
+    #[test]
+    fn test_parquet_row_group_filter() {
+        let file = File::open("test/data/uk_cities.parquet").unwrap();
+        let mut parquet = ParquetFile::open(file, None).unwrap();
+        let row_groups = parquet.num_row_groups();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let s = seen.clone();
+        parquet.set_row_group_filter(Box::new(move |i| {
+            s.borrow_mut().push(i);
+            false
+        }));
+        assert!(parquet.next().is_none());
+        assert_eq!((0..row_groups).collect::<Vec<_>>(), *seen.borrow());
+
+        let file = File::open("test/data/uk_cities.parquet").unwrap();
+        let mut parquet = ParquetFile::open(file, None).unwrap();
+        parquet.set_row_group_filter(Box::new(|_| true));
+        let rows: usize = DataSourceIterator::new(Rc::new(RefCell::new(parquet)))
+            .map(|b| b.unwrap().num_rows())
+            .sum();
+        assert_eq!(37, rows);
+    }
+
+    #[test]
+    fn test_parquet_column_summary() {
+        let file = File::open("test/data/uk_cities.parquet").unwrap();
+        let parquet = ParquetFile::open(file, None).unwrap();
+        assert_eq!(37, parquet.num_rows());
+        let lat = parquet.column_summary(1).unwrap();
+        assert_eq!(0, lat.null_count);
+        assert_eq!(None, lat.distinct_count);
+        assert_eq!(Some(ScalarValue::Float32(50.37629)), lat.min);
+        assert_eq!(Some(ScalarValue::Float32(57.653484)), lat.max);
+        // byte array statistics aren't used
+        let city = parquet.column_summary(0).unwrap();
+        assert_eq!((None, None), (city.min, city.max));
+
+        let file = File::open("test/data/alltypes_plain.parquet").unwrap();
+        let parquet = ParquetFile::open(file, None).unwrap();
+        assert_eq!(8, parquet.num_rows());
+        assert_eq!(None, parquet.column_summary(0));
+    }
+
     #[test]
     fn test_parquet_iterator() {
         let file = File::open("test/data/uk_cities.parquet").unwrap();
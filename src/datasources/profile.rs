@@ -0,0 +1,89 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Connection profiles
+//!
+//! A connection profile is a named set of options for reading external tables, such as the
+//! credentials and region of an object store, the headers of an HTTP source or the DSN of a
+//! database. Applications register profiles with the execution context and tables refer to
+//! them with `OPTIONS (profile 'name')`, so secrets are supplied programmatically instead of
+//! being written into every table definition.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Options for reading external tables, keyed by lower case name
+#[derive(Clone, Default, PartialEq)]
+pub struct ConnectionProfile {
+    options: BTreeMap<String, String>,
+}
+
+impl ConnectionProfile {
+    pub fn new() -> Self {
+        ConnectionProfile::default()
+    }
+
+    /// Set an option. Names are case insensitive.
+    pub fn with_option(mut self, key: &str, value: &str) -> Self {
+        self.options.insert(key.to_lowercase(), value.to_string());
+        self
+    }
+
+    /// Get the value of an option
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options.get(&key.to_lowercase()).map(|v| v.as_str())
+    }
+
+    /// Get the names of the options, in order
+    pub fn keys(&self) -> Vec<&str> {
+        self.options.keys().map(|k| k.as_str()).collect()
+    }
+
+    /// Combine the options of two profiles, with the options of `other` taking precedence
+    pub fn merge(&self, other: &ConnectionProfile) -> ConnectionProfile {
+        let mut options = self.options.clone();
+        options.extend(other.options.clone());
+        ConnectionProfile { options }
+    }
+}
+
+/// Values are left out, since they are usually secrets
+impl fmt::Debug for ConnectionProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.options.keys().map(|k| (k, "***")))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_and_redact() {
+        let profile = ConnectionProfile::new()
+            .with_option("Region", "us-east-1")
+            .with_option("secret_key", "abc");
+        let table = ConnectionProfile::new().with_option("region", "eu-west-1");
+        let merged = profile.merge(&table);
+        assert_eq!(Some("eu-west-1"), merged.get("REGION"));
+        assert_eq!(Some("abc"), merged.get("secret_key"));
+        assert_eq!(vec!["region", "secret_key"], merged.keys());
+        assert_eq!(
+            "{\"region\": \"***\", \"secret_key\": \"***\"}",
+            format!("{:?}", merged)
+        );
+    }
+}
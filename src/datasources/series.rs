@@ -0,0 +1,113 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Data source for the `generate_series` and `range` table functions
+
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::errors::*;
+use super::super::logical::series_len;
+use super::super::types::*;
+use super::common::*;
+
+/// Data source that produces a single Int64 column counting from `start` in increments of
+/// `step`, without materializing more than one batch at a time
+pub struct SeriesDataSource {
+    schema: Rc<Schema>,
+    next_value: i64,
+    step: i64,
+    remaining: usize,
+    batch_size: usize,
+}
+
+impl SeriesDataSource {
+    pub fn new(
+        schema: Rc<Schema>,
+        start: i64,
+        stop: i64,
+        step: i64,
+        inclusive: bool,
+        batch_size: usize,
+    ) -> Self {
+        SeriesDataSource {
+            schema,
+            next_value: start,
+            step,
+            remaining: series_len(start, stop, step, inclusive),
+            batch_size,
+        }
+    }
+}
+
+impl DataSource for SeriesDataSource {
+    fn schema(&self) -> &Rc<Schema> {
+        &self.schema
+    }
+
+    fn next(&mut self) -> Option<Result<Rc<RecordBatch>>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let n = self.remaining.min(self.batch_size);
+        let mut values: Vec<i64> = Vec::with_capacity(n);
+        for _ in 0..n {
+            values.push(self.next_value);
+            // the value after the last one may overflow, but is never used
+            self.next_value = self.next_value.wrapping_add(self.step);
+        }
+        self.remaining -= n;
+        Some(Ok(Rc::new(DefaultRecordBatch {
+            schema: self.schema.clone(),
+            data: vec![Value::Column(Rc::new(Array::from(values)))],
+            row_count: n,
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(start: i64, stop: i64, step: i64, inclusive: bool) -> Vec<Vec<i64>> {
+        let schema = Rc::new(Schema::new(vec![Field::new("n", DataType::Int64, false)]));
+        let mut ds = SeriesDataSource::new(schema, start, stop, step, inclusive, 3);
+        let mut batches = vec![];
+        while let Some(batch) = ds.next() {
+            let batch = batch.unwrap();
+            batches.push(
+                (0..batch.num_rows())
+                    .map(|i| match *batch.row_slice(i)[0] {
+                        ScalarValue::Int64(v) => v,
+                        ref other => panic!("unexpected value {:?}", other),
+                    })
+                    .collect(),
+            );
+        }
+        batches
+    }
+
+    #[test]
+    fn test_series() {
+        assert_eq!(vec![vec![1, 2, 3], vec![4, 5]], collect(1, 5, 1, true));
+        assert_eq!(vec![vec![1, 2, 3], vec![4]], collect(1, 5, 1, false));
+        assert_eq!(vec![vec![0, 4, 8]], collect(0, 10, 4, true));
+        assert_eq!(vec![vec![10, 7, 4], vec![1]], collect(10, 0, -3, true));
+        assert_eq!(Vec::<Vec<i64>>::new(), collect(5, 1, 1, true));
+        assert_eq!(Vec::<Vec<i64>>::new(), collect(1, 1, 1, false));
+        let max = i64::max_value();
+        assert_eq!(vec![vec![max]], collect(max, max, 1, true));
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Unbounded data sources
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::errors::*;
+use super::common::*;
+
+/// A source of rows that are read as they arrive, such as the messages of a topic. Sources
+/// can be unbounded, in which case queries over them only make progress through operators that
+/// produce results before their input ends, such as `GROUP BY window(ts, ..)`.
+pub trait StreamSource {
+    fn schema(&self) -> &Rc<Schema>;
+
+    /// Start reading rows, in batches of at most `batch_size` rows. Batches are produced as
+    /// rows arrive, so they can be smaller than this.
+    fn open(&self, batch_size: usize) -> Result<Rc<RefCell<DataSource>>>;
+}
@@ -14,47 +14,101 @@
 
 //! Error types
 
-use super::sqlparser::ParserError;
-use std::io::Error;
+use std::error::Error;
+use std::fmt;
+use std::io;
 use std::result;
 
 macro_rules! df_error {
     ($MSG:expr) => {
-        ExecutionError::from($MSG)
+        DataFusionError::from($MSG)
     };
 }
 
-pub type Result<T> = result::Result<T, ExecutionError>;
+pub type Result<T> = result::Result<T, DataFusionError>;
 
+/// An error from any stage of running a query, categorised by the stage that failed
 #[derive(Debug)]
-pub enum ExecutionError {
-    IoError(Error),
-    ParserError(ParserError),
-    General(String),
-    InvalidColumn(String),
-    NotImplemented,
+pub enum DataFusionError {
+    /// The SQL text could not be split into tokens
+    Tokenizer(String),
+    /// The tokens do not form a valid SQL statement
+    Parser(String),
+    /// A statement could not be turned into a logical plan, for example because it refers to an
+    /// unknown table, column or function
+    Plan(String),
+    /// Data does not match the schema that was declared or expected for it
+    Schema(String),
+    /// A query failed while it was running
+    Execution(String),
+    /// The server is executing as many queries as it can, so the query should be retried later
+    ServerBusy(String),
+    Io(io::Error),
+    /// An error raised by code outside of DataFusion, such as a user defined function
+    External(Box<Error>),
 }
 
-impl From<Error> for ExecutionError {
-    fn from(e: Error) -> Self {
-        ExecutionError::IoError(e)
+impl fmt::Display for DataFusionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DataFusionError::Tokenizer(ref msg) => write!(f, "Tokenizer error: {}", msg),
+            DataFusionError::Parser(ref msg) => write!(f, "Parser error: {}", msg),
+            DataFusionError::Plan(ref msg) => write!(f, "Planning error: {}", msg),
+            DataFusionError::Schema(ref msg) => write!(f, "Schema error: {}", msg),
+            DataFusionError::Execution(ref msg) => write!(f, "Execution error: {}", msg),
+            DataFusionError::ServerBusy(ref msg) => write!(f, "Server busy: {}", msg),
+            DataFusionError::Io(ref e) => write!(f, "IO error: {}", e),
+            DataFusionError::External(ref e) => write!(f, "External error: {}", e),
+        }
     }
 }
 
-impl From<String> for ExecutionError {
+impl Error for DataFusionError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match *self {
+            DataFusionError::Io(ref e) => Some(e),
+            DataFusionError::External(ref e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DataFusionError {
+    fn from(e: io::Error) -> Self {
+        DataFusionError::Io(e)
+    }
+}
+
+impl From<String> for DataFusionError {
     fn from(e: String) -> Self {
-        ExecutionError::General(e)
+        DataFusionError::Execution(e)
     }
 }
 
-impl From<&'static str> for ExecutionError {
+impl From<&'static str> for DataFusionError {
     fn from(e: &'static str) -> Self {
-        ExecutionError::General(e.to_string())
+        DataFusionError::Execution(e.to_string())
     }
 }
 
-impl From<ParserError> for ExecutionError {
-    fn from(e: ParserError) -> Self {
-        ExecutionError::ParserError(e)
+impl From<Box<Error>> for DataFusionError {
+    fn from(e: Box<Error>) -> Self {
+        DataFusionError::External(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_source() {
+        let e = DataFusionError::from(io::Error::new(io::ErrorKind::NotFound, "missing.csv"));
+        assert_eq!("IO error: missing.csv", e.to_string());
+        assert_eq!("missing.csv", e.source().unwrap().to_string());
+
+        let e = DataFusionError::Plan("no schema found for table t".to_string());
+        assert_eq!("Planning error: no schema found for table t", e.to_string());
+        assert!(e.source().is_none());
     }
 }
@@ -0,0 +1,648 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Arrow C Stream Interface
+//!
+//! Exports query results as an `ArrowArrayStream` so that consumers in other languages, such as
+//! pyarrow or DuckDB, can pull batches one at a time. Each batch is exported as a struct array
+//! with a child per column. Numeric columns share their value buffers with the batch; boolean
+//! and string values and validity bitmaps are laid out differently in memory, so they are
+//! repacked into Arrow's format.
+//!
+//! A panic must not unwind out of a callback into the consumer, so the callbacks catch panics.
+//! A panic in `get_schema` or `get_next` is returned as `EIO` with its message as the last
+//! error.
+
+use std::any::Any;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::rc::Rc;
+
+use arrow::array::PrimitiveArray;
+use arrow::datatypes::*;
+
+use super::datasources::common::*;
+use super::errors::*;
+use super::exec::*;
+use super::types::*;
+
+/// Set on fields that may contain nulls
+const ARROW_FLAG_NULLABLE: i64 = 2;
+
+/// Returned by `get_next` when the query fails
+const EIO: c_int = 5;
+
+/// `struct ArrowSchema` of the Arrow C data interface
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct FFI_ArrowSchema {
+    pub format: *const c_char,
+    pub name: *const c_char,
+    pub metadata: *const c_char,
+    pub flags: i64,
+    pub n_children: i64,
+    pub children: *mut *mut FFI_ArrowSchema,
+    pub dictionary: *mut FFI_ArrowSchema,
+    pub release: Option<unsafe extern "C" fn(*mut FFI_ArrowSchema)>,
+    pub private_data: *mut c_void,
+}
+
+/// `struct ArrowArray` of the Arrow C data interface
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct FFI_ArrowArray {
+    pub length: i64,
+    pub null_count: i64,
+    pub offset: i64,
+    pub n_buffers: i64,
+    pub n_children: i64,
+    pub buffers: *mut *const c_void,
+    pub children: *mut *mut FFI_ArrowArray,
+    pub dictionary: *mut FFI_ArrowArray,
+    pub release: Option<unsafe extern "C" fn(*mut FFI_ArrowArray)>,
+    pub private_data: *mut c_void,
+}
+
+/// `struct ArrowArrayStream` of the Arrow C stream interface
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct FFI_ArrowArrayStream {
+    pub get_schema:
+        Option<unsafe extern "C" fn(*mut FFI_ArrowArrayStream, *mut FFI_ArrowSchema) -> c_int>,
+    pub get_next:
+        Option<unsafe extern "C" fn(*mut FFI_ArrowArrayStream, *mut FFI_ArrowArray) -> c_int>,
+    pub get_last_error: Option<unsafe extern "C" fn(*mut FFI_ArrowArrayStream) -> *const c_char>,
+    pub release: Option<unsafe extern "C" fn(*mut FFI_ArrowArrayStream)>,
+    pub private_data: *mut c_void,
+}
+
+impl FFI_ArrowSchema {
+    fn empty() -> Self {
+        FFI_ArrowSchema {
+            format: ptr::null(),
+            name: ptr::null(),
+            metadata: ptr::null(),
+            flags: 0,
+            n_children: 0,
+            children: ptr::null_mut(),
+            dictionary: ptr::null_mut(),
+            release: None,
+            private_data: ptr::null_mut(),
+        }
+    }
+}
+
+impl FFI_ArrowArray {
+    fn empty() -> Self {
+        FFI_ArrowArray {
+            length: 0,
+            null_count: 0,
+            offset: 0,
+            n_buffers: 0,
+            n_children: 0,
+            buffers: ptr::null_mut(),
+            children: ptr::null_mut(),
+            dictionary: ptr::null_mut(),
+            release: None,
+            private_data: ptr::null_mut(),
+        }
+    }
+}
+
+/// Memory owned by an exported schema
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    children: Vec<*mut FFI_ArrowSchema>,
+}
+
+/// Memory owned by an exported array
+struct ArrayPrivate {
+    /// Keeps the value buffers that are shared with the batch alive
+    _array: Option<Rc<Array>>,
+    /// Buffers that were repacked into Arrow's format
+    _owned: Vec<Vec<u8>>,
+    _offsets: Vec<i32>,
+    buffers: Vec<*const c_void>,
+    children: Vec<*mut FFI_ArrowArray>,
+}
+
+/// State of an exported stream
+struct StreamPrivate {
+    /// Borrows from `relation`, so it is dropped first
+    batches: Option<Box<Iterator<Item = Result<Rc<RecordBatch>>>>>,
+    relation: *mut Box<SimpleRelation>,
+    schema: Schema,
+    last_error: Option<CString>,
+}
+
+/// Export the results of an execution plan as a stream. The plan is executed as the consumer
+/// pulls batches, and is dropped when the consumer releases the stream.
+pub fn export_stream(relation: Box<SimpleRelation>) -> Result<FFI_ArrowArrayStream> {
+    let schema = relation.schema().clone();
+    // check up front that every column has a type that can be exported
+    let mut out = FFI_ArrowSchema::empty();
+    export_schema(&schema, &mut out)?;
+    unsafe { release_schema(&mut out) };
+
+    let relation = Box::into_raw(Box::new(relation));
+    // the iterator borrows the relation, which lives until the stream is released
+    let batches = unsafe { (*relation).scan() };
+    let private = Box::new(StreamPrivate {
+        batches: Some(batches),
+        relation,
+        schema,
+        last_error: None,
+    });
+    Ok(FFI_ArrowArrayStream {
+        get_schema: Some(stream_get_schema),
+        get_next: Some(stream_get_next),
+        get_last_error: Some(stream_get_last_error),
+        release: Some(release_stream),
+        private_data: Box::into_raw(private) as *mut c_void,
+    })
+}
+
+/// Export a schema as a struct type with a child per column
+fn export_schema(schema: &Schema, out: &mut FFI_ArrowSchema) -> Result<()> {
+    export_field("+s", "", false, schema.columns(), out)
+}
+
+fn export_field(
+    format: &str,
+    name: &str,
+    nullable: bool,
+    children: &[Field],
+    out: &mut FFI_ArrowSchema,
+) -> Result<()> {
+    let name = CString::new(name)
+        .map_err(|_| DataFusionError::Execution(format!("Invalid column name '{}'", name)))?;
+    let mut exported = Vec::with_capacity(children.len());
+    for field in children {
+        let child = Box::into_raw(Box::new(FFI_ArrowSchema::empty()));
+        exported.push(child);
+        let result = match *field.data_type() {
+            DataType::Struct(ref fields) => {
+                export_field("+s", field.name(), field.is_nullable(), fields, unsafe {
+                    &mut *child
+                })
+            }
+            ref other => type_format(other).and_then(|format| {
+                export_field(format, field.name(), field.is_nullable(), &[], unsafe {
+                    &mut *child
+                })
+            }),
+        };
+        if let Err(e) = result {
+            unsafe { free_schemas(exported) };
+            return Err(e);
+        }
+    }
+    let mut private = Box::new(SchemaPrivate {
+        format: CString::new(format).unwrap(),
+        name,
+        children: exported,
+    });
+    out.format = private.format.as_ptr();
+    out.name = private.name.as_ptr();
+    out.metadata = ptr::null();
+    out.flags = if nullable { ARROW_FLAG_NULLABLE } else { 0 };
+    out.n_children = private.children.len() as i64;
+    out.children = private.children.as_mut_ptr();
+    out.dictionary = ptr::null_mut();
+    out.release = Some(release_schema);
+    out.private_data = Box::into_raw(private) as *mut c_void;
+    Ok(())
+}
+
+/// Get the format string of a type that doesn't have children
+fn type_format(data_type: &DataType) -> Result<&'static str> {
+    Ok(match *data_type {
+        DataType::Boolean => "b",
+        DataType::Int8 => "c",
+        DataType::UInt8 => "C",
+        DataType::Int16 => "s",
+        DataType::UInt16 => "S",
+        DataType::Int32 => "i",
+        DataType::UInt32 => "I",
+        DataType::Int64 => "l",
+        DataType::UInt64 => "L",
+        DataType::Float32 => "f",
+        DataType::Float64 => "g",
+        DataType::Utf8 => "u",
+        ref other => {
+            return Err(DataFusionError::Execution(format!(
+                "Cannot export columns of type {:?}",
+                other
+            )))
+        }
+    })
+}
+
+unsafe fn free_schemas(schemas: Vec<*mut FFI_ArrowSchema>) {
+    for schema in schemas {
+        release_schema(schema);
+        drop(Box::from_raw(schema));
+    }
+}
+
+unsafe extern "C" fn release_schema(schema: *mut FFI_ArrowSchema) {
+    // a release callback has no way to report a failure
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if schema.is_null() || (*schema).release.is_none() {
+            return;
+        }
+        let private = Box::from_raw((*schema).private_data as *mut SchemaPrivate);
+        free_schemas(private.children);
+        (*schema).release = None;
+        (*schema).private_data = ptr::null_mut();
+    }));
+}
+
+/// Export a batch as a struct array with a child per column
+fn export_batch(batch: &RecordBatch, schema: &Schema, out: &mut FFI_ArrowArray) -> Result<()> {
+    let rows = batch.num_rows();
+    let mut columns = Vec::with_capacity(batch.num_columns());
+    for (value, field) in batch.columns().iter().zip(schema.columns()) {
+        columns.push(match *value {
+            Value::Column(ref array) => array.clone(),
+            // scalars are repeated so that every child has a value for each row
+            Value::Scalar(ref v) => {
+                let values = vec![v.as_ref().clone(); rows];
+                Rc::new(array_from_scalars(&values, field.data_type())?)
+            }
+        });
+    }
+    let private = ArrayPrivate {
+        _array: None,
+        _owned: vec![],
+        _offsets: vec![],
+        buffers: vec![ptr::null()],
+        children: vec![],
+    };
+    finish_array(private, rows, 0, columns, out)
+}
+
+/// Export an array, sharing its numeric value buffers
+fn export_array(array: &Rc<Array>, out: &mut FFI_ArrowArray) -> Result<()> {
+    let len = array.len();
+    let mut owned = vec![];
+    let mut offsets = vec![];
+    let mut buffers = vec![];
+
+    // validity
+    match *array.validity_bitmap() {
+        Some(ref bitmap) if array.null_count() > 0 => {
+            let bits = pack_bits((0..len).map(|i| bitmap.is_set(i)), len);
+            buffers.push(bits.as_ptr() as *const c_void);
+            owned.push(bits);
+        }
+        _ => buffers.push(ptr::null()),
+    }
+
+    let mut children = vec![];
+    match *array.data() {
+        ArrayData::Boolean(ref v) => {
+            let bits = pack_bits(v.iter(), len);
+            buffers.push(bits.as_ptr() as *const c_void);
+            owned.push(bits);
+        }
+        ArrayData::Int8(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::Int16(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::Int32(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::Int64(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::UInt8(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::UInt16(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::UInt32(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::UInt64(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::Float32(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::Float64(ref v) => buffers.push(value_buffer(v, len)),
+        ArrayData::Utf8(ref list) => {
+            let mut values = vec![];
+            offsets.push(0);
+            for i in 0..len {
+                values.extend_from_slice(list.get(i));
+                offsets.push(values.len() as i32);
+            }
+            buffers.push(offsets.as_ptr() as *const c_void);
+            buffers.push(values.as_ptr() as *const c_void);
+            owned.push(values);
+        }
+        ArrayData::Struct(ref fields) => children = fields.clone(),
+    }
+
+    let private = ArrayPrivate {
+        _array: Some(array.clone()),
+        _owned: owned,
+        _offsets: offsets,
+        buffers,
+        children: vec![],
+    };
+    finish_array(private, len, array.null_count(), children, out)
+}
+
+fn finish_array(
+    mut private: ArrayPrivate,
+    len: usize,
+    null_count: usize,
+    children: Vec<Rc<Array>>,
+    out: &mut FFI_ArrowArray,
+) -> Result<()> {
+    for child in &children {
+        let exported = Box::into_raw(Box::new(FFI_ArrowArray::empty()));
+        private.children.push(exported);
+        if let Err(e) = export_array(child, unsafe { &mut *exported }) {
+            unsafe { free_arrays(private.children) };
+            return Err(e);
+        }
+    }
+    let mut private = Box::new(private);
+    out.length = len as i64;
+    out.null_count = null_count as i64;
+    out.offset = 0;
+    out.n_buffers = private.buffers.len() as i64;
+    out.n_children = private.children.len() as i64;
+    out.buffers = private.buffers.as_mut_ptr();
+    out.children = private.children.as_mut_ptr();
+    out.dictionary = ptr::null_mut();
+    out.release = Some(release_array);
+    out.private_data = Box::into_raw(private) as *mut c_void;
+    Ok(())
+}
+
+/// Get a pointer to the values of a buffer, which stays valid while the array is alive
+fn value_buffer<T>(buffer: &PrimitiveArray<T>, len: usize) -> *const c_void {
+    if len == 0 {
+        ptr::null()
+    } else {
+        buffer.get(0) as *const T as *const c_void
+    }
+}
+
+/// Pack booleans into a bitmap with the least significant bit first
+fn pack_bits<I: Iterator<Item = bool>>(values: I, len: usize) -> Vec<u8> {
+    let mut bits = vec![0_u8; (len + 7) / 8];
+    for (i, set) in values.enumerate() {
+        if set {
+            bits[i / 8] |= 1 << (i % 8);
+        }
+    }
+    bits
+}
+
+unsafe fn free_arrays(arrays: Vec<*mut FFI_ArrowArray>) {
+    for array in arrays {
+        release_array(array);
+        drop(Box::from_raw(array));
+    }
+}
+
+unsafe extern "C" fn release_array(array: *mut FFI_ArrowArray) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if array.is_null() || (*array).release.is_none() {
+            return;
+        }
+        let private = Box::from_raw((*array).private_data as *mut ArrayPrivate);
+        free_arrays(private.children);
+        (*array).release = None;
+        (*array).private_data = ptr::null_mut();
+    }));
+}
+
+/// Run the body of a stream callback, returning `EIO` and setting the last error when it fails
+/// or panics
+unsafe fn stream_call<F>(stream: *mut FFI_ArrowArrayStream, f: F) -> c_int
+where
+    F: FnOnce(&mut StreamPrivate) -> Result<()>,
+{
+    let private = &mut *((*stream).private_data as *mut StreamPrivate);
+    let result = match panic::catch_unwind(AssertUnwindSafe(|| f(&mut *private))) {
+        Ok(result) => result,
+        Err(payload) => Err(DataFusionError::Execution(format!(
+            "Panic while reading the stream: {}",
+            panic_message(payload.as_ref())
+        ))),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            private.set_error(e);
+            EIO
+        }
+    }
+}
+
+/// The message that a panic was started with
+fn panic_message(payload: &(Any + Send)) -> &str {
+    match payload.downcast_ref::<&'static str>() {
+        Some(msg) => *msg,
+        None => match payload.downcast_ref::<String>() {
+            Some(msg) => msg.as_str(),
+            None => "unknown panic",
+        },
+    }
+}
+
+unsafe extern "C" fn stream_get_schema(
+    stream: *mut FFI_ArrowArrayStream,
+    out: *mut FFI_ArrowSchema,
+) -> c_int {
+    stream_call(stream, |private| export_schema(&private.schema, &mut *out))
+}
+
+unsafe extern "C" fn stream_get_next(
+    stream: *mut FFI_ArrowArrayStream,
+    out: *mut FFI_ArrowArray,
+) -> c_int {
+    stream_call(stream, |private| {
+        let next = match private.batches {
+            Some(ref mut batches) => batches.next(),
+            None => None,
+        };
+        match next {
+            Some(Ok(batch)) => export_batch(batch.as_ref(), &private.schema, &mut *out),
+            Some(Err(e)) => Err(e),
+            None => {
+                // a released array marks the end of the stream
+                *out = FFI_ArrowArray::empty();
+                Ok(())
+            }
+        }
+    })
+}
+
+unsafe extern "C" fn stream_get_last_error(stream: *mut FFI_ArrowArrayStream) -> *const c_char {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let private = &*((*stream).private_data as *mut StreamPrivate);
+        match private.last_error {
+            Some(ref msg) => msg.as_ptr(),
+            None => ptr::null(),
+        }
+    })).unwrap_or(ptr::null())
+}
+
+unsafe extern "C" fn release_stream(stream: *mut FFI_ArrowArrayStream) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if stream.is_null() || (*stream).release.is_none() {
+            return;
+        }
+        let mut private = Box::from_raw((*stream).private_data as *mut StreamPrivate);
+        private.batches = None;
+        drop(Box::from_raw(private.relation));
+        (*stream).release = None;
+        (*stream).private_data = ptr::null_mut();
+    }));
+}
+
+impl StreamPrivate {
+    fn set_error(&mut self, e: DataFusionError) {
+        let msg = format!("{:?}", e).replace('\0', " ");
+        self.last_error = CString::new(msg).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn people_stream(ctx: &mut ExecutionContext, sql: &str) -> FFI_ArrowArrayStream {
+        let people = ctx
+            .load_csv(
+                "./test/data/people.csv",
+                &Schema::new(vec![
+                    Field::new("id", DataType::Int32, false),
+                    Field::new("name", DataType::Utf8, true),
+                ]),
+                true,
+                None,
+            )
+            .unwrap();
+        ctx.register("people", people);
+        ctx.set_batch_size(4);
+        let df = ctx.sql(sql).unwrap();
+        let relation = ctx.create_execution_plan(df.plan()).unwrap();
+        export_stream(relation).unwrap()
+    }
+
+    #[test]
+    fn test_export_stream() {
+        let mut ctx = ExecutionContext::local();
+        let mut stream = people_stream(&mut ctx, "SELECT id, name FROM people WHERE id < 7");
+
+        unsafe {
+            let mut schema = FFI_ArrowSchema::empty();
+            assert_eq!(0, stream.get_schema.unwrap()(&mut stream, &mut schema));
+            assert_eq!("+s", CStr::from_ptr(schema.format).to_str().unwrap());
+            assert_eq!(2, schema.n_children);
+            let name = &**schema.children.offset(1);
+            assert_eq!("name", CStr::from_ptr(name.name).to_str().unwrap());
+            assert_eq!("u", CStr::from_ptr(name.format).to_str().unwrap());
+            assert_eq!(ARROW_FLAG_NULLABLE, name.flags);
+            schema.release.unwrap()(&mut schema);
+            assert!(schema.release.is_none());
+
+            let mut ids = vec![];
+            let mut names = vec![];
+            loop {
+                let mut array = FFI_ArrowArray::empty();
+                assert_eq!(0, stream.get_next.unwrap()(&mut stream, &mut array));
+                if array.release.is_none() {
+                    break;
+                }
+                assert_eq!(2, array.n_children);
+                let id = &**array.children.offset(0);
+                let values = *id.buffers.offset(1) as *const i32;
+                for i in 0..id.length as isize {
+                    ids.push(*values.offset(i));
+                }
+                let name = &**array.children.offset(1);
+                let offsets = *name.buffers.offset(1) as *const i32;
+                let data = *name.buffers.offset(2) as *const u8;
+                for i in 0..name.length as isize {
+                    let start = *offsets.offset(i) as isize;
+                    let end = *offsets.offset(i + 1) as isize;
+                    let bytes = (start..end).map(|j| *data.offset(j)).collect::<Vec<u8>>();
+                    names.push(String::from_utf8(bytes).unwrap());
+                }
+                array.release.unwrap()(&mut array);
+            }
+            assert_eq!(vec![1, 2, 3, 4, 5, 6], ids);
+            assert_eq!("Andy", names[0]);
+            assert_eq!(6, names.len());
+
+            stream.release.unwrap()(&mut stream);
+            assert!(stream.release.is_none());
+        }
+    }
+
+    /// A relation whose scan panics, as a bug in a relation would
+    struct PanickingRelation {
+        schema: Schema,
+    }
+
+    impl SimpleRelation for PanickingRelation {
+        fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+            Box::new((0..1).map(|_| panic!("relation failed")))
+        }
+
+        fn schema<'a>(&'a self) -> &'a Schema {
+            &self.schema
+        }
+    }
+
+    #[test]
+    fn test_export_stream_panic() {
+        let schema = Schema::new(vec![Field::new("id", DataType::Int32, false)]);
+        let mut stream = export_stream(Box::new(PanickingRelation { schema })).unwrap();
+        unsafe {
+            let mut array = FFI_ArrowArray::empty();
+            assert_eq!(EIO, stream.get_next.unwrap()(&mut stream, &mut array));
+            let error = stream.get_last_error.unwrap()(&mut stream);
+            assert_eq!(
+                "Execution(\"Panic while reading the stream: relation failed\")",
+                CStr::from_ptr(error).to_str().unwrap()
+            );
+            stream.release.unwrap()(&mut stream);
+            assert!(stream.release.is_none());
+        }
+    }
+
+    #[test]
+    fn test_export_booleans() {
+        let mut ctx = ExecutionContext::local();
+        let mut stream = people_stream(&mut ctx, "SELECT id > 2 FROM people LIMIT 4");
+        unsafe {
+            let mut array = FFI_ArrowArray::empty();
+            assert_eq!(0, stream.get_next.unwrap()(&mut stream, &mut array));
+            let column = &**array.children;
+            assert_eq!(4, column.length);
+            let bits = *(*column.buffers.offset(1) as *const u8);
+            assert_eq!(0b1100, bits & 0x0f);
+            array.release.unwrap()(&mut array);
+            stream.release.unwrap()(&mut stream);
+        }
+    }
+
+    #[test]
+    fn test_pack_bits() {
+        let bits = pack_bits(
+            vec![true, false, false, true, true, false, false, false, true].into_iter(),
+            9,
+        );
+        assert_eq!(vec![0b0001_1001, 0b1], bits);
+    }
+}
@@ -0,0 +1,901 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The commands of the Arrow Flight SQL protocol
+//!
+//! A Flight SQL server receives commands as protobuf messages and returns their results as
+//! Arrow record batches. This module decodes the commands and actions from the
+//! `google.protobuf.Any` messages that clients send and handles them against an execution
+//! context: queries, plans that clients built themselves, prepared statements with bound
+//! parameters, and the catalog metadata requests that JDBC and ODBC drivers send to list
+//! tables. The result of each command is a DataFrame with the schema that the protocol defines
+//! for it. Serving these over gRPC is left to the application that embeds the engine.
+//!
+//! All tables are listed in a single catalog and schema, as the execution context doesn't
+//! group them.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use arrow::array::ListArray;
+use arrow::datatypes::*;
+use arrow::list_builder::ListBuilder;
+
+use super::dataframe::*;
+use super::datasources::common::*;
+use super::errors::*;
+use super::exec::*;
+use super::ipc::*;
+use super::logical::*;
+use super::plancodec::*;
+use super::types::*;
+
+/// Name of the catalog that tables are listed in
+pub const CATALOG_NAME: &'static str = "datafusion";
+/// Name of the schema that tables are listed in
+pub const DB_SCHEMA_NAME: &'static str = "public";
+/// Type of the tables that are listed
+pub const TABLE_TYPE: &'static str = "TABLE";
+
+/// Prefix of the type URLs in the `google.protobuf.Any` messages that commands are sent in
+const TYPE_URL_PREFIX: &'static str = "type.googleapis.com/";
+/// Package of the Flight SQL protobuf messages
+const FLIGHT_SQL_PACKAGE: &'static str = "arrow.flight.protocol.sql.";
+/// Message of the command that submits a serialized logical plan, which is not part of
+/// Flight SQL
+const LOGICAL_PLAN_COMMAND: &'static str = "datafusion.CommandStatementLogicalPlan";
+
+/// A Flight SQL command that returns record batches
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlightSqlCommand {
+    /// `CommandStatementQuery`
+    StatementQuery { query: String },
+    /// `datafusion.CommandStatementLogicalPlan`, an extension of Flight SQL that carries a
+    /// logical plan serialized with `plancodec::serialize_plan` in its `plan` field. Substrait
+    /// plans are not supported.
+    StatementLogicalPlan { plan: String },
+    /// `CommandPreparedStatementQuery`, with the parameters that have been bound to it
+    PreparedStatementQuery { handle: Vec<u8> },
+    /// `CommandGetCatalogs`
+    GetCatalogs,
+    /// `CommandGetDbSchemas`, where patterns match as in `LIKE`
+    GetDbSchemas {
+        catalog: Option<String>,
+        db_schema_filter_pattern: Option<String>,
+    },
+    /// `CommandGetTables`, where patterns match as in `LIKE`
+    GetTables {
+        catalog: Option<String>,
+        db_schema_filter_pattern: Option<String>,
+        table_name_filter_pattern: Option<String>,
+        table_types: Vec<String>,
+        include_schema: bool,
+    },
+    /// `CommandGetTableTypes`
+    GetTableTypes,
+}
+
+impl FlightSqlCommand {
+    /// Decode a command from the `google.protobuf.Any` message that is sent as the command of
+    /// a `FlightDescriptor` or in a `Ticket`
+    pub fn decode(any: &[u8]) -> Result<FlightSqlCommand> {
+        let (name, value) = unpack_any(any)?;
+        let message = ProtoMessage::decode(value)?;
+        if name == LOGICAL_PLAN_COMMAND {
+            return Ok(FlightSqlCommand::StatementLogicalPlan {
+                plan: message.string(1)?.unwrap_or_default(),
+            });
+        }
+        let command = if name.starts_with(FLIGHT_SQL_PACKAGE) {
+            &name[FLIGHT_SQL_PACKAGE.len()..]
+        } else {
+            ""
+        };
+        Ok(match command {
+            "CommandStatementQuery" => FlightSqlCommand::StatementQuery {
+                query: message.string(1)?.unwrap_or_default(),
+            },
+            "CommandPreparedStatementQuery" => FlightSqlCommand::PreparedStatementQuery {
+                handle: message.bytes(1).unwrap_or(&[]).to_vec(),
+            },
+            "CommandGetCatalogs" => FlightSqlCommand::GetCatalogs,
+            "CommandGetDbSchemas" => FlightSqlCommand::GetDbSchemas {
+                catalog: message.string(1)?,
+                db_schema_filter_pattern: message.string(2)?,
+            },
+            "CommandGetTables" => FlightSqlCommand::GetTables {
+                catalog: message.string(1)?,
+                db_schema_filter_pattern: message.string(2)?,
+                table_name_filter_pattern: message.string(3)?,
+                table_types: message.strings(4)?,
+                include_schema: message.varint(5) != 0,
+            },
+            "CommandGetTableTypes" => FlightSqlCommand::GetTableTypes,
+            "CommandStatementSubstraitPlan" => {
+                return Err(DataFusionError::Execution(format!(
+                    "Substrait plans are not supported, send a plan serialized with \
+                     serialize_plan as {} instead",
+                    LOGICAL_PLAN_COMMAND
+                )))
+            }
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "Unsupported Flight SQL command {}",
+                    name
+                )))
+            }
+        })
+    }
+
+    /// Encode the command as the `google.protobuf.Any` message that clients send it in
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = vec![];
+        let name = match *self {
+            FlightSqlCommand::StatementQuery { ref query } => {
+                write_bytes_field(&mut value, 1, query.as_bytes());
+                "CommandStatementQuery"
+            }
+            FlightSqlCommand::StatementLogicalPlan { ref plan } => {
+                write_bytes_field(&mut value, 1, plan.as_bytes());
+                return pack_any(LOGICAL_PLAN_COMMAND, &value);
+            }
+            FlightSqlCommand::PreparedStatementQuery { ref handle } => {
+                write_bytes_field(&mut value, 1, handle);
+                "CommandPreparedStatementQuery"
+            }
+            FlightSqlCommand::GetCatalogs => "CommandGetCatalogs",
+            FlightSqlCommand::GetDbSchemas {
+                ref catalog,
+                ref db_schema_filter_pattern,
+            } => {
+                write_optional_string(&mut value, 1, catalog);
+                write_optional_string(&mut value, 2, db_schema_filter_pattern);
+                "CommandGetDbSchemas"
+            }
+            FlightSqlCommand::GetTables {
+                ref catalog,
+                ref db_schema_filter_pattern,
+                ref table_name_filter_pattern,
+                ref table_types,
+                include_schema,
+            } => {
+                write_optional_string(&mut value, 1, catalog);
+                write_optional_string(&mut value, 2, db_schema_filter_pattern);
+                write_optional_string(&mut value, 3, table_name_filter_pattern);
+                for table_type in table_types {
+                    write_bytes_field(&mut value, 4, table_type.as_bytes());
+                }
+                if include_schema {
+                    write_varint_field(&mut value, 5, 1);
+                }
+                "CommandGetTables"
+            }
+            FlightSqlCommand::GetTableTypes => "CommandGetTableTypes",
+        };
+        pack_any(&format!("{}{}", FLIGHT_SQL_PACKAGE, name), &value)
+    }
+}
+
+/// A Flight SQL action, which is sent with `DoAction`
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlightSqlAction {
+    /// `ActionCreatePreparedStatementRequest`
+    CreatePreparedStatement { query: String },
+    /// `ActionClosePreparedStatementRequest`
+    ClosePreparedStatement { handle: Vec<u8> },
+}
+
+impl FlightSqlAction {
+    /// Decode an action from its type and the `google.protobuf.Any` message in its body
+    pub fn decode(action_type: &str, body: &[u8]) -> Result<FlightSqlAction> {
+        let (_, value) = unpack_any(body)?;
+        let message = ProtoMessage::decode(value)?;
+        match action_type {
+            "CreatePreparedStatement" => Ok(FlightSqlAction::CreatePreparedStatement {
+                query: message.string(1)?.unwrap_or_default(),
+            }),
+            "ClosePreparedStatement" => Ok(FlightSqlAction::ClosePreparedStatement {
+                handle: message.bytes(1).unwrap_or(&[]).to_vec(),
+            }),
+            _ => Err(DataFusionError::Execution(format!(
+                "Unsupported Flight SQL action {}",
+                action_type
+            ))),
+        }
+    }
+}
+
+/// The result of `ActionCreatePreparedStatementRequest`
+#[derive(Debug, Clone)]
+pub struct PreparedStatementResult {
+    pub handle: Vec<u8>,
+    /// Schema of the results, if it is known before parameters are bound
+    pub dataset_schema: Option<Rc<Schema>>,
+    pub parameter_count: usize,
+}
+
+impl PreparedStatementResult {
+    /// Encode the result as an `ActionCreatePreparedStatementResult` in a
+    /// `google.protobuf.Any` message
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut value = vec![];
+        write_bytes_field(&mut value, 1, &self.handle);
+        if let Some(ref schema) = self.dataset_schema {
+            write_bytes_field(&mut value, 2, &encode_schema(schema)?);
+        }
+        Ok(pack_any(
+            &format!("{}ActionCreatePreparedStatementResult", FLIGHT_SQL_PACKAGE),
+            &value,
+        ))
+    }
+}
+
+struct OpenStatement {
+    statement: PreparedStatement,
+    parameters: Vec<ScalarValue>,
+}
+
+/// Handles the Flight SQL commands and actions of a session
+pub struct FlightSqlService {
+    ctx: ExecutionContext,
+    statements: HashMap<Vec<u8>, OpenStatement>,
+    next_handle: u64,
+}
+
+impl FlightSqlService {
+    pub fn new(ctx: ExecutionContext) -> Self {
+        FlightSqlService {
+            ctx,
+            statements: HashMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    pub fn context(&self) -> &ExecutionContext {
+        &self.ctx
+    }
+
+    /// Run a command and get its results
+    pub fn execute(&mut self, command: &FlightSqlCommand) -> Result<Rc<DataFrame>> {
+        match *command {
+            FlightSqlCommand::StatementQuery { ref query } => self.ctx.sql(query),
+            FlightSqlCommand::StatementLogicalPlan { ref plan } => {
+                self.ctx.submit_plan(deserialize_plan(plan)?)
+            }
+            FlightSqlCommand::PreparedStatementQuery { ref handle } => {
+                let open = self.open_statement(handle)?;
+                self.ctx.execute_prepared(&open.statement, &open.parameters)
+            }
+            FlightSqlCommand::GetCatalogs => self.metadata(
+                vec![Field::new("catalog_name", DataType::Utf8, false)],
+                vec![vec![text(CATALOG_NAME)]],
+            ),
+            FlightSqlCommand::GetDbSchemas {
+                ref catalog,
+                ref db_schema_filter_pattern,
+            } => {
+                let rows = if in_catalog(catalog, db_schema_filter_pattern) {
+                    vec![vec![text(CATALOG_NAME), text(DB_SCHEMA_NAME)]]
+                } else {
+                    vec![]
+                };
+                self.metadata(
+                    vec![
+                        Field::new("catalog_name", DataType::Utf8, true),
+                        Field::new("db_schema_name", DataType::Utf8, false),
+                    ],
+                    rows,
+                )
+            }
+            FlightSqlCommand::GetTables {
+                ref catalog,
+                ref db_schema_filter_pattern,
+                ref table_name_filter_pattern,
+                ref table_types,
+                include_schema,
+            } => {
+                let listed = in_catalog(catalog, db_schema_filter_pattern)
+                    && (table_types.is_empty() || table_types.iter().any(|t| t == TABLE_TYPE));
+                let names: Vec<String> = if listed {
+                    self.ctx
+                        .table_names()
+                        .into_iter()
+                        .filter(|name| match *table_name_filter_pattern {
+                            Some(ref pattern) => matches_pattern(pattern, name),
+                            None => true,
+                        }).collect()
+                } else {
+                    vec![]
+                };
+                let mut rows = Vec::with_capacity(names.len());
+                for name in names {
+                    let mut row = vec![
+                        text(CATALOG_NAME),
+                        text(DB_SCHEMA_NAME),
+                        text(&name),
+                        text(TABLE_TYPE),
+                    ];
+                    if include_schema {
+                        // the schema is sent as an IPC message in a binary column
+                        let schema = self.ctx.table_schema(&name).ok_or_else(|| {
+                            DataFusionError::Execution(format!("No table named '{}'", name))
+                        })?;
+                        row.push(encode_schema(&schema)?);
+                    }
+                    rows.push(row);
+                }
+                let mut fields = vec![
+                    Field::new("catalog_name", DataType::Utf8, true),
+                    Field::new("db_schema_name", DataType::Utf8, true),
+                    Field::new("table_name", DataType::Utf8, false),
+                    Field::new("table_type", DataType::Utf8, false),
+                ];
+                if include_schema {
+                    // arrow has no binary type yet so bytes are stored like utf8 strings
+                    fields.push(Field::new("table_schema", DataType::Utf8, false));
+                }
+                self.metadata(fields, rows)
+            }
+            FlightSqlCommand::GetTableTypes => self.metadata(
+                vec![Field::new("table_type", DataType::Utf8, false)],
+                vec![vec![text(TABLE_TYPE)]],
+            ),
+        }
+    }
+
+    /// Run a command sent as the `google.protobuf.Any` message of a `FlightDescriptor` or
+    /// `Ticket`
+    pub fn execute_encoded(&mut self, command: &[u8]) -> Result<Rc<DataFrame>> {
+        let command = FlightSqlCommand::decode(command)?;
+        self.execute(&command)
+    }
+
+    /// Run a `DoAction` request and get the body of its result, which is empty for actions
+    /// that don't return anything
+    pub fn do_action(&mut self, action_type: &str, body: &[u8]) -> Result<Vec<u8>> {
+        match FlightSqlAction::decode(action_type, body)? {
+            FlightSqlAction::CreatePreparedStatement { query } => {
+                self.create_prepared_statement(&query)?.encode()
+            }
+            FlightSqlAction::ClosePreparedStatement { handle } => {
+                self.close_prepared_statement(&handle)?;
+                Ok(vec![])
+            }
+        }
+    }
+
+    /// `ActionCreatePreparedStatementRequest`
+    pub fn create_prepared_statement(&mut self, query: &str) -> Result<PreparedStatementResult> {
+        let statement = self.ctx.prepare(query)?;
+        let handle = self.next_handle.to_string().into_bytes();
+        self.next_handle += 1;
+        let result = PreparedStatementResult {
+            handle: handle.clone(),
+            dataset_schema: statement.schema(),
+            parameter_count: statement.parameter_count(),
+        };
+        self.statements.insert(
+            handle,
+            OpenStatement {
+                statement,
+                parameters: vec![],
+            },
+        );
+        Ok(result)
+    }
+
+    /// Bind the parameters of a prepared statement, as sent with `DoPut`. The batch has a
+    /// column for each parameter and a single row of values.
+    pub fn bind_parameters(&mut self, handle: &[u8], parameters: &RecordBatch) -> Result<()> {
+        if parameters.num_rows() != 1 {
+            return Err(DataFusionError::Execution(format!(
+                "Parameters must be bound with a single row but {} rows were given",
+                parameters.num_rows()
+            )));
+        }
+        let values: Vec<ScalarValue> = parameters
+            .columns()
+            .iter()
+            .map(|c| value_at(c, 0))
+            .collect();
+        let open = self.open_statement_mut(handle)?;
+        if values.len() != open.statement.parameter_count() {
+            return Err(DataFusionError::Execution(format!(
+                "Prepared statement has {} parameters but {} values were given",
+                open.statement.parameter_count(),
+                values.len()
+            )));
+        }
+        open.parameters = values;
+        Ok(())
+    }
+
+    /// `ActionClosePreparedStatementRequest`
+    pub fn close_prepared_statement(&mut self, handle: &[u8]) -> Result<()> {
+        match self.statements.remove(handle) {
+            Some(_) => Ok(()),
+            None => Err(unknown_handle(handle)),
+        }
+    }
+
+    fn open_statement(&self, handle: &[u8]) -> Result<&OpenStatement> {
+        self.statements.get(handle).ok_or_else(|| unknown_handle(handle))
+    }
+
+    fn open_statement_mut(&mut self, handle: &[u8]) -> Result<&mut OpenStatement> {
+        self.statements
+            .get_mut(handle)
+            .ok_or_else(|| unknown_handle(handle))
+    }
+
+    /// Create a DataFrame of string or binary columns from rows of values
+    fn metadata(&self, fields: Vec<Field>, rows: Vec<Vec<Vec<u8>>>) -> Result<Rc<DataFrame>> {
+        let schema = Rc::new(Schema::new(fields));
+        let data = (0..schema.columns().len())
+            .map(|i| {
+                let mut b: ListBuilder<u8> = ListBuilder::with_capacity(rows.len());
+                for row in &rows {
+                    b.push(&row[i]);
+                }
+                Value::Column(Rc::new(Array::new(
+                    rows.len(),
+                    ArrayData::Utf8(ListArray::from(b.finish())),
+                )))
+            }).collect();
+        let batch: Rc<RecordBatch> = Rc::new(DefaultRecordBatch {
+            schema: schema.clone(),
+            data,
+            row_count: rows.len(),
+        });
+        self.ctx.submit_plan(Rc::new(LogicalPlan::MemTable {
+            schema,
+            batches: Rc::new(vec![batch]),
+        }))
+    }
+}
+
+fn text(s: &str) -> Vec<u8> {
+    s.as_bytes().to_vec()
+}
+
+fn unknown_handle(handle: &[u8]) -> DataFusionError {
+    DataFusionError::Execution(format!(
+        "No prepared statement has the handle {}",
+        String::from_utf8_lossy(handle)
+    ))
+}
+
+/// Whether the catalog and schema filters of a metadata request match the single schema
+fn in_catalog(catalog: &Option<String>, db_schema_filter_pattern: &Option<String>) -> bool {
+    // an empty catalog asks for objects without a catalog, of which there are none
+    let catalog_matches = match *catalog {
+        Some(ref catalog) => catalog == CATALOG_NAME,
+        None => true,
+    };
+    let schema_matches = match *db_schema_filter_pattern {
+        Some(ref pattern) => matches_pattern(pattern, DB_SCHEMA_NAME),
+        None => true,
+    };
+    catalog_matches && schema_matches
+}
+
+/// Get the message name and the encoded message of a `google.protobuf.Any`
+fn unpack_any(any: &[u8]) -> Result<(String, &[u8])> {
+    let message = ProtoMessage::decode(any)?;
+    let type_url = message.string(1)?.unwrap_or_default();
+    // the name is everything after the last `/` of the URL
+    let name = match type_url.rfind('/') {
+        Some(i) => type_url[i + 1..].to_string(),
+        None => type_url,
+    };
+    Ok((name, message.bytes(2).unwrap_or(&[])))
+}
+
+fn pack_any(name: &str, value: &[u8]) -> Vec<u8> {
+    let mut any = vec![];
+    write_bytes_field(&mut any, 1, format!("{}{}", TYPE_URL_PREFIX, name).as_bytes());
+    write_bytes_field(&mut any, 2, value);
+    any
+}
+
+fn invalid_message() -> DataFusionError {
+    DataFusionError::Execution("Invalid protobuf message".to_string())
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        if *pos >= buf.len() || shift > 63 {
+            return Err(invalid_message());
+        }
+        let b = buf[*pos];
+        *pos += 1;
+        value |= ((b & 0x7F) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7F) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn write_varint_field(out: &mut Vec<u8>, number: u64, n: u64) {
+    write_varint(out, number << 3);
+    write_varint(out, n);
+}
+
+fn write_bytes_field(out: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+    write_varint(out, (number << 3) | 2);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_optional_string(out: &mut Vec<u8>, number: u64, s: &Option<String>) {
+    if let Some(ref s) = *s {
+        write_bytes_field(out, number, s.as_bytes());
+    }
+}
+
+/// The varint and length-delimited fields of a protobuf message, by field number. Fixed-width
+/// fields are skipped since none of the messages that are decoded here have any.
+struct ProtoMessage<'a> {
+    varints: Vec<(u64, u64)>,
+    bytes: Vec<(u64, &'a [u8])>,
+}
+
+impl<'a> ProtoMessage<'a> {
+    fn decode(buf: &'a [u8]) -> Result<Self> {
+        let mut message = ProtoMessage {
+            varints: vec![],
+            bytes: vec![],
+        };
+        let mut pos = 0;
+        while pos < buf.len() {
+            let key = read_varint(buf, &mut pos)?;
+            let number = key >> 3;
+            let skip = match key & 7 {
+                0 => {
+                    message.varints.push((number, read_varint(buf, &mut pos)?));
+                    0
+                }
+                1 => 8,
+                2 => {
+                    let len = read_varint(buf, &mut pos)? as usize;
+                    if len > buf.len() - pos {
+                        return Err(invalid_message());
+                    }
+                    message.bytes.push((number, &buf[pos..pos + len]));
+                    len
+                }
+                5 => 4,
+                _ => return Err(invalid_message()),
+            };
+            if skip > buf.len() - pos {
+                return Err(invalid_message());
+            }
+            pos += skip;
+        }
+        Ok(message)
+    }
+
+    /// The value of a varint field, where the last value wins as in protobuf, or zero when it
+    /// is missing
+    fn varint(&self, number: u64) -> u64 {
+        self.varints
+            .iter()
+            .rev()
+            .find(|f| f.0 == number)
+            .map(|f| f.1)
+            .unwrap_or(0)
+    }
+
+    fn bytes(&self, number: u64) -> Option<&'a [u8]> {
+        self.bytes.iter().rev().find(|f| f.0 == number).map(|f| f.1)
+    }
+
+    fn string(&self, number: u64) -> Result<Option<String>> {
+        match self.bytes(number) {
+            Some(bytes) => Ok(Some(utf8(bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The values of a repeated string field
+    fn strings(&self, number: u64) -> Result<Vec<String>> {
+        self.bytes
+            .iter()
+            .filter(|f| f.0 == number)
+            .map(|f| utf8(f.1))
+            .collect()
+    }
+}
+
+fn utf8(bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid_message())
+}
+
+/// Match a `LIKE` pattern, where `%` matches any characters and `_` matches one character
+fn matches_pattern(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    // positions in the pattern that the characters read so far can have reached
+    let mut states = vec![false; pattern.len() + 1];
+    states[0] = true;
+    // a `%` can match no characters
+    for p in 0..pattern.len() {
+        if states[p] && pattern[p] == '%' {
+            states[p + 1] = true;
+        }
+    }
+    for c in s {
+        let mut next = vec![false; pattern.len() + 1];
+        for p in 0..pattern.len() {
+            if !states[p] {
+                continue;
+            }
+            match pattern[p] {
+                '%' => next[p] = true,
+                '_' => next[p + 1] = true,
+                pc if pc == c => next[p + 1] = true,
+                _ => {}
+            }
+        }
+        // a `%` can match no characters
+        for p in 0..pattern.len() {
+            if next[p] && pattern[p] == '%' {
+                next[p + 1] = true;
+            }
+        }
+        states = next;
+    }
+    states[pattern.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_service() -> FlightSqlService {
+        let mut ctx = ExecutionContext::local();
+        let people = ctx
+            .load_csv(
+                "./test/data/people.csv",
+                &Schema::new(vec![
+                    Field::new("id", DataType::Int32, false),
+                    Field::new("name", DataType::Utf8, false),
+                ]),
+                true,
+                None,
+            ).unwrap();
+        ctx.register("people", people.clone());
+        ctx.register("pets", people);
+        FlightSqlService::new(ctx)
+    }
+
+    fn run(service: &mut FlightSqlService, command: FlightSqlCommand) -> String {
+        let df = service.execute(&command).unwrap();
+        service.context().write_string(df).unwrap()
+    }
+
+    #[test]
+    fn metadata_commands() {
+        let mut service = create_service();
+        assert_eq!("datafusion\n", run(&mut service, FlightSqlCommand::GetCatalogs));
+        assert_eq!(
+            "datafusion,public,people,TABLE\n",
+            run(
+                &mut service,
+                FlightSqlCommand::GetTables {
+                    catalog: None,
+                    db_schema_filter_pattern: Some("pub%".to_string()),
+                    table_name_filter_pattern: Some("pe_p%".to_string()),
+                    table_types: vec![],
+                    include_schema: false,
+                }
+            )
+        );
+        assert_eq!(
+            "",
+            run(
+                &mut service,
+                FlightSqlCommand::GetDbSchemas {
+                    catalog: Some("other".to_string()),
+                    db_schema_filter_pattern: None,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn table_schemas() {
+        let mut service = create_service();
+        let command = FlightSqlCommand::GetTables {
+            catalog: None,
+            db_schema_filter_pattern: None,
+            table_name_filter_pattern: Some("people".to_string()),
+            table_types: vec![],
+            include_schema: true,
+        };
+        let df = service.execute(&command).unwrap();
+        assert_eq!("table_schema", df.schema().column(4).name().as_str());
+        let mut relation = service.ctx.create_execution_plan(df.plan()).unwrap();
+        let batch = relation.scan().next().unwrap().unwrap();
+        assert_eq!(1, batch.num_rows());
+        let expected = encode_schema(&service.ctx.table_schema("people").unwrap()).unwrap();
+        match batch.column(4).data() {
+            &ArrayData::Utf8(ref list) => assert_eq!(&expected[..], list.get(0)),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn encoded_commands() {
+        let commands = vec![
+            FlightSqlCommand::StatementQuery {
+                query: "SELECT 1".to_string(),
+            },
+            FlightSqlCommand::StatementLogicalPlan {
+                plan: "{}".to_string(),
+            },
+            FlightSqlCommand::PreparedStatementQuery {
+                handle: vec![1, 200],
+            },
+            FlightSqlCommand::GetCatalogs,
+            FlightSqlCommand::GetDbSchemas {
+                catalog: Some("".to_string()),
+                db_schema_filter_pattern: None,
+            },
+            FlightSqlCommand::GetTables {
+                catalog: None,
+                db_schema_filter_pattern: Some("p%".to_string()),
+                table_name_filter_pattern: None,
+                table_types: vec!["TABLE".to_string(), "VIEW".to_string()],
+                include_schema: true,
+            },
+            FlightSqlCommand::GetTableTypes,
+        ];
+        for command in commands {
+            assert_eq!(command, FlightSqlCommand::decode(&command.encode()).unwrap());
+        }
+
+        // the message that a client sends for CommandStatementQuery { query: "x" }
+        let mut any = vec![0x0A, 0x43];
+        any.extend_from_slice(
+            b"type.googleapis.com/arrow.flight.protocol.sql.CommandStatementQuery",
+        );
+        any.extend_from_slice(&[0x12, 0x03, 0x0A, 0x01, b'x']);
+        assert_eq!(
+            FlightSqlCommand::StatementQuery {
+                query: "x".to_string()
+            },
+            FlightSqlCommand::decode(&any).unwrap()
+        );
+        assert_eq!(any, FlightSqlCommand::decode(&any).unwrap().encode());
+
+        let substrait = pack_any(
+            "arrow.flight.protocol.sql.CommandStatementSubstraitPlan",
+            &[],
+        );
+        assert!(FlightSqlCommand::decode(&substrait).is_err());
+        assert!(FlightSqlCommand::decode(&[0x0A, 0x05, b'a']).is_err());
+
+        let mut service = create_service();
+        let command = FlightSqlCommand::StatementQuery {
+            query: "SELECT id FROM people WHERE id < 3".to_string(),
+        };
+        let df = service.execute_encoded(&command.encode()).unwrap();
+        assert_eq!("1\n2\n", service.context().write_string(df).unwrap());
+    }
+
+    #[test]
+    fn actions() {
+        let mut service = create_service();
+        let mut request = vec![];
+        write_bytes_field(&mut request, 1, b"SELECT id FROM people WHERE id > ?");
+        let body = pack_any(
+            "arrow.flight.protocol.sql.ActionCreatePreparedStatementRequest",
+            &request,
+        );
+        let result = service.do_action("CreatePreparedStatement", &body).unwrap();
+        let (name, value) = unpack_any(&result).unwrap();
+        assert_eq!("arrow.flight.protocol.sql.ActionCreatePreparedStatementResult", name);
+        let message = ProtoMessage::decode(value).unwrap();
+        let handle = message.bytes(1).unwrap().to_vec();
+        assert!(message.bytes(2).is_some());
+
+        let mut request = vec![];
+        write_bytes_field(&mut request, 1, &handle);
+        let body = pack_any(
+            "arrow.flight.protocol.sql.ActionClosePreparedStatementRequest",
+            &request,
+        );
+        assert!(service
+            .do_action("ClosePreparedStatement", &body)
+            .unwrap()
+            .is_empty());
+        assert!(service.do_action("ClosePreparedStatement", &body).is_err());
+        assert!(service.do_action("CancelQuery", &body).is_err());
+    }
+
+    #[test]
+    fn prepared_statements() {
+        let mut service = create_service();
+        let prepared = service
+            .create_prepared_statement("SELECT id FROM people WHERE id > $1 AND id < ?")
+            .unwrap();
+        assert_eq!(2, prepared.parameter_count);
+        assert_eq!(
+            "id",
+            prepared.dataset_schema.unwrap().column(0).name().as_str()
+        );
+
+        let parameters = DefaultRecordBatch {
+            schema: Rc::new(Schema::new(vec![
+                Field::new("$1", DataType::Int64, false),
+                Field::new("$2", DataType::Int64, false),
+            ])),
+            data: vec![
+                Value::Column(Rc::new(Array::from(vec![7i64]))),
+                Value::Column(Rc::new(Array::from(vec![10i64]))),
+            ],
+            row_count: 1,
+        };
+        service
+            .bind_parameters(&prepared.handle, &parameters)
+            .unwrap();
+        let command = FlightSqlCommand::PreparedStatementQuery {
+            handle: prepared.handle.clone(),
+        };
+        assert_eq!("8\n9\n", run(&mut service, command.clone()));
+
+        service.close_prepared_statement(&prepared.handle).unwrap();
+        assert!(service.execute(&command).is_err());
+    }
+
+    #[test]
+    fn submitted_plans() {
+        let mut service = create_service();
+        let df = service
+            .ctx
+            .sql("SELECT name FROM people WHERE id > 8 ORDER BY name DESC")
+            .unwrap();
+        let plan = serialize_plan(df.plan()).unwrap();
+        assert_eq!(
+            "Juliet\nIrene\n",
+            run(&mut service, FlightSqlCommand::StatementLogicalPlan { plan })
+        );
+
+        let command = FlightSqlCommand::StatementLogicalPlan {
+            plan: "{\"node_type\": \"Projection\"}".to_string(),
+        };
+        assert!(service.execute(&command).is_err());
+    }
+
+    #[test]
+    fn like_patterns() {
+        assert!(matches_pattern("%", ""));
+        assert!(matches_pattern("p%s", "people_pets"));
+        assert!(matches_pattern("_e%", "pets"));
+        assert!(!matches_pattern("_e%", "e"));
+        assert!(!matches_pattern("pets", "pets2"));
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers shared by the SQL functions
+
+use std::str;
+
+use super::super::errors::*;
+use super::super::types::*;
+
+/// Get an owned string from a value of a Utf8 array. Binary columns are stored as Utf8, so
+/// bytes that are not valid utf8 are reported as an error rather than assumed to be text.
+pub fn utf8_value(bytes: &[u8]) -> Result<String> {
+    str::from_utf8(bytes).map(|s| s.to_string()).map_err(|e| {
+        DataFusionError::Execution(format!("Binary value is not valid utf8: {}", e))
+    })
+}
+
+/// Get the string value of an argument for a row, where `name` is the function it is an
+/// argument of. Scalar arguments apply to every row.
+pub fn arg_str<'a>(name: &str, arg: &'a Value, i: usize) -> Result<&'a str> {
+    let bytes = match *arg {
+        Value::Column(ref arr) => match arr.data() {
+            &ArrayData::Utf8(ref list) => list.get(i),
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "Unsupported arg type for {}",
+                    name
+                )))
+            }
+        },
+        Value::Scalar(ref v) => match v.as_ref() {
+            ScalarValue::Utf8(ref s) => s.as_bytes(),
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "Unsupported arg type for {}",
+                    name
+                )))
+            }
+        },
+    };
+    str::from_utf8(bytes)
+        .map_err(|e| DataFusionError::Execution(format!("Invalid utf8 in {}: {}", name, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[test]
+    fn test_utf8_value() {
+        assert_eq!("abc", utf8_value(b"abc").unwrap());
+        assert!(utf8_value(&[0x61, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_arg_str() {
+        let column = Value::Column(Rc::new(Array::from(vec!["a", "b"])));
+        assert_eq!("b", arg_str("f", &column, 1).unwrap());
+        let scalar = Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new("c".to_string()))));
+        assert_eq!("c", arg_str("f", &scalar, 1).unwrap());
+        let number = Value::Scalar(Rc::new(ScalarValue::Int64(1)));
+        assert!(arg_str("f", &number, 0).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time bucketing functions for downsampling time series
+//!
+//! Timestamps are represented as Int64 milliseconds since the Unix epoch.
+
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::errors::*;
+use super::super::types::*;
+
+/// Parse an interval such as `15 minutes` or `1 day` into milliseconds
+pub fn parse_interval(interval: &str) -> Result<i64> {
+    let parts: Vec<&str> = interval.split_whitespace().collect();
+    let (n, unit) = match parts.len() {
+        2 => (parts[0], parts[1]),
+        _ => return Err(invalid_interval(interval)),
+    };
+    let n: i64 = n.parse().map_err(|_| invalid_interval(interval))?;
+    let millis = match unit.to_lowercase().as_ref() {
+        "ms" | "millisecond" | "milliseconds" => 1,
+        "s" | "second" | "seconds" => 1_000,
+        "m" | "min" | "minute" | "minutes" => 60_000,
+        "h" | "hour" | "hours" => 3_600_000,
+        "d" | "day" | "days" => 86_400_000,
+        "w" | "week" | "weeks" => 604_800_000,
+        _ => return Err(invalid_interval(interval)),
+    };
+    if n <= 0 {
+        return Err(invalid_interval(interval));
+    }
+    Ok(n * millis)
+}
+
+fn invalid_interval(interval: &str) -> DataFusionError {
+    DataFusionError::Execution(format!("Invalid interval '{}'", interval))
+}
+
+/// Truncate a timestamp to the start of the bucket containing it, where buckets of width
+/// `stride` are aligned to `origin`
+pub fn bin(ts: i64, stride: i64, origin: i64) -> i64 {
+    let delta = ts - origin;
+    // round towards negative infinity so timestamps before the origin land in the right bucket
+    let mut buckets = delta / stride;
+    if delta % stride < 0 {
+        buckets -= 1;
+    }
+    origin + buckets * stride
+}
+
+/// Parse a timestamp in the given format into milliseconds since the epoch. The format can
+/// contain `%Y` (four digit year), `%m`, `%d`, `%H`, `%M` and `%S` (two digits each), `%f`
+/// (fractional seconds) and literal characters, and must match the whole string.
+pub fn parse_timestamp(s: &str, format: &str) -> Option<i64> {
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second, mut millis) = (0, 0, 0, 0);
+    let bytes = s.as_bytes();
+    let mut pos = 0;
+    let mut spec = false;
+    for c in format.chars() {
+        if !spec {
+            if c == '%' {
+                spec = true;
+            } else {
+                let mut buf = [0; 4];
+                let literal = c.encode_utf8(&mut buf).as_bytes();
+                if !bytes[pos..].starts_with(literal) {
+                    return None;
+                }
+                pos += literal.len();
+            }
+            continue;
+        }
+        spec = false;
+        let (min_digits, max_digits) = match c {
+            'Y' => (4, 4),
+            'm' | 'd' | 'H' | 'M' | 'S' => (2, 2),
+            'f' => (1, 9),
+            _ => return None,
+        };
+        let digits = bytes[pos..]
+            .iter()
+            .take(max_digits)
+            .take_while(|b| b.is_ascii_digit())
+            .count();
+        if digits < min_digits {
+            return None;
+        }
+        let n: i64 = s[pos..pos + digits].parse().ok()?;
+        pos += digits;
+        match c {
+            'Y' => year = n,
+            'm' => month = n,
+            'd' => day = n,
+            'H' => hour = n,
+            'M' => minute = n,
+            'S' => second = n,
+            _ => millis = n * 1000 / 10_i64.pow(digits as u32),
+        }
+    }
+    if spec || pos != bytes.len() {
+        return None;
+    }
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some(((days * 24 + hour) * 60 + minute) * 60_000 + second * 1000 + millis)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Number of days between 1970-01-01 and a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_from_march = (month + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn bin_values(name: &str, interval: &Value, ts: &Value, origin: i64) -> Result<Value> {
+    let stride = match *interval {
+        Value::Scalar(ref v) => match v.as_ref() {
+            ScalarValue::Utf8(ref s) => parse_interval(s)?,
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "{} requires an interval string",
+                    name
+                )))
+            }
+        },
+        Value::Column(_) => {
+            return Err(DataFusionError::Execution(format!(
+                "{} requires a literal interval",
+                name
+            )))
+        }
+    };
+    match *ts {
+        Value::Column(ref arr) => match arr.data() {
+            &ArrayData::Int64(ref v) => Ok(Value::Column(Rc::new(Array::from(
+                v.iter().map(|t| bin(t, stride, origin)).collect::<Vec<i64>>(),
+            )))),
+            _ => Err(DataFusionError::Execution(format!(
+                "Unsupported timestamp type for {}",
+                name
+            ))),
+        },
+        Value::Scalar(ref v) => match v.as_ref() {
+            ScalarValue::Int64(t) => Ok(Value::Scalar(Rc::new(ScalarValue::Int64(bin(
+                *t, stride, origin,
+            ))))),
+            _ => Err(DataFusionError::Execution(format!(
+                "Unsupported timestamp type for {}",
+                name
+            ))),
+        },
+    }
+}
+
+/// `date_bin(interval, timestamp, origin)` truncates timestamps to buckets of the given
+/// interval aligned to the origin
+pub struct DateBinFunction {}
+
+impl ScalarFunction for DateBinFunction {
+    fn name(&self) -> String {
+        "date_bin".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        assert_eq!(3, args.len());
+        let origin = match args[2] {
+            Value::Scalar(ref v) => match v.as_ref() {
+                ScalarValue::Int64(n) => *n,
+                _ => {
+                    return Err(DataFusionError::Execution(
+                        "date_bin requires an Int64 origin".to_string(),
+                    ))
+                }
+            },
+            Value::Column(_) => {
+                return Err(DataFusionError::Execution(
+                    "date_bin requires a literal origin".to_string(),
+                ))
+            }
+        };
+        bin_values("date_bin", &args[0], &args[1], origin)
+    }
+
+    fn args(&self) -> Vec<Field> {
+        vec![
+            Field::new("interval", DataType::Utf8, false),
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("origin", DataType::Int64, false),
+        ]
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Int64
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+/// `time_bucket(interval, timestamp)` is `date_bin` with buckets aligned to the Unix epoch
+pub struct TimeBucketFunction {}
+
+impl ScalarFunction for TimeBucketFunction {
+    fn name(&self) -> String {
+        "time_bucket".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        assert_eq!(2, args.len());
+        bin_values("time_bucket", &args[0], &args[1], 0)
+    }
+
+    fn args(&self) -> Vec<Field> {
+        vec![
+            Field::new("interval", DataType::Utf8, false),
+            Field::new("timestamp", DataType::Int64, false),
+        ]
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Int64
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(900_000, parse_interval("15 minutes").unwrap());
+        assert_eq!(86_400_000, parse_interval("1 DAY").unwrap());
+        assert!(parse_interval("15").is_err());
+        assert!(parse_interval("0 seconds").is_err());
+        assert!(parse_interval("1 fortnight").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(Some(0), parse_timestamp("1970-01-01", "%Y-%m-%d"));
+        assert_eq!(
+            Some(1_526_774_400_000),
+            parse_timestamp("2018-05-20 00:00:00", "%Y-%m-%d %H:%M:%S")
+        );
+        assert_eq!(
+            Some(951_782_400_123),
+            parse_timestamp("29/02/2000 00:00:00.123", "%d/%m/%Y %H:%M:%S.%f")
+        );
+        assert_eq!(Some(-86_400_000), parse_timestamp("1969-12-31", "%Y-%m-%d"));
+        assert_eq!(None, parse_timestamp("2018-02-30", "%Y-%m-%d"));
+        assert_eq!(None, parse_timestamp("2018-05-20", "%Y-%m-%d %H:%M:%S"));
+        assert_eq!(None, parse_timestamp("2018-05-20x", "%Y-%m-%d"));
+        assert_eq!(None, parse_timestamp("20180520", "%Y-%m-%d"));
+    }
+
+    #[test]
+    fn test_bin() {
+        assert_eq!(0, bin(999, 1000, 0));
+        assert_eq!(1000, bin(1000, 1000, 0));
+        assert_eq!(-1000, bin(-1, 1000, 0));
+        assert_eq!(250, bin(1100, 1000, 250));
+    }
+}
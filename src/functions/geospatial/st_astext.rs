@@ -42,16 +42,16 @@ impl ScalarFunction for STAsText {
                                 .collect();
                             Ok(Value::Column(Rc::new(Array::from(wkt))))
                         }
-                        _ => Err(ExecutionError::General(
+                        _ => Err(DataFusionError::Execution(
                             "Unsupported type for ST_AsText".to_string(),
                         )),
                     }
                 }
-                _ => Err(ExecutionError::General(
+                _ => Err(DataFusionError::Execution(
                     "Unsupported type for ST_AsText".to_string(),
                 )),
             },
-            _ => Err(ExecutionError::General(
+            _ => Err(DataFusionError::Execution(
                 "Unsupported type for ST_AsText".to_string(),
             )),
         }
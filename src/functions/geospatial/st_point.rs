@@ -38,12 +38,12 @@ impl ScalarFunction for STPointFunc {
                         let new_array = Array::new(arr1.len() as usize, ArrayData::Struct(nested));
                         Ok(Value::Column(Rc::new(new_array)))
                     }
-                    _ => Err(ExecutionError::General(
+                    _ => Err(DataFusionError::Execution(
                         "Unsupported type for ST_Point".to_string(),
                     )),
                 }
             }
-            _ => Err(ExecutionError::General(
+            _ => Err(DataFusionError::Execution(
                 "Unsupported type for ST_Point".to_string(),
             )),
         }
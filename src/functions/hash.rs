@@ -0,0 +1,426 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hash and checksum functions: md5, sha256, xxhash64 and crc32
+
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::errors::*;
+use super::super::types::*;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+/// Result of applying a hash to each value of the argument
+enum Hashed<T> {
+    Column(Vec<T>),
+    Scalar(T),
+}
+
+/// Apply a hash function to every value of a single utf8 argument
+fn hash_arg<T, F>(name: &str, args: &[Value], f: F) -> Result<Hashed<T>>
+where
+    F: Fn(&[u8]) -> T,
+{
+    if args.len() != 1 {
+        return Err(DataFusionError::Execution(format!(
+            "{} expects a single argument",
+            name
+        )));
+    }
+    match args[0] {
+        Value::Column(ref arr) => match arr.data() {
+            &ArrayData::Utf8(ref list) => Ok(Hashed::Column(
+                (0..list.len() as usize).map(|i| f(list.get(i))).collect(),
+            )),
+            _ => Err(DataFusionError::Execution(format!(
+                "Unsupported arg type for {}",
+                name
+            ))),
+        },
+        Value::Scalar(ref v) => match v.as_ref() {
+            ScalarValue::Utf8(ref s) => Ok(Hashed::Scalar(f(s.as_bytes()))),
+            _ => Err(DataFusionError::Execution(format!(
+                "Unsupported arg type for {}",
+                name
+            ))),
+        },
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_value(hashed: Hashed<String>) -> Value {
+    match hashed {
+        Hashed::Column(v) => Value::Column(Rc::new(Array::from(v))),
+        Hashed::Scalar(v) => Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new(v)))),
+    }
+}
+
+fn utf8_arg() -> Vec<Field> {
+    vec![Field::new("value", DataType::Utf8, false)]
+}
+
+/// Pad a message as required by md5 and sha256, with the bit length in the final eight bytes
+fn pad_message(data: &[u8], big_endian: bool) -> Vec<u8> {
+    let mut msg = data.to_vec();
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    let mut len_bytes = [0u8; 8];
+    if big_endian {
+        BigEndian::write_u64(&mut len_bytes, bit_len);
+    } else {
+        LittleEndian::write_u64(&mut len_bytes, bit_len);
+    }
+    msg.extend_from_slice(&len_bytes);
+    msg
+}
+
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    let k: Vec<u32> = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32)
+        .collect();
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    for chunk in pad_message(data, false).chunks(64) {
+        let mut m = [0u32; 16];
+        for (i, word) in m.iter_mut().enumerate() {
+            *word = LittleEndian::read_u32(&chunk[i * 4..i * 4 + 4]);
+        }
+        let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(k[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(MD5_SHIFTS[i]));
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+
+    let mut digest = [0u8; 16];
+    for (i, s) in state.iter().enumerate() {
+        LittleEndian::write_u32(&mut digest[i * 4..i * 4 + 4], *s);
+    }
+    digest
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    for chunk in pad_message(data, true).chunks(64) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            w[i] = BigEndian::read_u32(&chunk[i * 4..i * 4 + 4]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut v = state;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+            v[7] = v[6];
+            v[6] = v[5];
+            v[5] = v[4];
+            v[4] = v[3].wrapping_add(t1);
+            v[3] = v[2];
+            v[2] = v[1];
+            v[1] = v[0];
+            v[0] = t1.wrapping_add(t2);
+        }
+        for i in 0..8 {
+            state[i] = state[i].wrapping_add(v[i]);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (i, s) in state.iter().enumerate() {
+        BigEndian::write_u32(&mut digest[i * 4..i * 4 + 4], *s);
+    }
+    digest
+}
+
+const XXH_PRIME64_1: u64 = 11400714785074694791;
+const XXH_PRIME64_2: u64 = 14029467366897019727;
+const XXH_PRIME64_3: u64 = 1609587929392839161;
+const XXH_PRIME64_4: u64 = 9650029242287828579;
+const XXH_PRIME64_5: u64 = 2870177450012600261;
+
+fn xxh64_round(acc: u64, lane: u64) -> u64 {
+    acc.wrapping_add(lane.wrapping_mul(XXH_PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_PRIME64_1)
+}
+
+fn xxh64_merge_round(acc: u64, val: u64) -> u64 {
+    (acc ^ xxh64_round(0, val))
+        .wrapping_mul(XXH_PRIME64_1)
+        .wrapping_add(XXH_PRIME64_4)
+}
+
+pub fn xxhash64(data: &[u8], seed: u64) -> u64 {
+    let len = data.len();
+    let mut offset = 0;
+
+    let mut h = if len >= 32 {
+        let mut v1 = seed
+            .wrapping_add(XXH_PRIME64_1)
+            .wrapping_add(XXH_PRIME64_2);
+        let mut v2 = seed.wrapping_add(XXH_PRIME64_2);
+        let mut v3 = seed;
+        let mut v4 = seed.wrapping_sub(XXH_PRIME64_1);
+        while offset + 32 <= len {
+            v1 = xxh64_round(v1, LittleEndian::read_u64(&data[offset..]));
+            v2 = xxh64_round(v2, LittleEndian::read_u64(&data[offset + 8..]));
+            v3 = xxh64_round(v3, LittleEndian::read_u64(&data[offset + 16..]));
+            v4 = xxh64_round(v4, LittleEndian::read_u64(&data[offset + 24..]));
+            offset += 32;
+        }
+        let mut h = v1
+            .rotate_left(1)
+            .wrapping_add(v2.rotate_left(7))
+            .wrapping_add(v3.rotate_left(12))
+            .wrapping_add(v4.rotate_left(18));
+        h = xxh64_merge_round(h, v1);
+        h = xxh64_merge_round(h, v2);
+        h = xxh64_merge_round(h, v3);
+        xxh64_merge_round(h, v4)
+    } else {
+        seed.wrapping_add(XXH_PRIME64_5)
+    };
+
+    h = h.wrapping_add(len as u64);
+
+    while offset + 8 <= len {
+        h ^= xxh64_round(0, LittleEndian::read_u64(&data[offset..]));
+        h = h
+            .rotate_left(27)
+            .wrapping_mul(XXH_PRIME64_1)
+            .wrapping_add(XXH_PRIME64_4);
+        offset += 8;
+    }
+    if offset + 4 <= len {
+        h ^= (LittleEndian::read_u32(&data[offset..]) as u64).wrapping_mul(XXH_PRIME64_1);
+        h = h
+            .rotate_left(23)
+            .wrapping_mul(XXH_PRIME64_2)
+            .wrapping_add(XXH_PRIME64_3);
+        offset += 4;
+    }
+    while offset < len {
+        h ^= (data[offset] as u64).wrapping_mul(XXH_PRIME64_5);
+        h = h.rotate_left(11).wrapping_mul(XXH_PRIME64_1);
+        offset += 1;
+    }
+
+    h ^= h >> 33;
+    h = h.wrapping_mul(XXH_PRIME64_2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(XXH_PRIME64_3);
+    h ^ (h >> 32)
+}
+
+/// CRC-32 using the IEEE polynomial, as used by zlib and gzip
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Returns the md5 digest of a string as 32 hex characters
+pub struct Md5Function {}
+
+impl ScalarFunction for Md5Function {
+    fn name(&self) -> String {
+        "md5".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        Ok(hex_value(hash_arg("md5", args, |b| to_hex(&md5(b)))?))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        utf8_arg()
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+}
+
+/// Returns the sha256 digest of a string as 64 hex characters
+pub struct Sha256Function {}
+
+impl ScalarFunction for Sha256Function {
+    fn name(&self) -> String {
+        "sha256".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        Ok(hex_value(hash_arg("sha256", args, |b| to_hex(&sha256(b)))?))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        utf8_arg()
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+}
+
+/// Returns the 64-bit xxHash of a string, using a seed of zero
+pub struct XxHash64Function {}
+
+impl ScalarFunction for XxHash64Function {
+    fn name(&self) -> String {
+        "xxhash64".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        match hash_arg("xxhash64", args, |b| xxhash64(b, 0))? {
+            Hashed::Column(v) => Ok(Value::Column(Rc::new(Array::from(v)))),
+            Hashed::Scalar(v) => Ok(Value::Scalar(Rc::new(ScalarValue::UInt64(v)))),
+        }
+    }
+
+    fn args(&self) -> Vec<Field> {
+        utf8_arg()
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::UInt64
+    }
+}
+
+/// Returns the CRC-32 checksum of a string
+pub struct Crc32Function {}
+
+impl ScalarFunction for Crc32Function {
+    fn name(&self) -> String {
+        "crc32".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        match hash_arg("crc32", args, crc32)? {
+            Hashed::Column(v) => Ok(Value::Column(Rc::new(Array::from(v)))),
+            Hashed::Scalar(v) => Ok(Value::Scalar(Rc::new(ScalarValue::UInt32(v)))),
+        }
+    }
+
+    fn args(&self) -> Vec<Field> {
+        utf8_arg()
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::UInt32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5() {
+        assert_eq!("d41d8cd98f00b204e9800998ecf8427e", to_hex(&md5(b"")));
+        assert_eq!("900150983cd24fb0d6963f7d28e17f72", to_hex(&md5(b"abc")));
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            to_hex(&sha256(b"abc"))
+        );
+    }
+
+    #[test]
+    fn test_xxhash64() {
+        assert_eq!(0xEF46DB3751D8E999, xxhash64(b"", 0));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0xCBF43926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_hash_column() {
+        let arr = Value::Column(Rc::new(Array::from(vec!["abc", ""])));
+        match Crc32Function {}.execute(&[arr]).unwrap() {
+            Value::Column(ref a) => assert_eq!(2, a.len()),
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functions for extracting values from JSON documents stored in utf8 columns
+//!
+//! Paths are written as `$.store.books[0].title`, where the leading `$` is optional and keys
+//! containing periods can be quoted as `$["a.b"]`. Rows where the document cannot be parsed or
+//! the path does not exist evaluate to null.
+
+use std::rc::Rc;
+
+use arrow::array::ListArray;
+use arrow::bitmap::*;
+use arrow::builder::*;
+use arrow::datatypes::*;
+use arrow::list_builder::ListBuilder;
+
+use json;
+use json::JsonValue;
+
+use super::super::errors::*;
+use super::super::types::*;
+use super::common::arg_str;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathElement {
+    Key(String),
+    Index(usize),
+}
+
+/// Parse a JSON path such as `$.a.b[2]` into its elements
+pub fn parse_path(path: &str) -> Result<Vec<PathElement>> {
+    let mut elements = vec![];
+    let mut chars = path.trim().chars().peekable();
+    if chars.peek() == Some(&'$') {
+        chars.next();
+    }
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            '.' => {
+                chars.next();
+            }
+            '[' => {
+                chars.next();
+                match chars.peek() {
+                    Some(&q) if q == '"' || q == '\'' => {
+                        chars.next();
+                        let key: String = chars.by_ref().take_while(|c| *c != q).collect();
+                        elements.push(PathElement::Key(key));
+                        if chars.next() != Some(']') {
+                            return Err(invalid_path(path));
+                        }
+                    }
+                    _ => {
+                        let index: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                        match index.trim().parse::<usize>() {
+                            Ok(n) => elements.push(PathElement::Index(n)),
+                            Err(_) => return Err(invalid_path(path)),
+                        }
+                    }
+                }
+            }
+            _ => {
+                let mut key = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    key.push(c);
+                    chars.next();
+                }
+                elements.push(PathElement::Key(key));
+            }
+        }
+    }
+    Ok(elements)
+}
+
+fn invalid_path(path: &str) -> DataFusionError {
+    DataFusionError::Execution(format!("Invalid JSON path '{}'", path))
+}
+
+/// Find the value at a path within a JSON document, returning None if it does not exist
+pub fn extract_path<'a>(doc: &'a JsonValue, path: &[PathElement]) -> Option<&'a JsonValue> {
+    let mut value = doc;
+    for element in path {
+        value = match *element {
+            PathElement::Key(ref k) if value.is_object() => &value[k.as_str()],
+            PathElement::Index(n) if value.is_array() => &value[n],
+            _ => return None,
+        };
+        if value.is_null() {
+            return None;
+        }
+    }
+    Some(value)
+}
+
+/// Extract the value at the given path for every row of the arguments, also returning whether
+/// all arguments were scalar
+fn extract_values<T, F>(name: &str, args: &[Value], f: F) -> Result<(Vec<Option<T>>, bool)>
+where
+    F: Fn(&JsonValue) -> Option<T>,
+{
+    if args.len() != 2 {
+        return Err(DataFusionError::Execution(format!(
+            "{} expects a JSON document and a path",
+            name
+        )));
+    }
+    let (len, is_scalar) = match (&args[0], &args[1]) {
+        (&Value::Column(ref arr), _) | (_, &Value::Column(ref arr)) => (arr.len(), false),
+        _ => (1, true),
+    };
+
+    // the path is usually a literal so only parse it again when it changes
+    let mut path_str = "";
+    let mut path = vec![];
+    let mut values = Vec::with_capacity(len);
+    for i in 0..len {
+        let p = arg_str(name, &args[1], i)?;
+        if i == 0 || p != path_str {
+            path = parse_path(p)?;
+            path_str = p;
+        }
+        let value = match json::parse(arg_str(name, &args[0], i)?) {
+            Ok(doc) => extract_path(&doc, &path).and_then(|v| f(v)),
+            Err(_) => None,
+        };
+        values.push(value);
+    }
+    Ok((values, is_scalar))
+}
+
+fn json_string(value: &JsonValue) -> Option<String> {
+    Some(value.dump())
+}
+
+fn json_unquoted_string(value: &JsonValue) -> Option<String> {
+    match value.as_str() {
+        Some(s) => Some(s.to_string()),
+        None => Some(value.dump()),
+    }
+}
+
+fn utf8_result(values: Vec<Option<String>>, is_scalar: bool) -> Value {
+    if is_scalar {
+        return Value::Scalar(Rc::new(match values[0] {
+            Some(ref s) => ScalarValue::Utf8(Rc::new(s.clone())),
+            None => ScalarValue::Null,
+        }));
+    }
+    let mut b: ListBuilder<u8> = ListBuilder::with_capacity(values.len());
+    let mut bitmap = Bitmap::new(values.len());
+    let mut null_count = 0;
+    for (i, v) in values.iter().enumerate() {
+        match *v {
+            Some(ref s) => b.push(s.as_bytes()),
+            None => {
+                null_count += 1;
+                bitmap.clear(i);
+                b.push(b"");
+            }
+        }
+    }
+    Value::Column(Rc::new(Array::with_nulls(
+        values.len(),
+        ArrayData::Utf8(ListArray::from(b.finish())),
+        null_count,
+        bitmap,
+    )))
+}
+
+fn json_args() -> Vec<Field> {
+    vec![
+        Field::new("json", DataType::Utf8, false),
+        Field::new("path", DataType::Utf8, false),
+    ]
+}
+
+/// Returns the JSON text of the value at a path, which is what the `->` operator evaluates to
+pub struct JsonExtractFunction {}
+
+impl ScalarFunction for JsonExtractFunction {
+    fn name(&self) -> String {
+        "json_extract".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        let (values, is_scalar) = extract_values("json_extract", args, json_string)?;
+        Ok(utf8_result(values, is_scalar))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        json_args()
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the value at a path as a string, without quotes if the value is a JSON string, which
+/// is what the `->>` operator evaluates to
+pub struct JsonExtractStringFunction {}
+
+impl ScalarFunction for JsonExtractStringFunction {
+    fn name(&self) -> String {
+        "json_extract_string".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        let (values, is_scalar) =
+            extract_values("json_extract_string", args, json_unquoted_string)?;
+        Ok(utf8_result(values, is_scalar))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        json_args()
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+/// Define a function that extracts a typed primitive value from a JSON document
+macro_rules! json_extract_primitive {
+    ($NAME:ident, $SQL_NAME:expr, $TY:ty, $DT:expr, $ACCESSOR:ident, $SCALAR:ident) => {
+        pub struct $NAME {}
+
+        impl ScalarFunction for $NAME {
+            fn name(&self) -> String {
+                $SQL_NAME.to_string()
+            }
+
+            fn execute(&self, args: &[Value]) -> Result<Value> {
+                let (values, is_scalar) = extract_values($SQL_NAME, args, |v| v.$ACCESSOR())?;
+                if is_scalar {
+                    return Ok(Value::Scalar(Rc::new(match values[0] {
+                        Some(v) => ScalarValue::$SCALAR(v),
+                        None => ScalarValue::Null,
+                    })));
+                }
+                let mut b: Builder<$TY> = Builder::with_capacity(values.len());
+                let mut bitmap = Bitmap::new(values.len());
+                let mut null_count = 0;
+                for (i, v) in values.iter().enumerate() {
+                    match *v {
+                        Some(v) => b.push(v),
+                        None => {
+                            null_count += 1;
+                            bitmap.clear(i);
+                            b.push(<$TY>::default());
+                        }
+                    }
+                }
+                Ok(Value::Column(Rc::new(Array::with_nulls(
+                    values.len(),
+                    ArrayData::from(b.finish()),
+                    null_count,
+                    bitmap,
+                ))))
+            }
+
+            fn args(&self) -> Vec<Field> {
+                json_args()
+            }
+
+            fn return_type(&self) -> DataType {
+                $DT
+            }
+
+            fn null_propagating(&self) -> bool {
+                true
+            }
+        }
+    };
+}
+
+json_extract_primitive!(
+    JsonExtractIntFunction,
+    "json_extract_int",
+    i64,
+    DataType::Int64,
+    as_i64,
+    Int64
+);
+json_extract_primitive!(
+    JsonExtractDoubleFunction,
+    "json_extract_double",
+    f64,
+    DataType::Float64,
+    as_f64,
+    Float64
+);
+json_extract_primitive!(
+    JsonExtractBoolFunction,
+    "json_extract_bool",
+    bool,
+    DataType::Boolean,
+    as_bool,
+    Boolean
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            vec![
+                PathElement::Key("a".to_string()),
+                PathElement::Key("b.c".to_string()),
+                PathElement::Index(2),
+            ],
+            parse_path("$.a[\"b.c\"][2]").unwrap()
+        );
+        assert_eq!(
+            vec![PathElement::Key("name".to_string())],
+            parse_path("name").unwrap()
+        );
+        assert!(parse_path("$.a[x]").is_err());
+    }
+
+    #[test]
+    fn test_json_extract() {
+        let docs = Value::Column(Rc::new(Array::from(vec![
+            "{\"a\": {\"b\": [1, \"two\"]}}",
+            "{\"a\": 1}",
+            "not json",
+        ])));
+        let path = Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new("$.a.b[1]".to_string()))));
+        match JsonExtractStringFunction {}.execute(&[docs, path]).unwrap() {
+            Value::Column(ref arr) => {
+                assert_eq!(2, arr.null_count());
+                match arr.data() {
+                    ArrayData::Utf8(ref list) => assert_eq!(b"two", list.get(0)),
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_json_extract_int_scalar() {
+        let doc = Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new(
+            "{\"n\": [10, 20]}".to_string(),
+        ))));
+        let path = Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new("n[1]".to_string()))));
+        match JsonExtractIntFunction {}.execute(&[doc, path]).unwrap() {
+            Value::Scalar(ref v) => assert_eq!(ScalarValue::Int64(20), *v.as_ref()),
+            _ => panic!(),
+        }
+    }
+}
@@ -36,7 +36,7 @@ impl ScalarFunction for SqrtFunction {
                 ArrayData::Float64(ref v) => Ok(Value::Column(Rc::new(Array::from(
                     v.iter().map(|v| v.sqrt()).collect::<Vec<f64>>(),
                 )))),
-                _ => Err(ExecutionError::General(
+                _ => Err(DataFusionError::Execution(
                     "Unsupported arg type for sqrt".to_string(),
                 )),
             },
@@ -44,7 +44,7 @@ impl ScalarFunction for SqrtFunction {
                 ScalarValue::Float64(ref n) => {
                     Ok(Value::Scalar(Rc::new(ScalarValue::Float64(n.sqrt()))))
                 }
-                _ => Err(ExecutionError::General(
+                _ => Err(DataFusionError::Execution(
                     "Unsupported arg type for sqrt".to_string(),
                 )),
             },
@@ -58,4 +58,8 @@ impl ScalarFunction for SqrtFunction {
     fn return_type(&self) -> DataType {
         DataType::Float64
     }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
 }
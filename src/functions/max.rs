@@ -15,10 +15,10 @@
 //! MAX() aggregate function
 
 use std::rc::Rc;
-use std::str;
 
 use super::super::errors::*;
 use super::super::types::*;
+use super::common::utf8_value;
 
 //use arrow::array::*;
 use arrow::datatypes::*;
@@ -99,9 +99,9 @@ impl AggregateFunction for MaxFunction {
                     ArrayData::Float64(ref buf) => max_in_column!(self, buf, Float64),
                     ArrayData::Utf8(ref list) => {
                         if list.len() > 0 {
-                            let mut s = str::from_utf8(list.get(0)).unwrap().to_string();
+                            let mut s = utf8_value(list.get(0))?;
                             for i in 1..list.len() {
-                                let s2 = str::from_utf8(list.get(i)).unwrap().to_string();
+                                let s2 = utf8_value(list.get(i))?;
                                 if s2 < s {
                                     s = s2;
                                 }
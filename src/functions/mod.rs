@@ -14,9 +14,16 @@
 
 //! Example SQL functions
 
+pub mod common;
 pub mod count;
+pub mod datetime;
 pub mod geospatial;
+pub mod hash;
+pub mod json;
 pub mod math;
 pub mod max;
 pub mod min;
+pub mod regex;
 pub mod sum;
+pub mod uuid;
+pub mod window;
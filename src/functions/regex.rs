@@ -0,0 +1,347 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functions for extracting the capture groups of regular expressions from utf8 columns, such
+//! as the fields of log lines that were loaded as a single string column
+//!
+//! Patterns use the syntax of the `regex` crate. Rows that the pattern does not match, and
+//! groups that do not participate in a match, evaluate to null.
+
+use std::rc::Rc;
+
+use arrow::array::ListArray;
+use arrow::bitmap::*;
+use arrow::datatypes::*;
+use arrow::list_builder::ListBuilder;
+
+use regex::{Captures, Regex};
+
+use super::super::errors::*;
+use super::super::types::*;
+use super::common::arg_str;
+
+/// Compile a pattern, reporting an invalid one as an error
+pub fn compile_pattern(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| {
+        DataFusionError::Execution(format!("Invalid regular expression '{}': {}", pattern, e))
+    })
+}
+
+/// The fields of the struct that `regexp_extract_all` returns for a pattern, which are the
+/// capture groups of the pattern named `c1`, `c2` etc. unless the group has a name
+pub fn capture_fields(pattern: &str) -> Result<Vec<Field>> {
+    Ok(compile_pattern(pattern)?
+        .capture_names()
+        .enumerate()
+        .skip(1)
+        .map(|(i, name)| match name {
+            Some(name) => Field::new(name, DataType::Utf8, true),
+            None => Field::new(&format!("c{}", i), DataType::Utf8, true),
+        })
+        .collect())
+}
+
+/// Get the value of the group argument of `regexp_extract` for a row
+fn arg_group(arg: &Value, i: usize) -> Result<i64> {
+    match *arg {
+        Value::Column(ref arr) => match arr.data() {
+            &ArrayData::Int64(ref v) => Ok(*v.get(i)),
+            _ => Err(DataFusionError::Execution(
+                "Unsupported arg type for regexp_extract".to_string(),
+            )),
+        },
+        Value::Scalar(ref v) => match v.as_ref() {
+            ScalarValue::Int64(n) => Ok(*n),
+            _ => Err(DataFusionError::Execution(
+                "Unsupported arg type for regexp_extract".to_string(),
+            )),
+        },
+    }
+}
+
+/// The number of rows of the arguments, and whether they were all scalar
+fn args_len(args: &[Value]) -> (usize, bool) {
+    match args.iter().find(|arg| match *arg {
+        Value::Column(_) => true,
+        _ => false,
+    }) {
+        Some(&Value::Column(ref arr)) => (arr.len(), false),
+        _ => (1, true),
+    }
+}
+
+/// Match the pattern of every row against its text, calling `f` with the captures of each
+/// row that matches
+fn capture_values<T, F>(name: &str, args: &[Value], mut f: F) -> Result<Vec<Option<T>>>
+where
+    F: FnMut(usize, &Captures) -> Result<Option<T>>,
+{
+    let (len, _) = args_len(args);
+
+    // the pattern is usually a literal so only compile it again when it changes
+    let mut pattern_str = "";
+    let mut pattern = None;
+    let mut values = Vec::with_capacity(len);
+    for i in 0..len {
+        let p = arg_str(name, &args[1], i)?;
+        if pattern.is_none() || p != pattern_str {
+            pattern = Some(compile_pattern(p)?);
+            pattern_str = p;
+        }
+        let text = arg_str(name, &args[0], i)?;
+        values.push(match pattern.as_ref().unwrap().captures(text) {
+            Some(captures) => f(i, &captures)?,
+            None => None,
+        });
+    }
+    Ok(values)
+}
+
+fn utf8_array(values: &[Option<String>]) -> Array {
+    let mut b: ListBuilder<u8> = ListBuilder::with_capacity(values.len());
+    let mut bitmap = Bitmap::new(values.len());
+    let mut null_count = 0;
+    for (i, v) in values.iter().enumerate() {
+        match *v {
+            Some(ref s) => b.push(s.as_bytes()),
+            None => {
+                null_count += 1;
+                bitmap.clear(i);
+                b.push(b"");
+            }
+        }
+    }
+    Array::with_nulls(
+        values.len(),
+        ArrayData::Utf8(ListArray::from(b.finish())),
+        null_count,
+        bitmap,
+    )
+}
+
+fn utf8_scalar(value: &Option<String>) -> ScalarValue {
+    match *value {
+        Some(ref s) => ScalarValue::Utf8(Rc::new(s.clone())),
+        None => ScalarValue::Null,
+    }
+}
+
+/// Returns a capture group of the first match of a pattern, where group 0 is the whole match
+pub struct RegexpExtractFunction {}
+
+impl ScalarFunction for RegexpExtractFunction {
+    fn name(&self) -> String {
+        "regexp_extract".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        if args.len() != 3 {
+            return Err(DataFusionError::Execution(
+                "regexp_extract expects a string, a pattern and a group".to_string(),
+            ));
+        }
+        let (_, is_scalar) = args_len(args);
+        let values = capture_values("regexp_extract", args, |i, captures| {
+            let group = arg_group(&args[2], i)?;
+            if group < 0 || group as usize >= captures.len() {
+                return Err(DataFusionError::Execution(format!(
+                    "regexp_extract group {} is out of range, the pattern has {} groups",
+                    group,
+                    captures.len() - 1
+                )));
+            }
+            Ok(captures.get(group as usize).map(|m| m.as_str().to_string()))
+        })?;
+        Ok(if is_scalar {
+            Value::Scalar(Rc::new(utf8_scalar(&values[0])))
+        } else {
+            Value::Column(Rc::new(utf8_array(&values)))
+        })
+    }
+
+    fn args(&self) -> Vec<Field> {
+        vec![
+            Field::new("text", DataType::Utf8, false),
+            Field::new("pattern", DataType::Utf8, false),
+            Field::new("group", DataType::Int64, false),
+        ]
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+/// Returns every capture group of the first match of a pattern as a struct. The fields of the
+/// struct are derived from the pattern when the query is planned, so it must be a literal.
+pub struct RegexpExtractAllFunction {}
+
+impl ScalarFunction for RegexpExtractAllFunction {
+    fn name(&self) -> String {
+        "regexp_extract_all".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        if args.len() != 2 {
+            return Err(DataFusionError::Execution(
+                "regexp_extract_all expects a string and a pattern".to_string(),
+            ));
+        }
+        let (len, is_scalar) = args_len(args);
+        let mut groups = 0;
+        let values = capture_values("regexp_extract_all", args, |_, captures| {
+            groups = captures.len() - 1;
+            Ok(Some(
+                (1..captures.len())
+                    .map(|g| captures.get(g).map(|m| m.as_str().to_string()))
+                    .collect::<Vec<Option<String>>>(),
+            ))
+        })?;
+        if groups == 0 {
+            // no row matched, so find the number of groups from the pattern itself
+            groups = compile_pattern(arg_str("regexp_extract_all", &args[1], 0)?)?
+                .captures_len()
+                - 1;
+        }
+        if is_scalar {
+            return Ok(Value::Scalar(Rc::new(match values[0] {
+                Some(ref row) => ScalarValue::Struct(row.iter().map(utf8_scalar).collect()),
+                None => ScalarValue::Null,
+            })));
+        }
+        let fields = (0..groups)
+            .map(|g| {
+                let column: Vec<Option<String>> = values
+                    .iter()
+                    .map(|row| match *row {
+                        Some(ref row) => row[g].clone(),
+                        None => None,
+                    })
+                    .collect();
+                Rc::new(utf8_array(&column))
+            })
+            .collect();
+        Ok(Value::Column(Rc::new(Array::new(len, ArrayData::Struct(fields)))))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        vec![
+            Field::new("text", DataType::Utf8, false),
+            Field::new("pattern", DataType::Utf8, false),
+        ]
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Struct(vec![])
+    }
+
+    fn return_type_for(&self, literals: &[Option<ScalarValue>]) -> Result<DataType> {
+        match literals.get(1) {
+            Some(&Some(ScalarValue::Utf8(ref pattern))) => {
+                Ok(DataType::Struct(capture_fields(pattern)?))
+            }
+            _ => Err(DataFusionError::Plan(
+                "The pattern of regexp_extract_all must be a string literal".to_string(),
+            )),
+        }
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str;
+
+    use super::super::super::relations::coalesce::is_valid;
+    use super::*;
+
+    fn utf8(s: &str) -> Value {
+        Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new(s.to_string()))))
+    }
+
+    fn strings(arr: &Array) -> Vec<Option<String>> {
+        match arr.data() {
+            &ArrayData::Utf8(ref list) => (0..arr.len())
+                .map(|i| {
+                    if is_valid(arr, i) {
+                        Some(str::from_utf8(list.get(i)).unwrap().to_string())
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_capture_fields() {
+        let fields = capture_fields(r"(?P<host>\S+) (\d+)").unwrap();
+        assert_eq!(vec!["host", "c2"], fields.iter().map(|f| f.name()).collect::<Vec<_>>());
+        assert!(capture_fields("(unclosed").is_err());
+    }
+
+    #[test]
+    fn test_regexp_extract() {
+        let lines = vec!["GET /a 200", "nothing", "PUT /b 404"];
+        let lines = Value::Column(Rc::new(Array::from(lines)));
+        let group = Value::Scalar(Rc::new(ScalarValue::Int64(2)));
+        let args = [lines, utf8(r"^(\w+) (\S+)"), group];
+        match RegexpExtractFunction {}.execute(&args).unwrap() {
+            Value::Column(ref arr) => assert_eq!(
+                vec![Some("/a".to_string()), None, Some("/b".to_string())],
+                strings(arr)
+            ),
+            _ => panic!(),
+        }
+
+        let args = [utf8("abc"), utf8("b"), Value::Scalar(Rc::new(ScalarValue::Int64(1)))];
+        assert!(RegexpExtractFunction {}.execute(&args).is_err());
+    }
+
+    #[test]
+    fn test_regexp_extract_all() {
+        let lines = Value::Column(Rc::new(Array::from(vec!["GET /a 200", "nothing", "PUT /b"])));
+        let args = [lines, utf8(r"^(\w+) (\S+)(?: (\d+))?")];
+        match RegexpExtractAllFunction {}.execute(&args).unwrap() {
+            Value::Column(ref arr) => match arr.data() {
+                &ArrayData::Struct(ref fields) => {
+                    assert_eq!(3, fields.len());
+                    assert_eq!(
+                        vec![Some("GET".to_string()), None, Some("PUT".to_string())],
+                        strings(&fields[0])
+                    );
+                    assert_eq!(vec![Some("200".to_string()), None, None], strings(&fields[2]));
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+
+        match RegexpExtractAllFunction {}
+            .execute(&[utf8("a=1"), utf8(r"(\w)=(\d)")])
+            .unwrap()
+        {
+            Value::Scalar(ref v) => assert_eq!("a, 1", format!("{}", v)),
+            _ => panic!(),
+        }
+    }
+}
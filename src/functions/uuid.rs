@@ -0,0 +1,517 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functions for generating, parsing and formatting UUIDs and ULIDs, which are commonly used
+//! as synthetic keys
+//!
+//! UUIDs are formatted as lowercase hyphenated hex, and ULIDs as 26 characters of Crockford's
+//! base32. Values that can't be parsed evaluate to null.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use arrow::array::ListArray;
+use arrow::bitmap::*;
+use arrow::builder::*;
+use arrow::datatypes::*;
+use arrow::list_builder::ListBuilder;
+
+use super::super::errors::*;
+use super::super::relations::sample::SampleRng;
+use super::super::types::*;
+
+const CROCKFORD_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Parse a UUID written as 32 hex digits, optionally hyphenated as 8-4-4-4-12 digits and
+/// wrapped in braces
+pub fn parse_uuid(s: &str) -> Option<u128> {
+    let s = s.trim();
+    let s = if s.starts_with('{') && s.ends_with('}') {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    };
+    let hyphenated = s.len() == 36;
+    if !hyphenated && s.len() != 32 {
+        return None;
+    }
+    let mut value: u128 = 0;
+    for (i, ch) in s.chars().enumerate() {
+        if hyphenated && (i == 8 || i == 13 || i == 18 || i == 23) {
+            if ch != '-' {
+                return None;
+            }
+            continue;
+        }
+        value = (value << 4) | u128::from(ch.to_digit(16)?);
+    }
+    Some(value)
+}
+
+/// Format a UUID as lowercase hyphenated hex
+pub fn format_uuid(value: u128) -> String {
+    let hex = format!("{:032x}", value);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Parse a ULID, ignoring case and reading I and L as 1 and O as 0 as Crockford's base32 does
+pub fn parse_ulid(s: &str) -> Option<u128> {
+    let s = s.trim();
+    // 26 characters hold 130 bits, so the first can't be more than 7
+    if s.len() != 26 || !s.starts_with(|c: char| c >= '0' && c <= '7') {
+        return None;
+    }
+    let mut value: u128 = 0;
+    for ch in s.chars() {
+        let digit = match ch.to_ascii_uppercase() {
+            'I' | 'L' => 1,
+            'O' => 0,
+            c => CROCKFORD_ALPHABET.iter().position(|a| *a as char == c)?,
+        };
+        value = (value << 5) | digit as u128;
+    }
+    Some(value)
+}
+
+/// Format a ULID as 26 uppercase characters of Crockford's base32
+pub fn format_ulid(value: u128) -> String {
+    (0..26)
+        .map(|i| CROCKFORD_ALPHABET[((value >> (5 * (25 - i))) & 31) as usize] as char)
+        .collect()
+}
+
+/// Results of applying a function to each value of a utf8 argument
+struct Mapped<T> {
+    values: Vec<Option<T>>,
+    is_scalar: bool,
+}
+
+/// Apply a function to every value of a single utf8 argument, where nulls stay null
+fn map_utf8<T, F>(name: &str, args: &[Value], f: F) -> Result<Mapped<T>>
+where
+    F: Fn(&str) -> Option<T>,
+{
+    if args.len() != 1 {
+        return Err(DataFusionError::Execution(format!(
+            "{} expects a single argument",
+            name
+        )));
+    }
+    let unsupported = || DataFusionError::Execution(format!("Unsupported arg type for {}", name));
+    match args[0] {
+        Value::Column(ref arr) => match arr.data() {
+            &ArrayData::Utf8(ref list) => {
+                let mut values = Vec::with_capacity(arr.len());
+                for i in 0..arr.len() {
+                    let valid = match *arr.validity_bitmap() {
+                        Some(ref bitmap) => bitmap.is_set(i),
+                        None => true,
+                    };
+                    values.push(match str::from_utf8(list.get(i)) {
+                        Ok(s) if valid => f(s),
+                        _ => None,
+                    });
+                }
+                Ok(Mapped {
+                    values,
+                    is_scalar: false,
+                })
+            }
+            _ => Err(unsupported()),
+        },
+        Value::Scalar(ref v) => match v.as_ref() {
+            ScalarValue::Utf8(ref s) => Ok(Mapped {
+                values: vec![f(s)],
+                is_scalar: true,
+            }),
+            ScalarValue::Null => Ok(Mapped {
+                values: vec![None],
+                is_scalar: true,
+            }),
+            _ => Err(unsupported()),
+        },
+    }
+}
+
+fn utf8_result(mapped: Mapped<String>) -> Value {
+    let values = mapped.values;
+    if mapped.is_scalar {
+        return Value::Scalar(Rc::new(match values[0] {
+            Some(ref s) => ScalarValue::Utf8(Rc::new(s.clone())),
+            None => ScalarValue::Null,
+        }));
+    }
+    let mut b: ListBuilder<u8> = ListBuilder::with_capacity(values.len());
+    let mut bitmap = Bitmap::new(values.len());
+    let mut null_count = 0;
+    for (i, v) in values.iter().enumerate() {
+        match *v {
+            Some(ref s) => b.push(s.as_bytes()),
+            None => {
+                null_count += 1;
+                bitmap.clear(i);
+                b.push(b"");
+            }
+        }
+    }
+    Value::Column(Rc::new(Array::with_nulls(
+        values.len(),
+        ArrayData::Utf8(ListArray::from(b.finish())),
+        null_count,
+        bitmap,
+    )))
+}
+
+fn utf8_arg(name: &str) -> Vec<Field> {
+    vec![Field::new(name, DataType::Utf8, false)]
+}
+
+/// Generates a random (version 4) UUID. Every row gets a different value.
+pub struct UuidFunction {
+    rng: RefCell<SampleRng>,
+}
+
+impl UuidFunction {
+    pub fn new() -> Self {
+        UuidFunction {
+            rng: RefCell::new(SampleRng::from_time()),
+        }
+    }
+}
+
+impl ScalarFunction for UuidFunction {
+    fn name(&self) -> String {
+        "uuid".to_string()
+    }
+
+    fn execute(&self, _args: &[Value]) -> Result<Value> {
+        let mut rng = self.rng.borrow_mut();
+        let bits = (u128::from(rng.next_u64()) << 64) | u128::from(rng.next_u64());
+        // set the version to 4 and the variant to RFC 4122
+        let bits = (bits & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+        Ok(Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new(format_uuid(bits))))))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        vec![]
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn volatility(&self) -> Volatility {
+        Volatility::Volatile
+    }
+}
+
+/// Generates a ULID from the current time and 80 random bits, so that ULIDs generated in
+/// different milliseconds sort in the order they were generated
+pub struct UlidFunction {
+    rng: RefCell<SampleRng>,
+}
+
+impl UlidFunction {
+    pub fn new() -> Self {
+        UlidFunction {
+            rng: RefCell::new(SampleRng::from_time()),
+        }
+    }
+}
+
+impl ScalarFunction for UlidFunction {
+    fn name(&self) -> String {
+        "ulid".to_string()
+    }
+
+    fn execute(&self, _args: &[Value]) -> Result<Value> {
+        let millis = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() * 1000 + u64::from(d.subsec_nanos() / 1_000_000),
+            Err(_) => 0,
+        };
+        let mut rng = self.rng.borrow_mut();
+        let random = (u128::from(rng.next_u64()) << 16) | u128::from(rng.next_u64() >> 48);
+        let bits = (u128::from(millis & 0xffff_ffff_ffff) << 80) | random;
+        Ok(Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new(format_ulid(bits))))))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        vec![]
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn volatility(&self) -> Volatility {
+        Volatility::Volatile
+    }
+}
+
+/// Returns the 16 bytes of a UUID as 32 hex digits. Binary values are stored as UTF-8 strings
+/// so this is the compact form of the bytes, which compares and sorts like them.
+pub struct UuidToBinFunction {}
+
+impl ScalarFunction for UuidToBinFunction {
+    fn name(&self) -> String {
+        "uuid_to_bin".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        let mapped = map_utf8("uuid_to_bin", args, |s| {
+            parse_uuid(s).map(|v| format!("{:032x}", v))
+        })?;
+        Ok(utf8_result(mapped))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        utf8_arg("uuid")
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the hyphenated form of a UUID given in any of the forms that can be parsed, such as
+/// the result of `uuid_to_bin`
+pub struct BinToUuidFunction {}
+
+impl ScalarFunction for BinToUuidFunction {
+    fn name(&self) -> String {
+        "bin_to_uuid".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        let mapped = map_utf8("bin_to_uuid", args, |s| parse_uuid(s).map(format_uuid))?;
+        Ok(utf8_result(mapped))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        utf8_arg("bin")
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Utf8
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+/// Returns whether a string is a UUID, or null if it is null
+pub struct IsUuidFunction {}
+
+impl ScalarFunction for IsUuidFunction {
+    fn name(&self) -> String {
+        "is_uuid".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        let mapped = map_utf8("is_uuid", args, |s| Some(parse_uuid(s).is_some()))?;
+        if mapped.is_scalar {
+            return Ok(Value::Scalar(Rc::new(match mapped.values[0] {
+                Some(b) => ScalarValue::Boolean(b),
+                None => ScalarValue::Null,
+            })));
+        }
+        let values = mapped.values;
+        let mut b: Builder<bool> = Builder::with_capacity(values.len());
+        let mut bitmap = Bitmap::new(values.len());
+        let mut null_count = 0;
+        for (i, v) in values.iter().enumerate() {
+            match *v {
+                Some(v) => b.push(v),
+                None => {
+                    null_count += 1;
+                    bitmap.clear(i);
+                    b.push(false);
+                }
+            }
+        }
+        Ok(Value::Column(Rc::new(Array::with_nulls(
+            values.len(),
+            ArrayData::from(b.finish()),
+            null_count,
+            bitmap,
+        ))))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        utf8_arg("value")
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Boolean
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the time that a ULID was generated at, in milliseconds since the epoch
+pub struct UlidTimestampFunction {}
+
+impl ScalarFunction for UlidTimestampFunction {
+    fn name(&self) -> String {
+        "ulid_timestamp".to_string()
+    }
+
+    fn execute(&self, args: &[Value]) -> Result<Value> {
+        let mapped = map_utf8("ulid_timestamp", args, |s| {
+            parse_ulid(s).map(|v| (v >> 80) as i64)
+        })?;
+        if mapped.is_scalar {
+            return Ok(Value::Scalar(Rc::new(match mapped.values[0] {
+                Some(ts) => ScalarValue::Int64(ts),
+                None => ScalarValue::Null,
+            })));
+        }
+        let values = mapped.values;
+        let mut b: Builder<i64> = Builder::with_capacity(values.len());
+        let mut bitmap = Bitmap::new(values.len());
+        let mut null_count = 0;
+        for (i, v) in values.iter().enumerate() {
+            match *v {
+                Some(v) => b.push(v),
+                None => {
+                    null_count += 1;
+                    bitmap.clear(i);
+                    b.push(0);
+                }
+            }
+        }
+        Ok(Value::Column(Rc::new(Array::with_nulls(
+            values.len(),
+            ArrayData::from(b.finish()),
+            null_count,
+            bitmap,
+        ))))
+    }
+
+    fn args(&self) -> Vec<Field> {
+        utf8_arg("ulid")
+    }
+
+    fn return_type(&self) -> DataType {
+        DataType::Int64
+    }
+
+    fn null_propagating(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scalar_string(value: Value) -> String {
+        match value {
+            Value::Scalar(ref v) => match v.as_ref() {
+                ScalarValue::Utf8(ref s) => s.as_ref().clone(),
+                other => panic!("unexpected value {:?}", other),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_parse_and_format_uuid() {
+        let uuid = "123e4567-e89b-12d3-a456-426614174000";
+        let value = parse_uuid(uuid).unwrap();
+        assert_eq!(0x123e4567_e89b_12d3_a456_426614174000, value);
+        assert_eq!(uuid, format_uuid(value));
+        assert_eq!(Some(value), parse_uuid("{123E4567-E89B-12D3-A456-426614174000}"));
+        assert_eq!(Some(value), parse_uuid("123e4567e89b12d3a456426614174000"));
+        assert_eq!(None, parse_uuid("123e4567-e89b-12d3-a456_426614174000"));
+        assert_eq!(None, parse_uuid("123e4567-e89b-12d3-a456-42661417400g"));
+        assert_eq!(None, parse_uuid("123e4567"));
+    }
+
+    #[test]
+    fn test_parse_and_format_ulid() {
+        let ulid = "01ARZ3NDEKTSV4RRFFQ69G5FAV";
+        let value = parse_ulid(ulid).unwrap();
+        assert_eq!(ulid, format_ulid(value));
+        assert_eq!(1_469_918_176_385, (value >> 80) as i64);
+        assert_eq!(Some(value), parse_ulid("01arz3ndektsv4rrffq69g5fav"));
+        assert_eq!(None, parse_ulid("81ARZ3NDEKTSV4RRFFQ69G5FAV"));
+        assert_eq!(None, parse_ulid("01ARZ3NDEKTSV4RRFFQ69G5FAU!"));
+    }
+
+    #[test]
+    fn test_uuid() {
+        let f = UuidFunction::new();
+        let a = scalar_string(f.execute(&[]).unwrap());
+        let b = scalar_string(f.execute(&[]).unwrap());
+        assert_ne!(a, b);
+        assert_eq!(Some('4'), a.chars().nth(14));
+        assert!("89ab".contains(a.chars().nth(19).unwrap()));
+        assert_eq!(a, format_uuid(parse_uuid(&a).unwrap()));
+    }
+
+    #[test]
+    fn test_ulid() {
+        let f = UlidFunction::new();
+        let ulid = scalar_string(f.execute(&[]).unwrap());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let ts = match UlidTimestampFunction {}
+            .execute(&[Value::Scalar(Rc::new(ScalarValue::Utf8(Rc::new(ulid)))])
+            .unwrap()
+        {
+            Value::Scalar(ref v) => match v.as_ref() {
+                ScalarValue::Int64(ts) => *ts,
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+        assert!((ts / 1000 - now).abs() <= 1);
+    }
+
+    #[test]
+    fn test_uuid_to_bin() {
+        let uuids = Value::Column(Rc::new(Array::from(vec![
+            "123e4567-e89b-12d3-a456-426614174000",
+            "not a uuid",
+        ])));
+        match UuidToBinFunction {}.execute(&[uuids]).unwrap() {
+            Value::Column(ref arr) => {
+                assert_eq!(1, arr.null_count());
+                match arr.data() {
+                    ArrayData::Utf8(ref list) => {
+                        assert_eq!(b"123e4567e89b12d3a456426614174000", list.get(0))
+                    }
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Window functions, which compute a value for each row from the other rows in its partition
+
+use super::super::errors::*;
+
+use arrow::datatypes::*;
+
+/// The functions that can be called with an `OVER` clause
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowFunction {
+    Sum,
+    Min,
+    Max,
+    Count,
+    Avg,
+    RowNumber,
+    Rank,
+    DenseRank,
+    Ntile,
+    FirstValue,
+    LastValue,
+    NthValue,
+}
+
+impl WindowFunction {
+    /// Look up a window function by name, ignoring case
+    pub fn from_name(name: &str) -> Option<WindowFunction> {
+        match name.to_lowercase().as_ref() {
+            "sum" => Some(WindowFunction::Sum),
+            "min" => Some(WindowFunction::Min),
+            "max" => Some(WindowFunction::Max),
+            "count" => Some(WindowFunction::Count),
+            "avg" => Some(WindowFunction::Avg),
+            "row_number" => Some(WindowFunction::RowNumber),
+            "rank" => Some(WindowFunction::Rank),
+            "dense_rank" => Some(WindowFunction::DenseRank),
+            "ntile" => Some(WindowFunction::Ntile),
+            "first_value" => Some(WindowFunction::FirstValue),
+            "last_value" => Some(WindowFunction::LastValue),
+            "nth_value" => Some(WindowFunction::NthValue),
+            _ => None,
+        }
+    }
+
+    /// Ranking functions depend only on the ordering of the partition, not the frame
+    pub fn uses_frame(&self) -> bool {
+        match *self {
+            WindowFunction::RowNumber
+            | WindowFunction::Rank
+            | WindowFunction::DenseRank
+            | WindowFunction::Ntile => false,
+            _ => true,
+        }
+    }
+
+    /// Value functions pick a row from the frame, and can be told to skip rows where the
+    /// value is null with `IGNORE NULLS`
+    pub fn supports_null_treatment(&self) -> bool {
+        match *self {
+            WindowFunction::FirstValue | WindowFunction::LastValue | WindowFunction::NthValue => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The number of arguments the function takes
+    pub fn arg_count(&self) -> usize {
+        match *self {
+            WindowFunction::RowNumber | WindowFunction::Rank | WindowFunction::DenseRank => 0,
+            WindowFunction::NthValue => 2,
+            _ => 1,
+        }
+    }
+
+    /// Get the type of the values the function produces for the given argument types
+    pub fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        let expected_args = self.arg_count();
+        if arg_types.len() != expected_args {
+            return Err(DataFusionError::Plan(format!(
+                "Window function {:?} expects {} argument(s) but {} were provided",
+                self,
+                expected_args,
+                arg_types.len()
+            )));
+        }
+        match *self {
+            WindowFunction::Sum
+            | WindowFunction::Min
+            | WindowFunction::Max
+            | WindowFunction::FirstValue
+            | WindowFunction::LastValue
+            | WindowFunction::NthValue => Ok(arg_types[0].clone()),
+            WindowFunction::Avg => Ok(DataType::Float64),
+            WindowFunction::Count
+            | WindowFunction::RowNumber
+            | WindowFunction::Rank
+            | WindowFunction::DenseRank
+            | WindowFunction::Ntile => Ok(DataType::UInt64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_function_return_types() {
+        let f = WindowFunction::from_name("AVG").unwrap();
+        assert_eq!(
+            DataType::Float64,
+            f.return_type(&[DataType::Int32]).unwrap()
+        );
+        let f = WindowFunction::from_name("row_number").unwrap();
+        assert_eq!(DataType::UInt64, f.return_type(&[]).unwrap());
+        assert!(f.return_type(&[DataType::Int32]).is_err());
+        assert_eq!(None, WindowFunction::from_name("sqrt"));
+        let f = WindowFunction::from_name("nth_value").unwrap();
+        assert_eq!(
+            DataType::Utf8,
+            f.return_type(&[DataType::Utf8, DataType::Int64]).unwrap()
+        );
+        assert!(f.return_type(&[DataType::Utf8]).is_err());
+        let f = WindowFunction::from_name("NTILE").unwrap();
+        assert_eq!(DataType::UInt64, f.return_type(&[DataType::Int64]).unwrap());
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serialization of schemas as Arrow IPC messages
+//!
+//! Protocols such as Flight send the schema of a relation as an encapsulated IPC `Schema`
+//! message: a continuation marker, the length of the metadata, and a `Message` flatbuffer
+//! padded to a multiple of eight bytes. Record batches are not serialized here.
+
+use arrow::datatypes::*;
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use super::errors::*;
+
+/// `MetadataVersion.V5`
+const METADATA_VERSION: i16 = 4;
+/// `MessageHeader.Schema`
+const MESSAGE_HEADER_SCHEMA: u8 = 1;
+
+/// Members of the `Type` union
+const TYPE_INT: u8 = 2;
+const TYPE_FLOATING_POINT: u8 = 3;
+const TYPE_UTF8: u8 = 5;
+const TYPE_BOOL: u8 = 6;
+const TYPE_STRUCT: u8 = 13;
+
+/// Values of the `Precision` enum
+const PRECISION_HALF: i16 = 0;
+const PRECISION_SINGLE: i16 = 1;
+const PRECISION_DOUBLE: i16 = 2;
+
+/// Serialize a schema as an encapsulated IPC `Schema` message
+pub fn encode_schema(schema: &Schema) -> Result<Vec<u8>> {
+    let fields = schema
+        .columns()
+        .iter()
+        .map(field_table)
+        .collect::<Result<Vec<Object>>>()?;
+    let message = Object::Table(vec![
+        Slot::I16(METADATA_VERSION),
+        Slot::U8(MESSAGE_HEADER_SCHEMA),
+        // the endianness is left at its default, which is little endian
+        Slot::Offset(Object::Table(vec![
+            Slot::Absent,
+            Slot::Offset(Object::Vector(fields)),
+        ])),
+    ]);
+
+    let mut metadata = vec![0; 4];
+    let root = write_object(&mut metadata, &message);
+    LittleEndian::write_u32(&mut metadata[0..4], root as u32);
+    align(&mut metadata, 8);
+
+    let mut message = Vec::with_capacity(metadata.len() + 8);
+    message.write_u32::<LittleEndian>(0xFFFF_FFFF).unwrap();
+    message
+        .write_i32::<LittleEndian>(metadata.len() as i32)
+        .unwrap();
+    message.extend_from_slice(&metadata);
+    Ok(message)
+}
+
+/// The `Field` table of a column
+fn field_table(field: &Field) -> Result<Object> {
+    let (type_type, type_table, children) = match *field.data_type() {
+        DataType::Boolean => (TYPE_BOOL, vec![], vec![]),
+        DataType::Int8 => (TYPE_INT, int_type(8, true), vec![]),
+        DataType::Int16 => (TYPE_INT, int_type(16, true), vec![]),
+        DataType::Int32 => (TYPE_INT, int_type(32, true), vec![]),
+        DataType::Int64 => (TYPE_INT, int_type(64, true), vec![]),
+        DataType::UInt8 => (TYPE_INT, int_type(8, false), vec![]),
+        DataType::UInt16 => (TYPE_INT, int_type(16, false), vec![]),
+        DataType::UInt32 => (TYPE_INT, int_type(32, false), vec![]),
+        DataType::UInt64 => (TYPE_INT, int_type(64, false), vec![]),
+        DataType::Float16 => (TYPE_FLOATING_POINT, vec![Slot::I16(PRECISION_HALF)], vec![]),
+        DataType::Float32 => (TYPE_FLOATING_POINT, vec![Slot::I16(PRECISION_SINGLE)], vec![]),
+        DataType::Float64 => (TYPE_FLOATING_POINT, vec![Slot::I16(PRECISION_DOUBLE)], vec![]),
+        DataType::Utf8 => (TYPE_UTF8, vec![], vec![]),
+        DataType::Struct(ref fields) => (
+            TYPE_STRUCT,
+            vec![],
+            fields
+                .iter()
+                .map(field_table)
+                .collect::<Result<Vec<Object>>>()?,
+        ),
+        ref other => {
+            return Err(DataFusionError::Execution(format!(
+                "Columns of type {:?} can't be serialized as IPC messages",
+                other
+            )))
+        }
+    };
+    Ok(Object::Table(vec![
+        Slot::Offset(Object::String(field.name().to_string())),
+        Slot::Bool(field.is_nullable()),
+        Slot::U8(type_type),
+        Slot::Offset(Object::Table(type_table)),
+        // not dictionary encoded
+        Slot::Absent,
+        // readers expect a list of children even when there are none
+        Slot::Offset(Object::Vector(children)),
+    ]))
+}
+
+/// The fields of an `Int` table
+fn int_type(bit_width: i32, is_signed: bool) -> Vec<Slot> {
+    vec![Slot::I32(bit_width), Slot::Bool(is_signed)]
+}
+
+/// A flatbuffer table, string or vector of tables
+enum Object {
+    /// The fields of a table, in the order that the schema declares them
+    Table(Vec<Slot>),
+    String(String),
+    Vector(Vec<Object>),
+}
+
+/// A field of a flatbuffer table
+enum Slot {
+    /// A field that takes its default value
+    Absent,
+    Bool(bool),
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    Offset(Object),
+}
+
+/// Pad a buffer with zeros up to a multiple of the alignment
+fn align(buf: &mut Vec<u8>, alignment: usize) {
+    let len = buf.len() + (alignment - buf.len() % alignment) % alignment;
+    buf.resize(len, 0);
+}
+
+/// Append an object and the objects it refers to, returning the position that offsets to the
+/// object point at. Objects are written after whatever refers to them since offsets can only
+/// point forwards.
+fn write_object(buf: &mut Vec<u8>, object: &Object) -> usize {
+    match *object {
+        Object::Table(ref slots) => write_table(buf, slots),
+        Object::String(ref s) => {
+            align(buf, 4);
+            let pos = buf.len();
+            buf.write_u32::<LittleEndian>(s.len() as u32).unwrap();
+            buf.extend_from_slice(s.as_bytes());
+            // strings are null terminated
+            buf.push(0);
+            pos
+        }
+        Object::Vector(ref objects) => {
+            align(buf, 4);
+            let pos = buf.len();
+            buf.write_u32::<LittleEndian>(objects.len() as u32).unwrap();
+            buf.resize(pos + 4 + 4 * objects.len(), 0);
+            for (i, object) in objects.iter().enumerate() {
+                let slot = pos + 4 + 4 * i;
+                let target = write_object(buf, object);
+                LittleEndian::write_u32(&mut buf[slot..slot + 4], (target - slot) as u32);
+            }
+            pos
+        }
+    }
+}
+
+/// Append a table, which is preceded by its vtable of field offsets
+fn write_table(buf: &mut Vec<u8>, slots: &[Slot]) -> usize {
+    align(buf, 2);
+    let vtable = buf.len();
+    buf.resize(vtable + 4 + 2 * slots.len(), 0);
+    align(buf, 4);
+    let table = buf.len();
+    // the vtable is found by subtracting this from the position of the table
+    buf.write_i32::<LittleEndian>((table - vtable) as i32).unwrap();
+
+    let mut children = vec![];
+    for (i, slot) in slots.iter().enumerate() {
+        let size = match *slot {
+            Slot::Absent => continue,
+            Slot::Bool(_) | Slot::U8(_) => 1,
+            Slot::I16(_) => 2,
+            Slot::I32(_) | Slot::Offset(_) => 4,
+        };
+        align(buf, size);
+        let pos = buf.len();
+        match *slot {
+            Slot::Absent => {}
+            Slot::Bool(b) => buf.push(b as u8),
+            Slot::U8(n) => buf.push(n),
+            Slot::I16(n) => buf.write_i16::<LittleEndian>(n).unwrap(),
+            Slot::I32(n) => buf.write_i32::<LittleEndian>(n).unwrap(),
+            Slot::Offset(ref object) => {
+                buf.write_u32::<LittleEndian>(0).unwrap();
+                children.push((pos, object));
+            }
+        }
+        let entry = vtable + 4 + 2 * i;
+        LittleEndian::write_u16(&mut buf[entry..entry + 2], (pos - table) as u16);
+    }
+    let table_size = buf.len() - table;
+    LittleEndian::write_u16(&mut buf[vtable..vtable + 2], (4 + 2 * slots.len()) as u16);
+    LittleEndian::write_u16(&mut buf[vtable + 2..vtable + 4], table_size as u16);
+
+    for (slot, object) in children {
+        let target = write_object(buf, object);
+        LittleEndian::write_u32(&mut buf[slot..slot + 4], (target - slot) as u32);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the tables of a flatbuffer as the official readers do
+    struct Reader<'a> {
+        buf: &'a [u8],
+    }
+
+    impl<'a> Reader<'a> {
+        fn u32_at(&self, pos: usize) -> usize {
+            LittleEndian::read_u32(&self.buf[pos..pos + 4]) as usize
+        }
+
+        /// Position of a field of a table, if it is present
+        fn field(&self, table: usize, i: usize) -> Option<usize> {
+            let vtable = table - LittleEndian::read_i32(&self.buf[table..table + 4]) as usize;
+            let vtable_size = LittleEndian::read_u16(&self.buf[vtable..vtable + 2]) as usize;
+            if 4 + 2 * i >= vtable_size {
+                return None;
+            }
+            let entry = vtable + 4 + 2 * i;
+            match LittleEndian::read_u16(&self.buf[entry..entry + 2]) {
+                0 => None,
+                offset => Some(table + offset as usize),
+            }
+        }
+
+        /// Follow the offset stored in a field
+        fn deref(&self, table: usize, i: usize) -> usize {
+            let pos = self.field(table, i).unwrap();
+            pos + self.u32_at(pos)
+        }
+
+        fn string(&self, pos: usize) -> &'a str {
+            let len = self.u32_at(pos);
+            ::std::str::from_utf8(&self.buf[pos + 4..pos + 4 + len]).unwrap()
+        }
+
+        fn vector(&self, pos: usize) -> Vec<usize> {
+            (0..self.u32_at(pos))
+                .map(|i| {
+                    let slot = pos + 4 + 4 * i;
+                    slot + self.u32_at(slot)
+                }).collect()
+        }
+    }
+
+    #[test]
+    fn test_encode_schema() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new(
+                "point",
+                DataType::Struct(vec![Field::new("x", DataType::Float32, false)]),
+                true,
+            ),
+        ]);
+        let message = encode_schema(&schema).unwrap();
+        assert_eq!(&[0xFF, 0xFF, 0xFF, 0xFF], &message[0..4]);
+        let len = LittleEndian::read_i32(&message[4..8]) as usize;
+        assert_eq!(message.len(), 8 + len);
+        assert_eq!(0, len % 8);
+
+        let r = Reader {
+            buf: &message[8..],
+        };
+        let root = r.u32_at(0);
+        let version = r.field(root, 0).unwrap();
+        assert_eq!(METADATA_VERSION, LittleEndian::read_i16(&r.buf[version..]));
+        assert_eq!(MESSAGE_HEADER_SCHEMA, r.buf[r.field(root, 1).unwrap()]);
+        assert_eq!(None, r.field(root, 3));
+
+        let header = r.deref(root, 2);
+        let fields = r.vector(r.deref(header, 1));
+        assert_eq!(3, fields.len());
+
+        let names: Vec<&str> = fields.iter().map(|f| r.string(r.deref(*f, 0))).collect();
+        assert_eq!(vec!["id", "name", "point"], names);
+        let nullable: Vec<u8> = fields.iter().map(|f| r.buf[r.field(*f, 1).unwrap()]).collect();
+        assert_eq!(vec![0, 1, 1], nullable);
+        let types: Vec<u8> = fields.iter().map(|f| r.buf[r.field(*f, 2).unwrap()]).collect();
+        assert_eq!(vec![TYPE_INT, TYPE_UTF8, TYPE_STRUCT], types);
+
+        let int = r.deref(fields[0], 3);
+        assert_eq!(64, LittleEndian::read_i32(&r.buf[r.field(int, 0).unwrap()..]));
+        assert_eq!(1, r.buf[r.field(int, 1).unwrap()]);
+        assert_eq!(0, r.vector(r.deref(fields[0], 5)).len());
+
+        let children = r.vector(r.deref(fields[2], 5));
+        assert_eq!(1, children.len());
+        assert_eq!("x", r.string(r.deref(children[0], 0)));
+        let float = r.deref(children[0], 3);
+        assert_eq!(
+            PRECISION_SINGLE,
+            LittleEndian::read_i16(&r.buf[r.field(float, 0).unwrap()..])
+        );
+    }
+}
@@ -0,0 +1,412 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compilation of expressions to native code with Cranelift, enabled by the `jit` feature
+//!
+//! Arithmetic and comparisons over Float64 columns and literals are compiled to a function that
+//! loops over the rows of a batch, avoiding the intermediate arrays that the interpreter creates
+//! for every operator. Other expressions are not compiled, and batches containing nulls are
+//! evaluated by the interpreter.
+
+use std::collections::BTreeSet;
+use std::mem;
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use cranelift::prelude::{
+    settings, types, AbiParam, FloatCC, FunctionBuilder, FunctionBuilderContext, InstBuilder,
+    IntCC, MemFlags, Value as IrValue,
+};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+use super::errors::*;
+use super::exec::RuntimeExpr;
+use super::logical::{Expr, Operator};
+use super::types::*;
+
+/// Signature of compiled expressions: pointers to the input columns, a pointer to the output
+/// values and the number of rows
+type JitFunction = extern "C" fn(*const *const f64, *mut u8, usize);
+
+/// The kind of value an expression produces
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JitType {
+    Float64,
+    Boolean,
+}
+
+/// An expression compiled to native code
+pub struct JitExpr {
+    /// The module owns the memory that the compiled code lives in
+    _module: JITModule,
+    function: JitFunction,
+    /// Columns that the expression reads, in the order the function expects them
+    columns: Vec<usize>,
+    output_type: JitType,
+}
+
+impl JitExpr {
+    /// Evaluate the expression, or return None if the batch cannot be evaluated by compiled
+    /// code because an input column contains nulls or is not a Float64 array
+    pub fn evaluate(&self, batch: &RecordBatch) -> Option<Value> {
+        let len = batch.num_rows();
+        let mut inputs: Vec<*const f64> = Vec::with_capacity(self.columns.len());
+        for &i in &self.columns {
+            match *batch.column(i) {
+                Value::Column(ref array) if array.null_count() == 0 => match *array.data() {
+                    ArrayData::Float64(ref v) if len > 0 => inputs.push(v.get(0) as *const f64),
+                    ArrayData::Float64(_) => inputs.push(0 as *const f64),
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+        // the output buffers are fully written by the compiled function
+        Some(match self.output_type {
+            JitType::Float64 => {
+                let mut output = vec![0.0f64; len];
+                (self.function)(inputs.as_ptr(), output.as_mut_ptr() as *mut u8, len);
+                Value::Column(Rc::new(Array::from(output)))
+            }
+            JitType::Boolean => {
+                let mut output = vec![false; len];
+                (self.function)(inputs.as_ptr(), output.as_mut_ptr() as *mut u8, len);
+                Value::Column(Rc::new(Array::from(output)))
+            }
+        })
+    }
+
+    pub fn data_type(&self) -> DataType {
+        match self.output_type {
+            JitType::Float64 => DataType::Float64,
+            JitType::Boolean => DataType::Boolean,
+        }
+    }
+}
+
+/// Check whether an expression can be compiled, returning the type it produces
+fn jit_type(expr: &Expr, schema: &Schema) -> Option<JitType> {
+    match *expr {
+        Expr::Column(i) => match *schema.column(i).data_type() {
+            DataType::Float64 => Some(JitType::Float64),
+            _ => None,
+        },
+        Expr::Literal(ScalarValue::Float64(_)) => Some(JitType::Float64),
+        Expr::BinaryExpr {
+            ref left,
+            ref op,
+            ref right,
+        } => {
+            let l = jit_type(left, schema)?;
+            let r = jit_type(right, schema)?;
+            match (op, l, r) {
+                (&Operator::Plus, JitType::Float64, JitType::Float64)
+                | (&Operator::Minus, JitType::Float64, JitType::Float64)
+                | (&Operator::Multiply, JitType::Float64, JitType::Float64)
+                | (&Operator::Divide, JitType::Float64, JitType::Float64) => {
+                    Some(JitType::Float64)
+                }
+                (&Operator::Eq, JitType::Float64, JitType::Float64)
+                | (&Operator::NotEq, JitType::Float64, JitType::Float64)
+                | (&Operator::Lt, JitType::Float64, JitType::Float64)
+                | (&Operator::LtEq, JitType::Float64, JitType::Float64)
+                | (&Operator::Gt, JitType::Float64, JitType::Float64)
+                | (&Operator::GtEq, JitType::Float64, JitType::Float64)
+                | (&Operator::And, JitType::Boolean, JitType::Boolean)
+                | (&Operator::Or, JitType::Boolean, JitType::Boolean) => Some(JitType::Boolean),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn collect_columns(expr: &Expr, columns: &mut BTreeSet<usize>) {
+    match *expr {
+        Expr::Column(i) => {
+            columns.insert(i);
+        }
+        Expr::BinaryExpr {
+            ref left,
+            ref right,
+            ..
+        } => {
+            collect_columns(left, columns);
+            collect_columns(right, columns);
+        }
+        _ => {}
+    }
+}
+
+/// Compile an expression to native code. Returns None for expressions that are not supported,
+/// which includes single columns and literals since there is nothing to gain from compiling them.
+pub fn compile_expr(expr: &Expr, schema: &Schema) -> Option<JitExpr> {
+    match *expr {
+        Expr::BinaryExpr { .. } => {}
+        _ => return None,
+    }
+    let output_type = jit_type(expr, schema)?;
+    let mut columns = BTreeSet::new();
+    collect_columns(expr, &mut columns);
+    let columns: Vec<usize> = columns.into_iter().collect();
+    // failing to generate code is not an error since the interpreter can still be used
+    generate(expr, &columns, output_type).ok().map(|(module, function)| JitExpr {
+        _module: module,
+        function,
+        columns,
+        output_type,
+    })
+}
+
+fn generate(
+    expr: &Expr,
+    columns: &[usize],
+    output_type: JitType,
+) -> ::std::result::Result<(JITModule, JitFunction), String> {
+    let isa_builder = cranelift_native::builder()?;
+    let isa = isa_builder
+        .finish(settings::Flags::new(settings::builder()))
+        .map_err(|e| e.to_string())?;
+    let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+    let ptr = module.target_config().pointer_type();
+
+    let mut ctx = module.make_context();
+    ctx.func.signature.params.push(AbiParam::new(ptr));
+    ctx.func.signature.params.push(AbiParam::new(ptr));
+    ctx.func.signature.params.push(AbiParam::new(ptr));
+
+    let mut builder_ctx = FunctionBuilderContext::new();
+    {
+        let mut b = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+        let entry = b.create_block();
+        let header = b.create_block();
+        let body = b.create_block();
+        let exit = b.create_block();
+        b.append_block_params_for_function_params(entry);
+        b.append_block_param(header, ptr);
+
+        // load the pointers to the input columns
+        b.switch_to_block(entry);
+        let params = b.block_params(entry).to_vec();
+        let (inputs, output, len) = (params[0], params[1], params[2]);
+        let column_ptrs: Vec<IrValue> = (0..columns.len())
+            .map(|k| {
+                b.ins()
+                    .load(ptr, MemFlags::trusted(), inputs, (k * mem::size_of::<usize>()) as i32)
+            }).collect();
+        let zero = b.ins().iconst(ptr, 0);
+        b.ins().jump(header, &[zero]);
+
+        // loop while the row index is less than the number of rows
+        b.switch_to_block(header);
+        let row = b.block_params(header)[0];
+        let in_range = b.ins().icmp(IntCC::UnsignedLessThan, row, len);
+        b.ins().brif(in_range, body, &[], exit, &[]);
+
+        b.switch_to_block(body);
+        let offset = b.ins().imul_imm(row, 8);
+        let result = {
+            let mut gen = ExprGenerator {
+                builder: &mut b,
+                columns,
+                column_ptrs: &column_ptrs,
+                offset,
+            };
+            gen.generate(expr)?
+        };
+        let output_offset = match output_type {
+            JitType::Float64 => offset,
+            JitType::Boolean => row,
+        };
+        let address = b.ins().iadd(output, output_offset);
+        b.ins().store(MemFlags::trusted(), result, address, 0);
+        let next = b.ins().iadd_imm(row, 1);
+        b.ins().jump(header, &[next]);
+
+        b.switch_to_block(exit);
+        b.ins().return_(&[]);
+        b.seal_all_blocks();
+        b.finalize();
+    }
+
+    let id = module
+        .declare_function("expr", Linkage::Export, &ctx.func.signature)
+        .map_err(|e| e.to_string())?;
+    module
+        .define_function(id, &mut ctx)
+        .map_err(|e| e.to_string())?;
+    module.clear_context(&mut ctx);
+    module.finalize_definitions().map_err(|e| e.to_string())?;
+    let code = module.get_finalized_function(id);
+    let function = unsafe { mem::transmute::<*const u8, JitFunction>(code) };
+    Ok((module, function))
+}
+
+/// Generates the instructions that compute an expression for the current row
+struct ExprGenerator<'a, 'b: 'a> {
+    builder: &'a mut FunctionBuilder<'b>,
+    columns: &'a [usize],
+    column_ptrs: &'a [IrValue],
+    /// Offset of the current row's value within each column
+    offset: IrValue,
+}
+
+impl<'a, 'b> ExprGenerator<'a, 'b> {
+    fn generate(&mut self, expr: &Expr) -> ::std::result::Result<IrValue, String> {
+        match *expr {
+            Expr::Column(i) => {
+                let k = self
+                    .columns
+                    .iter()
+                    .position(|c| *c == i)
+                    .ok_or_else(|| format!("Column {} was not collected", i))?;
+                let address = self.builder.ins().iadd(self.column_ptrs[k], self.offset);
+                Ok(self
+                    .builder
+                    .ins()
+                    .load(types::F64, MemFlags::trusted(), address, 0))
+            }
+            Expr::Literal(ScalarValue::Float64(n)) => Ok(self.builder.ins().f64const(n)),
+            Expr::BinaryExpr {
+                ref left,
+                ref op,
+                ref right,
+            } => {
+                let l = self.generate(left)?;
+                let r = self.generate(right)?;
+                let ins = self.builder.ins();
+                Ok(match *op {
+                    Operator::Plus => ins.fadd(l, r),
+                    Operator::Minus => ins.fsub(l, r),
+                    Operator::Multiply => ins.fmul(l, r),
+                    Operator::Divide => ins.fdiv(l, r),
+                    Operator::Eq => ins.fcmp(FloatCC::Equal, l, r),
+                    Operator::NotEq => ins.fcmp(FloatCC::NotEqual, l, r),
+                    Operator::Lt => ins.fcmp(FloatCC::LessThan, l, r),
+                    Operator::LtEq => ins.fcmp(FloatCC::LessThanOrEqual, l, r),
+                    Operator::Gt => ins.fcmp(FloatCC::GreaterThan, l, r),
+                    Operator::GtEq => ins.fcmp(FloatCC::GreaterThanOrEqual, l, r),
+                    Operator::And => ins.band(l, r),
+                    Operator::Or => ins.bor(l, r),
+                    Operator::Modulus => return Err("Modulus is not supported".to_string()),
+                    Operator::Like | Operator::NotLike | Operator::ILike | Operator::NotILike => {
+                        return Err(format!("{:?} is not supported", op))
+                    }
+                })
+            }
+            _ => Err(format!("Unsupported expression {:?}", expr)),
+        }
+    }
+}
+
+/// Combine a compiled expression with the interpreted version of the same expression, which is
+/// used for batches that the compiled code cannot handle
+pub fn with_fallback(jit: JitExpr, interpreted: RuntimeExpr) -> RuntimeExpr {
+    let t = jit.data_type();
+    let fallback = interpreted.get_func().clone();
+    RuntimeExpr::Compiled {
+        f: Rc::new(move |batch: &RecordBatch| -> Result<Value> {
+            match jit.evaluate(batch) {
+                Some(value) => Ok(value),
+                None => fallback(batch),
+            }
+        }),
+        t,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    fn batch(schema: &Rc<Schema>, a: Array, b: Array) -> DefaultRecordBatch {
+        DefaultRecordBatch {
+            schema: schema.clone(),
+            data: vec![Value::Column(Rc::new(a)), Value::Column(Rc::new(b))],
+            row_count: 3,
+        }
+    }
+
+    fn binary(left: Expr, op: Operator, right: Expr) -> Expr {
+        Expr::BinaryExpr {
+            left: Rc::new(left),
+            op,
+            right: Rc::new(right),
+        }
+    }
+
+    #[test]
+    fn test_compile_arithmetic_and_comparison() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("a", DataType::Float64, false),
+            Field::new("b", DataType::Float64, false),
+        ]));
+        let b = batch(
+            &schema,
+            Array::from(vec![1.0, 2.0, 3.0]),
+            Array::from(vec![0.5, 4.0, 1.0]),
+        );
+
+        // a * 2.0 + b
+        let expr = binary(
+            binary(
+                Expr::Column(0),
+                Operator::Multiply,
+                Expr::Literal(ScalarValue::Float64(2.0)),
+            ),
+            Operator::Plus,
+            Expr::Column(1),
+        );
+        let jit = compile_expr(&expr, &schema).unwrap();
+        match jit.evaluate(&b) {
+            Some(Value::Column(ref a)) => match *a.data() {
+                ArrayData::Float64(ref v) => {
+                    assert_eq!(vec![2.5, 8.0, 7.0], v.iter().collect::<Vec<f64>>())
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+
+        // a > b
+        let expr = binary(Expr::Column(0), Operator::Gt, Expr::Column(1));
+        let jit = compile_expr(&expr, &schema).unwrap();
+        match jit.evaluate(&b) {
+            Some(Value::Column(ref a)) => match *a.data() {
+                ArrayData::Boolean(ref v) => {
+                    assert_eq!(vec![true, false, true], v.iter().collect::<Vec<bool>>())
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_unsupported_expressions() {
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::Float64, false),
+            Field::new("b", DataType::Int32, false),
+        ]);
+        assert!(compile_expr(&Expr::Column(0), &schema).is_none());
+        let mixed_types = binary(Expr::Column(0), Operator::Plus, Expr::Column(1));
+        assert!(compile_expr(&mixed_types, &schema).is_none());
+        let modulus = binary(Expr::Column(0), Operator::Modulus, Expr::Column(0));
+        assert!(compile_expr(&modulus, &schema).is_none());
+    }
+}
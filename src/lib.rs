@@ -19,28 +19,63 @@
 //! is also provided.
 
 extern crate arrow;
+#[cfg(feature = "kafka")]
+extern crate avro_rs;
 extern crate byteorder;
 extern crate bytes;
 extern crate clap;
+#[cfg(feature = "jit")]
+extern crate cranelift;
+#[cfg(feature = "jit")]
+extern crate cranelift_jit;
+#[cfg(feature = "jit")]
+extern crate cranelift_module;
+#[cfg(feature = "jit")]
+extern crate cranelift_native;
 extern crate csv;
+extern crate flate2;
 extern crate fnv;
 extern crate json;
 #[cfg(target_family = "unix")]
 extern crate liner;
 extern crate parquet;
+#[cfg(feature = "kafka")]
+extern crate rdkafka;
+extern crate regex;
+#[cfg(feature = "kafka")]
+extern crate reqwest;
 
 #[macro_use]
 extern crate lazy_static;
 
+pub mod admission;
+pub mod analyzer;
+pub mod audit;
+pub mod cache;
+pub mod catalog;
+pub mod checkpoint;
+pub mod cursor;
 pub mod dataframe;
 pub mod datasources;
 #[macro_use]
 pub mod errors;
 pub mod exec;
+pub mod ffi;
+pub mod flightsql;
 pub mod functions;
+pub mod ipc;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod lineage;
 pub mod logical;
+pub mod plancodec;
+pub mod plandiff;
+pub mod progress;
 pub mod relations;
+pub mod resource;
+pub mod spill;
 pub mod sqlast;
+pub mod sqlnormalizer;
 pub mod sqlparser;
 pub mod sqlplanner;
 pub mod sqltokenizer;
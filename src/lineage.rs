@@ -0,0 +1,356 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Column-level lineage of logical plans
+//!
+//! Reports which columns of the tables and files that a query reads each of its output columns
+//! is computed from. Only the columns that feed an output value are included, so the columns of
+//! filters, join keys and grouping expressions only appear where they are also output. The
+//! columns of window functions include their partitioning and ordering columns, which decide
+//! the value of each row.
+
+use std::collections::BTreeSet;
+
+use arrow::datatypes::Field;
+
+use super::logical::{Expr, LogicalPlan};
+
+/// A column of a table or file that a query reads
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceColumn {
+    /// The name of a registered table, or the path of a file
+    pub source: String,
+    pub column: String,
+}
+
+impl SourceColumn {
+    pub fn new(source: &str, column: &str) -> Self {
+        SourceColumn {
+            source: source.to_string(),
+            column: column.to_string(),
+        }
+    }
+}
+
+/// The source columns that an output column of a query is computed from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnLineage {
+    /// Name of the output column
+    pub name: String,
+    /// Source columns in order of source and column name. Columns computed only from literals
+    /// have none.
+    pub sources: Vec<SourceColumn>,
+}
+
+/// Get the lineage of each output column of a plan, in the order of the plan's schema
+pub fn column_lineage(plan: &LogicalPlan) -> Vec<ColumnLineage> {
+    plan.schema()
+        .columns()
+        .iter()
+        .zip(plan_lineage(plan, &[]))
+        .map(|(field, sources)| ColumnLineage {
+            name: field.name().clone(),
+            sources: sources.into_iter().collect(),
+        })
+        .collect()
+}
+
+type Sources = BTreeSet<SourceColumn>;
+
+/// Get the sources of each output column of a plan. `outer` has the sources of the columns of
+/// the outer row when the plan is a correlated subquery.
+fn plan_lineage(plan: &LogicalPlan, outer: &[Sources]) -> Vec<Sources> {
+    match *plan {
+        LogicalPlan::Limit { ref input, .. }
+        | LogicalPlan::Selection { ref input, .. }
+        | LogicalPlan::Sort { ref input, .. }
+        | LogicalPlan::Sample { ref input, .. }
+        | LogicalPlan::Deduplicate { ref input, .. } => plan_lineage(input, outer),
+        LogicalPlan::Projection {
+            ref expr,
+            ref input,
+            ..
+        } => {
+            let input = plan_lineage(input, outer);
+            expr.iter()
+                .map(|e| expr_lineage(e, &input, outer))
+                .collect()
+        }
+        LogicalPlan::Aggregate {
+            ref input,
+            ref group_expr,
+            ref aggr_expr,
+            ..
+        } => {
+            let input = plan_lineage(input, outer);
+            group_expr
+                .iter()
+                .chain(aggr_expr)
+                .map(|e| expr_lineage(e, &input, outer))
+                .collect()
+        }
+        LogicalPlan::Window {
+            ref input,
+            ref window_expr,
+            ..
+        } => {
+            let mut columns = plan_lineage(input, outer);
+            let window_columns: Vec<Sources> = window_expr
+                .iter()
+                .map(|w| {
+                    let mut sources = Sources::new();
+                    for e in w.args.iter().chain(&w.partition_by).chain(&w.order_by) {
+                        sources.extend(expr_lineage(e, &columns, outer));
+                    }
+                    sources
+                })
+                .collect();
+            columns.extend(window_columns);
+            columns
+        }
+        LogicalPlan::Join {
+            ref left,
+            ref right,
+            ref schema,
+            ..
+        } => {
+            // semi and anti joins only produce the columns of the left relation
+            let mut columns = plan_lineage(left, outer);
+            columns.extend(plan_lineage(right, outer));
+            columns.truncate(schema.columns().len());
+            columns
+        }
+        LogicalPlan::LateralJoin {
+            ref left,
+            ref subquery,
+            ..
+        } => {
+            let mut columns = plan_lineage(left, outer);
+            let subquery_columns = plan_lineage(subquery, &columns);
+            columns.extend(subquery_columns);
+            columns
+        }
+        LogicalPlan::Union {
+            ref inputs,
+            ref schema,
+        } => {
+            let mut columns = vec![Sources::new(); schema.columns().len()];
+            for input in inputs {
+                for (i, sources) in plan_lineage(input, outer).into_iter().enumerate() {
+                    columns[i].extend(sources);
+                }
+            }
+            columns
+        }
+        LogicalPlan::TableScan {
+            ref table_name,
+            ref schema,
+            ..
+        } => scan_lineage(table_name, schema.columns()),
+        LogicalPlan::StreamScan {
+            ref stream_name,
+            ref schema,
+        } => scan_lineage(stream_name, schema.columns()),
+        LogicalPlan::CsvFile {
+            ref filename,
+            ref schema,
+            ..
+        }
+        | LogicalPlan::NdJsonFile {
+            ref filename,
+            ref schema,
+            ..
+        }
+        | LogicalPlan::ParquetFile {
+            ref filename,
+            ref schema,
+            ..
+        } => scan_lineage(filename, schema.columns()),
+        LogicalPlan::EmptyRelation { ref schema }
+        | LogicalPlan::MemTable { ref schema, .. }
+        | LogicalPlan::GenerateSeries { ref schema, .. } => {
+            vec![Sources::new(); schema.columns().len()]
+        }
+    }
+}
+
+fn scan_lineage(source: &str, columns: &[Field]) -> Vec<Sources> {
+    columns
+        .iter()
+        .map(|field| {
+            let mut sources = Sources::new();
+            sources.insert(SourceColumn::new(source, field.name()));
+            sources
+        })
+        .collect()
+}
+
+/// Get the sources of the columns that an expression references
+fn expr_lineage(e: &Expr, input: &[Sources], outer: &[Sources]) -> Sources {
+    let mut sources = Sources::new();
+    collect_sources(e, input, outer, &mut sources);
+    sources
+}
+
+fn collect_sources(e: &Expr, input: &[Sources], outer: &[Sources], accum: &mut Sources) {
+    match *e {
+        Expr::Column(i) => accum.extend(input[i].iter().cloned()),
+        Expr::OuterColumn { index, .. } => {
+            if let Some(sources) = outer.get(index) {
+                accum.extend(sources.iter().cloned());
+            }
+        }
+        Expr::Literal(_) => {}
+        Expr::Cast { ref expr, .. }
+        | Expr::Sort { ref expr, .. }
+        | Expr::IsNull(ref expr)
+        | Expr::IsNotNull(ref expr)
+        | Expr::Not(ref expr) => collect_sources(expr, input, outer, accum),
+        Expr::BinaryExpr {
+            ref left,
+            ref right,
+            ..
+        } => {
+            collect_sources(left, input, outer, accum);
+            collect_sources(right, input, outer, accum);
+        }
+        Expr::ScalarFunction { ref args, .. } | Expr::AggregateFunction { ref args, .. } => {
+            args.iter()
+                .for_each(|e| collect_sources(e, input, outer, accum));
+        }
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            for &(ref when, ref then) in when_then {
+                collect_sources(when, input, outer, accum);
+                collect_sources(then, input, outer, accum);
+            }
+            if let Some(ref e) = *else_expr {
+                collect_sources(e, input, outer, accum);
+            }
+        }
+        Expr::QuantifiedComparison {
+            ref expr,
+            ref subquery,
+            ..
+        } => {
+            collect_sources(expr, input, outer, accum);
+            // the result depends on the values the subquery produces for this row
+            for sources in plan_lineage(&subquery.plan, input) {
+                accum.extend(sources);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::super::logical::*;
+    use super::super::types::ScalarValue;
+    use super::*;
+
+    use arrow::datatypes::*;
+
+    fn scan(table_name: &str, columns: Vec<&str>) -> Rc<LogicalPlan> {
+        Rc::new(LogicalPlan::TableScan {
+            schema_name: "default".to_string(),
+            table_name: table_name.to_string(),
+            schema: Rc::new(Schema::new(
+                columns
+                    .iter()
+                    .map(|c| Field::new(c, DataType::Float64, false))
+                    .collect(),
+            )),
+            projection: None,
+        })
+    }
+
+    fn sources(lineage: &ColumnLineage) -> Vec<String> {
+        lineage
+            .sources
+            .iter()
+            .map(|s| format!("{}.{}", s.source, s.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_column_lineage() {
+        let people = scan("people", vec!["id", "salary"]);
+        let orders = scan("orders", vec!["person_id", "amount"]);
+        let join = Rc::new(LogicalPlan::Join {
+            schema: Rc::new(Schema::new(vec![
+                people.schema().columns()[0].clone(),
+                people.schema().columns()[1].clone(),
+                orders.schema().columns()[0].clone(),
+                orders.schema().columns()[1].clone(),
+            ])),
+            left: people,
+            right: orders,
+            join_type: JoinType::Inner,
+            on: vec![(0, 0)],
+            null_equals_null: vec![false],
+            build_side: None,
+        });
+        let aggregate = Rc::new(LogicalPlan::Aggregate {
+            input: join,
+            group_expr: vec![Expr::Column(0)],
+            aggr_expr: vec![Expr::AggregateFunction {
+                name: "SUM".to_string(),
+                args: vec![Expr::Column(3)],
+                return_type: DataType::Float64,
+            }],
+            schema: Rc::new(Schema::new(vec![
+                Field::new("id", DataType::Float64, false),
+                Field::new("total", DataType::Float64, false),
+            ])),
+        });
+        let plan = LogicalPlan::Projection {
+            expr: vec![
+                Expr::Column(0),
+                Expr::BinaryExpr {
+                    left: Rc::new(Expr::Column(1)),
+                    op: Operator::Divide,
+                    right: Rc::new(Expr::Column(0)),
+                },
+                Expr::Literal(ScalarValue::Int64(1)),
+            ],
+            input: aggregate,
+            schema: Rc::new(Schema::new(vec![
+                Field::new("id", DataType::Float64, false),
+                Field::new("ratio", DataType::Float64, false),
+                Field::new("one", DataType::Int64, false),
+            ])),
+        };
+
+        let lineage = column_lineage(&plan);
+        assert_eq!(3, lineage.len());
+        assert_eq!("ratio", lineage[1].name);
+        assert_eq!(vec!["people.id"], sources(&lineage[0]));
+        assert_eq!(vec!["orders.amount", "people.id"], sources(&lineage[1]));
+        assert!(lineage[2].sources.is_empty());
+    }
+
+    #[test]
+    fn test_union_lineage() {
+        let schema = Rc::new(Schema::new(vec![Field::new("x", DataType::Float64, false)]));
+        let plan = LogicalPlan::Union {
+            inputs: vec![scan("a", vec!["x"]), scan("b", vec!["x"])],
+            schema,
+        };
+        assert_eq!(vec!["a.x", "b.x"], sources(&column_lineage(&plan)[0]));
+    }
+}
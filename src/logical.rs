@@ -18,9 +18,12 @@ use std::fmt;
 use std::fmt::{Error, Formatter};
 use std::rc::Rc;
 
+use super::datasources::common::RecordBatch;
+use super::errors::DataFusionError;
 use super::types::*;
 
 use arrow::datatypes::*;
+use json::JsonValue;
 
 #[derive(Debug, Clone)]
 pub enum FunctionType {
@@ -28,12 +31,19 @@ pub enum FunctionType {
     Aggregate,
 }
 
-#[derive(Debug, Clone)]
+/// Computes the return type of a function from the values of its literal arguments, where
+/// arguments that are not literals are None
+pub type ReturnTypeFunction = Fn(&[Option<ScalarValue>]) -> Result<DataType, DataFusionError>;
+
+#[derive(Clone)]
 pub struct FunctionMeta {
     name: String,
     args: Vec<Field>,
     return_type: DataType,
     function_type: FunctionType,
+    volatility: Volatility,
+    null_propagating: bool,
+    return_type_fn: Option<Rc<ReturnTypeFunction>>,
 }
 
 impl FunctionMeta {
@@ -48,8 +58,25 @@ impl FunctionMeta {
             args,
             return_type,
             function_type,
+            volatility: Volatility::Immutable,
+            null_propagating: false,
+            return_type_fn: None,
         }
     }
+    pub fn with_volatility(mut self, volatility: Volatility) -> Self {
+        self.volatility = volatility;
+        self
+    }
+    pub fn with_null_propagating(mut self, null_propagating: bool) -> Self {
+        self.null_propagating = null_propagating;
+        self
+    }
+    /// Compute the return type of each call from its arguments rather than using the same
+    /// return type for every call
+    pub fn with_return_type_fn(mut self, return_type_fn: Rc<ReturnTypeFunction>) -> Self {
+        self.return_type_fn = Some(return_type_fn);
+        self
+    }
     pub fn name(&self) -> &String {
         &self.name
     }
@@ -62,6 +89,41 @@ impl FunctionMeta {
     pub fn function_type(&self) -> &FunctionType {
         &self.function_type
     }
+    pub fn volatility(&self) -> Volatility {
+        self.volatility
+    }
+    pub fn null_propagating(&self) -> bool {
+        self.null_propagating
+    }
+    /// Get the return type of a call with the given arguments
+    pub fn return_type_for(&self, args: &[Expr]) -> Result<DataType, DataFusionError> {
+        match self.return_type_fn {
+            Some(ref f) => {
+                let literals: Vec<Option<ScalarValue>> = args
+                    .iter()
+                    .map(|arg| match *arg {
+                        Expr::Literal(ref v) => Some(v.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                f(&literals)
+            }
+            None => Ok(self.return_type.clone()),
+        }
+    }
+}
+
+impl fmt::Debug for FunctionMeta {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        f.debug_struct("FunctionMeta")
+            .field("name", &self.name)
+            .field("args", &self.args)
+            .field("return_type", &self.return_type)
+            .field("function_type", &self.function_type)
+            .field("volatility", &self.volatility)
+            .field("null_propagating", &self.null_propagating)
+            .finish()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -79,6 +141,10 @@ pub enum Operator {
     Modulus,
     And,
     Or,
+    Like,
+    NotLike,
+    ILike,
+    NotILike,
 }
 
 impl Operator {
@@ -87,6 +153,14 @@ impl Operator {
         //TODO: implement correctly, just go with left side for now
         l.get_type(schema).clone()
     }
+
+    /// Whether this is one of the `LIKE` pattern matching operators
+    pub fn is_like(&self) -> bool {
+        match *self {
+            Operator::Like | Operator::NotLike | Operator::ILike | Operator::NotILike => true,
+            _ => false,
+        }
+    }
 }
 
 /// Relation Expression
@@ -106,10 +180,22 @@ pub enum Expr {
     IsNotNull(Rc<Expr>),
     /// unary IS NULL
     IsNull(Rc<Expr>),
+    /// logical negation of a boolean expression, which is null when the expression is null
+    Not(Rc<Expr>),
     /// cast a value to a different type
     Cast { expr: Rc<Expr>, data_type: DataType },
-    /// sort expression
-    Sort { expr: Rc<Expr>, asc: bool },
+    /// sort expression, with nulls sorting before other values when `nulls_first` is true
+    Sort {
+        expr: Rc<Expr>,
+        asc: bool,
+        nulls_first: bool,
+    },
+    /// searched CASE expression, evaluating to the result of the first condition that is true
+    /// or to the else expression (or null) if no condition is true
+    Case {
+        when_then: Vec<(Rc<Expr>, Rc<Expr>)>,
+        else_expr: Option<Rc<Expr>>,
+    },
     /// scalar function
     ScalarFunction {
         name: String,
@@ -122,18 +208,62 @@ pub enum Expr {
         args: Vec<Expr>,
         return_type: DataType,
     },
+    /// column of the current row of the outer query, referenced from a correlated subquery
+    OuterColumn { index: usize, data_type: DataType },
+    /// comparison of a value with every row of a single column subquery, e.g. "x > ALL (...)",
+    /// which is true for ALL when every comparison is true and for ANY when at least one is
+    QuantifiedComparison {
+        expr: Rc<Expr>,
+        op: Operator,
+        all: bool,
+        subquery: Subquery,
+    },
+}
+
+/// A subquery used as an expression, evaluated for each row when it is correlated
+#[derive(Clone)]
+pub struct Subquery {
+    pub plan: Rc<LogicalPlan>,
+    /// Columns of the outer query that the subquery references
+    pub outer_columns: Vec<usize>,
+}
+
+impl Subquery {
+    pub fn is_correlated(&self) -> bool {
+        !self.outer_columns.is_empty()
+    }
+}
+
+impl PartialEq for Subquery {
+    fn eq(&self, other: &Subquery) -> bool {
+        self.outer_columns == other.outer_columns
+            && format!("{:?}", self.plan) == format!("{:?}", other.plan)
+    }
+}
+
+impl fmt::Debug for Subquery {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let plan = format!("{:?}", self.plan);
+        let lines: Vec<&str> = plan.lines().map(|line| line.trim()).collect();
+        write!(f, "({})", lines.join("; "))
+    }
 }
 
 impl Expr {
     pub fn get_type(&self, schema: &Schema) -> DataType {
         match self {
             Expr::Column(n) => schema.column(*n).data_type().clone(),
+            // a null literal has no type of its own, so it has the type it is compiled with
+            Expr::Literal(ScalarValue::Null) => DataType::Float64,
             Expr::Literal(l) => l.get_datatype(),
             Expr::Cast { data_type, .. } => data_type.clone(),
             Expr::ScalarFunction { return_type, .. } => return_type.clone(),
             Expr::AggregateFunction { return_type, .. } => return_type.clone(),
+            Expr::OuterColumn { data_type, .. } => data_type.clone(),
             Expr::IsNull(_) => DataType::Boolean,
+            Expr::Not(_) => DataType::Boolean,
             Expr::IsNotNull(_) => DataType::Boolean,
+            Expr::QuantifiedComparison { .. } => DataType::Boolean,
             Expr::BinaryExpr {
                 ref left,
                 ref right,
@@ -144,6 +274,8 @@ impl Expr {
                     Operator::Lt | Operator::LtEq => DataType::Boolean,
                     Operator::Gt | Operator::GtEq => DataType::Boolean,
                     Operator::And | Operator::Or => DataType::Boolean,
+                    Operator::Like | Operator::NotLike => DataType::Boolean,
+                    Operator::ILike | Operator::NotILike => DataType::Boolean,
                     _ => {
                         let left_type = left.get_type(schema);
                         let right_type = right.get_type(schema);
@@ -152,10 +284,11 @@ impl Expr {
                 }
             }
             Expr::Sort { ref expr, .. } => expr.get_type(schema),
+            Expr::Case { ref when_then, .. } => when_then[0].1.get_type(schema),
         }
     }
 
-    pub fn cast_to(&self, cast_to_type: &DataType, schema: &Schema) -> Result<Expr, String> {
+    pub fn cast_to(&self, cast_to_type: &DataType, schema: &Schema) -> Result<Expr, DataFusionError> {
         let this_type = self.get_type(schema);
         if this_type == *cast_to_type {
             Ok(self.clone())
@@ -165,10 +298,10 @@ impl Expr {
                 data_type: cast_to_type.clone(),
             })
         } else {
-            Err(format!(
+            Err(DataFusionError::Plan(format!(
                 "Cannot automatically convert {:?} to {:?}",
                 this_type, cast_to_type
-            ))
+            )))
         }
     }
 
@@ -225,16 +358,48 @@ impl fmt::Debug for Expr {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         match self {
             Expr::Column(i) => write!(f, "#{}", i),
+            Expr::OuterColumn { index, .. } => write!(f, "outer#{}", index),
             Expr::Literal(v) => write!(f, "{:?}", v),
             Expr::Cast { expr, data_type } => write!(f, "CAST({:?} AS {:?})", expr, data_type),
             Expr::IsNull(expr) => write!(f, "{:?} IS NULL", expr),
             Expr::IsNotNull(expr) => write!(f, "{:?} IS NOT NULL", expr),
+            Expr::Not(expr) => write!(f, "NOT {:?}", expr),
             Expr::BinaryExpr { left, op, right } => write!(f, "{:?} {:?} {:?}", left, op, right),
-            Expr::Sort { expr, asc } => if *asc {
-                write!(f, "{:?} ASC", expr)
-            } else {
-                write!(f, "{:?} DESC", expr)
-            },
+            Expr::QuantifiedComparison {
+                expr,
+                op,
+                all,
+                subquery,
+            } => {
+                let quantifier = if *all { "ALL" } else { "ANY" };
+                write!(f, "{:?} {:?} {} {:?}", expr, op, quantifier, subquery)
+            }
+            Expr::Sort {
+                expr,
+                asc,
+                nulls_first,
+            } => {
+                write!(f, "{:?} {}", expr, if *asc { "ASC" } else { "DESC" })?;
+                // only a null ordering that differs from the default is shown
+                if *nulls_first != *asc {
+                    let nulls = if *nulls_first { "FIRST" } else { "LAST" };
+                    write!(f, " NULLS {}", nulls)?;
+                }
+                Ok(())
+            }
+            Expr::Case {
+                when_then,
+                else_expr,
+            } => {
+                write!(f, "CASE")?;
+                for (when, then) in when_then {
+                    write!(f, " WHEN {:?} THEN {:?}", when, then)?;
+                }
+                if let Some(e) = else_expr {
+                    write!(f, " ELSE {:?}", e)?;
+                }
+                write!(f, " END")
+            }
             Expr::ScalarFunction { name, ref args, .. } => {
                 write!(f, "{}(", name)?;
                 for i in 0..args.len() {
@@ -261,6 +426,228 @@ impl fmt::Debug for Expr {
     }
 }
 
+/// How the offsets of a window frame are measured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowFrameUnits {
+    /// Physical rows
+    Rows,
+    /// Distance between values of the ORDER BY expression
+    Range,
+    /// Groups of peer rows, which have equal ORDER BY values
+    Groups,
+}
+
+/// One end of a window frame. For RANGE frames the offsets are in the units of the ORDER BY
+/// expression, with intervals converted to milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowFrameBound {
+    UnboundedPreceding,
+    Preceding(f64),
+    CurrentRow,
+    Following(f64),
+    UnboundedFollowing,
+}
+
+/// Rows that are left out of a window frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowFrameExclusion {
+    NoOthers,
+    CurrentRow,
+    /// The current row and its peers
+    Group,
+    /// The peers of the current row but not the row itself
+    Ties,
+}
+
+/// The rows of a partition that a window function is evaluated over for each row
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowFrame {
+    pub units: WindowFrameUnits,
+    pub start: WindowFrameBound,
+    pub end: WindowFrameBound,
+    pub exclusion: WindowFrameExclusion,
+}
+
+impl WindowFrame {
+    /// The frame used when none is specified, which is every row up to and including the peers
+    /// of the current row when the window is ordered, and otherwise the whole partition
+    pub fn default_for(ordered: bool) -> Self {
+        WindowFrame {
+            units: WindowFrameUnits::Range,
+            start: WindowFrameBound::UnboundedPreceding,
+            end: if ordered {
+                WindowFrameBound::CurrentRow
+            } else {
+                WindowFrameBound::UnboundedFollowing
+            },
+            exclusion: WindowFrameExclusion::NoOthers,
+        }
+    }
+}
+
+impl fmt::Display for WindowFrameBound {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            WindowFrameBound::UnboundedPreceding => write!(f, "UNBOUNDED PRECEDING"),
+            WindowFrameBound::Preceding(n) => write!(f, "{} PRECEDING", n),
+            WindowFrameBound::CurrentRow => write!(f, "CURRENT ROW"),
+            WindowFrameBound::Following(n) => write!(f, "{} FOLLOWING", n),
+            WindowFrameBound::UnboundedFollowing => write!(f, "UNBOUNDED FOLLOWING"),
+        }
+    }
+}
+
+/// A window function call, which produces one value for every row of its input
+#[derive(Clone, PartialEq)]
+pub struct WindowExpr {
+    pub name: String,
+    pub args: Vec<Expr>,
+    pub partition_by: Vec<Expr>,
+    /// Sort expressions that order the rows of each partition
+    pub order_by: Vec<Expr>,
+    pub frame: WindowFrame,
+    /// Whether value functions skip rows where their argument is null
+    pub ignore_nulls: bool,
+    pub return_type: DataType,
+}
+
+impl fmt::Debug for WindowExpr {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{}(", self.name)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:?}", arg)?;
+        }
+        write!(f, ")")?;
+        if self.ignore_nulls {
+            write!(f, " IGNORE NULLS")?;
+        }
+        write!(f, " OVER (")?;
+        if !self.partition_by.is_empty() {
+            write!(f, "PARTITION BY {:?} ", self.partition_by)?;
+        }
+        if !self.order_by.is_empty() {
+            write!(f, "ORDER BY {:?} ", self.order_by)?;
+        }
+        let units = match self.frame.units {
+            WindowFrameUnits::Rows => "ROWS",
+            WindowFrameUnits::Range => "RANGE",
+            WindowFrameUnits::Groups => "GROUPS",
+        };
+        write!(
+            f,
+            "{} BETWEEN {} AND {}",
+            units, self.frame.start, self.frame.end
+        )?;
+        match self.frame.exclusion {
+            WindowFrameExclusion::NoOthers => {}
+            WindowFrameExclusion::CurrentRow => write!(f, " EXCLUDE CURRENT ROW")?,
+            WindowFrameExclusion::Group => write!(f, " EXCLUDE GROUP")?,
+            WindowFrameExclusion::Ties => write!(f, " EXCLUDE TIES")?,
+        }
+        write!(f, ")")
+    }
+}
+
+/// Methods for sampling rows from a relation
+#[derive(Debug, Clone, PartialEq)]
+pub enum SampleMethod {
+    /// Each row is included independently with the sample probability
+    Bernoulli,
+    /// Whole batches are included or skipped with the sample probability
+    System,
+}
+
+/// Which row of each set of duplicates a deduplication keeps
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeduplicateKeep {
+    /// The first row in the sort order, or in input order when rows are equal
+    First,
+    /// The last row in the sort order, or in input order when rows are equal
+    Last,
+}
+
+/// The version of a table that a time travel query reads
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableSnapshot {
+    /// A version number assigned by the table
+    Version(i64),
+    /// The latest version as of a point in time, in milliseconds since the epoch
+    Timestamp(i64),
+}
+
+/// Types of join
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinType {
+    /// Rows from both relations where the join keys match
+    Inner,
+    /// Rows from the left relation that have at least one match in the right relation. Each
+    /// left row is produced once regardless of how many rows it matches.
+    LeftSemi,
+    /// Rows from the left relation that have no match in the right relation
+    LeftAnti,
+}
+
+/// The input of a join that its hash table is built from
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildSide {
+    Left,
+    Right,
+}
+
+/// A column that the rows of a relation are sorted by
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    /// Index of the column in the relation's schema
+    pub column: usize,
+    pub asc: bool,
+    pub nulls_first: bool,
+}
+
+impl SortKey {
+    /// Create a sort key where nulls sort before other values in ascending order and after
+    /// them in descending order
+    pub fn new(column: usize, asc: bool) -> Self {
+        SortKey {
+            column,
+            asc,
+            nulls_first: asc,
+        }
+    }
+
+    pub fn with_nulls_first(mut self, nulls_first: bool) -> Self {
+        self.nulls_first = nulls_first;
+        self
+    }
+}
+
+/// How the rows of a relation are divided between partitions
+#[derive(Debug, Clone, PartialEq)]
+pub enum Partitioning {
+    /// Nothing is known about which partition a row is in
+    Unknown,
+    /// Rows with equal values in the columns are always in the same partition
+    Hash {
+        columns: Vec<usize>,
+        partitions: usize,
+    },
+}
+
+impl Partitioning {
+    /// Check whether rows with equal values in the given columns are always in the same
+    /// partition, so that the partitions can be processed independently
+    pub fn satisfies(&self, columns: &[usize]) -> bool {
+        match *self {
+            Partitioning::Hash { columns: ref c, .. } => {
+                !c.is_empty() && c.iter().all(|i| columns.contains(i))
+            }
+            Partitioning::Unknown => false,
+        }
+    }
+}
+
 /// The LogicalPlan represents different types of relations (such as Projection, Selection, etc) and
 /// can be created by the SQL query planner and the DataFrame API.
 #[derive(Clone)]
@@ -292,6 +679,67 @@ pub enum LogicalPlan {
         input: Rc<LogicalPlan>,
         schema: Rc<Schema>,
     },
+    /// Evaluates window functions, producing the input columns followed by one column for
+    /// each window expression
+    Window {
+        input: Rc<LogicalPlan>,
+        window_expr: Vec<WindowExpr>,
+        schema: Rc<Schema>,
+    },
+    /// Represents a random sample of the rows in the child relation
+    Sample {
+        method: SampleMethod,
+        /// Fraction of rows to keep, between 0.0 and 1.0
+        fraction: f64,
+        /// Seed for the random number generator, for reproducible samples
+        seed: Option<u64>,
+        input: Rc<LogicalPlan>,
+        schema: Rc<Schema>,
+    },
+    /// Keeps one row for each distinct value of the key expressions
+    Deduplicate {
+        input: Rc<LogicalPlan>,
+        keys: Vec<Expr>,
+        /// Sort expressions that decide which of the duplicate rows is kept
+        order_by: Vec<Expr>,
+        keep: DeduplicateKeep,
+        schema: Rc<Schema>,
+    },
+    /// An equijoin of two relations. Key types may differ, in which case both sides are cast to
+    /// a common type when the join is executed.
+    Join {
+        left: Rc<LogicalPlan>,
+        right: Rc<LogicalPlan>,
+        join_type: JoinType,
+        /// Pairs of column indices from the left and right inputs that must be equal
+        on: Vec<(usize, usize)>,
+        /// Whether the null keys of each pair of `on` are equal to each other, as for a pair that
+        /// is compared with `IS NOT DISTINCT FROM`
+        null_equals_null: Vec<bool>,
+        /// The input to build the hash table from, as chosen by a `BROADCAST` hint. The physical
+        /// planner chooses when this is None. Only inner joins can be built on the left.
+        build_side: Option<BuildSide>,
+        schema: Rc<Schema>,
+    },
+    /// Evaluates a subquery for each row of the left relation, producing the left row followed
+    /// by each row of the subquery. The subquery refers to the columns of the left row with
+    /// `Expr::OuterColumn`.
+    LateralJoin {
+        left: Rc<LogicalPlan>,
+        subquery: Rc<LogicalPlan>,
+        /// When true, left rows that the subquery produces no rows for are kept, with nulls
+        /// for the subquery columns
+        left_outer: bool,
+        /// Whether the subquery references the left row at all. When it doesn't, it only needs
+        /// to be evaluated once.
+        correlated: bool,
+        schema: Rc<Schema>,
+    },
+    /// The rows of each input in turn. The inputs all have the same schema.
+    Union {
+        inputs: Vec<Rc<LogicalPlan>>,
+        schema: Rc<Schema>,
+    },
     /// A table scan against a table that has been registered on a context
     TableScan {
         schema_name: String,
@@ -320,6 +768,25 @@ pub enum LogicalPlan {
     },
     /// An empty relation with an empty schema
     EmptyRelation { schema: Rc<Schema> },
+    /// Results that have already been materialized in memory
+    MemTable {
+        schema: Rc<Schema>,
+        batches: Rc<Vec<Rc<RecordBatch>>>,
+    },
+    /// Rows read from a stream source that was registered on a context
+    StreamScan {
+        stream_name: String,
+        schema: Rc<Schema>,
+    },
+    /// The integers from `start` to `stop` in increments of `step`, as produced by the
+    /// `generate_series` and `range` table functions. `stop` is only included when `inclusive`.
+    GenerateSeries {
+        start: i64,
+        stop: i64,
+        step: i64,
+        inclusive: bool,
+        schema: Rc<Schema>,
+    },
 }
 
 impl LogicalPlan {
@@ -327,6 +794,9 @@ impl LogicalPlan {
     pub fn schema(&self) -> &Rc<Schema> {
         match self {
             LogicalPlan::EmptyRelation { schema } => &schema,
+            LogicalPlan::MemTable { schema, .. } => &schema,
+            LogicalPlan::StreamScan { schema, .. } => &schema,
+            LogicalPlan::GenerateSeries { schema, .. } => &schema,
             LogicalPlan::TableScan { schema, .. } => &schema,
             LogicalPlan::CsvFile { schema, .. } => &schema,
             LogicalPlan::NdJsonFile { schema, .. } => &schema,
@@ -335,7 +805,13 @@ impl LogicalPlan {
             LogicalPlan::Selection { input, .. } => input.schema(),
             LogicalPlan::Aggregate { schema, .. } => &schema,
             LogicalPlan::Sort { schema, .. } => &schema,
+            LogicalPlan::Window { schema, .. } => &schema,
             LogicalPlan::Limit { schema, .. } => &schema,
+            LogicalPlan::Sample { schema, .. } => &schema,
+            LogicalPlan::Deduplicate { schema, .. } => &schema,
+            LogicalPlan::Join { schema, .. } => &schema,
+            LogicalPlan::LateralJoin { schema, .. } => &schema,
+            LogicalPlan::Union { schema, .. } => &schema,
         }
     }
 }
@@ -350,11 +826,30 @@ impl LogicalPlan {
         }
         match *self {
             LogicalPlan::EmptyRelation { .. } => write!(f, "EmptyRelation"),
+            LogicalPlan::MemTable { ref batches, .. } => write!(
+                f,
+                "MemTable: rows={}",
+                batches.iter().map(|b| b.num_rows()).sum::<usize>()
+            ),
             LogicalPlan::TableScan {
                 ref table_name,
                 ref projection,
                 ..
             } => write!(f, "TableScan: {} projection={:?}", table_name, projection),
+            LogicalPlan::StreamScan {
+                ref stream_name, ..
+            } => write!(f, "StreamScan: {}", stream_name),
+            LogicalPlan::GenerateSeries {
+                start,
+                stop,
+                step,
+                inclusive,
+                ..
+            } => write!(
+                f,
+                "GenerateSeries: start={}, stop={}, step={}, inclusive={}",
+                start, stop, step, inclusive
+            ),
             LogicalPlan::CsvFile {
                 ref filename,
                 ref schema,
@@ -415,14 +910,443 @@ impl LogicalPlan {
                 }
                 input.fmt_with_indent(f, indent + 1)
             }
+            LogicalPlan::Window {
+                ref input,
+                ref window_expr,
+                ..
+            } => {
+                write!(f, "Window: ")?;
+                for (i, e) in window_expr.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", e)?;
+                }
+                input.fmt_with_indent(f, indent + 1)
+            }
             LogicalPlan::Limit {
                 ref input, limit, ..
             } => {
                 write!(f, "Limit: {}", limit)?;
                 input.fmt_with_indent(f, indent + 1)
             }
+            LogicalPlan::Sample {
+                ref method,
+                fraction,
+                ref seed,
+                ref input,
+                ..
+            } => {
+                write!(f, "Sample: method={:?}, fraction={}", method, fraction)?;
+                if let Some(seed) = seed {
+                    write!(f, ", seed={}", seed)?;
+                }
+                input.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::Deduplicate {
+                ref input,
+                ref keys,
+                ref order_by,
+                ref keep,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Deduplicate: keys={:?}, keep={:?}, orderBy={:?}",
+                    keys, keep, order_by
+                )?;
+                input.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::Join {
+                ref left,
+                ref right,
+                ref join_type,
+                ref on,
+                ref null_equals_null,
+                ref build_side,
+                ..
+            } => {
+                write!(f, "Join: type={:?}, on=[", join_type)?;
+                for (i, &(l, r)) in on.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    if null_equals_null[i] {
+                        write!(f, "#{} IS NOT DISTINCT FROM #{}", l, r)?;
+                    } else {
+                        write!(f, "#{} = #{}", l, r)?;
+                    }
+                }
+                write!(f, "]")?;
+                if let Some(ref side) = *build_side {
+                    write!(f, ", build={:?}", side)?;
+                }
+                left.fmt_with_indent(f, indent + 1)?;
+                right.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::LateralJoin {
+                ref left,
+                ref subquery,
+                left_outer,
+                correlated,
+                ..
+            } => {
+                write!(
+                    f,
+                    "LateralJoin: left_outer={}, correlated={}",
+                    left_outer, correlated
+                )?;
+                left.fmt_with_indent(f, indent + 1)?;
+                subquery.fmt_with_indent(f, indent + 1)
+            }
+            LogicalPlan::Union { ref inputs, .. } => {
+                write!(f, "Union")?;
+                for input in inputs {
+                    input.fmt_with_indent(f, indent + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl LogicalPlan {
+    /// Get the inputs of the plan, not including subqueries used in expressions
+    pub fn inputs(&self) -> Vec<&Rc<LogicalPlan>> {
+        match *self {
+            LogicalPlan::Limit { ref input, .. }
+            | LogicalPlan::Projection { ref input, .. }
+            | LogicalPlan::Selection { ref input, .. }
+            | LogicalPlan::Aggregate { ref input, .. }
+            | LogicalPlan::Sort { ref input, .. }
+            | LogicalPlan::Window { ref input, .. }
+            | LogicalPlan::Sample { ref input, .. }
+            | LogicalPlan::Deduplicate { ref input, .. } => vec![input],
+            LogicalPlan::Join {
+                ref left,
+                ref right,
+                ..
+            } => vec![left, right],
+            LogicalPlan::LateralJoin {
+                ref left,
+                ref subquery,
+                ..
+            } => vec![left, subquery],
+            LogicalPlan::Union { ref inputs, .. } => inputs.iter().collect(),
+            LogicalPlan::TableScan { .. }
+            | LogicalPlan::CsvFile { .. }
+            | LogicalPlan::NdJsonFile { .. }
+            | LogicalPlan::ParquetFile { .. }
+            | LogicalPlan::EmptyRelation { .. }
+            | LogicalPlan::MemTable { .. }
+            | LogicalPlan::StreamScan { .. }
+            | LogicalPlan::GenerateSeries { .. } => vec![],
+        }
+    }
+
+    /// Estimate the number of rows the plan produces, where this is known without reading data
+    pub fn estimated_rows(&self) -> Option<usize> {
+        match *self {
+            LogicalPlan::MemTable { ref batches, .. } => {
+                Some(batches.iter().map(|b| b.num_rows()).sum())
+            }
+            LogicalPlan::GenerateSeries {
+                start,
+                stop,
+                step,
+                inclusive,
+                ..
+            } => Some(series_len(start, stop, step, inclusive)),
+            LogicalPlan::Limit {
+                ref input, limit, ..
+            } => match input.estimated_rows() {
+                Some(n) if n < limit => Some(n),
+                _ => Some(limit),
+            },
+            LogicalPlan::Projection { ref input, .. }
+            | LogicalPlan::Sort { ref input, .. }
+            | LogicalPlan::Window { ref input, .. } => input.estimated_rows(),
+            LogicalPlan::Sample {
+                ref input,
+                fraction,
+                ..
+            } => input
+                .estimated_rows()
+                .map(|n| (n as f64 * fraction).ceil() as usize),
+            _ => None,
+        }
+    }
+
+    /// Get the exact number of rows the plan produces, where this is known without reading
+    /// data. Unlike `estimated_rows`, this is `None` when the count is only an estimate.
+    pub fn exact_rows(&self) -> Option<usize> {
+        match *self {
+            LogicalPlan::MemTable { ref batches, .. } => {
+                Some(batches.iter().map(|b| b.num_rows()).sum())
+            }
+            LogicalPlan::GenerateSeries {
+                start,
+                stop,
+                step,
+                inclusive,
+                ..
+            } => Some(series_len(start, stop, step, inclusive)),
+            LogicalPlan::Limit {
+                ref input, limit, ..
+            } => input.exact_rows().map(|n| n.min(limit)),
+            LogicalPlan::Projection { ref input, .. }
+            | LogicalPlan::Sort { ref input, .. }
+            | LogicalPlan::Window { ref input, .. } => input.exact_rows(),
+            _ => None,
         }
     }
+
+    /// Produce a machine-readable description of the plan tree, as used by
+    /// `EXPLAIN (FORMAT JSON)`
+    pub fn to_json(&self) -> JsonValue {
+        let mut node = JsonValue::new_object();
+        let mut expressions: Vec<&Expr> = vec![];
+        let mut inputs: Vec<&Rc<LogicalPlan>> = vec![];
+        let node_type = match *self {
+            LogicalPlan::EmptyRelation { .. } => "EmptyRelation",
+            LogicalPlan::MemTable { .. } => "MemTable",
+            LogicalPlan::StreamScan {
+                ref stream_name, ..
+            } => {
+                node["stream_name"] = stream_name.as_str().into();
+                "StreamScan"
+            }
+            LogicalPlan::GenerateSeries {
+                start,
+                stop,
+                step,
+                inclusive,
+                ..
+            } => {
+                node["start"] = start.into();
+                node["stop"] = stop.into();
+                node["step"] = step.into();
+                node["inclusive"] = inclusive.into();
+                "GenerateSeries"
+            }
+            LogicalPlan::TableScan {
+                ref table_name,
+                ref projection,
+                ..
+            } => {
+                node["table_name"] = table_name.as_str().into();
+                node["projection"] = projection_to_json(projection);
+                "TableScan"
+            }
+            LogicalPlan::CsvFile {
+                ref filename,
+                has_header,
+                ref projection,
+                ..
+            } => {
+                node["filename"] = filename.as_str().into();
+                node["has_header"] = has_header.into();
+                node["projection"] = projection_to_json(projection);
+                "CsvFile"
+            }
+            LogicalPlan::NdJsonFile {
+                ref filename,
+                ref projection,
+                ..
+            } => {
+                node["filename"] = filename.as_str().into();
+                node["projection"] = projection_to_json(projection);
+                "NdJsonFile"
+            }
+            LogicalPlan::ParquetFile {
+                ref filename,
+                ref projection,
+                ..
+            } => {
+                node["filename"] = filename.as_str().into();
+                node["projection"] = projection_to_json(projection);
+                "ParquetFile"
+            }
+            LogicalPlan::Projection {
+                ref expr,
+                ref input,
+                ..
+            } => {
+                expressions.extend(expr.iter());
+                inputs.push(input);
+                "Projection"
+            }
+            LogicalPlan::Selection {
+                ref expr,
+                ref input,
+            } => {
+                expressions.push(expr);
+                inputs.push(input);
+                "Selection"
+            }
+            LogicalPlan::Aggregate {
+                ref input,
+                ref group_expr,
+                ref aggr_expr,
+                ..
+            } => {
+                node["group_expr"] = JsonValue::Array(
+                    group_expr.iter().map(|e| format!("{:?}", e).into()).collect(),
+                );
+                expressions.extend(aggr_expr.iter());
+                inputs.push(input);
+                "Aggregate"
+            }
+            LogicalPlan::Sort {
+                ref expr,
+                ref input,
+                ..
+            } => {
+                expressions.extend(expr.iter());
+                inputs.push(input);
+                "Sort"
+            }
+            LogicalPlan::Window {
+                ref input,
+                ref window_expr,
+                ..
+            } => {
+                node["window_expr"] = JsonValue::Array(
+                    window_expr.iter().map(|e| format!("{:?}", e).into()).collect(),
+                );
+                inputs.push(input);
+                "Window"
+            }
+            LogicalPlan::Limit {
+                limit, ref input, ..
+            } => {
+                node["limit"] = limit.into();
+                inputs.push(input);
+                "Limit"
+            }
+            LogicalPlan::Sample {
+                ref method,
+                fraction,
+                seed,
+                ref input,
+                ..
+            } => {
+                node["method"] = format!("{:?}", method).into();
+                node["fraction"] = fraction.into();
+                node["seed"] = match seed {
+                    Some(s) => s.into(),
+                    None => JsonValue::Null,
+                };
+                inputs.push(input);
+                "Sample"
+            }
+            LogicalPlan::Deduplicate {
+                ref input,
+                ref keys,
+                ref order_by,
+                ref keep,
+                ..
+            } => {
+                node["keep"] = format!("{:?}", keep).into();
+                node["order_by"] = JsonValue::Array(
+                    order_by.iter().map(|e| format!("{:?}", e).into()).collect(),
+                );
+                expressions.extend(keys.iter());
+                inputs.push(input);
+                "Deduplicate"
+            }
+            LogicalPlan::Join {
+                ref left,
+                ref right,
+                ref join_type,
+                ref on,
+                ref null_equals_null,
+                ref build_side,
+                ..
+            } => {
+                node["join_type"] = format!("{:?}", join_type).into();
+                node["on"] = JsonValue::Array(
+                    on.iter()
+                        .map(|&(l, r)| JsonValue::Array(vec![l.into(), r.into()]))
+                        .collect(),
+                );
+                node["null_equals_null"] =
+                    JsonValue::Array(null_equals_null.iter().map(|&n| n.into()).collect());
+                if let Some(ref side) = *build_side {
+                    node["build_side"] = format!("{:?}", side).into();
+                }
+                inputs.push(left);
+                inputs.push(right);
+                "Join"
+            }
+            LogicalPlan::LateralJoin {
+                ref left,
+                ref subquery,
+                left_outer,
+                correlated,
+                ..
+            } => {
+                node["left_outer"] = left_outer.into();
+                node["correlated"] = correlated.into();
+                inputs.push(left);
+                inputs.push(subquery);
+                "LateralJoin"
+            }
+            LogicalPlan::Union { inputs: ref union_inputs, .. } => {
+                inputs.extend(union_inputs.iter());
+                "Union"
+            }
+        };
+
+        node["node_type"] = node_type.into();
+        node["expressions"] = JsonValue::Array(
+            expressions
+                .iter()
+                .map(|e| format!("{:?}", e).into())
+                .collect(),
+        );
+        node["schema"] = JsonValue::Array(
+            self.schema()
+                .columns()
+                .iter()
+                .map(|f| {
+                    let mut field = JsonValue::new_object();
+                    field["name"] = f.name().as_str().into();
+                    field["data_type"] = format!("{:?}", f.data_type()).into();
+                    field["nullable"] = f.is_nullable().into();
+                    field
+                })
+                .collect(),
+        );
+        node["estimated_rows"] = match self.estimated_rows() {
+            Some(n) => n.into(),
+            None => JsonValue::Null,
+        };
+        node["inputs"] = JsonValue::Array(inputs.iter().map(|p| p.to_json()).collect());
+        node
+    }
+}
+
+fn projection_to_json(projection: &Option<Vec<usize>>) -> JsonValue {
+    match *projection {
+        Some(ref p) => JsonValue::Array(p.iter().map(|i| (*i).into()).collect()),
+        None => JsonValue::Null,
+    }
+}
+
+/// Number of values in the series from `start` to `stop` in increments of `step`
+pub fn series_len(start: i64, stop: i64, step: i64, inclusive: bool) -> usize {
+    let (start, stop, step) = (i128::from(start), i128::from(stop), i128::from(step));
+    let span = if step > 0 { stop - start } else { start - stop };
+    let step = step.abs();
+    let n = if span < 0 || (span == 0 && !inclusive) {
+        0
+    } else if inclusive {
+        span / step + 1
+    } else {
+        (span + step - 1) / step
+    };
+    n as usize
 }
 
 impl fmt::Debug for LogicalPlan {
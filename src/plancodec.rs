@@ -0,0 +1,1018 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serialization of logical plans to JSON, so that a client can plan a query locally, rewrite
+//! the plan and submit it to a server to be executed with `ExecutionContext::submit_plan`.
+//!
+//! Unlike `LogicalPlan::to_json`, which describes a plan for `EXPLAIN`, the serialized form
+//! holds everything needed to rebuild the plan. Floating point values are written as strings so
+//! that they round trip exactly. Plans that scan in-memory tables can't be serialized, since
+//! their rows are not part of the plan.
+//!
+//! The worker binary accepts serialized plans in the body of `POST /plan` requests and responds
+//! with the results as CSV.
+
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+use json;
+use json::JsonValue;
+
+use super::errors::*;
+use super::logical::*;
+use super::types::*;
+
+/// Serialize a logical plan to a JSON string
+pub fn serialize_plan(plan: &LogicalPlan) -> Result<String> {
+    Ok(encode_plan(plan)?.dump())
+}
+
+/// Rebuild a logical plan from a JSON string created by `serialize_plan`. The plan is not
+/// validated, which `ExecutionContext::submit_plan` does before it is executed.
+pub fn deserialize_plan(text: &str) -> Result<Rc<LogicalPlan>> {
+    let value = json::parse(text).map_err(|e| invalid(format!("{}", e)))?;
+    Ok(Rc::new(decode_plan(&value)?))
+}
+
+fn invalid(msg: String) -> DataFusionError {
+    DataFusionError::Plan(format!("Invalid serialized plan: {}", msg))
+}
+
+fn array(values: Vec<JsonValue>) -> JsonValue {
+    JsonValue::Array(values)
+}
+
+fn encode_plan(plan: &LogicalPlan) -> Result<JsonValue> {
+    let mut node = JsonValue::new_object();
+    let node_type = match *plan {
+        LogicalPlan::Limit {
+            limit, ref input, ..
+        } => {
+            node["limit"] = limit.into();
+            node["input"] = encode_plan(input)?;
+            "Limit"
+        }
+        LogicalPlan::Projection {
+            ref expr,
+            ref input,
+            ..
+        } => {
+            node["expr"] = encode_exprs(expr)?;
+            node["input"] = encode_plan(input)?;
+            "Projection"
+        }
+        LogicalPlan::Selection {
+            ref expr,
+            ref input,
+        } => {
+            node["expr"] = encode_expr(expr)?;
+            node["input"] = encode_plan(input)?;
+            "Selection"
+        }
+        LogicalPlan::Aggregate {
+            ref input,
+            ref group_expr,
+            ref aggr_expr,
+            ..
+        } => {
+            node["group_expr"] = encode_exprs(group_expr)?;
+            node["aggr_expr"] = encode_exprs(aggr_expr)?;
+            node["input"] = encode_plan(input)?;
+            "Aggregate"
+        }
+        LogicalPlan::Sort {
+            ref expr,
+            ref input,
+            ..
+        } => {
+            node["expr"] = encode_exprs(expr)?;
+            node["input"] = encode_plan(input)?;
+            "Sort"
+        }
+        LogicalPlan::Window {
+            ref input,
+            ref window_expr,
+            ..
+        } => {
+            node["window_expr"] = array(
+                window_expr
+                    .iter()
+                    .map(encode_window_expr)
+                    .collect::<Result<Vec<JsonValue>>>()?,
+            );
+            node["input"] = encode_plan(input)?;
+            "Window"
+        }
+        LogicalPlan::Sample {
+            ref method,
+            fraction,
+            seed,
+            ref input,
+            ..
+        } => {
+            node["method"] = format!("{:?}", method).into();
+            node["fraction"] = encode_float(fraction);
+            node["seed"] = match seed {
+                Some(s) => s.into(),
+                None => JsonValue::Null,
+            };
+            node["input"] = encode_plan(input)?;
+            "Sample"
+        }
+        LogicalPlan::Deduplicate {
+            ref input,
+            ref keys,
+            ref order_by,
+            ref keep,
+            ..
+        } => {
+            node["keys"] = encode_exprs(keys)?;
+            node["order_by"] = encode_exprs(order_by)?;
+            node["keep"] = format!("{:?}", keep).into();
+            node["input"] = encode_plan(input)?;
+            "Deduplicate"
+        }
+        LogicalPlan::Join {
+            ref left,
+            ref right,
+            ref join_type,
+            ref on,
+            ref null_equals_null,
+            ref build_side,
+            ..
+        } => {
+            node["join_type"] = format!("{:?}", join_type).into();
+            node["on"] = array(
+                on.iter()
+                    .map(|&(l, r)| array(vec![l.into(), r.into()]))
+                    .collect(),
+            );
+            node["null_equals_null"] = array(null_equals_null.iter().map(|&n| n.into()).collect());
+            node["build_side"] = match *build_side {
+                Some(ref side) => format!("{:?}", side).into(),
+                None => JsonValue::Null,
+            };
+            node["left"] = encode_plan(left)?;
+            node["right"] = encode_plan(right)?;
+            "Join"
+        }
+        LogicalPlan::LateralJoin {
+            ref left,
+            ref subquery,
+            left_outer,
+            correlated,
+            ..
+        } => {
+            node["left_outer"] = left_outer.into();
+            node["correlated"] = correlated.into();
+            node["left"] = encode_plan(left)?;
+            node["subquery"] = encode_plan(subquery)?;
+            "LateralJoin"
+        }
+        LogicalPlan::Union { ref inputs, .. } => {
+            node["inputs"] = array(
+                inputs
+                    .iter()
+                    .map(|p| encode_plan(p))
+                    .collect::<Result<Vec<JsonValue>>>()?,
+            );
+            "Union"
+        }
+        LogicalPlan::TableScan {
+            ref schema_name,
+            ref table_name,
+            ref projection,
+            ..
+        } => {
+            node["schema_name"] = schema_name.as_str().into();
+            node["table_name"] = table_name.as_str().into();
+            node["projection"] = encode_projection(projection);
+            "TableScan"
+        }
+        LogicalPlan::CsvFile {
+            ref filename,
+            has_header,
+            ref projection,
+            ..
+        } => {
+            node["filename"] = filename.as_str().into();
+            node["has_header"] = has_header.into();
+            node["projection"] = encode_projection(projection);
+            "CsvFile"
+        }
+        LogicalPlan::NdJsonFile {
+            ref filename,
+            ref projection,
+            ..
+        } => {
+            node["filename"] = filename.as_str().into();
+            node["projection"] = encode_projection(projection);
+            "NdJsonFile"
+        }
+        LogicalPlan::ParquetFile {
+            ref filename,
+            ref projection,
+            ..
+        } => {
+            node["filename"] = filename.as_str().into();
+            node["projection"] = encode_projection(projection);
+            "ParquetFile"
+        }
+        LogicalPlan::EmptyRelation { .. } => "EmptyRelation",
+        LogicalPlan::MemTable { .. } => {
+            return Err(DataFusionError::Plan(
+                "Plans that scan in-memory tables can't be serialized".to_string(),
+            ))
+        }
+        LogicalPlan::StreamScan {
+            ref stream_name, ..
+        } => {
+            node["stream_name"] = stream_name.as_str().into();
+            "StreamScan"
+        }
+        LogicalPlan::GenerateSeries {
+            start,
+            stop,
+            step,
+            inclusive,
+            ..
+        } => {
+            node["start"] = start.into();
+            node["stop"] = stop.into();
+            node["step"] = step.into();
+            node["inclusive"] = inclusive.into();
+            "GenerateSeries"
+        }
+    };
+    node["node_type"] = node_type.into();
+    match *plan {
+        // a selection has the schema of its input
+        LogicalPlan::Selection { .. } => {}
+        _ => node["schema"] = encode_schema(plan.schema())?,
+    }
+    Ok(node)
+}
+
+fn decode_plan(node: &JsonValue) -> Result<LogicalPlan> {
+    let input = || decode_plan(&node["input"]).map(Rc::new);
+    let schema = || decode_schema(&node["schema"]).map(Rc::new);
+    Ok(match string(node, "node_type")?.as_str() {
+        "Limit" => LogicalPlan::Limit {
+            limit: index(node, "limit")?,
+            input: input()?,
+            schema: schema()?,
+        },
+        "Projection" => LogicalPlan::Projection {
+            expr: decode_exprs(&node["expr"])?,
+            input: input()?,
+            schema: schema()?,
+        },
+        "Selection" => LogicalPlan::Selection {
+            expr: decode_expr(&node["expr"])?,
+            input: input()?,
+        },
+        "Aggregate" => LogicalPlan::Aggregate {
+            input: input()?,
+            group_expr: decode_exprs(&node["group_expr"])?,
+            aggr_expr: decode_exprs(&node["aggr_expr"])?,
+            schema: schema()?,
+        },
+        "Sort" => LogicalPlan::Sort {
+            expr: decode_exprs(&node["expr"])?,
+            input: input()?,
+            schema: schema()?,
+        },
+        "Window" => LogicalPlan::Window {
+            input: input()?,
+            window_expr: node["window_expr"]
+                .members()
+                .map(decode_window_expr)
+                .collect::<Result<Vec<WindowExpr>>>()?,
+            schema: schema()?,
+        },
+        "Sample" => LogicalPlan::Sample {
+            method: match string(node, "method")?.as_str() {
+                "Bernoulli" => SampleMethod::Bernoulli,
+                "System" => SampleMethod::System,
+                other => return Err(invalid(format!("unknown sample method '{}'", other))),
+            },
+            fraction: float(node, "fraction")?,
+            seed: match node["seed"] {
+                JsonValue::Null => None,
+                ref seed => Some(
+                    seed.as_u64()
+                        .ok_or_else(|| invalid("'seed' is not a number".to_string()))?,
+                ),
+            },
+            input: input()?,
+            schema: schema()?,
+        },
+        "Deduplicate" => LogicalPlan::Deduplicate {
+            input: input()?,
+            keys: decode_exprs(&node["keys"])?,
+            order_by: decode_exprs(&node["order_by"])?,
+            keep: match string(node, "keep")?.as_str() {
+                "First" => DeduplicateKeep::First,
+                "Last" => DeduplicateKeep::Last,
+                other => return Err(invalid(format!("unknown deduplicate keep '{}'", other))),
+            },
+            schema: schema()?,
+        },
+        "Join" => LogicalPlan::Join {
+            left: Rc::new(decode_plan(&node["left"])?),
+            right: Rc::new(decode_plan(&node["right"])?),
+            join_type: match string(node, "join_type")?.as_str() {
+                "Inner" => JoinType::Inner,
+                "LeftSemi" => JoinType::LeftSemi,
+                "LeftAnti" => JoinType::LeftAnti,
+                other => return Err(invalid(format!("unknown join type '{}'", other))),
+            },
+            on: node["on"]
+                .members()
+                .map(|pair| match (pair[0].as_usize(), pair[1].as_usize()) {
+                    (Some(l), Some(r)) => Ok((l, r)),
+                    _ => Err(invalid("join keys are not pairs of indexes".to_string())),
+                }).collect::<Result<Vec<(usize, usize)>>>()?,
+            null_equals_null: node["null_equals_null"]
+                .members()
+                .map(|n| {
+                    n.as_bool().ok_or_else(|| {
+                        invalid("'null_equals_null' is not a list of booleans".to_string())
+                    })
+                }).collect::<Result<Vec<bool>>>()?,
+            build_side: match node["build_side"].as_str() {
+                Some("Left") => Some(BuildSide::Left),
+                Some("Right") => Some(BuildSide::Right),
+                Some(other) => return Err(invalid(format!("unknown build side '{}'", other))),
+                None => None,
+            },
+            schema: schema()?,
+        },
+        "LateralJoin" => LogicalPlan::LateralJoin {
+            left: Rc::new(decode_plan(&node["left"])?),
+            subquery: Rc::new(decode_plan(&node["subquery"])?),
+            left_outer: boolean(node, "left_outer")?,
+            correlated: boolean(node, "correlated")?,
+            schema: schema()?,
+        },
+        "Union" => LogicalPlan::Union {
+            inputs: node["inputs"]
+                .members()
+                .map(|p| decode_plan(p).map(Rc::new))
+                .collect::<Result<Vec<Rc<LogicalPlan>>>>()?,
+            schema: schema()?,
+        },
+        "TableScan" => LogicalPlan::TableScan {
+            schema_name: string(node, "schema_name")?,
+            table_name: string(node, "table_name")?,
+            schema: schema()?,
+            projection: decode_projection(&node["projection"])?,
+        },
+        "CsvFile" => LogicalPlan::CsvFile {
+            filename: string(node, "filename")?,
+            schema: schema()?,
+            has_header: boolean(node, "has_header")?,
+            projection: decode_projection(&node["projection"])?,
+        },
+        "NdJsonFile" => LogicalPlan::NdJsonFile {
+            filename: string(node, "filename")?,
+            schema: schema()?,
+            projection: decode_projection(&node["projection"])?,
+        },
+        "ParquetFile" => LogicalPlan::ParquetFile {
+            filename: string(node, "filename")?,
+            schema: schema()?,
+            projection: decode_projection(&node["projection"])?,
+        },
+        "EmptyRelation" => LogicalPlan::EmptyRelation { schema: schema()? },
+        "StreamScan" => LogicalPlan::StreamScan {
+            stream_name: string(node, "stream_name")?,
+            schema: schema()?,
+        },
+        "GenerateSeries" => LogicalPlan::GenerateSeries {
+            start: int(node, "start")?,
+            stop: int(node, "stop")?,
+            step: int(node, "step")?,
+            inclusive: boolean(node, "inclusive")?,
+            schema: schema()?,
+        },
+        other => return Err(invalid(format!("unknown relation '{}'", other))),
+    })
+}
+
+fn encode_exprs(expr: &[Expr]) -> Result<JsonValue> {
+    Ok(array(
+        expr.iter()
+            .map(encode_expr)
+            .collect::<Result<Vec<JsonValue>>>()?,
+    ))
+}
+
+fn decode_exprs(node: &JsonValue) -> Result<Vec<Expr>> {
+    node.members().map(decode_expr).collect()
+}
+
+fn encode_expr(expr: &Expr) -> Result<JsonValue> {
+    let mut node = JsonValue::new_object();
+    let expr_type = match *expr {
+        Expr::Column(i) => {
+            node["index"] = i.into();
+            "Column"
+        }
+        Expr::Literal(ref value) => {
+            node["value"] = encode_scalar(value);
+            "Literal"
+        }
+        Expr::BinaryExpr {
+            ref left,
+            ref op,
+            ref right,
+        } => {
+            node["left"] = encode_expr(left)?;
+            node["op"] = format!("{:?}", op).into();
+            node["right"] = encode_expr(right)?;
+            "BinaryExpr"
+        }
+        Expr::IsNotNull(ref e) => {
+            node["expr"] = encode_expr(e)?;
+            "IsNotNull"
+        }
+        Expr::IsNull(ref e) => {
+            node["expr"] = encode_expr(e)?;
+            "IsNull"
+        }
+        Expr::Not(ref e) => {
+            node["expr"] = encode_expr(e)?;
+            "Not"
+        }
+        Expr::Cast {
+            ref expr,
+            ref data_type,
+        } => {
+            node["expr"] = encode_expr(expr)?;
+            node["data_type"] = encode_data_type(data_type)?;
+            "Cast"
+        }
+        Expr::Sort {
+            ref expr,
+            asc,
+            nulls_first,
+        } => {
+            node["expr"] = encode_expr(expr)?;
+            node["asc"] = asc.into();
+            node["nulls_first"] = nulls_first.into();
+            "Sort"
+        }
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            node["when_then"] = array(
+                when_then
+                    .iter()
+                    .map(|&(ref when, ref then)| {
+                        Ok(array(vec![encode_expr(when)?, encode_expr(then)?]))
+                    }).collect::<Result<Vec<JsonValue>>>()?,
+            );
+            node["else_expr"] = match *else_expr {
+                Some(ref e) => encode_expr(e)?,
+                None => JsonValue::Null,
+            };
+            "Case"
+        }
+        Expr::ScalarFunction {
+            ref name,
+            ref args,
+            ref return_type,
+        } => {
+            node["name"] = name.as_str().into();
+            node["args"] = encode_exprs(args)?;
+            node["return_type"] = encode_data_type(return_type)?;
+            "ScalarFunction"
+        }
+        Expr::AggregateFunction {
+            ref name,
+            ref args,
+            ref return_type,
+        } => {
+            node["name"] = name.as_str().into();
+            node["args"] = encode_exprs(args)?;
+            node["return_type"] = encode_data_type(return_type)?;
+            "AggregateFunction"
+        }
+        Expr::OuterColumn {
+            index,
+            ref data_type,
+        } => {
+            node["index"] = index.into();
+            node["data_type"] = encode_data_type(data_type)?;
+            "OuterColumn"
+        }
+        Expr::QuantifiedComparison {
+            ref expr,
+            ref op,
+            all,
+            ref subquery,
+        } => {
+            node["expr"] = encode_expr(expr)?;
+            node["op"] = format!("{:?}", op).into();
+            node["all"] = all.into();
+            node["plan"] = encode_plan(&subquery.plan)?;
+            node["outer_columns"] =
+                array(subquery.outer_columns.iter().map(|&i| i.into()).collect());
+            "QuantifiedComparison"
+        }
+    };
+    node["expr_type"] = expr_type.into();
+    Ok(node)
+}
+
+fn decode_expr(node: &JsonValue) -> Result<Expr> {
+    let expr = || decode_expr(&node["expr"]).map(Rc::new);
+    Ok(match string(node, "expr_type")?.as_str() {
+        "Column" => Expr::Column(index(node, "index")?),
+        "Literal" => Expr::Literal(decode_scalar(&node["value"])?),
+        "BinaryExpr" => Expr::BinaryExpr {
+            left: Rc::new(decode_expr(&node["left"])?),
+            op: decode_operator(&string(node, "op")?)?,
+            right: Rc::new(decode_expr(&node["right"])?),
+        },
+        "IsNotNull" => Expr::IsNotNull(expr()?),
+        "IsNull" => Expr::IsNull(expr()?),
+        "Not" => Expr::Not(expr()?),
+        "Cast" => Expr::Cast {
+            expr: expr()?,
+            data_type: decode_data_type(&node["data_type"])?,
+        },
+        "Sort" => Expr::Sort {
+            expr: expr()?,
+            asc: boolean(node, "asc")?,
+            nulls_first: boolean(node, "nulls_first")?,
+        },
+        "Case" => Expr::Case {
+            when_then: node["when_then"]
+                .members()
+                .map(|pair| {
+                    Ok((
+                        Rc::new(decode_expr(&pair[0])?),
+                        Rc::new(decode_expr(&pair[1])?),
+                    ))
+                }).collect::<Result<Vec<(Rc<Expr>, Rc<Expr>)>>>()?,
+            else_expr: match node["else_expr"] {
+                JsonValue::Null => None,
+                ref e => Some(Rc::new(decode_expr(e)?)),
+            },
+        },
+        "ScalarFunction" => Expr::ScalarFunction {
+            name: string(node, "name")?,
+            args: decode_exprs(&node["args"])?,
+            return_type: decode_data_type(&node["return_type"])?,
+        },
+        "AggregateFunction" => Expr::AggregateFunction {
+            name: string(node, "name")?,
+            args: decode_exprs(&node["args"])?,
+            return_type: decode_data_type(&node["return_type"])?,
+        },
+        "OuterColumn" => Expr::OuterColumn {
+            index: index(node, "index")?,
+            data_type: decode_data_type(&node["data_type"])?,
+        },
+        "QuantifiedComparison" => Expr::QuantifiedComparison {
+            expr: expr()?,
+            op: decode_operator(&string(node, "op")?)?,
+            all: boolean(node, "all")?,
+            subquery: Subquery {
+                plan: Rc::new(decode_plan(&node["plan"])?),
+                outer_columns: node["outer_columns"]
+                    .members()
+                    .map(|i| {
+                        i.as_usize()
+                            .ok_or_else(|| invalid("outer column is not an index".to_string()))
+                    }).collect::<Result<Vec<usize>>>()?,
+            },
+        },
+        other => return Err(invalid(format!("unknown expression '{}'", other))),
+    })
+}
+
+fn decode_operator(name: &str) -> Result<Operator> {
+    Ok(match name {
+        "Eq" => Operator::Eq,
+        "NotEq" => Operator::NotEq,
+        "Lt" => Operator::Lt,
+        "LtEq" => Operator::LtEq,
+        "Gt" => Operator::Gt,
+        "GtEq" => Operator::GtEq,
+        "Plus" => Operator::Plus,
+        "Minus" => Operator::Minus,
+        "Multiply" => Operator::Multiply,
+        "Divide" => Operator::Divide,
+        "Modulus" => Operator::Modulus,
+        "And" => Operator::And,
+        "Or" => Operator::Or,
+        "Like" => Operator::Like,
+        "NotLike" => Operator::NotLike,
+        "ILike" => Operator::ILike,
+        "NotILike" => Operator::NotILike,
+        other => return Err(invalid(format!("unknown operator '{}'", other))),
+    })
+}
+
+fn encode_window_expr(w: &WindowExpr) -> Result<JsonValue> {
+    let mut node = JsonValue::new_object();
+    node["name"] = w.name.as_str().into();
+    node["args"] = encode_exprs(&w.args)?;
+    node["partition_by"] = encode_exprs(&w.partition_by)?;
+    node["order_by"] = encode_exprs(&w.order_by)?;
+    node["units"] = format!("{:?}", w.frame.units).into();
+    node["start"] = encode_bound(&w.frame.start);
+    node["end"] = encode_bound(&w.frame.end);
+    node["exclusion"] = format!("{:?}", w.frame.exclusion).into();
+    node["ignore_nulls"] = w.ignore_nulls.into();
+    node["return_type"] = encode_data_type(&w.return_type)?;
+    Ok(node)
+}
+
+fn decode_window_expr(node: &JsonValue) -> Result<WindowExpr> {
+    Ok(WindowExpr {
+        name: string(node, "name")?,
+        args: decode_exprs(&node["args"])?,
+        partition_by: decode_exprs(&node["partition_by"])?,
+        order_by: decode_exprs(&node["order_by"])?,
+        frame: WindowFrame {
+            units: match string(node, "units")?.as_str() {
+                "Rows" => WindowFrameUnits::Rows,
+                "Range" => WindowFrameUnits::Range,
+                "Groups" => WindowFrameUnits::Groups,
+                other => return Err(invalid(format!("unknown window frame units '{}'", other))),
+            },
+            start: decode_bound(&node["start"])?,
+            end: decode_bound(&node["end"])?,
+            exclusion: match string(node, "exclusion")?.as_str() {
+                "NoOthers" => WindowFrameExclusion::NoOthers,
+                "CurrentRow" => WindowFrameExclusion::CurrentRow,
+                "Group" => WindowFrameExclusion::Group,
+                "Ties" => WindowFrameExclusion::Ties,
+                other => return Err(invalid(format!("unknown frame exclusion '{}'", other))),
+            },
+        },
+        ignore_nulls: boolean(node, "ignore_nulls")?,
+        return_type: decode_data_type(&node["return_type"])?,
+    })
+}
+
+fn encode_bound(bound: &WindowFrameBound) -> JsonValue {
+    let mut node = JsonValue::new_object();
+    let (name, offset) = match *bound {
+        WindowFrameBound::UnboundedPreceding => ("UnboundedPreceding", None),
+        WindowFrameBound::Preceding(n) => ("Preceding", Some(n)),
+        WindowFrameBound::CurrentRow => ("CurrentRow", None),
+        WindowFrameBound::Following(n) => ("Following", Some(n)),
+        WindowFrameBound::UnboundedFollowing => ("UnboundedFollowing", None),
+    };
+    node["bound"] = name.into();
+    if let Some(n) = offset {
+        node["offset"] = encode_float(n);
+    }
+    node
+}
+
+fn decode_bound(node: &JsonValue) -> Result<WindowFrameBound> {
+    Ok(match string(node, "bound")?.as_str() {
+        "UnboundedPreceding" => WindowFrameBound::UnboundedPreceding,
+        "Preceding" => WindowFrameBound::Preceding(float(node, "offset")?),
+        "CurrentRow" => WindowFrameBound::CurrentRow,
+        "Following" => WindowFrameBound::Following(float(node, "offset")?),
+        "UnboundedFollowing" => WindowFrameBound::UnboundedFollowing,
+        other => return Err(invalid(format!("unknown window frame bound '{}'", other))),
+    })
+}
+
+fn encode_projection(projection: &Option<Vec<usize>>) -> JsonValue {
+    match *projection {
+        Some(ref p) => array(p.iter().map(|&i| i.into()).collect()),
+        None => JsonValue::Null,
+    }
+}
+
+fn decode_projection(node: &JsonValue) -> Result<Option<Vec<usize>>> {
+    if node.is_null() {
+        return Ok(None);
+    }
+    node.members()
+        .map(|i| {
+            i.as_usize()
+                .ok_or_else(|| invalid("projection is not a list of indexes".to_string()))
+        }).collect::<Result<Vec<usize>>>()
+        .map(Some)
+}
+
+fn encode_schema(schema: &Schema) -> Result<JsonValue> {
+    Ok(array(
+        schema
+            .columns()
+            .iter()
+            .map(encode_field)
+            .collect::<Result<Vec<JsonValue>>>()?,
+    ))
+}
+
+fn decode_schema(node: &JsonValue) -> Result<Schema> {
+    if !node.is_array() {
+        return Err(invalid("relation is missing 'schema'".to_string()));
+    }
+    Ok(Schema::new(
+        node.members()
+            .map(decode_field)
+            .collect::<Result<Vec<Field>>>()?,
+    ))
+}
+
+fn encode_field(field: &Field) -> Result<JsonValue> {
+    let mut node = JsonValue::new_object();
+    node["name"] = field.name().as_str().into();
+    node["data_type"] = encode_data_type(field.data_type())?;
+    node["nullable"] = field.is_nullable().into();
+    Ok(node)
+}
+
+fn decode_field(node: &JsonValue) -> Result<Field> {
+    Ok(Field::new(
+        &string(node, "name")?,
+        decode_data_type(&node["data_type"])?,
+        boolean(node, "nullable")?,
+    ))
+}
+
+/// Data types are written as their names, except for structs, which are written as the list
+/// of their fields
+fn encode_data_type(data_type: &DataType) -> Result<JsonValue> {
+    Ok(match *data_type {
+        DataType::Boolean => "Boolean".into(),
+        DataType::Int8 => "Int8".into(),
+        DataType::Int16 => "Int16".into(),
+        DataType::Int32 => "Int32".into(),
+        DataType::Int64 => "Int64".into(),
+        DataType::UInt8 => "UInt8".into(),
+        DataType::UInt16 => "UInt16".into(),
+        DataType::UInt32 => "UInt32".into(),
+        DataType::UInt64 => "UInt64".into(),
+        DataType::Float32 => "Float32".into(),
+        DataType::Float64 => "Float64".into(),
+        DataType::Utf8 => "Utf8".into(),
+        DataType::Struct(ref fields) => array(
+            fields
+                .iter()
+                .map(encode_field)
+                .collect::<Result<Vec<JsonValue>>>()?,
+        ),
+        ref other => {
+            return Err(DataFusionError::Plan(format!(
+                "Plans with values of type {:?} can't be serialized",
+                other
+            )))
+        }
+    })
+}
+
+fn decode_data_type(node: &JsonValue) -> Result<DataType> {
+    if node.is_array() {
+        return Ok(DataType::Struct(
+            node.members()
+                .map(decode_field)
+                .collect::<Result<Vec<Field>>>()?,
+        ));
+    }
+    Ok(match node.as_str() {
+        Some("Boolean") => DataType::Boolean,
+        Some("Int8") => DataType::Int8,
+        Some("Int16") => DataType::Int16,
+        Some("Int32") => DataType::Int32,
+        Some("Int64") => DataType::Int64,
+        Some("UInt8") => DataType::UInt8,
+        Some("UInt16") => DataType::UInt16,
+        Some("UInt32") => DataType::UInt32,
+        Some("UInt64") => DataType::UInt64,
+        Some("Float32") => DataType::Float32,
+        Some("Float64") => DataType::Float64,
+        Some("Utf8") => DataType::Utf8,
+        _ => return Err(invalid(format!("unknown data type {}", node.dump()))),
+    })
+}
+
+fn encode_scalar(value: &ScalarValue) -> JsonValue {
+    let mut node = JsonValue::new_object();
+    let (value_type, v): (&str, JsonValue) = match *value {
+        ScalarValue::Null => ("Null", JsonValue::Null),
+        ScalarValue::Boolean(b) => ("Boolean", b.into()),
+        ScalarValue::Float32(f) => ("Float32", encode_float(f64::from(f))),
+        ScalarValue::Float64(f) => ("Float64", encode_float(f)),
+        ScalarValue::Int8(n) => ("Int8", i64::from(n).into()),
+        ScalarValue::Int16(n) => ("Int16", i64::from(n).into()),
+        ScalarValue::Int32(n) => ("Int32", i64::from(n).into()),
+        ScalarValue::Int64(n) => ("Int64", n.into()),
+        ScalarValue::UInt8(n) => ("UInt8", u64::from(n).into()),
+        ScalarValue::UInt16(n) => ("UInt16", u64::from(n).into()),
+        ScalarValue::UInt32(n) => ("UInt32", u64::from(n).into()),
+        ScalarValue::UInt64(n) => ("UInt64", n.into()),
+        ScalarValue::Utf8(ref s) => ("Utf8", s.as_str().into()),
+        ScalarValue::Struct(ref values) => {
+            ("Struct", array(values.iter().map(encode_scalar).collect()))
+        }
+    };
+    node["type"] = value_type.into();
+    node["value"] = v;
+    node
+}
+
+fn decode_scalar(node: &JsonValue) -> Result<ScalarValue> {
+    // integers are range checked by converting them back to the width they were written from
+    let signed = || {
+        node["value"]
+            .as_i64()
+            .ok_or_else(|| invalid(format!("literal {} is not an integer", node.dump())))
+    };
+    let unsigned = || {
+        node["value"]
+            .as_u64()
+            .ok_or_else(|| invalid(format!("literal {} is not an integer", node.dump())))
+    };
+    let out_of_range = || invalid(format!("literal {} is out of range", node.dump()));
+    Ok(match string(node, "type")?.as_str() {
+        "Null" => ScalarValue::Null,
+        "Boolean" => ScalarValue::Boolean(boolean(node, "value")?),
+        "Float32" => ScalarValue::Float32(float(node, "value")? as f32),
+        "Float64" => ScalarValue::Float64(float(node, "value")?),
+        "Int8" => {
+            let n = signed()?;
+            if i64::from(n as i8) != n {
+                return Err(out_of_range());
+            }
+            ScalarValue::Int8(n as i8)
+        }
+        "Int16" => {
+            let n = signed()?;
+            if i64::from(n as i16) != n {
+                return Err(out_of_range());
+            }
+            ScalarValue::Int16(n as i16)
+        }
+        "Int32" => {
+            let n = signed()?;
+            if i64::from(n as i32) != n {
+                return Err(out_of_range());
+            }
+            ScalarValue::Int32(n as i32)
+        }
+        "Int64" => ScalarValue::Int64(signed()?),
+        "UInt8" => {
+            let n = unsigned()?;
+            if u64::from(n as u8) != n {
+                return Err(out_of_range());
+            }
+            ScalarValue::UInt8(n as u8)
+        }
+        "UInt16" => {
+            let n = unsigned()?;
+            if u64::from(n as u16) != n {
+                return Err(out_of_range());
+            }
+            ScalarValue::UInt16(n as u16)
+        }
+        "UInt32" => {
+            let n = unsigned()?;
+            if u64::from(n as u32) != n {
+                return Err(out_of_range());
+            }
+            ScalarValue::UInt32(n as u32)
+        }
+        "UInt64" => ScalarValue::UInt64(unsigned()?),
+        "Utf8" => ScalarValue::Utf8(Rc::new(string(node, "value")?)),
+        "Struct" => ScalarValue::Struct(
+            node["value"]
+                .members()
+                .map(decode_scalar)
+                .collect::<Result<Vec<ScalarValue>>>()?,
+        ),
+        other => return Err(invalid(format!("unknown literal type '{}'", other))),
+    })
+}
+
+/// Floats are written with `Debug`, which prints the shortest string that parses back to the
+/// same value, including for infinities and NaN, which JSON numbers can't represent
+fn encode_float(f: f64) -> JsonValue {
+    format!("{:?}", f).into()
+}
+
+fn float(node: &JsonValue, name: &str) -> Result<f64> {
+    string(node, name)?
+        .parse::<f64>()
+        .map_err(|_| invalid(format!("'{}' is not a number", name)))
+}
+
+fn string(node: &JsonValue, name: &str) -> Result<String> {
+    match node[name].as_str() {
+        Some(s) => Ok(s.to_string()),
+        None => Err(invalid(format!("'{}' is missing or not a string", name))),
+    }
+}
+
+fn boolean(node: &JsonValue, name: &str) -> Result<bool> {
+    node[name]
+        .as_bool()
+        .ok_or_else(|| invalid(format!("'{}' is missing or not a boolean", name)))
+}
+
+fn int(node: &JsonValue, name: &str) -> Result<i64> {
+    node[name]
+        .as_i64()
+        .ok_or_else(|| invalid(format!("'{}' is missing or not an integer", name)))
+}
+
+fn index(node: &JsonValue, name: &str) -> Result<usize> {
+    node[name]
+        .as_usize()
+        .ok_or_else(|| invalid(format!("'{}' is missing or not an index", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::exec::*;
+    use super::*;
+
+    fn roundtrip(plan: &LogicalPlan) {
+        let text = serialize_plan(plan).unwrap();
+        let decoded = deserialize_plan(&text).unwrap();
+        assert_eq!(format!("{:?}", plan), format!("{:?}", decoded));
+        assert_eq!(text, serialize_plan(&decoded).unwrap());
+    }
+
+    #[test]
+    fn test_roundtrip_sql_plans() {
+        let mut ctx = ExecutionContext::local();
+        let people = ctx
+            .load_csv(
+                "./test/data/people.csv",
+                &Schema::new(vec![
+                    Field::new("id", DataType::Int32, false),
+                    Field::new("name", DataType::Utf8, false),
+                ]),
+                true,
+                None,
+            ).unwrap();
+        ctx.register("people", people);
+        for sql in &[
+            "SELECT id, name FROM people WHERE id > 2 AND name LIKE 'A%' ORDER BY id DESC LIMIT 3",
+            "SELECT name, COUNT(id) FROM people GROUP BY name",
+            "SELECT id, ROW_NUMBER() OVER (ORDER BY id) FROM people",
+            "SELECT CASE WHEN id < 3 THEN 'low' ELSE 'high' END FROM people",
+            "SELECT DISTINCT name FROM people",
+        ] {
+            roundtrip(ctx.sql(sql).unwrap().plan());
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_values() {
+        let schema = Rc::new(Schema::new(vec![Field::new("x", DataType::Float64, true)]));
+        let plan = LogicalPlan::Projection {
+            expr: vec![
+                Expr::Literal(ScalarValue::Float64(0.1)),
+                Expr::Literal(ScalarValue::Float32(::std::f32::NAN)),
+                Expr::Literal(ScalarValue::UInt64(::std::u64::MAX)),
+                Expr::Literal(ScalarValue::Int8(-128)),
+                Expr::Literal(ScalarValue::Null),
+            ],
+            input: Rc::new(LogicalPlan::EmptyRelation {
+                schema: schema.clone(),
+            }),
+            schema,
+        };
+        roundtrip(&plan);
+    }
+
+    #[test]
+    fn test_invalid_plans() {
+        assert!(deserialize_plan("not json").is_err());
+        assert!(deserialize_plan("{\"node_type\": \"Unknown\"}").is_err());
+        // integers must fit the type of the literal
+        let plan = "{\"node_type\": \"Selection\", \"expr\": {\"expr_type\": \"Literal\", \
+                    \"value\": {\"type\": \"Int8\", \"value\": 300}}, \
+                    \"input\": {\"node_type\": \"EmptyRelation\", \"schema\": []}}";
+        match deserialize_plan(plan) {
+            Err(DataFusionError::Plan(msg)) => assert!(msg.contains("out of range"), "{}", msg),
+            _ => panic!("Expected a plan error"),
+        }
+    }
+}
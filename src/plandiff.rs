@@ -0,0 +1,747 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tools for detecting changes to logical plans
+//!
+//! Fingerprints only contain the structure of a plan (node types, attributes and expressions)
+//! so they can be stored in test suites as snapshots of the expected plan for a query. They
+//! are written in a textual form of their own, rather than with the `Debug` formatting of
+//! expressions and types, and start with the version of that form. Files are identified by
+//! their names without their directories, so that fingerprints don't depend on where the data
+//! is stored.
+
+use std::fmt;
+use std::path::Path;
+
+use json::JsonValue;
+
+use super::errors::*;
+use super::functions::hash::xxhash64;
+use super::logical::*;
+use super::types::ScalarValue;
+
+use arrow::datatypes::*;
+
+/// Version of the textual form of fingerprints, which is increased whenever the form of a
+/// node, expression or type changes, so that fingerprints stored with an older version can be
+/// recognized
+pub const FINGERPRINT_VERSION: u32 = 2;
+
+/// Produce a stable textual representation of a plan, with a line for the version of the
+/// form followed by one line per node. Plans with values of types that have no textual form
+/// don't have a fingerprint.
+pub fn plan_fingerprint(plan: &LogicalPlan) -> Result<String> {
+    let mut lines = vec![format!("v{}", FINGERPRINT_VERSION)];
+    fingerprint_node(&PlanNode::new(plan)?, 0, &mut lines);
+    Ok(lines.join("\n"))
+}
+
+/// Produce a 64-bit hash of the plan fingerprint
+pub fn plan_fingerprint_hash(plan: &LogicalPlan) -> Result<u64> {
+    Ok(xxhash64(plan_fingerprint(plan)?.as_bytes(), 0))
+}
+
+fn fingerprint_node(node: &PlanNode, indent: usize, lines: &mut Vec<String>) {
+    lines.push(format!("{}{}", "  ".repeat(indent), node.describe()));
+    for input in &node.inputs {
+        fingerprint_node(input, indent + 1, lines);
+    }
+}
+
+/// A plan node in the form that it is fingerprinted and compared in
+struct PlanNode {
+    node_type: &'static str,
+    /// Attributes in textual form, sorted by name
+    attributes: Vec<(&'static str, String)>,
+    inputs: Vec<PlanNode>,
+}
+
+impl PlanNode {
+    fn new(plan: &LogicalPlan) -> Result<Self> {
+        let mut attributes = vec![];
+        let mut inputs: Vec<&LogicalPlan> = vec![];
+        let node_type = match *plan {
+            LogicalPlan::Limit {
+                limit, ref input, ..
+            } => {
+                attributes.push(("limit", limit.to_string()));
+                inputs.push(input);
+                "Limit"
+            }
+            LogicalPlan::Projection {
+                ref expr,
+                ref input,
+                ..
+            } => {
+                attributes.push(("expr", exprs(expr)?));
+                inputs.push(input);
+                "Projection"
+            }
+            LogicalPlan::Selection {
+                ref expr,
+                ref input,
+            } => {
+                attributes.push(("expr", expr_text(expr)?));
+                inputs.push(input);
+                "Selection"
+            }
+            LogicalPlan::Aggregate {
+                ref input,
+                ref group_expr,
+                ref aggr_expr,
+                ..
+            } => {
+                attributes.push(("group_expr", exprs(group_expr)?));
+                attributes.push(("aggr_expr", exprs(aggr_expr)?));
+                inputs.push(input);
+                "Aggregate"
+            }
+            LogicalPlan::Sort {
+                ref expr,
+                ref input,
+                ..
+            } => {
+                attributes.push(("expr", exprs(expr)?));
+                inputs.push(input);
+                "Sort"
+            }
+            LogicalPlan::Window {
+                ref input,
+                ref window_expr,
+                ..
+            } => {
+                let window_expr = window_expr
+                    .iter()
+                    .map(window_expr_text)
+                    .collect::<Result<Vec<String>>>()?;
+                attributes.push(("window_expr", format!("[{}]", window_expr.join(", "))));
+                inputs.push(input);
+                "Window"
+            }
+            LogicalPlan::Sample {
+                ref method,
+                fraction,
+                seed,
+                ref input,
+                ..
+            } => {
+                let method = match *method {
+                    SampleMethod::Bernoulli => "BERNOULLI",
+                    SampleMethod::System => "SYSTEM",
+                };
+                attributes.push(("method", method.to_string()));
+                attributes.push(("fraction", fraction.to_string()));
+                attributes.push((
+                    "seed",
+                    seed.map(|s| s.to_string()).unwrap_or_else(|| "none".to_string()),
+                ));
+                inputs.push(input);
+                "Sample"
+            }
+            LogicalPlan::Deduplicate {
+                ref input,
+                ref keys,
+                ref order_by,
+                ref keep,
+                ..
+            } => {
+                let keep = match *keep {
+                    DeduplicateKeep::First => "FIRST",
+                    DeduplicateKeep::Last => "LAST",
+                };
+                attributes.push(("keys", exprs(keys)?));
+                attributes.push(("order_by", exprs(order_by)?));
+                attributes.push(("keep", keep.to_string()));
+                inputs.push(input);
+                "Deduplicate"
+            }
+            LogicalPlan::Join {
+                ref left,
+                ref right,
+                ref join_type,
+                ref on,
+                ref null_equals_null,
+                ref build_side,
+                ..
+            } => {
+                let join_type = match *join_type {
+                    JoinType::Inner => "INNER",
+                    JoinType::LeftSemi => "LEFT SEMI",
+                    JoinType::LeftAnti => "LEFT ANTI",
+                };
+                let build_side = match *build_side {
+                    Some(BuildSide::Left) => "LEFT",
+                    Some(BuildSide::Right) => "RIGHT",
+                    None => "none",
+                };
+                let on: Vec<String> = on
+                    .iter()
+                    .zip(null_equals_null)
+                    .map(|(&(l, r), &null_equal)| {
+                        let operator = if null_equal { "IS NOT DISTINCT FROM" } else { "=" };
+                        format!("#{} {} #{}", l, operator, r)
+                    }).collect();
+                attributes.push(("join_type", join_type.to_string()));
+                attributes.push(("on", format!("[{}]", on.join(", "))));
+                attributes.push(("build_side", build_side.to_string()));
+                inputs.push(left);
+                inputs.push(right);
+                "Join"
+            }
+            LogicalPlan::LateralJoin {
+                ref left,
+                ref subquery,
+                left_outer,
+                correlated,
+                ..
+            } => {
+                attributes.push(("left_outer", left_outer.to_string()));
+                attributes.push(("correlated", correlated.to_string()));
+                inputs.push(left);
+                inputs.push(subquery);
+                "LateralJoin"
+            }
+            LogicalPlan::Union {
+                inputs: ref union_inputs,
+                ..
+            } => {
+                inputs.extend(union_inputs.iter().map(|p| p.as_ref()));
+                "Union"
+            }
+            LogicalPlan::TableScan {
+                ref table_name,
+                ref projection,
+                ..
+            } => {
+                attributes.push(("table_name", quote(table_name)));
+                attributes.push(("projection", projection_text(projection)));
+                "TableScan"
+            }
+            LogicalPlan::CsvFile {
+                ref filename,
+                has_header,
+                ref projection,
+                ..
+            } => {
+                attributes.push(("file", file_name(filename)));
+                attributes.push(("has_header", has_header.to_string()));
+                attributes.push(("projection", projection_text(projection)));
+                "CsvFile"
+            }
+            LogicalPlan::NdJsonFile {
+                ref filename,
+                ref projection,
+                ..
+            } => {
+                attributes.push(("file", file_name(filename)));
+                attributes.push(("projection", projection_text(projection)));
+                "NdJsonFile"
+            }
+            LogicalPlan::ParquetFile {
+                ref filename,
+                ref projection,
+                ..
+            } => {
+                attributes.push(("file", file_name(filename)));
+                attributes.push(("projection", projection_text(projection)));
+                "ParquetFile"
+            }
+            LogicalPlan::EmptyRelation { .. } => "EmptyRelation",
+            LogicalPlan::MemTable { ref batches, .. } => {
+                let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+                attributes.push(("rows", rows.to_string()));
+                "MemTable"
+            }
+            LogicalPlan::StreamScan {
+                ref stream_name, ..
+            } => {
+                attributes.push(("stream_name", quote(stream_name)));
+                "StreamScan"
+            }
+            LogicalPlan::GenerateSeries {
+                start,
+                stop,
+                step,
+                inclusive,
+                ..
+            } => {
+                attributes.push(("start", start.to_string()));
+                attributes.push(("stop", stop.to_string()));
+                attributes.push(("step", step.to_string()));
+                attributes.push(("inclusive", inclusive.to_string()));
+                "GenerateSeries"
+            }
+        };
+        // a selection has the schema of its input
+        match *plan {
+            LogicalPlan::Selection { .. } => {}
+            _ => attributes.push(("schema", schema_text(plan.schema())?)),
+        }
+        attributes.sort_by(|a, b| a.0.cmp(b.0));
+        Ok(PlanNode {
+            node_type,
+            attributes,
+            inputs: inputs
+                .into_iter()
+                .map(PlanNode::new)
+                .collect::<Result<Vec<PlanNode>>>()?,
+        })
+    }
+
+    /// Get the node type and attributes of the node, e.g. `Limit(limit=10, schema=[...])`
+    fn describe(&self) -> String {
+        let attributes: Vec<String> = self
+            .attributes
+            .iter()
+            .map(|&(k, ref v)| format!("{}={}", k, v))
+            .collect();
+        format!("{}({})", self.node_type, attributes.join(", "))
+    }
+
+    /// Get the node and its inputs on one line, for plans of subqueries in expressions
+    fn describe_tree(&self) -> String {
+        if self.inputs.is_empty() {
+            return self.describe();
+        }
+        let inputs: Vec<String> = self.inputs.iter().map(|i| i.describe_tree()).collect();
+        format!("{}[{}]", self.describe(), inputs.join(", "))
+    }
+
+    fn attribute(&self, name: &str) -> Option<&String> {
+        self.attributes
+            .iter()
+            .find(|&&(k, _)| k == name)
+            .map(|&(_, ref v)| v)
+    }
+}
+
+fn quote(s: &str) -> String {
+    JsonValue::from(s).dump()
+}
+
+/// Get the name of a file without its directory
+fn file_name(filename: &str) -> String {
+    let name = Path::new(filename)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| filename.to_string());
+    quote(&name)
+}
+
+fn projection_text(projection: &Option<Vec<usize>>) -> String {
+    match *projection {
+        Some(ref p) => format!(
+            "[{}]",
+            p.iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        None => "all".to_string(),
+    }
+}
+
+fn schema_text(schema: &Schema) -> Result<String> {
+    let fields = schema
+        .columns()
+        .iter()
+        .map(field_text)
+        .collect::<Result<Vec<String>>>()?;
+    Ok(format!("[{}]", fields.join(", ")))
+}
+
+fn field_text(field: &Field) -> Result<String> {
+    Ok(format!(
+        "{}: {}{}",
+        quote(field.name()),
+        data_type_text(field.data_type())?,
+        if field.is_nullable() { "" } else { " NOT NULL" }
+    ))
+}
+
+fn data_type_text(data_type: &DataType) -> Result<String> {
+    Ok(match *data_type {
+        DataType::Boolean => "Boolean".to_string(),
+        DataType::Int8 => "Int8".to_string(),
+        DataType::Int16 => "Int16".to_string(),
+        DataType::Int32 => "Int32".to_string(),
+        DataType::Int64 => "Int64".to_string(),
+        DataType::UInt8 => "UInt8".to_string(),
+        DataType::UInt16 => "UInt16".to_string(),
+        DataType::UInt32 => "UInt32".to_string(),
+        DataType::UInt64 => "UInt64".to_string(),
+        DataType::Float16 => "Float16".to_string(),
+        DataType::Float32 => "Float32".to_string(),
+        DataType::Float64 => "Float64".to_string(),
+        DataType::Utf8 => "Utf8".to_string(),
+        DataType::Struct(ref fields) => format!(
+            "Struct<{}>",
+            fields
+                .iter()
+                .map(field_text)
+                .collect::<Result<Vec<String>>>()?
+                .join(", ")
+        ),
+        ref other => {
+            return Err(DataFusionError::Plan(format!(
+                "Plans with values of type {:?} have no fingerprint",
+                other
+            )))
+        }
+    })
+}
+
+fn scalar_text(value: &ScalarValue) -> String {
+    match *value {
+        ScalarValue::Null => "NULL".to_string(),
+        ScalarValue::Boolean(b) => format!("Boolean({})", b),
+        ScalarValue::Float32(f) => format!("Float32({})", f),
+        ScalarValue::Float64(f) => format!("Float64({})", f),
+        ScalarValue::Int8(n) => format!("Int8({})", n),
+        ScalarValue::Int16(n) => format!("Int16({})", n),
+        ScalarValue::Int32(n) => format!("Int32({})", n),
+        ScalarValue::Int64(n) => format!("Int64({})", n),
+        ScalarValue::UInt8(n) => format!("UInt8({})", n),
+        ScalarValue::UInt16(n) => format!("UInt16({})", n),
+        ScalarValue::UInt32(n) => format!("UInt32({})", n),
+        ScalarValue::UInt64(n) => format!("UInt64({})", n),
+        ScalarValue::Utf8(ref s) => format!("Utf8({})", quote(s)),
+        ScalarValue::Struct(ref values) => format!(
+            "Struct({})",
+            values
+                .iter()
+                .map(scalar_text)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn operator_text(op: &Operator) -> &'static str {
+    match *op {
+        Operator::Eq => "=",
+        Operator::NotEq => "!=",
+        Operator::Lt => "<",
+        Operator::LtEq => "<=",
+        Operator::Gt => ">",
+        Operator::GtEq => ">=",
+        Operator::Plus => "+",
+        Operator::Minus => "-",
+        Operator::Multiply => "*",
+        Operator::Divide => "/",
+        Operator::Modulus => "%",
+        Operator::And => "AND",
+        Operator::Or => "OR",
+        Operator::Like => "LIKE",
+        Operator::NotLike => "NOT LIKE",
+        Operator::ILike => "ILIKE",
+        Operator::NotILike => "NOT ILIKE",
+    }
+}
+
+fn exprs(expr: &[Expr]) -> Result<String> {
+    Ok(format!("[{}]", expr_list(expr)?))
+}
+
+fn expr_list(expr: &[Expr]) -> Result<String> {
+    Ok(expr
+        .iter()
+        .map(expr_text)
+        .collect::<Result<Vec<String>>>()?
+        .join(", "))
+}
+
+fn expr_text(expr: &Expr) -> Result<String> {
+    Ok(match *expr {
+        Expr::Column(i) => format!("#{}", i),
+        Expr::OuterColumn {
+            index,
+            ref data_type,
+        } => format!("outer#{}: {}", index, data_type_text(data_type)?),
+        Expr::Literal(ref value) => scalar_text(value),
+        Expr::BinaryExpr {
+            ref left,
+            ref op,
+            ref right,
+        } => format!(
+            "({} {} {})",
+            expr_text(left)?,
+            operator_text(op),
+            expr_text(right)?
+        ),
+        Expr::IsNotNull(ref e) => format!("({} IS NOT NULL)", expr_text(e)?),
+        Expr::IsNull(ref e) => format!("({} IS NULL)", expr_text(e)?),
+        Expr::Not(ref e) => format!("(NOT {})", expr_text(e)?),
+        Expr::Cast {
+            ref expr,
+            ref data_type,
+        } => format!("CAST({} AS {})", expr_text(expr)?, data_type_text(data_type)?),
+        Expr::Sort {
+            ref expr,
+            asc,
+            nulls_first,
+        } => format!(
+            "{} {} NULLS {}",
+            expr_text(expr)?,
+            if asc { "ASC" } else { "DESC" },
+            if nulls_first { "FIRST" } else { "LAST" }
+        ),
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            let mut text = "CASE".to_string();
+            for &(ref when, ref then) in when_then {
+                text.push_str(&format!(" WHEN {} THEN {}", expr_text(when)?, expr_text(then)?));
+            }
+            if let Some(ref e) = *else_expr {
+                text.push_str(&format!(" ELSE {}", expr_text(e)?));
+            }
+            text.push_str(" END");
+            text
+        }
+        Expr::ScalarFunction {
+            ref name,
+            ref args,
+            ref return_type,
+        } => format!(
+            "{}({}): {}",
+            name,
+            expr_list(args)?,
+            data_type_text(return_type)?
+        ),
+        Expr::AggregateFunction {
+            ref name,
+            ref args,
+            ref return_type,
+        } => format!(
+            "AGGREGATE {}({}): {}",
+            name,
+            expr_list(args)?,
+            data_type_text(return_type)?
+        ),
+        Expr::QuantifiedComparison {
+            ref expr,
+            ref op,
+            all,
+            ref subquery,
+        } => format!(
+            "({} {} {} ({}))",
+            expr_text(expr)?,
+            operator_text(op),
+            if all { "ALL" } else { "ANY" },
+            PlanNode::new(&subquery.plan)?.describe_tree()
+        ),
+    })
+}
+
+fn window_expr_text(w: &WindowExpr) -> Result<String> {
+    let units = match w.frame.units {
+        WindowFrameUnits::Rows => "ROWS",
+        WindowFrameUnits::Range => "RANGE",
+        WindowFrameUnits::Groups => "GROUPS",
+    };
+    let exclusion = match w.frame.exclusion {
+        WindowFrameExclusion::NoOthers => "",
+        WindowFrameExclusion::CurrentRow => " EXCLUDE CURRENT ROW",
+        WindowFrameExclusion::Group => " EXCLUDE GROUP",
+        WindowFrameExclusion::Ties => " EXCLUDE TIES",
+    };
+    Ok(format!(
+        "{}({}){} OVER (PARTITION BY [{}] ORDER BY [{}] {} BETWEEN {} AND {}{}): {}",
+        w.name,
+        expr_list(&w.args)?,
+        if w.ignore_nulls { " IGNORE NULLS" } else { "" },
+        expr_list(&w.partition_by)?,
+        expr_list(&w.order_by)?,
+        units,
+        w.frame.start,
+        w.frame.end,
+        exclusion,
+        data_type_text(&w.return_type)?
+    ))
+}
+
+/// A structural difference between two plans
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanDifference {
+    /// Location of the node in the plan, e.g. `Limit/Projection`
+    pub path: String,
+    /// Description of what changed at this node
+    pub description: String,
+}
+
+impl fmt::Display for PlanDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.description)
+    }
+}
+
+/// Compare two plans, returning the structural differences between them. An empty result
+/// means the plans have the same fingerprint.
+pub fn compare_plans(a: &LogicalPlan, b: &LogicalPlan) -> Result<Vec<PlanDifference>> {
+    let mut differences = vec![];
+    compare_nodes(&PlanNode::new(a)?, &PlanNode::new(b)?, "", &mut differences);
+    Ok(differences)
+}
+
+fn compare_nodes(a: &PlanNode, b: &PlanNode, parent: &str, diffs: &mut Vec<PlanDifference>) {
+    let path = if parent.is_empty() {
+        a.node_type.to_string()
+    } else {
+        format!("{}/{}", parent, a.node_type)
+    };
+
+    if a.node_type != b.node_type {
+        diffs.push(PlanDifference {
+            path,
+            description: format!("node type {} became {}", a.node_type, b.node_type),
+        });
+        // the children of different node types are not comparable
+        return;
+    }
+
+    for &(k, ref v) in &a.attributes {
+        match b.attribute(k) {
+            Some(b_value) if b_value == v => {}
+            Some(b_value) => diffs.push(PlanDifference {
+                path: path.clone(),
+                description: format!("{} changed from {} to {}", k, v, b_value),
+            }),
+            None => diffs.push(PlanDifference {
+                path: path.clone(),
+                description: format!("{} was removed", k),
+            }),
+        }
+    }
+    for &(k, ref v) in &b.attributes {
+        if a.attribute(k).is_none() {
+            diffs.push(PlanDifference {
+                path: path.clone(),
+                description: format!("{} was added with value {}", k, v),
+            });
+        }
+    }
+
+    if a.inputs.len() != b.inputs.len() {
+        diffs.push(PlanDifference {
+            path: path.clone(),
+            description: format!(
+                "number of inputs changed from {} to {}",
+                a.inputs.len(),
+                b.inputs.len()
+            ),
+        });
+    }
+    for (a_input, b_input) in a.inputs.iter().zip(b.inputs.iter()) {
+        compare_nodes(a_input, b_input, &path, diffs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::super::logical::*;
+    use super::super::types::ScalarValue;
+    use super::*;
+
+    use arrow::datatypes::*;
+
+    fn scan(projection: Option<Vec<usize>>) -> Rc<LogicalPlan> {
+        Rc::new(LogicalPlan::TableScan {
+            schema_name: "default".to_string(),
+            table_name: "people".to_string(),
+            schema: Rc::new(Schema::new(vec![
+                Field::new("id", DataType::Int32, false),
+                Field::new("name", DataType::Utf8, false),
+            ])),
+            projection,
+        })
+    }
+
+    fn limit(n: usize, input: Rc<LogicalPlan>) -> LogicalPlan {
+        let schema = input.schema().clone();
+        LogicalPlan::Limit {
+            limit: n,
+            input,
+            schema,
+        }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let plan = limit(10, scan(None));
+        assert_eq!(
+            "v2\n\
+             Limit(limit=10, schema=[\"id\": Int32 NOT NULL, \"name\": Utf8 NOT NULL])\n  \
+             TableScan(projection=all, schema=[\"id\": Int32 NOT NULL, \"name\": Utf8 NOT NULL], \
+             table_name=\"people\")",
+            plan_fingerprint(&plan).unwrap()
+        );
+        assert_eq!(
+            plan_fingerprint_hash(&plan).unwrap(),
+            plan_fingerprint_hash(&limit(10, scan(None))).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_expressions() {
+        let input = scan(None);
+        let plan = LogicalPlan::Selection {
+            expr: Expr::BinaryExpr {
+                left: Rc::new(Expr::Column(1)),
+                op: Operator::Eq,
+                right: Rc::new(Expr::Literal(ScalarValue::Utf8(Rc::new("a\"b".to_string())))),
+            },
+            input,
+        };
+        assert!(
+            plan_fingerprint(&plan)
+                .unwrap()
+                .contains("Selection(expr=(#1 = Utf8(\"a\\\"b\")))")
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_excludes_directories() {
+        let csv = |filename: &str| LogicalPlan::CsvFile {
+            filename: filename.to_string(),
+            schema: Rc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)])),
+            has_header: true,
+            projection: None,
+        };
+        let fingerprint = plan_fingerprint(&csv("/home/alice/data/people.csv")).unwrap();
+        assert!(fingerprint.contains("file=\"people.csv\""));
+        assert!(!fingerprint.contains("alice"));
+        assert_eq!(
+            fingerprint,
+            plan_fingerprint(&csv("../test/data/people.csv")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compare_plans() {
+        let a = limit(10, scan(None));
+        assert!(compare_plans(&a, &limit(10, scan(None))).unwrap().is_empty());
+
+        let diffs = compare_plans(&a, &limit(5, scan(Some(vec![0])))).unwrap();
+        assert_eq!(2, diffs.len());
+        assert_eq!("Limit: limit changed from 10 to 5", diffs[0].to_string());
+        assert_eq!(
+            "Limit/TableScan: projection changed from all to [0]",
+            diffs[1].to_string()
+        );
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Progress reporting for queries. The files that a query reads are sized when it is planned,
+//! and each file scan reports how far through its file it has read after every batch.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::rc::Rc;
+
+use arrow::datatypes::Schema;
+
+use super::errors::*;
+use super::exec::*;
+use super::types::*;
+
+/// How far the current query, or the last query to run, has got through its input files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Progress {
+    pub bytes_scanned: u64,
+    /// The total size of the files that the query reads
+    pub bytes_total: u64,
+    pub rows_scanned: usize,
+    pub files_completed: usize,
+    pub files_total: usize,
+}
+
+impl Progress {
+    /// Get the fraction of the input that has been read, if the size of the input is known
+    pub fn fraction(&self) -> Option<f64> {
+        if self.bytes_total == 0 {
+            None
+        } else {
+            Some((self.bytes_scanned as f64 / self.bytes_total as f64).min(1.0))
+        }
+    }
+}
+
+/// Called with the latest progress after each batch is read from a file
+pub type ProgressCallback = Rc<Fn(&Progress)>;
+
+/// Progress that is shared by the file scans of a query
+pub struct ProgressTracker {
+    progress: RefCell<Progress>,
+    callback: RefCell<Option<ProgressCallback>>,
+}
+
+impl ProgressTracker {
+    pub fn new() -> Self {
+        ProgressTracker {
+            progress: RefCell::new(Progress::default()),
+            callback: RefCell::new(None),
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.progress.borrow().clone()
+    }
+
+    pub fn set_callback(&self, callback: Option<ProgressCallback>) {
+        *self.callback.borrow_mut() = callback;
+    }
+
+    /// Start tracking a query that reads files with the given sizes
+    pub fn start(&self, file_sizes: &[u64]) {
+        *self.progress.borrow_mut() = Progress {
+            bytes_total: file_sizes.iter().sum(),
+            files_total: file_sizes.len(),
+            ..Progress::default()
+        };
+    }
+
+    fn update<F: FnOnce(&mut Progress)>(&self, f: F) {
+        f(&mut self.progress.borrow_mut());
+        let callback = self.callback.borrow().clone();
+        if let Some(callback) = callback {
+            callback(&self.progress.borrow());
+        }
+    }
+}
+
+/// Reports the progress of a file scan. The position of the file is read from a handle that
+/// shares its offset with the handle the data source reads from.
+pub struct ScanProgressRelation {
+    input: Box<SimpleRelation>,
+    tracker: Rc<ProgressTracker>,
+    file: Option<File>,
+    size: u64,
+}
+
+impl ScanProgressRelation {
+    pub fn new(
+        input: Box<SimpleRelation>,
+        tracker: Rc<ProgressTracker>,
+        file: Option<File>,
+        size: u64,
+    ) -> Self {
+        ScanProgressRelation {
+            input,
+            tracker,
+            file,
+            size,
+        }
+    }
+}
+
+impl SimpleRelation for ScanProgressRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        Box::new(ScanProgressIterator {
+            input: self.input.scan(),
+            tracker: self.tracker.clone(),
+            file: self.file.as_mut(),
+            size: self.size,
+            reported: 0,
+            done: false,
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+struct ScanProgressIterator<'a> {
+    input: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    tracker: Rc<ProgressTracker>,
+    file: Option<&'a mut File>,
+    size: u64,
+    /// Bytes of this file already added to the progress
+    reported: u64,
+    done: bool,
+}
+
+impl<'a> Iterator for ScanProgressIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.input.next();
+        match next {
+            Some(Ok(ref batch)) => {
+                // data sources read ahead, so the position can be past the rows returned so far
+                let position = match self.file {
+                    Some(ref mut f) => f.seek(SeekFrom::Current(0)).unwrap_or(self.reported),
+                    None => self.reported,
+                }.min(self.size);
+                let delta = position.saturating_sub(self.reported);
+                self.reported += delta;
+                self.tracker.update(|p| {
+                    p.bytes_scanned += delta;
+                    p.rows_scanned += batch.num_rows();
+                });
+            }
+            None if !self.done => {
+                self.done = true;
+                let delta = self.size.saturating_sub(self.reported);
+                self.reported = self.size;
+                self.tracker.update(|p| {
+                    p.bytes_scanned += delta;
+                    p.files_completed += 1;
+                });
+            }
+            _ => {}
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_fraction() {
+        let tracker = ProgressTracker::new();
+        assert_eq!(None, tracker.progress().fraction());
+        tracker.start(&[100, 300]);
+        tracker.update(|p| p.bytes_scanned += 100);
+        assert_eq!(Some(0.25), tracker.progress().fraction());
+        assert_eq!(2, tracker.progress().files_total);
+    }
+}
@@ -13,32 +13,43 @@
 // limitations under the License.
 
 //! Aggregate / Grouping Relation
+//!
+//! When the groups don't fit in the memory quota of the session's resource group, the rows of
+//! groups that aren't in the hash table yet are spilled to files partitioned by the hash of
+//! their group keys. The groups in memory are output once the input has been read, and then
+//! each partition is aggregated in turn.
 
 use std::cell::RefCell;
+use std::iter;
 use std::rc::Rc;
-use std::str;
 
 use super::super::datasources::common::*;
 use super::super::errors::*;
 use super::super::exec::*;
+use super::super::functions::common::utf8_value;
 use super::super::functions::count::CountFunction;
 use super::super::functions::max::MaxFunction;
 use super::super::functions::min::MinFunction;
 use super::super::functions::sum::SumFunction;
+use super::super::resource::MemoryReservation;
+use super::super::spill::{SpillFile, SpillManager, SpillPartitions};
 use super::super::types::*;
+use super::row_hash::{hash_rows, HashedKey, RowHashMap};
 
 use arrow::array::ListArray;
 use arrow::builder::*;
 use arrow::datatypes::*;
 use arrow::list_builder::*;
 
-use fnv::FnvHashMap;
-
 pub struct AggregateRelation {
     schema: Rc<Schema>,
     input: Box<SimpleRelation>,
     group_expr: Vec<RuntimeExpr>,
     aggr_expr: Vec<RuntimeExpr>,
+    /// Memory of a resource group that the groups of the hash table are counted against
+    reservation: Option<MemoryReservation>,
+    /// Manager of the files that rows are spilled to once the reservation can't grow
+    spill_manager: Option<Rc<SpillManager>>,
 }
 
 struct AggregateEntry {
@@ -57,13 +68,41 @@ impl AggregateRelation {
             input,
             group_expr,
             aggr_expr,
+            reservation: None,
+            spill_manager: None,
         }
     }
+
+    /// Count the groups of the hash table against the memory quota of a resource group
+    pub fn with_memory_reservation(mut self, reservation: MemoryReservation) -> Self {
+        self.reservation = Some(reservation);
+        self
+    }
+
+    /// Spill the rows of new groups to files of the spill manager once the memory reservation
+    /// can't grow, rather than failing
+    pub fn with_spill_manager(mut self, spill_manager: Rc<SpillManager>) -> Self {
+        self.spill_manager = Some(spill_manager);
+        self
+    }
+}
+
+/// Estimate the bytes of memory that a group of the hash table uses for its key and the state
+/// of its aggregates
+fn group_memory_size(key: &[GroupScalar], aggregates: usize) -> usize {
+    let key_size: usize = key
+        .iter()
+        .map(|k| match *k {
+            GroupScalar::Utf8(ref s) => s.len() + 8,
+            _ => 8,
+        })
+        .sum();
+    key_size + aggregates * 8
 }
 
 /// Enumeration of types that can be used in a GROUP BY expression
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
-enum GroupScalar {
+pub enum GroupScalar {
     Boolean(bool),
     UInt8(u8),
     UInt16(u16),
@@ -77,7 +116,7 @@ enum GroupScalar {
 }
 
 impl GroupScalar {
-    fn as_scalar(&self) -> ScalarValue {
+    pub fn as_scalar(&self) -> ScalarValue {
         match *self {
             GroupScalar::Boolean(v) => ScalarValue::Boolean(v),
             GroupScalar::UInt8(v) => ScalarValue::UInt8(v),
@@ -94,7 +133,7 @@ impl GroupScalar {
 }
 
 /// Make a hash map key from a list of values
-fn write_key(key: &mut Vec<GroupScalar>, group_values: &Vec<Value>, i: usize) {
+pub fn write_key(key: &mut Vec<GroupScalar>, group_values: &Vec<Value>, i: usize) -> Result<()> {
     for j in 0..group_values.len() {
         key[j] = match group_values[j] {
             Value::Scalar(ref vv) => match vv.as_ref() {
@@ -111,20 +150,26 @@ fn write_key(key: &mut Vec<GroupScalar>, group_values: &Vec<Value>, i: usize) {
                 ArrayData::UInt16(ref buf) => GroupScalar::UInt16(*buf.get(i)),
                 ArrayData::UInt32(ref buf) => GroupScalar::UInt32(*buf.get(i)),
                 ArrayData::UInt64(ref buf) => GroupScalar::UInt64(*buf.get(i)),
-                ArrayData::Utf8(ref list) => {
-                    GroupScalar::Utf8(Rc::new(str::from_utf8(list.get(i)).unwrap().to_string()))
-                }
+                ArrayData::Utf8(ref list) => GroupScalar::Utf8(Rc::new(utf8_value(list.get(i))?)),
                 _ => unimplemented!("Unsupported datatype for aggregate grouping expression"),
             },
         };
     }
+    Ok(())
 }
 
 /// Create an initial aggregate entry
-fn create_aggregate_entry(aggr_expr: &Vec<RuntimeExpr>) -> Rc<RefCell<AggregateEntry>> {
+fn create_aggregate_entry(aggr_expr: &[RuntimeExpr]) -> Rc<RefCell<AggregateEntry>> {
     //println!("Creating new aggregate entry");
 
-    let functions = aggr_expr
+    Rc::new(RefCell::new(AggregateEntry {
+        aggr_values: create_aggregate_functions(aggr_expr),
+    }))
+}
+
+/// Create the accumulators for one group of an aggregation
+pub fn create_aggregate_functions(aggr_expr: &[RuntimeExpr]) -> Vec<Box<AggregateFunction>> {
+    aggr_expr
         .iter()
         .map(|e| match e {
             RuntimeExpr::AggregateFunction { ref f, ref t, .. } => match f {
@@ -136,11 +181,7 @@ fn create_aggregate_entry(aggr_expr: &Vec<RuntimeExpr>) -> Rc<RefCell<AggregateE
             },
             _ => panic!(),
         })
-        .collect();
-
-    Rc::new(RefCell::new(AggregateEntry {
-        aggr_values: functions,
-    }))
+        .collect()
 }
 
 macro_rules! build_aggregate_array {
@@ -153,119 +194,138 @@ macro_rules! build_aggregate_array {
     }};
 }
 
-impl SimpleRelation for AggregateRelation {
-    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
-        let aggr_expr = &self.aggr_expr;
-        let group_expr = &self.group_expr;
-        //        let mut map: HashMap<Vec<GroupScalar>, Rc<RefCell<AggregateEntry>>> = HashMap::new();
-        let mut map: FnvHashMap<Vec<GroupScalar>, Rc<RefCell<AggregateEntry>>> =
-            FnvHashMap::default();
-
-        //println!("There are {} aggregate expressions", aggr_expr.len());
-
-        self.input.scan().for_each(|batch| {
-            match batch {
-                Ok(ref b) => {
-                    //println!("Processing aggregates for batch with {} rows", b.num_rows());
-
-                    // evaluate the single argument to each aggregate function
-                    let mut aggr_col_args: Vec<Vec<Value>> = Vec::with_capacity(aggr_expr.len());
-                    for i in 0..aggr_expr.len() {
-                        match aggr_expr[i] {
-                            RuntimeExpr::AggregateFunction { ref args, .. } => {
-                                // arguments to the aggregate function
-                                let aggr_func_args: Result<
-                                    Vec<Value>,
-                                > = args.iter().map(|e| (*e)(b.as_ref())).collect();
-
-                                // push the column onto the vector
-                                aggr_col_args.push(aggr_func_args.unwrap());
-                            }
-                            _ => panic!(),
-                        }
-                    }
-
-                    // evaluate the grouping expressions
-                    let group_values_result: Result<Vec<Value>> = group_expr
-                        .iter()
-                        .map(|e| e.get_func()(b.as_ref()))
-                        .collect();
-
-                    let group_values: Vec<Value> = group_values_result.unwrap(); //TODO
+/// Get the arguments of an aggregate function for one row
+fn row_values(args: &[Value], i: usize) -> Vec<Value> {
+    args.iter()
+        .map(|col| match *col {
+            Value::Column(ref col) => Value::Scalar(Rc::new(get_value(col, i))),
+            Value::Scalar(ref v) => Value::Scalar(v.clone()),
+        })
+        .collect()
+}
 
-                    if group_values.len() == 0 {
-                        // aggregate columns directly
-                        let key: Vec<GroupScalar> = Vec::with_capacity(0);
+/// The hash table of an aggregation while it reads its input
+struct Aggregation<'a> {
+    group_expr: &'a [RuntimeExpr],
+    aggr_expr: &'a [RuntimeExpr],
+    map: RowHashMap<Vec<GroupScalar>, Rc<RefCell<AggregateEntry>>>,
+    reservation: &'a mut Option<MemoryReservation>,
+    /// The spill manager and input schema, when rows may be spilled
+    spill: Option<(Rc<SpillManager>, Rc<Schema>)>,
+    /// Partitions that the rows of new groups are written to once the reservation can't grow
+    spilled: Option<SpillPartitions>,
+}
 
-                        let entry = map
-                            .entry(key)
-                            .or_insert_with(|| create_aggregate_entry(aggr_expr));
-                        let mut entry_mut = entry.borrow_mut();
+impl<'a> Aggregation<'a> {
+    /// Aggregate the rows of a batch into their groups
+    fn aggregate(&mut self, b: &RecordBatch) -> Result<()> {
+        let aggr_expr = self.aggr_expr;
+
+        // evaluate the single argument to each aggregate function
+        let mut aggr_col_args: Vec<Vec<Value>> = Vec::with_capacity(aggr_expr.len());
+        for e in aggr_expr {
+            match *e {
+                RuntimeExpr::AggregateFunction { ref args, .. } => {
+                    let values = args.iter().map(|e| (*e)(b)).collect::<Result<Vec<Value>>>()?;
+                    aggr_col_args.push(values);
+                }
+                _ => panic!(),
+            }
+        }
 
-                        for i in 0..aggr_expr.len() {
-                            (*entry_mut).aggr_values[i]
-                                .execute(&aggr_col_args[i])
-                                .unwrap();
-                        }
-                    } else {
-                        let mut key: Vec<GroupScalar> = Vec::with_capacity(group_values.len());
-                        for _ in 0..group_values.len() {
-                            key.push(GroupScalar::Int32(0));
-                        }
+        // evaluate the grouping expressions
+        let group_values = self
+            .group_expr
+            .iter()
+            .map(|e| e.get_func()(b))
+            .collect::<Result<Vec<Value>>>()?;
+
+        if group_values.is_empty() {
+            // aggregate columns directly
+            let key = HashedKey {
+                hash: 0,
+                key: Vec::with_capacity(0),
+            };
+            let entry = self
+                .map
+                .entry(key)
+                .or_insert_with(|| create_aggregate_entry(aggr_expr));
+            let mut entry_mut = entry.borrow_mut();
+            for (i, args) in aggr_col_args.iter().enumerate() {
+                entry_mut.aggr_values[i].execute(args)?;
+            }
+            return Ok(());
+        }
 
-                        // expensive row-based aggregation by group
-                        for i in 0..b.num_rows() {
-                            write_key(&mut key, &group_values, i);
-                            //let key = make_key(&group_values, i);
-                            //println!("key = {:?}", key);
-
-                            let x = match map.get(&key) {
-                                Some(entry) => {
-                                    let mut entry_mut = entry.borrow_mut();
-
-                                    for j in 0..aggr_expr.len() {
-                                        let row_aggr_values: Vec<Value> = aggr_col_args[j].iter()
-                                            .map(|col| match col {
-                                                Value::Column(ref col) => Value::Scalar(Rc::new(get_value(col, i))),
-                                                Value::Scalar(ref v) => Value::Scalar(v.clone())
-                                            }).collect();
-                                        (*entry_mut).aggr_values[j]
-                                            .execute(&row_aggr_values)
-                                            .unwrap();
-                                    }
-
-                                    true
-                                }
-                                None => false,
-                            };
-
-                            if !x {
-                                let entry = create_aggregate_entry(aggr_expr);
-                                {
-                                    let mut entry_mut = entry.borrow_mut();
-
-                                    for j in 0..aggr_expr.len() {
-                                        let row_aggr_values: Vec<Value> = aggr_col_args[j].iter()
-                                            .map(|col| match col {
-                                                Value::Column(ref col) =>
-                                                    Value::Scalar(Rc::new(get_value(col, i))),
-                                                Value::Scalar(ref v) => Value::Scalar(v.clone())
-                                            }).collect();
-                                        (*entry_mut).aggr_values[j]
-                                            .execute(&row_aggr_values)
-                                            .unwrap();
-                                    }
+        let mut key: Vec<GroupScalar> = vec![GroupScalar::Int32(0); group_values.len()];
+        let hashes = hash_rows(&group_values, b.num_rows(), true);
+        // rows of groups that are spilled rather than added to the hash table
+        let mut spill_rows = vec![];
+
+        // expensive row-based aggregation by group
+        for i in 0..b.num_rows() {
+            write_key(&mut key, &group_values, i)?;
+            let hashed = HashedKey {
+                hash: hashes[i].unwrap(),
+                key,
+            };
+
+            let found = match self.map.get(&hashed) {
+                Some(entry) => {
+                    let mut entry_mut = entry.borrow_mut();
+                    for (j, args) in aggr_col_args.iter().enumerate() {
+                        entry_mut.aggr_values[j].execute(&row_values(args, i))?;
+                    }
+                    true
+                }
+                None => false,
+            };
+
+            if !found {
+                if self.spilled.is_none() {
+                    if let Some(ref mut r) = *self.reservation {
+                        let size = group_memory_size(&hashed.key, aggr_expr.len());
+                        if let Err(e) = r.grow(size) {
+                            match self.spill {
+                                Some((ref spill_manager, ref schema)) => {
+                                    self.spilled = Some(SpillPartitions::new(
+                                        spill_manager.clone(),
+                                        schema.clone(),
+                                    ))
                                 }
-                                map.insert(key.clone(), entry);
+                                None => return Err(e),
                             }
                         }
                     }
                 }
-                Err(e) => panic!("Error aggregating batch: {:?}", e),
+                if self.spilled.is_some() {
+                    spill_rows.push(i);
+                } else {
+                    let entry = create_aggregate_entry(aggr_expr);
+                    {
+                        let mut entry_mut = entry.borrow_mut();
+                        for (j, args) in aggr_col_args.iter().enumerate() {
+                            entry_mut.aggr_values[j].execute(&row_values(args, i))?;
+                        }
+                    }
+                    self.map.insert(hashed.clone(), entry);
+                }
             }
-        });
+            key = hashed.key;
+        }
+
+        if let Some(ref mut spilled) = self.spilled {
+            if !spill_rows.is_empty() {
+                spilled.write(b, &spill_rows, &hashes)?;
+            }
+        }
+        Ok(())
+    }
 
-        //        println!("Preparing results");
+    /// Build a batch from the groups of the hash table, and empty the table
+    fn finish(&mut self) -> Result<Rc<RecordBatch>> {
+        let aggr_expr = self.aggr_expr;
+        let group_expr = self.group_expr;
 
         let mut result_columns: Vec<Vec<ScalarValue>> =
             Vec::with_capacity(group_expr.len() + aggr_expr.len());
@@ -276,17 +336,17 @@ impl SimpleRelation for AggregateRelation {
             result_columns.push(Vec::new());
         }
 
-        for (k, v) in map.iter() {
-            for col_index in 0..k.len() {
-                result_columns[col_index].push(k[col_index].as_scalar());
+        for (k, v) in self.map.iter() {
+            for col_index in 0..k.key.len() {
+                result_columns[col_index].push(k.key[col_index].as_scalar());
             }
 
             let g: Vec<Value> = v
                 .borrow()
                 .aggr_values
                 .iter()
-                .map(|v| v.finish().unwrap())
-                .collect();
+                .map(|v| v.finish())
+                .collect::<Result<Vec<Value>>>()?;
 
             //            println!("aggregate entry: {:?}", g);
 
@@ -301,7 +361,7 @@ impl SimpleRelation for AggregateRelation {
         let mut aggr_batch = DefaultRecordBatch {
             schema: Rc::new(Schema::empty()),
             data: Vec::new(),
-            row_count: map.len(),
+            row_count: self.map.len(),
         };
 
         // create Arrow arrays from grouping scalar values
@@ -354,11 +414,79 @@ impl SimpleRelation for AggregateRelation {
             }
         }
 
-        let tb: Rc<RecordBatch> = Rc::new(aggr_batch);
+        // the hash table is freed now that its results are built
+        self.map = RowHashMap::default();
+        self.release();
+
+        Ok(Rc::new(aggr_batch))
+    }
+
+    fn release(&mut self) {
+        if let Some(ref mut r) = *self.reservation {
+            r.release();
+        }
+    }
+
+    /// Aggregate the rows of a spilled partition, which has to fit in the memory reservation
+    fn aggregate_partition(&mut self, file: SpillFile) -> Result<Rc<RecordBatch>> {
+        for batch in file.read()? {
+            self.aggregate(batch?.as_ref())?;
+        }
+        self.finish()
+    }
+}
 
-        // create iterator over the single batch
-        let v = vec![Ok(tb)];
-        Box::new(v.into_iter())
+impl SimpleRelation for AggregateRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let spill = match self.spill_manager {
+            Some(ref spill_manager) => Some((
+                spill_manager.clone(),
+                Rc::new(self.input.schema().clone()),
+            )),
+            None => None,
+        };
+        let mut aggregation = Aggregation {
+            group_expr: &self.group_expr,
+            aggr_expr: &self.aggr_expr,
+            map: RowHashMap::default(),
+            reservation: &mut self.reservation,
+            spill,
+            spilled: None,
+        };
+        // the groups of an earlier scan are no longer held
+        aggregation.release();
+
+        let mut result = Ok(());
+        for batch in self.input.scan() {
+            result = batch.and_then(|b| aggregation.aggregate(b.as_ref()));
+            if result.is_err() {
+                break;
+            }
+        }
+        let first = match result.and_then(|_| aggregation.finish()) {
+            Ok(batch) => batch,
+            Err(e) => {
+                aggregation.release();
+                return Box::new(iter::once(Err(e)));
+            }
+        };
+
+        // the partitions are aggregated without spilling again
+        aggregation.spill = None;
+        let files = match aggregation.spilled.take() {
+            Some(partitions) => match partitions.finish() {
+                Ok(files) => files,
+                Err(e) => return Box::new(iter::once(Err(e))),
+            },
+            None => vec![],
+        };
+        Box::new(
+            iter::once(Ok(first)).chain(
+                files
+                    .into_iter()
+                    .filter_map(move |file| file.map(|f| aggregation.aggregate_partition(f))),
+            ),
+        )
     }
 
     fn schema<'a>(&'a self) -> &'a Schema {
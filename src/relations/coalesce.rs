@@ -0,0 +1,281 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Merges small batches into larger ones. Selective filters can produce many batches with only
+//! a handful of rows, and the per-batch overhead of downstream relations then dominates.
+
+use std::rc::Rc;
+
+use arrow::array::ListArray;
+use arrow::bitmap::Bitmap;
+use arrow::builder::*;
+use arrow::datatypes::*;
+use arrow::list_builder::ListBuilder;
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::types::*;
+
+pub struct CoalesceBatchesRelation {
+    input: Box<SimpleRelation>,
+    target_batch_size: usize,
+}
+
+impl CoalesceBatchesRelation {
+    pub fn new(input: Box<SimpleRelation>, target_batch_size: usize) -> Self {
+        CoalesceBatchesRelation {
+            input,
+            target_batch_size,
+        }
+    }
+}
+
+impl SimpleRelation for CoalesceBatchesRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        Box::new(CoalesceIterator {
+            target_batch_size: self.target_batch_size,
+            input: self.input.scan(),
+            buffer: vec![],
+            buffered_rows: 0,
+            pending: None,
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+struct CoalesceIterator<'a> {
+    target_batch_size: usize,
+    input: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    buffer: Vec<Rc<RecordBatch>>,
+    buffered_rows: usize,
+    /// A large batch that arrived while small batches were buffered, and which is returned
+    /// after them to preserve ordering
+    pending: Option<Rc<RecordBatch>>,
+}
+
+impl<'a> CoalesceIterator<'a> {
+    fn flush(&mut self) -> Result<Rc<RecordBatch>> {
+        let batches: Vec<Rc<RecordBatch>> = self.buffer.drain(..).collect();
+        self.buffered_rows = 0;
+        if batches.len() == 1 {
+            return Ok(batches[0].clone());
+        }
+        concat_batches(&batches)
+    }
+}
+
+impl<'a> Iterator for CoalesceIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(batch) = self.pending.take() {
+            return Some(Ok(batch));
+        }
+        loop {
+            match self.input.next() {
+                Some(Ok(batch)) => {
+                    if batch.num_rows() == 0 {
+                        continue;
+                    }
+                    // batches that are already large enough are passed through unchanged
+                    if batch.num_rows() >= self.target_batch_size {
+                        if self.buffer.is_empty() {
+                            return Some(Ok(batch));
+                        }
+                        self.pending = Some(batch);
+                        return Some(self.flush());
+                    }
+                    self.buffered_rows += batch.num_rows();
+                    self.buffer.push(batch);
+                    if self.buffered_rows >= self.target_batch_size {
+                        return Some(self.flush());
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    if self.buffer.is_empty() {
+                        return None;
+                    }
+                    return Some(self.flush());
+                }
+            }
+        }
+    }
+}
+
+/// Concatenate batches that share a schema into a single batch
+pub fn concat_batches(batches: &[Rc<RecordBatch>]) -> Result<Rc<RecordBatch>> {
+    let first = match batches.first() {
+        Some(b) => b,
+        None => {
+            return Err(DataFusionError::Execution(
+                "Cannot concatenate an empty list of batches".to_string(),
+            ))
+        }
+    };
+    let mut columns = Vec::with_capacity(first.num_columns());
+    for i in 0..first.num_columns() {
+        let column = match *first.column(i) {
+            // scalar columns are placeholders for columns that were not projected
+            Value::Scalar(ref v) => Value::Scalar(v.clone()),
+            Value::Column(_) => {
+                let mut arrays: Vec<&Array> = Vec::with_capacity(batches.len());
+                for batch in batches {
+                    match *batch.column(i) {
+                        Value::Column(ref arr) => arrays.push(arr.as_ref()),
+                        Value::Scalar(_) => {
+                            return Err(DataFusionError::Execution(format!(
+                                "Cannot concatenate scalar and array values in column {}",
+                                i
+                            )))
+                        }
+                    }
+                }
+                Value::Column(Rc::new(concat_arrays(&arrays)?))
+            }
+        };
+        columns.push(column);
+    }
+    Ok(Rc::new(DefaultRecordBatch {
+        schema: first.schema().clone(),
+        data: columns,
+        row_count: batches.iter().map(|b| b.num_rows()).sum(),
+    }))
+}
+
+/// Check whether the value at an index is non-null
+pub fn is_valid(array: &Array, index: usize) -> bool {
+    match *array.validity_bitmap() {
+        Some(ref bitmap) => bitmap.is_set(index),
+        None => true,
+    }
+}
+
+macro_rules! concat_primitive {
+    ($ARRAYS:expr, $LEN:expr, $VARIANT:ident, $TY:ty) => {{
+        let mut b: Builder<$TY> = Builder::with_capacity($LEN);
+        let mut bitmap = Bitmap::new($LEN);
+        let mut null_count = 0;
+        let mut offset = 0;
+        for array in $ARRAYS {
+            match *array.data() {
+                ArrayData::$VARIANT(ref v) => {
+                    for (i, value) in v.iter().enumerate() {
+                        if !is_valid(array, i) {
+                            null_count += 1;
+                            bitmap.clear(offset + i);
+                        }
+                        b.push(value);
+                    }
+                }
+                _ => return Err(type_mismatch()),
+            }
+            offset += array.len();
+        }
+        Array::with_nulls($LEN, ArrayData::from(b.finish()), null_count, bitmap)
+    }};
+}
+
+fn type_mismatch() -> DataFusionError {
+    DataFusionError::Execution("Cannot concatenate arrays of different types".to_string())
+}
+
+/// Concatenate arrays of the same type, preserving nulls
+pub fn concat_arrays(arrays: &[&Array]) -> Result<Array> {
+    let len = arrays.iter().map(|a| a.len()).sum();
+    let first = match arrays.first() {
+        Some(a) => a,
+        None => return Ok(Array::from(Vec::<i32>::new())),
+    };
+    Ok(match *first.data() {
+        ArrayData::Boolean(_) => concat_primitive!(arrays.iter(), len, Boolean, bool),
+        ArrayData::Int8(_) => concat_primitive!(arrays.iter(), len, Int8, i8),
+        ArrayData::Int16(_) => concat_primitive!(arrays.iter(), len, Int16, i16),
+        ArrayData::Int32(_) => concat_primitive!(arrays.iter(), len, Int32, i32),
+        ArrayData::Int64(_) => concat_primitive!(arrays.iter(), len, Int64, i64),
+        ArrayData::UInt8(_) => concat_primitive!(arrays.iter(), len, UInt8, u8),
+        ArrayData::UInt16(_) => concat_primitive!(arrays.iter(), len, UInt16, u16),
+        ArrayData::UInt32(_) => concat_primitive!(arrays.iter(), len, UInt32, u32),
+        ArrayData::UInt64(_) => concat_primitive!(arrays.iter(), len, UInt64, u64),
+        ArrayData::Float32(_) => concat_primitive!(arrays.iter(), len, Float32, f32),
+        ArrayData::Float64(_) => concat_primitive!(arrays.iter(), len, Float64, f64),
+        ArrayData::Utf8(_) => {
+            let mut b: ListBuilder<u8> = ListBuilder::with_capacity(len);
+            let mut bitmap = Bitmap::new(len);
+            let mut null_count = 0;
+            let mut offset = 0;
+            for array in arrays {
+                match *array.data() {
+                    ArrayData::Utf8(ref list) => {
+                        for i in 0..array.len() {
+                            if !is_valid(array, i) {
+                                null_count += 1;
+                                bitmap.clear(offset + i);
+                            }
+                            b.push(list.get(i));
+                        }
+                    }
+                    _ => return Err(type_mismatch()),
+                }
+                offset += array.len();
+            }
+            Array::with_nulls(
+                len,
+                ArrayData::Utf8(ListArray::from(b.finish())),
+                null_count,
+                bitmap,
+            )
+        }
+        ArrayData::Struct(_) => {
+            return Err(DataFusionError::Execution(
+                "Cannot concatenate struct arrays yet".to_string(),
+            ))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concat_arrays() {
+        let a = Array::from(vec![1, 2]);
+        let mut bitmap = Bitmap::new(2);
+        bitmap.clear(0);
+        let mut builder: Builder<i32> = Builder::with_capacity(2);
+        builder.push(0);
+        builder.push(4);
+        let b = Array::with_nulls(2, ArrayData::from(builder.finish()), 1, bitmap);
+        let c = concat_arrays(&[&a, &b]).unwrap();
+        assert_eq!(4, c.len());
+        assert_eq!(1, c.null_count());
+        assert!(!is_valid(&c, 2));
+        match *c.data() {
+            ArrayData::Int32(ref v) => assert_eq!(vec![1, 2, 0, 4], v.iter().collect::<Vec<i32>>()),
+            _ => panic!(),
+        }
+
+        let s = concat_arrays(&[&Array::from(vec!["a"]), &Array::from(vec!["bc"])]).unwrap();
+        match *s.data() {
+            ArrayData::Utf8(ref list) => assert_eq!(b"bc", list.get(1)),
+            _ => panic!(),
+        }
+        assert!(concat_arrays(&[&a, &s]).is_err());
+    }
+}
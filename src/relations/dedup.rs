@@ -0,0 +1,210 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deduplicate Relation
+//!
+//! Keeps one row for each distinct key. The input is read in a single pass and only the row
+//! currently kept for each key is held in memory, so unlike a window function over the same
+//! rows the input never needs to be sorted or materialized. Keys are output in the order they
+//! were first seen.
+
+use std::cmp::Ordering;
+use std::iter;
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::logical::DeduplicateKeep;
+use super::super::types::*;
+use super::join::{encode_key, evaluate_keys};
+use super::row_hash::{hash_rows, HashedKey, RowHashMap};
+
+pub struct DeduplicateRelation {
+    input: Box<SimpleRelation>,
+    keys: Vec<CompiledExpr>,
+    order_by: Vec<CompiledExpr>,
+    /// Sort direction of each ORDER BY expression
+    order_asc: Vec<bool>,
+    keep: DeduplicateKeep,
+    schema: Rc<Schema>,
+}
+
+/// The row kept so far for a key
+struct KeptRow {
+    order_values: Vec<ScalarValue>,
+    values: Vec<ScalarValue>,
+}
+
+impl DeduplicateRelation {
+    pub fn new(
+        input: Box<SimpleRelation>,
+        keys: Vec<CompiledExpr>,
+        order_by: Vec<CompiledExpr>,
+        order_asc: Vec<bool>,
+        keep: DeduplicateKeep,
+        schema: Rc<Schema>,
+    ) -> Self {
+        DeduplicateRelation {
+            input,
+            keys,
+            order_by,
+            order_asc,
+            keep,
+            schema,
+        }
+    }
+
+    fn evaluate(&mut self) -> Result<Option<Rc<RecordBatch>>> {
+        let mut index: RowHashMap<Vec<u8>, usize> = RowHashMap::default();
+        let mut kept: Vec<KeptRow> = vec![];
+        for batch in self.input.scan() {
+            let batch = batch?;
+            let key_values = evaluate_keys(&self.keys, batch.as_ref())?;
+            let order_values = evaluate_keys(&self.order_by, batch.as_ref())?;
+            // null keys are equal to each other, as in GROUP BY
+            let hashes = hash_rows(&key_values, batch.num_rows(), true);
+            for row in 0..batch.num_rows() {
+                let key = HashedKey {
+                    hash: hashes[row].unwrap(),
+                    key: encode_key(&key_values, row, true).unwrap(),
+                };
+                let candidate: Vec<ScalarValue> =
+                    order_values.iter().map(|v| value_at(v, row)).collect();
+                match index.get(&key) {
+                    Some(&i) => {
+                        if !replaces(&self.keep, &self.order_asc, &candidate, &kept[i]) {
+                            continue;
+                        }
+                        kept[i] = KeptRow {
+                            order_values: candidate,
+                            values: row_values(batch.as_ref(), row),
+                        };
+                    }
+                    None => {
+                        index.insert(key, kept.len());
+                        kept.push(KeptRow {
+                            order_values: candidate,
+                            values: row_values(batch.as_ref(), row),
+                        });
+                    }
+                }
+            }
+        }
+
+        if kept.is_empty() {
+            return Ok(None);
+        }
+        let mut columns = Vec::with_capacity(self.schema.columns().len());
+        for (i, field) in self.schema.columns().iter().enumerate() {
+            let values: Vec<ScalarValue> = kept.iter().map(|r| r.values[i].clone()).collect();
+            columns.push(Value::Column(Rc::new(array_from_scalars(
+                &values,
+                field.data_type(),
+            )?)));
+        }
+        Ok(Some(Rc::new(DefaultRecordBatch {
+            schema: self.schema.clone(),
+            data: columns,
+            row_count: kept.len(),
+        })))
+    }
+}
+
+fn row_values(batch: &RecordBatch, row: usize) -> Vec<ScalarValue> {
+    batch.columns().iter().map(|c| value_at(c, row)).collect()
+}
+
+/// Check whether a row replaces the row kept so far for its key. Rows that are equal in the sort
+/// order are kept in input order, so the first of them is kept for `First` and the last of them
+/// for `Last`.
+fn replaces(
+    keep: &DeduplicateKeep,
+    order_asc: &[bool],
+    candidate: &[ScalarValue],
+    kept: &KeptRow,
+) -> bool {
+    let mut ordering = Ordering::Equal;
+    for (i, asc) in order_asc.iter().enumerate() {
+        let o = candidate[i]
+            .partial_cmp(&kept.order_values[i])
+            .unwrap_or(Ordering::Equal);
+        if o != Ordering::Equal {
+            ordering = if *asc { o } else { o.reverse() };
+            break;
+        }
+    }
+    match *keep {
+        DeduplicateKeep::First => ordering == Ordering::Less,
+        DeduplicateKeep::Last => ordering != Ordering::Less,
+    }
+}
+
+impl SimpleRelation for DeduplicateRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        match self.evaluate() {
+            Ok(Some(batch)) => Box::new(iter::once(Ok(batch))),
+            Ok(None) => Box::new(iter::empty()),
+            Err(e) => Box::new(iter::once(Err(e))),
+        }
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kept(order_values: Vec<ScalarValue>) -> KeptRow {
+        KeptRow {
+            order_values,
+            values: vec![],
+        }
+    }
+
+    #[test]
+    fn test_replaces() {
+        let row = kept(vec![ScalarValue::Int32(5), ScalarValue::Int32(1)]);
+        let earlier = vec![ScalarValue::Int32(3), ScalarValue::Int32(9)];
+        let later = vec![ScalarValue::Int32(5), ScalarValue::Int32(2)];
+        let same = vec![ScalarValue::Int32(5), ScalarValue::Int32(1)];
+
+        let asc = [true, true];
+        assert!(replaces(&DeduplicateKeep::First, &asc, &earlier, &row));
+        assert!(!replaces(&DeduplicateKeep::First, &asc, &later, &row));
+        assert!(!replaces(&DeduplicateKeep::First, &asc, &same, &row));
+        assert!(!replaces(&DeduplicateKeep::Last, &asc, &earlier, &row));
+        assert!(replaces(&DeduplicateKeep::Last, &asc, &later, &row));
+        assert!(replaces(&DeduplicateKeep::Last, &asc, &same, &row));
+
+        // the second key only matters when the first is equal
+        let desc = [true, false];
+        assert!(replaces(
+            &DeduplicateKeep::First,
+            &desc,
+            &later,
+            &kept(same.clone())
+        ));
+        assert!(replaces(&DeduplicateKeep::First, &desc, &earlier, &row));
+
+        // without sort expressions every row is equal, so input order decides
+        assert!(!replaces(&DeduplicateKeep::First, &[], &[], &kept(vec![])));
+        assert!(replaces(&DeduplicateKeep::Last, &[], &[], &kept(vec![])));
+    }
+}
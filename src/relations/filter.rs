@@ -12,24 +12,154 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cell::RefCell;
 use std::rc::Rc;
 
 use super::super::datasources::common::*;
 use super::super::errors::*;
 use super::super::exec::*;
+use super::super::logical::Operator;
 use super::super::types::*;
 
-//use arrow::array::*;
+use arrow::array::ListArray;
+use arrow::bitmap::Bitmap;
 use arrow::datatypes::*;
+use arrow::list_builder::ListBuilder;
+
+use super::coalesce::is_valid;
+
+/// A comparison between a column and a constant that every row passing a filter must satisfy
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnBound {
+    pub column: usize,
+    pub op: Operator,
+    pub value: ScalarValue,
+}
+
+impl ColumnBound {
+    /// Check whether any value between `min` and `max` can satisfy the bound
+    fn may_match(&self, min: &ScalarValue, max: &ScalarValue) -> bool {
+        let v = &self.value;
+        match self.op {
+            Operator::Eq => v >= min && v <= max,
+            Operator::NotEq => !(min == v && max == v),
+            Operator::Lt => min < v,
+            Operator::LtEq => min <= v,
+            Operator::Gt => max > v,
+            Operator::GtEq => max >= v,
+            _ => true,
+        }
+    }
+}
+
+/// Counts of batches checked against filter statistics
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterMetrics {
+    pub batches_checked: usize,
+    pub batches_skipped: usize,
+}
 
 pub struct FilterRelation {
     input: Box<SimpleRelation>,
     expr: CompiledExpr,
+    bounds: Vec<ColumnBound>,
+    metrics: Rc<RefCell<FilterMetrics>>,
 }
 
 impl FilterRelation {
     pub fn new(input: Box<SimpleRelation>, expr: CompiledExpr) -> Self {
-        FilterRelation { input, expr }
+        FilterRelation {
+            input,
+            expr,
+            bounds: vec![],
+            metrics: Rc::new(RefCell::new(FilterMetrics::default())),
+        }
+    }
+
+    /// Skip batches where the min and max of a column show that a bound can't be satisfied,
+    /// without evaluating the filter expression over them
+    pub fn with_bounds(mut self, bounds: Vec<ColumnBound>, metrics: Rc<RefCell<FilterMetrics>>) -> Self {
+        self.bounds = bounds;
+        self.metrics = metrics;
+        self
+    }
+}
+
+/// Check whether the min and max of the bounded columns show that no row in the batch can pass
+fn can_skip(batch: &RecordBatch, bounds: &[ColumnBound]) -> bool {
+    bounds.iter().any(|bound| match *batch.column(bound.column) {
+        Value::Column(ref array) => match min_max(array) {
+            Some((ref min, ref max)) => {
+                // the bound is only usable when the constant has the column's type
+                min.get_datatype() == bound.value.get_datatype() && !bound.may_match(min, max)
+            }
+            // every value is null, so no comparison can be true
+            None => array.null_count() == array.len(),
+        },
+        Value::Scalar(_) => false,
+    })
+}
+
+macro_rules! min_max_primitive {
+    ($ARRAY:expr, $VALUES:expr, $VARIANT:ident) => {{
+        let mut result = None;
+        for (i, v) in $VALUES.iter().enumerate() {
+            if !is_valid($ARRAY, i) {
+                continue;
+            }
+            // NaN doesn't fit in the ordering, so no statistics are reported
+            if v.partial_cmp(&v).is_none() {
+                return None;
+            }
+            result = match result {
+                None => Some((v, v)),
+                Some((min, max)) => Some((
+                    if v < min { v } else { min },
+                    if v > max { v } else { max },
+                )),
+            };
+        }
+        result.map(|(min, max)| (ScalarValue::$VARIANT(min), ScalarValue::$VARIANT(max)))
+    }};
+}
+
+/// Get the min and max of the non-null values in an array
+pub fn min_max(array: &Array) -> Option<(ScalarValue, ScalarValue)> {
+    match *array.data() {
+        ArrayData::Int8(ref v) => min_max_primitive!(array, v, Int8),
+        ArrayData::Int16(ref v) => min_max_primitive!(array, v, Int16),
+        ArrayData::Int32(ref v) => min_max_primitive!(array, v, Int32),
+        ArrayData::Int64(ref v) => min_max_primitive!(array, v, Int64),
+        ArrayData::UInt8(ref v) => min_max_primitive!(array, v, UInt8),
+        ArrayData::UInt16(ref v) => min_max_primitive!(array, v, UInt16),
+        ArrayData::UInt32(ref v) => min_max_primitive!(array, v, UInt32),
+        ArrayData::UInt64(ref v) => min_max_primitive!(array, v, UInt64),
+        ArrayData::Float32(ref v) => min_max_primitive!(array, v, Float32),
+        ArrayData::Float64(ref v) => min_max_primitive!(array, v, Float64),
+        ArrayData::Utf8(ref list) => {
+            let mut result: Option<(&[u8], &[u8])> = None;
+            for i in 0..array.len() {
+                if !is_valid(array, i) {
+                    continue;
+                }
+                let v = list.get(i);
+                result = match result {
+                    None => Some((v, v)),
+                    Some((min, max)) => Some((
+                        if v < min { v } else { min },
+                        if v > max { v } else { max },
+                    )),
+                };
+            }
+            match result {
+                Some((min, max)) => Some((
+                    ScalarValue::Utf8(Rc::new(String::from_utf8_lossy(min).into_owned())),
+                    ScalarValue::Utf8(Rc::new(String::from_utf8_lossy(max).into_owned())),
+                )),
+                None => None,
+            }
+        }
+        ArrayData::Boolean(_) | ArrayData::Struct(_) => None,
     }
 }
 
@@ -37,8 +167,21 @@ impl SimpleRelation for FilterRelation {
     fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
         let filter_expr = &self.expr;
         let schema = Rc::new(self.schema().clone());
+        let bounds = &self.bounds;
+        let metrics = self.metrics.clone();
 
-        Box::new(self.input.scan().map(move |b| {
+        Box::new(self.input.scan().filter(move |b| match *b {
+            Ok(ref batch) if !bounds.is_empty() => {
+                let skip = can_skip(batch.as_ref(), bounds);
+                let mut m = metrics.borrow_mut();
+                m.batches_checked += 1;
+                if skip {
+                    m.batches_skipped += 1;
+                }
+                !skip
+            }
+            _ => true,
+        }).map(move |b| {
             match b {
                 Ok(ref batch) => {
                     //println!("FilterRelation batch {} rows with {} columns", batch.num_rows(), batch.num_columns());
@@ -51,6 +194,12 @@ impl SimpleRelation for FilterRelation {
                         Value::Column(ref filter_eval) => {
                             assert_eq!(batch.num_rows(), filter_eval.len());
 
+                            // when every row matches, the input columns are passed through
+                            // rather than copied, which matters most for string columns
+                            if all_set(filter_eval) {
+                                return Ok(batch.clone());
+                            }
+
                             let filtered_columns: Vec<Value> = (0..batch.num_columns())
                                 .map(move |column_index| {
                                     //println!("Filtering column {}", column_index);
@@ -96,6 +245,17 @@ impl SimpleRelation for FilterRelation {
     }
 }
 
+/// Check whether a boolean filter result selects every row
+fn all_set(bools: &Array) -> bool {
+    match bools.data() {
+        &ArrayData::Boolean(ref b) => bools.null_count() == 0 && b.iter().all(|f| f),
+        _ => false,
+    }
+}
+
+/// Select the rows of a column that a boolean array is true for. The selected values are
+/// copied into new buffers, strings included, so filters only avoid copying string data by
+/// passing through batches that every row of passes.
 pub fn filter(column: &Value, bools: &Array) -> Array {
     match column {
         &Value::Scalar(ref v) => match v.as_ref() {
@@ -185,15 +345,29 @@ pub fn filter(column: &Value, bools: &Array) -> Array {
                         .collect::<Vec<i64>>(),
                 ),
                 &ArrayData::Utf8(ref v) => {
-                    //println!("utf8 len = {}, bools len = {}", v.len(), b.len());
-                    let mut x: Vec<String> = Vec::with_capacity(b.len() as usize);
+                    // the selected bytes are copied, but straight into the new buffers without
+                    // materializing an intermediate String per row
+                    let len = b.iter().filter(|f| *f).count();
+                    let mut builder: ListBuilder<u8> = ListBuilder::with_capacity(len);
+                    let mut bitmap = Bitmap::new(len);
+                    let mut null_count = 0;
+                    let mut j = 0;
                     for i in 0..b.len() as usize {
                         if *b.get(i) {
-                            //println!("i = {}", i);
-                            x.push(String::from_utf8(v.get(i as usize).to_vec()).unwrap());
+                            if !is_valid(arr, i) {
+                                null_count += 1;
+                                bitmap.clear(j);
+                            }
+                            builder.push(v.get(i));
+                            j += 1;
                         }
                     }
-                    Array::from(x)
+                    Array::with_nulls(
+                        len,
+                        ArrayData::Utf8(ListArray::from(builder.finish())),
+                        null_count,
+                        bitmap,
+                    )
                 }
                 &ArrayData::Struct(ref _v) => unimplemented!("Cannot filter on structs yet"),
             },
@@ -201,3 +375,77 @@ pub fn filter(column: &Value, bools: &Array) -> Array {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::builder::*;
+
+    #[test]
+    fn test_filter_utf8_preserves_nulls() {
+        let mut bitmap = Bitmap::new(3);
+        bitmap.clear(2);
+        let mut builder: ListBuilder<u8> = ListBuilder::with_capacity(3);
+        builder.push(b"a");
+        builder.push(b"bc");
+        builder.push(b"");
+        let column = Value::Column(Rc::new(Array::with_nulls(
+            3,
+            ArrayData::Utf8(ListArray::from(builder.finish())),
+            1,
+            bitmap,
+        )));
+        let filtered = filter(&column, &Array::from(vec![false, true, true]));
+        assert_eq!(2, filtered.len());
+        assert_eq!(1, filtered.null_count());
+        assert!(is_valid(&filtered, 0));
+        assert!(!is_valid(&filtered, 1));
+        match *filtered.data() {
+            ArrayData::Utf8(ref list) => assert_eq!(b"bc", list.get(0)),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_min_max() {
+        let mut bitmap = Bitmap::new(3);
+        bitmap.clear(0);
+        let mut builder: Builder<i32> = Builder::with_capacity(3);
+        builder.push(100);
+        builder.push(7);
+        builder.push(3);
+        let array = Array::with_nulls(3, ArrayData::from(builder.finish()), 1, bitmap);
+        assert_eq!(Some((ScalarValue::Int32(3), ScalarValue::Int32(7))), min_max(&array));
+        assert_eq!(None, min_max(&Array::from(vec![1.0, ::std::f64::NAN])));
+        assert_eq!(
+            Some((
+                ScalarValue::Utf8(Rc::new("a".to_string())),
+                ScalarValue::Utf8(Rc::new("c".to_string()))
+            )),
+            min_max(&Array::from(vec!["b", "a", "c"]))
+        );
+    }
+
+    #[test]
+    fn test_column_bound() {
+        let bound = |op| ColumnBound {
+            column: 0,
+            op,
+            value: ScalarValue::Int32(10),
+        };
+        let (min, max) = (ScalarValue::Int32(1), ScalarValue::Int32(10));
+        assert!(bound(Operator::Eq).may_match(&min, &max));
+        assert!(!bound(Operator::Gt).may_match(&min, &max));
+        assert!(bound(Operator::GtEq).may_match(&min, &max));
+        assert!(bound(Operator::Lt).may_match(&min, &max));
+        let (min, max) = (ScalarValue::Int32(11), ScalarValue::Int32(20));
+        assert!(!bound(Operator::Eq).may_match(&min, &max));
+        assert!(!bound(Operator::LtEq).may_match(&min, &max));
+    }
+
+    #[test]
+    fn test_all_set() {
+        assert!(all_set(&Array::from(vec![true, true])));
+        assert!(!all_set(&Array::from(vec![true, false])));
+    }
+}
@@ -0,0 +1,1026 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hash join. The right relation is loaded into a hash table keyed on the join keys and the
+//! left relation is streamed through it. Semi and anti joins use the same hash table but only
+//! check whether a left row has a match, so they never produce duplicate left rows. An inner
+//! join can instead build its hash table on the left relation, when a hint says that the left
+//! relation is the smaller. When both inputs are known to be sorted on the join keys a merge
+//! join is used instead.
+//!
+//! When the build side doesn't fit in the memory quota of the session's resource group, both
+//! inputs of a hash join are spilled to files partitioned by the hash of the join keys, and
+//! each partition of the build side is joined with the same partition of the probe side in
+//! turn.
+
+use std::cell::RefCell;
+use std::iter;
+use std::rc::Rc;
+use std::vec;
+
+use arrow::array::ListArray;
+use arrow::bitmap::Bitmap;
+use arrow::builder::*;
+use arrow::datatypes::*;
+use arrow::list_builder::ListBuilder;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::logical::JoinType;
+use super::super::resource::{batch_memory_size, MemoryReservation};
+use super::super::spill::{SpillFile, SpillManager, SpillPartitions};
+use super::super::types::*;
+use super::coalesce::{concat_batches, is_valid};
+use super::row_format::{encode_rows, Rows};
+use super::row_hash::{hash_keys, HashedKey, RowHashMap};
+use super::runtime_filter::{BloomFilter, SharedBloomFilter};
+
+pub struct HashJoinRelation {
+    left: Box<SimpleRelation>,
+    right: Box<SimpleRelation>,
+    join_type: JoinType,
+    left_keys: Vec<CompiledExpr>,
+    right_keys: Vec<CompiledExpr>,
+    null_equals_null: Vec<bool>,
+    schema: Rc<Schema>,
+    bloom_filter: Option<SharedBloomFilter>,
+    /// Build the hash table on the left relation and stream the right relation through it
+    build_left: bool,
+    /// Memory that the build side is counted against, and the manager of the files that both
+    /// sides are spilled to once the reservation can't grow
+    spill: Option<(MemoryReservation, Rc<SpillManager>)>,
+}
+
+impl HashJoinRelation {
+    pub fn new(
+        left: Box<SimpleRelation>,
+        right: Box<SimpleRelation>,
+        join_type: JoinType,
+        left_keys: Vec<CompiledExpr>,
+        right_keys: Vec<CompiledExpr>,
+        null_equals_null: Vec<bool>,
+        schema: Rc<Schema>,
+    ) -> Self {
+        HashJoinRelation {
+            left,
+            right,
+            join_type,
+            left_keys,
+            right_keys,
+            null_equals_null,
+            schema,
+            bloom_filter: None,
+            build_left: false,
+            spill: None,
+        }
+    }
+
+    /// Build the hash table on the left relation rather than the right. This is only valid
+    /// for inner joins.
+    pub fn with_build_on_left(mut self) -> Self {
+        self.build_left = true;
+        self
+    }
+
+    /// Publish a bloom filter of the build side keys once the hash table has been built, so
+    /// that a `RuntimeFilterRelation` on the probe side can discard rows without a match
+    pub fn with_bloom_filter(mut self, bloom_filter: SharedBloomFilter) -> Self {
+        self.bloom_filter = Some(bloom_filter);
+        self
+    }
+
+    /// Count the build side against a memory reservation, partitioning both sides into files
+    /// of the spill manager rather than failing when it doesn't fit
+    pub fn with_spilling(
+        mut self,
+        reservation: MemoryReservation,
+        spill_manager: Rc<SpillManager>,
+    ) -> Self {
+        self.spill = Some((reservation, spill_manager));
+        self
+    }
+
+    /// Read the build side, keeping it in memory until the reservation can't grow and then
+    /// spilling all of it to partitions
+    fn read_build_side(&mut self) -> Result<BuildInput> {
+        let null_equals_null = &self.null_equals_null;
+        let (build, keys) = if self.build_left {
+            (&mut self.left, &self.left_keys)
+        } else {
+            (&mut self.right, &self.right_keys)
+        };
+        let (reservation, spill_manager) = match self.spill {
+            Some((ref mut reservation, ref spill_manager)) => (reservation, spill_manager),
+            None => return Ok(BuildInput::Batch(collect_build_side(build)?)),
+        };
+        // the build side of an earlier scan is no longer held
+        reservation.release();
+        let schema = Rc::new(build.schema().clone());
+        let mut batches: Vec<Rc<RecordBatch>> = vec![];
+        let mut partitions: Option<SpillPartitions> = None;
+        for batch in build.scan() {
+            let batch = batch?;
+            if batch.num_rows() == 0 {
+                continue;
+            }
+            if partitions.is_none() && reservation.grow(batch_memory_size(batch.as_ref())).is_err()
+            {
+                let mut spilled = SpillPartitions::new(spill_manager.clone(), schema.clone());
+                for b in batches.drain(..) {
+                    spill_batch(&mut spilled, b.as_ref(), keys, null_equals_null)?;
+                }
+                reservation.release();
+                partitions = Some(spilled);
+            }
+            match partitions {
+                Some(ref mut p) => spill_batch(p, batch.as_ref(), keys, null_equals_null)?,
+                None => batches.push(batch),
+            }
+        }
+        match partitions {
+            Some(p) => Ok(BuildInput::Spilled(p.finish()?)),
+            None if batches.is_empty() => Ok(BuildInput::Batch(None)),
+            None => Ok(BuildInput::Batch(Some(concat_batches(&batches)?))),
+        }
+    }
+
+    /// Write the probe side to spill files, partitioned like the build side
+    fn partition_probe_side(&mut self) -> Result<Vec<Option<SpillFile>>> {
+        let spill_manager = match self.spill {
+            Some((_, ref spill_manager)) => spill_manager.clone(),
+            None => {
+                return Err(DataFusionError::Execution(
+                    "Cannot partition a hash join without a spill manager".to_string(),
+                ))
+            }
+        };
+        let null_equals_null = &self.null_equals_null;
+        let (probe, keys) = if self.build_left {
+            (&mut self.right, &self.right_keys)
+        } else {
+            (&mut self.left, &self.left_keys)
+        };
+        let mut partitions = SpillPartitions::new(spill_manager, Rc::new(probe.schema().clone()));
+        for batch in probe.scan() {
+            spill_batch(&mut partitions, batch?.as_ref(), keys, null_equals_null)?;
+        }
+        partitions.finish()
+    }
+
+    /// Build the hash table of a spilled partition of the build side, which has to fit in the
+    /// memory reservation
+    fn load_partition(&mut self, file: Option<SpillFile>) -> Result<HashTable> {
+        let keys = if self.build_left {
+            &self.left_keys
+        } else {
+            &self.right_keys
+        };
+        // the hash table of the previous partition is no longer held
+        if let Some((ref mut reservation, _)) = self.spill {
+            reservation.release();
+        }
+        let mut batches = vec![];
+        if let Some(file) = file {
+            for batch in file.read()? {
+                let batch = batch?;
+                if let Some((ref mut reservation, _)) = self.spill {
+                    reservation.grow(batch_memory_size(batch.as_ref()))?;
+                }
+                batches.push(batch);
+            }
+        }
+        let batch = if batches.is_empty() {
+            None
+        } else {
+            Some(concat_batches(&batches)?)
+        };
+        index_batch(batch, keys, &self.null_equals_null)
+    }
+
+    /// Join a batch of the probe side against the hash table of the build side
+    fn probe(&self, table: &HashTable, batch: &RecordBatch) -> Result<Rc<RecordBatch>> {
+        if self.build_left {
+            probe_swapped(
+                &self.schema,
+                table,
+                batch,
+                &self.right_keys,
+                &self.null_equals_null,
+            )
+        } else {
+            probe_batch(
+                &self.join_type,
+                &self.schema,
+                batch,
+                &self.left_keys,
+                &self.null_equals_null,
+                table,
+            )
+        }
+    }
+
+    /// Join the spilled partitions of the build side with those of the probe side
+    fn scan_partitioned<'a>(
+        &'a mut self,
+        build_files: Vec<Option<SpillFile>>,
+    ) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let probe_files = match self.partition_probe_side() {
+            Ok(files) => files,
+            Err(e) => return Box::new(iter::once(Err(e))),
+        };
+        let partitions: Vec<(Option<SpillFile>, Option<SpillFile>)> =
+            build_files.into_iter().zip(probe_files).collect();
+        Box::new(PartitionedJoinIterator {
+            join: self,
+            partitions: partitions.into_iter(),
+            current: None,
+            done: false,
+        })
+    }
+
+    /// Join the probe side against the hash table of a build side that fits in memory
+    fn scan_in_memory<'a>(
+        &'a mut self,
+        batch: Option<Rc<RecordBatch>>,
+    ) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        if self.build_left {
+            let table = match index_batch(batch, &self.left_keys, &self.null_equals_null) {
+                Ok(t) => t,
+                Err(e) => return Box::new(iter::once(Err(e))),
+            };
+            let right_keys = &self.right_keys;
+            let null_equals_null = &self.null_equals_null;
+            let schema = self.schema.clone();
+            return Box::new(self.right.scan().map(move |b| {
+                probe_swapped(&schema, &table, b?.as_ref(), right_keys, null_equals_null)
+            }));
+        }
+        let table = match index_batch(batch, &self.right_keys, &self.null_equals_null) {
+            Ok(t) => t,
+            Err(e) => return Box::new(iter::once(Err(e))),
+        };
+        if let Some(ref bloom_filter) = self.bloom_filter {
+            let mut filter = BloomFilter::with_capacity(table.rows.len());
+            for key in table.rows.keys() {
+                filter.insert(key.hash);
+            }
+            *bloom_filter.borrow_mut() = Some(filter);
+        }
+        let left_keys = &self.left_keys;
+        let null_equals_null = &self.null_equals_null;
+        let schema = self.schema.clone();
+        let join_type = self.join_type.clone();
+
+        Box::new(self.left.scan().map(move |b| {
+            probe_batch(
+                &join_type,
+                &schema,
+                b?.as_ref(),
+                left_keys,
+                null_equals_null,
+                &table,
+            )
+        }))
+    }
+}
+
+/// The build side of a hash join, either in memory or spilled to partitions
+enum BuildInput {
+    Batch(Option<Rc<RecordBatch>>),
+    Spilled(Vec<Option<SpillFile>>),
+}
+
+/// Write the rows of a batch to the spill partitions of their join keys
+fn spill_batch(
+    partitions: &mut SpillPartitions,
+    batch: &RecordBatch,
+    keys: &[CompiledExpr],
+    null_equals_null: &[bool],
+) -> Result<()> {
+    let key_values = evaluate_keys(keys, batch)?;
+    let hashes = hash_keys(&key_values, batch.num_rows(), null_equals_null);
+    let rows: Vec<usize> = (0..batch.num_rows()).collect();
+    partitions.write(batch, &rows, &hashes)
+}
+
+/// Joins the partitions of a spilled hash join one at a time, so that only the hash table of
+/// one partition of the build side is held in memory
+struct PartitionedJoinIterator<'a> {
+    join: &'a mut HashJoinRelation,
+    /// The remaining pairs of build and probe partitions
+    partitions: vec::IntoIter<(Option<SpillFile>, Option<SpillFile>)>,
+    /// The hash table of the current partition and the batches of its probe side that are
+    /// still to be joined, along with the file that they are read from
+    current: Option<(
+        HashTable,
+        Box<Iterator<Item = Result<Rc<RecordBatch>>>>,
+        SpillFile,
+    )>,
+    done: bool,
+}
+
+impl<'a> PartitionedJoinIterator<'a> {
+    /// Move on to the next partition with probe rows, returning false after the last one
+    fn next_partition(&mut self) -> Result<bool> {
+        // release the hash table of the previous partition before loading the next
+        self.current = None;
+        loop {
+            match self.partitions.next() {
+                Some((build, Some(probe))) => {
+                    let table = self.join.load_partition(build)?;
+                    let batches = probe.read()?;
+                    self.current = Some((table, batches, probe));
+                    return Ok(true);
+                }
+                // a partition without probe rows produces no rows
+                Some((_, None)) => continue,
+                None => return Ok(false),
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for PartitionedJoinIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if let Some((ref table, ref mut batches, _)) = self.current {
+                if let Some(batch) = batches.next() {
+                    let result = match batch {
+                        Ok(b) => self.join.probe(table, b.as_ref()),
+                        Err(e) => Err(e),
+                    };
+                    if result.is_err() {
+                        self.done = true;
+                    }
+                    return Some(result);
+                }
+            }
+            match self.next_partition() {
+                Ok(true) => {}
+                Ok(false) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Rows of the build side, indexed by the hash and encoding of their join keys
+struct HashTable {
+    batch: Option<Rc<RecordBatch>>,
+    rows: RowHashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl HashTable {
+    /// Find the build side rows that match a row of the probe side
+    fn matches(&self, key_values: &[Value], hash: Option<u64>, row: usize) -> &[usize] {
+        let hash = match hash {
+            Some(hash) => hash,
+            None => return &[],
+        };
+        // a row with a hash has no null keys, unless nulls are equal
+        let key = HashedKey {
+            hash,
+            key: encode_key(key_values, row, true).unwrap(),
+        };
+        match self.rows.get(&key) {
+            Some(rows) => rows.as_slice(),
+            None => &[],
+        }
+    }
+}
+
+/// Read all of the right relation into a single batch, or None if it has no rows
+fn collect_build_side(input: &mut Box<SimpleRelation>) -> Result<Option<Rc<RecordBatch>>> {
+    let batches = input
+        .scan()
+        .filter(|b| match *b {
+            Ok(ref b) => b.num_rows() > 0,
+            Err(_) => true,
+        })
+        .collect::<Result<Vec<Rc<RecordBatch>>>>()?;
+    if batches.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(concat_batches(&batches)?))
+    }
+}
+
+/// Build a hash table over the rows of a batch
+fn index_batch(
+    batch: Option<Rc<RecordBatch>>,
+    keys: &[CompiledExpr],
+    null_equals_null: &[bool],
+) -> Result<HashTable> {
+    let mut rows: RowHashMap<Vec<u8>, Vec<usize>> = RowHashMap::default();
+    if let Some(ref batch) = batch {
+        let key_values = evaluate_keys(keys, batch.as_ref())?;
+        let hashes = hash_keys(&key_values, batch.num_rows(), null_equals_null);
+        for (i, hash) in hashes.into_iter().enumerate() {
+            if let Some(hash) = hash {
+                let key = HashedKey {
+                    hash,
+                    key: encode_key(&key_values, i, true).unwrap(),
+                };
+                rows.entry(key).or_insert_with(Vec::new).push(i);
+            }
+        }
+    }
+    Ok(HashTable { batch, rows })
+}
+
+pub fn evaluate_keys(keys: &[CompiledExpr], batch: &RecordBatch) -> Result<Vec<Value>> {
+    keys.iter().map(|k| k(batch)).collect()
+}
+
+/// Encode the join key for a row into bytes that can be compared. Keys on both sides of the
+/// join have already been cast to the same types so values of different types never need to
+/// compare equal. Returns None for keys containing nulls unless nulls are considered equal.
+pub fn encode_key(key_values: &[Value], row: usize, null_equals_null: bool) -> Option<Vec<u8>> {
+    let mut buf = Vec::with_capacity(key_values.len() * 9);
+    for value in key_values {
+        let scalar = match *value {
+            Value::Scalar(ref v) => v.as_ref().clone(),
+            Value::Column(ref arr) => match *arr.validity_bitmap() {
+                Some(ref bitmap) if !bitmap.is_set(row) => ScalarValue::Null,
+                _ => get_value(arr, row),
+            },
+        };
+        if scalar == ScalarValue::Null {
+            if !null_equals_null {
+                return None;
+            }
+            buf.push(0);
+            continue;
+        }
+        buf.push(1);
+        encode_scalar(&scalar, &mut buf);
+    }
+    Some(buf)
+}
+
+fn encode_scalar(value: &ScalarValue, buf: &mut Vec<u8>) {
+    // writing to a Vec cannot fail
+    match *value {
+        ScalarValue::Boolean(b) => buf.push(b as u8),
+        ScalarValue::Int8(n) => buf.write_i64::<LittleEndian>(n as i64).unwrap(),
+        ScalarValue::Int16(n) => buf.write_i64::<LittleEndian>(n as i64).unwrap(),
+        ScalarValue::Int32(n) => buf.write_i64::<LittleEndian>(n as i64).unwrap(),
+        ScalarValue::Int64(n) => buf.write_i64::<LittleEndian>(n).unwrap(),
+        ScalarValue::UInt8(n) => buf.write_u64::<LittleEndian>(n as u64).unwrap(),
+        ScalarValue::UInt16(n) => buf.write_u64::<LittleEndian>(n as u64).unwrap(),
+        ScalarValue::UInt32(n) => buf.write_u64::<LittleEndian>(n as u64).unwrap(),
+        ScalarValue::UInt64(n) => buf.write_u64::<LittleEndian>(n).unwrap(),
+        // normalize negative zero so that 0.0 and -0.0 join
+        ScalarValue::Float32(n) => buf
+            .write_u64::<LittleEndian>((n as f64 + 0.0).to_bits())
+            .unwrap(),
+        ScalarValue::Float64(n) => buf.write_u64::<LittleEndian>((n + 0.0).to_bits()).unwrap(),
+        ScalarValue::Utf8(ref s) => {
+            buf.write_u64::<LittleEndian>(s.len() as u64).unwrap();
+            buf.extend_from_slice(s.as_bytes());
+        }
+        ScalarValue::Struct(ref fields) => {
+            for f in fields {
+                encode_scalar(f, buf);
+            }
+        }
+        ScalarValue::Null => buf.push(0),
+    }
+}
+
+/// Record the output rows for a left row given the right rows that it matches
+fn add_matches(
+    join_type: &JoinType,
+    left_row: usize,
+    matches: &[usize],
+    left_indices: &mut Vec<usize>,
+    right_indices: &mut Vec<usize>,
+) {
+    match *join_type {
+        JoinType::Inner => for j in matches {
+            left_indices.push(left_row);
+            right_indices.push(*j);
+        },
+        JoinType::LeftSemi => if !matches.is_empty() {
+            left_indices.push(left_row);
+        },
+        JoinType::LeftAnti => if matches.is_empty() {
+            left_indices.push(left_row);
+        },
+    }
+}
+
+/// Build an output batch from the selected rows of a left batch and the build side
+fn join_batch(
+    join_type: &JoinType,
+    schema: &Rc<Schema>,
+    left: &RecordBatch,
+    left_indices: &[usize],
+    right: &Option<Rc<RecordBatch>>,
+    right_indices: &[usize],
+) -> Result<Rc<RecordBatch>> {
+    let mut columns: Vec<Value> = left
+        .columns()
+        .iter()
+        .map(|c| take(c, left_indices))
+        .collect::<Result<Vec<Value>>>()?;
+    // semi and anti joins only produce the left columns
+    if *join_type == JoinType::Inner {
+        match *right {
+            Some(ref right) => for c in right.columns() {
+                columns.push(take(c, right_indices)?);
+            },
+            // there are no matches when the build side is empty
+            None => for _ in left.num_columns()..schema.columns().len() {
+                columns.push(Value::Scalar(Rc::new(ScalarValue::Null)));
+            },
+        }
+    }
+    Ok(Rc::new(DefaultRecordBatch {
+        schema: schema.clone(),
+        data: columns,
+        row_count: left_indices.len(),
+    }))
+}
+
+impl SimpleRelation for HashJoinRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        match self.read_build_side() {
+            Ok(BuildInput::Batch(batch)) => self.scan_in_memory(batch),
+            Ok(BuildInput::Spilled(files)) => self.scan_partitioned(files),
+            Err(e) => Box::new(iter::once(Err(e))),
+        }
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+/// Join a batch of the left relation against the hash table of the right relation
+fn probe_batch(
+    join_type: &JoinType,
+    schema: &Rc<Schema>,
+    batch: &RecordBatch,
+    keys: &[CompiledExpr],
+    null_equals_null: &[bool],
+    table: &HashTable,
+) -> Result<Rc<RecordBatch>> {
+    let key_values = evaluate_keys(keys, batch)?;
+    let hashes = hash_keys(&key_values, batch.num_rows(), null_equals_null);
+    let mut left_indices = vec![];
+    let mut right_indices = vec![];
+    for (i, hash) in hashes.into_iter().enumerate() {
+        add_matches(
+            join_type,
+            i,
+            table.matches(&key_values, hash, i),
+            &mut left_indices,
+            &mut right_indices,
+        );
+    }
+    join_batch(
+        join_type,
+        schema,
+        batch,
+        &left_indices,
+        &table.batch,
+        &right_indices,
+    )
+}
+
+/// Counts of the decisions made by adaptive joins
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdaptiveMetrics {
+    /// Number of joins executed adaptively
+    pub joins: usize,
+    /// Number of joins that built the hash table on the left input because it turned out to be
+    /// smaller than the right input
+    pub build_sides_swapped: usize,
+}
+
+/// Inner hash join that chooses which input to build the hash table on once it has seen how
+/// many rows the inputs actually produce, rather than always building on the right input. The
+/// right input is read first, then the left input is read until it is larger than the right
+/// input. If it runs out first the hash table is built on it instead.
+pub struct AdaptiveJoinRelation {
+    left: Box<SimpleRelation>,
+    right: Box<SimpleRelation>,
+    left_keys: Vec<CompiledExpr>,
+    right_keys: Vec<CompiledExpr>,
+    null_equals_null: Vec<bool>,
+    schema: Rc<Schema>,
+    metrics: Rc<RefCell<AdaptiveMetrics>>,
+}
+
+impl AdaptiveJoinRelation {
+    pub fn new(
+        left: Box<SimpleRelation>,
+        right: Box<SimpleRelation>,
+        left_keys: Vec<CompiledExpr>,
+        right_keys: Vec<CompiledExpr>,
+        null_equals_null: Vec<bool>,
+        schema: Rc<Schema>,
+        metrics: Rc<RefCell<AdaptiveMetrics>>,
+    ) -> Self {
+        AdaptiveJoinRelation {
+            left,
+            right,
+            left_keys,
+            right_keys,
+            null_equals_null,
+            schema,
+            metrics,
+        }
+    }
+}
+
+impl SimpleRelation for AdaptiveJoinRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let right = match collect_build_side(&mut self.right) {
+            Ok(b) => b,
+            Err(e) => return Box::new(iter::once(Err(e))),
+        };
+        let right_rows = right.as_ref().map(|b| b.num_rows()).unwrap_or(0);
+
+        // read just enough of the left input to know which input is smaller
+        let mut left_iter = self.left.scan();
+        let mut buffered: Vec<Rc<RecordBatch>> = vec![];
+        let mut left_rows = 0;
+        let mut exhausted = false;
+        while left_rows <= right_rows {
+            match left_iter.next() {
+                Some(Ok(batch)) => {
+                    left_rows += batch.num_rows();
+                    buffered.push(batch);
+                }
+                Some(Err(e)) => return Box::new(iter::once(Err(e))),
+                None => {
+                    exhausted = true;
+                    break;
+                }
+            }
+        }
+
+        let swap = exhausted && left_rows < right_rows;
+        {
+            let mut metrics = self.metrics.borrow_mut();
+            metrics.joins += 1;
+            if swap {
+                metrics.build_sides_swapped += 1;
+            }
+        }
+        if swap {
+            return Box::new(iter::once(join_swapped(
+                &self.schema,
+                buffered,
+                &self.left_keys,
+                right,
+                &self.right_keys,
+                &self.null_equals_null,
+            )));
+        }
+
+        let table = match index_batch(right, &self.right_keys, &self.null_equals_null) {
+            Ok(t) => t,
+            Err(e) => return Box::new(iter::once(Err(e))),
+        };
+        let left_keys = &self.left_keys;
+        let null_equals_null = &self.null_equals_null;
+        let schema = self.schema.clone();
+        Box::new(
+            buffered
+                .into_iter()
+                .map(Ok)
+                .chain(left_iter)
+                .map(move |b| {
+                    probe_batch(
+                        &JoinType::Inner,
+                        &schema,
+                        b?.as_ref(),
+                        left_keys,
+                        null_equals_null,
+                        &table,
+                    )
+                }),
+        )
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+/// Inner join with the hash table built on the left rows and the right rows probing it
+fn join_swapped(
+    schema: &Rc<Schema>,
+    left: Vec<Rc<RecordBatch>>,
+    left_keys: &[CompiledExpr],
+    right: Option<Rc<RecordBatch>>,
+    right_keys: &[CompiledExpr],
+    null_equals_null: &[bool],
+) -> Result<Rc<RecordBatch>> {
+    let left = if left.is_empty() {
+        None
+    } else {
+        Some(concat_batches(&left)?)
+    };
+    let table = index_batch(left, left_keys, null_equals_null)?;
+    match right {
+        Some(ref right) => {
+            probe_swapped(schema, &table, right.as_ref(), right_keys, null_equals_null)
+        }
+        None => Ok(empty_batch(schema)),
+    }
+}
+
+/// Inner join of a batch of right rows against a hash table built on the left rows, producing
+/// the left columns followed by the right columns
+fn probe_swapped(
+    schema: &Rc<Schema>,
+    table: &HashTable,
+    right: &RecordBatch,
+    right_keys: &[CompiledExpr],
+    null_equals_null: &[bool],
+) -> Result<Rc<RecordBatch>> {
+    let left = match table.batch {
+        Some(ref left) => left,
+        // there are no matches when the left input is empty
+        None => return Ok(empty_batch(schema)),
+    };
+    let key_values = evaluate_keys(right_keys, right)?;
+    let hashes = hash_keys(&key_values, right.num_rows(), null_equals_null);
+    let mut left_indices = vec![];
+    let mut right_indices = vec![];
+    for (j, hash) in hashes.into_iter().enumerate() {
+        for i in table.matches(&key_values, hash, j) {
+            left_indices.push(*i);
+            right_indices.push(j);
+        }
+    }
+    let mut columns = vec![];
+    for c in left.columns() {
+        columns.push(take(c, &left_indices)?);
+    }
+    for c in right.columns() {
+        columns.push(take(c, &right_indices)?);
+    }
+    Ok(Rc::new(DefaultRecordBatch {
+        schema: schema.clone(),
+        data: columns,
+        row_count: left_indices.len(),
+    }))
+}
+
+/// A batch without rows
+fn empty_batch(schema: &Rc<Schema>) -> Rc<RecordBatch> {
+    Rc::new(DefaultRecordBatch {
+        schema: schema.clone(),
+        data: schema
+            .columns()
+            .iter()
+            .map(|_| Value::Scalar(Rc::new(ScalarValue::Null)))
+            .collect(),
+        row_count: 0,
+    })
+}
+
+/// Join of two relations that are both sorted in ascending order on the join keys. The right
+/// relation is loaded into memory and the left relation is streamed, with a cursor into the
+/// right rows that only moves forward, so no hash table is needed. Null keys never match.
+pub struct SortMergeJoinRelation {
+    left: Box<SimpleRelation>,
+    right: Box<SimpleRelation>,
+    join_type: JoinType,
+    left_keys: Vec<CompiledExpr>,
+    right_keys: Vec<CompiledExpr>,
+    schema: Rc<Schema>,
+}
+
+impl SortMergeJoinRelation {
+    pub fn new(
+        left: Box<SimpleRelation>,
+        right: Box<SimpleRelation>,
+        join_type: JoinType,
+        left_keys: Vec<CompiledExpr>,
+        right_keys: Vec<CompiledExpr>,
+        schema: Rc<Schema>,
+    ) -> Self {
+        SortMergeJoinRelation {
+            left,
+            right,
+            join_type,
+            left_keys,
+            right_keys,
+            schema,
+        }
+    }
+}
+
+/// Encode the join keys of a batch in ascending order, so that they compare as bytes
+fn encode_join_keys(key_values: &[Value], num_rows: usize) -> Rows {
+    let asc = vec![true; key_values.len()];
+    encode_rows(key_values, &asc, &asc, num_rows)
+}
+
+/// Check whether a right row comes before a left key, treating null keys as skippable
+fn precedes(right: &Rows, row: usize, key: &[u8]) -> bool {
+    right.has_null(row) || right.row(row) < key
+}
+
+impl SimpleRelation for SortMergeJoinRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let right = match collect_build_side(&mut self.right) {
+            Ok(b) => b,
+            Err(e) => return Box::new(iter::once(Err(e))),
+        };
+        let right_rows = match right {
+            Some(ref batch) => match evaluate_keys(&self.right_keys, batch.as_ref()) {
+                Ok(values) => encode_join_keys(&values, batch.num_rows()),
+                Err(e) => return Box::new(iter::once(Err(e))),
+            },
+            None => Rows::default(),
+        };
+        let left_keys = &self.left_keys;
+        let schema = self.schema.clone();
+        let join_type = self.join_type.clone();
+        // position of the first right row that could match the current left key
+        let mut cursor = 0;
+
+        Box::new(self.left.scan().map(move |b| {
+            let batch = b?;
+            let key_values = evaluate_keys(left_keys, batch.as_ref())?;
+            let keys = encode_join_keys(&key_values, batch.num_rows());
+            let mut left_indices = vec![];
+            let mut right_indices = vec![];
+            let mut matches = vec![];
+            for i in 0..batch.num_rows() {
+                matches.clear();
+                if !keys.has_null(i) {
+                    let key = keys.row(i);
+                    let right_count = right_rows.num_rows();
+                    while cursor < right_count && precedes(&right_rows, cursor, key) {
+                        cursor += 1;
+                    }
+                    // the cursor stays at the start of the run of equal keys, since the next
+                    // left row may have the same key
+                    let mut j = cursor;
+                    while j < right_count {
+                        if !right_rows.has_null(j) {
+                            if right_rows.row(j) != key {
+                                break;
+                            }
+                            matches.push(j);
+                        }
+                        j += 1;
+                    }
+                }
+                add_matches(
+                    &join_type,
+                    i,
+                    &matches,
+                    &mut left_indices,
+                    &mut right_indices,
+                );
+            }
+            join_batch(
+                &join_type,
+                &schema,
+                batch.as_ref(),
+                &left_indices,
+                &right,
+                &right_indices,
+            )
+        }))
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+macro_rules! take_primitive {
+    ($ARRAY:expr, $VALUES:expr, $INDICES:expr, $TY:ty) => {{
+        let mut b: Builder<$TY> = Builder::with_capacity($INDICES.len());
+        let mut bitmap = Bitmap::new($INDICES.len());
+        let mut null_count = 0;
+        for (i, index) in $INDICES.iter().enumerate() {
+            if !is_valid($ARRAY, *index) {
+                null_count += 1;
+                bitmap.clear(i);
+            }
+            b.push(*$VALUES.get(*index));
+        }
+        Array::with_nulls(
+            $INDICES.len(),
+            ArrayData::from(b.finish()),
+            null_count,
+            bitmap,
+        )
+    }};
+}
+
+/// Select the rows at the given indices from a column, in the order of the indices
+pub fn take(column: &Value, indices: &[usize]) -> Result<Value> {
+    let array = match *column {
+        // scalars are placeholders for columns that were not projected
+        Value::Scalar(ref v) => return Ok(Value::Scalar(v.clone())),
+        Value::Column(ref arr) => arr.as_ref(),
+    };
+    let taken = match *array.data() {
+        ArrayData::Boolean(ref v) => take_primitive!(array, v, indices, bool),
+        ArrayData::Int8(ref v) => take_primitive!(array, v, indices, i8),
+        ArrayData::Int16(ref v) => take_primitive!(array, v, indices, i16),
+        ArrayData::Int32(ref v) => take_primitive!(array, v, indices, i32),
+        ArrayData::Int64(ref v) => take_primitive!(array, v, indices, i64),
+        ArrayData::UInt8(ref v) => take_primitive!(array, v, indices, u8),
+        ArrayData::UInt16(ref v) => take_primitive!(array, v, indices, u16),
+        ArrayData::UInt32(ref v) => take_primitive!(array, v, indices, u32),
+        ArrayData::UInt64(ref v) => take_primitive!(array, v, indices, u64),
+        ArrayData::Float32(ref v) => take_primitive!(array, v, indices, f32),
+        ArrayData::Float64(ref v) => take_primitive!(array, v, indices, f64),
+        ArrayData::Utf8(ref list) => {
+            let mut b: ListBuilder<u8> = ListBuilder::with_capacity(indices.len());
+            let mut bitmap = Bitmap::new(indices.len());
+            let mut null_count = 0;
+            for (i, index) in indices.iter().enumerate() {
+                if !is_valid(array, *index) {
+                    null_count += 1;
+                    bitmap.clear(i);
+                }
+                b.push(list.get(*index));
+            }
+            Array::with_nulls(
+                indices.len(),
+                ArrayData::Utf8(ListArray::from(b.finish())),
+                null_count,
+                bitmap,
+            )
+        }
+        ArrayData::Struct(_) => {
+            return Err(DataFusionError::Execution(
+                "Cannot join on relations containing structs yet".to_string(),
+            ))
+        }
+    };
+    Ok(Value::Column(Rc::new(taken)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_key_nulls() {
+        let mut bitmap = Bitmap::new(2);
+        bitmap.clear(1);
+        let mut b: Builder<i32> = Builder::with_capacity(2);
+        b.push(7);
+        b.push(0);
+        let keys = vec![Value::Column(Rc::new(Array::with_nulls(
+            2,
+            ArrayData::from(b.finish()),
+            1,
+            bitmap,
+        )))];
+        assert!(encode_key(&keys, 0, false).is_some());
+        assert_eq!(None, encode_key(&keys, 1, false));
+        assert_eq!(Some(vec![0]), encode_key(&keys, 1, true));
+    }
+
+    #[test]
+    fn test_take() {
+        let column = Value::Column(Rc::new(Array::from(vec!["a", "b", "c"])));
+        match take(&column, &[2, 0, 2]).unwrap() {
+            Value::Column(ref arr) => match *arr.data() {
+                ArrayData::Utf8(ref list) => {
+                    assert_eq!(3, arr.len());
+                    assert_eq!(b"c", list.get(0));
+                    assert_eq!(b"a", list.get(1));
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lateral Join Relation
+//!
+//! The subquery of a lateral join is evaluated once for each row of the left input, with that
+//! row's values in place of its outer column references. A subquery that doesn't reference the
+//! left row is evaluated once and its rows are joined to every left row.
+
+use std::rc::Rc;
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::types::*;
+
+use arrow::datatypes::*;
+
+/// Evaluates the subquery of a lateral join for the given values of the left row
+pub type SubqueryFn = Rc<Fn(Vec<ScalarValue>) -> Result<Vec<Rc<RecordBatch>>>>;
+
+pub struct LateralJoinRelation {
+    left: Box<SimpleRelation>,
+    subquery: SubqueryFn,
+    left_outer: bool,
+    correlated: bool,
+    schema: Rc<Schema>,
+    /// Rows of an uncorrelated subquery, once it has been evaluated
+    uncorrelated_rows: Option<Vec<Vec<ScalarValue>>>,
+}
+
+impl LateralJoinRelation {
+    pub fn new(
+        left: Box<SimpleRelation>,
+        subquery: SubqueryFn,
+        left_outer: bool,
+        correlated: bool,
+        schema: Rc<Schema>,
+    ) -> Self {
+        LateralJoinRelation {
+            left,
+            subquery,
+            left_outer,
+            correlated,
+            schema,
+            uncorrelated_rows: None,
+        }
+    }
+}
+
+impl SimpleRelation for LateralJoinRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let subquery = self.subquery.clone();
+        let left_outer = self.left_outer;
+        let correlated = self.correlated;
+        let schema = self.schema.clone();
+        let uncorrelated_rows = &mut self.uncorrelated_rows;
+        Box::new(self.left.scan().map(move |batch| {
+            let batch = batch?;
+            let left_columns = batch.num_columns();
+            let subquery_columns = schema.columns().len() - left_columns;
+            let mut rows: Vec<Vec<ScalarValue>> = vec![];
+            for i in 0..batch.num_rows() {
+                let left_row: Vec<ScalarValue> = batch
+                    .columns()
+                    .iter()
+                    .map(|column| value_at(column, i))
+                    .collect();
+                let correlated_rows;
+                let subquery_rows = if correlated {
+                    correlated_rows = batch_rows(&subquery(left_row.clone())?);
+                    &correlated_rows
+                } else {
+                    if uncorrelated_rows.is_none() {
+                        *uncorrelated_rows = Some(batch_rows(&subquery(vec![])?));
+                    }
+                    uncorrelated_rows.as_ref().unwrap()
+                };
+                if subquery_rows.is_empty() && left_outer {
+                    let mut row = left_row.clone();
+                    row.extend(vec![ScalarValue::Null; subquery_columns]);
+                    rows.push(row);
+                }
+                for subquery_row in subquery_rows {
+                    let mut row = left_row.clone();
+                    row.extend(subquery_row.iter().cloned());
+                    rows.push(row);
+                }
+            }
+
+            let mut columns = Vec::with_capacity(schema.columns().len());
+            for (i, field) in schema.columns().iter().enumerate() {
+                let values: Vec<ScalarValue> = rows.iter().map(|row| row[i].clone()).collect();
+                let array = array_from_scalars(&values, field.data_type())?;
+                columns.push(Value::Column(Rc::new(array)));
+            }
+            let batch: Rc<RecordBatch> = Rc::new(DefaultRecordBatch {
+                schema: schema.clone(),
+                data: columns,
+                row_count: rows.len(),
+            });
+            Ok(batch)
+        }))
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+/// Get the values of every row in a list of batches
+fn batch_rows(batches: &[Rc<RecordBatch>]) -> Vec<Vec<ScalarValue>> {
+    let mut rows = vec![];
+    for batch in batches {
+        for i in 0..batch.num_rows() {
+            rows.push(
+                batch
+                    .columns()
+                    .iter()
+                    .map(|column| value_at(column, i))
+                    .collect(),
+            );
+        }
+    }
+    rows
+}
@@ -13,6 +13,21 @@
 // limitations under the License.
 
 pub mod aggregate;
+pub mod coalesce;
+pub mod dedup;
 pub mod filter;
+pub mod join;
+pub mod lateral;
 pub mod limit;
+pub mod parallel_dedup;
+pub mod parallel_scan;
 pub mod projection;
+pub mod row_format;
+pub mod row_hash;
+pub mod runtime_filter;
+pub mod sample;
+pub mod sink;
+pub mod sort;
+pub mod time_window;
+pub mod union;
+pub mod window;
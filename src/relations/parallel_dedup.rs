@@ -0,0 +1,219 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parallel Deduplicate Relation
+//!
+//! Keeps one row for each distinct key, as for `SELECT DISTINCT` and `COUNT(DISTINCT x)`, with
+//! the keys hash-partitioned across a number of threads. The input is read on the query's
+//! thread, which sends each row to the worker that owns the hash of its key, so each worker
+//! only holds the keys of its own partition and the hash sets are built in parallel. Rows are
+//! output partition by partition, each in the order that its keys were first seen.
+
+use std::iter;
+use std::rc::Rc;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+
+use arrow::datatypes::*;
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::logical::DeduplicateKeep;
+use super::super::types::*;
+use super::join::{encode_key, evaluate_keys};
+use super::parallel_scan::SendValue;
+use super::row_hash::{hash_rows, partition_of, HashedKey, RowHashMap};
+
+/// Number of chunks of rows that can be waiting for each worker
+const PREFETCH_CHUNKS: usize = 2;
+
+/// The rows of a batch that belong to a partition, each with its hashed and encoded key
+type Chunk = Vec<(HashedKey<Vec<u8>>, Vec<SendValue>)>;
+
+pub struct ParallelDeduplicateRelation {
+    input: Box<SimpleRelation>,
+    keys: Vec<CompiledExpr>,
+    keep: DeduplicateKeep,
+    partitions: usize,
+    schema: Rc<Schema>,
+}
+
+impl ParallelDeduplicateRelation {
+    /// Create a deduplication that partitions the keys across `partitions` threads
+    pub fn new(
+        input: Box<SimpleRelation>,
+        keys: Vec<CompiledExpr>,
+        keep: DeduplicateKeep,
+        partitions: usize,
+        schema: Rc<Schema>,
+    ) -> Self {
+        ParallelDeduplicateRelation {
+            input,
+            keys,
+            keep,
+            partitions: partitions.max(1),
+            schema,
+        }
+    }
+
+    fn evaluate(&mut self) -> Result<Option<Rc<RecordBatch>>> {
+        let keep_last = self.keep == DeduplicateKeep::Last;
+        let mut senders = Vec::with_capacity(self.partitions);
+        let mut workers = Vec::with_capacity(self.partitions);
+        for _ in 0..self.partitions {
+            let (sender, receiver) = sync_channel(PREFETCH_CHUNKS);
+            senders.push(sender);
+            workers.push(thread::spawn(move || {
+                deduplicate_partition(receiver, keep_last)
+            }));
+        }
+
+        // returning early drops the senders, which stops the workers
+        for batch in self.input.scan() {
+            let batch = batch?;
+            let key_values = evaluate_keys(&self.keys, batch.as_ref())?;
+            // null keys are equal to each other, as in GROUP BY
+            let hashes = hash_rows(&key_values, batch.num_rows(), true);
+            let mut chunks: Vec<Chunk> = (0..self.partitions).map(|_| vec![]).collect();
+            for row in 0..batch.num_rows() {
+                let key = HashedKey {
+                    hash: hashes[row].unwrap(),
+                    key: encode_key(&key_values, row, true).unwrap(),
+                };
+                let partition = partition_of(key.hash, self.partitions);
+                let values = batch
+                    .columns()
+                    .iter()
+                    .map(|c| SendValue::from(value_at(c, row)))
+                    .collect();
+                chunks[partition].push((key, values));
+            }
+            for (sender, chunk) in senders.iter().zip(chunks) {
+                if !chunk.is_empty() && sender.send(chunk).is_err() {
+                    return Err(DataFusionError::Execution(String::from(
+                        "Deduplicate worker stopped unexpectedly",
+                    )));
+                }
+            }
+        }
+        drop(senders);
+
+        let mut kept: Vec<Vec<ScalarValue>> = vec![];
+        for worker in workers {
+            let rows = worker.join().map_err(|_| {
+                DataFusionError::Execution(String::from("Deduplicate worker panicked"))
+            })?;
+            kept.extend(
+                rows.into_iter()
+                    .map(|row| row.into_iter().map(ScalarValue::from).collect()),
+            );
+        }
+
+        if kept.is_empty() {
+            return Ok(None);
+        }
+        let mut columns = Vec::with_capacity(self.schema.columns().len());
+        for (i, field) in self.schema.columns().iter().enumerate() {
+            let values: Vec<ScalarValue> = kept.iter().map(|r| r[i].clone()).collect();
+            columns.push(Value::Column(Rc::new(array_from_scalars(
+                &values,
+                field.data_type(),
+            )?)));
+        }
+        Ok(Some(Rc::new(DefaultRecordBatch {
+            schema: self.schema.clone(),
+            data: columns,
+            row_count: kept.len(),
+        })))
+    }
+}
+
+/// Keep one row for each key of a partition, in the order the keys were first seen
+fn deduplicate_partition(receiver: Receiver<Chunk>, keep_last: bool) -> Vec<Vec<SendValue>> {
+    let mut index: RowHashMap<Vec<u8>, usize> = RowHashMap::default();
+    let mut kept: Vec<Vec<SendValue>> = vec![];
+    for chunk in receiver {
+        for (key, values) in chunk {
+            match index.get(&key) {
+                Some(&i) => {
+                    if keep_last {
+                        kept[i] = values;
+                    }
+                }
+                None => {
+                    index.insert(key, kept.len());
+                    kept.push(values);
+                }
+            }
+        }
+    }
+    kept
+}
+
+impl SimpleRelation for ParallelDeduplicateRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        match self.evaluate() {
+            Ok(Some(batch)) => Box::new(iter::once(Ok(batch))),
+            Ok(None) => Box::new(iter::empty()),
+            Err(e) => Box::new(iter::once(Err(e))),
+        }
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(rows: Vec<(u8, i64)>) -> Chunk {
+        rows.into_iter()
+            .map(|(key, value)| {
+                let key = HashedKey {
+                    hash: key as u64,
+                    key: vec![key],
+                };
+                (key, vec![SendValue::Int64(value)])
+            })
+            .collect()
+    }
+
+    fn values(rows: Vec<Vec<SendValue>>) -> Vec<i64> {
+        rows.into_iter()
+            .map(
+                |row| match ScalarValue::from(row.into_iter().next().unwrap()) {
+                    ScalarValue::Int64(v) => v,
+                    other => panic!("unexpected value {:?}", other),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn test_deduplicate_partition() {
+        for &(keep_last, ref expected) in &[(false, vec![1, 2, 4]), (true, vec![5, 3, 4])] {
+            let (sender, receiver) = sync_channel(2);
+            sender.send(chunk(vec![(1, 1), (2, 2), (2, 3)])).unwrap();
+            sender.send(chunk(vec![(3, 4), (1, 5)])).unwrap();
+            drop(sender);
+            assert_eq!(
+                *expected,
+                values(deduplicate_partition(receiver, keep_last))
+            );
+        }
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parallel Scan Relation
+//!
+//! Reads the partitions of a scan, which are whole files or single row groups of Parquet
+//! files, on a pool of threads so that reading and decoding files overlaps. Record batches
+//! share their data with `Rc` and can't be sent between threads, so the workers send the values
+//! of each batch and the arrays are built again on the scanning thread. Batches are produced in
+//! partition order, so the results are the same as those of a sequential scan.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use arrow::datatypes::*;
+
+use super::super::datasources::common::*;
+use super::super::datasources::csv::CsvFile;
+use super::super::datasources::infer::ValueFormats;
+use super::super::datasources::ndjson::NdJsonFile;
+use super::super::datasources::parquet::ParquetFile;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::resource::ThreadPermit;
+use super::super::types::*;
+
+/// Number of batches that each partition can read ahead of the scan
+const PREFETCH_BATCHES: usize = 2;
+
+/// How the file of a partition is read
+#[derive(Debug, Clone)]
+pub enum ScanFormat {
+    Csv {
+        has_header: bool,
+        formats: ValueFormats,
+        error_policy: ScanErrorPolicy,
+    },
+    NdJson,
+    /// Only the given row groups of the file are read
+    Parquet {
+        row_groups: Vec<usize>,
+    },
+}
+
+/// A part of a scan that can be read independently of the others
+#[derive(Debug, Clone)]
+pub struct ScanPartition {
+    pub filename: String,
+    pub schema: Schema,
+    pub projection: Option<Vec<usize>>,
+    pub format: ScanFormat,
+    pub batch_size: usize,
+}
+
+impl ScanPartition {
+    /// Open the data source for this partition on the thread that will read it
+    fn open(&self, metrics: Rc<RefCell<ScanMetrics>>) -> Result<Box<DataSource>> {
+        let file = File::open(&self.filename)?;
+        let schema = Rc::new(self.schema.clone());
+        let projection = self.projection.clone();
+        match self.format {
+            ScanFormat::Csv {
+                has_header,
+                ref formats,
+                error_policy,
+            } => {
+                let mut csv = CsvFile::open(file, schema, has_header, projection)?;
+                csv.set_error_policy(error_policy, metrics);
+                csv.set_value_formats(Rc::new(formats.clone()));
+                csv.set_batch_size(self.batch_size);
+                Ok(Box::new(csv))
+            }
+            ScanFormat::NdJson => {
+                let mut json = NdJsonFile::open(file, schema, projection)?;
+                json.set_batch_size(self.batch_size);
+                Ok(Box::new(json))
+            }
+            ScanFormat::Parquet { ref row_groups } => {
+                let mut parquet = ParquetFile::open(file, projection)?;
+                parquet.set_row_groups(row_groups.clone());
+                parquet.set_batch_size(self.batch_size);
+                Ok(Box::new(parquet))
+            }
+        }
+    }
+
+    /// Whether a column is read, rather than left out by the projection
+    fn is_loaded(&self, column: usize) -> bool {
+        match self.projection {
+            Some(ref p) => p.contains(&column),
+            None => true,
+        }
+    }
+}
+
+/// A copy of a `ScalarValue` that can be sent between threads
+#[derive(Debug)]
+pub enum SendValue {
+    Null,
+    Boolean(bool),
+    Float32(f32),
+    Float64(f64),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    UInt8(u8),
+    UInt16(u16),
+    UInt32(u32),
+    UInt64(u64),
+    Utf8(String),
+    Struct(Vec<SendValue>),
+}
+
+impl From<ScalarValue> for SendValue {
+    fn from(value: ScalarValue) -> Self {
+        match value {
+            ScalarValue::Null => SendValue::Null,
+            ScalarValue::Boolean(v) => SendValue::Boolean(v),
+            ScalarValue::Float32(v) => SendValue::Float32(v),
+            ScalarValue::Float64(v) => SendValue::Float64(v),
+            ScalarValue::Int8(v) => SendValue::Int8(v),
+            ScalarValue::Int16(v) => SendValue::Int16(v),
+            ScalarValue::Int32(v) => SendValue::Int32(v),
+            ScalarValue::Int64(v) => SendValue::Int64(v),
+            ScalarValue::UInt8(v) => SendValue::UInt8(v),
+            ScalarValue::UInt16(v) => SendValue::UInt16(v),
+            ScalarValue::UInt32(v) => SendValue::UInt32(v),
+            ScalarValue::UInt64(v) => SendValue::UInt64(v),
+            ScalarValue::Utf8(v) => SendValue::Utf8(v.as_ref().clone()),
+            ScalarValue::Struct(v) => {
+                SendValue::Struct(v.into_iter().map(SendValue::from).collect())
+            }
+        }
+    }
+}
+
+impl From<SendValue> for ScalarValue {
+    fn from(value: SendValue) -> Self {
+        match value {
+            SendValue::Null => ScalarValue::Null,
+            SendValue::Boolean(v) => ScalarValue::Boolean(v),
+            SendValue::Float32(v) => ScalarValue::Float32(v),
+            SendValue::Float64(v) => ScalarValue::Float64(v),
+            SendValue::Int8(v) => ScalarValue::Int8(v),
+            SendValue::Int16(v) => ScalarValue::Int16(v),
+            SendValue::Int32(v) => ScalarValue::Int32(v),
+            SendValue::Int64(v) => ScalarValue::Int64(v),
+            SendValue::UInt8(v) => ScalarValue::UInt8(v),
+            SendValue::UInt16(v) => ScalarValue::UInt16(v),
+            SendValue::UInt32(v) => ScalarValue::UInt32(v),
+            SendValue::UInt64(v) => ScalarValue::UInt64(v),
+            SendValue::Utf8(v) => ScalarValue::Utf8(Rc::new(v)),
+            SendValue::Struct(v) => {
+                ScalarValue::Struct(v.into_iter().map(ScalarValue::from).collect())
+            }
+        }
+    }
+}
+
+/// What a worker sends for a partition
+enum Message {
+    /// The values of each column of a batch, or `None` for columns that weren't read
+    Batch {
+        columns: Vec<Option<Vec<SendValue>>>,
+        row_count: usize,
+    },
+    /// The partition has been read, with any bad data that the scan skipped or replaced
+    Done(ScanMetrics),
+    Error(String),
+}
+
+type PartitionQueue = Arc<Mutex<VecDeque<(ScanPartition, SyncSender<Message>)>>>;
+
+pub struct ParallelScanRelation {
+    partitions: Vec<ScanPartition>,
+    schema: Rc<Schema>,
+    max_concurrent_files: usize,
+    metrics: Rc<RefCell<ScanMetrics>>,
+    /// Threads taken from a resource group, which are held until the scan is dropped
+    permit: Option<ThreadPermit>,
+}
+
+impl ParallelScanRelation {
+    /// Create a scan of the partitions that reads at most `max_concurrent_files` of them at a
+    /// time. Bad data found by the partitions is added to `metrics`.
+    pub fn new(
+        partitions: Vec<ScanPartition>,
+        schema: Rc<Schema>,
+        max_concurrent_files: usize,
+        metrics: Rc<RefCell<ScanMetrics>>,
+    ) -> Self {
+        ParallelScanRelation {
+            partitions,
+            schema,
+            max_concurrent_files,
+            metrics,
+            permit: None,
+        }
+    }
+
+    /// Hold threads taken from a resource group for as long as the scan exists
+    pub fn with_thread_permit(mut self, permit: ThreadPermit) -> Self {
+        self.permit = Some(permit);
+        self
+    }
+}
+
+impl SimpleRelation for ParallelScanRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let mut queue = VecDeque::with_capacity(self.partitions.len());
+        let mut receivers = VecDeque::with_capacity(self.partitions.len());
+        for partition in &self.partitions {
+            let (sender, receiver) = sync_channel(PREFETCH_BATCHES);
+            queue.push_back((partition.clone(), sender));
+            receivers.push_back(receiver);
+        }
+
+        // each worker reads one partition at a time, taking them in order, so a partition is
+        // never waiting on one that comes after it
+        let queue: PartitionQueue = Arc::new(Mutex::new(queue));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let workers = self.max_concurrent_files.max(1).min(self.partitions.len());
+        for _ in 0..workers {
+            let queue = queue.clone();
+            let cancelled = cancelled.clone();
+            thread::spawn(move || run_worker(&queue, &cancelled));
+        }
+
+        Box::new(ParallelScan {
+            receivers,
+            schema: self.schema.clone(),
+            metrics: self.metrics.clone(),
+            cancelled,
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+/// Read partitions from the queue until it is empty or the scan is dropped
+fn run_worker(queue: &PartitionQueue, cancelled: &AtomicBool) {
+    loop {
+        let (partition, sender) = match queue.lock().unwrap().pop_front() {
+            Some(next) => next,
+            None => return,
+        };
+        if cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+        let metrics = Rc::new(RefCell::new(ScanMetrics::default()));
+        let message = match read_partition(&partition, metrics.clone(), &sender, cancelled) {
+            Ok(()) => Message::Done(metrics.borrow().clone()),
+            Err(e) => Message::Error(e.to_string()),
+        };
+        // the receiver is gone when the scan stopped early
+        let _ = sender.send(message);
+    }
+}
+
+fn read_partition(
+    partition: &ScanPartition,
+    metrics: Rc<RefCell<ScanMetrics>>,
+    sender: &SyncSender<Message>,
+    cancelled: &AtomicBool,
+) -> Result<()> {
+    let mut ds = partition.open(metrics)?;
+    while let Some(batch) = ds.next() {
+        if cancelled.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let batch = batch?;
+        let columns = batch
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                if partition.is_loaded(i) {
+                    Some(
+                        (0..batch.num_rows())
+                            .map(|row| SendValue::from(value_at(column, row)))
+                            .collect(),
+                    )
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let message = Message::Batch {
+            columns,
+            row_count: batch.num_rows(),
+        };
+        if sender.send(message).is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// The batches of each partition in turn
+struct ParallelScan {
+    receivers: VecDeque<Receiver<Message>>,
+    schema: Rc<Schema>,
+    metrics: Rc<RefCell<ScanMetrics>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Iterator for ParallelScan {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let message = self.receivers.front()?.recv();
+            match message {
+                Ok(Message::Batch { columns, row_count }) => {
+                    return Some(build_batch(&self.schema, columns, row_count))
+                }
+                Ok(Message::Done(metrics)) => {
+                    self.metrics.borrow_mut().merge(metrics);
+                    self.receivers.pop_front();
+                }
+                Ok(Message::Error(msg)) => {
+                    self.receivers.pop_front();
+                    return Some(Err(DataFusionError::Execution(msg)));
+                }
+                Err(_) => {
+                    self.receivers.pop_front();
+                    return Some(Err(DataFusionError::Execution(
+                        "Scan thread stopped before reading its partition".to_string(),
+                    )));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ParallelScan {
+    fn drop(&mut self) {
+        // stop the workers from reading partitions that nobody will see
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+fn build_batch(
+    schema: &Rc<Schema>,
+    columns: Vec<Option<Vec<SendValue>>>,
+    row_count: usize,
+) -> Result<Rc<RecordBatch>> {
+    let mut data = Vec::with_capacity(columns.len());
+    for (field, column) in schema.columns().iter().zip(columns) {
+        data.push(match column {
+            Some(values) => {
+                let values: Vec<ScalarValue> = values.into_iter().map(ScalarValue::from).collect();
+                Value::Column(Rc::new(array_from_scalars(&values, field.data_type())?))
+            }
+            None => Value::Scalar(Rc::new(ScalarValue::Null)),
+        });
+    }
+    Ok(Rc::new(DefaultRecordBatch {
+        schema: schema.clone(),
+        data,
+        row_count,
+    }))
+}
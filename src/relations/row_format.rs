@@ -0,0 +1,408 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Row Format
+//!
+//! Encodes the keys of each row into bytes that compare in the same order as the keys, so that
+//! rows can be sorted and merged by comparing byte slices instead of comparing each key in
+//! turn. Each key is encoded as:
+//!
+//! - a flag byte, which is 1 for values and 0 for null, so that nulls come first, or 2 for
+//!   null when nulls sort after other values
+//! - booleans as a single byte
+//! - integers as 8 big endian bytes, with the sign bit of signed integers flipped
+//! - floats as 8 big endian bytes, with the sign bit flipped for positive numbers and every bit
+//!   flipped for negative ones, and negative zero encoded as zero
+//! - strings as their bytes with each zero byte followed by 0xFF, ending with two zero bytes
+//! - structs as the encodings of their fields
+//!
+//! Every bit of a key that sorts in descending order is inverted, which also moves its nulls
+//! to the other end. No encoding is a prefix of another, so keys never run into each other.
+
+use arrow::datatypes::*;
+
+use super::super::datasources::common::*;
+use super::super::types::*;
+use super::coalesce::is_valid;
+
+/// The encoded keys of a batch of rows
+#[derive(Debug, Clone, Default)]
+pub struct Rows {
+    buffer: Vec<u8>,
+    /// Start of each row in the buffer, followed by the end of the last row
+    offsets: Vec<usize>,
+    /// Whether any key of each row is null
+    nulls: Vec<bool>,
+}
+
+impl Rows {
+    pub fn num_rows(&self) -> usize {
+        self.nulls.len()
+    }
+
+    /// Get the encoded keys of a row
+    pub fn row(&self, i: usize) -> &[u8] {
+        &self.buffer[self.offsets[i]..self.offsets[i + 1]]
+    }
+
+    /// Check whether any key of a row is null
+    pub fn has_null(&self, i: usize) -> bool {
+        self.nulls[i]
+    }
+}
+
+/// Encode the keys of each row of a batch, with each key in ascending order unless the
+/// corresponding entry of `asc` is false, and with its nulls before other values when the
+/// corresponding entry of `nulls_first` is true
+pub fn encode_rows(
+    key_values: &[Value],
+    asc: &[bool],
+    nulls_first: &[bool],
+    num_rows: usize,
+) -> Rows {
+    // the rows are sized first so that each key can be written a column at a time
+    let mut lengths = vec![0; num_rows];
+    let mut nulls = vec![false; num_rows];
+    let mut scratch = vec![];
+    for value in key_values {
+        match *value {
+            Value::Scalar(ref v) => {
+                scratch.clear();
+                encode_scalar(v, &mut scratch);
+                let null = **v == ScalarValue::Null;
+                for i in 0..num_rows {
+                    lengths[i] += scratch.len();
+                    nulls[i] |= null;
+                }
+            }
+            Value::Column(ref arr) => {
+                for i in 0..num_rows {
+                    if !is_valid(arr, i) {
+                        lengths[i] += 1;
+                        nulls[i] = true;
+                        continue;
+                    }
+                    lengths[i] += 1 + match *arr.data() {
+                        ArrayData::Boolean(_) => 1,
+                        ArrayData::Utf8(ref list) => utf8_len(list.get(i)),
+                        ArrayData::Struct(_) => {
+                            scratch.clear();
+                            encode_scalar(&get_value(arr, i), &mut scratch);
+                            scratch.len() - 1
+                        }
+                        _ => 8,
+                    };
+                }
+            }
+        }
+    }
+
+    let mut offsets = Vec::with_capacity(num_rows + 1);
+    offsets.push(0);
+    for i in 0..num_rows {
+        let end = offsets[i] + lengths[i];
+        offsets.push(end);
+    }
+    let mut buffer = vec![0; offsets[num_rows]];
+    let mut cursors = offsets[..num_rows].to_vec();
+    for ((value, asc), nulls_first) in key_values.iter().zip(asc.iter()).zip(nulls_first) {
+        // the flag is chosen so that nulls end up at the requested end after inversion
+        let null_flag = if *nulls_first == *asc { 0 } else { 2 };
+        encode_column(value, *asc, null_flag, &mut buffer, &mut cursors);
+    }
+    Rows {
+        buffer,
+        offsets,
+        nulls,
+    }
+}
+
+/// Append the encoding of a row of keys to a buffer, for rows whose keys have already been
+/// read into scalars
+pub fn encode_scalars(values: &[ScalarValue], asc: &[bool], buffer: &mut Vec<u8>) {
+    for (value, asc) in values.iter().zip(asc.iter()) {
+        let start = buffer.len();
+        encode_scalar(value, buffer);
+        if !asc {
+            invert(&mut buffer[start..]);
+        }
+    }
+}
+
+/// Write a key of every row at the row's cursor, moving the cursors past it
+fn encode_column(
+    value: &Value,
+    asc: bool,
+    null_flag: u8,
+    buffer: &mut [u8],
+    cursors: &mut [usize],
+) {
+    let arr = match *value {
+        Value::Column(ref arr) => arr,
+        Value::Scalar(ref v) => {
+            // a constant key is only encoded once
+            let mut encoded = vec![];
+            encode_scalar(v, &mut encoded);
+            if **v == ScalarValue::Null {
+                encoded[0] = null_flag;
+            }
+            if !asc {
+                invert(&mut encoded);
+            }
+            for cursor in cursors.iter_mut() {
+                buffer[*cursor..*cursor + encoded.len()].copy_from_slice(&encoded);
+                *cursor += encoded.len();
+            }
+            return;
+        }
+    };
+    let (b, c) = (buffer, cursors);
+    let order = (asc, null_flag);
+    match *arr.data() {
+        ArrayData::Boolean(ref v) => encode_values(arr, order, b, c, |i, out| {
+            out[0] = *v.get(i) as u8;
+            1
+        }),
+        ArrayData::Int8(ref v) => encode_values(arr, order, b, c, |i, out| {
+            put_i64(out, *v.get(i) as i64)
+        }),
+        ArrayData::Int16(ref v) => encode_values(arr, order, b, c, |i, out| {
+            put_i64(out, *v.get(i) as i64)
+        }),
+        ArrayData::Int32(ref v) => encode_values(arr, order, b, c, |i, out| {
+            put_i64(out, *v.get(i) as i64)
+        }),
+        ArrayData::Int64(ref v) => {
+            encode_values(arr, order, b, c, |i, out| put_i64(out, *v.get(i)))
+        }
+        ArrayData::UInt8(ref v) => encode_values(arr, order, b, c, |i, out| {
+            put_u64(out, *v.get(i) as u64)
+        }),
+        ArrayData::UInt16(ref v) => encode_values(arr, order, b, c, |i, out| {
+            put_u64(out, *v.get(i) as u64)
+        }),
+        ArrayData::UInt32(ref v) => encode_values(arr, order, b, c, |i, out| {
+            put_u64(out, *v.get(i) as u64)
+        }),
+        ArrayData::UInt64(ref v) => {
+            encode_values(arr, order, b, c, |i, out| put_u64(out, *v.get(i)))
+        }
+        ArrayData::Float32(ref v) => encode_values(arr, order, b, c, |i, out| {
+            put_f64(out, *v.get(i) as f64)
+        }),
+        ArrayData::Float64(ref v) => {
+            encode_values(arr, order, b, c, |i, out| put_f64(out, *v.get(i)))
+        }
+        ArrayData::Utf8(ref list) => {
+            encode_values(arr, order, b, c, |i, out| put_utf8(out, list.get(i)))
+        }
+        ArrayData::Struct(_) => encode_values(arr, order, b, c, |i, out| {
+            let mut encoded = vec![];
+            encode_scalar(&get_value(arr, i), &mut encoded);
+            // the null flag has already been written
+            out[..encoded.len() - 1].copy_from_slice(&encoded[1..]);
+            encoded.len() - 1
+        }),
+    }
+}
+
+/// Write the null flag of each row of an array, followed by the value written by `f` for rows
+/// that are not null. `order` is whether the key is ascending and the flag written for nulls.
+fn encode_values<F>(
+    arr: &Array,
+    order: (bool, u8),
+    buffer: &mut [u8],
+    cursors: &mut [usize],
+    f: F,
+) where
+    F: Fn(usize, &mut [u8]) -> usize,
+{
+    let (asc, null_flag) = order;
+    for (i, cursor) in cursors.iter_mut().enumerate() {
+        let start = *cursor;
+        if is_valid(arr, i) {
+            buffer[start] = 1;
+            *cursor += 1 + f(i, &mut buffer[start + 1..]);
+        } else {
+            buffer[start] = null_flag;
+            *cursor += 1;
+        }
+        if !asc {
+            invert(&mut buffer[start..*cursor]);
+        }
+    }
+}
+
+/// Append the null flag and encoding of a value to a buffer
+fn encode_scalar(value: &ScalarValue, buffer: &mut Vec<u8>) {
+    if *value == ScalarValue::Null {
+        buffer.push(0);
+        return;
+    }
+    buffer.push(1);
+    let start = buffer.len();
+    let len = match *value {
+        ScalarValue::Boolean(b) => {
+            buffer.push(b as u8);
+            return;
+        }
+        ScalarValue::Utf8(ref s) => utf8_len(s.as_bytes()),
+        ScalarValue::Struct(ref fields) => {
+            for f in fields {
+                encode_scalar(f, buffer);
+            }
+            return;
+        }
+        _ => 8,
+    };
+    buffer.resize(start + len, 0);
+    let out = &mut buffer[start..];
+    match *value {
+        ScalarValue::Int8(n) => put_i64(out, n as i64),
+        ScalarValue::Int16(n) => put_i64(out, n as i64),
+        ScalarValue::Int32(n) => put_i64(out, n as i64),
+        ScalarValue::Int64(n) => put_i64(out, n),
+        ScalarValue::UInt8(n) => put_u64(out, n as u64),
+        ScalarValue::UInt16(n) => put_u64(out, n as u64),
+        ScalarValue::UInt32(n) => put_u64(out, n as u64),
+        ScalarValue::UInt64(n) => put_u64(out, n),
+        ScalarValue::Float32(n) => put_f64(out, n as f64),
+        ScalarValue::Float64(n) => put_f64(out, n),
+        ScalarValue::Utf8(ref s) => put_utf8(out, s.as_bytes()),
+        _ => unreachable!(),
+    };
+}
+
+fn put_u64(out: &mut [u8], n: u64) -> usize {
+    for (i, b) in out[..8].iter_mut().enumerate() {
+        *b = (n >> (56 - 8 * i)) as u8;
+    }
+    8
+}
+
+fn put_i64(out: &mut [u8], n: i64) -> usize {
+    put_u64(out, n as u64 ^ (1 << 63))
+}
+
+fn put_f64(out: &mut [u8], n: f64) -> usize {
+    let bits = (n + 0.0).to_bits();
+    if bits >> 63 == 1 {
+        put_u64(out, !bits)
+    } else {
+        put_u64(out, bits | (1 << 63))
+    }
+}
+
+/// Length of the encoding of a string
+fn utf8_len(bytes: &[u8]) -> usize {
+    bytes.len() + bytes.iter().filter(|b| **b == 0).count() + 2
+}
+
+fn put_utf8(out: &mut [u8], bytes: &[u8]) -> usize {
+    let mut n = 0;
+    for b in bytes {
+        out[n] = *b;
+        n += 1;
+        if *b == 0 {
+            out[n] = 0xFF;
+            n += 1;
+        }
+    }
+    out[n] = 0;
+    out[n + 1] = 0;
+    n + 2
+}
+
+fn invert(bytes: &mut [u8]) {
+    for b in bytes.iter_mut() {
+        *b = !*b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::bitmap::Bitmap;
+    use arrow::builder::*;
+    use std::rc::Rc;
+
+    /// Sort row numbers by their encoded keys
+    fn sorted(rows: &Rows) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..rows.num_rows()).collect();
+        indices.sort_by(|a, b| rows.row(*a).cmp(rows.row(*b)));
+        indices
+    }
+
+    #[test]
+    fn test_encode_rows_orders_numbers() {
+        let ints = Value::Column(Rc::new(Array::from(vec![3_i32, -1, 0, -7, 12])));
+        assert_eq!(vec![3, 1, 2, 0, 4], sorted(&encode_rows(&[ints], &[true], &[true], 5)));
+
+        let floats = Value::Column(Rc::new(Array::from(vec![1.5, -0.0, -2.25, 0.0, -0.5])));
+        let rows = encode_rows(&[floats.clone()], &[true], &[true], 5);
+        assert_eq!(rows.row(1), rows.row(3));
+        assert_eq!(vec![2, 4, 1, 3, 0], sorted(&rows));
+        assert_eq!(vec![0, 1, 3, 4, 2], sorted(&encode_rows(&[floats], &[false], &[false], 5)));
+    }
+
+    #[test]
+    fn test_encode_rows_orders_strings() {
+        let strings = vec!["b", "a\u{0}", "", "ab", "a"];
+        let column = Value::Column(Rc::new(Array::from(strings.clone())));
+        let mut expected: Vec<usize> = (0..strings.len()).collect();
+        expected.sort_by_key(|i| strings[*i]);
+        assert_eq!(expected, sorted(&encode_rows(&[column], &[true], &[true], 5)));
+    }
+
+    #[test]
+    fn test_encode_rows_multiple_keys() {
+        let mut bitmap = Bitmap::new(4);
+        bitmap.clear(2);
+        let mut b: Builder<i64> = Builder::with_capacity(4);
+        for n in &[1, 2, 0, 1] {
+            b.push(*n);
+        }
+        let a = Value::Column(Rc::new(Array::with_nulls(
+            4,
+            ArrayData::from(b.finish()),
+            1,
+            bitmap,
+        )));
+        let b = Value::Column(Rc::new(Array::from(vec!["x", "y", "z", "y"])));
+        let constant = Value::Scalar(Rc::new(ScalarValue::Int32(5)));
+        let keys = vec![a, constant, b];
+
+        // nulls come first in ascending order and last in descending order by default
+        let rows = encode_rows(&keys, &[true, true, false], &[true, true, false], 4);
+        assert_eq!(vec![2, 3, 0, 1], sorted(&rows));
+        assert!(rows.has_null(2));
+        assert!(!rows.has_null(0));
+        let rows = encode_rows(&keys, &[false, true, true], &[false, true, true], 4);
+        assert_eq!(vec![1, 0, 3, 2], sorted(&rows));
+        let nulls_last = encode_rows(&keys, &[true, true, false], &[false, false, false], 4);
+        assert_eq!(vec![3, 0, 1, 2], sorted(&nulls_last));
+        let nulls_first = encode_rows(&keys, &[false, true, true], &[true, true, true], 4);
+        assert_eq!(vec![2, 1, 0, 3], sorted(&nulls_first));
+
+        // encoding scalars gives the same rows as encoding columns
+        let mut buffer = vec![];
+        let values = vec![
+            ScalarValue::Int64(1),
+            ScalarValue::Int32(5),
+            ScalarValue::Utf8(Rc::new("y".to_string())),
+        ];
+        encode_scalars(&values, &[false, true, true], &mut buffer);
+        assert_eq!(rows.row(3), &buffer[..]);
+    }
+}
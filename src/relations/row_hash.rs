@@ -0,0 +1,321 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Row Hashing
+//!
+//! Hashes the keys of the rows of a batch a column at a time. Hash joins, hash aggregates,
+//! deduplication, runtime filters and hash partitioning all hash their keys here, so that they
+//! agree on which keys hash the same:
+//!
+//! - integers of every width hash as 64-bit integers and floats as 64-bit floats, with negative
+//!   zero hashing the same as zero
+//! - nulls all hash to the same value
+//! - a constant key is hashed once for the whole batch
+//!
+//! The hashes are kept alongside the keys in a `RowHashMap`, which uses them as they are
+//! rather than hashing the keys again.
+
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+
+use arrow::datatypes::*;
+
+use super::super::functions::hash::xxhash64;
+use super::super::types::*;
+
+/// Hash of rows before any keys are mixed in
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Mixed into the hash of a row in place of a null key
+const NULL_VALUE: u64 = 0x5851_f42d_4c95_7f2d;
+
+/// Hash the keys of each row of a batch. Rows with a null key have no hash unless nulls are
+/// considered equal, in which case all nulls hash the same.
+pub fn hash_rows(
+    key_values: &[Value],
+    num_rows: usize,
+    null_equals_null: bool,
+) -> Vec<Option<u64>> {
+    hash_keys(key_values, num_rows, &vec![null_equals_null; key_values.len()])
+}
+
+/// Hash the keys of each row of a batch, where `null_equals_null` says for each key whether
+/// its nulls are considered equal. Rows with a null in any other key have no hash.
+pub fn hash_keys(
+    key_values: &[Value],
+    num_rows: usize,
+    null_equals_null: &[bool],
+) -> Vec<Option<u64>> {
+    let mut hashes = vec![SEED; num_rows];
+    // rows with a null in a key whose nulls are not equal
+    let mut nulls: Option<Vec<bool>> = None;
+    for (value, &null_equal) in key_values.iter().zip(null_equals_null) {
+        match *value {
+            Value::Scalar(ref v) => {
+                if **v == ScalarValue::Null && !null_equal {
+                    nulls = Some(vec![true; num_rows]);
+                }
+                let mut values = vec![];
+                scalar_values(v, &mut values);
+                for h in hashes.iter_mut() {
+                    *h = values.iter().fold(*h, |h, v| mix(h, *v));
+                }
+            }
+            Value::Column(ref arr) => match *arr.validity_bitmap() {
+                Some(ref bitmap) => {
+                    let before = hashes.clone();
+                    hash_array(arr, &mut hashes);
+                    for i in 0..num_rows {
+                        if !bitmap.is_set(i) {
+                            // the value of a null slot is undefined so it must not be hashed
+                            hashes[i] = mix(before[i], NULL_VALUE);
+                            if !null_equal {
+                                nulls.get_or_insert_with(|| vec![false; num_rows])[i] = true;
+                            }
+                        }
+                    }
+                }
+                None => hash_array(arr, &mut hashes),
+            },
+        }
+    }
+    match nulls {
+        Some(ref nulls) => hashes
+            .iter()
+            .zip(nulls)
+            .map(|(h, null)| if *null { None } else { Some(*h) })
+            .collect(),
+        _ => hashes.into_iter().map(Some).collect(),
+    }
+}
+
+/// Mix the values of an array into the hashes of its rows
+fn hash_array(array: &Array, hashes: &mut [u64]) {
+    match *array.data() {
+        ArrayData::Boolean(ref v) => mix_values(hashes, |i| *v.get(i) as u64),
+        ArrayData::Int8(ref v) => mix_values(hashes, |i| *v.get(i) as i64 as u64),
+        ArrayData::Int16(ref v) => mix_values(hashes, |i| *v.get(i) as i64 as u64),
+        ArrayData::Int32(ref v) => mix_values(hashes, |i| *v.get(i) as i64 as u64),
+        ArrayData::Int64(ref v) => mix_values(hashes, |i| *v.get(i) as u64),
+        ArrayData::UInt8(ref v) => mix_values(hashes, |i| *v.get(i) as u64),
+        ArrayData::UInt16(ref v) => mix_values(hashes, |i| *v.get(i) as u64),
+        ArrayData::UInt32(ref v) => mix_values(hashes, |i| *v.get(i) as u64),
+        ArrayData::UInt64(ref v) => mix_values(hashes, |i| *v.get(i)),
+        ArrayData::Float32(ref v) => mix_values(hashes, |i| float_bits(*v.get(i) as f64)),
+        ArrayData::Float64(ref v) => mix_values(hashes, |i| float_bits(*v.get(i))),
+        ArrayData::Utf8(ref list) => mix_values(hashes, |i| xxhash64(list.get(i), 0)),
+        ArrayData::Struct(ref fields) => for f in fields {
+            hash_array(&f, hashes);
+        },
+    }
+}
+
+#[inline]
+fn mix_values<F: Fn(usize) -> u64>(hashes: &mut [u64], value: F) {
+    for (i, h) in hashes.iter_mut().enumerate() {
+        *h = mix(*h, value(i));
+    }
+}
+
+/// Get the values that a constant key mixes into the hash of each row, which are the same
+/// as for an array of the same values
+fn scalar_values(value: &ScalarValue, values: &mut Vec<u64>) {
+    values.push(match *value {
+        ScalarValue::Boolean(b) => b as u64,
+        ScalarValue::Int8(n) => n as i64 as u64,
+        ScalarValue::Int16(n) => n as i64 as u64,
+        ScalarValue::Int32(n) => n as i64 as u64,
+        ScalarValue::Int64(n) => n as u64,
+        ScalarValue::UInt8(n) => n as u64,
+        ScalarValue::UInt16(n) => n as u64,
+        ScalarValue::UInt32(n) => n as u64,
+        ScalarValue::UInt64(n) => n,
+        ScalarValue::Float32(n) => float_bits(n as f64),
+        ScalarValue::Float64(n) => float_bits(n),
+        ScalarValue::Utf8(ref s) => xxhash64(s.as_bytes(), 0),
+        // the fields of a struct are mixed in one after another, as for struct arrays
+        ScalarValue::Struct(ref fields) => {
+            for f in fields {
+                scalar_values(f, values);
+            }
+            return;
+        }
+        ScalarValue::Null => NULL_VALUE,
+    })
+}
+
+/// Bits of a float with negative zero normalized, so that 0.0 and -0.0 hash the same
+#[inline]
+fn float_bits(n: f64) -> u64 {
+    (n + 0.0).to_bits()
+}
+
+/// Combine the hash of a row so far with the next of its keys
+#[inline]
+fn mix(hash: u64, value: u64) -> u64 {
+    // rotating the hash so far makes the hash depend on the order of the keys, and the
+    // finalizer of MurmurHash3 spreads every bit of the value over the whole hash
+    let mut h = hash.rotate_left(27) ^ value;
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+/// Choose the partition of a row from its hash. The high bits of the hash are used because
+/// the hash maps within each partition are indexed by the low bits.
+pub fn partition_of(hash: u64, partitions: usize) -> usize {
+    (((hash >> 32) * partitions as u64) >> 32) as usize
+}
+
+/// A key together with the hash of its row
+#[derive(Debug, Clone)]
+pub struct HashedKey<K> {
+    pub hash: u64,
+    pub key: K,
+}
+
+impl<K: PartialEq> PartialEq for HashedKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && self.key == other.key
+    }
+}
+
+impl<K: Eq> Eq for HashedKey<K> {}
+
+impl<K> Hash for HashedKey<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+/// Hasher that passes the hashes of `HashedKey`s through unchanged
+#[derive(Debug, Default)]
+pub struct RowHasher {
+    hash: u64,
+}
+
+impl Hasher for RowHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // only used for keys other than HashedKey
+        self.hash = xxhash64(bytes, self.hash);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.hash = n;
+    }
+}
+
+/// A hash map keyed by keys whose hashes were computed by `hash_rows`
+pub type RowHashMap<K, V> = HashMap<HashedKey<K>, V, BuildHasherDefault<RowHasher>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::bitmap::Bitmap;
+    use arrow::builder::*;
+    use std::rc::Rc;
+
+    fn hashes(key_values: &[Value], num_rows: usize) -> Vec<u64> {
+        hash_rows(key_values, num_rows, true)
+            .into_iter()
+            .map(|h| h.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_hash_rows_matches_equal_keys() {
+        let ints = Value::Column(Rc::new(Array::from(vec![1_i32, 2, 1])));
+        let longs = Value::Column(Rc::new(Array::from(vec![1_i64, 2, 1])));
+        let names = Value::Column(Rc::new(Array::from(vec!["a", "b", "a"])));
+        let h = hashes(&[ints.clone(), names.clone()], 3);
+        assert_eq!(h[0], h[2]);
+        assert_ne!(h[0], h[1]);
+        // integer widths and constant keys hash the same as columns of the same values
+        assert_eq!(h, hashes(&[longs, names.clone()], 3));
+        let one = Value::Scalar(Rc::new(ScalarValue::Int32(1)));
+        assert_eq!(h[0], hashes(&[one, names.clone()], 1)[0]);
+        // the order of the keys matters
+        assert_ne!(h, hashes(&[names, ints], 3));
+    }
+
+    #[test]
+    fn test_hash_rows_floats() {
+        let floats = Value::Column(Rc::new(Array::from(vec![0.0_f64, -0.0, 1.5])));
+        let h = hashes(&[floats], 3);
+        assert_eq!(h[0], h[1]);
+        let single = Value::Column(Rc::new(Array::from(vec![1.5_f32])));
+        assert_eq!(h[2], hashes(&[single], 1)[0]);
+    }
+
+    #[test]
+    fn test_hash_rows_nulls() {
+        let mut bitmap = Bitmap::new(3);
+        bitmap.clear(1);
+        bitmap.clear(2);
+        let mut b: Builder<i32> = Builder::with_capacity(3);
+        b.push(7);
+        b.push(8);
+        b.push(9);
+        let keys = vec![Value::Column(Rc::new(Array::with_nulls(
+            3,
+            ArrayData::from(b.finish()),
+            2,
+            bitmap,
+        )))];
+        let h = hash_rows(&keys, 3, false);
+        assert!(h[0].is_some());
+        assert_eq!(None, h[1]);
+        assert_eq!(None, h[2]);
+        // nulls hash the same whatever their slots hold
+        let h = hash_rows(&keys, 3, true);
+        assert_eq!(h[1], h[2]);
+        assert_ne!(h[0], h[1]);
+        let null = Value::Scalar(Rc::new(ScalarValue::Null));
+        assert_eq!(h[1], hash_rows(&[null.clone()], 1, true)[0]);
+
+        // only the nulls of keys whose nulls are equal are hashed
+        let keys = vec![keys[0].clone(), null];
+        let h = hash_keys(&keys, 3, &[false, true]);
+        assert!(h[0].is_some());
+        assert_eq!(None, h[1]);
+        assert_eq!(hash_keys(&keys, 3, &[true, true])[0], h[0]);
+        assert_eq!(vec![None; 3], hash_keys(&keys, 3, &[true, false]));
+    }
+
+    #[test]
+    fn test_partition_of() {
+        let keys = Value::Column(Rc::new(Array::from((0..1000_i64).collect::<Vec<i64>>())));
+        let mut counts = vec![0; 4];
+        for h in hashes(&[keys], 1000) {
+            counts[partition_of(h, 4)] += 1;
+        }
+        assert!(counts.iter().all(|&c| c > 200 && c < 300));
+    }
+
+    #[test]
+    fn test_row_hash_map() {
+        let mut map: RowHashMap<Vec<u8>, usize> = RowHashMap::default();
+        map.insert(HashedKey { hash: 1, key: vec![1] }, 1);
+        // keys with the same hash are told apart by comparing them
+        map.insert(HashedKey { hash: 1, key: vec![2] }, 2);
+        assert_eq!(Some(&2), map.get(&HashedKey { hash: 1, key: vec![2] }));
+        assert_eq!(None, map.get(&HashedKey { hash: 2, key: vec![1] }));
+    }
+}
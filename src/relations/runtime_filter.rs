@@ -0,0 +1,186 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runtime filters for joins. Once a hash join has built its hash table it publishes a bloom
+//! filter of the build side keys, which a filter on the probe side uses to discard rows that
+//! cannot have a match before they reach the join. The filter is placed on the probe side's
+//! scan when its filters and projections pass the key columns through.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::types::*;
+use super::join::{evaluate_keys, take};
+use super::row_hash::hash_keys;
+
+/// Number of bits per key, which gives a false positive rate of about 1%
+const BITS_PER_KEY: usize = 10;
+
+/// Number of bits set for each key
+const NUM_HASHES: u64 = 7;
+
+/// A bloom filter over the row hashes of join keys
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Create a filter sized for the expected number of keys
+    pub fn with_capacity(num_keys: usize) -> Self {
+        let num_words = (num_keys * BITS_PER_KEY + 63) / 64;
+        BloomFilter {
+            bits: vec![0; num_words.max(1)],
+        }
+    }
+
+    pub fn insert(&mut self, hash: u64) {
+        for bit in self.bit_positions(hash) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns false if the key was definitely not inserted
+    pub fn may_contain(&self, hash: u64) -> bool {
+        self.bit_positions(hash)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    fn bit_positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        // derive all of the hashes from the two halves of the row hash
+        let h1 = hash & 0xffff_ffff;
+        let h2 = (hash >> 32) | 1;
+        let num_bits = (self.bits.len() * 64) as u64;
+        (0..NUM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+/// The bloom filter published by a join, which is None until the build side has been read
+pub type SharedBloomFilter = Rc<RefCell<Option<BloomFilter>>>;
+
+/// Counts of rows checked against runtime filters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuntimeFilterMetrics {
+    pub rows_checked: usize,
+    pub rows_pruned: usize,
+}
+
+/// Discards probe side rows whose join key is not in the bloom filter published by the join
+pub struct RuntimeFilterRelation {
+    input: Box<SimpleRelation>,
+    keys: Vec<CompiledExpr>,
+    null_equals_null: Vec<bool>,
+    bloom_filter: SharedBloomFilter,
+    metrics: Rc<RefCell<RuntimeFilterMetrics>>,
+}
+
+impl RuntimeFilterRelation {
+    pub fn new(
+        input: Box<SimpleRelation>,
+        keys: Vec<CompiledExpr>,
+        null_equals_null: Vec<bool>,
+        bloom_filter: SharedBloomFilter,
+        metrics: Rc<RefCell<RuntimeFilterMetrics>>,
+    ) -> Self {
+        RuntimeFilterRelation {
+            input,
+            keys,
+            null_equals_null,
+            bloom_filter,
+            metrics,
+        }
+    }
+}
+
+impl SimpleRelation for RuntimeFilterRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let keys = &self.keys;
+        let null_equals_null = &self.null_equals_null;
+        let bloom_filter = self.bloom_filter.clone();
+        let metrics = self.metrics.clone();
+
+        Box::new(
+            self.input
+                .scan()
+                .map(move |b| {
+                    let batch = b?;
+                    let bloom_filter = bloom_filter.borrow();
+                    let bloom_filter = match *bloom_filter {
+                        Some(ref f) => f,
+                        // the join has not published a filter so every row is kept
+                        None => return Ok(batch),
+                    };
+                    let key_values = evaluate_keys(keys, batch.as_ref())?;
+                    let hashes = hash_keys(&key_values, batch.num_rows(), null_equals_null);
+                    let indices: Vec<usize> = (0..batch.num_rows())
+                        .filter(|&i| match hashes[i] {
+                            Some(hash) => bloom_filter.may_contain(hash),
+                            None => false,
+                        }).collect();
+
+                    let mut m = metrics.borrow_mut();
+                    m.rows_checked += batch.num_rows();
+                    m.rows_pruned += batch.num_rows() - indices.len();
+                    if indices.len() == batch.num_rows() {
+                        return Ok(batch);
+                    }
+                    Ok(Rc::new(DefaultRecordBatch {
+                        schema: batch.schema().clone(),
+                        data: batch
+                            .columns()
+                            .iter()
+                            .map(|c| take(c, &indices))
+                            .collect::<Result<Vec<Value>>>()?,
+                        row_count: indices.len(),
+                    }) as Rc<RecordBatch>)
+                }).filter(|b| match *b {
+                    Ok(ref b) => b.num_rows() > 0,
+                    Err(_) => true,
+                }),
+        )
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::functions::hash::xxhash64;
+    use super::*;
+
+    fn hash(i: u32) -> u64 {
+        xxhash64(format!("key{}", i).as_bytes(), 0)
+    }
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::with_capacity(100);
+        for i in 0..100u32 {
+            filter.insert(hash(i));
+        }
+        for i in 0..100u32 {
+            assert!(filter.may_contain(hash(i)));
+        }
+        let false_positives = (100..10_100u32)
+            .filter(|i| filter.may_contain(hash(*i)))
+            .count();
+        assert!(false_positives < 300);
+    }
+}
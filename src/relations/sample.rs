@@ -0,0 +1,149 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sample Relation
+
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::logical::SampleMethod;
+use super::super::types::*;
+use super::filter::filter;
+
+use arrow::datatypes::Schema;
+
+pub struct SampleRelation {
+    input: Box<SimpleRelation>,
+    method: SampleMethod,
+    /// Fraction of rows (or batches, for system sampling) to keep
+    fraction: f64,
+    seed: Option<u64>,
+}
+
+impl SampleRelation {
+    pub fn new(
+        input: Box<SimpleRelation>,
+        method: SampleMethod,
+        fraction: f64,
+        seed: Option<u64>,
+    ) -> Self {
+        SampleRelation {
+            input,
+            method,
+            fraction,
+            seed,
+        }
+    }
+}
+
+/// Small xorshift random number generator so that a given seed always produces the same
+/// sample, regardless of platform
+pub struct SampleRng {
+    state: u64,
+}
+
+impl SampleRng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on zero so mix the seed first
+        let state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        SampleRng {
+            state: if state == 0 { 1 } else { state },
+        }
+    }
+
+    /// Create a generator seeded from the system clock
+    pub fn from_time() -> Self {
+        let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() ^ d.subsec_nanos() as u64,
+            Err(_) => 0,
+        };
+        SampleRng::new(nanos)
+    }
+
+    /// Return a random number in the range [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Return 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+impl SimpleRelation for SampleRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let fraction = self.fraction;
+        let method = self.method.clone();
+        let mut rng = match self.seed {
+            Some(seed) => SampleRng::new(seed),
+            None => SampleRng::from_time(),
+        };
+
+        match method {
+            // skip whole batches without looking at their contents
+            SampleMethod::System => Box::new(self.input.scan().filter(move |b| match *b {
+                Ok(_) => rng.next_f64() < fraction,
+                Err(_) => true,
+            })),
+            SampleMethod::Bernoulli => Box::new(self.input.scan().map(move |b| match b {
+                Ok(ref batch) => {
+                    let bools: Vec<bool> = (0..batch.num_rows())
+                        .map(|_| rng.next_f64() < fraction)
+                        .collect();
+                    let row_count = bools.iter().filter(|b| **b).count();
+                    let bools = Array::from(bools);
+
+                    let sampled_batch: Rc<RecordBatch> = Rc::new(DefaultRecordBatch {
+                        schema: batch.schema().clone(),
+                        data: batch
+                            .columns()
+                            .iter()
+                            .map(|c| Value::Column(Rc::new(filter(c, &bools))))
+                            .collect(),
+                        row_count,
+                    });
+                    Ok(sampled_batch)
+                }
+                Err(e) => Err(e),
+            })),
+        }
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_is_repeatable() {
+        let mut a = SampleRng::new(42);
+        let mut b = SampleRng::new(42);
+        for _ in 0..100 {
+            let n = a.next_f64();
+            assert!(n >= 0.0 && n < 1.0);
+            assert_eq!(n, b.next_f64());
+        }
+    }
+}
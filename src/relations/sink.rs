@@ -0,0 +1,436 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writing the results of a query to files as the batches are produced, so that the results
+//! never have to be held in memory
+
+use std::collections::HashMap;
+use std::fs::{create_dir_all, metadata};
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::datasources::common::*;
+use super::super::datasources::csv::{CsvWriteOptions, CsvWriter};
+use super::super::datasources::ndjson::NdJsonWriter;
+use super::super::datasources::output::{FileCompression, FileOutput};
+use super::super::datasources::parquet::ParquetWriter;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::types::*;
+use super::sort::take_rows;
+
+/// The format of the files written by a `DataSinkRelation`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkFormat {
+    Csv,
+    NdJson,
+    Parquet,
+}
+
+impl SinkFormat {
+    fn extension(&self) -> &'static str {
+        match *self {
+            SinkFormat::Csv => "csv",
+            SinkFormat::NdJson => "json",
+            SinkFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Options for how a `DataSinkRelation` lays out and encodes the files it writes
+#[derive(Debug, Clone)]
+pub struct SinkOptions {
+    /// Start a new file once the current one has reached this many bytes
+    pub max_file_size: Option<u64>,
+    /// Start a new file once the current one has this many rows
+    pub max_rows_per_file: Option<usize>,
+    /// Columns whose values choose a `column=value` directory for each row, in order. These
+    /// columns are not written to the files, as their values are in the paths.
+    pub partition_by: Vec<String>,
+    pub compression: FileCompression,
+    /// Options for CSV files, which are ignored for other formats
+    pub csv: CsvWriteOptions,
+}
+
+impl Default for SinkOptions {
+    fn default() -> Self {
+        SinkOptions {
+            max_file_size: None,
+            max_rows_per_file: None,
+            partition_by: vec![],
+            compression: FileCompression::Uncompressed,
+            csv: CsvWriteOptions::default(),
+        }
+    }
+}
+
+impl SinkOptions {
+    /// Whether the location is a directory of files rather than a single file
+    fn is_directory(&self) -> bool {
+        self.max_file_size.is_some()
+            || self.max_rows_per_file.is_some()
+            || !self.partition_by.is_empty()
+    }
+}
+
+/// An output file that is being written
+enum SinkFile {
+    Csv { path: String, writer: CsvWriter },
+    NdJson { path: String, writer: NdJsonWriter },
+    Parquet { path: String, writer: ParquetWriter },
+}
+
+impl SinkFile {
+    fn create(
+        path: String,
+        format: &SinkFormat,
+        schema: Rc<Schema>,
+        options: &SinkOptions,
+    ) -> Result<Self> {
+        match *format {
+            SinkFormat::Csv => {
+                let output = FileOutput::create(&path, options.compression)?;
+                let mut writer = CsvWriter::new(output, options.csv.clone());
+                if options.csv.has_header {
+                    writer.write_header(&schema);
+                }
+                Ok(SinkFile::Csv { path, writer })
+            }
+            SinkFormat::NdJson => {
+                let writer = NdJsonWriter::new(FileOutput::create(&path, options.compression)?);
+                Ok(SinkFile::NdJson { path, writer })
+            }
+            SinkFormat::Parquet => {
+                let writer = ParquetWriter::create(&path, schema, options.compression)?;
+                Ok(SinkFile::Parquet { path, writer })
+            }
+        }
+    }
+
+    fn write_batch(&mut self, batch: &RecordBatch) -> Result<usize> {
+        match *self {
+            SinkFile::Csv { ref mut writer, .. } => Ok(writer.write_batch(batch)),
+            SinkFile::NdJson { ref mut writer, .. } => writer.write_batch(batch),
+            SinkFile::Parquet { ref mut writer, .. } => writer.write_batch(batch),
+        }
+    }
+
+    /// The number of bytes written to the file so far
+    fn size(&mut self) -> Result<u64> {
+        let path = match *self {
+            SinkFile::Csv {
+                ref path,
+                ref mut writer,
+            } => {
+                writer.flush()?;
+                path
+            }
+            SinkFile::NdJson {
+                ref path,
+                ref mut writer,
+            } => {
+                writer.flush()?;
+                path
+            }
+            // row groups are written to the file as soon as they are closed
+            SinkFile::Parquet { ref path, .. } => path,
+        };
+        Ok(metadata(path)?.len())
+    }
+
+    fn close(self) -> Result<()> {
+        match self {
+            SinkFile::Csv { writer, .. } => writer.close(),
+            SinkFile::NdJson { writer, .. } => writer.close(),
+            SinkFile::Parquet { writer, .. } => writer.close(),
+        }
+    }
+}
+
+/// The files written to one directory of the output, or the single output file
+struct SinkDirectory {
+    path: String,
+    /// The file being written and the number of rows written to it
+    current: Option<(SinkFile, usize)>,
+    files: usize,
+}
+
+impl SinkDirectory {
+    fn new(path: String) -> Self {
+        SinkDirectory {
+            path,
+            current: None,
+            files: 0,
+        }
+    }
+
+    /// Write the rows of a batch, starting a new file whenever the current one is full, and
+    /// return the number of rows written
+    fn write_batch(
+        &mut self,
+        batch: &RecordBatch,
+        format: &SinkFormat,
+        options: &SinkOptions,
+    ) -> Result<usize> {
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            if self.current.is_none() {
+                self.open_file(format, batch.schema().clone(), options)?;
+            }
+            let full = {
+                let (ref mut file, ref mut file_rows) = *self.current.as_mut().unwrap();
+                let n = match options.max_rows_per_file {
+                    Some(max) => (max - *file_rows).min(batch.num_rows() - offset),
+                    None => batch.num_rows() - offset,
+                };
+                if n == batch.num_rows() {
+                    file.write_batch(batch)?;
+                } else {
+                    let indices: Vec<usize> = (offset..offset + n).collect();
+                    file.write_batch(take_rows(batch, &indices)?.as_ref())?;
+                }
+                offset += n;
+                *file_rows += n;
+                let too_many_rows = match options.max_rows_per_file {
+                    Some(max) => *file_rows >= max,
+                    None => false,
+                };
+                too_many_rows || match options.max_file_size {
+                    Some(max) => file.size()? >= max,
+                    None => false,
+                }
+            };
+            if full {
+                self.current.take().unwrap().0.close()?;
+            }
+        }
+        Ok(offset)
+    }
+
+    fn open_file(
+        &mut self,
+        format: &SinkFormat,
+        schema: Rc<Schema>,
+        options: &SinkOptions,
+    ) -> Result<()> {
+        let path = if options.is_directory() {
+            // Parquet files compress their pages rather than the whole file
+            let compression = match *format {
+                SinkFormat::Parquet => "",
+                _ => options.compression.extension(),
+            };
+            format!(
+                "{}/part-{:05}.{}{}",
+                self.path.trim_right_matches('/'),
+                self.files,
+                format.extension(),
+                compression
+            )
+        } else {
+            self.path.clone()
+        };
+        self.current = Some((SinkFile::create(path, format, schema, options)?, 0));
+        self.files += 1;
+        Ok(())
+    }
+
+    fn close(self) -> Result<()> {
+        match self.current {
+            Some((file, _)) => file.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes the batches of its input to files as they are produced and returns a single row with
+/// the number of rows and files written.
+///
+/// Without any of the options that split the output, the results are written to the file at
+/// `location`. Otherwise `location` is a directory that files named `part-00000.csv`,
+/// `part-00001.csv` and so on are written to, and a new file is started whenever the current
+/// one reaches the maximum size or number of rows. When partitioning by columns the files are
+/// written to Hive-style `column=value` subdirectories instead, with one set of numbered files
+/// for each combination of values. The directory can then be registered as a table.
+pub struct DataSinkRelation {
+    input: Box<SimpleRelation>,
+    location: String,
+    format: SinkFormat,
+    options: SinkOptions,
+    schema: Schema,
+}
+
+impl DataSinkRelation {
+    pub fn new(
+        input: Box<SimpleRelation>,
+        location: &str,
+        format: SinkFormat,
+        options: SinkOptions,
+    ) -> Self {
+        DataSinkRelation {
+            input,
+            location: location.to_string(),
+            format,
+            options,
+            schema: Schema::new(vec![
+                Field::new("count", DataType::UInt64, false),
+                Field::new("files", DataType::UInt64, false),
+            ]),
+        }
+    }
+
+    /// Write every batch of the input and return the number of rows and files written
+    fn write_all(&mut self) -> Result<Rc<RecordBatch>> {
+        let input_schema = self.input.schema().clone();
+        let partition_columns = self
+            .options
+            .partition_by
+            .iter()
+            .map(|name| {
+                input_schema
+                    .columns()
+                    .iter()
+                    .position(|f| f.name() == name)
+                    .ok_or_else(|| {
+                        DataFusionError::Execution(format!(
+                            "Cannot partition by '{}', which is not a column of the results",
+                            name
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<usize>>>()?;
+        let file_columns: Vec<usize> = (0..input_schema.columns().len())
+            .filter(|i| !partition_columns.contains(i))
+            .collect();
+        if file_columns.is_empty() {
+            return Err(DataFusionError::Execution(
+                "Cannot partition by every column of the results".to_string(),
+            ));
+        }
+        let file_schema = Rc::new(Schema::new(
+            file_columns
+                .iter()
+                .map(|&i| input_schema.column(i).clone())
+                .collect(),
+        ));
+        if self.options.is_directory() {
+            create_dir_all(&self.location)?;
+        }
+
+        let location = &self.location;
+        let mut rows = 0;
+        let mut directories: HashMap<String, SinkDirectory> = HashMap::new();
+        for batch in self.input.scan() {
+            let batch = batch?;
+            let file_batch = DefaultRecordBatch {
+                schema: file_schema.clone(),
+                data: file_columns.iter().map(|&i| batch.column(i).clone()).collect(),
+                row_count: batch.num_rows(),
+            };
+            if partition_columns.is_empty() {
+                let directory = directories
+                    .entry(location.clone())
+                    .or_insert_with(|| SinkDirectory::new(location.clone()));
+                rows += directory.write_batch(&file_batch, &self.format, &self.options)?;
+                continue;
+            }
+            for (path, indices) in partition_rows(batch.as_ref(), &partition_columns) {
+                let path = format!("{}/{}", location.trim_right_matches('/'), path);
+                if !directories.contains_key(&path) {
+                    create_dir_all(&path)?;
+                    directories.insert(path.clone(), SinkDirectory::new(path.clone()));
+                }
+                let part = take_rows(&file_batch, &indices)?;
+                rows += directories.get_mut(&path).unwrap().write_batch(
+                    part.as_ref(),
+                    &self.format,
+                    &self.options,
+                )?;
+            }
+        }
+
+        let mut files: usize = directories.values().map(|d| d.files).sum();
+        for (_, directory) in directories {
+            directory.close()?;
+        }
+        // an empty result is still written, so that it can be read back
+        if files == 0 && partition_columns.is_empty() {
+            let mut directory = SinkDirectory::new(self.location.clone());
+            directory.open_file(&self.format, file_schema, &self.options)?;
+            directory.close()?;
+            files = 1;
+        }
+
+        Ok(Rc::new(DefaultRecordBatch {
+            schema: Rc::new(self.schema.clone()),
+            data: vec![
+                Value::Column(Rc::new(Array::from(vec![rows as u64]))),
+                Value::Column(Rc::new(Array::from(vec![files as u64]))),
+            ],
+            row_count: 1,
+        }))
+    }
+}
+
+/// Group the rows of a batch by the `column=value` path that the values of the partition
+/// columns choose, in the order the paths are first seen
+fn partition_rows(batch: &RecordBatch, partition_columns: &[usize]) -> Vec<(String, Vec<usize>)> {
+    let schema = batch.schema();
+    let mut groups: Vec<(String, Vec<usize>)> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for i in 0..batch.num_rows() {
+        let path = partition_columns
+            .iter()
+            .map(|&c| {
+                let value = match value_at(batch.column(c), i) {
+                    ScalarValue::Null => "__HIVE_DEFAULT_PARTITION__".to_string(),
+                    v => escape_path_name(&v.to_string()),
+                };
+                format!("{}={}", escape_path_name(schema.column(c).name()), value)
+            })
+            .collect::<Vec<String>>()
+            .join("/");
+        let next = groups.len();
+        let g = *index.entry(path.clone()).or_insert(next);
+        if g == next {
+            groups.push((path, vec![]));
+        }
+        groups[g].1.push(i);
+    }
+    groups
+}
+
+/// Escape the characters that cannot appear in a directory name, the way Hive does
+fn escape_path_name(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if c.is_control() || "\"#%'*/:=?\\{[]^".contains(c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+impl SimpleRelation for DataSinkRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        // the input is only read when the result is asked for
+        Box::new((0..1).map(move |_| self.write_all()))
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        &self.schema
+    }
+}
@@ -13,11 +13,524 @@
 // limitations under the License.
 
 //! Relational Sort
+//!
+//! Sorts input that is already partly in the requested order. A sort preserving merge combines
+//! partitions that are each sorted into one sorted relation, reading every partition once. A
+//! partial sort handles input that is sorted by a prefix of the sort keys, such as rows sorted
+//! by day that need to be sorted by day and hour, by sorting each run of rows with equal prefix
+//! keys as soon as the run ends, so that only one run is held in memory at a time. When a run
+//! doesn't fit in the memory quota of the session's resource group, the part of it in memory is
+//! sorted and spilled to a file whenever the quota is reached, and the sorted files are merged
+//! when the run ends.
 
-//pub struct SortRelation {
-//    schema: Schema,
-//    input: Box<SimpleRelation>,
-//    sort_expr: Vec<CompiledExpr>,
-//    sort_asc: Vec<bool>
-//}
+use std::iter;
+use std::rc::Rc;
 
+use arrow::datatypes::*;
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::logical::SortKey;
+use super::super::resource::{batch_memory_size, MemoryReservation};
+use super::super::spill::{SpillFile, SpillManager};
+use super::coalesce::concat_batches;
+use super::join::take;
+use super::row_format::{encode_rows, Rows};
+
+/// Encode the sort keys of each row of a batch, so that rows compare as bytes
+fn encode_sort_keys(batch: &RecordBatch, sort_keys: &[SortKey]) -> Rows {
+    let values: Vec<Value> = sort_keys
+        .iter()
+        .map(|k| batch.column(k.column).clone())
+        .collect();
+    let asc: Vec<bool> = sort_keys.iter().map(|k| k.asc).collect();
+    let nulls_first: Vec<bool> = sort_keys.iter().map(|k| k.nulls_first).collect();
+    encode_rows(&values, &asc, &nulls_first, batch.num_rows())
+}
+
+/// Select the rows at the given indices from every column of a batch
+pub fn take_rows(batch: &RecordBatch, indices: &[usize]) -> Result<Rc<RecordBatch>> {
+    let data = batch
+        .columns()
+        .iter()
+        .map(|c| take(c, indices))
+        .collect::<Result<Vec<Value>>>()?;
+    Ok(Rc::new(DefaultRecordBatch {
+        schema: batch.schema().clone(),
+        data,
+        row_count: indices.len(),
+    }))
+}
+
+/// Sort the rows of a list of batches into a single batch. The sort is stable, so rows with
+/// equal keys keep their input order.
+pub fn sort_batches(batches: &[Rc<RecordBatch>], sort_keys: &[SortKey]) -> Result<Rc<RecordBatch>> {
+    let batch = concat_batches(batches)?;
+    let rows = encode_sort_keys(batch.as_ref(), sort_keys);
+    let mut indices: Vec<usize> = (0..batch.num_rows()).collect();
+    indices.sort_by(|a, b| rows.row(*a).cmp(rows.row(*b)));
+    take_rows(batch.as_ref(), &indices)
+}
+
+/// Merges partitions that are each sorted by the sort keys into a single sorted relation
+pub struct SortPreservingMergeRelation {
+    inputs: Vec<Box<SimpleRelation>>,
+    sort_keys: Vec<SortKey>,
+    schema: Rc<Schema>,
+    batch_size: usize,
+}
+
+impl SortPreservingMergeRelation {
+    pub fn new(
+        inputs: Vec<Box<SimpleRelation>>,
+        sort_keys: Vec<SortKey>,
+        schema: Rc<Schema>,
+        batch_size: usize,
+    ) -> Self {
+        SortPreservingMergeRelation {
+            inputs,
+            sort_keys,
+            schema,
+            batch_size: batch_size.max(1),
+        }
+    }
+}
+
+impl SimpleRelation for SortPreservingMergeRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let inputs: Vec<Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>> =
+            self.inputs.iter_mut().map(|input| input.scan()).collect();
+        Box::new(MergeIterator::new(
+            inputs,
+            &self.sort_keys,
+            self.batch_size,
+        ))
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+/// The position of a merge in one of its inputs
+#[derive(Default)]
+struct MergeCursor {
+    batch: Option<Rc<RecordBatch>>,
+    rows: Rows,
+    position: usize,
+    /// Offset of the cursor's batch within the sources of the pending output batch
+    offset: usize,
+}
+
+struct MergeIterator<'a> {
+    inputs: Vec<Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>>,
+    cursors: Vec<MergeCursor>,
+    sort_keys: &'a [SortKey],
+    batch_size: usize,
+    /// Batches that rows of the pending output batch are taken from
+    sources: Vec<Rc<RecordBatch>>,
+    source_rows: usize,
+    /// Indices of the rows of the pending output batch within the concatenated sources
+    indices: Vec<usize>,
+    started: bool,
+    done: bool,
+}
+
+impl<'a> MergeIterator<'a> {
+    fn new(
+        inputs: Vec<Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>>,
+        sort_keys: &'a [SortKey],
+        batch_size: usize,
+    ) -> Self {
+        let cursors = inputs.iter().map(|_| MergeCursor::default()).collect();
+        MergeIterator {
+            inputs,
+            cursors,
+            sort_keys,
+            batch_size,
+            sources: vec![],
+            source_rows: 0,
+            indices: vec![],
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Move a cursor to the next non-empty batch of its input, or past the end of the input
+    fn advance(&mut self, input: usize) -> Result<()> {
+        loop {
+            match self.inputs[input].next() {
+                Some(batch) => {
+                    let batch = batch?;
+                    if batch.num_rows() == 0 {
+                        continue;
+                    }
+                    let cursor = &mut self.cursors[input];
+                    cursor.rows = encode_sort_keys(batch.as_ref(), self.sort_keys);
+                    cursor.position = 0;
+                    cursor.offset = self.source_rows;
+                    self.source_rows += batch.num_rows();
+                    self.sources.push(batch.clone());
+                    cursor.batch = Some(batch);
+                    return Ok(());
+                }
+                None => {
+                    self.cursors[input].batch = None;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Find the input whose current row comes first. There are only as many inputs as
+    /// partitions, so comparing the current row of each is cheap.
+    fn smallest(&self) -> Option<usize> {
+        let mut smallest: Option<usize> = None;
+        for (i, cursor) in self.cursors.iter().enumerate() {
+            if cursor.batch.is_none() {
+                continue;
+            }
+            let row = cursor.rows.row(cursor.position);
+            smallest = match smallest {
+                Some(s) if self.cursors[s].rows.row(self.cursors[s].position) <= row => Some(s),
+                _ => Some(i),
+            };
+        }
+        smallest
+    }
+
+    /// Build the pending output batch and start a new one from the batches of the cursors
+    fn flush(&mut self) -> Result<Rc<RecordBatch>> {
+        let sources = concat_batches(&self.sources)?;
+        let batch = take_rows(sources.as_ref(), &self.indices)?;
+        self.sources.clear();
+        self.source_rows = 0;
+        self.indices.clear();
+        for cursor in self.cursors.iter_mut() {
+            if let Some(ref b) = cursor.batch {
+                cursor.offset = self.source_rows;
+                self.source_rows += b.num_rows();
+                self.sources.push(b.clone());
+            }
+        }
+        Ok(batch)
+    }
+
+    fn next_batch(&mut self) -> Result<Option<Rc<RecordBatch>>> {
+        if !self.started {
+            self.started = true;
+            for i in 0..self.inputs.len() {
+                self.advance(i)?;
+            }
+        }
+        while let Some(i) = self.smallest() {
+            let (index, exhausted) = {
+                let cursor = &mut self.cursors[i];
+                let index = cursor.offset + cursor.position;
+                cursor.position += 1;
+                (index, cursor.position == cursor.rows.num_rows())
+            };
+            self.indices.push(index);
+            if exhausted {
+                self.advance(i)?;
+            }
+            if self.indices.len() == self.batch_size {
+                return self.flush().map(Some);
+            }
+        }
+        self.done = true;
+        if self.indices.is_empty() {
+            Ok(None)
+        } else {
+            self.flush().map(Some)
+        }
+    }
+}
+
+impl<'a> Iterator for MergeIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_batch() {
+            Ok(batch) => batch.map(Ok),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Sorts input that is already sorted by the first `prefix_len` sort keys
+pub struct PartialSortRelation {
+    input: Box<SimpleRelation>,
+    sort_keys: Vec<SortKey>,
+    prefix_len: usize,
+    /// Memory that the open run is counted against, the manager of the files that it is
+    /// spilled to once the reservation can't grow, and the size of the batches of those files
+    spill: Option<(MemoryReservation, Rc<SpillManager>, usize)>,
+}
+
+impl PartialSortRelation {
+    pub fn new(input: Box<SimpleRelation>, sort_keys: Vec<SortKey>, prefix_len: usize) -> Self {
+        PartialSortRelation {
+            input,
+            sort_keys,
+            prefix_len,
+            spill: None,
+        }
+    }
+
+    /// Count the open run against a memory reservation, spilling it to sorted files of the
+    /// spill manager rather than holding it all in memory when it doesn't fit
+    pub fn with_spilling(
+        mut self,
+        reservation: MemoryReservation,
+        spill_manager: Rc<SpillManager>,
+        batch_size: usize,
+    ) -> Self {
+        self.spill = Some((reservation, spill_manager, batch_size.max(1)));
+        self
+    }
+}
+
+impl SimpleRelation for PartialSortRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let spill = match self.spill {
+            Some((ref mut reservation, ref spill_manager, batch_size)) => {
+                // the run of an earlier scan is no longer held
+                reservation.release();
+                Some((reservation, spill_manager.clone(), batch_size))
+            }
+            None => None,
+        };
+        Box::new(PartialSortIterator {
+            input: self.input.scan(),
+            sort_keys: &self.sort_keys,
+            prefix_len: self.prefix_len,
+            buffer: vec![],
+            last_prefix: None,
+            done: false,
+            spill,
+            spilled: vec![],
+            merging: None,
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+struct PartialSortIterator<'a> {
+    input: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    sort_keys: &'a [SortKey],
+    prefix_len: usize,
+    /// Rows of the run that is still open, which may continue in the next batch
+    buffer: Vec<Rc<RecordBatch>>,
+    /// Encoded prefix keys of the last row read
+    last_prefix: Option<Vec<u8>>,
+    done: bool,
+    spill: Option<(&'a mut MemoryReservation, Rc<SpillManager>, usize)>,
+    /// Sorted parts of the open run that were spilled to disk
+    spilled: Vec<SpillFile>,
+    /// Merge of the parts of a run that was spilled, which produces the run's sorted rows,
+    /// along with the files that it reads
+    merging: Option<(MergeIterator<'a>, Vec<SpillFile>)>,
+}
+
+impl<'a> PartialSortIterator<'a> {
+    /// Sort the rows of every run that ends within a batch, keeping the rows of the run that
+    /// is still open. Returns `None` when no run ends within the batch.
+    fn push(&mut self, batch: Rc<RecordBatch>) -> Result<Option<Rc<RecordBatch>>> {
+        let n = batch.num_rows();
+        let rows = encode_sort_keys(batch.as_ref(), &self.sort_keys[..self.prefix_len]);
+        // the open run starts at `split`, and every row before it belongs to a run that ended
+        let mut split = n - 1;
+        while split > 0 && rows.row(split - 1) == rows.row(n - 1) {
+            split -= 1;
+        }
+        let run_ended = match self.last_prefix {
+            Some(ref p) => split > 0 || p.as_slice() != rows.row(0),
+            None => split > 0,
+        };
+        self.last_prefix = Some(rows.row(n - 1).to_vec());
+        if !run_ended {
+            self.push_to_run(batch)?;
+            return Ok(None);
+        }
+        if split > 0 {
+            let head: Vec<usize> = (0..split).collect();
+            self.push_to_run(take_rows(batch.as_ref(), &head)?)?;
+        }
+        let ended: Vec<Rc<RecordBatch>> = self.buffer.drain(..).collect();
+        let spilled: Vec<SpillFile> = self.spilled.drain(..).collect();
+        self.release();
+        let tail: Vec<usize> = (split..n).collect();
+        self.push_to_run(take_rows(batch.as_ref(), &tail)?)?;
+        self.finish_run(ended, spilled)
+    }
+
+    /// Add rows to the open run, spilling the part of the run in memory once it doesn't fit in
+    /// the reservation
+    fn push_to_run(&mut self, batch: Rc<RecordBatch>) -> Result<()> {
+        let size = batch_memory_size(batch.as_ref());
+        self.buffer.push(batch);
+        let fits = match self.spill {
+            Some((ref mut reservation, _, _)) => reservation.grow(size).is_ok(),
+            None => true,
+        };
+        if fits {
+            Ok(())
+        } else {
+            self.spill_run()
+        }
+    }
+
+    /// Sort the part of the open run in memory and write it to a spill file
+    fn spill_run(&mut self) -> Result<()> {
+        if let Some((ref mut reservation, ref spill_manager, batch_size)) = self.spill {
+            let sorted = sort_batches(&self.buffer, self.sort_keys)?;
+            let mut writer = spill_manager.create_file(sorted.schema().clone())?;
+            let mut start = 0;
+            while start < sorted.num_rows() {
+                let end = (start + batch_size).min(sorted.num_rows());
+                let indices: Vec<usize> = (start..end).collect();
+                writer.write_batch(take_rows(sorted.as_ref(), &indices)?.as_ref())?;
+                start = end;
+            }
+            self.spilled.push(writer.finish()?);
+            self.buffer.clear();
+            reservation.release();
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        if let Some((ref mut reservation, _, _)) = self.spill {
+            reservation.release();
+        }
+    }
+
+    /// Sort the rows of a run that ended. The rows of a run that was partly spilled are merged
+    /// from its sorted parts, and the merge produces the batches of the run.
+    fn finish_run(
+        &mut self,
+        run: Vec<Rc<RecordBatch>>,
+        spilled: Vec<SpillFile>,
+    ) -> Result<Option<Rc<RecordBatch>>> {
+        if spilled.is_empty() {
+            return sort_batches(&run, self.sort_keys).map(Some);
+        }
+        let mut inputs: Vec<Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>> = vec![];
+        for file in &spilled {
+            inputs.push(file.read()?);
+        }
+        // the part in memory comes last, so rows with equal keys keep their input order
+        if !run.is_empty() {
+            inputs.push(Box::new(iter::once(sort_batches(&run, self.sort_keys))));
+        }
+        let batch_size = match self.spill {
+            Some((_, _, batch_size)) => batch_size,
+            None => 1,
+        };
+        let mut merge = MergeIterator::new(inputs, self.sort_keys, batch_size);
+        let first = merge.next();
+        self.merging = Some((merge, spilled));
+        match first {
+            Some(Ok(batch)) => Ok(Some(batch)),
+            Some(Err(e)) => Err(e),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> Iterator for PartialSortIterator<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // the batches of a spilled run are merged before more input is read
+            if let Some((ref mut merge, _)) = self.merging {
+                if let Some(batch) = merge.next() {
+                    if batch.is_err() {
+                        self.done = true;
+                    }
+                    return Some(batch);
+                }
+            }
+            self.merging = None;
+            if self.done {
+                return None;
+            }
+            let result = match self.input.next() {
+                Some(Ok(batch)) => {
+                    if batch.num_rows() == 0 {
+                        continue;
+                    }
+                    self.push(batch)
+                }
+                Some(Err(e)) => Err(e),
+                None => {
+                    self.done = true;
+                    let run: Vec<Rc<RecordBatch>> = self.buffer.drain(..).collect();
+                    let spilled: Vec<SpillFile> = self.spilled.drain(..).collect();
+                    self.release();
+                    if run.is_empty() && spilled.is_empty() {
+                        return None;
+                    }
+                    self.finish_run(run, spilled)
+                }
+            };
+            match result {
+                Ok(Some(batch)) => return Some(Ok(batch)),
+                Ok(None) => continue,
+                Err(e) => {
+                    self.done = true;
+                    self.merging = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_batches() {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("day", DataType::Int32, false),
+            Field::new("hour", DataType::Int32, false),
+        ]));
+        let batch = |day: Vec<i32>, hour: Vec<i32>| -> Rc<RecordBatch> {
+            Rc::new(DefaultRecordBatch {
+                schema: schema.clone(),
+                row_count: day.len(),
+                data: vec![
+                    Value::Column(Rc::new(Array::from(day))),
+                    Value::Column(Rc::new(Array::from(hour))),
+                ],
+            })
+        };
+        let batches = vec![batch(vec![2, 1], vec![3, 5]), batch(vec![1, 2], vec![7, 3])];
+        let keys = vec![SortKey::new(0, true), SortKey::new(1, false)];
+        let sorted = sort_batches(&batches, &keys).unwrap();
+        let values = |i: usize| match *sorted.column(i) {
+            Value::Column(ref arr) => match *arr.data() {
+                ArrayData::Int32(ref v) => v.iter().collect::<Vec<i32>>(),
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+        assert_eq!(vec![1, 1, 2, 2], values(0));
+        // rows with equal keys keep their input order
+        assert_eq!(vec![7, 5, 3, 3], values(1));
+    }
+}
@@ -0,0 +1,324 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time Window Aggregate Relation
+//!
+//! Aggregates rows by the time windows that contain them, as grouped by
+//! `GROUP BY window(ts, '5 minutes')` for tumbling windows or
+//! `GROUP BY window(ts, '5 minutes', '1 minute')` for windows of five minutes starting every
+//! minute. Rows are aggregated as they are read and the results of a window are produced once
+//! the window has closed, so the input can be unbounded.
+//!
+//! A window closes when a row has been read whose timestamp is past the end of the window by
+//! more than the allowed lateness. Rows that only belong to closed windows are dropped. Any
+//! windows still open at the end of the input are produced then.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::functions::datetime::bin;
+use super::super::logical::*;
+use super::super::types::*;
+use super::aggregate::*;
+use super::row_hash::{hash_rows, HashedKey, RowHashMap};
+
+/// Name of the function that groups rows by time window
+pub const TIME_WINDOW_FUNCTION: &'static str = "window";
+
+/// Counts of the rows and windows of time window aggregations
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeWindowMetrics {
+    /// Windows whose results have been produced
+    pub windows_closed: usize,
+    /// Rows that were dropped because every window they belong to had already closed
+    pub late_rows: usize,
+}
+
+/// Get the timestamp, width and slide of a `window(ts, width, slide)` grouping expression,
+/// whose width and slide are planned as milliseconds
+pub fn time_window_args(expr: &Expr) -> Option<(&Expr, i64, i64)> {
+    match *expr {
+        Expr::ScalarFunction {
+            ref name, ref args, ..
+        } if name == TIME_WINDOW_FUNCTION && args.len() == 3 =>
+        {
+            match (&args[1], &args[2]) {
+                (
+                    &Expr::Literal(ScalarValue::Int64(width)),
+                    &Expr::Literal(ScalarValue::Int64(slide)),
+                ) => Some((&args[0], width, slide)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+pub struct TimeWindowAggregateRelation {
+    input: Box<SimpleRelation>,
+    aggregation: TimeWindowAggregation,
+}
+
+/// What a time window aggregate relation computes, kept apart from its input so that the input
+/// can be scanned while the aggregation is read
+struct TimeWindowAggregation {
+    schema: Rc<Schema>,
+    /// Evaluates the timestamps of rows in milliseconds
+    time_expr: RuntimeExpr,
+    width: i64,
+    slide: i64,
+    allowed_lateness: i64,
+    /// Position of the window among the grouping expressions
+    window_index: usize,
+    /// The grouping expressions other than the window
+    group_expr: Vec<RuntimeExpr>,
+    aggr_expr: Vec<RuntimeExpr>,
+    metrics: Rc<RefCell<TimeWindowMetrics>>,
+}
+
+impl TimeWindowAggregateRelation {
+    pub fn new(
+        schema: Rc<Schema>,
+        input: Box<SimpleRelation>,
+        time_expr: RuntimeExpr,
+        (width, slide): (i64, i64),
+        window_index: usize,
+        group_expr: Vec<RuntimeExpr>,
+        aggr_expr: Vec<RuntimeExpr>,
+        metrics: Rc<RefCell<TimeWindowMetrics>>,
+    ) -> Self {
+        TimeWindowAggregateRelation {
+            input,
+            aggregation: TimeWindowAggregation {
+                schema,
+                time_expr,
+                width,
+                slide,
+                allowed_lateness: 0,
+                window_index,
+                group_expr,
+                aggr_expr,
+                metrics,
+            },
+        }
+    }
+
+    /// Keep windows open for this many milliseconds after rows past their end have been read,
+    /// so that rows which arrive out of order are still aggregated
+    pub fn with_allowed_lateness(mut self, allowed_lateness: i64) -> Self {
+        self.aggregation.allowed_lateness = allowed_lateness.max(0);
+        self
+    }
+}
+
+/// The accumulators of the groups of each open window, by window start
+type OpenWindows = BTreeMap<i64, RowHashMap<Vec<GroupScalar>, Vec<Box<AggregateFunction>>>>;
+
+struct TimeWindowAggregate<'a> {
+    rel: &'a TimeWindowAggregation,
+    input: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+    windows: OpenWindows,
+    /// Latest timestamp that has been read
+    max_time: Option<i64>,
+    output: VecDeque<Rc<RecordBatch>>,
+    done: bool,
+}
+
+impl<'a> TimeWindowAggregate<'a> {
+    /// Aggregate the rows of a batch into the windows they belong to
+    fn aggregate(&mut self, batch: &RecordBatch) -> Result<()> {
+        let rel = self.rel;
+        let times = rel.time_expr.get_func()(batch)?;
+        let group_values = rel
+            .group_expr
+            .iter()
+            .map(|e| e.get_func()(batch))
+            .collect::<Result<Vec<Value>>>()?;
+        let aggr_args = rel
+            .aggr_expr
+            .iter()
+            .map(|e| match *e {
+                RuntimeExpr::AggregateFunction { ref args, .. } => {
+                    args.iter().map(|a| (*a)(batch)).collect::<Result<Vec<Value>>>()
+                }
+                _ => Err(DataFusionError::Execution(
+                    "Expected an aggregate expression".to_string(),
+                )),
+            }).collect::<Result<Vec<Vec<Value>>>>()?;
+
+        let hashes = hash_rows(&group_values, batch.num_rows(), true);
+        let mut key = vec![GroupScalar::Int32(0); group_values.len()];
+        for i in 0..batch.num_rows() {
+            let t = match value_at(&times, i) {
+                ScalarValue::Int64(t) => t,
+                ScalarValue::Null => continue,
+                other => {
+                    return Err(DataFusionError::Execution(format!(
+                        "Time window timestamps must be Int64 milliseconds but found {:?}",
+                        other
+                    )))
+                }
+            };
+            write_key(&mut key, &group_values, i)?;
+            let watermark = self.watermark();
+            let mut aggregated = false;
+            // the latest window containing the row starts at the last slide boundary before it
+            let mut start = bin(t, rel.slide, 0);
+            while start + rel.width > t {
+                if watermark.map(|w| start + rel.width > w).unwrap_or(true) {
+                    let groups = self.windows.entry(start).or_insert_with(RowHashMap::default);
+                    let hashed = HashedKey {
+                        hash: hashes[i].unwrap(),
+                        key: key.clone(),
+                    };
+                    let accumulators = groups
+                        .entry(hashed)
+                        .or_insert_with(|| create_aggregate_functions(&rel.aggr_expr));
+                    for (j, args) in aggr_args.iter().enumerate() {
+                        let row_args: Vec<Value> = args
+                            .iter()
+                            .map(|a| Value::Scalar(Rc::new(value_at(a, i))))
+                            .collect();
+                        accumulators[j].execute(&row_args)?;
+                    }
+                    aggregated = true;
+                }
+                start -= rel.slide;
+            }
+            if !aggregated {
+                self.rel.metrics.borrow_mut().late_rows += 1;
+            }
+            self.max_time = Some(self.max_time.map(|m| m.max(t)).unwrap_or(t));
+        }
+        Ok(())
+    }
+
+    /// Windows that end at or before the watermark are closed
+    fn watermark(&self) -> Option<i64> {
+        self.max_time.map(|t| t - self.rel.allowed_lateness)
+    }
+
+    /// Produce the results of the windows that have closed, or of all windows at the end of
+    /// the input
+    fn close_windows(&mut self, all: bool) -> Result<()> {
+        let closed: Vec<i64> = match self.watermark() {
+            _ if all => self.windows.keys().cloned().collect(),
+            Some(watermark) => self
+                .windows
+                .keys()
+                .cloned()
+                .take_while(|start| start + self.rel.width <= watermark)
+                .collect(),
+            None => vec![],
+        };
+        if closed.is_empty() {
+            return Ok(());
+        }
+
+        let rel = self.rel;
+        let group_count = rel.group_expr.len() + 1;
+        let mut columns: Vec<Vec<ScalarValue>> =
+            vec![vec![]; group_count + rel.aggr_expr.len()];
+        for start in &closed {
+            let groups = self.windows.remove(start).unwrap();
+            for (key, accumulators) in groups {
+                let mut keys = key.key.iter().map(|k| k.as_scalar());
+                for c in 0..group_count {
+                    columns[c].push(if c == rel.window_index {
+                        ScalarValue::Int64(*start)
+                    } else {
+                        keys.next().unwrap()
+                    });
+                }
+                for (j, accumulator) in accumulators.iter().enumerate() {
+                    columns[group_count + j].push(match accumulator.finish()? {
+                        Value::Scalar(v) => v.as_ref().clone(),
+                        Value::Column(_) => {
+                            return Err(DataFusionError::Execution(
+                                "Aggregate produced a column".to_string(),
+                            ))
+                        }
+                    });
+                }
+            }
+        }
+
+        let row_count = columns[0].len();
+        let data = columns
+            .iter()
+            .zip(rel.schema.columns())
+            .map(|(values, field)| {
+                array_from_scalars(values, field.data_type()).map(|a| Value::Column(Rc::new(a)))
+            }).collect::<Result<Vec<Value>>>()?;
+        self.output.push_back(Rc::new(DefaultRecordBatch {
+            schema: rel.schema.clone(),
+            data,
+            row_count,
+        }));
+        self.rel.metrics.borrow_mut().windows_closed += closed.len();
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for TimeWindowAggregate<'a> {
+    type Item = Result<Rc<RecordBatch>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(batch) = self.output.pop_front() {
+                return Some(Ok(batch));
+            }
+            if self.done {
+                return None;
+            }
+            let result = match self.input.next() {
+                Some(Ok(batch)) => self
+                    .aggregate(batch.as_ref())
+                    .and_then(|_| self.close_windows(false)),
+                Some(Err(e)) => Err(e),
+                None => {
+                    self.done = true;
+                    self.close_windows(true)
+                }
+            };
+            if let Err(e) = result {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl SimpleRelation for TimeWindowAggregateRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        Box::new(TimeWindowAggregate {
+            rel: &self.aggregation,
+            input: self.input.scan(),
+            windows: BTreeMap::new(),
+            max_time: None,
+            output: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.aggregation.schema.as_ref()
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Union of relations that share a schema, such as the partitions of a partitioned table
+
+use std::rc::Rc;
+
+use arrow::datatypes::*;
+
+use super::super::errors::*;
+use super::super::exec::*;
+
+pub struct UnionRelation {
+    inputs: Vec<Box<SimpleRelation>>,
+    schema: Rc<Schema>,
+}
+
+impl UnionRelation {
+    pub fn new(inputs: Vec<Box<SimpleRelation>>, schema: Rc<Schema>) -> Self {
+        UnionRelation { inputs, schema }
+    }
+}
+
+impl SimpleRelation for UnionRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        // each input is scanned in turn, so only one is being read at a time
+        Box::new(self.inputs.iter_mut().flat_map(|input| input.scan()))
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
@@ -0,0 +1,827 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Window Relation
+//!
+//! Window functions need every row of a partition before they can produce a value, so the whole
+//! input is read into memory. The rows of each partition are sorted by the window's ORDER BY
+//! expressions and the frame of each row is found from its position in that order. The output
+//! batches are the input batches, in their original order, with a column appended for each
+//! window function.
+//!
+//! When the input is counted against a memory quota, the batches that don't fit are spilled to
+//! a file and read back for each pass over the input. The values of the window expressions are
+//! still held in memory for every row.
+
+use std::cmp::Ordering;
+use std::iter;
+use std::rc::Rc;
+
+use super::super::datasources::common::*;
+use super::super::errors::*;
+use super::super::exec::*;
+use super::super::functions::window::WindowFunction;
+use super::super::logical::{
+    WindowFrame, WindowFrameBound, WindowFrameExclusion, WindowFrameUnits,
+};
+use super::super::resource::{batch_memory_size, MemoryReservation};
+use super::super::spill::{SpillFile, SpillManager, SpillWriter};
+use super::super::types::*;
+use super::row_format::encode_scalars;
+
+use arrow::datatypes::*;
+
+/// A window expression with its argument, partition and order expressions compiled
+pub struct CompiledWindowExpr {
+    pub function: WindowFunction,
+    pub args: Vec<CompiledExpr>,
+    pub partition_by: Vec<CompiledExpr>,
+    pub order_by: Vec<CompiledExpr>,
+    /// Sort direction of each ORDER BY expression
+    pub order_asc: Vec<bool>,
+    pub frame: WindowFrame,
+    pub ignore_nulls: bool,
+    pub return_type: DataType,
+}
+
+pub struct WindowRelation {
+    input: Box<SimpleRelation>,
+    window_expr: Vec<CompiledWindowExpr>,
+    schema: Rc<Schema>,
+    /// Memory that the input is counted against, and the manager of the file that the input
+    /// is spilled to once the reservation can't grow
+    spill: Option<(MemoryReservation, Rc<SpillManager>)>,
+    /// The input of the last scan
+    buffered: Option<WindowInput>,
+}
+
+/// The input of a window relation: the batches that are held in memory, followed by the
+/// batches that were spilled to disk
+struct WindowInput {
+    batches: Vec<Rc<RecordBatch>>,
+    spilled: Option<SpillFile>,
+}
+
+impl WindowInput {
+    /// Read the batches in the order of the input
+    fn batches<'a>(&'a self) -> Result<Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>> {
+        let in_memory = self.batches.iter().map(|b| Ok(b.clone()));
+        Ok(match self.spilled {
+            Some(ref file) => Box::new(in_memory.chain(file.read()?)),
+            None => Box::new(in_memory),
+        })
+    }
+}
+
+impl WindowRelation {
+    pub fn new(
+        input: Box<SimpleRelation>,
+        window_expr: Vec<CompiledWindowExpr>,
+        schema: Rc<Schema>,
+    ) -> Self {
+        WindowRelation {
+            input,
+            window_expr,
+            schema,
+            spill: None,
+            buffered: None,
+        }
+    }
+
+    /// Count the input against a memory reservation, spilling the batches that don't fit to a
+    /// file of the spill manager rather than failing
+    pub fn with_spilling(
+        mut self,
+        reservation: MemoryReservation,
+        spill_manager: Rc<SpillManager>,
+    ) -> Self {
+        self.spill = Some((reservation, spill_manager));
+        self
+    }
+
+    /// Read the whole input, keeping batches in memory until the reservation can't grow and
+    /// spilling the rest
+    fn read_input(&mut self) -> Result<WindowInput> {
+        let schema = Rc::new(self.input.schema().clone());
+        if let Some((ref mut reservation, _)) = self.spill {
+            reservation.release();
+        }
+        let mut batches = vec![];
+        let mut writer: Option<SpillWriter> = None;
+        for batch in self.input.scan() {
+            let batch = batch?;
+            if writer.is_none() {
+                if let Some((ref mut reservation, ref spill_manager)) = self.spill {
+                    if reservation.grow(batch_memory_size(batch.as_ref())).is_err() {
+                        writer = Some(spill_manager.create_file(schema.clone())?);
+                    }
+                }
+            }
+            match writer {
+                Some(ref mut w) => w.write_batch(batch.as_ref())?,
+                None => batches.push(batch),
+            }
+        }
+        let spilled = match writer {
+            Some(w) => Some(w.finish()?),
+            None => None,
+        };
+        Ok(WindowInput { batches, spilled })
+    }
+
+    /// Read the input and compute the values of each window expression for every row
+    fn evaluate(&mut self) -> Result<Vec<Vec<ScalarValue>>> {
+        // release the input of an earlier scan before reading it again
+        self.buffered = None;
+        let input = self.read_input()?;
+
+        let mut window_columns: Vec<Vec<ScalarValue>> = Vec::with_capacity(self.window_expr.len());
+        for expr in &self.window_expr {
+            let rows = WindowRows {
+                partition_keys: evaluate_rows(&expr.partition_by, input.batches()?)?,
+                order_keys: evaluate_rows(&expr.order_by, input.batches()?)?,
+                order_asc: expr.order_asc.clone(),
+                args: evaluate_rows(&expr.args, input.batches()?)?,
+            };
+            window_columns.push(evaluate_window(
+                expr.function,
+                &expr.frame,
+                expr.ignore_nulls,
+                &rows,
+            )?);
+        }
+        self.buffered = Some(input);
+        Ok(window_columns)
+    }
+}
+
+impl SimpleRelation for WindowRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        let window_columns = match self.evaluate() {
+            Ok(columns) => columns,
+            Err(e) => return Box::new(iter::once(Err(e))),
+        };
+        let batches = match self.buffered {
+            Some(ref input) => match input.batches() {
+                Ok(batches) => batches,
+                Err(e) => return Box::new(iter::once(Err(e))),
+            },
+            None => return Box::new(iter::empty()),
+        };
+        let window_expr = &self.window_expr;
+        let schema = self.schema.clone();
+        let mut offset = 0;
+        Box::new(batches.map(move |batch| {
+            let batch = batch?;
+            let row_count = batch.num_rows();
+            let mut columns = batch.columns().clone();
+            for (expr, values) in window_expr.iter().zip(window_columns.iter()) {
+                let array =
+                    array_from_scalars(&values[offset..offset + row_count], &expr.return_type)?;
+                columns.push(Value::Column(Rc::new(array)));
+            }
+            offset += row_count;
+            Ok(Rc::new(DefaultRecordBatch {
+                schema: schema.clone(),
+                data: columns,
+                row_count,
+            }) as Rc<RecordBatch>)
+        }))
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.schema.as_ref()
+    }
+}
+
+/// Evaluate expressions against every row of the input, producing one list of values per row
+fn evaluate_rows<'a>(
+    expr: &[CompiledExpr],
+    batches: Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a>,
+) -> Result<Vec<Vec<ScalarValue>>> {
+    let mut rows = vec![];
+    for batch in batches {
+        let batch = batch?;
+        let values = expr
+            .iter()
+            .map(|e| e(batch.as_ref()))
+            .collect::<Result<Vec<Value>>>()?;
+        for i in 0..batch.num_rows() {
+            rows.push(values.iter().map(|v| value_at(v, i)).collect());
+        }
+    }
+    Ok(rows)
+}
+
+/// The values that a window function needs for each row of its input
+pub struct WindowRows {
+    pub partition_keys: Vec<Vec<ScalarValue>>,
+    pub order_keys: Vec<Vec<ScalarValue>>,
+    pub order_asc: Vec<bool>,
+    pub args: Vec<Vec<ScalarValue>>,
+}
+
+impl WindowRows {
+    fn compare_order(&self, a: usize, b: usize) -> Ordering {
+        for (i, asc) in self.order_asc.iter().enumerate() {
+            let ordering = compare_values(&self.order_keys[a][i], &self.order_keys[b][i]);
+            if ordering != Ordering::Equal {
+                return if *asc { ordering } else { ordering.reverse() };
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/// Compare two values, with nulls before all other values
+fn compare_values(a: &ScalarValue, b: &ScalarValue) -> Ordering {
+    a.partial_cmp(b).unwrap_or(Ordering::Equal)
+}
+
+/// Compute the value of a window function for every row, in input order
+pub fn evaluate_window(
+    function: WindowFunction,
+    frame: &WindowFrame,
+    ignore_nulls: bool,
+    rows: &WindowRows,
+) -> Result<Vec<ScalarValue>> {
+    let row_count = rows.args.len();
+    // sort by the partition keys and then the order keys, compared as encoded rows
+    let partition_asc = vec![true; rows.partition_keys.first().map(|k| k.len()).unwrap_or(0)];
+    let sort_keys: Vec<Vec<u8>> = (0..row_count)
+        .map(|i| {
+            let mut key = vec![];
+            encode_scalars(&rows.partition_keys[i], &partition_asc, &mut key);
+            encode_scalars(&rows.order_keys[i], &rows.order_asc, &mut key);
+            key
+        }).collect();
+    let mut sorted: Vec<usize> = (0..row_count).collect();
+    sorted.sort_by(|a, b| sort_keys[*a].cmp(&sort_keys[*b]));
+
+    let mut results = vec![ScalarValue::Null; row_count];
+    let mut start = 0;
+    while start < row_count {
+        let mut end = start + 1;
+        while end < row_count
+            && rows.partition_keys[sorted[end]] == rows.partition_keys[sorted[start]]
+        {
+            end += 1;
+        }
+        let partition = Partition::new(&sorted[start..end], rows, frame)?;
+        let values = partition.evaluate(function, frame, ignore_nulls)?;
+        for (position, value) in values.into_iter().enumerate() {
+            results[sorted[start + position]] = value;
+        }
+        start = end;
+    }
+    Ok(results)
+}
+
+/// The rows of one partition, in window order
+struct Partition<'a> {
+    rows: &'a [usize],
+    values: &'a WindowRows,
+    /// The peer group of each row
+    group_of: Vec<usize>,
+    /// The position of the first row of each peer group, followed by the number of rows
+    group_start: Vec<usize>,
+    /// The first ORDER BY value of each row, when RANGE offsets are measured from it
+    range_keys: Vec<Option<f64>>,
+}
+
+impl<'a> Partition<'a> {
+    fn new(rows: &'a [usize], values: &'a WindowRows, frame: &WindowFrame) -> Result<Self> {
+        let mut group_of = Vec::with_capacity(rows.len());
+        let mut group_start = vec![];
+        for i in 0..rows.len() {
+            if i == 0 || values.compare_order(rows[i - 1], rows[i]) != Ordering::Equal {
+                group_start.push(i);
+            }
+            group_of.push(group_start.len() - 1);
+        }
+        group_start.push(rows.len());
+
+        let has_offset = |b: &WindowFrameBound| match *b {
+            WindowFrameBound::Preceding(_) | WindowFrameBound::Following(_) => true,
+            _ => false,
+        };
+        let mut range_keys = vec![];
+        if frame.units == WindowFrameUnits::Range
+            && (has_offset(&frame.start) || has_offset(&frame.end))
+        {
+            for row in rows {
+                range_keys.push(match values.order_keys[*row][0] {
+                    ScalarValue::Null => None,
+                    ref v => Some(as_f64(v)?),
+                });
+            }
+        }
+
+        Ok(Partition {
+            rows,
+            values,
+            group_of,
+            group_start,
+            range_keys,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The position of the first row of a peer group, or the partition length past the last
+    fn group_position(&self, group: usize) -> usize {
+        self.group_start[group.min(self.group_start.len() - 1)]
+    }
+
+    fn arg(&self, position: usize) -> &ScalarValue {
+        &self.values.args[self.rows[position]][0]
+    }
+
+    /// Get the constant integer argument at `index`, such as the bucket count of NTILE
+    fn parameter(&self, index: usize) -> Result<usize> {
+        match self.values.args[self.rows[0]][index] {
+            ScalarValue::Int64(n) if n > 0 => Ok(n as usize),
+            ref other => Err(DataFusionError::Execution(format!(
+                "Window function expected a positive integer but found {:?}",
+                other
+            ))),
+        }
+    }
+
+    fn evaluate(
+        &self,
+        function: WindowFunction,
+        frame: &WindowFrame,
+        ignore_nulls: bool,
+    ) -> Result<Vec<ScalarValue>> {
+        let mut results = Vec::with_capacity(self.len());
+        match function {
+            WindowFunction::RowNumber => {
+                for p in 0..self.len() {
+                    results.push(ScalarValue::UInt64(p as u64 + 1));
+                }
+            }
+            WindowFunction::Rank => {
+                for p in 0..self.len() {
+                    let rank = self.group_start[self.group_of[p]] + 1;
+                    results.push(ScalarValue::UInt64(rank as u64));
+                }
+            }
+            WindowFunction::DenseRank => {
+                for p in 0..self.len() {
+                    results.push(ScalarValue::UInt64(self.group_of[p] as u64 + 1));
+                }
+            }
+            WindowFunction::Ntile => {
+                let buckets = self.parameter(0)?;
+                let size = self.len() / buckets;
+                let remainder = self.len() % buckets;
+                // the first `remainder` buckets each hold one extra row
+                let large = remainder * (size + 1);
+                for p in 0..self.len() {
+                    let bucket = if p < large {
+                        p / (size + 1)
+                    } else {
+                        remainder + (p - large) / size
+                    };
+                    results.push(ScalarValue::UInt64(bucket as u64 + 1));
+                }
+            }
+            WindowFunction::FirstValue | WindowFunction::LastValue | WindowFunction::NthValue => {
+                let n = match function {
+                    WindowFunction::NthValue => self.parameter(1)?,
+                    _ => 1,
+                };
+                for p in 0..self.len() {
+                    let ranges = self.frame_ranges(frame, p);
+                    let mut positions = ranges
+                        .iter()
+                        .flat_map(|&(start, end)| start..end)
+                        .filter(|&q| !ignore_nulls || *self.arg(q) != ScalarValue::Null);
+                    let position = match function {
+                        WindowFunction::LastValue => positions.next_back(),
+                        _ => positions.nth(n - 1),
+                    };
+                    results.push(match position {
+                        Some(q) => self.arg(q).clone(),
+                        None => ScalarValue::Null,
+                    });
+                }
+            }
+            _ if frame.start == WindowFrameBound::UnboundedPreceding
+                && frame.exclusion == WindowFrameExclusion::NoOthers =>
+            {
+                // every frame starts at the first row and the frame ends never move backwards,
+                // so the frames can be aggregated incrementally
+                let mut accumulator = Accumulator::new(function);
+                let mut end = 0;
+                for p in 0..self.len() {
+                    let (_, frame_end) = self.frame_bounds(frame, p);
+                    while end < frame_end {
+                        accumulator.update(self.arg(end))?;
+                        end += 1;
+                    }
+                    results.push(accumulator.result());
+                }
+            }
+            _ => {
+                for p in 0..self.len() {
+                    let mut accumulator = Accumulator::new(function);
+                    for (start, end) in self.frame_ranges(frame, p) {
+                        for q in start..end {
+                            accumulator.update(self.arg(q))?;
+                        }
+                    }
+                    results.push(accumulator.result());
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Get the ranges of positions in the frame of the row at position `p`, after exclusions
+    fn frame_ranges(&self, frame: &WindowFrame, p: usize) -> Vec<(usize, usize)> {
+        let (start, end) = self.frame_bounds(frame, p);
+        let group = self.group_of[p];
+        let (group_start, group_end) = (self.group_start[group], self.group_start[group + 1]);
+        let excluded = match frame.exclusion {
+            WindowFrameExclusion::NoOthers => vec![],
+            WindowFrameExclusion::CurrentRow => vec![(p, p + 1)],
+            WindowFrameExclusion::Group => vec![(group_start, group_end)],
+            WindowFrameExclusion::Ties => vec![(group_start, p), (p + 1, group_end)],
+        };
+        let mut ranges = vec![(start, end)];
+        for (a, b) in excluded {
+            ranges = ranges
+                .into_iter()
+                .flat_map(|(s, e)| vec![(s, e.min(a)), (s.max(b), e)])
+                .filter(|&(s, e)| s < e)
+                .collect();
+        }
+        ranges
+    }
+
+    /// Get the first position in the frame of the row at position `p` and the position after
+    /// the last
+    fn frame_bounds(&self, frame: &WindowFrame, p: usize) -> (usize, usize) {
+        let start = self.bound_position(frame.units, &frame.start, p, true);
+        let end = self.bound_position(frame.units, &frame.end, p, false);
+        (start, end.max(start))
+    }
+
+    /// Find the position that a frame bound refers to for the row at position `p`. For the
+    /// start of a frame this is the first position in it, and for the end it is the position
+    /// after the last.
+    fn bound_position(
+        &self,
+        units: WindowFrameUnits,
+        bound: &WindowFrameBound,
+        p: usize,
+        is_start: bool,
+    ) -> usize {
+        let len = self.len();
+        let group = self.group_of[p];
+        let (offset, preceding) = match *bound {
+            WindowFrameBound::UnboundedPreceding => return 0,
+            WindowFrameBound::UnboundedFollowing => return len,
+            WindowFrameBound::CurrentRow => {
+                return match (units, is_start) {
+                    (WindowFrameUnits::Rows, true) => p,
+                    (WindowFrameUnits::Rows, false) => p + 1,
+                    (_, true) => self.group_start[group],
+                    (_, false) => self.group_start[group + 1],
+                }
+            }
+            WindowFrameBound::Preceding(n) => (n, true),
+            WindowFrameBound::Following(n) => (n, false),
+        };
+        match units {
+            WindowFrameUnits::Rows => {
+                let n = offset as usize;
+                match (preceding, is_start) {
+                    (true, true) => p.saturating_sub(n),
+                    (true, false) => (p + 1).saturating_sub(n),
+                    (false, true) => (p + n).min(len),
+                    (false, false) => (p + n + 1).min(len),
+                }
+            }
+            WindowFrameUnits::Groups => {
+                let n = offset as usize;
+                match (preceding, is_start) {
+                    (true, true) => self.group_position(group.saturating_sub(n)),
+                    (true, false) if group + 1 < n => 0,
+                    (true, false) => self.group_position(group + 1 - n),
+                    (false, true) => self.group_position(group + n),
+                    (false, false) => self.group_position(group + n + 1),
+                }
+            }
+            WindowFrameUnits::Range => {
+                let value = match self.range_keys[p] {
+                    Some(v) => v,
+                    // offsets can't be applied to a null, so the frame is the null peers
+                    None if is_start => return self.group_start[group],
+                    None => return self.group_start[group + 1],
+                };
+                let direction = if self.values.order_asc[0] { 1.0 } else { -1.0 };
+                let target = if preceding { -offset } else { offset };
+                // nulls sort first when ascending and last when descending, and the distance
+                // from the current value increases through the rows between them
+                let (mut lo, mut hi) = (0, len);
+                while lo < hi && self.range_keys[lo].is_none() {
+                    lo += 1;
+                }
+                while hi > lo && self.range_keys[hi - 1].is_none() {
+                    hi -= 1;
+                }
+                let distance = |q: usize| (self.range_keys[q].unwrap() - value) * direction;
+                while lo < hi {
+                    let mid = lo + (hi - lo) / 2;
+                    let inside = if is_start {
+                        distance(mid) < target
+                    } else {
+                        distance(mid) <= target
+                    };
+                    if inside {
+                        lo = mid + 1;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                lo
+            }
+        }
+    }
+}
+
+/// Computes an aggregate over the rows of a frame, ignoring nulls
+struct Accumulator {
+    function: WindowFunction,
+    /// The sum, minimum or maximum so far
+    value: ScalarValue,
+    count: u64,
+    /// The sum so far for AVG
+    total: f64,
+}
+
+impl Accumulator {
+    fn new(function: WindowFunction) -> Self {
+        Accumulator {
+            function,
+            value: ScalarValue::Null,
+            count: 0,
+            total: 0.0,
+        }
+    }
+
+    fn update(&mut self, value: &ScalarValue) -> Result<()> {
+        if *value == ScalarValue::Null {
+            return Ok(());
+        }
+        self.count += 1;
+        match self.function {
+            WindowFunction::Sum => {
+                // checks that the value is numeric, which addition requires
+                as_f64(value)?;
+                self.value = match self.value {
+                    ScalarValue::Null => value.clone(),
+                    ref sum => sum.clone() + value.clone(),
+                };
+            }
+            WindowFunction::Min => {
+                if self.value == ScalarValue::Null || *value < self.value {
+                    self.value = value.clone();
+                }
+            }
+            WindowFunction::Max => {
+                if self.value == ScalarValue::Null || *value > self.value {
+                    self.value = value.clone();
+                }
+            }
+            WindowFunction::Avg => self.total += as_f64(value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn result(&self) -> ScalarValue {
+        match self.function {
+            WindowFunction::Count => ScalarValue::UInt64(self.count),
+            WindowFunction::Avg if self.count == 0 => ScalarValue::Null,
+            WindowFunction::Avg => ScalarValue::Float64(self.total / self.count as f64),
+            _ => self.value.clone(),
+        }
+    }
+}
+
+fn as_f64(value: &ScalarValue) -> Result<f64> {
+    match *value {
+        ScalarValue::Int8(n) => Ok(n as f64),
+        ScalarValue::Int16(n) => Ok(n as f64),
+        ScalarValue::Int32(n) => Ok(n as f64),
+        ScalarValue::Int64(n) => Ok(n as f64),
+        ScalarValue::UInt8(n) => Ok(n as f64),
+        ScalarValue::UInt16(n) => Ok(n as f64),
+        ScalarValue::UInt32(n) => Ok(n as f64),
+        ScalarValue::UInt64(n) => Ok(n as f64),
+        ScalarValue::Float32(n) => Ok(n as f64),
+        ScalarValue::Float64(n) => Ok(n),
+        ref other => Err(DataFusionError::Execution(format!(
+            "Window function expected a numeric value but found {:?}",
+            other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(
+        units: WindowFrameUnits,
+        start: WindowFrameBound,
+        end: WindowFrameBound,
+    ) -> WindowFrame {
+        WindowFrame {
+            units,
+            start,
+            end,
+            exclusion: WindowFrameExclusion::NoOthers,
+        }
+    }
+
+    /// Rows in one partition ordered by `keys`, summing `values`
+    fn rows(keys: Vec<ScalarValue>, values: Vec<i64>) -> WindowRows {
+        WindowRows {
+            partition_keys: keys.iter().map(|_| vec![]).collect(),
+            order_keys: keys.into_iter().map(|k| vec![k]).collect(),
+            order_asc: vec![true],
+            args: values
+                .into_iter()
+                .map(|v| vec![ScalarValue::Int64(v)])
+                .collect(),
+        }
+    }
+
+    fn sums(frame: &WindowFrame, rows: &WindowRows) -> Vec<ScalarValue> {
+        evaluate_window(WindowFunction::Sum, frame, false, rows).unwrap()
+    }
+
+    fn ints(values: Vec<i64>) -> Vec<ScalarValue> {
+        values.into_iter().map(ScalarValue::Int64).collect()
+    }
+
+    #[test]
+    fn test_rows_frame() {
+        let rows = rows(ints(vec![1, 2, 3, 4]), vec![1, 10, 100, 1000]);
+        let f = frame(
+            WindowFrameUnits::Rows,
+            WindowFrameBound::Preceding(1.0),
+            WindowFrameBound::Following(1.0),
+        );
+        assert_eq!(ints(vec![11, 111, 1110, 1100]), sums(&f, &rows));
+    }
+
+    #[test]
+    fn test_range_frame() {
+        // keys 1 and 2 are within 1 of each other but 5 is not
+        let rows = rows(ints(vec![5, 1, 2, 2]), vec![1, 10, 100, 1000]);
+        let f = frame(
+            WindowFrameUnits::Range,
+            WindowFrameBound::Preceding(1.0),
+            WindowFrameBound::CurrentRow,
+        );
+        assert_eq!(ints(vec![1, 10, 1110, 1110]), sums(&f, &rows));
+    }
+
+    #[test]
+    fn test_range_frame_descending_with_nulls() {
+        let mut rows = rows(
+            vec![
+                ScalarValue::Int64(1),
+                ScalarValue::Null,
+                ScalarValue::Int64(3),
+            ],
+            vec![1, 10, 100],
+        );
+        rows.order_asc = vec![false];
+        let f = frame(
+            WindowFrameUnits::Range,
+            WindowFrameBound::CurrentRow,
+            WindowFrameBound::Following(2.0),
+        );
+        // following rows have smaller keys when descending, and the null row is on its own
+        assert_eq!(ints(vec![1, 10, 101]), sums(&f, &rows));
+    }
+
+    #[test]
+    fn test_groups_frame_with_exclusions() {
+        let rows = rows(ints(vec![1, 1, 2, 3]), vec![1, 10, 100, 1000]);
+        let mut f = frame(
+            WindowFrameUnits::Groups,
+            WindowFrameBound::Preceding(1.0),
+            WindowFrameBound::CurrentRow,
+        );
+        assert_eq!(ints(vec![11, 11, 111, 1100]), sums(&f, &rows));
+        f.exclusion = WindowFrameExclusion::CurrentRow;
+        assert_eq!(ints(vec![10, 1, 11, 100]), sums(&f, &rows));
+        f.exclusion = WindowFrameExclusion::Ties;
+        assert_eq!(ints(vec![1, 10, 111, 1100]), sums(&f, &rows));
+        f.exclusion = WindowFrameExclusion::Group;
+        assert_eq!(
+            vec![
+                ScalarValue::Null,
+                ScalarValue::Null,
+                ScalarValue::Int64(11),
+                ScalarValue::Int64(100)
+            ],
+            sums(&f, &rows)
+        );
+    }
+
+    #[test]
+    fn test_ranking_and_partitions() {
+        let mut rows = rows(ints(vec![1, 1, 2, 1]), vec![0, 0, 0, 0]);
+        rows.partition_keys = ints(vec![0, 0, 0, 1])
+            .into_iter()
+            .map(|k| vec![k])
+            .collect();
+        let f = WindowFrame::default_for(true);
+        let rank = |function| evaluate_window(function, &f, false, &rows).unwrap();
+        let uints = |v: Vec<u64>| v.into_iter().map(ScalarValue::UInt64).collect::<Vec<_>>();
+        assert_eq!(uints(vec![1, 2, 3, 1]), rank(WindowFunction::RowNumber));
+        assert_eq!(uints(vec![1, 1, 3, 1]), rank(WindowFunction::Rank));
+        assert_eq!(uints(vec![1, 1, 2, 1]), rank(WindowFunction::DenseRank));
+    }
+
+    #[test]
+    fn test_ntile() {
+        let mut rows = rows(ints(vec![1, 2, 3, 4, 5]), vec![0, 0, 0, 0, 0]);
+        let f = WindowFrame::default_for(true);
+        let uints = |v: Vec<u64>| v.into_iter().map(ScalarValue::UInt64).collect::<Vec<_>>();
+        rows.args = vec![vec![ScalarValue::Int64(3)]; 5];
+        assert_eq!(
+            uints(vec![1, 1, 2, 2, 3]),
+            evaluate_window(WindowFunction::Ntile, &f, false, &rows).unwrap()
+        );
+        rows.args = vec![vec![ScalarValue::Int64(7)]; 5];
+        assert_eq!(
+            uints(vec![1, 2, 3, 4, 5]),
+            evaluate_window(WindowFunction::Ntile, &f, false, &rows).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_value_functions() {
+        let mut rows = rows(ints(vec![1, 2, 3, 4]), vec![0, 0, 0, 0]);
+        rows.args = vec![
+            vec![ScalarValue::Null, ScalarValue::Int64(2)],
+            vec![ScalarValue::Int64(10), ScalarValue::Int64(2)],
+            vec![ScalarValue::Null, ScalarValue::Int64(2)],
+            vec![ScalarValue::Int64(30), ScalarValue::Int64(2)],
+        ];
+        let f = frame(
+            WindowFrameUnits::Rows,
+            WindowFrameBound::UnboundedPreceding,
+            WindowFrameBound::CurrentRow,
+        );
+        let values = |function, ignore_nulls| {
+            evaluate_window(function, &f, ignore_nulls, &rows).unwrap()
+        };
+        let null = ScalarValue::Null;
+        let (ten, thirty) = (ScalarValue::Int64(10), ScalarValue::Int64(30));
+        assert_eq!(vec![null.clone(); 4], values(WindowFunction::FirstValue, false));
+        assert_eq!(
+            vec![null.clone(), ten.clone(), ten.clone(), ten.clone()],
+            values(WindowFunction::FirstValue, true)
+        );
+        assert_eq!(
+            vec![null.clone(), ten.clone(), null.clone(), thirty.clone()],
+            values(WindowFunction::LastValue, false)
+        );
+        assert_eq!(
+            vec![null.clone(), ten.clone(), ten.clone(), thirty.clone()],
+            values(WindowFunction::LastValue, true)
+        );
+        assert_eq!(
+            vec![null.clone(), ten.clone(), ten.clone(), ten.clone()],
+            values(WindowFunction::NthValue, false)
+        );
+        assert_eq!(
+            vec![null.clone(), null.clone(), null.clone(), thirty.clone()],
+            values(WindowFunction::NthValue, true)
+        );
+    }
+}
@@ -0,0 +1,367 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Resource groups, which limit the resources that the queries of a set of sessions use
+//!
+//! When sessions of several tenants share a server, each tenant's sessions are put in a
+//! group of their own with `ExecutionContext::set_resource_group`, and the group's limits
+//! apply to all of its queries together. A query that would exceed a quota fails, and a query
+//! that wants more scan threads than its group has free gets fewer, so one tenant's large
+//! query can't starve the queries of other tenants.
+//!
+//! Memory is counted for the batches that relations hold until their input is complete, the
+//! build side of joins and the input of window functions, for the groups of hash aggregations
+//! and for the rows that ORDER BY buffers while they share a prefix of the sort keys. It is
+//! not counted for the state of time window aggregations. Window functions, sorts of rows that
+//! share a prefix, hash joins and hash aggregations spill what would exceed the quota instead
+//! of failing, see `spill`.
+//!
+//! A group can be shared by sessions on different threads.
+
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use arrow::datatypes::Schema;
+
+use super::datasources::common::*;
+use super::errors::*;
+use super::exec::*;
+use super::types::*;
+
+/// Limits on the resources of a group. A limit of None is unlimited.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// Maximum number of threads that the group's queries scan files with at once. Each query
+    /// gets at least one thread, so that it can make progress.
+    pub max_concurrency: Option<usize>,
+    /// Maximum bytes that the group's queries hold in memory at once, counting the batches
+    /// held by joins and window functions and the groups of hash aggregations
+    pub max_memory: Option<usize>,
+    /// Maximum bytes of files that the group's queries read in total, until the count is reset
+    pub max_scan_bytes: Option<u64>,
+}
+
+/// The limits of a group of sessions and the resources they are using
+pub struct ResourceGroup {
+    name: String,
+    limits: ResourceLimits,
+    usage: Mutex<ResourceUsage>,
+}
+
+/// The resources that the queries of a group are using
+#[derive(Default)]
+struct ResourceUsage {
+    threads: usize,
+    memory: usize,
+    bytes_scanned: u64,
+}
+
+impl ResourceGroup {
+    pub fn new(name: &str, limits: ResourceLimits) -> Self {
+        ResourceGroup {
+            name: name.to_string(),
+            limits,
+            usage: Mutex::new(ResourceUsage::default()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn limits(&self) -> &ResourceLimits {
+        &self.limits
+    }
+
+    /// Number of scan threads that the group's queries hold
+    pub fn threads_used(&self) -> usize {
+        self.usage.lock().unwrap().threads
+    }
+
+    /// Bytes that the group's queries hold in memory
+    pub fn memory_used(&self) -> usize {
+        self.usage.lock().unwrap().memory
+    }
+
+    /// Bytes of files that the group's queries have read since the count was last reset
+    pub fn bytes_scanned(&self) -> u64 {
+        self.usage.lock().unwrap().bytes_scanned
+    }
+
+    /// Start counting scanned bytes from zero, e.g. at the start of a billing period
+    pub fn reset_bytes_scanned(&self) {
+        self.usage.lock().unwrap().bytes_scanned = 0;
+    }
+
+    /// Count the bytes of the files that a query is about to read, failing without counting
+    /// them if that would exceed the group's quota
+    pub fn charge_scan(&self, bytes: u64) -> Result<()> {
+        let mut usage = self.usage.lock().unwrap();
+        let total = usage.bytes_scanned + bytes;
+        match self.limits.max_scan_bytes {
+            Some(max) if total > max => Err(DataFusionError::Execution(format!(
+                "Resource group '{}' would exceed its scan quota of {} bytes: {} bytes have \
+                 been scanned and the query reads {} bytes",
+                self.name, max, usage.bytes_scanned, bytes
+            ))),
+            _ => {
+                usage.bytes_scanned = total;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Scan threads held by a query, which are returned to the group when dropped
+pub struct ThreadPermit {
+    group: Arc<ResourceGroup>,
+    threads: usize,
+}
+
+impl ThreadPermit {
+    /// Take up to `wanted` threads from the group, or a single thread if none are free
+    pub fn acquire(group: &Arc<ResourceGroup>, wanted: usize) -> Self {
+        let mut usage = group.usage.lock().unwrap();
+        let threads = match group.limits.max_concurrency {
+            Some(max) => wanted.min(max.saturating_sub(usage.threads)),
+            None => wanted,
+        }.max(1);
+        usage.threads += threads;
+        ThreadPermit {
+            group: group.clone(),
+            threads,
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+}
+
+impl Drop for ThreadPermit {
+    fn drop(&mut self) {
+        self.group.usage.lock().unwrap().threads -= self.threads;
+    }
+}
+
+/// Memory held by a relation, which is returned to the group when released or dropped
+pub struct MemoryReservation {
+    group: Arc<ResourceGroup>,
+    bytes: usize,
+}
+
+impl MemoryReservation {
+    pub fn new(group: Arc<ResourceGroup>) -> Self {
+        MemoryReservation { group, bytes: 0 }
+    }
+
+    /// Bytes that the reservation holds
+    pub fn size(&self) -> usize {
+        self.bytes
+    }
+
+    /// Reserve more memory, failing without reserving it if that would exceed the group's quota
+    pub fn grow(&mut self, bytes: usize) -> Result<()> {
+        let mut usage = self.group.usage.lock().unwrap();
+        let used = usage.memory + bytes;
+        match self.group.limits.max_memory {
+            Some(max) if used > max => Err(DataFusionError::Execution(format!(
+                "Resource group '{}' exceeded its memory quota of {} bytes",
+                self.group.name, max
+            ))),
+            _ => {
+                usage.memory = used;
+                self.bytes += bytes;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.group.usage.lock().unwrap().memory -= self.bytes;
+        self.bytes = 0;
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Estimate the bytes of memory that the columns of a batch use
+pub fn batch_memory_size(batch: &RecordBatch) -> usize {
+    batch
+        .columns()
+        .iter()
+        .map(|c| match *c {
+            Value::Column(ref array) => array_memory_size(array),
+            Value::Scalar(_) => 0,
+        })
+        .sum()
+}
+
+fn array_memory_size(array: &Array) -> usize {
+    let len = array.len();
+    let values = match *array.data() {
+        ArrayData::Boolean(_) | ArrayData::Int8(_) | ArrayData::UInt8(_) => len,
+        ArrayData::Int16(_) | ArrayData::UInt16(_) => len * 2,
+        ArrayData::Float32(_) | ArrayData::Int32(_) | ArrayData::UInt32(_) => len * 4,
+        ArrayData::Float64(_) | ArrayData::Int64(_) | ArrayData::UInt64(_) => len * 8,
+        // each string has a 4 byte offset
+        ArrayData::Utf8(ref list) => (0..len).map(|i| list.get(i).len() + 4).sum(),
+        ArrayData::Struct(ref fields) => fields.iter().map(|f| array_memory_size(f)).sum(),
+    };
+    let bitmap = match *array.validity_bitmap() {
+        Some(_) => (len + 7) / 8,
+        None => 0,
+    };
+    values + bitmap
+}
+
+/// Passes the batches of its input through, counting them against the memory quota of a group
+/// until the relation is dropped. It is placed below relations that hold all of their input.
+pub struct MemoryTrackingRelation {
+    input: Box<SimpleRelation>,
+    reservation: MemoryReservation,
+}
+
+impl MemoryTrackingRelation {
+    pub fn new(input: Box<SimpleRelation>, group: Arc<ResourceGroup>) -> Self {
+        MemoryTrackingRelation {
+            input,
+            reservation: MemoryReservation::new(group),
+        }
+    }
+}
+
+impl SimpleRelation for MemoryTrackingRelation {
+    fn scan<'a>(&'a mut self) -> Box<Iterator<Item = Result<Rc<RecordBatch>>> + 'a> {
+        // the batches of an earlier scan are no longer held when the input is read again
+        self.reservation.release();
+        let reservation = &mut self.reservation;
+        Box::new(self.input.scan().map(move |batch| {
+            let batch = batch?;
+            reservation.grow(batch_memory_size(batch.as_ref()))?;
+            Ok(batch)
+        }))
+    }
+
+    fn schema<'a>(&'a self) -> &'a Schema {
+        self.input.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn group(limits: ResourceLimits) -> Arc<ResourceGroup> {
+        Arc::new(ResourceGroup::new("tenant", limits))
+    }
+
+    #[test]
+    fn thread_permits() {
+        let group = group(ResourceLimits {
+            max_concurrency: Some(4),
+            ..ResourceLimits::default()
+        });
+        let a = ThreadPermit::acquire(&group, 3);
+        assert_eq!(3, a.threads());
+        let b = ThreadPermit::acquire(&group, 3);
+        assert_eq!(1, b.threads());
+        // a query always gets a thread
+        let c = ThreadPermit::acquire(&group, 3);
+        assert_eq!(1, c.threads());
+        assert_eq!(5, group.threads_used());
+        drop(a);
+        drop(c);
+        assert_eq!(1, group.threads_used());
+        assert_eq!(3, ThreadPermit::acquire(&group, 3).threads());
+    }
+
+    #[test]
+    fn memory_reservations() {
+        let group = group(ResourceLimits {
+            max_memory: Some(100),
+            ..ResourceLimits::default()
+        });
+        let mut a = MemoryReservation::new(group.clone());
+        let mut b = MemoryReservation::new(group.clone());
+        a.grow(60).unwrap();
+        assert!(b.grow(50).is_err());
+        b.grow(40).unwrap();
+        assert_eq!(100, group.memory_used());
+        drop(a);
+        assert_eq!(40, group.memory_used());
+        b.release();
+        assert_eq!(0, group.memory_used());
+        assert_eq!(0, b.size());
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let group = group(ResourceLimits {
+            max_memory: Some(1000),
+            ..ResourceLimits::default()
+        });
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let group = group.clone();
+                thread::spawn(move || {
+                    let mut reservation = MemoryReservation::new(group.clone());
+                    for _ in 0..100 {
+                        reservation.grow(1).unwrap();
+                    }
+                    let _permit = ThreadPermit::acquire(&group, 1);
+                    reservation.size()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(100, handle.join().unwrap());
+        }
+        assert_eq!(0, group.memory_used());
+        assert_eq!(0, group.threads_used());
+    }
+
+    #[test]
+    fn scan_quota() {
+        let group = group(ResourceLimits {
+            max_scan_bytes: Some(100),
+            ..ResourceLimits::default()
+        });
+        group.charge_scan(70).unwrap();
+        assert!(group.charge_scan(40).is_err());
+        assert_eq!(70, group.bytes_scanned());
+        group.reset_bytes_scanned();
+        group.charge_scan(100).unwrap();
+    }
+
+    #[test]
+    fn batch_memory() {
+        let schema = Rc::new(Schema::new(vec![]));
+        let batch = DefaultRecordBatch {
+            schema,
+            data: vec![
+                Value::Column(Rc::new(Array::from(vec![1i64, 2, 3]))),
+                Value::Column(Rc::new(Array::from(vec!["a", "bcd"]))),
+                Value::Scalar(Rc::new(ScalarValue::Int32(1))),
+            ],
+            row_count: 3,
+        };
+        assert_eq!(24 + 12, batch_memory_size(&batch));
+    }
+}
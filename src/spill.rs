@@ -0,0 +1,649 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Temporary files for relations that spill data to disk when it does not fit in memory
+//!
+//! Each execution context has one `SpillManager`, which creates spill files in a directory of
+//! its own beneath the configured directory and enforces a quota on the total size of the files
+//! that exist at any time. Spill files are deleted when they are dropped, so they are cleaned up
+//! when a query completes or is cancelled, and the directory is removed with the manager.
+//!
+//! Relations spill what would exceed the memory quota of the session's resource group:
+//!
+//! - Window relations spill the part of their input that doesn't fit.
+//! - Partial sorts spill each run of rows that doesn't fit as sorted chunks, which are merged
+//!   when the run ends.
+//! - Hash joins and hash aggregations use `SpillPartitions` to distribute the rows that don't
+//!   fit among files by the hash of their keys. Each file is then processed on its own, and
+//!   must fit in the quota.
+//!
+//! Adaptive and merge joins don't spill their build side.
+
+use std::cell::Cell;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use arrow::array::ListArray;
+use arrow::bitmap::Bitmap;
+use arrow::builder::*;
+use arrow::datatypes::*;
+use arrow::list_builder::ListBuilder;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::errors::*;
+use super::relations::coalesce::is_valid;
+use super::relations::row_hash::partition_of;
+use super::relations::sort::take_rows;
+use super::types::*;
+
+/// Distinguishes the directories of managers within the same process
+static NEXT_MANAGER_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpillConfig {
+    /// Directory that spill files are created under
+    pub directory: PathBuf,
+    /// Maximum number of bytes of spill files that can exist at once
+    pub max_bytes: Option<u64>,
+    /// Compress spill files, trading CPU time for disk space and bandwidth
+    pub compression: bool,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        SpillConfig {
+            directory: env::temp_dir(),
+            max_bytes: None,
+            compression: false,
+        }
+    }
+}
+
+pub struct SpillManager {
+    config: SpillConfig,
+    /// Directory owned by this manager, created when the first file is spilled
+    directory: PathBuf,
+    bytes_used: Rc<Cell<u64>>,
+    next_file_id: Cell<usize>,
+}
+
+impl SpillManager {
+    pub fn new(config: SpillConfig) -> Self {
+        let directory = config.directory.join(format!(
+            "datafusion-spill-{}-{}",
+            process::id(),
+            NEXT_MANAGER_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        SpillManager {
+            config,
+            directory,
+            bytes_used: Rc::new(Cell::new(0)),
+            next_file_id: Cell::new(0),
+        }
+    }
+
+    pub fn config(&self) -> &SpillConfig {
+        &self.config
+    }
+
+    /// Total size of the spill files that currently exist
+    pub fn bytes_used(&self) -> u64 {
+        self.bytes_used.get()
+    }
+
+    /// Create a spill file for batches with the given schema
+    pub fn create_file(&self, schema: Rc<Schema>) -> Result<SpillWriter> {
+        fs::create_dir_all(&self.directory)?;
+        let id = self.next_file_id.get();
+        self.next_file_id.set(id + 1);
+        let path = self.directory.join(format!("spill-{}.bin", id));
+        let file_bytes = Rc::new(Cell::new(0));
+        let output = QuotaWriter {
+            inner: BufWriter::new(File::create(&path)?),
+            file_bytes: file_bytes.clone(),
+            bytes_used: self.bytes_used.clone(),
+            max_bytes: self.config.max_bytes,
+        };
+        Ok(SpillWriter {
+            output: Some(if self.config.compression {
+                SpillOutput::Compressed(GzEncoder::new(output, Compression::fast()))
+            } else {
+                SpillOutput::Plain(output)
+            }),
+            path,
+            schema,
+            compressed: self.config.compression,
+            num_batches: 0,
+            file_bytes,
+            bytes_used: self.bytes_used.clone(),
+        })
+    }
+}
+
+impl Drop for SpillManager {
+    fn drop(&mut self) {
+        // files that are still open are removed along with the directory
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
+
+/// Writes to a spill file, failing once the manager's quota would be exceeded
+struct QuotaWriter {
+    inner: BufWriter<File>,
+    file_bytes: Rc<Cell<u64>>,
+    bytes_used: Rc<Cell<u64>>,
+    max_bytes: Option<u64>,
+}
+
+impl Write for QuotaWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(max) = self.max_bytes {
+            if self.bytes_used.get() + buf.len() as u64 > max {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Spill quota of {} bytes exceeded", max),
+                ));
+            }
+        }
+        let n = self.inner.write(buf)?;
+        self.file_bytes.set(self.file_bytes.get() + n as u64);
+        self.bytes_used.set(self.bytes_used.get() + n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+enum SpillOutput {
+    Plain(QuotaWriter),
+    Compressed(GzEncoder<QuotaWriter>),
+}
+
+impl SpillOutput {
+    fn writer(&mut self) -> &mut Write {
+        match *self {
+            SpillOutput::Plain(ref mut w) => w,
+            SpillOutput::Compressed(ref mut w) => w,
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        let mut w = match self {
+            SpillOutput::Plain(w) => w,
+            SpillOutput::Compressed(w) => w.finish()?,
+        };
+        w.flush()
+    }
+}
+
+pub struct SpillWriter {
+    /// None once the writer has been finished
+    output: Option<SpillOutput>,
+    path: PathBuf,
+    schema: Rc<Schema>,
+    compressed: bool,
+    num_batches: usize,
+    file_bytes: Rc<Cell<u64>>,
+    bytes_used: Rc<Cell<u64>>,
+}
+
+impl SpillWriter {
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        match self.output {
+            Some(ref mut output) => write_batch(output.writer(), batch)?,
+            None => {
+                return Err(DataFusionError::Execution(
+                    "Spill file has already been finished".to_string(),
+                ))
+            }
+        }
+        self.num_batches += 1;
+        Ok(())
+    }
+
+    /// Finish writing, returning the file so that it can be read back
+    pub fn finish(mut self) -> Result<SpillFile> {
+        if let Some(output) = self.output.take() {
+            if let Err(e) = output.finish() {
+                self.abandon();
+                return Err(e.into());
+            }
+        }
+        Ok(SpillFile {
+            path: self.path.clone(),
+            schema: self.schema.clone(),
+            compressed: self.compressed,
+            num_batches: self.num_batches,
+            size: self.file_bytes.get(),
+            bytes_used: self.bytes_used.clone(),
+        })
+    }
+
+    /// Delete a file that could not be completed and release its space
+    fn abandon(&mut self) {
+        self.output = None;
+        let _ = fs::remove_file(&self.path);
+        self.bytes_used
+            .set(self.bytes_used.get() - self.file_bytes.get());
+        self.file_bytes.set(0);
+    }
+}
+
+impl Drop for SpillWriter {
+    fn drop(&mut self) {
+        // a writer that was not finished is abandoned, e.g. because the query failed
+        if self.output.is_some() {
+            self.abandon();
+        }
+    }
+}
+
+/// A completed spill file, which is deleted when dropped
+pub struct SpillFile {
+    path: PathBuf,
+    schema: Rc<Schema>,
+    compressed: bool,
+    num_batches: usize,
+    size: u64,
+    bytes_used: Rc<Cell<u64>>,
+}
+
+impl SpillFile {
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Size of the file on disk
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn num_batches(&self) -> usize {
+        self.num_batches
+    }
+
+    /// Read the batches back in the order they were written
+    pub fn read(&self) -> Result<Box<Iterator<Item = Result<Rc<RecordBatch>>>>> {
+        let file = BufReader::new(File::open(&self.path)?);
+        let mut input: Box<Read> = if self.compressed {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let schema = self.schema.clone();
+        Ok(Box::new(
+            (0..self.num_batches).map(move |_| read_batch(&mut input, &schema)),
+        ))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        self.bytes_used.set(self.bytes_used.get() - self.size);
+    }
+}
+
+/// Number of files that hash joins and aggregations distribute their rows among when they
+/// spill
+pub const SPILL_PARTITIONS: usize = 16;
+
+/// Spill files that rows are distributed among by the hash of their keys, so that all rows with
+/// equal keys are in the same file
+pub struct SpillPartitions {
+    manager: Rc<SpillManager>,
+    schema: Rc<Schema>,
+    /// The writer of each partition, created when the first row is written to it
+    writers: Vec<Option<SpillWriter>>,
+}
+
+impl SpillPartitions {
+    pub fn new(manager: Rc<SpillManager>, schema: Rc<Schema>) -> Self {
+        SpillPartitions {
+            manager,
+            schema,
+            writers: (0..SPILL_PARTITIONS).map(|_| None).collect(),
+        }
+    }
+
+    /// Write rows of a batch to the partitions of their hashes, which are indexed by row. Rows
+    /// without a hash, because their keys are null, are written to the first partition.
+    pub fn write(
+        &mut self,
+        batch: &RecordBatch,
+        rows: &[usize],
+        hashes: &[Option<u64>],
+    ) -> Result<()> {
+        let mut partitions: Vec<Vec<usize>> = vec![vec![]; SPILL_PARTITIONS];
+        for &row in rows {
+            let partition = match hashes[row] {
+                Some(hash) => partition_of(hash, SPILL_PARTITIONS),
+                None => 0,
+            };
+            partitions[partition].push(row);
+        }
+        for (partition, indices) in partitions.iter().enumerate() {
+            if indices.is_empty() {
+                continue;
+            }
+            if self.writers[partition].is_none() {
+                self.writers[partition] = Some(self.manager.create_file(self.schema.clone())?);
+            }
+            if let Some(ref mut writer) = self.writers[partition] {
+                writer.write_batch(take_rows(batch, indices)?.as_ref())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish writing, returning the file of each partition, or None for partitions without
+    /// rows
+    pub fn finish(self) -> Result<Vec<Option<SpillFile>>> {
+        self.writers
+            .into_iter()
+            .map(|writer| match writer {
+                Some(writer) => writer.finish().map(Some),
+                None => Ok(None),
+            })
+            .collect()
+    }
+}
+
+const TAG_NULL_SCALAR: u8 = 0;
+const TAG_ARRAY: u8 = 1;
+
+macro_rules! write_values {
+    ($W:expr, $VALUES:expr, $WRITE:ident) => {{
+        for v in $VALUES.iter() {
+            $W.$WRITE::<LittleEndian>(v)?;
+        }
+    }};
+}
+
+/// Write a batch in the format of spill files. Columns of scalar values other than nulls
+/// can't be written.
+pub fn write_batch(w: &mut Write, batch: &RecordBatch) -> Result<()> {
+    w.write_u64::<LittleEndian>(batch.num_rows() as u64)?;
+    for column in batch.columns() {
+        let array = match *column {
+            Value::Column(ref array) => array,
+            // scalars only appear as placeholders for columns that were not projected
+            Value::Scalar(ref v) => match v.as_ref() {
+                ScalarValue::Null => {
+                    w.write_u8(TAG_NULL_SCALAR)?;
+                    continue;
+                }
+                _ => {
+                    return Err(DataFusionError::Execution(
+                        "Cannot spill scalar values".to_string(),
+                    ))
+                }
+            },
+        };
+        w.write_u8(TAG_ARRAY)?;
+        w.write_u64::<LittleEndian>(array.null_count() as u64)?;
+        if array.null_count() > 0 {
+            for i in 0..array.len() {
+                w.write_u8(is_valid(array, i) as u8)?;
+            }
+        }
+        match *array.data() {
+            ArrayData::Boolean(ref v) => for b in v.iter() {
+                w.write_u8(b as u8)?;
+            },
+            ArrayData::Int8(ref v) => for n in v.iter() {
+                w.write_i8(n)?;
+            },
+            ArrayData::UInt8(ref v) => for n in v.iter() {
+                w.write_u8(n)?;
+            },
+            ArrayData::Int16(ref v) => write_values!(w, v, write_i16),
+            ArrayData::Int32(ref v) => write_values!(w, v, write_i32),
+            ArrayData::Int64(ref v) => write_values!(w, v, write_i64),
+            ArrayData::UInt16(ref v) => write_values!(w, v, write_u16),
+            ArrayData::UInt32(ref v) => write_values!(w, v, write_u32),
+            ArrayData::UInt64(ref v) => write_values!(w, v, write_u64),
+            ArrayData::Float32(ref v) => write_values!(w, v, write_f32),
+            ArrayData::Float64(ref v) => write_values!(w, v, write_f64),
+            ArrayData::Utf8(ref list) => for i in 0..array.len() {
+                let bytes = list.get(i);
+                w.write_u64::<LittleEndian>(bytes.len() as u64)?;
+                w.write_all(bytes)?;
+            },
+            ArrayData::Struct(_) => {
+                return Err(DataFusionError::Execution(
+                    "Cannot spill struct arrays yet".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+macro_rules! read_values {
+    ($LEN:expr, $TY:ty, $READ:expr) => {{
+        let mut b: Builder<$TY> = Builder::with_capacity($LEN);
+        for _ in 0..$LEN {
+            b.push($READ);
+        }
+        ArrayData::from(b.finish())
+    }};
+}
+
+/// Read a batch written by `write_batch`
+pub fn read_batch(r: &mut Read, schema: &Rc<Schema>) -> Result<Rc<RecordBatch>> {
+    let num_rows = r.read_u64::<LittleEndian>()? as usize;
+    let mut columns = Vec::with_capacity(schema.columns().len());
+    for field in schema.columns() {
+        if r.read_u8()? == TAG_NULL_SCALAR {
+            columns.push(Value::Scalar(Rc::new(ScalarValue::Null)));
+            continue;
+        }
+        let null_count = r.read_u64::<LittleEndian>()? as usize;
+        let mut bitmap = Bitmap::new(num_rows);
+        if null_count > 0 {
+            for i in 0..num_rows {
+                if r.read_u8()? == 0 {
+                    bitmap.clear(i);
+                }
+            }
+        }
+        let data = match *field.data_type() {
+            DataType::Boolean => read_values!(num_rows, bool, r.read_u8()? != 0),
+            DataType::Int8 => read_values!(num_rows, i8, r.read_i8()?),
+            DataType::UInt8 => read_values!(num_rows, u8, r.read_u8()?),
+            DataType::Int16 => read_values!(num_rows, i16, r.read_i16::<LittleEndian>()?),
+            DataType::Int32 => read_values!(num_rows, i32, r.read_i32::<LittleEndian>()?),
+            DataType::Int64 => read_values!(num_rows, i64, r.read_i64::<LittleEndian>()?),
+            DataType::UInt16 => read_values!(num_rows, u16, r.read_u16::<LittleEndian>()?),
+            DataType::UInt32 => read_values!(num_rows, u32, r.read_u32::<LittleEndian>()?),
+            DataType::UInt64 => read_values!(num_rows, u64, r.read_u64::<LittleEndian>()?),
+            DataType::Float32 => read_values!(num_rows, f32, r.read_f32::<LittleEndian>()?),
+            DataType::Float64 => read_values!(num_rows, f64, r.read_f64::<LittleEndian>()?),
+            DataType::Utf8 => {
+                let mut b: ListBuilder<u8> = ListBuilder::with_capacity(num_rows);
+                for _ in 0..num_rows {
+                    let len = r.read_u64::<LittleEndian>()? as usize;
+                    let mut bytes = vec![0; len];
+                    r.read_exact(&mut bytes)?;
+                    b.push(&bytes);
+                }
+                ArrayData::Utf8(ListArray::from(b.finish()))
+            }
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "Cannot read spilled column of type {:?}",
+                    field.data_type()
+                )))
+            }
+        };
+        columns.push(Value::Column(Rc::new(if null_count > 0 {
+            Array::with_nulls(num_rows, data, null_count, bitmap)
+        } else {
+            Array::new(num_rows, data)
+        })));
+    }
+    Ok(Rc::new(DefaultRecordBatch {
+        schema: schema.clone(),
+        data: columns,
+        row_count: num_rows,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_batch() -> (Rc<Schema>, Rc<RecordBatch>) {
+        let schema = Rc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("unused", DataType::Float64, false),
+        ]));
+        let mut bitmap = Bitmap::new(3);
+        bitmap.clear(1);
+        let mut ids: Builder<i32> = Builder::with_capacity(3);
+        ids.push(1);
+        ids.push(0);
+        ids.push(3);
+        let batch = DefaultRecordBatch {
+            schema: schema.clone(),
+            data: vec![
+                Value::Column(Rc::new(Array::with_nulls(
+                    3,
+                    ArrayData::from(ids.finish()),
+                    1,
+                    bitmap,
+                ))),
+                Value::Column(Rc::new(Array::from(vec!["a", "bc", ""]))),
+                Value::Scalar(Rc::new(ScalarValue::Null)),
+            ],
+            row_count: 3,
+        };
+        (schema, Rc::new(batch))
+    }
+
+    fn config(name: &str) -> SpillConfig {
+        SpillConfig {
+            directory: env::temp_dir().join(name),
+            ..SpillConfig::default()
+        }
+    }
+
+    fn round_trip(config: SpillConfig) {
+        let manager = SpillManager::new(config);
+        let (schema, batch) = test_batch();
+        let mut writer = manager.create_file(schema).unwrap();
+        writer.write_batch(batch.as_ref()).unwrap();
+        writer.write_batch(batch.as_ref()).unwrap();
+        let file = writer.finish().unwrap();
+        assert_eq!(file.size(), manager.bytes_used());
+
+        let batches = file
+            .read()
+            .unwrap()
+            .collect::<Result<Vec<Rc<RecordBatch>>>>()
+            .unwrap();
+        assert_eq!(2, batches.len());
+        let ids = match *batches[1].column(0) {
+            Value::Column(ref a) => a.clone(),
+            _ => panic!(),
+        };
+        assert_eq!(1, ids.null_count());
+        assert!(!is_valid(&ids, 1));
+        match *batches[1].column(1) {
+            Value::Column(ref a) => match *a.data() {
+                ArrayData::Utf8(ref list) => assert_eq!(b"bc", list.get(1)),
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+        match *batches[1].column(2) {
+            Value::Scalar(ref v) => assert_eq!(ScalarValue::Null, *v.as_ref()),
+            _ => panic!(),
+        }
+
+        let path = file.path().clone();
+        assert!(path.exists());
+        drop(file);
+        assert!(!path.exists());
+        assert_eq!(0, manager.bytes_used());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(config("spill_plain"));
+        round_trip(SpillConfig {
+            compression: true,
+            ..config("spill_compressed")
+        });
+    }
+
+    #[test]
+    fn test_quota_and_cleanup() {
+        let manager = SpillManager::new(SpillConfig {
+            max_bytes: Some(16),
+            ..config("spill_quota")
+        });
+        let (schema, batch) = test_batch();
+        let mut writer = manager.create_file(schema).unwrap();
+        assert!(writer.write_batch(batch.as_ref()).is_err());
+        assert!(manager.bytes_used() > 0);
+        // abandoning the file releases its space
+        drop(writer);
+        assert_eq!(0, manager.bytes_used());
+
+        let directory = manager.directory.clone();
+        assert!(directory.exists());
+        drop(manager);
+        assert!(!directory.exists());
+    }
+
+    #[test]
+    fn test_partitions() {
+        let manager = Rc::new(SpillManager::new(config("spill_partitions")));
+        let (schema, batch) = test_batch();
+        let mut partitions = SpillPartitions::new(manager.clone(), schema);
+        // rows with equal hashes are written to the same file, and rows without one to the first
+        let hashes = vec![Some(7 << 60), None, Some(7 << 60)];
+        partitions.write(batch.as_ref(), &[0, 1, 2], &hashes).unwrap();
+        partitions.write(batch.as_ref(), &[2], &hashes).unwrap();
+        let files = partitions.finish().unwrap();
+        assert_eq!(SPILL_PARTITIONS, files.len());
+        let rows = |file: &SpillFile| -> usize {
+            file.read()
+                .unwrap()
+                .map(|b| b.unwrap().num_rows())
+                .sum()
+        };
+        assert_eq!(1, rows(files[0].as_ref().unwrap()));
+        let partition = partition_of(7 << 60, SPILL_PARTITIONS);
+        assert_eq!(3, rows(files[partition].as_ref().unwrap()));
+        assert_eq!(2, files.iter().filter(|f| f.is_some()).count());
+        drop(files);
+        assert_eq!(0, manager.bytes_used());
+    }
+}
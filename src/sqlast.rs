@@ -22,6 +22,71 @@ pub enum FileType {
     Parquet,
 }
 
+/// Output formats for `EXPLAIN`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLExplainFormat {
+    Text,
+    Json,
+}
+
+/// Sampling methods for `TABLESAMPLE`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLJoinType {
+    Inner,
+    LeftSemi,
+    LeftAnti,
+}
+
+/// Optimizer hints from a `/*+ ... */` comment after SELECT, which override the choices the
+/// planner would otherwise make for the joins of the FROM clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLHint {
+    /// `BROADCAST(t, ...)` builds the hash tables of joins with these tables from the tables,
+    /// so that the other side of each join is streamed
+    Broadcast(Vec<String>),
+    /// `JOIN_ORDER(t1, t2, ...)` joins the tables of the FROM clause in this order, followed by
+    /// any tables that the hint leaves out
+    JoinOrder(Vec<String>),
+}
+
+/// Types of typed literals such as `DATE '2024-01-01'`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLTypedLiteralType {
+    Date,
+    /// A time of day
+    Time,
+    Timestamp,
+    Interval(SQLIntervalUnit),
+}
+
+/// Units of `INTERVAL` literals
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLIntervalUnit {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+/// The version of a table to read in a time travel query
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLSnapshot {
+    /// A version number from `AT (VERSION => n)`
+    Version(i64),
+    /// A point in time from `FOR SYSTEM_TIME AS OF ts` or `AT (TIMESTAMP => ts)`
+    Timestamp(Box<ASTNode>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLSampleMethod {
+    /// Each row is included independently with the given probability
+    Bernoulli,
+    /// Whole batches of rows are included or skipped together
+    System,
+}
+
 /// SQL Abstract Syntax Tree (AST)
 #[derive(Debug, Clone, PartialEq)]
 pub enum ASTNode {
@@ -47,23 +112,115 @@ pub enum ASTNode {
     SQLLiteralLong(i64),
     SQLLiteralDouble(f64),
     SQLLiteralString(String),
+    /// `X'DEADBEEF'` or `0x1F`
+    SQLLiteralBinary(Vec<u8>),
+    /// A type keyword followed by a string, such as `DATE '2024-01-01'` or `INTERVAL '3' DAY`.
+    /// The unit of an interval is taken out of the string when written as `INTERVAL '3 days'`.
+    SQLTypedLiteral {
+        literal_type: SQLTypedLiteralType,
+        value: String,
+    },
+    /// `TRUE` or `FALSE`
+    SQLLiteralBoolean(bool),
+    /// `NULL`
+    SQLLiteralNull,
+    /// A parameter of a prepared statement, numbered from 1, which is bound to a value before
+    /// the statement is executed
+    SQLParameter(usize),
     SQLFunction {
         id: String,
         args: Vec<ASTNode>,
+        /// Whether duplicate arguments are ignored, from e.g. `COUNT(DISTINCT x)`
+        distinct: bool,
+    },
+    /// A function evaluated over a window of rows, e.g.
+    /// `SUM(x) OVER (PARTITION BY a ORDER BY b ROWS BETWEEN 1 PRECEDING AND CURRENT ROW)`
+    SQLWindowFunction {
+        id: String,
+        args: Vec<ASTNode>,
+        partition_by: Vec<ASTNode>,
+        /// `SQLOrderBy` expressions
+        order_by: Vec<ASTNode>,
+        /// The frame, when one is given explicitly
+        frame: Option<SQLWindowFrame>,
+        /// Whether rows with null values are skipped, from `IGNORE NULLS`
+        ignore_nulls: bool,
+    },
+    /// `expr op {ANY | SOME | ALL} (subquery)`, comparing a value with every row of a subquery
+    SQLQuantifiedComparison {
+        expr: Box<ASTNode>,
+        op: SQLOperator,
+        /// True for ALL, false for ANY and its synonym SOME
+        all: bool,
+        subquery: Box<ASTNode>,
     },
     SQLOrderBy {
         expr: Box<ASTNode>,
         asc: bool,
+        /// Whether nulls sort before other values, from `NULLS FIRST` or `NULLS LAST`
+        nulls_first: Option<bool>,
     },
     SQLSelect {
+        hints: Vec<SQLHint>,
+        /// Whether duplicate rows are removed, from `SELECT DISTINCT`
+        distinct: bool,
         projection: Vec<ASTNode>,
         relation: Option<Box<ASTNode>>,
         selection: Option<Box<ASTNode>>,
         order_by: Option<Vec<ASTNode>>,
         group_by: Option<Vec<ASTNode>>,
         having: Option<Box<ASTNode>>,
+        /// Filter on the results of window functions
+        qualify: Option<Box<ASTNode>>,
         limit: Option<Box<ASTNode>>,
     },
+    SQLJoin {
+        left: Box<ASTNode>,
+        right: Box<ASTNode>,
+        join_type: SQLJoinType,
+        /// The join condition
+        on: Box<ASTNode>,
+    },
+    /// `left {CROSS | [INNER] | LEFT [OUTER]} JOIN LATERAL (subquery) [ON condition]`, where the
+    /// subquery is evaluated for each row of `left` and can reference its columns
+    SQLLateralJoin {
+        left: Box<ASTNode>,
+        subquery: Box<ASTNode>,
+        /// Keep the rows of `left` that the subquery produces no rows for, from `LEFT JOIN`
+        left_outer: bool,
+        on: Option<Box<ASTNode>>,
+    },
+    SQLTableSample {
+        /// The relation being sampled
+        relation: Box<ASTNode>,
+        /// Sampling method
+        method: SQLSampleMethod,
+        /// Percentage of rows to sample, between 0 and 100
+        percent: f64,
+        /// Optional seed from a `REPEATABLE (n)` clause
+        seed: Option<u64>,
+    },
+    /// `table FOR SYSTEM_TIME AS OF ts` or `table AT (VERSION => n)` reads a table as it was at
+    /// an earlier point in time
+    SQLTableSnapshot {
+        relation: Box<ASTNode>,
+        snapshot: SQLSnapshot,
+    },
+    /// `relation [AS] alias` names a relation of a FROM clause, so that its columns can be
+    /// qualified with the alias rather than the table name
+    SQLTableAlias {
+        relation: Box<ASTNode>,
+        alias: String,
+    },
+    /// `DESCRIBE query` returns the output schema of a query without executing it
+    SQLDescribe(Box<ASTNode>),
+    /// `EXPLAIN [ANALYZE] [(FORMAT {TEXT | JSON})] query`
+    SQLExplain {
+        /// Execute the query and report runtime metrics
+        analyze: bool,
+        format: SQLExplainFormat,
+        statement: Box<ASTNode>,
+    },
     SQLCreateTable {
         /// Table name
         name: String,
@@ -75,7 +232,86 @@ pub enum ASTNode {
         header_row: bool,
         /// Path to file or directory contianing files
         location: String,
+        /// Options for reading the table from `OPTIONS (key 'value', ...)`, with keys in lower
+        /// case. A `profile` option names a connection profile that supplies the others.
+        options: Vec<(String, String)>,
+    },
+    SQLCreateTableAs {
+        /// Table name
+        name: String,
+        /// True for `CREATE TEMPORARY TABLE`
+        temporary: bool,
+        /// Query that produces the contents of the table
+        query: Box<ASTNode>,
+    },
+    /// `COPY (query) TO 'location' [STORED AS {CSV | PARQUET}] [OPTIONS (...)]` writes the
+    /// results of a query to files
+    SQLCopyTo {
+        query: Box<ASTNode>,
+        location: String,
+        /// From the STORED AS clause, or else the extension of the location
+        file_type: FileType,
+        /// Options for writing the files, with keys in lower case
+        options: Vec<(String, String)>,
+    },
+    /// `DROP TABLE [IF EXISTS] name`
+    SQLDropTable {
+        name: String,
+        /// Don't fail when the table doesn't exist
+        if_exists: bool,
     },
+    /// `ALTER TABLE name RENAME TO new_name`
+    SQLAlterTableRename { name: String, new_name: String },
+    /// `CREATE FUNCTION name(arg type, ...) RETURNS type AS 'expression'`
+    SQLCreateFunction {
+        name: String,
+        /// Argument names and types
+        args: Vec<(String, SQLType)>,
+        return_type: SQLType,
+        /// The SQL expression the function is defined as
+        body: String,
+    },
+}
+
+/// How the offsets of a window frame are measured
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLWindowFrameUnits {
+    /// Physical rows
+    Rows,
+    /// Distance between values of the ORDER BY expression
+    Range,
+    /// Groups of peer rows, which have equal ORDER BY values
+    Groups,
+}
+
+/// One end of a window frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLWindowFrameBound {
+    UnboundedPreceding,
+    Preceding(Box<ASTNode>),
+    CurrentRow,
+    Following(Box<ASTNode>),
+    UnboundedFollowing,
+}
+
+/// Rows that are left out of a window frame by an `EXCLUDE` clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum SQLWindowFrameExclusion {
+    NoOthers,
+    CurrentRow,
+    /// The current row and its peers
+    Group,
+    /// The peers of the current row but not the row itself
+    Ties,
+}
+
+/// `{ROWS | RANGE | GROUPS} BETWEEN start AND end [EXCLUDE ...]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SQLWindowFrame {
+    pub units: SQLWindowFrameUnits,
+    pub start: SQLWindowFrameBound,
+    pub end: SQLWindowFrameBound,
+    pub exclusion: SQLWindowFrameExclusion,
 }
 
 /// SQL column definition
@@ -101,6 +337,7 @@ pub enum SQLType {
     Float32,
     Double64,
     Utf8(usize),
+    Binary,
 }
 
 /// SQL Operator
@@ -111,6 +348,13 @@ pub enum SQLOperator {
     Multiply,
     Divide,
     Modulus,
+    /// String concatenation, `||`
+    StringConcat,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
     Gt,
     Lt,
     GtEq,
@@ -119,4 +363,13 @@ pub enum SQLOperator {
     NotEq,
     And,
     Or,
+    /// Logical negation, only used as a prefix operator
+    Not,
+    IsNotDistinctFrom,
+    /// Pattern match where `%` matches any sequence of characters and `_` any one character
+    Like,
+    NotLike,
+    /// Case insensitive `LIKE`
+    ILike,
+    NotILike,
 }
@@ -0,0 +1,796 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Normalization of SQL statements for query fingerprinting. A statement is printed from its AST
+//! with keywords in upper case, identifiers folded to a single case and literals replaced with
+//! `?`, so queries that differ only in formatting, case or constant values share a normalized
+//! form and fingerprint. This makes it possible to group metrics or cache plans by query shape.
+//!
+//! Identifiers are folded by the tokenizer, which leaves quoted identifiers as they were
+//! written, so `"Name"` and `name` remain different identifiers as they are when the statement
+//! is planned.
+
+use std::hash::Hasher;
+
+use fnv::FnvHasher;
+
+use super::errors::*;
+use super::sqlast::*;
+use super::sqlparser::Parser;
+use super::sqltokenizer::{is_keyword, IdentifierCasing, Tokenizer};
+
+/// How unquoted identifiers are folded when a statement is normalized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdentifierCase {
+    Preserve,
+    Lower,
+    Upper,
+}
+
+impl IdentifierCase {
+    /// The casing to tokenize a statement with so that its unquoted identifiers are folded
+    pub fn casing(&self) -> IdentifierCasing {
+        match *self {
+            IdentifierCase::Preserve => IdentifierCasing::CaseSensitive,
+            IdentifierCase::Lower => IdentifierCasing::FoldLower,
+            IdentifierCase::Upper => IdentifierCasing::FoldUpper,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizeOptions {
+    pub identifier_case: IdentifierCase,
+    /// Replace literal values with `?`
+    pub strip_literals: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        NormalizeOptions {
+            identifier_case: IdentifierCase::Lower,
+            strip_literals: true,
+        }
+    }
+}
+
+/// The normalized form of a statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizedQuery {
+    pub sql: String,
+    /// A hash of the normalized SQL that is stable across processes and releases
+    pub fingerprint: u64,
+}
+
+/// Normalize a parsed statement. Identifiers are printed as they were parsed, so the statement
+/// should have been tokenized with `options.identifier_case.casing()` for them to be folded.
+pub fn normalize(ast: &ASTNode, options: &NormalizeOptions) -> NormalizedQuery {
+    let mut sql = String::new();
+    Normalizer { options, out: &mut sql }.node(ast);
+    // FNV-1a is used rather than the standard library's hasher, whose output may change
+    // between releases of Rust, so that fingerprints can be stored and compared over time
+    let mut hasher = FnvHasher::default();
+    hasher.write(sql.as_bytes());
+    NormalizedQuery {
+        fingerprint: hasher.finish(),
+        sql,
+    }
+}
+
+/// Parse and normalize a statement
+pub fn normalize_sql(sql: &str, options: &NormalizeOptions) -> Result<NormalizedQuery> {
+    let casing = options.identifier_case.casing();
+    let tokens = Tokenizer::new(sql)
+        .with_identifier_casing(casing)
+        .tokenize()?;
+    let mut parser = Parser::new(tokens);
+    parser.set_identifier_casing(casing);
+    Ok(normalize(&parser.parse_statement()?, options))
+}
+
+struct Normalizer<'a> {
+    options: &'a NormalizeOptions,
+    out: &'a mut String,
+}
+
+impl<'a> Normalizer<'a> {
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn identifier(&mut self, id: &str) {
+        // identifiers that would otherwise be read as keywords, split into several tokens or
+        // folded to another case are quoted so the normalized statement can be parsed again
+        let plain = id.chars().enumerate().all(|(i, c)| match c {
+            '_' | '@' => true,
+            c if c.is_alphabetic() => true,
+            c if c.is_alphanumeric() => i > 0,
+            _ => false,
+        });
+        let folds = match self.options.identifier_case {
+            IdentifierCase::Preserve => false,
+            IdentifierCase::Lower => id.chars().any(char::is_uppercase),
+            IdentifierCase::Upper => id.chars().any(char::is_lowercase),
+        };
+        if plain && !folds && !id.is_empty() && !is_keyword(id) {
+            self.push(id);
+        } else {
+            self.push(&format!("\"{}\"", id.replace('"', "\"\"")));
+        }
+    }
+
+    fn literal(&mut self, value: String) {
+        if self.options.strip_literals {
+            self.push("?");
+        } else {
+            self.push(&value);
+        }
+    }
+
+    fn list(&mut self, nodes: &[ASTNode]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.push(", ");
+            }
+            self.node(node);
+        }
+    }
+
+    /// Frames are always printed with BETWEEN, so `ROWS 1 PRECEDING` and
+    /// `ROWS BETWEEN 1 PRECEDING AND CURRENT ROW` normalize the same way
+    fn window_frame(&mut self, frame: &SQLWindowFrame) {
+        self.push(match frame.units {
+            SQLWindowFrameUnits::Rows => "ROWS BETWEEN ",
+            SQLWindowFrameUnits::Range => "RANGE BETWEEN ",
+            SQLWindowFrameUnits::Groups => "GROUPS BETWEEN ",
+        });
+        self.window_frame_bound(&frame.start);
+        self.push(" AND ");
+        self.window_frame_bound(&frame.end);
+        self.push(match frame.exclusion {
+            SQLWindowFrameExclusion::NoOthers => "",
+            SQLWindowFrameExclusion::CurrentRow => " EXCLUDE CURRENT ROW",
+            SQLWindowFrameExclusion::Group => " EXCLUDE GROUP",
+            SQLWindowFrameExclusion::Ties => " EXCLUDE TIES",
+        });
+    }
+
+    fn window_frame_bound(&mut self, bound: &SQLWindowFrameBound) {
+        match *bound {
+            SQLWindowFrameBound::UnboundedPreceding => self.push("UNBOUNDED PRECEDING"),
+            SQLWindowFrameBound::Preceding(ref n) => {
+                self.node(n);
+                self.push(" PRECEDING");
+            }
+            SQLWindowFrameBound::CurrentRow => self.push("CURRENT ROW"),
+            SQLWindowFrameBound::Following(ref n) => {
+                self.node(n);
+                self.push(" FOLLOWING");
+            }
+            SQLWindowFrameBound::UnboundedFollowing => self.push("UNBOUNDED FOLLOWING"),
+        }
+    }
+
+    fn node(&mut self, node: &ASTNode) {
+        match *node {
+            ASTNode::SQLIdentifier(ref id) => self.identifier(id),
+            ASTNode::SQLWildcard => self.push("*"),
+            ASTNode::SQLCompoundIdentifier(ref parts) => {
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        self.push(".");
+                    }
+                    self.identifier(part);
+                }
+            }
+            ASTNode::SQLIsNull(ref expr) => {
+                self.node(expr);
+                self.push(" IS NULL");
+            }
+            ASTNode::SQLIsNotNull(ref expr) => {
+                self.node(expr);
+                self.push(" IS NOT NULL");
+            }
+            ASTNode::SQLBinaryExpr {
+                ref left,
+                ref op,
+                ref right,
+            } => {
+                self.node(left);
+                self.push(&format!(" {} ", operator(op)));
+                self.node(right);
+            }
+            ASTNode::SQLQuantifiedComparison {
+                ref expr,
+                ref op,
+                all,
+                ref subquery,
+            } => {
+                self.node(expr);
+                let quantifier = if all { "ALL" } else { "ANY" };
+                self.push(&format!(" {} {} (", operator(op), quantifier));
+                self.node(subquery);
+                self.push(")");
+            }
+            ASTNode::SQLCast {
+                ref expr,
+                ref data_type,
+            } => {
+                self.push("CAST(");
+                self.node(expr);
+                self.push(&format!(" AS {})", data_type_name(data_type)));
+            }
+            ASTNode::SQLNested(ref expr) => {
+                self.push("(");
+                self.node(expr);
+                self.push(")");
+            }
+            ASTNode::SQLUnary {
+                ref operator,
+                ref rex,
+            } => {
+                self.push(self::operator(operator));
+                if *operator == SQLOperator::Not {
+                    self.push(" ");
+                }
+                self.node(rex);
+            }
+            ASTNode::SQLLiteralLong(n) => self.literal(n.to_string()),
+            ASTNode::SQLLiteralDouble(n) => self.literal(format!("{:?}", n)),
+            ASTNode::SQLLiteralString(ref s) => self.literal(quote(s)),
+            ASTNode::SQLLiteralBinary(ref bytes) => {
+                let digits: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                self.literal(format!("X'{}'", digits.concat()))
+            }
+            ASTNode::SQLTypedLiteral {
+                ref literal_type,
+                ref value,
+            } => self.literal(match *literal_type {
+                SQLTypedLiteralType::Date => format!("DATE {}", quote(value)),
+                SQLTypedLiteralType::Time => format!("TIME {}", quote(value)),
+                SQLTypedLiteralType::Timestamp => format!("TIMESTAMP {}", quote(value)),
+                SQLTypedLiteralType::Interval(ref unit) => {
+                    let unit = match *unit {
+                        SQLIntervalUnit::Year => "YEAR",
+                        SQLIntervalUnit::Month => "MONTH",
+                        SQLIntervalUnit::Day => "DAY",
+                        SQLIntervalUnit::Hour => "HOUR",
+                        SQLIntervalUnit::Minute => "MINUTE",
+                        SQLIntervalUnit::Second => "SECOND",
+                    };
+                    format!("INTERVAL {} {}", quote(value), unit)
+                }
+            }),
+            ASTNode::SQLLiteralBoolean(b) => {
+                self.literal(if b { "TRUE" } else { "FALSE" }.to_string())
+            }
+            ASTNode::SQLLiteralNull => self.push("NULL"),
+            ASTNode::SQLParameter(n) => self.push(&format!("${}", n)),
+            ASTNode::SQLFunction {
+                ref id,
+                ref args,
+                distinct,
+            } => {
+                self.identifier(id);
+                self.push(if distinct { "(DISTINCT " } else { "(" });
+                self.list(args);
+                self.push(")");
+            }
+            ASTNode::SQLWindowFunction {
+                ref id,
+                ref args,
+                ref partition_by,
+                ref order_by,
+                ref frame,
+                ignore_nulls,
+            } => {
+                self.identifier(id);
+                self.push("(");
+                self.list(args);
+                self.push(")");
+                if ignore_nulls {
+                    self.push(" IGNORE NULLS");
+                }
+                self.push(" OVER (");
+                let mut clauses = 0;
+                if !partition_by.is_empty() {
+                    self.push("PARTITION BY ");
+                    self.list(partition_by);
+                    clauses += 1;
+                }
+                if !order_by.is_empty() {
+                    if clauses > 0 {
+                        self.push(" ");
+                    }
+                    self.push("ORDER BY ");
+                    self.list(order_by);
+                    clauses += 1;
+                }
+                if let Some(ref frame) = *frame {
+                    if clauses > 0 {
+                        self.push(" ");
+                    }
+                    self.window_frame(frame);
+                }
+                self.push(")");
+            }
+            ASTNode::SQLOrderBy {
+                ref expr,
+                asc,
+                nulls_first,
+            } => {
+                self.node(expr);
+                self.push(if asc { " ASC" } else { " DESC" });
+                match nulls_first {
+                    Some(true) => self.push(" NULLS FIRST"),
+                    Some(false) => self.push(" NULLS LAST"),
+                    None => {}
+                }
+            }
+            ASTNode::SQLSelect {
+                ref hints,
+                distinct,
+                ref projection,
+                ref relation,
+                ref selection,
+                ref order_by,
+                ref group_by,
+                ref having,
+                ref qualify,
+                ref limit,
+            } => {
+                self.push("SELECT ");
+                // hints change the plan, so queries with different hints are kept apart
+                if !hints.is_empty() {
+                    self.push("/*+");
+                    for hint in hints {
+                        let (name, tables) = match *hint {
+                            SQLHint::Broadcast(ref tables) => ("BROADCAST", tables),
+                            SQLHint::JoinOrder(ref tables) => ("JOIN_ORDER", tables),
+                        };
+                        self.push(" ");
+                        self.push(name);
+                        self.push("(");
+                        for (i, table) in tables.iter().enumerate() {
+                            if i > 0 {
+                                self.push(", ");
+                            }
+                            self.identifier(table);
+                        }
+                        self.push(")");
+                    }
+                    self.push(" */ ");
+                }
+                if distinct {
+                    self.push("DISTINCT ");
+                }
+                self.list(projection);
+                if let Some(ref relation) = *relation {
+                    self.push(" FROM ");
+                    self.node(relation);
+                }
+                if let Some(ref selection) = *selection {
+                    self.push(" WHERE ");
+                    self.node(selection);
+                }
+                if let Some(ref group_by) = *group_by {
+                    self.push(" GROUP BY ");
+                    self.list(group_by);
+                }
+                if let Some(ref having) = *having {
+                    self.push(" HAVING ");
+                    self.node(having);
+                }
+                if let Some(ref qualify) = *qualify {
+                    self.push(" QUALIFY ");
+                    self.node(qualify);
+                }
+                if let Some(ref order_by) = *order_by {
+                    self.push(" ORDER BY ");
+                    self.list(order_by);
+                }
+                if let Some(ref limit) = *limit {
+                    self.push(" LIMIT ");
+                    self.node(limit);
+                }
+            }
+            ASTNode::SQLJoin {
+                ref left,
+                ref right,
+                ref join_type,
+                ref on,
+            } => {
+                self.node(left);
+                self.push(match *join_type {
+                    SQLJoinType::Inner => " JOIN ",
+                    SQLJoinType::LeftSemi => " LEFT SEMI JOIN ",
+                    SQLJoinType::LeftAnti => " LEFT ANTI JOIN ",
+                });
+                self.node(right);
+                self.push(" ON ");
+                self.node(on);
+            }
+            ASTNode::SQLLateralJoin {
+                ref left,
+                ref subquery,
+                left_outer,
+                ref on,
+            } => {
+                self.node(left);
+                self.push(match (left_outer, on) {
+                    (true, _) => " LEFT JOIN LATERAL (",
+                    (false, &Some(_)) => " JOIN LATERAL (",
+                    (false, &None) => " CROSS JOIN LATERAL (",
+                });
+                self.node(subquery);
+                self.push(")");
+                if let Some(ref on) = *on {
+                    self.push(" ON ");
+                    self.node(on);
+                }
+            }
+            ASTNode::SQLTableSample {
+                ref relation,
+                ref method,
+                percent,
+                seed,
+            } => {
+                self.node(relation);
+                self.push(match *method {
+                    SQLSampleMethod::Bernoulli => " TABLESAMPLE BERNOULLI (",
+                    SQLSampleMethod::System => " TABLESAMPLE SYSTEM (",
+                });
+                self.literal(format!("{:?}", percent));
+                self.push(")");
+                if let Some(seed) = seed {
+                    self.push(" REPEATABLE (");
+                    self.literal(seed.to_string());
+                    self.push(")");
+                }
+            }
+            ASTNode::SQLTableSnapshot {
+                ref relation,
+                ref snapshot,
+            } => {
+                self.node(relation);
+                match *snapshot {
+                    SQLSnapshot::Version(n) => {
+                        self.push(" AT (VERSION => ");
+                        self.literal(n.to_string());
+                        self.push(")");
+                    }
+                    SQLSnapshot::Timestamp(ref ts) => {
+                        self.push(" FOR SYSTEM_TIME AS OF ");
+                        self.node(ts);
+                    }
+                }
+            }
+            ASTNode::SQLTableAlias {
+                ref relation,
+                ref alias,
+            } => {
+                self.node(relation);
+                self.push(" AS ");
+                self.identifier(alias);
+            }
+            ASTNode::SQLDescribe(ref statement) => {
+                self.push("DESCRIBE ");
+                self.node(statement);
+            }
+            ASTNode::SQLExplain {
+                analyze,
+                ref format,
+                ref statement,
+            } => {
+                self.push("EXPLAIN ");
+                if analyze {
+                    self.push("ANALYZE ");
+                }
+                if *format == SQLExplainFormat::Json {
+                    self.push("(FORMAT JSON) ");
+                }
+                self.node(statement);
+            }
+            ASTNode::SQLCreateTable {
+                ref name,
+                ref columns,
+                ref file_type,
+                header_row,
+                ref location,
+                ref options,
+            } => {
+                self.push("CREATE EXTERNAL TABLE ");
+                self.identifier(name);
+                if !columns.is_empty() {
+                    self.push(" (");
+                    for (i, column) in columns.iter().enumerate() {
+                        if i > 0 {
+                            self.push(", ");
+                        }
+                        self.identifier(&column.name);
+                        self.push(" ");
+                        self.push(&data_type_name(&column.data_type));
+                        if !column.allow_null {
+                            self.push(" NOT NULL");
+                        }
+                    }
+                    self.push(")");
+                }
+                self.push(match *file_type {
+                    FileType::CSV if header_row => " STORED AS CSV WITH HEADER ROW",
+                    FileType::CSV => " STORED AS CSV WITHOUT HEADER ROW",
+                    FileType::NdJson => " STORED AS NDJSON",
+                    FileType::Parquet => " STORED AS PARQUET",
+                });
+                self.push(" LOCATION ");
+                self.literal(quote(location));
+                if !options.is_empty() {
+                    self.push(" OPTIONS (");
+                    for (i, &(ref key, ref value)) in options.iter().enumerate() {
+                        if i > 0 {
+                            self.push(", ");
+                        }
+                        self.identifier(key);
+                        self.push(" ");
+                        self.literal(quote(value));
+                    }
+                    self.push(")");
+                }
+            }
+            ASTNode::SQLCreateTableAs {
+                ref name,
+                temporary,
+                ref query,
+            } => {
+                self.push(if temporary {
+                    "CREATE TEMPORARY TABLE "
+                } else {
+                    "CREATE TABLE "
+                });
+                self.identifier(name);
+                self.push(" AS ");
+                self.node(query);
+            }
+            ASTNode::SQLCopyTo {
+                ref query,
+                ref location,
+                ref file_type,
+                ref options,
+            } => {
+                self.push("COPY (");
+                self.node(query);
+                self.push(") TO ");
+                self.literal(quote(location));
+                self.push(match *file_type {
+                    FileType::CSV => " STORED AS CSV",
+                    FileType::NdJson => " STORED AS NDJSON",
+                    FileType::Parquet => " STORED AS PARQUET",
+                });
+                if !options.is_empty() {
+                    self.push(" OPTIONS (");
+                    for (i, &(ref key, ref value)) in options.iter().enumerate() {
+                        if i > 0 {
+                            self.push(", ");
+                        }
+                        self.identifier(key);
+                        self.push(" ");
+                        self.literal(quote(value));
+                    }
+                    self.push(")");
+                }
+            }
+            ASTNode::SQLDropTable {
+                ref name,
+                if_exists,
+            } => {
+                self.push(if if_exists {
+                    "DROP TABLE IF EXISTS "
+                } else {
+                    "DROP TABLE "
+                });
+                self.identifier(name);
+            }
+            ASTNode::SQLAlterTableRename {
+                ref name,
+                ref new_name,
+            } => {
+                self.push("ALTER TABLE ");
+                self.identifier(name);
+                self.push(" RENAME TO ");
+                self.identifier(new_name);
+            }
+            ASTNode::SQLCreateFunction {
+                ref name,
+                ref args,
+                ref return_type,
+                ref body,
+            } => {
+                self.push("CREATE FUNCTION ");
+                self.identifier(name);
+                self.push("(");
+                for (i, &(ref arg_name, ref data_type)) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.push(", ");
+                    }
+                    self.identifier(arg_name);
+                    self.push(" ");
+                    self.push(&data_type_name(data_type));
+                }
+                self.push(") RETURNS ");
+                self.push(&data_type_name(return_type));
+                self.push(" AS ");
+                self.literal(quote(body));
+            }
+        }
+    }
+}
+
+fn operator(op: &SQLOperator) -> &'static str {
+    match *op {
+        SQLOperator::Plus => "+",
+        SQLOperator::Minus => "-",
+        SQLOperator::Multiply => "*",
+        SQLOperator::Divide => "/",
+        SQLOperator::Modulus => "%",
+        SQLOperator::StringConcat => "||",
+        SQLOperator::BitwiseAnd => "&",
+        SQLOperator::BitwiseOr => "|",
+        SQLOperator::BitwiseXor => "^",
+        SQLOperator::ShiftLeft => "<<",
+        SQLOperator::ShiftRight => ">>",
+        SQLOperator::Gt => ">",
+        SQLOperator::Lt => "<",
+        SQLOperator::GtEq => ">=",
+        SQLOperator::LtEq => "<=",
+        SQLOperator::Eq => "=",
+        SQLOperator::NotEq => "!=",
+        SQLOperator::And => "AND",
+        SQLOperator::Or => "OR",
+        SQLOperator::Not => "NOT",
+        SQLOperator::IsNotDistinctFrom => "IS NOT DISTINCT FROM",
+        SQLOperator::Like => "LIKE",
+        SQLOperator::NotLike => "NOT LIKE",
+        SQLOperator::ILike => "ILIKE",
+        SQLOperator::NotILike => "NOT ILIKE",
+    }
+}
+
+fn data_type_name(data_type: &SQLType) -> String {
+    match *data_type {
+        SQLType::Boolean => "BOOLEAN".to_string(),
+        SQLType::UInt8 => "UINT8".to_string(),
+        SQLType::UInt16 => "UINT16".to_string(),
+        SQLType::UInt32 => "UINT32".to_string(),
+        SQLType::UInt64 => "UINT64".to_string(),
+        SQLType::Int8 => "INT8".to_string(),
+        SQLType::Int16 => "INT16".to_string(),
+        SQLType::Int32 => "INT".to_string(),
+        SQLType::Int64 => "LONG".to_string(),
+        SQLType::Float32 => "FLOAT".to_string(),
+        SQLType::Double64 => "DOUBLE".to_string(),
+        SQLType::Utf8(n) if n == usize::max_value() => "TEXT".to_string(),
+        SQLType::Utf8(n) => format!("VARCHAR({})", n),
+        SQLType::Binary => "BINARY".to_string(),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_folds_case_and_strips_literals() {
+        let options = NormalizeOptions::default();
+        let a = normalize_sql(
+            "select ID, Name from People where id = 1 and name = 'Bob' limit 10",
+            &options,
+        ).unwrap();
+        let b = normalize_sql(
+            "SELECT id,   name FROM people WHERE ID = 42 AND NAME = 'Alice' LIMIT 5",
+            &options,
+        ).unwrap();
+        assert_eq!(
+            "SELECT id, name FROM people WHERE id = ? AND name = ? LIMIT ?",
+            a.sql
+        );
+        assert_eq!(a, b);
+
+        let c = normalize_sql("SELECT id FROM people WHERE id > 1", &options).unwrap();
+        assert_ne!(a.fingerprint, c.fingerprint);
+
+        let d = normalize_sql("select id from people where not id = 1", &options).unwrap();
+        assert_eq!("SELECT id FROM people WHERE NOT id = ?", d.sql);
+
+        let e = normalize_sql("select id from people order by id desc nulls first", &options);
+        assert_eq!("SELECT id FROM people ORDER BY id DESC NULLS FIRST", e.unwrap().sql);
+    }
+
+    #[test]
+    fn test_normalize_quoted_identifiers() {
+        let options = NormalizeOptions::default();
+        let q = normalize_sql("SELECT \"order\", \"first name\" FROM t", &options).unwrap();
+        assert_eq!("SELECT \"order\", \"first name\" FROM t", q.sql);
+        assert_eq!(q, normalize_sql(&q.sql, &options).unwrap());
+
+        // letters of any script don't need quoting, but a leading digit does
+        let q = normalize_sql("SELECT \"país\", \"2名前\" FROM 顧客", &options).unwrap();
+        assert_eq!("SELECT país, \"2名前\" FROM 顧客", q.sql);
+
+        // quoted identifiers aren't folded, so they stay distinct from unquoted ones
+        let q = normalize_sql("SELECT \"Name\", Name FROM \"People\"", &options).unwrap();
+        assert_eq!("SELECT \"Name\", name FROM \"People\"", q.sql);
+        assert_eq!(q, normalize_sql(&q.sql, &options).unwrap());
+        let upper = NormalizeOptions {
+            identifier_case: IdentifierCase::Upper,
+            ..NormalizeOptions::default()
+        };
+        let q = normalize_sql("SELECT \"Name\", Name, \"NAME\" FROM t", &upper).unwrap();
+        assert_eq!("SELECT \"Name\", NAME, NAME FROM T", q.sql);
+    }
+
+    #[test]
+    fn test_normalize_typed_literals() {
+        let sql = "SELECT * FROM t WHERE d > date '2024-01-01' AND x < interval '3 days'";
+        let q = normalize_sql(sql, &NormalizeOptions::default()).unwrap();
+        assert_eq!("SELECT * FROM t WHERE d > ? AND x < ?", q.sql);
+
+        let options = NormalizeOptions {
+            identifier_case: IdentifierCase::Preserve,
+            strip_literals: false,
+        };
+        let q = normalize_sql(sql, &options).unwrap();
+        assert_eq!(
+            "SELECT * FROM t WHERE d > DATE '2024-01-01' AND x < INTERVAL '3' DAY",
+            q.sql
+        );
+        assert_eq!(q, normalize_sql(&q.sql, &options).unwrap());
+    }
+
+    #[test]
+    fn test_normalize_hints() {
+        let options = NormalizeOptions::default();
+        let q = normalize_sql(
+            "select /*+ broadcast(B), join_order(B,a) */ id from a join b on a.id = b.id",
+            &options,
+        ).unwrap();
+        assert_eq!(
+            "SELECT /*+ BROADCAST(b) JOIN_ORDER(b, a) */ id FROM a JOIN b ON a.id = b.id",
+            q.sql
+        );
+        assert_eq!(q, normalize_sql(&q.sql, &options).unwrap());
+
+        let plain = normalize_sql("SELECT id FROM a JOIN b ON a.id = b.id", &options).unwrap();
+        assert_ne!(q.fingerprint, plain.fingerprint);
+    }
+
+    #[test]
+    fn test_normalize_options() {
+        let options = NormalizeOptions {
+            identifier_case: IdentifierCase::Upper,
+            strip_literals: false,
+        };
+        let q = normalize_sql(
+            "select cast(amount as double) * 1.5 from orders where note = 'a b'",
+            &options,
+        ).unwrap();
+        assert_eq!(
+            "SELECT CAST(AMOUNT AS DOUBLE) * 1.5 FROM ORDERS WHERE NOTE = 'a b'",
+            q.sql
+        );
+    }
+}
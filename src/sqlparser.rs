@@ -14,57 +14,237 @@
 
 //! SQL Parser
 
+use std::borrow::Cow;
+use std::ops::Range;
+
+use super::errors::*;
 use super::sqlast::*;
 use super::sqltokenizer::*;
 
-#[derive(Debug, Clone)]
-pub enum ParserError {
-    TokenizerError(String),
-    ParserError(String),
-}
-
 macro_rules! parser_err {
     ($MSG:expr) => {
-        Err(ParserError::ParserError($MSG.to_string()))
+        Err(DataFusionError::Parser($MSG.to_string()))
     };
 }
 
-impl From<TokenizerError> for ParserError {
-    fn from(e: TokenizerError) -> Self {
-        ParserError::TokenizerError(format!("{:?}", e))
-    }
+/// The maximum nesting of statements, such as subqueries in FROM, when expressions are parsed
+/// iteratively
+const MAX_STATEMENT_DEPTH: usize = 32;
+
+/// A construct that is waiting for an operand to be parsed by `parse_expr_iterative`
+enum Pending<'a> {
+    /// A binary operator waiting for its right operand
+    Binary {
+        left: ASTNode,
+        op: SQLOperator,
+        precedence: u8,
+    },
+    /// A `->` or `->>` operator waiting for its key or index
+    Arrow {
+        left: ASTNode,
+        tok: Token<'a>,
+        precedence: u8,
+    },
+    /// A function call waiting for its next argument
+    Function {
+        id: String,
+        args: Vec<ASTNode>,
+        distinct: bool,
+    },
+    /// A CAST waiting for the expression being cast
+    Cast,
+    Describe,
+    /// A `+` or `-` sign or `NOT` waiting for its operand
+    Unary { operator: SQLOperator },
+    /// An opening parenthesis waiting for the expression it contains
+    Nested,
 }
 
-/// SQL Parser
-pub struct Parser {
-    tokens: Vec<Token>,
+/// Precedence of the `+` and `-` signs, which bind more tightly than any binary operator
+/// except `->`, `->>` and `::`, so `-a * b` multiplies `-a` and `-a::INT` negates the cast
+const UNARY_PRECEDENCE: u8 = 45;
+
+/// Precedence of `NOT`, which binds more tightly than `AND` and less tightly than comparisons,
+/// so `NOT a = 1 AND b` negates `a = 1` before the conjunction
+const NOT_PRECEDENCE: u8 = 12;
+
+/// SQL Parser, which borrows the text of its tokens from the statement
+pub struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    /// Where each token was read from, when the parser was created from spanned tokens
+    spans: Vec<Span>,
     index: usize,
+    /// Index of the token that was looked at most recently, which is the one an error is
+    /// reported at
+    examined: usize,
+    /// Parse expressions with an explicit stack rather than by recursion
+    iterative: bool,
+    /// Number of statements being parsed that contain the current position
+    statement_depth: usize,
+    /// Number of parenthesized subqueries being parsed, which end at a closing parenthesis
+    subquery_depth: usize,
+    /// Number of `?` placeholders parsed so far, which are numbered in order
+    positional_parameters: usize,
+    /// Casing applied to non-reserved keywords that are used as names, which should be the
+    /// casing the tokenizer applied to unquoted identifiers
+    identifier_casing: IdentifierCasing,
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
     /// Parse the specified tokens
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<Token<'a>>) -> Self {
+        let mut kept: Vec<Token<'a>> = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            if !is_skipped(kept.last(), &token) {
+                kept.push(token);
+            }
+        }
         Parser {
-            tokens: tokens,
+            tokens: kept,
+            spans: vec![],
             index: 0,
+            examined: 0,
+            iterative: false,
+            statement_depth: 0,
+            subquery_depth: 0,
+            positional_parameters: 0,
+            identifier_casing: IdentifierCasing::CaseSensitive,
+        }
+    }
+
+    /// Parse the tokens returned by `Tokenizer::tokenize_with_spans`, so that errors report
+    /// the line and column they occurred at. Whitespace and comments are skipped.
+    pub fn with_spans(tokens: Vec<(Token<'a>, Span)>) -> Self {
+        let mut kept: Vec<Token<'a>> = Vec::with_capacity(tokens.len());
+        let mut spans = Vec::with_capacity(tokens.len());
+        for (token, span) in tokens {
+            if !is_skipped(kept.last(), &token) {
+                kept.push(token);
+                spans.push(span);
+            }
+        }
+        Parser {
+            spans,
+            ..Parser::new(kept)
         }
     }
 
+    /// Parse expressions using an explicit stack instead of recursion, so that the stack used
+    /// by the parser is bounded however deeply the input is nested. This is intended for
+    /// services that parse untrusted SQL. Statements can still be nested, as subqueries or
+    /// EXPLAIN and DESCRIBE targets, but only up to a fixed depth.
+    pub fn set_iterative(&mut self, iterative: bool) {
+        self.iterative = iterative;
+    }
+
+    /// Set the casing of unquoted identifiers that the tokens were read with, so that
+    /// non-reserved keywords used as names are read the same way as identifiers
+    pub fn set_identifier_casing(&mut self, casing: IdentifierCasing) {
+        self.identifier_casing = casing;
+    }
+
     /// Parse a SQL statement and produce an Abstract Syntax Tree (AST)
-    pub fn parse_sql(sql: String) -> Result<ASTNode, ParserError> {
+    pub fn parse_sql(sql: String) -> Result<ASTNode> {
         let mut tokenizer = Tokenizer::new(&sql);
-        let tokens = tokenizer.tokenize()?;
-        let mut parser = Parser::new(tokens);
-        parser.parse()
+        let tokens = tokenizer.tokenize_with_spans()?;
+        let mut parser = Parser::with_spans(tokens);
+        parser.parse_statement()
+    }
+
+    /// Parse a SQL statement with bounded stack usage, see `set_iterative`
+    pub fn parse_sql_iterative(sql: String) -> Result<ASTNode> {
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize_with_spans()?;
+        let mut parser = Parser::with_spans(tokens);
+        parser.set_iterative(true);
+        parser.parse_statement()
+    }
+
+    /// Parse a statement. When the parser was created from spanned tokens, errors report the
+    /// position of the token that could not be parsed.
+    pub fn parse_statement(&mut self) -> Result<ASTNode> {
+        self.parse().map_err(|e| self.locate_error(e))
+    }
+
+    /// Parse the semicolon-separated statements of a script. Empty statements and trailing
+    /// semicolons are skipped, and `?` placeholders are numbered from 1 in each statement.
+    pub fn parse_statements(&mut self) -> Result<Vec<ASTNode>> {
+        Ok(self
+            .parse_statement_tokens()?
+            .into_iter()
+            .map(|(statement, _)| statement)
+            .collect())
+    }
+
+    /// Parse the statements of a script like `parse_statements`, along with the byte range of
+    /// the text of each statement, which starts at its first token and ends after its last.
+    /// The parser must have been created from spanned tokens.
+    pub fn parse_statements_with_ranges(&mut self) -> Result<Vec<(ASTNode, Range<usize>)>> {
+        if self.spans.is_empty() && !self.tokens.is_empty() {
+            return Err(DataFusionError::Parser(
+                "Statement ranges require spanned tokens".to_string(),
+            ));
+        }
+        let statements = self.parse_statement_tokens()?;
+        Ok(statements
+            .into_iter()
+            .map(|(statement, tokens)| {
+                let range = self.spans[tokens.start].start..self.spans[tokens.end - 1].end;
+                (statement, range)
+            })
+            .collect())
+    }
+
+    /// Parse the statements of a script along with the range of tokens of each statement
+    fn parse_statement_tokens(&mut self) -> Result<Vec<(ASTNode, Range<usize>)>> {
+        let mut statements = vec![];
+        loop {
+            while self.peek_token() == Some(Token::Semicolon) {
+                self.next_token();
+            }
+            if self.peek_token().is_none() {
+                return Ok(statements);
+            }
+            self.positional_parameters = 0;
+            let start = self.index;
+            let statement = self.parse_statement()?;
+            statements.push((statement, start..self.index));
+            match self.peek_token() {
+                Some(Token::Semicolon) | None => {}
+                Some(other) => {
+                    let e = DataFusionError::Parser(format!(
+                        "Expected ';' after statement, found {:?}",
+                        other
+                    ));
+                    return Err(self.locate_error(e));
+                }
+            }
+        }
+    }
+
+    /// Add the position of the token that was being parsed to the message of a parser error
+    fn locate_error(&self, e: DataFusionError) -> DataFusionError {
+        match e {
+            DataFusionError::Parser(msg) if !self.spans.is_empty() => {
+                match self.spans.get(self.examined) {
+                    Some(span) => DataFusionError::Parser(format!("{} at {}", msg, span)),
+                    None => DataFusionError::Parser(format!("{} at end of input", msg)),
+                }
+            }
+            other => other,
+        }
     }
 
     /// Parse a new expression
-    pub fn parse(&mut self) -> Result<ASTNode, ParserError> {
+    pub fn parse(&mut self) -> Result<ASTNode> {
         self.parse_expr(0)
     }
 
     /// Parse tokens until the precedence changes
-    fn parse_expr(&mut self, precedence: u8) -> Result<ASTNode, ParserError> {
+    fn parse_expr(&mut self, precedence: u8) -> Result<ASTNode> {
+        if self.iterative {
+            return self.parse_expr_iterative(precedence);
+        }
         //        println!("parse_expr() precendence = {}", precedence);
 
         let mut expr = self.parse_prefix()?;
@@ -88,57 +268,245 @@ impl Parser {
         Ok(expr)
     }
 
+    /// Parse tokens until the precedence changes, keeping the operators, function calls and
+    /// casts that are waiting for an operand on a stack rather than recursing into them
+    fn parse_expr_iterative(&mut self, precedence: u8) -> Result<ASTNode> {
+        let mut stack: Vec<Pending> = vec![];
+        loop {
+            // parse an operand, or open a construct that contains one
+            let mut expr = match self.peek_token() {
+                Some(Token::Keyword(ref k))
+                    if k == "CAST" && self.tokens.get(self.index + 1) == Some(&Token::LParen) =>
+                {
+                    self.index += 2;
+                    stack.push(Pending::Cast);
+                    continue;
+                }
+                Some(ref tok)
+                    if self.tokens.get(self.index + 1) == Some(&Token::LParen)
+                        && name_of(tok, self.identifier_casing).is_some() =>
+                {
+                    let id = name_of(tok, self.identifier_casing).unwrap();
+                    self.index += 2;
+                    let distinct = self.parse_keyword("DISTINCT");
+                    if !self.consume_token(&Token::RParen)? {
+                        stack.push(Pending::Function {
+                            id,
+                            args: vec![],
+                            distinct,
+                        });
+                        continue;
+                    }
+                    // a call without arguments is complete already
+                    self.parse_over(id, vec![], distinct)?
+                }
+                Some(Token::Keyword(ref k)) if k == "DESCRIBE" => {
+                    self.index += 1;
+                    stack.push(Pending::Describe);
+                    continue;
+                }
+                Some(Token::LParen) => {
+                    self.index += 1;
+                    stack.push(Pending::Nested);
+                    continue;
+                }
+                Some(ref tok) if *tok == Token::Plus || *tok == Token::Minus => {
+                    self.index += 1;
+                    stack.push(Pending::Unary {
+                        operator: self.to_sql_operator(tok)?,
+                    });
+                    continue;
+                }
+                Some(Token::Keyword(ref k)) if k == "NOT" => {
+                    self.index += 1;
+                    stack.push(Pending::Unary {
+                        operator: SQLOperator::Not,
+                    });
+                    continue;
+                }
+                Some(Token::Keyword(_)) => self.parse_nested_statement()?,
+                _ => self.parse_prefix()?,
+            };
+
+            // apply the operators that follow the operand and complete the constructs it closes
+            loop {
+                let binding = match stack.last() {
+                    Some(&Pending::Binary { precedence, .. })
+                    | Some(&Pending::Arrow { precedence, .. }) => precedence,
+                    Some(&Pending::Unary { ref operator }) => unary_precedence(operator),
+                    Some(_) => 0,
+                    None => precedence,
+                };
+                let next_precedence = self.get_next_precedence()?;
+                if binding < next_precedence {
+                    // a token with a precedence above zero follows
+                    let tok = self.next_token().unwrap();
+                    if tok == Token::Keyword(Keyword::new("IS")) {
+                        if self.parse_keywords(vec!["NULL"]) {
+                            expr = ASTNode::SQLIsNull(Box::new(expr));
+                            continue;
+                        } else if self.parse_keywords(vec!["NOT", "NULL"]) {
+                            expr = ASTNode::SQLIsNotNull(Box::new(expr));
+                            continue;
+                        } else if self.parse_keywords(vec!["NOT", "DISTINCT", "FROM"]) {
+                            stack.push(Pending::Binary {
+                                left: expr,
+                                op: SQLOperator::IsNotDistinctFrom,
+                                precedence: next_precedence,
+                            });
+                        } else {
+                            return parser_err!("Invalid tokens after IS");
+                        }
+                    } else if tok == Token::DoubleColon {
+                        expr = ASTNode::SQLCast {
+                            expr: Box::new(expr),
+                            data_type: self.parse_data_type()?,
+                        };
+                        continue;
+                    } else if tok == Token::Arrow || tok == Token::LongArrow {
+                        stack.push(Pending::Arrow {
+                            left: expr,
+                            tok,
+                            precedence: next_precedence,
+                        });
+                    } else {
+                        let op = self.parse_operator(&tok)?;
+                        if is_comparison(&op) {
+                            if let Some(all) = self.parse_quantifier() {
+                                expr = ASTNode::SQLQuantifiedComparison {
+                                    expr: Box::new(expr),
+                                    op,
+                                    all,
+                                    subquery: Box::new(self.parse_subquery()?),
+                                };
+                                continue;
+                            }
+                        }
+                        stack.push(Pending::Binary {
+                            left: expr,
+                            op,
+                            precedence: next_precedence,
+                        });
+                    }
+                    break;
+                }
+
+                expr = match stack.pop() {
+                    None => return Ok(expr),
+                    Some(Pending::Binary { left, op, .. }) => ASTNode::SQLBinaryExpr {
+                        left: Box::new(left),
+                        op,
+                        right: Box::new(expr),
+                    },
+                    Some(Pending::Arrow { left, tok, .. }) => json_extract_call(left, &tok, expr)?,
+                    Some(Pending::Function {
+                        id,
+                        mut args,
+                        distinct,
+                    }) => {
+                        args.push(expr);
+                        match self.next_token() {
+                            Some(Token::Comma) => {
+                                stack.push(Pending::Function { id, args, distinct });
+                                break;
+                            }
+                            Some(Token::RParen) => self.parse_over(id, args, distinct)?,
+                            other => {
+                                return parser_err!(format!(
+                                    "Expected ',' or ')' after function argument, found {:?}",
+                                    other
+                                ))
+                            }
+                        }
+                    }
+                    Some(Pending::Cast) => self.finish_cast(expr)?,
+                    Some(Pending::Describe) => ASTNode::SQLDescribe(Box::new(expr)),
+                    Some(Pending::Unary { operator }) => ASTNode::SQLUnary {
+                        operator,
+                        rex: Box::new(expr),
+                    },
+                    Some(Pending::Nested) => self.finish_nested(expr)?,
+                };
+            }
+        }
+    }
+
+    /// Parse a statement that starts with a keyword, limiting how deeply statements can nest
+    fn parse_nested_statement(&mut self) -> Result<ASTNode> {
+        if self.statement_depth >= MAX_STATEMENT_DEPTH {
+            return parser_err!(format!(
+                "Statements can't be nested more than {} deep",
+                MAX_STATEMENT_DEPTH
+            ));
+        }
+        self.statement_depth += 1;
+        let result = self.parse_prefix();
+        self.statement_depth -= 1;
+        result
+    }
+
     /// Parse an expression prefix
-    fn parse_prefix(&mut self) -> Result<ASTNode, ParserError> {
+    fn parse_prefix(&mut self) -> Result<ASTNode> {
         match self.next_token() {
             Some(t) => {
                 match t {
-                    Token::Keyword(k) => match k.to_uppercase().as_ref() {
+                    Token::Keyword(k) => match k.value() {
                         "SELECT" => Ok(self.parse_select()?),
                         "CREATE" => Ok(self.parse_create()?),
+                        "DROP" => Ok(self.parse_drop()?),
+                        "ALTER" => Ok(self.parse_alter()?),
+                        "COPY" => Ok(self.parse_copy()?),
+                        "DESCRIBE" => Ok(ASTNode::SQLDescribe(Box::new(self.parse_expr(0)?))),
+                        "EXPLAIN" => Ok(self.parse_explain()?),
+                        "NOT" => Ok(ASTNode::SQLUnary {
+                            operator: SQLOperator::Not,
+                            rex: Box::new(self.parse_expr(NOT_PRECEDENCE)?),
+                        }),
+                        "CAST" if self.consume_token(&Token::LParen)? => {
+                            self.parse_cast_expression()
+                        }
+                        // DATE, TIME and TIMESTAMP are also names, unless a string follows
+                        "DATE" | "TIME" | "TIMESTAMP" | "INTERVAL" if self.peek_string() => {
+                            self.parse_typed_literal(k.value())
+                        }
+                        // WINDOW is reserved for named window clauses but also groups rows by
+                        // time window, as in `GROUP BY window(ts, '5 minutes')`
+                        "WINDOW" if self.peek_token() == Some(Token::LParen) => {
+                            self.parse_name_expr(self.keyword_name(&k))
+                        }
+                        _ if !is_reserved_keyword(&k) => {
+                            self.parse_name_expr(self.keyword_name(&k))
+                        }
                         _ => return parser_err!(format!("No prefix parser for keyword {}", k)),
                     },
                     Token::Mult => Ok(ASTNode::SQLWildcard),
-                    Token::Identifier(id) => {
-                        match self.peek_token() {
-                            Some(Token::LParen) => {
-                                self.next_token(); // skip lparen
-                                match id.to_uppercase().as_ref() {
-                                    "CAST" => self.parse_cast_expression(),
-                                    _ => {
-                                        let args = self.parse_expr_list()?;
-                                        self.next_token(); // skip rparen
-                                        Ok(ASTNode::SQLFunction { id, args })
-                                    }
-                                }
-                            }
-                            Some(Token::Period) => {
-                                let mut id_parts: Vec<String> = vec![id];
-                                while self.peek_token() == Some(Token::Period) {
-                                    self.consume_token(&Token::Period)?;
-                                    match self.next_token() {
-                                        Some(Token::Identifier(id)) => id_parts.push(id),
-                                        _ => {
-                                            return parser_err!(format!(
-                                                "Error parsing compound identifier"
-                                            ))
-                                        }
-                                    }
-                                }
-                                Ok(ASTNode::SQLCompoundIdentifier(id_parts))
-                            }
-                            _ => Ok(ASTNode::SQLIdentifier(id)),
-                        }
+                    Token::LParen => {
+                        let expr = self.parse_expr(0)?;
+                        self.finish_nested(expr)
                     }
-                    Token::Number(ref n) if n.contains(".") => match n.parse::<f64>() {
+                    Token::Plus | Token::Minus => Ok(ASTNode::SQLUnary {
+                        operator: self.to_sql_operator(&t)?,
+                        rex: Box::new(self.parse_expr(UNARY_PRECEDENCE)?),
+                    }),
+                    Token::Identifier(id) => self.parse_name_expr(id.into_owned()),
+                    // numbers with a fractional part or an exponent are doubles
+                    Token::Decimal(ref n) | Token::Float(ref n) => match n.parse::<f64>() {
                         Ok(n) => Ok(ASTNode::SQLLiteralDouble(n)),
-                        Err(e) => parser_err!(format!("Could not parse '{}' as i64: {}", n, e)),
+                        Err(e) => parser_err!(format!("Could not parse '{}' as f64: {}", n, e)),
                     },
-                    Token::Number(ref n) => match n.parse::<i64>() {
+                    Token::Integer(ref n) => match n.parse::<i64>() {
                         Ok(n) => Ok(ASTNode::SQLLiteralLong(n)),
                         Err(e) => parser_err!(format!("Could not parse '{}' as i64: {}", n, e)),
                     },
                     Token::String(ref s) => Ok(ASTNode::SQLLiteralString(s.to_string())),
+                    Token::Binary(bytes) => Ok(ASTNode::SQLLiteralBinary(bytes)),
+                    Token::Boolean(b) => Ok(ASTNode::SQLLiteralBoolean(b)),
+                    Token::Null => Ok(ASTNode::SQLLiteralNull),
+                    Token::Placeholder(Some(n)) => Ok(ASTNode::SQLParameter(n)),
+                    Token::Placeholder(None) => {
+                        self.positional_parameters += 1;
+                        Ok(ASTNode::SQLParameter(self.positional_parameters))
+                    }
                     _ => parser_err!(format!(
                         "Prefix parser expected a keyword but found {:?}",
                         t
@@ -149,10 +517,132 @@ impl Parser {
         }
     }
 
+    /// Parse the closing parenthesis of a parenthesized expression
+    fn finish_nested(&mut self, expr: ASTNode) -> Result<ASTNode> {
+        if !self.consume_token(&Token::RParen)? {
+            return parser_err!(format!(
+                "Expected ')' after parenthesized expression, found {:?}",
+                self.peek_token()
+            ));
+        }
+        Ok(ASTNode::SQLNested(Box::new(expr)))
+    }
+
+    /// Whether the next token is a string literal
+    fn peek_string(&mut self) -> bool {
+        match self.peek_token() {
+            Some(Token::String(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Parse the string and any interval unit of a typed literal after its type keyword
+    fn parse_typed_literal(&mut self, keyword: &str) -> Result<ASTNode> {
+        let value = match self.next_token() {
+            Some(Token::String(s)) => s.into_owned(),
+            other => {
+                return parser_err!(format!(
+                    "Expected a string after {}, found {:?}",
+                    keyword, other
+                ))
+            }
+        };
+        let literal_type = match keyword {
+            "DATE" => SQLTypedLiteralType::Date,
+            "TIME" => SQLTypedLiteralType::Time,
+            "TIMESTAMP" => SQLTypedLiteralType::Timestamp,
+            _ => {
+                let unit = match self.peek_token() {
+                    Some(Token::Keyword(ref k)) => interval_unit(k),
+                    _ => None,
+                };
+                let (value, unit) = match unit {
+                    Some(unit) => {
+                        self.next_token();
+                        (value, unit)
+                    }
+                    // `INTERVAL '3 days'` has its unit in the string
+                    None => {
+                        let parts: Vec<&str> = value.split_whitespace().collect();
+                        match (parts.len(), parts.get(1).and_then(|u| interval_unit(u))) {
+                            (2, Some(unit)) => (parts[0].to_string(), unit),
+                            _ => {
+                                return parser_err!(format!(
+                                    "Expected a unit for INTERVAL '{}'",
+                                    value
+                                ))
+                            }
+                        }
+                    }
+                };
+                return Ok(ASTNode::SQLTypedLiteral {
+                    literal_type: SQLTypedLiteralType::Interval(unit),
+                    value,
+                });
+            }
+        };
+        Ok(ASTNode::SQLTypedLiteral {
+            literal_type,
+            value,
+        })
+    }
+
+    /// Parse an expression that starts with a name: a function call, a qualified name or a
+    /// plain identifier
+    fn parse_name_expr(&mut self, id: String) -> Result<ASTNode> {
+        match self.peek_token() {
+            Some(Token::LParen) => {
+                self.next_token(); // skip lparen
+                let distinct = self.parse_keyword("DISTINCT");
+                let args = if self.consume_token(&Token::RParen)? {
+                    vec![]
+                } else {
+                    let args = self.parse_expr_list()?;
+                    self.next_token(); // skip rparen
+                    args
+                };
+                self.parse_over(id, args, distinct)
+            }
+            Some(Token::Period) => self.parse_compound_identifier(id),
+            _ => Ok(ASTNode::SQLIdentifier(id)),
+        }
+    }
+
+    /// The name a non-reserved keyword stands for when it is used as a name
+    fn keyword_name(&self, k: &Keyword) -> String {
+        fold_identifier(k.text(), self.identifier_casing).into_owned()
+    }
+
+    /// Parse the rest of a qualified name such as `schema.table.column` after its first part.
+    /// Any word can follow a period, so a keyword there is read as a name.
+    fn parse_compound_identifier(&mut self, first: String) -> Result<ASTNode> {
+        let mut id_parts: Vec<String> = vec![first];
+        while self.peek_token() == Some(Token::Period) {
+            self.next_token();
+            match self.next_token() {
+                Some(Token::Identifier(id)) => id_parts.push(id.into_owned()),
+                Some(Token::Keyword(k)) => id_parts.push(self.keyword_name(&k)),
+                other => {
+                    return parser_err!(format!(
+                        "Expected an identifier after '.' in {}, found {:?}",
+                        id_parts.join("."),
+                        other
+                    ))
+                }
+            }
+        }
+        Ok(ASTNode::SQLCompoundIdentifier(id_parts))
+    }
+
     /// Parse a SQL CAST function e.g. `CAST(expr AS FLOAT)`
-    fn parse_cast_expression(&mut self) -> Result<ASTNode, ParserError> {
+    fn parse_cast_expression(&mut self) -> Result<ASTNode> {
         let expr = self.parse_expr(0)?;
-        self.consume_token(&Token::Keyword("AS".to_string()))?;
+        self.finish_cast(expr)
+    }
+
+    /// Parse the `AS type)` that follows the expression in a CAST
+    fn finish_cast(&mut self, expr: ASTNode) -> Result<ASTNode> {
+        self.consume_token(&Token::Keyword(Keyword::new("AS")))?;
         let data_type = self.parse_data_type()?;
         self.consume_token(&Token::RParen)?;
         Ok(ASTNode::SQLCast {
@@ -166,7 +656,7 @@ impl Parser {
         &mut self,
         expr: ASTNode,
         precedence: u8,
-    ) -> Result<Option<ASTNode>, ParserError> {
+    ) -> Result<Option<ASTNode>> {
         match self.next_token() {
             Some(tok) => match tok {
                 Token::Keyword(ref k) => if k == "IS" {
@@ -174,13 +664,19 @@ impl Parser {
                         Ok(Some(ASTNode::SQLIsNull(Box::new(expr))))
                     } else if self.parse_keywords(vec!["NOT", "NULL"]) {
                         Ok(Some(ASTNode::SQLIsNotNull(Box::new(expr))))
+                    } else if self.parse_keywords(vec!["NOT", "DISTINCT", "FROM"]) {
+                        Ok(Some(ASTNode::SQLBinaryExpr {
+                            left: Box::new(expr),
+                            op: SQLOperator::IsNotDistinctFrom,
+                            right: Box::new(self.parse_expr(precedence)?),
+                        }))
                     } else {
                         parser_err!("Invalid tokens after IS")
                     }
                 } else {
                     Ok(Some(ASTNode::SQLBinaryExpr {
                         left: Box::new(expr),
-                        op: self.to_sql_operator(&tok)?,
+                        op: self.parse_operator(&tok)?,
                         right: Box::new(self.parse_expr(precedence)?),
                     }))
                 },
@@ -189,24 +685,82 @@ impl Parser {
                 | Token::Gt
                 | Token::GtEq
                 | Token::Lt
-                | Token::LtEq
-                | Token::Plus
+                | Token::LtEq => {
+                    let op = self.to_sql_operator(&tok)?;
+                    match self.parse_quantifier() {
+                        Some(all) => Ok(Some(ASTNode::SQLQuantifiedComparison {
+                            expr: Box::new(expr),
+                            op,
+                            all,
+                            subquery: Box::new(self.parse_subquery()?),
+                        })),
+                        None => Ok(Some(ASTNode::SQLBinaryExpr {
+                            left: Box::new(expr),
+                            op,
+                            right: Box::new(self.parse_expr(precedence)?),
+                        })),
+                    }
+                }
+                Token::Plus
                 | Token::Minus
                 | Token::Mult
                 | Token::Mod
-                | Token::Div => Ok(Some(ASTNode::SQLBinaryExpr {
+                | Token::Div
+                | Token::StringConcat
+                | Token::BitwiseAnd
+                | Token::BitwiseOr
+                | Token::BitwiseXor
+                | Token::ShiftLeft
+                | Token::ShiftRight => Ok(Some(ASTNode::SQLBinaryExpr {
                     left: Box::new(expr),
                     op: self.to_sql_operator(&tok)?,
                     right: Box::new(self.parse_expr(precedence)?),
                 })),
+                Token::DoubleColon => Ok(Some(ASTNode::SQLCast {
+                    expr: Box::new(expr),
+                    data_type: self.parse_data_type()?,
+                })),
+                Token::Arrow | Token::LongArrow => {
+                    let key = self.parse_expr(precedence)?;
+                    Ok(Some(json_extract_call(expr, &tok, key)?))
+                }
                 _ => parser_err!(format!("No infix parser for token {:?}", tok)),
             },
             None => Ok(None),
         }
     }
 
+    /// Parse the quantifier of a comparison with a subquery, returning whether it is ALL rather
+    /// than ANY or SOME
+    fn parse_quantifier(&mut self) -> Option<bool> {
+        if self.tokens.get(self.index + 1) != Some(&Token::LParen) {
+            None
+        } else if self.parse_keyword("ANY") || self.parse_keyword("SOME") {
+            Some(false)
+        } else if self.parse_keyword("ALL") {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// Convert an operator token to an AST operator, consuming the rest of `NOT LIKE` and
+    /// `NOT ILIKE`
+    fn parse_operator(&mut self, tok: &Token) -> Result<SQLOperator> {
+        match tok {
+            &Token::Keyword(ref k) if k == "NOT" => if self.parse_keyword("LIKE") {
+                Ok(SQLOperator::NotLike)
+            } else if self.parse_keyword("ILIKE") {
+                Ok(SQLOperator::NotILike)
+            } else {
+                parser_err!("Expected LIKE or ILIKE after NOT")
+            },
+            _ => self.to_sql_operator(tok),
+        }
+    }
+
     /// Convert a token operator to an AST operator
-    fn to_sql_operator(&self, tok: &Token) -> Result<SQLOperator, ParserError> {
+    fn to_sql_operator(&self, tok: &Token) -> Result<SQLOperator> {
         match tok {
             &Token::Eq => Ok(SQLOperator::Eq),
             &Token::Neq => Ok(SQLOperator::NotEq),
@@ -219,23 +773,35 @@ impl Parser {
             &Token::Mult => Ok(SQLOperator::Multiply),
             &Token::Div => Ok(SQLOperator::Divide),
             &Token::Mod => Ok(SQLOperator::Modulus),
+            &Token::StringConcat => Ok(SQLOperator::StringConcat),
+            &Token::BitwiseAnd => Ok(SQLOperator::BitwiseAnd),
+            &Token::BitwiseOr => Ok(SQLOperator::BitwiseOr),
+            &Token::BitwiseXor => Ok(SQLOperator::BitwiseXor),
+            &Token::ShiftLeft => Ok(SQLOperator::ShiftLeft),
+            &Token::ShiftRight => Ok(SQLOperator::ShiftRight),
             &Token::Keyword(ref k) if k == "AND" => Ok(SQLOperator::And),
             &Token::Keyword(ref k) if k == "OR" => Ok(SQLOperator::Or),
+            &Token::Keyword(ref k) if k == "LIKE" => Ok(SQLOperator::Like),
+            &Token::Keyword(ref k) if k == "ILIKE" => Ok(SQLOperator::ILike),
             _ => parser_err!(format!("Unsupported SQL operator {:?}", tok)),
         }
     }
 
     /// Get the precedence of the next token
-    fn get_next_precedence(&self) -> Result<u8, ParserError> {
-        if self.index < self.tokens.len() {
-            self.get_precedence(&self.tokens[self.index])
-        } else {
-            Ok(0)
+    fn get_next_precedence(&self) -> Result<u8> {
+        match self.tokens.get(self.index) {
+            // `NOT` only continues an expression as part of `NOT LIKE` or `NOT ILIKE`
+            Some(&Token::Keyword(ref k)) if k == "NOT" => match self.tokens.get(self.index + 1) {
+                Some(tok) if is_like(tok) => self.get_precedence(tok),
+                _ => Ok(0),
+            },
+            Some(tok) => self.get_precedence(tok),
+            None => Ok(0),
         }
     }
 
     /// Get the precedence of a token
-    fn get_precedence(&self, tok: &Token) -> Result<u8, ParserError> {
+    fn get_precedence(&self, tok: &Token) -> Result<u8> {
         //println!("get_precedence() {:?}", tok);
 
         match tok {
@@ -245,14 +811,27 @@ impl Parser {
             &Token::Eq | &Token::Lt | &Token::LtEq | &Token::Neq | &Token::Gt | &Token::GtEq => {
                 Ok(20)
             }
+            ref tok if is_like(tok) => Ok(20),
+            // bitwise operators bind more tightly than comparisons and less tightly than
+            // arithmetic, with OR below XOR below AND as in C, so `a | b & 1 = 0` compares
+            // `a | (b & 1)` with zero
+            &Token::BitwiseOr => Ok(21),
+            &Token::BitwiseXor => Ok(22),
+            &Token::BitwiseAnd => Ok(23),
+            // binds less tightly than arithmetic, so `'a' || 1 + 2` concatenates 3
+            &Token::StringConcat => Ok(25),
+            &Token::ShiftLeft | &Token::ShiftRight => Ok(27),
             &Token::Plus | &Token::Minus => Ok(30),
             &Token::Mult | &Token::Div | &Token::Mod => Ok(40),
+            &Token::Arrow | &Token::LongArrow => Ok(50),
+            &Token::DoubleColon => Ok(60),
             _ => Ok(0),
         }
     }
 
     /// Peek at the next token
-    fn peek_token(&mut self) -> Option<Token> {
+    fn peek_token(&mut self) -> Option<Token<'a>> {
+        self.examined = self.index;
         if self.index < self.tokens.len() {
             Some(self.tokens[self.index].clone())
         } else {
@@ -261,7 +840,8 @@ impl Parser {
     }
 
     /// Get the next token and increment the token index
-    fn next_token(&mut self) -> Option<Token> {
+    fn next_token(&mut self) -> Option<Token<'a>> {
+        self.examined = self.index;
         if self.index < self.tokens.len() {
             self.index = self.index + 1;
             Some(self.tokens[self.index - 1].clone())
@@ -271,7 +851,7 @@ impl Parser {
     }
 
     /// Get the previous token and decrement the token index
-    fn prev_token(&mut self) -> Option<Token> {
+    fn prev_token(&mut self) -> Option<Token<'a>> {
         if self.index > 0 {
             Some(self.tokens[self.index - 1].clone())
         } else {
@@ -283,17 +863,68 @@ impl Parser {
     fn parse_keyword(&mut self, expected: &'static str) -> bool {
         match self.peek_token() {
             Some(Token::Keyword(k)) => {
-                if expected.eq_ignore_ascii_case(k.as_str()) {
+                if expected.eq_ignore_ascii_case(k.value()) {
                     self.next_token();
                     true
                 } else {
                     false
                 }
             }
+            // NULL is tokenized as a literal but is also a keyword of `IS NULL` and `NOT NULL`
+            Some(Token::Null) if expected.eq_ignore_ascii_case("NULL") => {
+                self.next_token();
+                true
+            }
             _ => false,
         }
     }
 
+    /// Parse `EXPLAIN [ANALYZE] [( option [, ...] )] query` where the options are `ANALYZE`
+    /// and `FORMAT { TEXT | JSON }`
+    fn parse_explain(&mut self) -> Result<ASTNode> {
+        let mut analyze = self.parse_keyword("ANALYZE");
+        let mut format = SQLExplainFormat::Text;
+        if self.consume_token(&Token::LParen)? {
+            loop {
+                if self.parse_keyword("ANALYZE") {
+                    analyze = true;
+                } else if self.parse_keyword("FORMAT") {
+                    let name = match self.next_token() {
+                        Some(Token::Identifier(f)) => f,
+                        Some(Token::Keyword(f)) => Cow::Borrowed(f.text()),
+                        other => {
+                            return parser_err!(format!(
+                                "Expected EXPLAIN format, found {:?}",
+                                other
+                            ))
+                        }
+                    };
+                    format = match name.to_uppercase().as_ref() {
+                        "TEXT" => SQLExplainFormat::Text,
+                        "JSON" => SQLExplainFormat::Json,
+                        _ => return parser_err!(format!("Invalid EXPLAIN format {}", name)),
+                    };
+                } else {
+                    return parser_err!(format!(
+                        "Invalid EXPLAIN option {:?}",
+                        self.peek_token()
+                    ));
+                }
+                if !self.consume_token(&Token::Comma)? {
+                    break;
+                }
+            }
+            if !self.consume_token(&Token::RParen)? {
+                return parser_err!("Expected ')' after EXPLAIN options");
+            }
+        }
+        Ok(ASTNode::SQLExplain {
+            analyze,
+            format,
+            statement: Box::new(self.parse_expr(0)?),
+        })
+    }
+
     /// Look for an expected sequence of keywords and consume them if they exist
     fn parse_keywords(&mut self, keywords: Vec<&'static str>) -> bool {
         let index = self.index;
@@ -309,16 +940,17 @@ impl Parser {
         true
     }
 
-    //    fn parse_identifier(&mut self) -> Result<ASTNode::SQLIdentifier, Err> {
-    //        let expr = self.parse_expr()?;
-    //        match expr {
-    //            Some(ASTNode::SQLIdentifier { .. }) => Ok(expr),
-    //            _ => parser_err!(format!("Expected identifier but found {:?}", expr)))
-    //        }
-    //    }
+    /// Consume the next token if it can be read as a name
+    fn parse_identifier(&mut self) -> Option<String> {
+        let name = self.peek_token().and_then(|t| name_of(&t, self.identifier_casing));
+        if name.is_some() {
+            self.next_token();
+        }
+        name
+    }
 
     /// Consume the next token if it matches the expected token, otherwise return an error
-    fn consume_token(&mut self, expected: &Token) -> Result<bool, ParserError> {
+    fn consume_token(&mut self, expected: &Token) -> Result<bool> {
         match self.peek_token() {
             Some(ref t) => if *t == *expected {
                 self.next_token();
@@ -335,15 +967,15 @@ impl Parser {
     }
 
     /// Parse a SQL CREATE statement
-    fn parse_create(&mut self) -> Result<ASTNode, ParserError> {
+    fn parse_create(&mut self) -> Result<ASTNode> {
         if self.parse_keywords(vec!["EXTERNAL", "TABLE"]) {
-            match self.next_token() {
-                Some(Token::Identifier(id)) => {
+            match self.parse_identifier() {
+                Some(id) => {
                     // parse optional column list (schema)
                     let mut columns = vec![];
                     if self.consume_token(&Token::LParen)? {
                         loop {
-                            if let Some(Token::Identifier(column_name)) = self.next_token() {
+                            if let Some(column_name) = self.parse_identifier() {
                                 if let Ok(data_type) = self.parse_data_type() {
                                     let allow_null = if self.parse_keywords(vec!["NOT", "NULL"]) {
                                         false
@@ -415,12 +1047,19 @@ impl Parser {
                         return parser_err!("Missing 'LOCATION' clause");
                     };
 
+                    let options = if self.parse_keyword("OPTIONS") {
+                        self.parse_table_options()?
+                    } else {
+                        vec![]
+                    };
+
                     Ok(ASTNode::SQLCreateTable {
                         name: id,
                         columns,
                         file_type,
                         header_row: headers,
                         location,
+                        options,
                     })
                 }
                 _ => parser_err!(format!(
@@ -428,6 +1067,12 @@ impl Parser {
                     self.peek_token()
                 )),
             }
+        } else if self.parse_keyword("TABLE") {
+            self.parse_create_table_as(false)
+        } else if self.parse_keywords(vec!["TEMPORARY", "TABLE"]) {
+            self.parse_create_table_as(true)
+        } else if self.parse_keyword("FUNCTION") {
+            self.parse_create_function()
         } else {
             parser_err!(format!(
                 "Unexpected token after CREATE: {:?}",
@@ -436,28 +1081,236 @@ impl Parser {
         }
     }
 
+    /// Parse the remainder of a `CREATE [TEMPORARY] TABLE name AS SELECT ...` statement
+    fn parse_create_table_as(&mut self, temporary: bool) -> Result<ASTNode> {
+        let name = match self.parse_identifier() {
+            Some(id) => id,
+            None => {
+                return parser_err!(format!("Expected table name, found {:?}", self.peek_token()))
+            }
+        };
+        if !self.parse_keyword("AS") {
+            return parser_err!(format!(
+                "Expected AS after CREATE TABLE {}, found {:?}",
+                name,
+                self.peek_token()
+            ));
+        }
+        if !self.parse_keyword("SELECT") {
+            return parser_err!(format!(
+                "Expected SELECT after CREATE TABLE {} AS, found {:?}",
+                name,
+                self.peek_token()
+            ));
+        }
+        Ok(ASTNode::SQLCreateTableAs {
+            name,
+            temporary,
+            query: Box::new(self.parse_select()?),
+        })
+    }
+
+    /// Parse the remainder of a `CREATE FUNCTION name(arg type, ...) RETURNS type AS 'expr'`
+    /// statement
+    fn parse_create_function(&mut self) -> Result<ASTNode> {
+        let name = match self.parse_identifier() {
+            Some(id) => id,
+            None => {
+                return parser_err!(format!(
+                    "Expected function name, found {:?}",
+                    self.peek_token()
+                ))
+            }
+        };
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!(format!(
+                "Expected '(' after CREATE FUNCTION {}, found {:?}",
+                name,
+                self.peek_token()
+            ));
+        }
+        let mut args = vec![];
+        if !self.consume_token(&Token::RParen)? {
+            loop {
+                let arg_name = match self.parse_identifier() {
+                    Some(id) => id,
+                    None => {
+                        return parser_err!(format!(
+                            "Expected argument name, found {:?}",
+                            self.peek_token()
+                        ))
+                    }
+                };
+                args.push((arg_name, self.parse_data_type()?));
+                match self.next_token() {
+                    Some(Token::Comma) => {}
+                    Some(Token::RParen) => break,
+                    _ => return parser_err!("Expected ',' or ')' after argument definition"),
+                }
+            }
+        }
+        if !self.parse_keyword("RETURNS") {
+            return parser_err!(format!(
+                "Expected RETURNS after CREATE FUNCTION {}, found {:?}",
+                name,
+                self.peek_token()
+            ));
+        }
+        let return_type = self.parse_data_type()?;
+        if !self.parse_keyword("AS") {
+            return parser_err!(format!(
+                "Expected AS after CREATE FUNCTION {}, found {:?}",
+                name,
+                self.peek_token()
+            ));
+        }
+        Ok(ASTNode::SQLCreateFunction {
+            name,
+            args,
+            return_type,
+            body: self.parse_literal_string()?,
+        })
+    }
+
+    /// Parse the `(key 'value', ...)` list of an OPTIONS clause
+    fn parse_table_options(&mut self) -> Result<Vec<(String, String)>> {
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!("Expected '(' after OPTIONS");
+        }
+        let mut options = vec![];
+        loop {
+            let key = match self.next_token() {
+                Some(Token::Identifier(k)) => k.to_lowercase(),
+                Some(Token::Keyword(k)) => k.to_lowercase(),
+                other => return parser_err!(format!("Expected option name, found {:?}", other)),
+            };
+            options.push((key, self.parse_literal_string()?));
+            match self.next_token() {
+                Some(Token::Comma) => {}
+                Some(Token::RParen) => return Ok(options),
+                other => {
+                    return parser_err!(format!(
+                        "Expected ',' or ')' after option, found {:?}",
+                        other
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Parse a `COPY (query) TO 'location' [STORED AS {CSV | PARQUET}] [OPTIONS (...)]`
+    /// statement
+    fn parse_copy(&mut self) -> Result<ASTNode> {
+        let query = self.parse_subquery()?;
+        if !self.parse_keyword("TO") {
+            return parser_err!(format!(
+                "Expected TO after COPY query, found {:?}",
+                self.peek_token()
+            ));
+        }
+        let location = self.parse_literal_string()?;
+        // compressed files are named with the extension of their format followed by `.gz`
+        let name = location.to_lowercase();
+        let name = name.trim_right_matches(".gz");
+        let file_type = if self.parse_keywords(vec!["STORED", "AS", "CSV"]) {
+            FileType::CSV
+        } else if self.parse_keywords(vec!["STORED", "AS", "NDJSON"]) {
+            FileType::NdJson
+        } else if self.parse_keywords(vec!["STORED", "AS", "PARQUET"]) {
+            FileType::Parquet
+        } else if name.ends_with(".csv") {
+            FileType::CSV
+        } else if name.ends_with(".json") || name.ends_with(".ndjson") {
+            FileType::NdJson
+        } else if name.ends_with(".parquet") {
+            FileType::Parquet
+        } else {
+            return parser_err!(format!(
+                "Expected 'STORED AS' clause for COPY to '{}', found {:?}",
+                location,
+                self.peek_token()
+            ));
+        };
+        let options = if self.parse_keyword("OPTIONS") {
+            self.parse_table_options()?
+        } else {
+            vec![]
+        };
+        Ok(ASTNode::SQLCopyTo {
+            query: Box::new(query),
+            location,
+            file_type,
+            options,
+        })
+    }
+
+    /// Parse a SQL DROP statement
+    fn parse_drop(&mut self) -> Result<ASTNode> {
+        if !self.parse_keyword("TABLE") {
+            return parser_err!(format!(
+                "Expected TABLE after DROP, found {:?}",
+                self.peek_token()
+            ));
+        }
+        let if_exists = self.parse_keywords(vec!["IF", "EXISTS"]);
+        match self.parse_identifier() {
+            Some(name) => Ok(ASTNode::SQLDropTable { name, if_exists }),
+            None => parser_err!(format!("Expected table name, found {:?}", self.peek_token())),
+        }
+    }
+
+    /// Parse a SQL ALTER statement
+    fn parse_alter(&mut self) -> Result<ASTNode> {
+        if !self.parse_keyword("TABLE") {
+            return parser_err!(format!(
+                "Expected TABLE after ALTER, found {:?}",
+                self.peek_token()
+            ));
+        }
+        let name = match self.parse_identifier() {
+            Some(id) => id,
+            None => {
+                return parser_err!(format!("Expected table name, found {:?}", self.peek_token()))
+            }
+        };
+        if !self.parse_keywords(vec!["RENAME", "TO"]) {
+            return parser_err!(format!(
+                "Expected RENAME TO after ALTER TABLE {}, found {:?}",
+                name,
+                self.peek_token()
+            ));
+        }
+        match self.parse_identifier() {
+            Some(new_name) => Ok(ASTNode::SQLAlterTableRename { name, new_name }),
+            None => parser_err!(format!(
+                "Expected new table name, found {:?}",
+                self.peek_token()
+            )),
+        }
+    }
+
     /// Parse a literal integer/long
-    fn parse_literal_int(&mut self) -> Result<i64, ParserError> {
+    fn parse_literal_int(&mut self) -> Result<i64> {
         match self.next_token() {
-            Some(Token::Number(s)) => s.parse::<i64>().map_err(|e| {
-                ParserError::ParserError(format!("Could not parse '{}' as i64: {}", s, e))
+            Some(Token::Integer(s)) => s.parse::<i64>().map_err(|e| {
+                DataFusionError::Parser(format!("Could not parse '{}' as i64: {}", s, e))
             }),
             other => parser_err!(format!("Expected literal int, found {:?}", other)),
         }
     }
 
     /// Parse a literal string
-    fn parse_literal_string(&mut self) -> Result<String, ParserError> {
+    fn parse_literal_string(&mut self) -> Result<String> {
         match self.next_token() {
-            Some(Token::String(ref s)) => Ok(s.clone()),
+            Some(Token::String(s)) => Ok(s.into_owned()),
             other => parser_err!(format!("Expected literal string, found {:?}", other)),
         }
     }
 
     /// Parse a SQL datatype (in the context of a CREATE TABLE statement for example)
-    fn parse_data_type(&mut self) -> Result<SQLType, ParserError> {
+    fn parse_data_type(&mut self) -> Result<SQLType> {
         match self.next_token() {
-            Some(Token::Keyword(k)) => match k.to_uppercase().as_ref() {
+            Some(Token::Keyword(k)) => match k.value() {
                 "BOOLEAN" => Ok(SQLType::Boolean),
                 "UINT8" => Ok(SQLType::UInt8),
                 "UINT16" => Ok(SQLType::UInt16),
@@ -479,6 +1332,8 @@ impl Parser {
                         Ok(SQLType::Utf8(100 as usize))
                     }
                 }
+                "TEXT" => Ok(SQLType::Utf8(usize::max_value())),
+                "BYTEA" | "BINARY" | "VARBINARY" => Ok(SQLType::Binary),
                 _ => parser_err!(format!("Invalid data type '{:?}'", k)),
             },
             other => parser_err!(format!("Invalid data type: '{:?}'", other)),
@@ -486,22 +1341,97 @@ impl Parser {
     }
 
     /// Parse a SELECT statement
-    fn parse_select(&mut self) -> Result<ASTNode, ParserError> {
+    fn parse_select(&mut self) -> Result<ASTNode> {
+        let hints = match self.peek_token() {
+            Some(Token::Hint(text)) => {
+                self.next_token();
+                let tokens = Tokenizer::new(&text)
+                    .with_identifier_casing(self.identifier_casing)
+                    .tokenize()?;
+                let mut parser = Parser::new(tokens);
+                parser.set_identifier_casing(self.identifier_casing);
+                parser.parse_hints()?
+            }
+            _ => vec![],
+        };
+        let distinct = self.parse_keyword("DISTINCT");
         let projection = self.parse_expr_list()?;
 
         let relation: Option<Box<ASTNode>> = if self.parse_keyword("FROM") {
-            //TODO: add support for JOIN
-            Some(Box::new(self.parse_expr(0)?))
-        } else {
-            None
-        };
-
-        let selection = if self.parse_keyword("WHERE") {
-            Some(Box::new(self.parse_expr(0)?))
-        } else {
-            None
-        };
-
+            let mut relation = self.parse_table_factor()?;
+            loop {
+                if self.parse_keywords(vec!["CROSS", "JOIN", "LATERAL"]) {
+                    relation = ASTNode::SQLLateralJoin {
+                        left: Box::new(relation),
+                        subquery: Box::new(self.parse_subquery()?),
+                        left_outer: false,
+                        on: None,
+                    };
+                    continue;
+                }
+                if self.parse_keywords(vec!["LEFT", "JOIN", "LATERAL"])
+                    || self.parse_keywords(vec!["LEFT", "OUTER", "JOIN", "LATERAL"])
+                {
+                    let subquery = self.parse_subquery()?;
+                    let on = if self.parse_keyword("ON") {
+                        Some(Box::new(self.parse_expr(0)?))
+                    } else {
+                        None
+                    };
+                    relation = ASTNode::SQLLateralJoin {
+                        left: Box::new(relation),
+                        subquery: Box::new(subquery),
+                        left_outer: true,
+                        on,
+                    };
+                    continue;
+                }
+                let join_type = if self.parse_keyword("JOIN")
+                    || self.parse_keywords(vec!["INNER", "JOIN"])
+                {
+                    SQLJoinType::Inner
+                } else if self.parse_keywords(vec!["LEFT", "SEMI", "JOIN"]) {
+                    SQLJoinType::LeftSemi
+                } else if self.parse_keywords(vec!["LEFT", "ANTI", "JOIN"]) {
+                    SQLJoinType::LeftAnti
+                } else {
+                    break;
+                };
+                if join_type == SQLJoinType::Inner && self.parse_keyword("LATERAL") {
+                    let subquery = self.parse_subquery()?;
+                    if !self.parse_keyword("ON") {
+                        return parser_err!("Expected ON after JOIN LATERAL subquery");
+                    }
+                    relation = ASTNode::SQLLateralJoin {
+                        left: Box::new(relation),
+                        subquery: Box::new(subquery),
+                        left_outer: false,
+                        on: Some(Box::new(self.parse_expr(0)?)),
+                    };
+                    continue;
+                }
+                let right = self.parse_table_factor()?;
+                if !self.parse_keyword("ON") {
+                    return parser_err!("Expected ON after JOIN");
+                }
+                relation = ASTNode::SQLJoin {
+                    left: Box::new(relation),
+                    right: Box::new(right),
+                    join_type,
+                    on: Box::new(self.parse_expr(0)?),
+                };
+            }
+            Some(Box::new(relation))
+        } else {
+            None
+        };
+
+        let selection = if self.parse_keyword("WHERE") {
+            Some(Box::new(self.parse_expr(0)?))
+        } else {
+            None
+        };
+
         let group_by = if self.parse_keywords(vec!["GROUP", "BY"]) {
             Some(self.parse_expr_list()?)
         } else {
@@ -514,6 +1444,12 @@ impl Parser {
             None
         };
 
+        let qualify = if self.parse_keyword("QUALIFY") {
+            Some(Box::new(self.parse_expr(0)?))
+        } else {
+            None
+        };
+
         let order_by = if self.parse_keywords(vec!["ORDER", "BY"]) {
             Some(self.parse_order_by_expr_list()?)
         } else {
@@ -526,26 +1462,216 @@ impl Parser {
             None
         };
 
-        if let Some(next_token) = self.peek_token() {
-            parser_err!(format!(
-                "Unexpected token at end of SELECT: {:?}",
-                next_token
-            ))
+        match self.peek_token() {
+            // the closing parenthesis of a subquery is consumed by `parse_subquery`
+            Some(Token::RParen) if self.subquery_depth > 0 => {}
+            Some(Token::Semicolon) => {}
+            Some(next_token) => {
+                return parser_err!(format!(
+                    "Unexpected token at end of SELECT: {:?}",
+                    next_token
+                ))
+            }
+            None => {}
+        }
+        Ok(ASTNode::SQLSelect {
+            hints,
+            distinct,
+            projection,
+            selection,
+            relation,
+            limit,
+            order_by,
+            group_by,
+            having,
+            qualify,
+        })
+    }
+
+    /// Parse the hints of a `/*+ ... */` comment, such as `BROADCAST(t1) JOIN_ORDER(t1, t2)`.
+    /// Hints can also be separated by commas.
+    fn parse_hints(&mut self) -> Result<Vec<SQLHint>> {
+        let mut hints = vec![];
+        loop {
+            let name = match self.next_token() {
+                Some(ref t) => match name_of(t, self.identifier_casing) {
+                    Some(name) => name.to_uppercase(),
+                    None => return parser_err!(format!("Expected a hint, found {:?}", t)),
+                },
+                None => return Ok(hints),
+            };
+            if !self.consume_token(&Token::LParen)? {
+                return parser_err!(format!("Expected '(' after hint {}", name));
+            }
+            let mut tables = vec![];
+            loop {
+                match self.parse_identifier() {
+                    Some(table) => tables.push(table),
+                    None => return parser_err!(format!("Expected a table name in hint {}", name)),
+                }
+                if self.consume_token(&Token::RParen)? {
+                    break;
+                }
+                if !self.consume_token(&Token::Comma)? {
+                    return parser_err!(format!("Expected ',' or ')' in hint {}", name));
+                }
+            }
+            hints.push(match name.as_str() {
+                "BROADCAST" => SQLHint::Broadcast(tables),
+                "JOIN_ORDER" => SQLHint::JoinOrder(tables),
+                _ => return parser_err!(format!("Unknown hint {}", name)),
+            });
+            if self.peek_token() == Some(Token::Comma) {
+                self.next_token();
+            }
+        }
+    }
+
+    /// Parse a parenthesized SELECT statement, such as the subquery of a LATERAL join
+    fn parse_subquery(&mut self) -> Result<ASTNode> {
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!("Expected '(' before subquery");
+        }
+        if self.peek_token() != Some(Token::Keyword(Keyword::new("SELECT"))) {
+            return parser_err!("Expected SELECT in subquery");
+        }
+        self.subquery_depth += 1;
+        let query = self.parse_nested_statement();
+        self.subquery_depth -= 1;
+        let query = query?;
+        if !self.consume_token(&Token::RParen)? {
+            return parser_err!("Expected ')' after subquery");
+        }
+        Ok(query)
+    }
+
+    /// Parse a single relation in a FROM clause, with an optional time travel clause, an optional
+    /// alias and an optional TABLESAMPLE clause
+    fn parse_table_factor(&mut self) -> Result<ASTNode> {
+        // RANGE is reserved for window frames but is also the name of a table function
+        let is_range = match (self.tokens.get(self.index), self.tokens.get(self.index + 1)) {
+            (Some(&Token::Keyword(ref k)), Some(&Token::LParen)) => k == "RANGE",
+            _ => false,
+        };
+        let mut relation = if is_range {
+            self.next_token();
+            self.parse_name_expr("range".to_string())?
         } else {
-            Ok(ASTNode::SQLSelect {
-                projection,
-                selection,
-                relation,
-                limit,
-                order_by,
-                group_by,
-                having,
-            })
+            self.parse_expr(0)?
+        };
+        if let Some(snapshot) = self.parse_table_snapshot()? {
+            relation = ASTNode::SQLTableSnapshot {
+                relation: Box::new(relation),
+                snapshot,
+            };
+        }
+        // AS can be omitted before an alias, unless the alias is a keyword
+        let alias = if self.parse_keyword("AS") {
+            match self.parse_identifier() {
+                Some(alias) => Some(alias),
+                None => return parser_err!("Expected an alias after AS"),
+            }
+        } else {
+            match self.peek_token() {
+                Some(Token::Identifier(_)) => self.parse_identifier(),
+                _ => None,
+            }
+        };
+        if let Some(alias) = alias {
+            relation = ASTNode::SQLTableAlias {
+                relation: Box::new(relation),
+                alias,
+            };
+        }
+        if self.parse_keyword("TABLESAMPLE") {
+            self.parse_table_sample(relation)
+        } else {
+            Ok(relation)
+        }
+    }
+
+    /// Parse an optional `FOR SYSTEM_TIME AS OF ts` or `AT ({VERSION | TIMESTAMP} => value)`
+    /// clause
+    fn parse_table_snapshot(&mut self) -> Result<Option<SQLSnapshot>> {
+        if self.parse_keywords(vec!["FOR", "SYSTEM_TIME", "AS", "OF"]) {
+            return Ok(Some(SQLSnapshot::Timestamp(Box::new(self.parse_expr(0)?))));
+        }
+        if !self.parse_keyword("AT") {
+            return Ok(None);
         }
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!("Expected '(' after AT");
+        }
+        let kind = match self.next_token() {
+            Some(Token::Identifier(ref k)) => k.to_uppercase(),
+            Some(Token::Keyword(ref k)) => k.value().to_string(),
+            other => {
+                return parser_err!(format!("Expected VERSION or TIMESTAMP, found {:?}", other))
+            }
+        };
+        if !self.consume_token(&Token::FatArrow)? {
+            return parser_err!(format!("Expected '=>' after {}", kind));
+        }
+        let snapshot = match kind.as_ref() {
+            "VERSION" => SQLSnapshot::Version(self.parse_literal_int()?),
+            "TIMESTAMP" => SQLSnapshot::Timestamp(Box::new(self.parse_expr(0)?)),
+            _ => return parser_err!(format!("Invalid AT option {}", kind)),
+        };
+        if !self.consume_token(&Token::RParen)? {
+            return parser_err!("Expected ')' after AT option");
+        }
+        Ok(Some(snapshot))
+    }
+
+    /// Parse the remainder of a `TABLESAMPLE [BERNOULLI | SYSTEM] (p) [REPEATABLE (n)]` clause
+    fn parse_table_sample(&mut self, relation: ASTNode) -> Result<ASTNode> {
+        let method = if self.parse_keyword("SYSTEM") {
+            SQLSampleMethod::System
+        } else {
+            // BERNOULLI is the default when no method is specified
+            self.parse_keyword("BERNOULLI");
+            SQLSampleMethod::Bernoulli
+        };
+
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!("Expected '(' after TABLESAMPLE");
+        }
+        let percent = match self.next_token() {
+            Some(Token::Integer(ref n))
+            | Some(Token::Decimal(ref n))
+            | Some(Token::Float(ref n)) => match n.parse::<f64>() {
+                Ok(n) => n,
+                Err(e) => return parser_err!(format!("Could not parse '{}' as f64: {}", n, e)),
+            },
+            other => return parser_err!(format!("Expected sample percentage, found {:?}", other)),
+        };
+        if !self.consume_token(&Token::RParen)? {
+            return parser_err!("Expected ')' after sample percentage");
+        }
+
+        let seed = if self.parse_keyword("REPEATABLE") {
+            if !self.consume_token(&Token::LParen)? {
+                return parser_err!("Expected '(' after REPEATABLE");
+            }
+            let seed = self.parse_literal_int()?;
+            if !self.consume_token(&Token::RParen)? {
+                return parser_err!("Expected ')' after REPEATABLE seed");
+            }
+            Some(seed as u64)
+        } else {
+            None
+        };
+
+        Ok(ASTNode::SQLTableSample {
+            relation: Box::new(relation),
+            method,
+            percent,
+            seed,
+        })
     }
 
     /// Parse a comma-delimited list of SQL expressions
-    fn parse_expr_list(&mut self) -> Result<Vec<ASTNode>, ParserError> {
+    fn parse_expr_list(&mut self) -> Result<Vec<ASTNode>> {
         let mut expr_list: Vec<ASTNode> = vec![];
         loop {
             expr_list.push(self.parse_expr(0)?);
@@ -564,36 +1690,42 @@ impl Parser {
     }
 
     /// Parse a comma-delimited list of SQL ORDER BY expressions
-    fn parse_order_by_expr_list(&mut self) -> Result<Vec<ASTNode>, ParserError> {
+    fn parse_order_by_expr_list(&mut self) -> Result<Vec<ASTNode>> {
         let mut expr_list: Vec<ASTNode> = vec![];
         loop {
             let expr = self.parse_expr(0)?;
 
-            // look for optional ASC / DESC specifier
+            // look for optional ASC / DESC specifier, any other keyword starts the next clause
             let asc = match self.peek_token() {
-                Some(Token::Keyword(k)) => {
-                    self.next_token(); // consume it
-                    match k.to_uppercase().as_ref() {
-                        "ASC" => true,
-                        "DESC" => false,
-                        _ => {
-                            return parser_err!(format!(
-                                "Invalid modifier for ORDER BY expression: {:?}",
-                                k
-                            ))
-                        }
-                    }
-                }
-                Some(Token::Comma) => true,
+                Some(Token::Keyword(_)) => if self.parse_keyword("DESC") {
+                    false
+                } else {
+                    self.parse_keyword("ASC");
+                    true
+                },
+                Some(Token::Comma) | Some(Token::RParen) | Some(Token::Semicolon) => true,
                 Some(other) => {
                     return parser_err!(format!("Unexpected token after ORDER BY expr: {:?}", other))
                 }
                 None => true,
             };
 
+            let nulls_first = if self.parse_keyword("NULLS") {
+                if self.parse_keyword("FIRST") {
+                    Some(true)
+                } else if self.parse_keyword("LAST") {
+                    Some(false)
+                } else {
+                    return parser_err!("Expected FIRST or LAST after NULLS");
+                }
+            } else {
+                None
+            };
+
             expr_list.push(ASTNode::SQLOrderBy {
                 expr: Box::new(expr),
                 asc,
+                nulls_first,
             });
 
             if let Some(t) = self.peek_token() {
@@ -610,8 +1742,139 @@ impl Parser {
         Ok(expr_list)
     }
 
+    /// Parse the `OVER (...)` clause that follows a function call, if there is one
+    fn parse_over(&mut self, id: String, args: Vec<ASTNode>, distinct: bool) -> Result<ASTNode> {
+        let null_treatment = if self.parse_keywords(vec!["IGNORE", "NULLS"]) {
+            Some(true)
+        } else if self.parse_keywords(vec!["RESPECT", "NULLS"]) {
+            Some(false)
+        } else {
+            None
+        };
+        if !self.parse_keyword("OVER") {
+            return match null_treatment {
+                Some(_) => parser_err!("Expected OVER after RESPECT NULLS or IGNORE NULLS"),
+                None => Ok(ASTNode::SQLFunction { id, args, distinct }),
+            };
+        }
+        if distinct {
+            return parser_err!("DISTINCT is not supported in window functions");
+        }
+        if !self.consume_token(&Token::LParen)? {
+            return parser_err!("Expected '(' after OVER");
+        }
+        let partition_by = if self.parse_keywords(vec!["PARTITION", "BY"]) {
+            self.parse_expr_list()?
+        } else {
+            vec![]
+        };
+        let order_by = if self.parse_keywords(vec!["ORDER", "BY"]) {
+            self.parse_order_by_expr_list()?
+        } else {
+            vec![]
+        };
+        let frame = if self.parse_keyword("ROWS") {
+            Some(self.parse_window_frame(SQLWindowFrameUnits::Rows)?)
+        } else if self.parse_keyword("RANGE") {
+            Some(self.parse_window_frame(SQLWindowFrameUnits::Range)?)
+        } else if self.parse_keyword("GROUPS") {
+            Some(self.parse_window_frame(SQLWindowFrameUnits::Groups)?)
+        } else {
+            None
+        };
+        match self.next_token() {
+            Some(Token::RParen) => Ok(ASTNode::SQLWindowFunction {
+                id,
+                args,
+                partition_by,
+                order_by,
+                frame,
+                ignore_nulls: null_treatment.unwrap_or(false),
+            }),
+            other => parser_err!(format!(
+                "Expected ')' at end of window specification, found {:?}",
+                other
+            )),
+        }
+    }
+
+    /// Parse a window frame after its units. A frame with only a start bound ends at the
+    /// current row.
+    fn parse_window_frame(&mut self, units: SQLWindowFrameUnits) -> Result<SQLWindowFrame> {
+        let (start, end) = if self.parse_keyword("BETWEEN") {
+            let start = self.parse_window_frame_bound()?;
+            if !self.parse_keyword("AND") {
+                return parser_err!("Expected AND in window frame");
+            }
+            (start, self.parse_window_frame_bound()?)
+        } else {
+            (self.parse_window_frame_bound()?, SQLWindowFrameBound::CurrentRow)
+        };
+        let exclusion = if self.parse_keyword("EXCLUDE") {
+            if self.parse_keywords(vec!["CURRENT", "ROW"]) {
+                SQLWindowFrameExclusion::CurrentRow
+            } else if self.parse_keyword("GROUP") {
+                SQLWindowFrameExclusion::Group
+            } else if self.parse_keyword("TIES") {
+                SQLWindowFrameExclusion::Ties
+            } else if self.parse_keywords(vec!["NO", "OTHERS"]) {
+                SQLWindowFrameExclusion::NoOthers
+            } else {
+                return parser_err!(
+                    "Expected CURRENT ROW, GROUP, TIES or NO OTHERS after EXCLUDE"
+                );
+            }
+        } else {
+            SQLWindowFrameExclusion::NoOthers
+        };
+        Ok(SQLWindowFrame {
+            units,
+            start,
+            end,
+            exclusion,
+        })
+    }
+
+    /// Parse `UNBOUNDED PRECEDING`, `offset PRECEDING`, `CURRENT ROW`, `offset FOLLOWING` or
+    /// `UNBOUNDED FOLLOWING`, where the offset is a number or, for RANGE frames over
+    /// timestamps, an interval string such as `'1 day'`
+    fn parse_window_frame_bound(&mut self) -> Result<SQLWindowFrameBound> {
+        if self.parse_keywords(vec!["CURRENT", "ROW"]) {
+            return Ok(SQLWindowFrameBound::CurrentRow);
+        }
+        let offset = if self.parse_keyword("UNBOUNDED") {
+            None
+        } else {
+            match self.peek_token() {
+                Some(Token::Integer(_))
+                | Some(Token::Decimal(_))
+                | Some(Token::Float(_))
+                | Some(Token::String(_)) => Some(Box::new(self.parse_prefix()?)),
+                other => {
+                    return parser_err!(format!(
+                        "Expected a window frame bound, found {:?}",
+                        other
+                    ))
+                }
+            }
+        };
+        let preceding = if self.parse_keyword("PRECEDING") {
+            true
+        } else if self.parse_keyword("FOLLOWING") {
+            false
+        } else {
+            return parser_err!("Expected PRECEDING or FOLLOWING in window frame bound");
+        };
+        Ok(match (offset, preceding) {
+            (None, true) => SQLWindowFrameBound::UnboundedPreceding,
+            (None, false) => SQLWindowFrameBound::UnboundedFollowing,
+            (Some(n), true) => SQLWindowFrameBound::Preceding(n),
+            (Some(n), false) => SQLWindowFrameBound::Following(n),
+        })
+    }
+
     /// Parse a LIMIT clause
-    fn parse_limit(&mut self) -> Result<Option<Box<ASTNode>>, ParserError> {
+    fn parse_limit(&mut self) -> Result<Option<Box<ASTNode>>> {
         if self.parse_keyword("ALL") {
             Ok(None)
         } else {
@@ -621,6 +1884,98 @@ impl Parser {
     }
 }
 
+/// Whether the parser skips a token. A hint is only read straight after SELECT, and anywhere
+/// else is skipped like any other comment.
+fn is_skipped(previous: Option<&Token>, token: &Token) -> bool {
+    match *token {
+        Token::Whitespace | Token::Comment(_) => true,
+        Token::Hint(_) => match previous {
+            Some(&Token::Keyword(ref k)) => *k != "SELECT",
+            _ => true,
+        },
+        _ => false,
+    }
+}
+
+/// Get the unit of an interval from its name, such as `DAY` or `days`
+fn interval_unit(name: &str) -> Option<SQLIntervalUnit> {
+    match name.to_uppercase().as_ref() {
+        "YEAR" | "YEARS" => Some(SQLIntervalUnit::Year),
+        "MONTH" | "MONTHS" => Some(SQLIntervalUnit::Month),
+        "DAY" | "DAYS" => Some(SQLIntervalUnit::Day),
+        "HOUR" | "HOURS" => Some(SQLIntervalUnit::Hour),
+        "MINUTE" | "MINUTES" => Some(SQLIntervalUnit::Minute),
+        "SECOND" | "SECONDS" => Some(SQLIntervalUnit::Second),
+        _ => None,
+    }
+}
+
+/// The name a token is read as where a name is expected: an identifier, or a non-reserved
+/// keyword as it was written, with the casing of unquoted identifiers applied
+fn name_of(tok: &Token, casing: IdentifierCasing) -> Option<String> {
+    match *tok {
+        Token::Identifier(ref id) => Some(id.to_string()),
+        Token::Keyword(ref k) if !is_reserved_keyword(k) => {
+            Some(fold_identifier(k.text(), casing).into_owned())
+        }
+        _ => None,
+    }
+}
+
+/// Rewrite `doc -> 'key'` and `doc -> 0` as calls to the JSON extraction functions with the
+/// equivalent path
+fn json_extract_call(expr: ASTNode, tok: &Token, key: ASTNode) -> Result<ASTNode> {
+    let path = match key {
+        ASTNode::SQLLiteralString(key) => format!("[\"{}\"]", key),
+        ASTNode::SQLLiteralLong(n) => format!("[{}]", n),
+        other => {
+            return parser_err!(format!(
+                "Expected a key or index after {:?}, found {:?}",
+                tok, other
+            ))
+        }
+    };
+    let id = if *tok == Token::Arrow {
+        "json_extract"
+    } else {
+        "json_extract_string"
+    };
+    Ok(ASTNode::SQLFunction {
+        id: id.to_string(),
+        args: vec![expr, ASTNode::SQLLiteralString(path)],
+        distinct: false,
+    })
+}
+
+/// Precedence of the operand of a prefix operator
+fn unary_precedence(op: &SQLOperator) -> u8 {
+    match *op {
+        SQLOperator::Not => NOT_PRECEDENCE,
+        _ => UNARY_PRECEDENCE,
+    }
+}
+
+/// Whether an operator is a comparison that can be quantified over a subquery
+fn is_comparison(op: &SQLOperator) -> bool {
+    match *op {
+        SQLOperator::Eq
+        | SQLOperator::NotEq
+        | SQLOperator::Gt
+        | SQLOperator::GtEq
+        | SQLOperator::Lt
+        | SQLOperator::LtEq => true,
+        _ => false,
+    }
+}
+
+/// Whether a token is `LIKE` or `ILIKE`
+fn is_like(tok: &Token) -> bool {
+    match *tok {
+        Token::Keyword(ref k) => k == "LIKE" || k == "ILIKE",
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -641,6 +1996,128 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_quoted_identifiers() {
+        let sql = String::from("SELECT \"order\", \"first name\" FROM \"select\"");
+        let ast = parse_sql(&sql);
+        match ast {
+            ASTNode::SQLSelect {
+                projection,
+                relation,
+                ..
+            } => {
+                assert_eq!(
+                    vec![
+                        ASTNode::SQLIdentifier("order".to_string()),
+                        ASTNode::SQLIdentifier("first name".to_string()),
+                    ],
+                    projection
+                );
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLIdentifier("select".to_string()))),
+                    relation
+                );
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn parse_non_reserved_keywords_as_names() {
+        let sql = String::from("SELECT format, date, if(key > 1, 1, 0) FROM schema.text");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                projection,
+                relation,
+                ..
+            } => {
+                assert_eq!(
+                    vec![
+                        ASTNode::SQLIdentifier("format".to_string()),
+                        ASTNode::SQLIdentifier("date".to_string()),
+                        ASTNode::SQLFunction {
+                            id: "if".to_string(),
+                            args: vec![
+                                ASTNode::SQLBinaryExpr {
+                                    left: Box::new(ASTNode::SQLIdentifier("key".to_string())),
+                                    op: SQLOperator::Gt,
+                                    right: Box::new(ASTNode::SQLLiteralLong(1)),
+                                },
+                                ASTNode::SQLLiteralLong(1),
+                                ASTNode::SQLLiteralLong(0),
+                            ],
+                            distinct: false,
+                        },
+                    ],
+                    projection
+                );
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLCompoundIdentifier(vec![
+                        "schema".to_string(),
+                        "text".to_string(),
+                    ]))),
+                    relation
+                );
+            }
+            _ => assert!(false),
+        }
+
+        match parse_sql(&String::from("DROP TABLE timestamp")) {
+            ASTNode::SQLDropTable { name, .. } => assert_eq!("timestamp", name),
+            _ => assert!(false),
+        }
+
+        // reserved keywords can only be used as names when quoted
+        assert!(Parser::parse_sql("SELECT when FROM t".to_string()).is_err());
+
+        // keywords keep the case they were written in, like identifiers
+        let names = |sql: &str, casing: IdentifierCasing| {
+            let tokens = Tokenizer::new(sql)
+                .with_identifier_casing(casing)
+                .tokenize()
+                .unwrap();
+            let mut parser = Parser::new(tokens);
+            parser.set_identifier_casing(casing);
+            match parser.parse_statement().unwrap() {
+                ASTNode::SQLSelect { projection, .. } => projection,
+                _ => panic!(),
+            }
+        };
+        let sql = "SELECT Date, t.Key, Ts FROM t";
+        assert_eq!(
+            vec![
+                ASTNode::SQLIdentifier("Date".to_string()),
+                ASTNode::SQLCompoundIdentifier(vec!["t".to_string(), "Key".to_string()]),
+                ASTNode::SQLIdentifier("Ts".to_string()),
+            ],
+            names(sql, IdentifierCasing::CaseSensitive)
+        );
+        assert_eq!(
+            vec![
+                ASTNode::SQLIdentifier("date".to_string()),
+                ASTNode::SQLCompoundIdentifier(vec!["t".to_string(), "key".to_string()]),
+                ASTNode::SQLIdentifier("ts".to_string()),
+            ],
+            names(sql, IdentifierCasing::FoldLower)
+        );
+    }
+
+    #[test]
+    fn parse_decimal_literals() {
+        let sql = String::from("SELECT 3.14, 1.5e2, 42 FROM t");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { projection, .. } => assert_eq!(
+                vec![
+                    ASTNode::SQLLiteralDouble(3.14),
+                    ASTNode::SQLLiteralDouble(150.0),
+                    ASTNode::SQLLiteralLong(42),
+                ],
+                projection
+            ),
+            _ => assert!(false),
+        }
+    }
+
     #[test]
     fn parse_select_wildcard() {
         let sql = String::from("SELECT * FROM customer");
@@ -665,6 +2142,7 @@ mod tests {
                     ASTNode::SQLFunction {
                         id: "COUNT".to_string(),
                         args: vec![ASTNode::SQLWildcard],
+                        distinct: false,
                     },
                     projection[0]
                 );
@@ -679,27 +2157,190 @@ mod tests {
             "SELECT id, fname, lname FROM customer \
              WHERE salary != 'Not Provided' AND salary != ''",
         );
-        let _ast = parse_sql(&sql);
-        //TODO: add assertions
-    }
-
-    #[test]
-    fn parse_projection_nested_type() {
-        let sql = String::from("SELECT customer.address.state FROM foo");
-        let _ast = parse_sql(&sql);
-        //TODO: add assertions
-    }
-
-    #[test]
-    fn parse_compound_expr_1() {
-        use self::ASTNode::*;
-        use self::SQLOperator::*;
-        let sql = String::from("a + b * c");
-        let ast = parse_sql(&sql);
-        assert_eq!(
-            SQLBinaryExpr {
-                left: Box::new(SQLIdentifier("a".to_string())),
-                op: Plus,
+        let not_equal = |value: &str| ASTNode::SQLBinaryExpr {
+            left: Box::new(ASTNode::SQLIdentifier("salary".to_string())),
+            op: SQLOperator::NotEq,
+            right: Box::new(ASTNode::SQLLiteralString(value.to_string())),
+        };
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                projection,
+                relation,
+                selection,
+                limit,
+                ..
+            } => {
+                assert_eq!(
+                    vec![
+                        ASTNode::SQLIdentifier("id".to_string()),
+                        ASTNode::SQLIdentifier("fname".to_string()),
+                        ASTNode::SQLIdentifier("lname".to_string()),
+                    ],
+                    projection
+                );
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLIdentifier("customer".to_string()))),
+                    relation
+                );
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLBinaryExpr {
+                        left: Box::new(not_equal("Not Provided")),
+                        op: SQLOperator::And,
+                        right: Box::new(not_equal("")),
+                    })),
+                    selection
+                );
+                assert_eq!(None, limit);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_select_limit() {
+        let sql = String::from("SELECT * FROM customer WHERE id = 1 LIMIT 5");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                projection,
+                selection,
+                limit,
+                ..
+            } => {
+                assert_eq!(vec![ASTNode::SQLWildcard], projection);
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLBinaryExpr {
+                        left: Box::new(ASTNode::SQLIdentifier("id".to_string())),
+                        op: SQLOperator::Eq,
+                        right: Box::new(ASTNode::SQLLiteralLong(1)),
+                    })),
+                    selection
+                );
+                assert_eq!(Some(Box::new(ASTNode::SQLLiteralLong(5))), limit);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_projection_nested_type() {
+        let sql = String::from("SELECT customer.address.state FROM foo");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { projection, .. } => assert_eq!(
+                vec![ASTNode::SQLCompoundIdentifier(vec![
+                    "customer".to_string(),
+                    "address".to_string(),
+                    "state".to_string(),
+                ])],
+                projection
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_qualified_names() {
+        let sql = String::from("SELECT t.id, \"my schema\".t.\"first name\" FROM schema.table t");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                projection,
+                relation,
+                ..
+            } => {
+                assert_eq!(
+                    vec![
+                        ASTNode::SQLCompoundIdentifier(vec!["t".to_string(), "id".to_string()]),
+                        ASTNode::SQLCompoundIdentifier(vec![
+                            "my schema".to_string(),
+                            "t".to_string(),
+                            "first name".to_string(),
+                        ]),
+                    ],
+                    projection
+                );
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLTableAlias {
+                        relation: Box::new(ASTNode::SQLCompoundIdentifier(vec![
+                            "schema".to_string(),
+                            "table".to_string(),
+                        ])),
+                        alias: "t".to_string(),
+                    })),
+                    relation
+                );
+            }
+            _ => panic!(),
+        }
+
+        match Parser::parse_sql("SELECT t.(a) FROM t".to_string()) {
+            Err(DataFusionError::Parser(msg)) => assert!(
+                msg.starts_with("Expected an identifier after '.' in t"),
+                msg
+            ),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_table_aliases() {
+        let sql = String::from("SELECT t.id FROM schema.table t");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { relation, .. } => assert_eq!(
+                Some(Box::new(ASTNode::SQLTableAlias {
+                    relation: Box::new(ASTNode::SQLCompoundIdentifier(vec![
+                        "schema".to_string(),
+                        "table".to_string(),
+                    ])),
+                    alias: "t".to_string(),
+                })),
+                relation
+            ),
+            _ => panic!(),
+        }
+
+        let sql = String::from(
+            "SELECT p.id FROM person AS p TABLESAMPLE (10) JOIN orders o ON o.person_id = p.id",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { relation, .. } => match relation.map(|r| *r) {
+                Some(ASTNode::SQLJoin { left, right, .. }) => {
+                    assert_eq!(
+                        ASTNode::SQLTableSample {
+                            relation: Box::new(ASTNode::SQLTableAlias {
+                                relation: Box::new(ASTNode::SQLIdentifier("person".to_string())),
+                                alias: "p".to_string(),
+                            }),
+                            method: SQLSampleMethod::Bernoulli,
+                            percent: 10.0,
+                            seed: None,
+                        },
+                        *left
+                    );
+                    assert_eq!(
+                        ASTNode::SQLTableAlias {
+                            relation: Box::new(ASTNode::SQLIdentifier("orders".to_string())),
+                            alias: "o".to_string(),
+                        },
+                        *right
+                    );
+                }
+                other => panic!("{:?}", other),
+            },
+            _ => panic!(),
+        }
+
+        assert!(Parser::parse_sql("SELECT id FROM person AS WHERE id = 1".to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_compound_expr_1() {
+        use self::ASTNode::*;
+        use self::SQLOperator::*;
+        let sql = String::from("a + b * c");
+        let ast = parse_sql(&sql);
+        assert_eq!(
+            SQLBinaryExpr {
+                left: Box::new(SQLIdentifier("a".to_string())),
+                op: Plus,
                 right: Box::new(SQLBinaryExpr {
                     left: Box::new(SQLIdentifier("b".to_string())),
                     op: Multiply,
@@ -730,6 +2371,146 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_nested_precedence() {
+        use self::ASTNode::*;
+        use self::SQLOperator::*;
+        let id = |name: &str| Box::new(SQLIdentifier(name.to_string()));
+        let binary = |left: ASTNode, op: SQLOperator, right: ASTNode| SQLBinaryExpr {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        };
+        let cases = vec![
+            (
+                "a + b * c = d",
+                binary(
+                    binary(*id("a"), Plus, binary(*id("b"), Multiply, *id("c"))),
+                    Eq,
+                    *id("d"),
+                ),
+            ),
+            (
+                "(a + b) * c",
+                binary(
+                    SQLNested(Box::new(binary(*id("a"), Plus, *id("b")))),
+                    Multiply,
+                    *id("c"),
+                ),
+            ),
+            (
+                "-a * b - -c",
+                binary(
+                    binary(
+                        SQLUnary {
+                            operator: Minus,
+                            rex: id("a"),
+                        },
+                        Multiply,
+                        *id("b"),
+                    ),
+                    Minus,
+                    SQLUnary {
+                        operator: Minus,
+                        rex: id("c"),
+                    },
+                ),
+            ),
+            (
+                "a * (b - (c + 1)) < +d",
+                binary(
+                    binary(
+                        *id("a"),
+                        Multiply,
+                        SQLNested(Box::new(binary(
+                            *id("b"),
+                            Minus,
+                            SQLNested(Box::new(binary(*id("c"), Plus, SQLLiteralLong(1)))),
+                        ))),
+                    ),
+                    Lt,
+                    SQLUnary {
+                        operator: Plus,
+                        rex: id("d"),
+                    },
+                ),
+            ),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(expected, parse_sql(sql), "{}", sql);
+            match Parser::parse_sql_iterative(format!("SELECT {}", sql)).unwrap() {
+                SQLSelect { projection, .. } => assert_eq!(expected, projection[0], "{}", sql),
+                other => panic!("Expected a SELECT but got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_logical_precedence() {
+        use self::ASTNode::*;
+        use self::SQLOperator::*;
+        let id = |name: &str| SQLIdentifier(name.to_string());
+        let binary = |left: ASTNode, op: SQLOperator, right: ASTNode| SQLBinaryExpr {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        };
+        let not = |rex: ASTNode| SQLUnary {
+            operator: Not,
+            rex: Box::new(rex),
+        };
+        let cases = vec![
+            (
+                "a = 1 AND b = 2 OR c = 3",
+                binary(
+                    binary(
+                        binary(id("a"), Eq, SQLLiteralLong(1)),
+                        And,
+                        binary(id("b"), Eq, SQLLiteralLong(2)),
+                    ),
+                    Or,
+                    binary(id("c"), Eq, SQLLiteralLong(3)),
+                ),
+            ),
+            (
+                "a OR b AND c",
+                binary(id("a"), Or, binary(id("b"), And, id("c"))),
+            ),
+            (
+                "NOT a = 1 AND b",
+                binary(not(binary(id("a"), Eq, SQLLiteralLong(1))), And, id("b")),
+            ),
+            (
+                "a OR NOT NOT b IS NULL",
+                binary(id("a"), Or, not(not(SQLIsNull(Box::new(id("b")))))),
+            ),
+            (
+                "NOT (a OR b) AND c NOT LIKE 'x%'",
+                binary(
+                    not(SQLNested(Box::new(binary(id("a"), Or, id("b"))))),
+                    And,
+                    binary(id("c"), NotLike, SQLLiteralString("x%".to_string())),
+                ),
+            ),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(expected, parse_sql(sql), "{}", sql);
+            match Parser::parse_sql_iterative(format!("SELECT {}", sql)).unwrap() {
+                SQLSelect { projection, .. } => assert_eq!(expected, projection[0], "{}", sql),
+                other => panic!("Expected a SELECT but got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_parentheses() {
+        assert!(Parser::parse_sql(String::from("SELECT (a + b FROM t")).is_err());
+        assert!(Parser::parse_sql_iterative(String::from("SELECT (a + b FROM t")).is_err());
+        let depth = 5000;
+        let sql = format!("SELECT {}1{} FROM t", "(".repeat(depth), ")".repeat(depth));
+        assert!(Parser::parse_sql_iterative(sql).is_ok());
+    }
+
     #[test]
     fn parse_is_null() {
         use self::ASTNode::*;
@@ -746,10 +2527,67 @@ mod tests {
         assert_eq!(SQLIsNotNull(Box::new(SQLIdentifier("a".to_string()))), ast);
     }
 
+    #[test]
+    fn parse_like() {
+        use self::ASTNode::*;
+        use self::SQLOperator::*;
+        let sql = "name LIKE 'a%' AND code NOT ILIKE 'x_' || suffix";
+        let expected = SQLBinaryExpr {
+            left: Box::new(SQLBinaryExpr {
+                left: Box::new(SQLIdentifier("name".to_string())),
+                op: Like,
+                right: Box::new(SQLLiteralString("a%".to_string())),
+            }),
+            op: And,
+            right: Box::new(SQLBinaryExpr {
+                left: Box::new(SQLIdentifier("code".to_string())),
+                op: NotILike,
+                right: Box::new(SQLBinaryExpr {
+                    left: Box::new(SQLLiteralString("x_".to_string())),
+                    op: StringConcat,
+                    right: Box::new(SQLIdentifier("suffix".to_string())),
+                }),
+            }),
+        };
+        assert_eq!(expected, parse_sql(sql));
+        let select = format!("SELECT * FROM t WHERE {}", sql);
+        match Parser::parse_sql_iterative(select).unwrap() {
+            SQLSelect {
+                selection: Some(ref selection),
+                ..
+            } => assert_eq!(expected, **selection),
+            other => panic!("Expected a SELECT with a WHERE clause but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_boolean_and_null_literals() {
+        use self::ASTNode::*;
+        let sql = String::from("flag = TRUE OR false OR NULL IS NULL");
+        let ast = parse_sql(&sql);
+        assert_eq!(
+            SQLBinaryExpr {
+                left: Box::new(SQLBinaryExpr {
+                    left: Box::new(SQLBinaryExpr {
+                        left: Box::new(SQLIdentifier("flag".to_string())),
+                        op: SQLOperator::Eq,
+                        right: Box::new(SQLLiteralBoolean(true)),
+                    }),
+                    op: SQLOperator::Or,
+                    right: Box::new(SQLLiteralBoolean(false)),
+                }),
+                op: SQLOperator::Or,
+                right: Box::new(SQLIsNull(Box::new(SQLLiteralNull))),
+            },
+            ast
+        );
+    }
+
     #[test]
     fn parse_select_order_by() {
         let sql = String::from(
-            "SELECT id, fname, lname FROM customer WHERE id < 5 ORDER BY lname ASC, fname DESC",
+            "SELECT id, fname, lname FROM customer WHERE id < 5 \
+             ORDER BY lname ASC, fname DESC NULLS FIRST, id NULLS LAST",
         );
         let ast = parse_sql(&sql);
         match ast {
@@ -759,10 +2597,17 @@ mod tests {
                         ASTNode::SQLOrderBy {
                             expr: Box::new(ASTNode::SQLIdentifier("lname".to_string())),
                             asc: true,
+                            nulls_first: None,
                         },
                         ASTNode::SQLOrderBy {
                             expr: Box::new(ASTNode::SQLIdentifier("fname".to_string())),
                             asc: false,
+                            nulls_first: Some(true),
+                        },
+                        ASTNode::SQLOrderBy {
+                            expr: Box::new(ASTNode::SQLIdentifier("id".to_string())),
+                            asc: true,
+                            nulls_first: Some(false),
                         },
                     ]),
                     order_by
@@ -770,6 +2615,9 @@ mod tests {
             }
             _ => assert!(false),
         }
+
+        let sql = "SELECT id FROM customer ORDER BY id NULLS";
+        assert!(Parser::parse_sql(sql.to_string()).is_err());
     }
 
     #[test]
@@ -790,6 +2638,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_select_having() {
+        let sql = String::from(
+            "SELECT lname, COUNT(*) FROM customer GROUP BY lname HAVING COUNT(*) > 1 LIMIT 5",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                group_by,
+                having,
+                limit,
+                ..
+            } => {
+                assert_eq!(Some(vec![ASTNode::SQLIdentifier("lname".to_string())]), group_by);
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLBinaryExpr {
+                        left: Box::new(ASTNode::SQLFunction {
+                            id: "COUNT".to_string(),
+                            args: vec![ASTNode::SQLWildcard],
+                            distinct: false,
+                        }),
+                        op: SQLOperator::Gt,
+                        right: Box::new(ASTNode::SQLLiteralLong(1)),
+                    })),
+                    having
+                );
+                assert_eq!(Some(Box::new(ASTNode::SQLLiteralLong(5))), limit);
+            }
+            _ => assert!(false),
+        }
+    }
+
     #[test]
     fn parse_limit_accepts_all() {
         let sql = String::from("SELECT id, fname, lname FROM customer WHERE id = 1 LIMIT ALL");
@@ -842,6 +2721,7 @@ mod tests {
                 file_type,
                 header_row,
                 location,
+                ..
             } => {
                 assert_eq!("uk_cities", name);
                 assert_eq!(3, columns.len());
@@ -886,6 +2766,7 @@ mod tests {
                 file_type,
                 header_row,
                 location,
+                ..
             } => {
                 assert_eq!("uk_cities", name);
                 assert_eq!(3, columns.len());
@@ -923,41 +2804,1016 @@ mod tests {
     }
 
     #[test]
-    fn parse_scalar_function_in_projection() {
-        let sql = String::from("SELECT sqrt(id) FROM foo");
-        let ast = parse_sql(&sql);
-        if let ASTNode::SQLSelect { projection, .. } = ast {
-            assert_eq!(
-                vec![ASTNode::SQLFunction {
-                    id: String::from("sqrt"),
-                    args: vec![ASTNode::SQLIdentifier(String::from("id"))],
-                }],
-                projection
-            );
-        } else {
-            assert!(false);
+    fn parse_create_external_table_options() {
+        let sql = String::from(
+            "CREATE EXTERNAL TABLE events STORED AS PARQUET \
+             LOCATION 's3://bucket/events' OPTIONS (profile 'prod', Region 'eu-west-1')",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLCreateTable { options, .. } => assert_eq!(
+                vec![
+                    ("profile".to_string(), "prod".to_string()),
+                    ("region".to_string(), "eu-west-1".to_string()),
+                ],
+                options
+            ),
+            _ => panic!(),
         }
+        let sql = "CREATE EXTERNAL TABLE t STORED AS CSV LOCATION 'a' OPTIONS (profile)";
+        assert!(Parser::parse_sql(sql.to_string()).is_err());
     }
 
     #[test]
-    fn parse_aggregate_with_group_by() {
-        let sql = String::from("SELECT a, COUNT(1), MIN(b), MAX(b) FROM foo GROUP BY a");
-        let _ast = parse_sql(&sql);
-        //TODO: assertions
+    fn parse_create_external_table_binary_column() {
+        let sql = String::from(
+            "CREATE EXTERNAL TABLE blobs (id INT, notes TEXT, payload BYTEA) \
+             STORED AS CSV \
+             LOCATION '/mnt/ssd/blobs.csv'",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLCreateTable { columns, .. } => {
+                assert_eq!(SQLType::Utf8(usize::max_value()), columns[1].data_type);
+                assert_eq!(SQLType::Binary, columns[2].data_type);
+            }
+            _ => assert!(false),
+        }
     }
 
     #[test]
-    fn parse_select_version() {
-        let sql = "SELECT @@version";
+    fn parse_copy_to() {
+        let sql = String::from(
+            "COPY (SELECT city FROM uk_cities WHERE lat > 52) TO '/tmp/cities' \
+             STORED AS PARQUET OPTIONS (max_file_size '1048576')",
+        );
         match parse_sql(&sql) {
-            ASTNode::SQLSelect { ref projection, .. } => {
+            ASTNode::SQLCopyTo {
+                query,
+                location,
+                file_type,
+                options,
+            } => {
+                match *query {
+                    ASTNode::SQLSelect { .. } => {}
+                    _ => panic!(),
+                }
+                assert_eq!("/tmp/cities", location);
+                assert_eq!(FileType::Parquet, file_type);
                 assert_eq!(
-                    projection[0],
-                    ASTNode::SQLIdentifier("@@version".to_string())
+                    vec![("max_file_size".to_string(), "1048576".to_string())],
+                    options
                 );
             }
             _ => panic!(),
         }
+
+        // the format can come from the extension of the file
+        match parse_sql("COPY (SELECT city FROM uk_cities) TO 'cities.CSV'") {
+            ASTNode::SQLCopyTo { file_type, .. } => assert_eq!(FileType::CSV, file_type),
+            _ => panic!(),
+        }
+        match parse_sql("COPY (SELECT city FROM uk_cities) TO 'cities.json.gz'") {
+            ASTNode::SQLCopyTo { file_type, .. } => assert_eq!(FileType::NdJson, file_type),
+            _ => panic!(),
+        }
+        match parse_sql("COPY (SELECT city FROM uk_cities) TO 'cities' STORED AS NDJSON") {
+            ASTNode::SQLCopyTo { file_type, .. } => assert_eq!(FileType::NdJson, file_type),
+            _ => panic!(),
+        }
+        let sql = "COPY (SELECT city FROM uk_cities) TO 'cities'";
+        assert!(Parser::parse_sql(sql.to_string()).is_err());
+        let sql = "COPY uk_cities TO 'cities.csv'";
+        assert!(Parser::parse_sql(sql.to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_create_temporary_table_as() {
+        let sql = String::from("CREATE TEMPORARY TABLE big_cities AS SELECT city FROM uk_cities");
+        match parse_sql(&sql) {
+            ASTNode::SQLCreateTableAs {
+                name,
+                temporary,
+                query,
+            } => {
+                assert_eq!("big_cities", name);
+                assert_eq!(true, temporary);
+                match *query {
+                    ASTNode::SQLSelect { .. } => {}
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_create_table_as() {
+        let sql = String::from("CREATE TABLE t AS SELECT 1");
+        match parse_sql(&sql) {
+            ASTNode::SQLCreateTableAs { temporary, .. } => assert_eq!(false, temporary),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_create_function() {
+        let sql = String::from(
+            "CREATE FUNCTION with_tax(amount DOUBLE, rate DOUBLE) RETURNS DOUBLE \
+             AS 'amount * (1 + rate)'",
+        );
+        assert_eq!(
+            ASTNode::SQLCreateFunction {
+                name: String::from("with_tax"),
+                args: vec![
+                    (String::from("amount"), SQLType::Double64),
+                    (String::from("rate"), SQLType::Double64),
+                ],
+                return_type: SQLType::Double64,
+                body: String::from("amount * (1 + rate)"),
+            },
+            parse_sql(&sql)
+        );
+
+        let sql = String::from("CREATE FUNCTION answer() RETURNS INT AS '42'");
+        match parse_sql(&sql) {
+            ASTNode::SQLCreateFunction { args, .. } => assert!(args.is_empty()),
+            _ => panic!(),
+        }
+
+        let sql = String::from("CREATE FUNCTION f(x INT) AS 'x'");
+        assert!(Parser::parse_sql(sql).is_err());
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let sql = String::from("CREATE FUNCTION f(x INT)\n  AS 'x'");
+        assert_eq!(
+            "Parser error: Expected RETURNS after CREATE FUNCTION f, found \
+             Some(Keyword(\"AS\")) at line 2, column 3",
+            Parser::parse_sql(sql).unwrap_err().to_string()
+        );
+
+        let sql = String::from("CREATE FUNCTION f(x INT)");
+        assert_eq!(
+            "Parser error: Expected RETURNS after CREATE FUNCTION f, found None at end of input",
+            Parser::parse_sql(sql).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn parse_statements() {
+        let sql = "CREATE TABLE t AS SELECT 1;\n SELECT 'a;b' FROM t;; DROP TABLE t;";
+        let tokens = Tokenizer::new(sql).tokenize_with_spans().unwrap();
+        let statements = Parser::with_spans(tokens).parse_statements().unwrap();
+        assert_eq!(3, statements.len());
+        match statements[1] {
+            ASTNode::SQLSelect { ref projection, .. } => assert_eq!(
+                vec![ASTNode::SQLLiteralString("a;b".to_string())],
+                *projection
+            ),
+            _ => panic!(),
+        }
+        assert_eq!(
+            ASTNode::SQLDropTable {
+                name: "t".to_string(),
+                if_exists: false,
+            },
+            statements[2]
+        );
+
+        let tokens = Tokenizer::new(" ; ;").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse_statements().unwrap().is_empty());
+
+        // an ORDER BY expression can end the statement
+        let tokens = Tokenizer::new("SELECT a FROM t ORDER BY a; SELECT b FROM t ORDER BY b DESC;")
+            .tokenize()
+            .unwrap();
+        assert_eq!(2, Parser::new(tokens).parse_statements().unwrap().len());
+
+        let tokens = Tokenizer::new("DROP TABLE t u").tokenize_with_spans().unwrap();
+        assert_eq!(
+            "Parser error: Expected ';' after statement, found Identifier(\"u\") at line 1, \
+             column 14",
+            Parser::with_spans(tokens)
+                .parse_statements()
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn parse_statements_with_ranges() {
+        let sql = "SELECT 1 -- done; really\n; /* a; b */ SELECT 2;";
+        let tokens = Tokenizer::new(sql).tokenize_with_spans().unwrap();
+        let statements = Parser::with_spans(tokens)
+            .parse_statements_with_ranges()
+            .unwrap();
+        assert_eq!(
+            vec!["SELECT 1", "SELECT 2"],
+            statements
+                .iter()
+                .map(|(_, range)| &sql[range.clone()])
+                .collect::<Vec<&str>>()
+        );
+
+        let sql = "SELECT `a;b`, 'it\\'s;' FROM t; SELECT 2";
+        let tokens = Tokenizer::new(sql)
+            .with_dialect(Dialect::MySql)
+            .tokenize_with_spans()
+            .unwrap();
+        let statements = Parser::with_spans(tokens)
+            .parse_statements_with_ranges()
+            .unwrap();
+        assert_eq!(2, statements.len());
+        assert_eq!("SELECT `a;b`, 'it\\'s;' FROM t", &sql[statements[0].1.clone()]);
+
+        let sql = "SELECT [a;b] FROM t";
+        let tokens = Tokenizer::new(sql)
+            .with_dialect(Dialect::MsSql)
+            .tokenize_with_spans()
+            .unwrap();
+        assert_eq!(
+            1,
+            Parser::with_spans(tokens)
+                .parse_statements_with_ranges()
+                .unwrap()
+                .len()
+        );
+
+        let tokens = Tokenizer::new("SELECT 1").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse_statements_with_ranges().is_err());
+    }
+
+    #[test]
+    fn parse_drop_table() {
+        assert_eq!(
+            ASTNode::SQLDropTable {
+                name: "t".to_string(),
+                if_exists: false,
+            },
+            parse_sql(&String::from("DROP TABLE t"))
+        );
+        assert_eq!(
+            ASTNode::SQLDropTable {
+                name: "t".to_string(),
+                if_exists: true,
+            },
+            parse_sql(&String::from("DROP TABLE IF EXISTS t"))
+        );
+    }
+
+    #[test]
+    fn parse_alter_table_rename() {
+        assert_eq!(
+            ASTNode::SQLAlterTableRename {
+                name: "t".to_string(),
+                new_name: "u".to_string(),
+            },
+            parse_sql(&String::from("ALTER TABLE t RENAME TO u"))
+        );
+        assert!(Parser::parse_sql(String::from("ALTER TABLE t ADD COLUMN c INT")).is_err());
+    }
+
+    #[test]
+    fn parse_json_operators() {
+        let sql = String::from("SELECT doc -> 'tags' ->> 0 FROM events");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { projection, .. } => match projection[0] {
+                ASTNode::SQLFunction { ref id, ref args, .. } => {
+                    assert_eq!("json_extract_string", id);
+                    assert_eq!(ASTNode::SQLLiteralString("[0]".to_string()), args[1]);
+                    match args[0] {
+                        ASTNode::SQLFunction { ref id, ref args, .. } => {
+                            assert_eq!("json_extract", id);
+                            assert_eq!(
+                                ASTNode::SQLLiteralString("[\"tags\"]".to_string()),
+                                args[1]
+                            );
+                        }
+                        _ => panic!(),
+                    }
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_explain() {
+        match parse_sql("EXPLAIN (ANALYZE, FORMAT JSON) SELECT id FROM customer") {
+            ASTNode::SQLExplain {
+                analyze,
+                format,
+                statement,
+            } => {
+                assert!(analyze);
+                assert_eq!(SQLExplainFormat::Json, format);
+                match *statement {
+                    ASTNode::SQLSelect { .. } => {}
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+
+        match parse_sql("EXPLAIN SELECT id FROM customer") {
+            ASTNode::SQLExplain {
+                analyze, format, ..
+            } => {
+                assert!(!analyze);
+                assert_eq!(SQLExplainFormat::Text, format);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_describe() {
+        let sql = String::from("DESCRIBE SELECT id FROM customer");
+        match parse_sql(&sql) {
+            ASTNode::SQLDescribe(query) => match *query {
+                ASTNode::SQLSelect { .. } => {}
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_scalar_function_in_projection() {
+        let sql = String::from("SELECT sqrt(id) FROM foo");
+        let ast = parse_sql(&sql);
+        if let ASTNode::SQLSelect { projection, .. } = ast {
+            assert_eq!(
+                vec![ASTNode::SQLFunction {
+                    id: String::from("sqrt"),
+                    args: vec![ASTNode::SQLIdentifier(String::from("id"))],
+                    distinct: false,
+                }],
+                projection
+            );
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn parse_time_window_in_group_by() {
+        let sql = String::from(
+            "SELECT window(ts, '1 minute'), COUNT(1) FROM foo GROUP BY window(ts, '1 minute')",
+        );
+        let ast = parse_sql(&sql);
+        let window = ASTNode::SQLFunction {
+            id: String::from("window"),
+            args: vec![
+                ASTNode::SQLIdentifier(String::from("ts")),
+                ASTNode::SQLLiteralString(String::from("1 minute")),
+            ],
+            distinct: false,
+        };
+        if let ASTNode::SQLSelect { group_by, .. } = ast {
+            assert_eq!(Some(vec![window]), group_by);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn parse_aggregate_with_group_by() {
+        let sql = String::from("SELECT a, COUNT(1), MIN(b), MAX(b) FROM foo GROUP BY a");
+        let _ast = parse_sql(&sql);
+        //TODO: assertions
+    }
+
+    #[test]
+    fn parse_select_version() {
+        let sql = "SELECT @@version";
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { ref projection, .. } => {
+                assert_eq!(
+                    projection[0],
+                    ASTNode::SQLIdentifier("@@version".to_string())
+                );
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_select_table_sample() {
+        let sql = String::from("SELECT id FROM customer TABLESAMPLE SYSTEM (12.5) REPEATABLE (42)");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { relation, .. } => assert_eq!(
+                Some(Box::new(ASTNode::SQLTableSample {
+                    relation: Box::new(ASTNode::SQLIdentifier("customer".to_string())),
+                    method: SQLSampleMethod::System,
+                    percent: 12.5,
+                    seed: Some(42),
+                })),
+                relation
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_select_table_sample_default_method() {
+        let sql = String::from("SELECT id FROM customer TABLESAMPLE (10) WHERE id > 1");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                relation,
+                selection,
+                ..
+            } => {
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLTableSample {
+                        relation: Box::new(ASTNode::SQLIdentifier("customer".to_string())),
+                        method: SQLSampleMethod::Bernoulli,
+                        percent: 10.0,
+                        seed: None,
+                    })),
+                    relation
+                );
+                assert!(selection.is_some());
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_select_table_function() {
+        let sql = String::from("SELECT * FROM generate_series(1, 10, 3) WHERE x > 1");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { relation, .. } => assert_eq!(
+                Some(Box::new(ASTNode::SQLFunction {
+                    id: "generate_series".to_string(),
+                    args: vec![
+                        ASTNode::SQLLiteralLong(1),
+                        ASTNode::SQLLiteralLong(10),
+                        ASTNode::SQLLiteralLong(3),
+                    ],
+                    distinct: false,
+                })),
+                relation
+            ),
+            _ => panic!(),
+        }
+
+        // RANGE is a keyword but can still name the table function
+        let sql = String::from("SELECT * FROM range(0, 5)");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { relation, .. } => assert_eq!(
+                Some(Box::new(ASTNode::SQLFunction {
+                    id: "range".to_string(),
+                    args: vec![ASTNode::SQLLiteralLong(0), ASTNode::SQLLiteralLong(5)],
+                    distinct: false,
+                })),
+                relation
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_parameters() {
+        let sql = String::from("SELECT a FROM t WHERE b = ? AND c > ? OR d = $1");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { selection, .. } => assert_eq!(
+                ASTNode::SQLBinaryExpr {
+                    left: Box::new(ASTNode::SQLBinaryExpr {
+                        left: Box::new(ASTNode::SQLBinaryExpr {
+                            left: Box::new(ASTNode::SQLIdentifier(String::from("b"))),
+                            op: SQLOperator::Eq,
+                            right: Box::new(ASTNode::SQLParameter(1)),
+                        }),
+                        op: SQLOperator::And,
+                        right: Box::new(ASTNode::SQLBinaryExpr {
+                            left: Box::new(ASTNode::SQLIdentifier(String::from("c"))),
+                            op: SQLOperator::Gt,
+                            right: Box::new(ASTNode::SQLParameter(2)),
+                        }),
+                    }),
+                    op: SQLOperator::Or,
+                    right: Box::new(ASTNode::SQLBinaryExpr {
+                        left: Box::new(ASTNode::SQLIdentifier(String::from("d"))),
+                        op: SQLOperator::Eq,
+                        right: Box::new(ASTNode::SQLParameter(1)),
+                    }),
+                },
+                *selection.unwrap()
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_select_distinct() {
+        let sql = String::from("SELECT DISTINCT a, COUNT(DISTINCT b) FROM t GROUP BY a");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                distinct,
+                projection,
+                ..
+            } => {
+                assert!(distinct);
+                assert_eq!(
+                    ASTNode::SQLFunction {
+                        id: "COUNT".to_string(),
+                        args: vec![ASTNode::SQLIdentifier("b".to_string())],
+                        distinct: true,
+                    },
+                    projection[1]
+                );
+            }
+            _ => panic!(),
+        }
+        let window = String::from("SELECT COUNT(DISTINCT b) OVER () FROM t");
+        assert!(Parser::parse_sql(window).is_err());
+    }
+
+    #[test]
+    fn parse_select_qualify() {
+        let sql = String::from(
+            "SELECT id FROM customer WHERE id > 1 \
+             QUALIFY ROW_NUMBER() OVER (PARTITION BY state) = 1 ORDER BY id",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect {
+                qualify, order_by, ..
+            } => {
+                assert_eq!(
+                    Some(Box::new(ASTNode::SQLBinaryExpr {
+                        left: Box::new(ASTNode::SQLWindowFunction {
+                            id: "ROW_NUMBER".to_string(),
+                            args: vec![],
+                            partition_by: vec![ASTNode::SQLIdentifier("state".to_string())],
+                            order_by: vec![],
+                            frame: None,
+                            ignore_nulls: false,
+                        }),
+                        op: SQLOperator::Eq,
+                        right: Box::new(ASTNode::SQLLiteralLong(1)),
+                    })),
+                    qualify
+                );
+                assert!(order_by.is_some());
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_select_table_snapshot() {
+        let sql = String::from(
+            "SELECT id FROM customer FOR SYSTEM_TIME AS OF '2018-01-01' TABLESAMPLE (10)",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { relation, .. } => assert_eq!(
+                Some(Box::new(ASTNode::SQLTableSample {
+                    relation: Box::new(ASTNode::SQLTableSnapshot {
+                        relation: Box::new(ASTNode::SQLIdentifier("customer".to_string())),
+                        snapshot: SQLSnapshot::Timestamp(Box::new(ASTNode::SQLLiteralString(
+                            "2018-01-01".to_string()
+                        ))),
+                    }),
+                    method: SQLSampleMethod::Bernoulli,
+                    percent: 10.0,
+                    seed: None,
+                })),
+                relation
+            ),
+            _ => panic!(),
+        }
+
+        let sql = String::from("SELECT id FROM customer AT (version => 3) WHERE id > 1");
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { relation, .. } => assert_eq!(
+                Some(Box::new(ASTNode::SQLTableSnapshot {
+                    relation: Box::new(ASTNode::SQLIdentifier("customer".to_string())),
+                    snapshot: SQLSnapshot::Version(3),
+                })),
+                relation
+            ),
+            _ => panic!(),
+        }
+
+        assert!(Parser::parse_sql("SELECT id FROM customer AT (VERSION 3)".to_string()).is_err());
+        assert!(Parser::parse_sql("SELECT id FROM customer AT (BRANCH => 3)".to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_select_join() {
+        let sql = String::from(
+            "SELECT id, amount FROM people JOIN orders ON people.id IS NOT DISTINCT FROM orders.person_id",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { relation, .. } => assert_eq!(
+                Some(Box::new(ASTNode::SQLJoin {
+                    left: Box::new(ASTNode::SQLIdentifier("people".to_string())),
+                    right: Box::new(ASTNode::SQLIdentifier("orders".to_string())),
+                    join_type: SQLJoinType::Inner,
+                    on: Box::new(ASTNode::SQLBinaryExpr {
+                        left: Box::new(ASTNode::SQLCompoundIdentifier(vec![
+                            "people".to_string(),
+                            "id".to_string(),
+                        ])),
+                        op: SQLOperator::IsNotDistinctFrom,
+                        right: Box::new(ASTNode::SQLCompoundIdentifier(vec![
+                            "orders".to_string(),
+                            "person_id".to_string(),
+                        ])),
+                    }),
+                })),
+                relation
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_select_semi_and_anti_join() {
+        for &(sql, ref expected) in &[
+            (
+                "SELECT id FROM people LEFT SEMI JOIN orders ON id = person_id",
+                SQLJoinType::LeftSemi,
+            ),
+            (
+                "SELECT id FROM people LEFT ANTI JOIN orders ON id = person_id",
+                SQLJoinType::LeftAnti,
+            ),
+        ] {
+            match parse_sql(sql) {
+                ASTNode::SQLSelect { relation, .. } => match *relation.unwrap() {
+                    ASTNode::SQLJoin { join_type, .. } => assert_eq!(*expected, join_type),
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_select_join_requires_on() {
+        let sql = String::from("SELECT id FROM people INNER JOIN orders WHERE id > 1");
+        let mut parser = Parser::new(Tokenizer::new(&sql).tokenize().unwrap());
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn parse_multi_char_operators() {
+        let sql = String::from("SELECT a || b + 1 = c::INT FROM t");
+        let ident = |id: &str| Box::new(ASTNode::SQLIdentifier(id.to_string()));
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { projection, .. } => assert_eq!(
+                ASTNode::SQLBinaryExpr {
+                    left: Box::new(ASTNode::SQLBinaryExpr {
+                        left: ident("a"),
+                        op: SQLOperator::StringConcat,
+                        right: Box::new(ASTNode::SQLBinaryExpr {
+                            left: ident("b"),
+                            op: SQLOperator::Plus,
+                            right: Box::new(ASTNode::SQLLiteralLong(1)),
+                        }),
+                    }),
+                    op: SQLOperator::Eq,
+                    right: Box::new(ASTNode::SQLCast {
+                        expr: ident("c"),
+                        data_type: SQLType::Int32,
+                    }),
+                },
+                projection[0]
+            ),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_bitwise_operators() {
+        let sql = String::from("SELECT a | b ^ c & d << 1 + 2 = 0 FROM t");
+        let ident = |id: &str| Box::new(ASTNode::SQLIdentifier(id.to_string()));
+        let binary = |left, op, right| Box::new(ASTNode::SQLBinaryExpr { left, op, right });
+        let shift = binary(
+            ident("d"),
+            SQLOperator::ShiftLeft,
+            binary(
+                Box::new(ASTNode::SQLLiteralLong(1)),
+                SQLOperator::Plus,
+                Box::new(ASTNode::SQLLiteralLong(2)),
+            ),
+        );
+        let expected = binary(
+            binary(
+                ident("a"),
+                SQLOperator::BitwiseOr,
+                binary(
+                    ident("b"),
+                    SQLOperator::BitwiseXor,
+                    binary(ident("c"), SQLOperator::BitwiseAnd, shift),
+                ),
+            ),
+            SQLOperator::Eq,
+            Box::new(ASTNode::SQLLiteralLong(0)),
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { projection, .. } => assert_eq!(*expected, projection[0]),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn parse_iterative_matches_recursive() {
+        for sql in &[
+            "SELECT a, b FROM t WHERE a > 1 AND b IS NOT NULL OR c = 'x' ORDER BY a DESC LIMIT 5",
+            "SELECT sqrt(a * 2 + b % 3), CAST(a AS DOUBLE), doc -> 'k' ->> 0 FROM t",
+            "SELECT id FROM people INNER JOIN orders ON id IS NOT DISTINCT FROM person_id",
+            "EXPLAIN (ANALYZE) SELECT f(g(1, 2), h(3)) FROM t",
+            "DESCRIBE SELECT a - b - c FROM t",
+            "SELECT DISTINCT a || b + 1 != c::INT % 2, COUNT(DISTINCT a) FROM t",
+            "SELECT a | b ^ c & 1 << 2 + 3 >> d FROM t WHERE a & 4 = 4",
+            "SELECT a FROM t WHERE NOT a = 1 AND NOT (b LIKE 'x' OR c IS NOT NULL)",
+        ] {
+            assert_eq!(
+                Parser::parse_sql(sql.to_string()).unwrap(),
+                Parser::parse_sql_iterative(sql.to_string()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn parse_iterative_deeply_nested() {
+        let depth = 5000;
+        let sql = format!("SELECT {}1{} FROM t", "abs(".repeat(depth), ")".repeat(depth));
+        match Parser::parse_sql_iterative(sql).unwrap() {
+            ASTNode::SQLSelect { projection, .. } => {
+                let mut expr = &projection[0];
+                let mut n = 0;
+                while let ASTNode::SQLFunction { ref args, .. } = *expr {
+                    expr = &args[0];
+                    n += 1;
+                }
+                assert_eq!(depth, n);
+            }
+            _ => panic!(),
+        }
+
+        assert!(Parser::parse_sql_iterative(String::from("SELECT abs(1, FROM t")).is_err());
+
+        let nested = |n: usize| format!("{}SELECT a FROM t", "SELECT a FROM ".repeat(n));
+        assert!(Parser::parse_sql_iterative(nested(MAX_STATEMENT_DEPTH - 1)).is_ok());
+        assert!(Parser::parse_sql_iterative(nested(MAX_STATEMENT_DEPTH)).is_err());
+    }
+
+    #[test]
+    fn parse_window_function() {
+        let sql = String::from(
+            "SELECT SUM(amount) OVER (PARTITION BY region ORDER BY ts DESC \
+             RANGE BETWEEN '1 day' PRECEDING AND CURRENT ROW EXCLUDE GROUP) FROM sales",
+        );
+        match parse_sql(&sql) {
+            ASTNode::SQLSelect { projection, .. } => assert_eq!(
+                ASTNode::SQLWindowFunction {
+                    id: "SUM".to_string(),
+                    args: vec![ASTNode::SQLIdentifier("amount".to_string())],
+                    partition_by: vec![ASTNode::SQLIdentifier("region".to_string())],
+                    order_by: vec![ASTNode::SQLOrderBy {
+                        expr: Box::new(ASTNode::SQLIdentifier("ts".to_string())),
+                        asc: false,
+                        nulls_first: None,
+                    }],
+                    frame: Some(SQLWindowFrame {
+                        units: SQLWindowFrameUnits::Range,
+                        start: SQLWindowFrameBound::Preceding(Box::new(
+                            ASTNode::SQLLiteralString("1 day".to_string())
+                        )),
+                        end: SQLWindowFrameBound::CurrentRow,
+                        exclusion: SQLWindowFrameExclusion::Group,
+                    }),
+                    ignore_nulls: false,
+                },
+                projection[0]
+            ),
+            _ => assert!(false),
+        }
+
+        // a frame with only a start bound ends at the current row
+        let sql = "SELECT ROW_NUMBER() OVER (), COUNT(x) OVER (ORDER BY y GROUPS 2 FOLLOWING) \
+                   FROM t";
+        let ast = parse_sql(sql);
+        assert_eq!(ast, Parser::parse_sql_iterative(sql.to_string()).unwrap());
+        match ast {
+            ASTNode::SQLSelect { projection, .. } => {
+                match projection[0] {
+                    ASTNode::SQLWindowFunction {
+                        ref partition_by,
+                        ref frame,
+                        ..
+                    } => assert!(partition_by.is_empty() && frame.is_none()),
+                    _ => assert!(false),
+                }
+                match projection[1] {
+                    ASTNode::SQLWindowFunction {
+                        frame: Some(ref frame),
+                        ..
+                    } => {
+                        assert_eq!(SQLWindowFrameUnits::Groups, frame.units);
+                        assert_eq!(
+                            SQLWindowFrameBound::Following(Box::new(ASTNode::SQLLiteralLong(2))),
+                            frame.start
+                        );
+                        assert_eq!(SQLWindowFrameBound::CurrentRow, frame.end);
+                    }
+                    _ => assert!(false),
+                }
+            }
+            _ => assert!(false),
+        }
+
+        let sql = "SELECT SUM(x) OVER (ROWS BETWEEN 1 PRECEDING) FROM t";
+        assert!(Parser::parse_sql(sql.to_string()).is_err());
+
+        let sql = "SELECT FIRST_VALUE(x) IGNORE NULLS OVER (ORDER BY y), \
+                   LAST_VALUE(x) RESPECT NULLS OVER (ORDER BY y) FROM t";
+        let ast = parse_sql(sql);
+        assert_eq!(ast, Parser::parse_sql_iterative(sql.to_string()).unwrap());
+        match ast {
+            ASTNode::SQLSelect { projection, .. } => {
+                let ignore_nulls: Vec<bool> = projection
+                    .iter()
+                    .map(|p| match *p {
+                        ASTNode::SQLWindowFunction { ignore_nulls, .. } => ignore_nulls,
+                        _ => panic!(),
+                    })
+                    .collect();
+                assert_eq!(vec![true, false], ignore_nulls);
+            }
+            _ => assert!(false),
+        }
+        let sql = "SELECT FIRST_VALUE(x) IGNORE NULLS FROM t";
+        assert!(Parser::parse_sql(sql.to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_select_lateral_join() {
+        let sql = "SELECT name, amount FROM people CROSS JOIN LATERAL \
+                   (SELECT amount FROM orders WHERE person_id = id ORDER BY amount DESC LIMIT 1)";
+        let ast = parse_sql(sql);
+        assert_eq!(ast, Parser::parse_sql_iterative(sql.to_string()).unwrap());
+        match ast {
+            ASTNode::SQLSelect {
+                relation: Some(ref relation),
+                ..
+            } => match **relation {
+                ASTNode::SQLLateralJoin {
+                    ref left,
+                    ref subquery,
+                    left_outer,
+                    ref on,
+                } => {
+                    assert_eq!(ASTNode::SQLIdentifier("people".to_string()), **left);
+                    match **subquery {
+                        ASTNode::SQLSelect { ref limit, .. } => assert!(limit.is_some()),
+                        _ => panic!(),
+                    }
+                    assert!(!left_outer && on.is_none());
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+
+        let sql = "SELECT name FROM people LEFT JOIN LATERAL (SELECT amount FROM orders) \
+                   ON person_id = id WHERE id > 1";
+        match parse_sql(sql) {
+            ASTNode::SQLSelect {
+                relation: Some(ref relation),
+                ref selection,
+                ..
+            } => {
+                match **relation {
+                    ASTNode::SQLLateralJoin {
+                        left_outer,
+                        ref on,
+                        ..
+                    } => assert!(left_outer && on.is_some()),
+                    _ => panic!(),
+                }
+                assert!(selection.is_some());
+            }
+            _ => panic!(),
+        }
+
+        for sql in &[
+            "SELECT name FROM people JOIN LATERAL (SELECT amount FROM orders)",
+            "SELECT name FROM people CROSS JOIN LATERAL orders",
+            "SELECT name FROM people CROSS JOIN LATERAL (SELECT amount FROM orders",
+            "SELECT name FROM people)",
+        ] {
+            assert!(Parser::parse_sql(sql.to_string()).is_err(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn parse_quantified_comparison() {
+        let sql = "SELECT id FROM orders WHERE amount > ALL (SELECT amount FROM orders) AND id = 1";
+        let ast = parse_sql(sql);
+        assert_eq!(ast, Parser::parse_sql_iterative(sql.to_string()).unwrap());
+        match ast {
+            ASTNode::SQLSelect {
+                selection: Some(ref selection),
+                ..
+            } => match **selection {
+                ASTNode::SQLBinaryExpr {
+                    ref left,
+                    op: SQLOperator::And,
+                    ..
+                } => match **left {
+                    ASTNode::SQLQuantifiedComparison {
+                        ref expr,
+                        op: SQLOperator::Gt,
+                        all: true,
+                        ref subquery,
+                    } => {
+                        assert_eq!(ASTNode::SQLIdentifier("amount".to_string()), **expr);
+                        match **subquery {
+                            ASTNode::SQLSelect { .. } => {}
+                            _ => panic!(),
+                        }
+                    }
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+
+        for sql in &[
+            "SELECT id FROM orders WHERE id = ANY (SELECT id FROM orders)",
+            "SELECT id FROM orders WHERE id <> SOME (SELECT id FROM orders)",
+        ] {
+            match parse_sql(sql) {
+                ASTNode::SQLSelect {
+                    selection: Some(ref selection),
+                    ..
+                } => match **selection {
+                    ASTNode::SQLQuantifiedComparison { all: false, .. } => {}
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            }
+        }
+
+        assert!(Parser::parse_sql("SELECT id FROM t WHERE id = ANY (1, 2)".to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_typed_literals() {
+        let literal = |literal_type, value: &str| ASTNode::SQLTypedLiteral {
+            literal_type,
+            value: value.to_string(),
+        };
+        let sql = "SELECT DATE '2024-01-01', time '12:30:00', TIMESTAMP '2024-01-01 12:30:00', \
+                   INTERVAL '3' DAY, INTERVAL '90 minutes', date FROM t";
+        match parse_sql(sql) {
+            ASTNode::SQLSelect { projection, .. } => assert_eq!(
+                vec![
+                    literal(SQLTypedLiteralType::Date, "2024-01-01"),
+                    literal(SQLTypedLiteralType::Time, "12:30:00"),
+                    literal(SQLTypedLiteralType::Timestamp, "2024-01-01 12:30:00"),
+                    literal(SQLTypedLiteralType::Interval(SQLIntervalUnit::Day), "3"),
+                    literal(SQLTypedLiteralType::Interval(SQLIntervalUnit::Minute), "90"),
+                    ASTNode::SQLIdentifier("date".to_string()),
+                ],
+                projection
+            ),
+            _ => assert!(false),
+        }
+
+        assert!(Parser::parse_sql("SELECT INTERVAL '3'".to_string()).is_err());
+        assert!(Parser::parse_sql("SELECT INTERVAL '3 fortnights'".to_string()).is_err());
+    }
+
+    #[test]
+    fn parse_select_hints() {
+        let sql = "SELECT /*+ BROADCAST(b, c), join_order(c, a) */ * FROM a JOIN b ON a.id = b.id";
+        match parse_sql(sql) {
+            ASTNode::SQLSelect { hints, .. } => assert_eq!(
+                vec![
+                    SQLHint::Broadcast(vec!["b".to_string(), "c".to_string()]),
+                    SQLHint::JoinOrder(vec!["c".to_string(), "a".to_string()]),
+                ],
+                hints
+            ),
+            _ => assert!(false),
+        }
+
+        // a hint anywhere other than straight after SELECT is a comment
+        match parse_sql("SELECT a /*+ BROADCAST(b) */ FROM a") {
+            ASTNode::SQLSelect { hints, .. } => assert!(hints.is_empty()),
+            _ => assert!(false),
+        }
+
+        assert!(Parser::parse_sql("SELECT /*+ SHUFFLE(a) */ * FROM a".to_string()).is_err());
+        assert!(Parser::parse_sql("SELECT /*+ BROADCAST(a */ * FROM a".to_string()).is_err());
     }
 
     fn parse_sql(sql: &str) -> ASTNode {
@@ -14,12 +14,21 @@
 
 //! SQL Query Planner (produces logical plan from SQL AST)
 
+use std::cell::RefCell;
 use std::collections::HashSet;
+use std::ptr;
 use std::rc::Rc;
 use std::string::String;
 
+use super::datasources::common::{DefaultRecordBatch, RecordBatch};
+use super::datasources::infer::ValueFormats;
+use super::errors::*;
+use super::functions::datetime::{parse_interval, parse_timestamp};
+use super::functions::window::WindowFunction;
 use super::logical::*;
+use super::relations::time_window::{time_window_args, TIME_WINDOW_FUNCTION};
 use super::sqlast::*;
+use super::sqltokenizer::IdentifierCasing;
 use super::types::*;
 
 use arrow::datatypes::*;
@@ -27,23 +36,135 @@ use arrow::datatypes::*;
 pub trait SchemaProvider {
     fn get_table_meta(&self, name: &str) -> Option<Rc<Schema>>;
     fn get_function_meta(&self, name: &str) -> Option<Rc<FunctionMeta>>;
+
+    /// Get the plan that reads a table as it was at the given snapshot, for providers of tables
+    /// with versioned data
+    fn get_table_snapshot(&self, name: &str, _snapshot: &TableSnapshot) -> Result<Rc<LogicalPlan>> {
+        Err(DataFusionError::Plan(format!(
+            "Table '{}' does not support time travel",
+            name
+        )))
+    }
+
+    /// Get a function that was defined in SQL with `CREATE FUNCTION`
+    fn get_sql_function(&self, _name: &str) -> Option<Rc<SqlFunction>> {
+        None
+    }
+
+    /// Get the number of rows in a table, for providers that know it without scanning the
+    /// table. `SELECT COUNT(*) FROM t` is answered from this count when it is known.
+    fn get_table_row_count(&self, _name: &str) -> Option<usize> {
+        None
+    }
+}
+
+/// A scalar function defined with `CREATE FUNCTION name(args) RETURNS type AS 'expression'`.
+/// Calls to the function are planned by substituting the arguments into the expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlFunction {
+    pub name: String,
+    pub args: Vec<(String, SQLType)>,
+    pub return_type: SQLType,
+    /// The parsed expression, which refers to the arguments by name
+    pub body: ASTNode,
+}
+
+impl SqlFunction {
+    /// The schema that the body is planned against, with a column for each argument
+    pub fn args_schema(&self) -> Schema {
+        Schema::new(
+            self.args
+                .iter()
+                .map(|&(ref name, ref data_type)| {
+                    Field::new(name, convert_data_type(data_type), true)
+                })
+                .collect(),
+        )
+    }
 }
 
 /// SQL query planner
 pub struct SqlToRel {
     schema_provider: Rc<SchemaProvider>,
+    /// Schema of the outer query when planning a correlated subquery
+    outer_schema: Option<Rc<Schema>>,
+    /// Columns of the outer query that identifiers have resolved to
+    outer_columns: RefCell<Vec<usize>>,
+    /// Window functions that have already been evaluated into columns, for planning a QUALIFY
+    /// predicate
+    window_columns: RefCell<Vec<(ASTNode, usize)>>,
+    /// How identifiers are matched against column names
+    identifier_casing: IdentifierCasing,
+    /// Values of the `$n` and `?` parameters, where `$1` is the first
+    parameters: Vec<ScalarValue>,
+    /// Tables named by the BROADCAST hint of the query whose FROM clause is being planned
+    broadcast_tables: RefCell<Vec<String>>,
+    /// Schema of the FROM clause of the query being planned, with the table names and aliases
+    /// that qualify its columns and the offset and number of the columns that each qualifies
+    from_columns: RefCell<Option<(Rc<Schema>, Vec<(String, usize, usize)>)>>,
 }
 
 impl SqlToRel {
     /// Create a new query planner
     pub fn new(schema_provider: Rc<SchemaProvider>) -> Self {
-        SqlToRel { schema_provider }
+        SqlToRel {
+            schema_provider,
+            outer_schema: None,
+            outer_columns: RefCell::new(vec![]),
+            window_columns: RefCell::new(vec![]),
+            identifier_casing: IdentifierCasing::CaseSensitive,
+            parameters: vec![],
+            broadcast_tables: RefCell::new(vec![]),
+            from_columns: RefCell::new(None),
+        }
+    }
+
+    /// Match identifiers against column names as required by `casing`. This should be the same
+    /// casing that the statement was tokenized with.
+    pub fn with_identifier_casing(mut self, casing: IdentifierCasing) -> Self {
+        self.identifier_casing = casing;
+        self
+    }
+
+    /// Plan parameters as literals of the given values, e.g. to execute a prepared statement
+    pub fn with_parameters(mut self, parameters: Vec<ScalarValue>) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// Create a planner for a subquery that can reference the columns of an outer query
+    fn for_subquery(&self, outer_schema: Rc<Schema>) -> Self {
+        SqlToRel {
+            schema_provider: self.schema_provider.clone(),
+            outer_schema: Some(outer_schema),
+            outer_columns: RefCell::new(vec![]),
+            window_columns: RefCell::new(vec![]),
+            identifier_casing: self.identifier_casing,
+            parameters: self.parameters.clone(),
+            broadcast_tables: RefCell::new(vec![]),
+            from_columns: RefCell::new(None),
+        }
+    }
+
+    /// Plan a subquery that can reference the columns of the outer query's schema
+    fn plan_subquery(&self, sql: &ASTNode, outer_schema: &Schema) -> Result<Subquery> {
+        let planner = self.for_subquery(Rc::new(outer_schema.clone()));
+        let plan = planner.sql_to_rel(sql)?;
+        let mut outer_columns = planner.outer_columns.into_inner();
+        outer_columns.sort();
+        outer_columns.dedup();
+        Ok(Subquery {
+            plan,
+            outer_columns,
+        })
     }
 
     /// Generate a logic plan from a SQL AST node
-    pub fn sql_to_rel(&self, sql: &ASTNode) -> Result<Rc<LogicalPlan>, String> {
+    pub fn sql_to_rel(&self, sql: &ASTNode) -> Result<Rc<LogicalPlan>> {
         match sql {
             &ASTNode::SQLSelect {
+                ref hints,
+                distinct,
                 ref projection,
                 ref relation,
                 ref selection,
@@ -51,11 +172,16 @@ impl SqlToRel {
                 ref order_by,
                 ref group_by,
                 ref having,
-                ..
+                ref qualify,
             } => {
                 // parse the input relation so we have access to the row type
                 let input = match relation {
-                    &Some(ref r) => self.sql_to_rel(r)?,
+                    &Some(ref r) => self.plan_from(r, hints)?,
+                    &None if !hints.is_empty() => {
+                        return Err(DataFusionError::Plan(
+                            "Join hints require a FROM clause".to_string(),
+                        ))
+                    }
                     &None => Rc::new(LogicalPlan::EmptyRelation {
                         schema: Rc::new(Schema::empty()),
                     }),
@@ -63,22 +189,59 @@ impl SqlToRel {
 
                 let input_schema = input.schema();
 
+                // qualified identifiers are resolved against the columns of the FROM clause
+                let from_columns = match relation {
+                    &Some(ref r) => Some((input_schema.clone(), self.qualified_columns(r)?.1)),
+                    &None => None,
+                };
+                self.from_columns.replace(from_columns);
+
                 // selection first
                 let selection_plan = match selection {
-                    &Some(ref filter_expr) => Some(LogicalPlan::Selection {
-                        expr: self.sql_to_rex(&filter_expr, &input_schema.clone())?,
-                        input: input.clone(),
-                    }),
+                    &Some(ref filter_expr) => Some(plan_selection(
+                        input.clone(),
+                        self.sql_to_rex(&filter_expr, &input_schema.clone())?,
+                    )),
                     _ => None,
                 };
 
+                if projection.iter().any(is_distinct_aggregate) {
+                    if having.is_some() {
+                        return Err(DataFusionError::Plan(String::from(
+                            "HAVING can't be used with aggregate functions with DISTINCT yet",
+                        )));
+                    }
+                    let aggregate_input = match selection_plan {
+                        Some(s) => s,
+                        _ => input.clone(),
+                    };
+                    let aggregate =
+                        self.plan_distinct_aggregate(aggregate_input, projection, group_by)?;
+                    return Ok(if distinct {
+                        Rc::new(plan_distinct(aggregate))
+                    } else {
+                        aggregate
+                    });
+                }
+
+                // window functions are evaluated by a Window plan under the projection, which
+                // appends a column for each of them to its input
+                let mut window_expr: Vec<WindowExpr> = vec![];
+                let mut window_ast: Vec<ASTNode> = vec![];
                 let expr: Vec<Expr> = projection
                     .iter()
-                    .map(|e| self.sql_to_rex(&e, &input_schema))
-                    .collect::<Result<Vec<Expr>, String>>()?;
+                    .map(|e| match *e {
+                        ASTNode::SQLWindowFunction { .. } => {
+                            window_expr.push(self.sql_to_window_expr(e, &input_schema)?);
+                            window_ast.push(e.clone());
+                            Ok(Expr::Column(input_schema.columns().len() + window_expr.len() - 1))
+                        }
+                        _ => self.sql_to_rex(&e, &input_schema),
+                    })
+                    .collect::<Result<Vec<Expr>>>()?;
 
                 // collect aggregate expressions
-                let aggr_expr: Vec<Expr> = expr
+                let mut aggr_expr: Vec<Expr> = expr
                     .iter()
                     .filter(|e| match e {
                         Expr::AggregateFunction { .. } => true,
@@ -87,9 +250,30 @@ impl SqlToRel {
                     .map(|e| e.clone())
                     .collect();
 
-                if aggr_expr.len() > 0 {
+                // HAVING is evaluated against the input and then rewritten to refer to the
+                // columns of the aggregate
+                let having_expr = match *having {
+                    Some(ref having) => Some(self.sql_to_rex(having, &input_schema)?),
+                    None => None,
+                };
+                let is_aggregate = aggr_expr.len() > 0
+                    || having_expr.as_ref().map_or(false, |e| has_aggregate(e));
+
+                if having_expr.is_some() && !is_aggregate && group_by.is_none() {
+                    return Err(DataFusionError::Plan(String::from(
+                        "HAVING requires GROUP BY or an aggregate function",
+                    )));
+                }
+
+                if is_aggregate && (!window_expr.is_empty() || qualify.is_some()) {
+                    return Err(DataFusionError::Plan(String::from(
+                        "Window functions can't be used in aggregate queries yet",
+                    )));
+                }
+
+                if is_aggregate || having_expr.is_some() {
                     let aggregate_input: Rc<LogicalPlan> = match selection_plan {
-                        Some(s) => Rc::new(s),
+                        Some(s) => s,
                         _ => input.clone(),
                     };
 
@@ -97,32 +281,97 @@ impl SqlToRel {
                         Some(gbe) => gbe
                             .iter()
                             .map(|e| self.sql_to_rex(&e, &input_schema))
-                            .collect::<Result<Vec<Expr>, String>>()?,
+                            .collect::<Result<Vec<Expr>>>()?,
                         None => vec![],
                     };
                     //println!("GROUP BY: {:?}", group_expr);
 
+                    // aggregate functions that are only used by HAVING are computed after the
+                    // others, and removed again once the predicate has been evaluated
+                    let output_count = group_expr.len() + aggr_expr.len();
+                    let having_expr = match having_expr {
+                        Some(ref e) => Some(rewrite_having(e, &group_expr, &mut aggr_expr)?),
+                        None => None,
+                    };
+
                     let mut all_fields: Vec<Expr> = group_expr.clone();
                     aggr_expr.iter().for_each(|x| all_fields.push(x.clone()));
 
                     let aggr_schema = Schema::new(exprlist_to_fields(&all_fields, input_schema));
 
+                    // counting every row of a table whose size is known doesn't need a scan
+                    if selection.is_none()
+                        && group_by.is_none()
+                        && having.is_none()
+                        && projection.iter().all(is_count_star)
+                    {
+                        if let Some(ref relation) = *relation {
+                            if let ASTNode::SQLIdentifier(ref name) = **relation {
+                                if let Some(n) = self.schema_provider.get_table_row_count(name) {
+                                    return Ok(plan_row_count(n, Rc::new(aggr_schema)));
+                                }
+                            }
+                        }
+                    }
+
                     //TODO: selection, projection, everything else
-                    Ok(Rc::new(LogicalPlan::Aggregate {
+                    let aggregate = Rc::new(LogicalPlan::Aggregate {
                         input: aggregate_input,
                         group_expr,
                         aggr_expr,
                         schema: Rc::new(aggr_schema),
-                    }))
+                    });
+                    let aggregate = match having_expr {
+                        Some(e) => {
+                            let selection = Rc::new(LogicalPlan::Selection {
+                                expr: e,
+                                input: aggregate,
+                            });
+                            if selection.schema().columns().len() == output_count {
+                                selection
+                            } else {
+                                let expr: Vec<Expr> = (0..output_count).map(Expr::Column).collect();
+                                let schema = Rc::new(Schema::new(exprlist_to_fields(
+                                    &expr,
+                                    selection.schema(),
+                                )));
+                                Rc::new(LogicalPlan::Projection {
+                                    expr,
+                                    input: selection,
+                                    schema,
+                                })
+                            }
+                        }
+                        None => aggregate,
+                    };
+                    Ok(if distinct {
+                        Rc::new(plan_distinct(aggregate))
+                    } else {
+                        aggregate
+                    })
                 } else {
                     let projection_input: Rc<LogicalPlan> = match selection_plan {
-                        Some(s) => Rc::new(s),
+                        Some(s) => s,
                         _ => input.clone(),
                     };
 
+                    if expr.iter().any(|e| time_window_args(e).is_some()) {
+                        return Err(DataFusionError::Plan(String::from(
+                            "window can only be used in aggregate queries that group by it",
+                        )));
+                    }
+
+                    let projection_input = match *qualify {
+                        Some(ref qualify) => {
+                            self.plan_qualify(projection_input, qualify, window_ast, window_expr)?
+                        }
+                        None if window_expr.is_empty() => projection_input,
+                        None => plan_window(projection_input, window_expr),
+                    };
+
                     let projection_schema = Rc::new(Schema::new(exprlist_to_fields(
                         &expr,
-                        input_schema.as_ref(),
+                        projection_input.schema().as_ref(),
                     )));
 
                     let projection = LogicalPlan::Projection {
@@ -130,6 +379,11 @@ impl SqlToRel {
                         input: projection_input,
                         schema: projection_schema.clone(),
                     };
+                    let projection = if distinct {
+                        plan_distinct(Rc::new(projection))
+                    } else {
+                        projection
+                    };
 
                     // aggregate queries
                     //                    match group_by {
@@ -137,14 +391,10 @@ impl SqlToRel {
                     //                        None => {}
                     //                    }
 
-                    if let &Some(_) = having {
-                        return Err(String::from("HAVING is not implemented yet"));
-                    }
-
                     let order_by_plan = match order_by {
                         &Some(ref order_by_expr) => {
                             let input_schema = projection.schema();
-                            let order_by_rex: Result<Vec<Expr>, String> = order_by_expr
+                            let order_by_rex: Result<Vec<Expr>> = order_by_expr
                                 .iter()
                                 .map(|e| self.sql_to_rex(e, &input_schema))
                                 .collect();
@@ -162,7 +412,11 @@ impl SqlToRel {
                         &Some(ref limit_ast_node) => {
                             let limit_count = match **limit_ast_node {
                                 ASTNode::SQLLiteralLong(n) => n,
-                                _ => return Err(String::from("LIMIT parameter is not a number")),
+                                _ => {
+                                    return Err(DataFusionError::Plan(String::from(
+                                        "LIMIT parameter is not a number",
+                                    )))
+                                }
                             };
                             LogicalPlan::Limit {
                                 limit: limit_count as usize,
@@ -185,34 +439,254 @@ impl SqlToRel {
                         schema: schema.clone(),
                         projection: None,
                     })),
-                    None => Err(format!("no schema found for table {}", id)),
+                    None => Err(DataFusionError::Plan(format!("no schema found for table {}", id))),
+                }
+            }
+
+            // tables are registered under their qualified names, such as `schema.table`
+            &ASTNode::SQLCompoundIdentifier(ref parts) => {
+                self.sql_to_rel(&ASTNode::SQLIdentifier(parts.join(".")))
+            }
+
+            &ASTNode::SQLTableAlias { ref relation, .. } => self.sql_to_rel(relation),
+
+            &ASTNode::SQLTableSample {
+                ref relation,
+                ref method,
+                percent,
+                seed,
+            } => {
+                if percent < 0.0 || percent > 100.0 {
+                    return Err(DataFusionError::Plan(format!(
+                        "TABLESAMPLE percentage must be between 0 and 100 but was {}",
+                        percent
+                    )));
                 }
+                let input = self.sql_to_rel(relation)?;
+                Ok(Rc::new(LogicalPlan::Sample {
+                    method: match method {
+                        SQLSampleMethod::Bernoulli => SampleMethod::Bernoulli,
+                        SQLSampleMethod::System => SampleMethod::System,
+                    },
+                    fraction: percent / 100.0,
+                    seed,
+                    schema: input.schema().clone(),
+                    input,
+                }))
             }
 
-            _ => Err(format!(
+            &ASTNode::SQLTableSnapshot {
+                ref relation,
+                ref snapshot,
+            } => {
+                let name = match **relation {
+                    ASTNode::SQLIdentifier(ref id) => id,
+                    ref other => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Time travel is only supported on tables, not {:?}",
+                            other
+                        )))
+                    }
+                };
+                let snapshot = match *snapshot {
+                    SQLSnapshot::Version(n) => TableSnapshot::Version(n),
+                    SQLSnapshot::Timestamp(ref ts) => TableSnapshot::Timestamp(match **ts {
+                        ASTNode::SQLLiteralLong(ms) => ms,
+                        ASTNode::SQLTypedLiteral {
+                            ref literal_type,
+                            ref value,
+                        } if *literal_type == SQLTypedLiteralType::Timestamp
+                            || *literal_type == SQLTypedLiteralType::Date =>
+                        {
+                            typed_literal_millis(literal_type, value)?
+                        }
+                        ASTNode::SQLLiteralString(ref s) => {
+                            match ValueFormats::default().parse_timestamp(s) {
+                                Some(ms) => ms,
+                                None => {
+                                    return Err(DataFusionError::Plan(format!(
+                                        "Invalid timestamp '{}' in time travel query",
+                                        s
+                                    )))
+                                }
+                            }
+                        }
+                        ref other => {
+                            return Err(DataFusionError::Plan(format!(
+                                "Expected a timestamp literal in time travel query, found {:?}",
+                                other
+                            )))
+                        }
+                    }),
+                };
+                if self.schema_provider.get_table_meta(name).is_none() {
+                    return Err(DataFusionError::Plan(format!(
+                        "no schema found for table {}",
+                        name
+                    )));
+                }
+                self.schema_provider.get_table_snapshot(name, &snapshot)
+            }
+
+            &ASTNode::SQLJoin {
+                ref left,
+                ref right,
+                ref join_type,
+                ref on,
+            } => {
+                let join_type = match join_type {
+                    SQLJoinType::Inner => JoinType::Inner,
+                    SQLJoinType::LeftSemi => JoinType::LeftSemi,
+                    SQLJoinType::LeftAnti => JoinType::LeftAnti,
+                };
+                self.plan_join(
+                    self.sql_to_rel(left)?,
+                    &relation_names(left),
+                    self.sql_to_rel(right)?,
+                    &relation_names(right),
+                    join_type,
+                    &split_conjunction(on),
+                    self.hinted_build_side(Some(left.as_ref()), right),
+                )
+            }
+
+            &ASTNode::SQLLateralJoin {
+                ref left,
+                ref subquery,
+                left_outer,
+                ref on,
+            } => {
+                let left_plan = self.sql_to_rel(left)?;
+                let planner = self.for_subquery(left_plan.schema().clone());
+                let mut subquery_plan = planner.sql_to_rel(subquery)?;
+                if let Some(ref on) = *on {
+                    // the join condition filters the subquery rows for each left row
+                    let expr = planner.sql_to_rex(on, &subquery_plan.schema())?;
+                    if expr.get_type(&subquery_plan.schema()) != DataType::Boolean {
+                        return Err(DataFusionError::Plan(format!(
+                            "Join condition must be boolean: {:?}",
+                            expr
+                        )));
+                    }
+                    subquery_plan = Rc::new(LogicalPlan::Selection {
+                        expr,
+                        input: subquery_plan,
+                    });
+                }
+                let mut fields = left_plan.schema().columns().clone();
+                fields.extend(subquery_plan.schema().columns().iter().map(|f| {
+                    Field::new(f.name(), f.data_type().clone(), left_outer || f.is_nullable())
+                }));
+                Ok(Rc::new(LogicalPlan::LateralJoin {
+                    left: left_plan,
+                    subquery: subquery_plan,
+                    left_outer,
+                    correlated: !planner.outer_columns.borrow().is_empty(),
+                    schema: Rc::new(Schema::new(fields)),
+                }))
+            }
+
+            &ASTNode::SQLFunction {
+                ref id, ref args, ..
+            } => self.plan_table_function(id, args),
+
+            _ => Err(DataFusionError::Plan(format!(
                 "sql_to_rel does not support this relation: {:?}",
                 sql
-            )),
+            ))),
         }
     }
 
     /// Generate a relational expression from a SQL expression
-    pub fn sql_to_rex(&self, sql: &ASTNode, schema: &Schema) -> Result<Expr, String> {
+    pub fn sql_to_rex(&self, sql: &ASTNode, schema: &Schema) -> Result<Expr> {
         match sql {
             &ASTNode::SQLLiteralLong(n) => Ok(Expr::Literal(ScalarValue::Int64(n))),
             &ASTNode::SQLLiteralDouble(n) => Ok(Expr::Literal(ScalarValue::Float64(n))),
+            // binary values are stored as strings of raw bytes, so a binary literal is compared
+            // as a string, which has to be valid UTF-8
+            &ASTNode::SQLLiteralBinary(ref bytes) => match String::from_utf8(bytes.clone()) {
+                Ok(s) => Ok(Expr::Literal(ScalarValue::Utf8(Rc::new(s)))),
+                Err(_) => Err(DataFusionError::Plan(format!(
+                    "Binary literal X'{}' is not valid UTF-8, which binary values are compared as",
+                    bytes
+                        .iter()
+                        .map(|b| format!("{:02X}", b))
+                        .collect::<Vec<String>>()
+                        .concat()
+                ))),
+            },
+            &ASTNode::SQLLiteralBoolean(b) => Ok(Expr::Literal(ScalarValue::Boolean(b))),
+            &ASTNode::SQLLiteralNull => Ok(Expr::Literal(ScalarValue::Null)),
             &ASTNode::SQLLiteralString(ref s) => {
                 Ok(Expr::Literal(ScalarValue::Utf8(Rc::new(s.clone()))))
             }
+            &ASTNode::SQLTypedLiteral {
+                ref literal_type,
+                ref value,
+            } => Ok(Expr::Literal(ScalarValue::Int64(typed_literal_millis(
+                literal_type,
+                value,
+            )?))),
+
+            &ASTNode::SQLParameter(n) => match self.parameters.get(n.wrapping_sub(1)) {
+                Some(value) => Ok(Expr::Literal(value.clone())),
+                None => Err(DataFusionError::Plan(format!(
+                    "No value is bound to parameter ${}",
+                    n
+                ))),
+            },
 
             &ASTNode::SQLIdentifier(ref id) => {
-                match schema.columns().iter().position(|c| c.name().eq(id)) {
-                    Some(index) => Ok(Expr::Column(index)),
-                    None => Err(format!(
-                        "Invalid identifier '{}' for schema {}",
-                        id,
-                        schema.to_string()
-                    )),
+                if let Some(index) = find_column(schema, id, self.identifier_casing)? {
+                    return Ok(Expr::Column(index));
+                }
+                // a correlated subquery can also reference the columns of the outer query
+                if let Some(ref outer) = self.outer_schema {
+                    if let Some(index) = find_column(outer, id, self.identifier_casing)? {
+                        self.outer_columns.borrow_mut().push(index);
+                        return Ok(Expr::OuterColumn {
+                            index,
+                            data_type: outer.column(index).data_type().clone(),
+                        });
+                    }
+                }
+                Err(DataFusionError::Plan(format!(
+                    "Invalid identifier '{}' for schema {}",
+                    id,
+                    schema.to_string()
+                )))
+            }
+
+            &ASTNode::SQLCompoundIdentifier(ref parts) if parts.len() == 2 => {
+                let (qualifier, name) = (&parts[0], &parts[1]);
+                let from_columns = self.from_columns.borrow().clone();
+                let qualified = from_columns.as_ref().and_then(|&(ref from_schema, ref q)| {
+                    q.iter()
+                        .find(|c| c.0 == *qualifier)
+                        .map(|&(_, offset, count)| (from_schema, offset, count))
+                });
+                let (from_schema, offset, count) = match qualified {
+                    Some(qualified) => qualified,
+                    None => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Unknown table '{}' in identifier '{}.{}'",
+                            qualifier, qualifier, name
+                        )))
+                    }
+                };
+                if !ptr::eq(schema, &**from_schema) {
+                    // columns are no longer qualified once they have been aggregated
+                    return self.sql_to_rex(&ASTNode::SQLIdentifier(name.clone()), schema);
+                }
+                let columns = Schema::new(schema.columns()[offset..offset + count].to_vec());
+                match find_column(&columns, name, self.identifier_casing)? {
+                    Some(index) => Ok(Expr::Column(offset + index)),
+                    None => Err(DataFusionError::Plan(format!(
+                        "Invalid identifier '{}.{}' for schema {}",
+                        qualifier,
+                        name,
+                        columns.to_string()
+                    ))),
                 }
             }
 
@@ -230,6 +704,44 @@ impl SqlToRel {
                 data_type: convert_data_type(data_type),
             }),
 
+            &ASTNode::SQLNested(ref expr) => self.sql_to_rex(expr, schema),
+
+            &ASTNode::SQLUnary {
+                ref operator,
+                ref rex,
+            } => match (operator, rex.as_ref()) {
+                (&SQLOperator::Plus, _) => self.sql_to_rex(rex, schema),
+                (&SQLOperator::Not, _) => Ok(Expr::Not(Rc::new(self.sql_to_rex(rex, schema)?))),
+                (&SQLOperator::Minus, &ASTNode::SQLLiteralLong(n)) => {
+                    Ok(Expr::Literal(ScalarValue::Int64(-n)))
+                }
+                (&SQLOperator::Minus, &ASTNode::SQLLiteralDouble(n)) => {
+                    Ok(Expr::Literal(ScalarValue::Float64(-n)))
+                }
+                (&SQLOperator::Minus, _) => {
+                    // other values are subtracted from a zero of their own type
+                    let expr = self.sql_to_rex(rex, schema)?;
+                    let data_type = expr.get_type(schema);
+                    let zero = Expr::Literal(ScalarValue::Int8(0))
+                        .cast_to(&data_type, schema)
+                        .map_err(|_| {
+                            DataFusionError::Plan(format!(
+                                "Cannot negate a value of type {:?}",
+                                data_type
+                            ))
+                        })?;
+                    Ok(Expr::BinaryExpr {
+                        left: Rc::new(zero),
+                        op: Operator::Minus,
+                        right: Rc::new(expr),
+                    })
+                }
+                _ => Err(DataFusionError::Plan(format!(
+                    "Unsupported unary operator {:?}",
+                    operator
+                ))),
+            },
+
             &ASTNode::SQLIsNull(ref expr) => {
                 Ok(Expr::IsNull(Rc::new(self.sql_to_rex(expr, schema)?)))
             }
@@ -257,6 +769,30 @@ impl SqlToRel {
                     &SQLOperator::Modulus => Operator::Modulus,
                     &SQLOperator::And => Operator::And,
                     &SQLOperator::Or => Operator::Or,
+                    &SQLOperator::IsNotDistinctFrom => {
+                        return Err(DataFusionError::Plan(String::from(
+                            "IS NOT DISTINCT FROM is only supported in join conditions",
+                        )))
+                    }
+                    &SQLOperator::Like => Operator::Like,
+                    &SQLOperator::NotLike => Operator::NotLike,
+                    &SQLOperator::ILike => Operator::ILike,
+                    &SQLOperator::NotILike => Operator::NotILike,
+                    &SQLOperator::StringConcat => {
+                        return Err(DataFusionError::Plan(String::from(
+                            "String concatenation with || is not supported yet",
+                        )))
+                    }
+                    &SQLOperator::BitwiseAnd
+                    | &SQLOperator::BitwiseOr
+                    | &SQLOperator::BitwiseXor
+                    | &SQLOperator::ShiftLeft
+                    | &SQLOperator::ShiftRight => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Bitwise operator {:?} is not supported yet",
+                            op
+                        )))
+                    }
                 };
 
                 let left_expr = self.sql_to_rex(&left, &schema)?;
@@ -264,6 +800,20 @@ impl SqlToRel {
                 let left_type = left_expr.get_type(schema);
                 let right_type = right_expr.get_type(schema);
 
+                if operator.is_like() {
+                    return match (&left_type, &right_type) {
+                        (&DataType::Utf8, &DataType::Utf8) => Ok(Expr::BinaryExpr {
+                            left: Rc::new(left_expr),
+                            op: operator,
+                            right: Rc::new(right_expr),
+                        }),
+                        _ => Err(DataFusionError::Plan(format!(
+                            "{:?} requires string operands but found {:?} and {:?}",
+                            operator, left_type, right_type
+                        ))),
+                    };
+                }
+
                 match get_supertype(&left_type, &right_type) {
                     Some(supertype) => Ok(Expr::BinaryExpr {
                         left: Rc::new(left_expr.cast_to(&supertype, schema)?),
@@ -271,28 +821,103 @@ impl SqlToRel {
                         right: Rc::new(right_expr.cast_to(&supertype, schema)?),
                     }),
                     None => {
-                        return Err(format!(
+                        return Err(DataFusionError::Plan(format!(
                             "No common supertype found for binary operator {:?} \
                              with input types {:?} and {:?}",
                             operator, left_type, right_type
-                        ))
+                        )))
                     }
                 }
             }
 
-            &ASTNode::SQLOrderBy { ref expr, asc } => Ok(Expr::Sort {
+            &ASTNode::SQLQuantifiedComparison {
+                ref expr,
+                ref op,
+                all,
+                ref subquery,
+            } => {
+                let operator = match op {
+                    &SQLOperator::Gt => Operator::Gt,
+                    &SQLOperator::GtEq => Operator::GtEq,
+                    &SQLOperator::Lt => Operator::Lt,
+                    &SQLOperator::LtEq => Operator::LtEq,
+                    &SQLOperator::Eq => Operator::Eq,
+                    &SQLOperator::NotEq => Operator::NotEq,
+                    _ => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Operator {:?} cannot be used with ANY or ALL",
+                            op
+                        )))
+                    }
+                };
+
+                let value = self.sql_to_rex(expr, schema)?;
+                let mut subquery = self.plan_subquery(subquery, schema)?;
+                let subquery_schema = subquery.plan.schema().clone();
+                if subquery_schema.columns().len() != 1 {
+                    return Err(DataFusionError::Plan(format!(
+                        "Subquery of ANY or ALL must return one column but returns {}",
+                        subquery_schema.columns().len()
+                    )));
+                }
+                let value_type = value.get_type(schema);
+                let subquery_type = subquery_schema.column(0).data_type().clone();
+                let supertype = match get_supertype(&value_type, &subquery_type) {
+                    Some(supertype) => supertype,
+                    None => {
+                        return Err(DataFusionError::Plan(format!(
+                            "No common supertype found for {:?} with input types {:?} and {:?}",
+                            operator, value_type, subquery_type
+                        )))
+                    }
+                };
+                if subquery_type != supertype {
+                    let expr = vec![Expr::Column(0).cast_to(&supertype, &subquery_schema)?];
+                    let fields = exprlist_to_fields(&expr, &subquery_schema);
+                    subquery.plan = Rc::new(LogicalPlan::Projection {
+                        expr,
+                        input: subquery.plan.clone(),
+                        schema: Rc::new(Schema::new(fields)),
+                    });
+                }
+                Ok(Expr::QuantifiedComparison {
+                    expr: Rc::new(value.cast_to(&supertype, schema)?),
+                    op: operator,
+                    all,
+                    subquery,
+                })
+            }
+
+            &ASTNode::SQLOrderBy {
+                ref expr,
+                asc,
+                nulls_first,
+            } => Ok(Expr::Sort {
                 expr: Rc::new(self.sql_to_rex(&expr, &schema)?),
                 asc,
+                // nulls sort before other values unless the order is reversed
+                nulls_first: nulls_first.unwrap_or(asc),
             }),
 
-            &ASTNode::SQLFunction { ref id, ref args } => {
+            &ASTNode::SQLFunction {
+                ref id,
+                ref args,
+                distinct,
+            } => {
+                if distinct {
+                    return Err(DataFusionError::Plan(format!(
+                        "DISTINCT can only be used in aggregate functions in the projection of \
+                         a query, found '{}'",
+                        id
+                    )));
+                }
                 //TODO: fix this hack
                 match id.to_lowercase().as_ref() {
                     "min" | "max" | "sum" | "avg" => {
                         let rex_args = args
                             .iter()
                             .map(|a| self.sql_to_rex(a, schema))
-                            .collect::<Result<Vec<Expr>, String>>()?;
+                            .collect::<Result<Vec<Expr>>>()?;
 
                         // return type is same as the argument type for these aggregate functions
                         let return_type = rex_args[0].get_type(schema).clone();
@@ -312,7 +937,7 @@ impl SqlToRel {
                                 ASTNode::SQLWildcard => Ok(Expr::Column(0)),
                                 _ => self.sql_to_rex(a, schema),
                             })
-                            .collect::<Result<Vec<Expr>, String>>()?;
+                            .collect::<Result<Vec<Expr>>>()?;
 
                         Ok(Expr::AggregateFunction {
                             name: id.clone(),
@@ -320,12 +945,24 @@ impl SqlToRel {
                             return_type: DataType::UInt64,
                         })
                     }
+                    TIME_WINDOW_FUNCTION => self.time_window_to_rex(args, schema),
+                    "if" | "ifnull" | "nvl" | "coalesce" | "decode" => {
+                        let rex_args = args
+                            .iter()
+                            .map(|a| self.sql_to_rex(a, schema))
+                            .collect::<Result<Vec<Expr>>>()?;
+                        self.conditional_to_rex(&id.to_lowercase(), rex_args, schema)
+                    }
+                    _ if self.schema_provider.get_sql_function(id).is_some() => {
+                        let function = self.schema_provider.get_sql_function(id).unwrap();
+                        self.sql_function_to_rex(&function, args, schema)
+                    }
                     _ => match self.schema_provider.get_function_meta(id) {
                         Some(fm) => {
                             let rex_args = args
                                 .iter()
                                 .map(|a| self.sql_to_rex(a, schema))
-                                .collect::<Result<Vec<Expr>, String>>()?;
+                                .collect::<Result<Vec<Expr>>>()?;
 
                             let mut safe_args: Vec<Expr> = vec![];
                             for i in 0..rex_args.len() {
@@ -335,21 +972,926 @@ impl SqlToRel {
 
                             Ok(Expr::ScalarFunction {
                                 name: id.clone(),
+                                return_type: fm.return_type_for(&safe_args)?,
                                 args: safe_args,
-                                return_type: fm.return_type().clone(),
                             })
                         }
-                        _ => Err(format!("Invalid function '{}'", id)),
+                        _ => Err(DataFusionError::Plan(format!("Invalid function '{}'", id))),
                     },
                 }
             }
 
-            _ => Err(String::from(format!(
+            &ASTNode::SQLWindowFunction { ref id, .. } => {
+                match self.window_columns.borrow().iter().find(|w| w.0 == *sql) {
+                    Some(&(_, i)) => Ok(Expr::Column(i)),
+                    None => Err(DataFusionError::Plan(format!(
+                        "Window function '{}' can only be used as a projection expression or \
+                         in QUALIFY",
+                        id
+                    ))),
+                }
+            }
+
+            _ => Err(DataFusionError::Plan(format!(
                 "Unsupported ast node {:?} in sqltorel",
                 sql
             ))),
         }
     }
+
+    /// Plan a call to a function defined with `CREATE FUNCTION` by inlining its body
+    fn sql_function_to_rex(
+        &self,
+        function: &SqlFunction,
+        args: &[ASTNode],
+        schema: &Schema,
+    ) -> Result<Expr> {
+        if args.len() != function.args.len() {
+            return Err(DataFusionError::Plan(format!(
+                "Function '{}' expects {} arguments but was called with {}",
+                function.name,
+                function.args.len(),
+                args.len()
+            )));
+        }
+        // each argument is cast to its declared type before it is substituted
+        let arguments: Vec<(String, ASTNode)> = function
+            .args
+            .iter()
+            .zip(args)
+            .map(|(&(ref name, ref data_type), arg)| {
+                let arg = ASTNode::SQLCast {
+                    expr: Box::new(arg.clone()),
+                    data_type: data_type.clone(),
+                };
+                (name.clone(), arg)
+            })
+            .collect();
+        let body = substitute_arguments(&function.body, &arguments);
+        self.sql_to_rex(&body, schema)?
+            .cast_to(&convert_data_type(&function.return_type), schema)
+    }
+
+    /// Plan a QUALIFY clause as a filter over the results of the window functions, which are
+    /// evaluated along with those of the projection. A filter that only keeps the first row of
+    /// each partition, `ROW_NUMBER() OVER (PARTITION BY keys ORDER BY ...) = 1`, is planned as a
+    /// deduplication instead when the projection has no window functions of its own.
+    fn plan_qualify(
+        &self,
+        input: Rc<LogicalPlan>,
+        qualify: &ASTNode,
+        mut window_ast: Vec<ASTNode>,
+        mut window_expr: Vec<WindowExpr>,
+    ) -> Result<Rc<LogicalPlan>> {
+        if window_expr.is_empty() {
+            if let Some(plan) = self.qualify_to_deduplicate(&input, qualify)? {
+                return Ok(plan);
+            }
+        }
+
+        let input_schema = input.schema().clone();
+        let mut functions = vec![];
+        collect_window_functions(qualify, &mut functions);
+        for f in functions {
+            if !window_ast.contains(&f) {
+                window_expr.push(self.sql_to_window_expr(&f, &input_schema)?);
+                window_ast.push(f);
+            }
+        }
+        if window_expr.is_empty() {
+            return Err(DataFusionError::Plan(String::from(
+                "QUALIFY requires a window function in the projection or the QUALIFY predicate",
+            )));
+        }
+
+        let window = plan_window(input, window_expr);
+        *self.window_columns.borrow_mut() = window_ast
+            .into_iter()
+            .enumerate()
+            .map(|(i, f)| (f, input_schema.columns().len() + i))
+            .collect();
+        let predicate = self.sql_to_rex(qualify, window.schema());
+        self.window_columns.borrow_mut().clear();
+        Ok(Rc::new(LogicalPlan::Selection {
+            expr: predicate?,
+            input: window,
+        }))
+    }
+
+    /// Plan `ROW_NUMBER() OVER (PARTITION BY keys ORDER BY ...) = 1` as a deduplication on the
+    /// partition keys, or return None for any other predicate
+    fn qualify_to_deduplicate(
+        &self,
+        input: &Rc<LogicalPlan>,
+        qualify: &ASTNode,
+    ) -> Result<Option<Rc<LogicalPlan>>> {
+        let function = match *qualify {
+            ASTNode::SQLBinaryExpr {
+                ref left,
+                op: SQLOperator::Eq,
+                ref right,
+            } => match (left.as_ref(), right.as_ref()) {
+                (f, &ASTNode::SQLLiteralLong(1)) | (&ASTNode::SQLLiteralLong(1), f) => f,
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        match *function {
+            ASTNode::SQLWindowFunction {
+                ref id,
+                ref args,
+                ref partition_by,
+                ref order_by,
+                ignore_nulls: false,
+                ..
+            } if id.eq_ignore_ascii_case("ROW_NUMBER") && args.is_empty() => {
+                let schema = input.schema().clone();
+                let keys = partition_by
+                    .iter()
+                    .map(|e| self.sql_to_rex(e, &schema))
+                    .collect::<Result<Vec<Expr>>>()?;
+                let order_by = order_by
+                    .iter()
+                    .map(|e| self.sql_to_rex(e, &schema))
+                    .collect::<Result<Vec<Expr>>>()?;
+                Ok(Some(Rc::new(LogicalPlan::Deduplicate {
+                    input: input.clone(),
+                    keys,
+                    order_by,
+                    keep: DeduplicateKeep::First,
+                    schema,
+                })))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Plan the FROM clause of a query, following the query's join hints
+    fn plan_from(&self, relation: &ASTNode, hints: &[SQLHint]) -> Result<Rc<LogicalPlan>> {
+        let tables = relation_names(relation);
+        let mut broadcast = vec![];
+        let mut join_order = None;
+        for hint in hints {
+            let (name, hinted) = match *hint {
+                SQLHint::Broadcast(ref hinted) => {
+                    broadcast.extend(hinted.iter().cloned());
+                    ("BROADCAST", hinted)
+                }
+                SQLHint::JoinOrder(ref hinted) => {
+                    join_order = Some(hinted);
+                    ("JOIN_ORDER", hinted)
+                }
+            };
+            if let Some(table) = hinted.iter().find(|t| !tables.contains(t)) {
+                return Err(DataFusionError::Plan(format!(
+                    "Table '{}' in {} hint is not in the FROM clause",
+                    table, name
+                )));
+            }
+        }
+        // subqueries in the FROM clause have hints of their own
+        let outer = self.broadcast_tables.replace(broadcast);
+        let plan = match join_order {
+            Some(order) => self.plan_join_order(relation, order),
+            None => self.sql_to_rel(relation),
+        };
+        self.broadcast_tables.replace(outer);
+        plan
+    }
+
+    /// Get the number of columns of a relation in a FROM clause, and the table names and
+    /// aliases in it with the offset and number of the columns that each qualifies
+    fn qualified_columns(
+        &self,
+        relation: &ASTNode,
+    ) -> Result<(usize, Vec<(String, usize, usize)>)> {
+        match *relation {
+            ASTNode::SQLJoin {
+                ref left,
+                ref right,
+                ref join_type,
+                ..
+            } => {
+                let (count, mut columns) = self.qualified_columns(left)?;
+                // semi and anti joins only produce columns from the left relation
+                if *join_type != SQLJoinType::Inner {
+                    return Ok((count, columns));
+                }
+                let (right_count, right_columns) = self.qualified_columns(right)?;
+                columns.extend(
+                    right_columns
+                        .into_iter()
+                        .map(|(name, offset, n)| (name, count + offset, n)),
+                );
+                Ok((count + right_count, columns))
+            }
+            // the columns of a lateral subquery can't be qualified
+            ASTNode::SQLLateralJoin { ref left, .. } => Ok((
+                self.sql_to_rel(relation)?.schema().columns().len(),
+                self.qualified_columns(left)?.1,
+            )),
+            _ => {
+                let count = self.sql_to_rel(relation)?.schema().columns().len();
+                let names = relation_names(relation);
+                Ok((count, names.into_iter().map(|name| (name, 0, count)).collect()))
+            }
+        }
+    }
+
+    /// Plan a call to a table function in the FROM clause. `generate_series(start, stop[, step])`
+    /// produces the integers from `start` to `stop` inclusive and `range` the same without
+    /// `stop`. Timestamps and intervals are plain milliseconds, so a series of timestamps is
+    /// written with a TIMESTAMP start and stop and an INTERVAL step.
+    fn plan_table_function(&self, id: &str, args: &[ASTNode]) -> Result<Rc<LogicalPlan>> {
+        let name = id.to_lowercase();
+        let inclusive = match name.as_ref() {
+            "generate_series" => true,
+            "range" => false,
+            _ => return Err(DataFusionError::Plan(format!("Unknown table function {}", id))),
+        };
+        if args.len() < 2 || args.len() > 3 {
+            return Err(DataFusionError::Plan(format!(
+                "{} expects 2 or 3 arguments but was given {}",
+                name,
+                args.len()
+            )));
+        }
+        let empty_schema = Schema::new(vec![]);
+        let mut values = vec![];
+        for arg in args {
+            values.push(match self.sql_to_rex(arg, &empty_schema)? {
+                Expr::Literal(ScalarValue::Int64(n)) => n,
+                Expr::Literal(ScalarValue::Int32(n)) => i64::from(n),
+                Expr::Literal(ScalarValue::Int16(n)) => i64::from(n),
+                Expr::Literal(ScalarValue::Int8(n)) => i64::from(n),
+                other => {
+                    return Err(DataFusionError::Plan(format!(
+                        "Arguments of {} must be integer constants, found {:?}",
+                        name, other
+                    )))
+                }
+            });
+        }
+        let step = if values.len() == 3 { values[2] } else { 1 };
+        if step == 0 {
+            return Err(DataFusionError::Plan(format!("The step of {} must not be zero", name)));
+        }
+        Ok(Rc::new(LogicalPlan::GenerateSeries {
+            start: values[0],
+            stop: values[1],
+            step,
+            inclusive,
+            schema: Rc::new(Schema::new(vec![Field::new(&name, DataType::Int64, false)])),
+        }))
+    }
+
+    /// Plan a chain of inner joins so that its relations are joined in the order of a
+    /// JOIN_ORDER hint, followed by the relations the hint leaves out in the order they are
+    /// written. Each predicate of the join conditions is checked by the first join that has
+    /// both of the columns it compares. The columns are still produced in the order the
+    /// relations are written.
+    fn plan_join_order(&self, relation: &ASTNode, order: &[String]) -> Result<Rc<LogicalPlan>> {
+        let mut relations = vec![];
+        let mut predicates = vec![];
+        flatten_inner_joins(relation, &mut relations, &mut predicates);
+        let names: Vec<Vec<String>> = relations.iter().map(|r| relation_names(r)).collect();
+        let mut ordered: Vec<usize> = vec![];
+        for name in order {
+            // the hint has been checked against the tables of the FROM clause
+            let i = names.iter().position(|n| n.contains(name)).unwrap();
+            if !ordered.contains(&i) {
+                ordered.push(i);
+            }
+        }
+        for i in 0..relations.len() {
+            if !ordered.contains(&i) {
+                ordered.push(i);
+            }
+        }
+
+        let plans = relations
+            .iter()
+            .map(|r| self.sql_to_rel(r))
+            .collect::<Result<Vec<Rc<LogicalPlan>>>>()?;
+        let sources = predicates
+            .iter()
+            .map(|p| predicate_sources(p, &names, &plans, self.identifier_casing))
+            .collect::<Result<Vec<Vec<usize>>>>()?;
+        let mut plan = plans[ordered[0]].clone();
+        let mut tables = names[ordered[0]].clone();
+        let mut applied = vec![false; predicates.len()];
+        for step in 1..ordered.len() {
+            let next = ordered[step];
+            let mut on = vec![];
+            for (i, predicate) in predicates.iter().enumerate() {
+                if !applied[i] && sources[i].iter().all(|r| ordered[..step + 1].contains(r)) {
+                    applied[i] = true;
+                    on.push(*predicate);
+                }
+            }
+            if on.is_empty() {
+                return Err(DataFusionError::Plan(format!(
+                    "JOIN_ORDER hint joins {} without a join condition",
+                    names[next].join(", ")
+                )));
+            }
+            let left = if step == 1 {
+                Some(relations[ordered[0]])
+            } else {
+                None
+            };
+            plan = self.plan_join(
+                plan,
+                &tables,
+                plans[next].clone(),
+                &names[next],
+                JoinType::Inner,
+                &on,
+                self.hinted_build_side(left, relations[next]),
+            )?;
+            tables.extend(names[next].iter().cloned());
+        }
+        if ordered.iter().enumerate().all(|(i, r)| i == *r) {
+            return Ok(plan);
+        }
+
+        let mut offsets = vec![0; relations.len()];
+        let mut offset = 0;
+        for &i in &ordered {
+            offsets[i] = offset;
+            offset += plans[i].schema().columns().len();
+        }
+        let mut expr = vec![];
+        let mut fields = vec![];
+        for (i, p) in plans.iter().enumerate() {
+            for (c, field) in p.schema().columns().iter().enumerate() {
+                expr.push(Expr::Column(offsets[i] + c));
+                fields.push(field.clone());
+            }
+        }
+        Ok(Rc::new(LogicalPlan::Projection {
+            expr,
+            input: plan,
+            schema: Rc::new(Schema::new(fields)),
+        }))
+    }
+
+    /// Choose the build side of a join from the BROADCAST hint, when the relation on one side
+    /// of the join is a table that the hint names. The left relation is None when it is the
+    /// result of other joins.
+    fn hinted_build_side(&self, left: Option<&ASTNode>, right: &ASTNode) -> Option<BuildSide> {
+        let broadcast = self.broadcast_tables.borrow();
+        let hinted = |r: &ASTNode| table_name(r).map(|t| broadcast.contains(t)).unwrap_or(false);
+        match (left.map(hinted).unwrap_or(false), hinted(right)) {
+            (true, false) => Some(BuildSide::Left),
+            (false, true) => Some(BuildSide::Right),
+            _ => None,
+        }
+    }
+
+    /// Plan an equijoin of two relations on the predicates of its join condition
+    fn plan_join(
+        &self,
+        left_plan: Rc<LogicalPlan>,
+        left_tables: &[String],
+        right_plan: Rc<LogicalPlan>,
+        right_tables: &[String],
+        join_type: JoinType,
+        predicates: &[&ASTNode],
+        build_side: Option<BuildSide>,
+    ) -> Result<Rc<LogicalPlan>> {
+        let left_schema = left_plan.schema().clone();
+        let right_schema = right_plan.schema().clone();
+
+        let mut keys = vec![];
+        let mut null_safe = vec![];
+        for predicate in predicates {
+            match **predicate {
+                ASTNode::SQLBinaryExpr {
+                    ref left,
+                    ref op,
+                    ref right,
+                } if *op == SQLOperator::Eq || *op == SQLOperator::IsNotDistinctFrom =>
+                {
+                    let a = resolve_join_column(
+                        left,
+                        left_tables,
+                        &left_schema,
+                        right_tables,
+                        &right_schema,
+                        self.identifier_casing,
+                    )?;
+                    let b = resolve_join_column(
+                        right,
+                        left_tables,
+                        &left_schema,
+                        right_tables,
+                        &right_schema,
+                        self.identifier_casing,
+                    )?;
+                    keys.push(match (a, b) {
+                        (JoinSide::Left(l), JoinSide::Right(r))
+                        | (JoinSide::Right(r), JoinSide::Left(l)) => (l, r),
+                        _ => {
+                            return Err(DataFusionError::Plan(format!(
+                                "Join condition {:?} must compare a column from each \
+                                 side of the join",
+                                predicate
+                            )))
+                        }
+                    });
+                    null_safe.push(*op == SQLOperator::IsNotDistinctFrom);
+                }
+                _ => {
+                    return Err(DataFusionError::Plan(format!(
+                        "Unsupported join condition {:?}, only equality between \
+                         columns is supported",
+                        predicate
+                    )))
+                }
+            }
+        }
+
+        // semi and anti joins only produce columns from the left relation
+        let mut fields = left_schema.columns().clone();
+        if join_type == JoinType::Inner {
+            fields.extend(right_schema.columns().iter().cloned());
+        }
+        Ok(Rc::new(LogicalPlan::Join {
+            left: left_plan,
+            right: right_plan,
+            join_type,
+            on: keys,
+            null_equals_null: null_safe,
+            build_side,
+            schema: Rc::new(Schema::new(fields)),
+        }))
+    }
+
+    /// Plan aggregate functions over distinct values, such as `COUNT(DISTINCT x)`, as the
+    /// aggregate functions over a deduplication of the grouping keys and the argument. Rows
+    /// where the argument is null are removed first, since they aren't counted.
+    fn plan_distinct_aggregate(
+        &self,
+        input: Rc<LogicalPlan>,
+        projection: &[ASTNode],
+        group_by: &Option<Vec<ASTNode>>,
+    ) -> Result<Rc<LogicalPlan>> {
+        let input_schema = input.schema().clone();
+        let mut keys: Vec<Expr> = match *group_by {
+            Some(ref group_by) => group_by
+                .iter()
+                .map(|e| self.sql_to_rex(e, &input_schema))
+                .collect::<Result<Vec<Expr>>>()?,
+            None => vec![],
+        };
+        let group_count = keys.len();
+
+        let mut aggr_expr: Vec<Expr> = vec![];
+        for e in projection {
+            let (name, arg, return_type) = match *e {
+                ASTNode::SQLFunction {
+                    ref id,
+                    ref args,
+                    distinct: true,
+                } if args.len() == 1 => {
+                    let function = ASTNode::SQLFunction {
+                        id: id.clone(),
+                        args: args.clone(),
+                        distinct: false,
+                    };
+                    match self.sql_to_rex(&function, &input_schema)? {
+                        Expr::AggregateFunction {
+                            name,
+                            mut args,
+                            return_type,
+                        } => (name, args.remove(0), return_type),
+                        _ => {
+                            return Err(DataFusionError::Plan(format!(
+                                "DISTINCT can only be used in aggregate functions, found '{}'",
+                                id
+                            )))
+                        }
+                    }
+                }
+                _ => match self.sql_to_rex(e, &input_schema)? {
+                    Expr::AggregateFunction { .. } => {
+                        return Err(DataFusionError::Plan(String::from(
+                            "Aggregate functions with DISTINCT can't be combined with other \
+                             aggregate functions",
+                        )))
+                    }
+                    _ => continue,
+                },
+            };
+            if keys.len() == group_count {
+                keys.push(arg);
+            } else if keys[group_count] != arg {
+                return Err(DataFusionError::Plan(String::from(
+                    "Aggregate functions with DISTINCT must all have the same argument",
+                )));
+            }
+            aggr_expr.push(Expr::AggregateFunction {
+                name,
+                args: vec![Expr::Column(group_count)],
+                return_type,
+            });
+        }
+
+        let selection = Rc::new(LogicalPlan::Selection {
+            expr: Expr::IsNotNull(Rc::new(keys[group_count].clone())),
+            input,
+        });
+        let projection_schema = Rc::new(Schema::new(exprlist_to_fields(&keys, &input_schema)));
+        let projection = Rc::new(LogicalPlan::Projection {
+            expr: keys,
+            input: selection,
+            schema: projection_schema.clone(),
+        });
+        let group_expr: Vec<Expr> = (0..group_count).map(Expr::Column).collect();
+        let mut all_fields = group_expr.clone();
+        all_fields.extend(aggr_expr.iter().cloned());
+        let aggr_schema = Schema::new(exprlist_to_fields(&all_fields, &projection_schema));
+        Ok(Rc::new(LogicalPlan::Aggregate {
+            input: Rc::new(plan_distinct(projection)),
+            group_expr,
+            aggr_expr,
+            schema: Rc::new(aggr_schema),
+        }))
+    }
+
+    /// Generate a window expression from a SQL window function call
+    fn sql_to_window_expr(&self, sql: &ASTNode, schema: &Schema) -> Result<WindowExpr> {
+        match *sql {
+            ASTNode::SQLWindowFunction {
+                ref id,
+                ref args,
+                ref partition_by,
+                ref order_by,
+                ref frame,
+                ignore_nulls,
+            } => {
+                let function = match WindowFunction::from_name(id) {
+                    Some(f) => f,
+                    None => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Invalid window function '{}'",
+                            id
+                        )))
+                    }
+                };
+                let args = args
+                    .iter()
+                    .map(|a| match *a {
+                        // COUNT(*) is translated to COUNT(first_column) as for aggregates
+                        ASTNode::SQLWildcard => Ok(Expr::Column(0)),
+                        _ => self.sql_to_rex(a, schema),
+                    })
+                    .collect::<Result<Vec<Expr>>>()?;
+                let arg_types: Vec<DataType> = args.iter().map(|a| a.get_type(schema)).collect();
+                let return_type = function.return_type(&arg_types)?;
+                if ignore_nulls && !function.supports_null_treatment() {
+                    return Err(DataFusionError::Plan(format!(
+                        "IGNORE NULLS is not supported by window function '{}'",
+                        id
+                    )));
+                }
+                // the bucket count of NTILE and the row number of NTH_VALUE are constants
+                let parameter = match function {
+                    WindowFunction::Ntile => Some(&args[0]),
+                    WindowFunction::NthValue => Some(&args[1]),
+                    _ => None,
+                };
+                match parameter {
+                    Some(&Expr::Literal(ScalarValue::Int64(n))) if n > 0 => {}
+                    Some(other) => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Window function '{}' requires a positive integer literal but \
+                             found {:?}",
+                            id, other
+                        )))
+                    }
+                    None => {}
+                }
+                let partition_by = partition_by
+                    .iter()
+                    .map(|e| self.sql_to_rex(e, schema))
+                    .collect::<Result<Vec<Expr>>>()?;
+                let order_by = order_by
+                    .iter()
+                    .map(|e| self.sql_to_rex(e, schema))
+                    .collect::<Result<Vec<Expr>>>()?;
+                let frame = match *frame {
+                    Some(ref frame) => window_frame(frame, &order_by, schema)?,
+                    None => WindowFrame::default_for(!order_by.is_empty()),
+                };
+                Ok(WindowExpr {
+                    name: id.clone(),
+                    args,
+                    partition_by,
+                    order_by,
+                    frame,
+                    ignore_nulls,
+                    return_type,
+                })
+            }
+            _ => Err(DataFusionError::Plan(format!(
+                "Expected a window function but found {:?}",
+                sql
+            ))),
+        }
+    }
+
+    /// Plan `window(ts, width [, slide])`, which groups rows by the time windows of the given
+    /// width that contain their timestamp. Windows start every `slide`, which defaults to the
+    /// width so that windows don't overlap.
+    fn time_window_to_rex(&self, args: &Vec<ASTNode>, schema: &Schema) -> Result<Expr> {
+        if args.len() != 2 && args.len() != 3 {
+            return Err(DataFusionError::Plan(format!(
+                "window requires 2 or 3 arguments but {} were provided",
+                args.len()
+            )));
+        }
+        let ts = self.sql_to_rex(&args[0], schema)?;
+        if ts.get_type(schema) != DataType::Int64 {
+            return Err(DataFusionError::Plan(format!(
+                "window timestamps must be Int64 milliseconds: {:?}",
+                ts
+            )));
+        }
+        let intervals = args[1..]
+            .iter()
+            .map(|a| match a {
+                ASTNode::SQLLiteralString(s) => parse_interval(s),
+                ASTNode::SQLTypedLiteral {
+                    literal_type: SQLTypedLiteralType::Interval(unit),
+                    value,
+                } => match interval_millis(unit, value)? {
+                    n if n > 0 => Ok(n),
+                    _ => Err(DataFusionError::Plan(format!(
+                        "window interval must be positive: {:?}",
+                        a
+                    ))),
+                },
+                _ => Err(DataFusionError::Plan(format!(
+                    "window interval must be a string literal such as '5 minutes': {:?}",
+                    a
+                ))),
+            })
+            .collect::<Result<Vec<i64>>>()?;
+        let width = intervals[0];
+        let slide = *intervals.get(1).unwrap_or(&width);
+        if slide > width {
+            return Err(DataFusionError::Plan(String::from(
+                "window slide can't be longer than its width",
+            )));
+        }
+        Ok(Expr::ScalarFunction {
+            name: TIME_WINDOW_FUNCTION.to_string(),
+            args: vec![
+                ts,
+                Expr::Literal(ScalarValue::Int64(width)),
+                Expr::Literal(ScalarValue::Int64(slide)),
+            ],
+            return_type: DataType::Int64,
+        })
+    }
+
+    /// Rewrite the MySQL and Oracle style conditional functions IF, IFNULL, NVL, COALESCE and
+    /// DECODE as CASE expressions
+    fn conditional_to_rex(
+        &self,
+        name: &str,
+        args: Vec<Expr>,
+        schema: &Schema,
+    ) -> Result<Expr> {
+        let (when_then, else_expr) = match name {
+            "if" => {
+                if args.len() != 3 {
+                    return Err(DataFusionError::Plan(format!(
+                        "IF requires 3 arguments but {} were provided",
+                        args.len()
+                    )));
+                }
+                if args[0].get_type(schema) != DataType::Boolean {
+                    return Err(DataFusionError::Plan(format!(
+                        "IF condition must be boolean: {:?}",
+                        args[0]
+                    )));
+                }
+                (vec![(args[0].clone(), args[1].clone())], Some(args[2].clone()))
+            }
+            "ifnull" | "nvl" | "coalesce" => {
+                if args.is_empty() || (name != "coalesce" && args.len() != 2) {
+                    return Err(DataFusionError::Plan(format!(
+                        "{} was called with the wrong number of arguments",
+                        name.to_uppercase()
+                    )));
+                }
+                let (last, rest) = args.split_last().unwrap();
+                let when_then = rest
+                    .iter()
+                    .map(|a| (Expr::IsNotNull(Rc::new(a.clone())), a.clone()))
+                    .collect();
+                (when_then, Some(last.clone()))
+            }
+            "decode" => {
+                // DECODE(expr, search1, result1, [search2, result2, ...] [, default])
+                if args.len() < 3 {
+                    return Err(DataFusionError::Plan(format!(
+                        "DECODE requires at least 3 arguments but {} were provided",
+                        args.len()
+                    )));
+                }
+                let expr_type = args[0].get_type(schema);
+                let mut when_then = vec![];
+                for pair in args[1..].chunks(2).filter(|c| c.len() == 2) {
+                    let search_type = pair[0].get_type(schema);
+                    let supertype = match get_supertype(&expr_type, &search_type) {
+                        Some(t) => t,
+                        None => {
+                            return Err(DataFusionError::Plan(format!(
+                                "Cannot compare {:?} with {:?} in DECODE",
+                                expr_type, search_type
+                            )))
+                        }
+                    };
+                    let when = args[0]
+                        .cast_to(&supertype, schema)?
+                        .eq(&pair[0].cast_to(&supertype, schema)?);
+                    when_then.push((when, pair[1].clone()));
+                }
+                let else_expr = if args.len() % 2 == 0 {
+                    Some(args[args.len() - 1].clone())
+                } else {
+                    None
+                };
+                (when_then, else_expr)
+            }
+            _ => {
+                return Err(DataFusionError::Plan(format!(
+                    "Invalid conditional function '{}'",
+                    name
+                )))
+            }
+        };
+
+        // all results are cast to a common type
+        let mut result_type = when_then[0].1.get_type(schema);
+        for t in when_then
+            .iter()
+            .map(|wt| &wt.1)
+            .chain(else_expr.iter())
+            .map(|e| e.get_type(schema))
+        {
+            result_type = match get_supertype(&result_type, &t) {
+                Some(supertype) => supertype,
+                None => {
+                    return Err(DataFusionError::Plan(format!(
+                        "No common type for {} results {:?} and {:?}",
+                        name.to_uppercase(),
+                        result_type,
+                        t
+                    )))
+                }
+            };
+        }
+
+        let when_then = when_then
+            .into_iter()
+            .map(|(when, then)| Ok((Rc::new(when), Rc::new(then.cast_to(&result_type, schema)?))))
+            .collect::<Result<Vec<(Rc<Expr>, Rc<Expr>)>>>()?;
+        let else_expr = match else_expr {
+            Some(e) => Some(Rc::new(e.cast_to(&result_type, schema)?)),
+            None => None,
+        };
+
+        Ok(Expr::Case {
+            when_then,
+            else_expr,
+        })
+    }
+}
+
+/// Convert a window frame, checking that its bounds are in order and that offsets suit the
+/// frame units. RANGE offsets are measured on the single numeric ORDER BY expression.
+fn window_frame(frame: &SQLWindowFrame, order_by: &[Expr], schema: &Schema) -> Result<WindowFrame> {
+    fn position(bound: &WindowFrameBound) -> u8 {
+        match *bound {
+            WindowFrameBound::UnboundedPreceding => 0,
+            WindowFrameBound::Preceding(_) => 1,
+            WindowFrameBound::CurrentRow => 2,
+            WindowFrameBound::Following(_) => 3,
+            WindowFrameBound::UnboundedFollowing => 4,
+        }
+    }
+
+    let units = match frame.units {
+        SQLWindowFrameUnits::Rows => WindowFrameUnits::Rows,
+        SQLWindowFrameUnits::Range => WindowFrameUnits::Range,
+        SQLWindowFrameUnits::Groups => WindowFrameUnits::Groups,
+    };
+    let start = window_frame_bound(&frame.start, units)?;
+    let end = window_frame_bound(&frame.end, units)?;
+    if start == WindowFrameBound::UnboundedFollowing
+        || end == WindowFrameBound::UnboundedPreceding
+        || position(&start) > position(&end)
+    {
+        return Err(DataFusionError::Plan(format!(
+            "Invalid window frame from {} to {}",
+            start, end
+        )));
+    }
+
+    let has_offset = |b: &WindowFrameBound| match *b {
+        WindowFrameBound::Preceding(_) | WindowFrameBound::Following(_) => true,
+        _ => false,
+    };
+    if units == WindowFrameUnits::Groups && order_by.is_empty() {
+        return Err(DataFusionError::Plan(String::from(
+            "GROUPS window frames require ORDER BY",
+        )));
+    }
+    if units == WindowFrameUnits::Range && (has_offset(&start) || has_offset(&end)) {
+        let numeric = order_by.len() == 1 && match order_by[0].get_type(schema) {
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64 => true,
+            _ => false,
+        };
+        if !numeric {
+            return Err(DataFusionError::Plan(String::from(
+                "RANGE window frames with offsets require a single numeric ORDER BY expression",
+            )));
+        }
+    }
+
+    let exclusion = match frame.exclusion {
+        SQLWindowFrameExclusion::NoOthers => WindowFrameExclusion::NoOthers,
+        SQLWindowFrameExclusion::CurrentRow => WindowFrameExclusion::CurrentRow,
+        SQLWindowFrameExclusion::Group => WindowFrameExclusion::Group,
+        SQLWindowFrameExclusion::Ties => WindowFrameExclusion::Ties,
+    };
+    Ok(WindowFrame {
+        units,
+        start,
+        end,
+        exclusion,
+    })
+}
+
+/// Convert a window frame bound. ROWS and GROUPS offsets are counts, and RANGE offsets are
+/// numbers or intervals such as `'1 day'`, which are converted to milliseconds to be compared
+/// with timestamps.
+fn window_frame_bound(
+    bound: &SQLWindowFrameBound,
+    units: WindowFrameUnits,
+) -> Result<WindowFrameBound> {
+    let offset = |n: &ASTNode| -> Result<f64> {
+        let value = match (n, units) {
+            (&ASTNode::SQLLiteralLong(n), _) => n as f64,
+            (&ASTNode::SQLLiteralDouble(n), WindowFrameUnits::Range) => n,
+            (&ASTNode::SQLLiteralString(ref s), WindowFrameUnits::Range) => {
+                parse_interval(s).map_err(|_| {
+                    DataFusionError::Plan(format!("Invalid interval '{}' in window frame", s))
+                })? as f64
+            }
+            (
+                &ASTNode::SQLTypedLiteral {
+                    literal_type: SQLTypedLiteralType::Interval(ref unit),
+                    ref value,
+                },
+                WindowFrameUnits::Range,
+            ) => interval_millis(unit, value)? as f64,
+            _ => {
+                return Err(DataFusionError::Plan(format!(
+                    "Invalid {:?} window frame offset {:?}",
+                    units, n
+                )))
+            }
+        };
+        if value < 0.0 {
+            return Err(DataFusionError::Plan(format!(
+                "Window frame offsets can't be negative but found {}",
+                value
+            )));
+        }
+        Ok(value)
+    };
+    Ok(match *bound {
+        SQLWindowFrameBound::UnboundedPreceding => WindowFrameBound::UnboundedPreceding,
+        SQLWindowFrameBound::Preceding(ref n) => WindowFrameBound::Preceding(offset(n)?),
+        SQLWindowFrameBound::CurrentRow => WindowFrameBound::CurrentRow,
+        SQLWindowFrameBound::Following(ref n) => WindowFrameBound::Following(offset(n)?),
+        SQLWindowFrameBound::UnboundedFollowing => WindowFrameBound::UnboundedFollowing,
+    })
 }
 
 /// Convert SQL data type to relational representation of data type
@@ -367,12 +1909,17 @@ pub fn convert_data_type(sql: &SQLType) -> DataType {
         SQLType::Float32 => DataType::Float64,
         SQLType::Double64 => DataType::Float64,
         SQLType::Utf8(_) => DataType::Utf8,
+        // arrow does not have binary or large-offset types yet so BYTEA columns are read
+        // into the same list array representation as utf8 strings. Only binary values that
+        // are valid utf8 are supported until arrow has a binary array type.
+        SQLType::Binary => DataType::Utf8,
     }
 }
 
 pub fn expr_to_field(e: &Expr, input_schema: &Schema) -> Field {
     match e {
         Expr::Column(i) => input_schema.columns()[*i].clone(),
+        Expr::OuterColumn { ref data_type, .. } => Field::new("outer", data_type.clone(), true),
         Expr::Literal(ref lit) => Field::new("lit", lit.get_datatype(), true),
         Expr::ScalarFunction {
             ref name,
@@ -385,27 +1932,677 @@ pub fn expr_to_field(e: &Expr, input_schema: &Schema) -> Field {
             ..
         } => Field::new(name, return_type.clone(), true),
         Expr::Cast { ref data_type, .. } => Field::new("cast", data_type.clone(), true),
+        Expr::Case { .. } => Field::new("case", e.get_type(input_schema), true),
+        Expr::Not(_) => Field::new("not", DataType::Boolean, true),
+        Expr::QuantifiedComparison { .. } => {
+            Field::new("quantified_comparison", DataType::Boolean, true)
+        }
+        Expr::BinaryExpr { .. } => Field::new("binary_expr", e.get_type(input_schema), true),
+        _ => unimplemented!("Cannot determine schema type for expression {:?}", e),
+    }
+}
+
+pub fn exprlist_to_fields(expr: &Vec<Expr>, input_schema: &Schema) -> Vec<Field> {
+    expr.iter()
+        .map(|e| expr_to_field(e, input_schema))
+        .collect()
+}
+
+/// Determine the volatility of an expression, which is the most volatile of any function that
+/// it calls. Unknown functions are assumed to be volatile.
+pub fn expr_volatility(e: &Expr, schema_provider: &SchemaProvider) -> Volatility {
+    match e {
+        Expr::Column(_) | Expr::Literal(_) | Expr::OuterColumn { .. } => Volatility::Immutable,
+        Expr::BinaryExpr { left, right, .. } => ::std::cmp::max(
+            expr_volatility(left, schema_provider),
+            expr_volatility(right, schema_provider),
+        ),
+        Expr::IsNull(expr) | Expr::IsNotNull(expr) | Expr::Not(expr) => {
+            expr_volatility(expr, schema_provider)
+        }
+        // the rows of a subquery can change between queries
+        Expr::QuantifiedComparison { expr, .. } => {
+            ::std::cmp::max(expr_volatility(expr, schema_provider), Volatility::Stable)
+        }
+        Expr::Cast { expr, .. } | Expr::Sort { expr, .. } => {
+            expr_volatility(expr, schema_provider)
+        }
+        Expr::ScalarFunction { name, args, .. } => {
+            let volatility = match schema_provider.get_function_meta(&name.to_lowercase()) {
+                Some(fm) => fm.volatility(),
+                None => Volatility::Volatile,
+            };
+            args.iter()
+                .map(|a| expr_volatility(a, schema_provider))
+                .fold(volatility, ::std::cmp::max)
+        }
+        Expr::AggregateFunction { args, .. } => args
+            .iter()
+            .map(|a| expr_volatility(a, schema_provider))
+            .fold(Volatility::Immutable, ::std::cmp::max),
+        Expr::Case {
+            when_then,
+            else_expr,
+        } => when_then
+            .iter()
+            .flat_map(|wt| vec![wt.0.clone(), wt.1.clone()])
+            .chain(else_expr.iter().cloned())
+            .map(|e| expr_volatility(&e, schema_provider))
+            .fold(Volatility::Immutable, ::std::cmp::max),
+    }
+}
+
+/// Plan a WHERE clause. Conditions of the form `column = ANY (subquery)` on an uncorrelated
+/// subquery become semi joins with the subquery, and the other conditions filter the result.
+/// Evaluate window functions over a relation, appending a column for each of them
+fn plan_window(input: Rc<LogicalPlan>, window_expr: Vec<WindowExpr>) -> Rc<LogicalPlan> {
+    let mut fields = input.schema().columns().clone();
+    fields.extend(
+        window_expr
+            .iter()
+            .map(|w| Field::new(&w.name, w.return_type.clone(), true)),
+    );
+    Rc::new(LogicalPlan::Window {
+        input,
+        window_expr,
+        schema: Rc::new(Schema::new(fields)),
+    })
+}
+
+/// Remove the duplicate rows of a relation
+fn plan_distinct(input: Rc<LogicalPlan>) -> LogicalPlan {
+    let schema = input.schema().clone();
+    LogicalPlan::Deduplicate {
+        keys: (0..schema.columns().len()).map(Expr::Column).collect(),
+        order_by: vec![],
+        keep: DeduplicateKeep::First,
+        schema,
+        input,
+    }
+}
+
+/// Whether an expression is an aggregate function over distinct values, e.g. `COUNT(DISTINCT x)`
+fn is_distinct_aggregate(sql: &ASTNode) -> bool {
+    match *sql {
+        ASTNode::SQLFunction { distinct, .. } => distinct,
+        _ => false,
+    }
+}
+
+/// Whether an expression contains an aggregate function
+fn has_aggregate(e: &Expr) -> bool {
+    match *e {
+        Expr::AggregateFunction { .. } => true,
+        Expr::Column(_) | Expr::Literal(_) | Expr::OuterColumn { .. } => false,
+        Expr::IsNotNull(ref expr)
+        | Expr::IsNull(ref expr)
+        | Expr::Not(ref expr)
+        | Expr::Cast { ref expr, .. }
+        | Expr::Sort { ref expr, .. }
+        | Expr::QuantifiedComparison { ref expr, .. } => has_aggregate(expr),
+        Expr::BinaryExpr {
+            ref left,
+            ref right,
+            ..
+        } => has_aggregate(left) || has_aggregate(right),
+        Expr::ScalarFunction { ref args, .. } => args.iter().any(has_aggregate),
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            when_then
+                .iter()
+                .any(|&(ref when, ref then)| has_aggregate(when) || has_aggregate(then))
+                || else_expr.as_ref().map_or(false, |e| has_aggregate(e))
+        }
+    }
+}
+
+/// Rewrite a HAVING predicate that was planned against the input of an aggregate to refer to
+/// the columns of the aggregate instead, which are the grouping expressions followed by the
+/// aggregate functions. Aggregate functions that the aggregate doesn't compute yet are appended
+/// to `aggr_expr`.
+fn rewrite_having(e: &Expr, group_expr: &[Expr], aggr_expr: &mut Vec<Expr>) -> Result<Expr> {
+    if let Some(i) = group_expr.iter().position(|g| g == e) {
+        return Ok(Expr::Column(i));
+    }
+    let mut rewrite = |e: &Rc<Expr>| -> Result<Rc<Expr>> {
+        Ok(Rc::new(rewrite_having(e, group_expr, aggr_expr)?))
+    };
+    Ok(match *e {
+        Expr::AggregateFunction { .. } => {
+            let i = match aggr_expr.iter().position(|a| a == e) {
+                Some(i) => i,
+                None => {
+                    aggr_expr.push(e.clone());
+                    aggr_expr.len() - 1
+                }
+            };
+            Expr::Column(group_expr.len() + i)
+        }
+        Expr::Column(_) | Expr::OuterColumn { .. } => {
+            return Err(DataFusionError::Plan(String::from(
+                "HAVING can only refer to columns in GROUP BY or in aggregate functions",
+            )))
+        }
+        Expr::QuantifiedComparison {
+            ref subquery,
+            ..
+        } if subquery.is_correlated() => {
+            return Err(DataFusionError::Plan(String::from(
+                "Correlated subqueries can't be used in HAVING yet",
+            )))
+        }
+        Expr::Literal(_) => e.clone(),
+        Expr::IsNotNull(ref expr) => Expr::IsNotNull(rewrite(expr)?),
+        Expr::IsNull(ref expr) => Expr::IsNull(rewrite(expr)?),
+        Expr::Not(ref expr) => Expr::Not(rewrite(expr)?),
+        Expr::Cast {
+            ref expr,
+            ref data_type,
+        } => Expr::Cast {
+            expr: rewrite(expr)?,
+            data_type: data_type.clone(),
+        },
+        Expr::Sort {
+            ref expr,
+            asc,
+            nulls_first,
+        } => Expr::Sort {
+            expr: rewrite(expr)?,
+            asc,
+            nulls_first,
+        },
+        Expr::BinaryExpr {
+            ref left,
+            ref op,
+            ref right,
+        } => Expr::BinaryExpr {
+            left: rewrite(left)?,
+            op: op.clone(),
+            right: rewrite(right)?,
+        },
+        Expr::ScalarFunction {
+            ref name,
+            ref args,
+            ref return_type,
+        } => Expr::ScalarFunction {
+            name: name.clone(),
+            args: args
+                .iter()
+                .map(|a| rewrite_having(a, group_expr, aggr_expr))
+                .collect::<Result<Vec<Expr>>>()?,
+            return_type: return_type.clone(),
+        },
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            let mut rewritten = vec![];
+            for &(ref when, ref then) in when_then {
+                rewritten.push((rewrite(when)?, rewrite(then)?));
+            }
+            Expr::Case {
+                when_then: rewritten,
+                else_expr: match *else_expr {
+                    Some(ref e) => Some(rewrite(e)?),
+                    None => None,
+                },
+            }
+        }
+        Expr::QuantifiedComparison {
+            ref expr,
+            ref op,
+            all,
+            ref subquery,
+        } => Expr::QuantifiedComparison {
+            expr: rewrite(expr)?,
+            op: op.clone(),
+            all,
+            subquery: subquery.clone(),
+        },
+    })
+}
+
+/// Whether an expression is `COUNT(*)` or `COUNT(1)`, which count every row of their input
+fn is_count_star(sql: &ASTNode) -> bool {
+    match *sql {
+        ASTNode::SQLFunction {
+            ref id,
+            ref args,
+            distinct: false,
+        } if id.to_lowercase() == "count" && args.len() == 1 => match args[0] {
+            ASTNode::SQLWildcard | ASTNode::SQLLiteralLong(1) => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Plan a single row that holds a count of rows in each of the columns of `schema`
+fn plan_row_count(n: usize, schema: Rc<Schema>) -> Rc<LogicalPlan> {
+    let columns = (0..schema.columns().len())
+        .map(|_| Value::Column(Rc::new(Array::from(vec![n as u64]))))
+        .collect();
+    let batch = Rc::new(DefaultRecordBatch {
+        schema: schema.clone(),
+        data: columns,
+        row_count: 1,
+    }) as Rc<RecordBatch>;
+    Rc::new(LogicalPlan::MemTable {
+        schema,
+        batches: Rc::new(vec![batch]),
+    })
+}
+
+/// Find the window functions in an expression, in the order they first appear
+fn collect_window_functions(sql: &ASTNode, accum: &mut Vec<ASTNode>) {
+    match *sql {
+        ASTNode::SQLWindowFunction { .. } => {
+            if !accum.contains(sql) {
+                accum.push(sql.clone());
+            }
+        }
+        ASTNode::SQLBinaryExpr {
+            ref left,
+            ref right,
+            ..
+        } => {
+            collect_window_functions(left, accum);
+            collect_window_functions(right, accum);
+        }
+        ASTNode::SQLIsNull(ref e)
+        | ASTNode::SQLIsNotNull(ref e)
+        | ASTNode::SQLNested(ref e)
+        | ASTNode::SQLCast { expr: ref e, .. }
+        | ASTNode::SQLUnary { rex: ref e, .. } => collect_window_functions(e, accum),
+        ASTNode::SQLFunction { ref args, .. } => {
+            args.iter().for_each(|a| collect_window_functions(a, accum))
+        }
+        _ => {}
+    }
+}
+
+/// Replace the identifiers in the body of a SQL function that name its arguments with the
+/// expressions the function was called with. Subqueries are left as they are.
+fn substitute_arguments(sql: &ASTNode, arguments: &[(String, ASTNode)]) -> ASTNode {
+    let sub = |e: &ASTNode| Box::new(substitute_arguments(e, arguments));
+    let sub_all = |list: &Vec<ASTNode>| -> Vec<ASTNode> {
+        list.iter()
+            .map(|e| substitute_arguments(e, arguments))
+            .collect()
+    };
+    match *sql {
+        ASTNode::SQLIdentifier(ref id) => match arguments.iter().find(|a| a.0 == *id) {
+            Some(&(_, ref arg)) => arg.clone(),
+            None => sql.clone(),
+        },
+        ASTNode::SQLIsNull(ref e) => ASTNode::SQLIsNull(sub(e)),
+        ASTNode::SQLIsNotNull(ref e) => ASTNode::SQLIsNotNull(sub(e)),
+        ASTNode::SQLNested(ref e) => ASTNode::SQLNested(sub(e)),
+        ASTNode::SQLBinaryExpr {
+            ref left,
+            ref op,
+            ref right,
+        } => ASTNode::SQLBinaryExpr {
+            left: sub(left),
+            op: op.clone(),
+            right: sub(right),
+        },
+        ASTNode::SQLCast {
+            ref expr,
+            ref data_type,
+        } => ASTNode::SQLCast {
+            expr: sub(expr),
+            data_type: data_type.clone(),
+        },
+        ASTNode::SQLUnary {
+            ref operator,
+            ref rex,
+        } => ASTNode::SQLUnary {
+            operator: operator.clone(),
+            rex: sub(rex),
+        },
+        ASTNode::SQLFunction {
+            ref id,
+            ref args,
+            distinct,
+        } => ASTNode::SQLFunction {
+            id: id.clone(),
+            args: sub_all(args),
+            distinct,
+        },
+        ASTNode::SQLOrderBy {
+            ref expr,
+            asc,
+            nulls_first,
+        } => ASTNode::SQLOrderBy {
+            expr: sub(expr),
+            asc,
+            nulls_first,
+        },
+        ASTNode::SQLWindowFunction {
+            ref id,
+            ref args,
+            ref partition_by,
+            ref order_by,
+            ref frame,
+            ignore_nulls,
+        } => ASTNode::SQLWindowFunction {
+            id: id.clone(),
+            args: sub_all(args),
+            partition_by: sub_all(partition_by),
+            order_by: sub_all(order_by),
+            frame: frame.clone(),
+            ignore_nulls,
+        },
+        _ => sql.clone(),
+    }
+}
+
+fn plan_selection(input: Rc<LogicalPlan>, expr: Expr) -> Rc<LogicalPlan> {
+    let predicates = split_expr_conjunction(&expr);
+    let mut plan = input;
+    let mut remaining: Vec<Expr> = vec![];
+    for predicate in &predicates {
+        if let Expr::QuantifiedComparison {
+            expr: ref value,
+            op: Operator::Eq,
+            all: false,
+            ref subquery,
+        } = **predicate
+        {
+            if let (&Expr::Column(index), false) = (value.as_ref(), subquery.is_correlated()) {
+                let schema = plan.schema().clone();
+                plan = Rc::new(LogicalPlan::Join {
+                    left: plan,
+                    right: subquery.plan.clone(),
+                    join_type: JoinType::LeftSemi,
+                    on: vec![(index, 0)],
+                    null_equals_null: vec![false],
+                    build_side: None,
+                    schema,
+                });
+                continue;
+            }
+        }
+        remaining.push((*predicate).clone());
+    }
+    if remaining.len() == predicates.len() {
+        return Rc::new(LogicalPlan::Selection {
+            expr: expr.clone(),
+            input: plan,
+        });
+    }
+    let mut remaining = remaining.into_iter();
+    match remaining.next() {
+        Some(first) => Rc::new(LogicalPlan::Selection {
+            expr: remaining.fold(first, |left, right| Expr::BinaryExpr {
+                left: Rc::new(left),
+                op: Operator::And,
+                right: Rc::new(right),
+            }),
+            input: plan,
+        }),
+        None => plan,
+    }
+}
+
+/// Split an expression into the conditions that are combined with AND
+fn split_expr_conjunction(expr: &Expr) -> Vec<&Expr> {
+    match *expr {
         Expr::BinaryExpr {
             ref left,
+            op: Operator::And,
+            ref right,
+        } => {
+            let mut predicates = split_expr_conjunction(left);
+            predicates.extend(split_expr_conjunction(right));
+            predicates
+        }
+        _ => vec![expr],
+    }
+}
+
+/// A column on one side of a join
+enum JoinSide {
+    Left(usize),
+    Right(usize),
+}
+
+/// Get the names of the tables in a FROM clause, for resolving qualified column names
+fn relation_names(relation: &ASTNode) -> Vec<String> {
+    match *relation {
+        ASTNode::SQLIdentifier(ref name) => vec![name.clone()],
+        // `schema.table` is qualified by its table name and an aliased relation by its alias
+        ASTNode::SQLCompoundIdentifier(ref parts) => parts.last().cloned().into_iter().collect(),
+        ASTNode::SQLTableAlias { ref alias, .. } => vec![alias.clone()],
+        ASTNode::SQLTableSample { ref relation, .. }
+        | ASTNode::SQLTableSnapshot { ref relation, .. } => relation_names(relation),
+        ASTNode::SQLLateralJoin { ref left, .. } => relation_names(left),
+        ASTNode::SQLJoin {
+            ref left,
+            ref right,
+            ..
+        } => {
+            let mut names = relation_names(left);
+            names.extend(relation_names(right));
+            names
+        }
+        _ => vec![],
+    }
+}
+
+/// Get the value of a typed literal in milliseconds, as dates and times are represented:
+/// dates and timestamps since the epoch, times of day since midnight and intervals as their
+/// length
+fn typed_literal_millis(literal_type: &SQLTypedLiteralType, value: &str) -> Result<i64> {
+    let (name, formats): (&str, &[&str]) = match *literal_type {
+        SQLTypedLiteralType::Date => ("DATE", &["%Y-%m-%d"]),
+        SQLTypedLiteralType::Time => ("TIME", &["%H:%M:%S", "%H:%M:%S.%f", "%H:%M"]),
+        SQLTypedLiteralType::Timestamp => (
+            "TIMESTAMP",
+            &[
+                "%Y-%m-%d %H:%M:%S",
+                "%Y-%m-%d %H:%M:%S.%f",
+                "%Y-%m-%dT%H:%M:%S",
+                "%Y-%m-%dT%H:%M:%S.%f",
+                "%Y-%m-%d",
+            ],
+        ),
+        SQLTypedLiteralType::Interval(ref unit) => return interval_millis(unit, value),
+    };
+    formats
+        .iter()
+        .filter_map(|f| parse_timestamp(value, f))
+        .next()
+        .ok_or_else(|| DataFusionError::Plan(format!("Invalid {} literal '{}'", name, value)))
+}
+
+/// Get the length of an interval in milliseconds. Years and months have no fixed length.
+fn interval_millis(unit: &SQLIntervalUnit, value: &str) -> Result<i64> {
+    let millis = match *unit {
+        SQLIntervalUnit::Second => 1_000,
+        SQLIntervalUnit::Minute => 60_000,
+        SQLIntervalUnit::Hour => 3_600_000,
+        SQLIntervalUnit::Day => 86_400_000,
+        SQLIntervalUnit::Year | SQLIntervalUnit::Month => {
+            return Err(DataFusionError::Plan(format!(
+                "Interval '{}' {:?} is not supported, intervals of years and months have no \
+                 fixed length",
+                value, unit
+            )))
+        }
+    };
+    match value.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok((n * millis as f64).round() as i64),
+        _ => Err(DataFusionError::Plan(format!("Invalid interval '{}'", value))),
+    }
+}
+
+/// Get the name of the table that a relation of a FROM clause reads, if it is a table
+fn table_name(relation: &ASTNode) -> Option<&String> {
+    match *relation {
+        ASTNode::SQLIdentifier(ref name) => Some(name),
+        ASTNode::SQLCompoundIdentifier(ref parts) => parts.last(),
+        ASTNode::SQLTableAlias { ref alias, .. } => Some(alias),
+        ASTNode::SQLTableSample { ref relation, .. }
+        | ASTNode::SQLTableSnapshot { ref relation, .. } => table_name(relation),
+        _ => None,
+    }
+}
+
+/// Split a chain of inner joins into the relations it joins, in the order they are written,
+/// and the predicates of its join conditions
+fn flatten_inner_joins<'a>(
+    relation: &'a ASTNode,
+    relations: &mut Vec<&'a ASTNode>,
+    predicates: &mut Vec<&'a ASTNode>,
+) {
+    match *relation {
+        ASTNode::SQLJoin {
+            ref left,
+            ref right,
+            join_type: SQLJoinType::Inner,
+            ref on,
+        } => {
+            flatten_inner_joins(left, relations, predicates);
+            flatten_inner_joins(right, relations, predicates);
+            predicates.extend(split_conjunction(on));
+        }
+        _ => relations.push(relation),
+    }
+}
+
+/// Find which of the relations of a chain of joins the columns compared by a join predicate
+/// belong to
+fn predicate_sources(
+    predicate: &ASTNode,
+    names: &[Vec<String>],
+    plans: &[Rc<LogicalPlan>],
+    casing: IdentifierCasing,
+) -> Result<Vec<usize>> {
+    let operands = match *predicate {
+        ASTNode::SQLBinaryExpr {
+            ref left,
+            ref op,
+            ref right,
+        } if *op == SQLOperator::Eq || *op == SQLOperator::IsNotDistinctFrom =>
+        {
+            vec![left, right]
+        }
+        _ => vec![],
+    };
+    let mut sources = vec![];
+    for operand in operands {
+        match **operand {
+            ASTNode::SQLIdentifier(ref name) => for (i, plan) in plans.iter().enumerate() {
+                if find_column(plan.schema(), name, casing)?.is_some() {
+                    sources.push(i);
+                }
+            },
+            ASTNode::SQLCompoundIdentifier(ref parts) if parts.len() == 2 => {
+                sources.extend(names.iter().position(|n| n.contains(&parts[0])));
+            }
+            _ => {}
+        }
+    }
+    Ok(sources)
+}
+
+/// Split a join condition into the predicates that are combined with AND
+fn split_conjunction(expr: &ASTNode) -> Vec<&ASTNode> {
+    match *expr {
+        ASTNode::SQLBinaryExpr {
+            ref left,
+            op: SQLOperator::And,
             ref right,
-            ..
         } => {
-            let left_type = left.get_type(input_schema);
-            let right_type = right.get_type(input_schema);
-            Field::new(
-                "binary_expr",
-                get_supertype(&left_type, &right_type).unwrap(),
-                true,
-            )
+            let mut predicates = split_conjunction(left);
+            predicates.extend(split_conjunction(right));
+            predicates
         }
-        _ => unimplemented!("Cannot determine schema type for expression {:?}", e),
+        ASTNode::SQLNested(ref e) => split_conjunction(e),
+        _ => vec![expr],
     }
 }
 
-pub fn exprlist_to_fields(expr: &Vec<Expr>, input_schema: &Schema) -> Vec<Field> {
-    expr.iter()
-        .map(|e| expr_to_field(e, input_schema))
-        .collect()
+/// Find the column that an identifier refers to
+fn find_column(schema: &Schema, name: &str, casing: IdentifierCasing) -> Result<Option<usize>> {
+    let columns = schema.columns();
+    if let Some(index) = columns.iter().position(|c| c.name() == name) {
+        return Ok(Some(index));
+    }
+    if casing != IdentifierCasing::CaseInsensitive {
+        return Ok(None);
+    }
+    let matches: Vec<usize> = (0..columns.len())
+        .filter(|&i| columns[i].name().to_lowercase() == name.to_lowercase())
+        .collect();
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(Some(matches[0])),
+        _ => Err(DataFusionError::Plan(format!(
+            "Identifier '{}' is ambiguous, it matches columns {}",
+            name,
+            matches
+                .iter()
+                .map(|&i| columns[i].name().as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// Find which side of a join a column in the join condition belongs to
+fn resolve_join_column(
+    expr: &ASTNode,
+    left_tables: &[String],
+    left_schema: &Schema,
+    right_tables: &[String],
+    right_schema: &Schema,
+    casing: IdentifierCasing,
+) -> Result<JoinSide> {
+    let find = |schema: &Schema, name: &str| find_column(schema, name, casing);
+    match *expr {
+        ASTNode::SQLIdentifier(ref name) => {
+            match (find(left_schema, name)?, find(right_schema, name)?) {
+                (Some(_), Some(_)) => Err(DataFusionError::Plan(format!(
+                    "Column '{}' in join condition is ambiguous, qualify it with a table name",
+                    name
+                ))),
+                (Some(i), None) => Ok(JoinSide::Left(i)),
+                (None, Some(i)) => Ok(JoinSide::Right(i)),
+                (None, None) => Err(DataFusionError::Plan(format!(
+                    "Invalid identifier '{}' in join condition",
+                    name
+                ))),
+            }
+        }
+        ASTNode::SQLCompoundIdentifier(ref parts) if parts.len() == 2 => {
+            let (table, name) = (&parts[0], &parts[1]);
+            let side = if left_tables.contains(table) {
+                find(left_schema, name)?.map(JoinSide::Left)
+            } else if right_tables.contains(table) {
+                find(right_schema, name)?.map(JoinSide::Right)
+            } else {
+                return Err(DataFusionError::Plan(format!(
+                    "Unknown table '{}' in join condition",
+                    table
+                )));
+            };
+            side.ok_or_else(|| {
+                DataFusionError::Plan(format!(
+                    "Invalid identifier '{}.{}' in join condition",
+                    table, name
+                ))
+            })
+        }
+        _ => Err(DataFusionError::Plan(format!(
+            "Join conditions must reference columns but found {:?}",
+            expr
+        ))),
+    }
 }
 
 fn collect_expr(e: &Expr, accum: &mut HashSet<usize>) {
@@ -414,9 +2611,10 @@ fn collect_expr(e: &Expr, accum: &mut HashSet<usize>) {
             accum.insert(*i);
         }
         Expr::Cast { ref expr, .. } => collect_expr(expr, accum),
-        Expr::Literal(_) => {}
+        Expr::Literal(_) | Expr::OuterColumn { .. } => {}
         Expr::IsNotNull(ref expr) => collect_expr(expr, accum),
         Expr::IsNull(ref expr) => collect_expr(expr, accum),
+        Expr::Not(ref expr) => collect_expr(expr, accum),
         Expr::BinaryExpr {
             ref left,
             ref right,
@@ -432,6 +2630,26 @@ fn collect_expr(e: &Expr, accum: &mut HashSet<usize>) {
             args.iter().for_each(|e| collect_expr(e, accum));
         }
         Expr::Sort { ref expr, .. } => collect_expr(expr, accum),
+        Expr::QuantifiedComparison {
+            ref expr,
+            ref subquery,
+            ..
+        } => {
+            collect_expr(expr, accum);
+            accum.extend(subquery.outer_columns.iter().cloned());
+        }
+        Expr::Case {
+            ref when_then,
+            ref else_expr,
+        } => {
+            for &(ref when, ref then) in when_then {
+                collect_expr(when, accum);
+                collect_expr(then, accum);
+            }
+            if let Some(ref e) = *else_expr {
+                collect_expr(e, accum);
+            }
+        }
     }
 }
 
@@ -469,6 +2687,37 @@ pub fn push_down_projection(
                 input: push_down_projection(&input, &accum),
             })
         }
+        LogicalPlan::Sample {
+            ref method,
+            fraction,
+            seed,
+            ref input,
+            ref schema,
+        } => Rc::new(LogicalPlan::Sample {
+            method: method.clone(),
+            fraction: *fraction,
+            seed: *seed,
+            input: push_down_projection(&input, projection),
+            schema: schema.clone(),
+        }),
+        LogicalPlan::Deduplicate {
+            ref input,
+            ref keys,
+            ref order_by,
+            ref keep,
+            ref schema,
+        } => {
+            let mut accum: HashSet<usize> = projection.clone();
+            keys.iter().for_each(|e| collect_expr(e, &mut accum));
+            order_by.iter().for_each(|e| collect_expr(e, &mut accum));
+            Rc::new(LogicalPlan::Deduplicate {
+                input: push_down_projection(&input, &accum),
+                keys: keys.clone(),
+                order_by: order_by.clone(),
+                keep: keep.clone(),
+                schema: schema.clone(),
+            })
+        }
         LogicalPlan::TableScan {
             ref schema_name,
             ref table_name,
@@ -512,13 +2761,30 @@ pub fn push_down_projection(
         LogicalPlan::Projection { .. } => plan.clone(),
         LogicalPlan::Limit { .. } => plan.clone(),
         LogicalPlan::Sort { .. } => plan.clone(),
+        LogicalPlan::Window { .. } => plan.clone(),
+        LogicalPlan::Join { .. } => plan.clone(),
+        LogicalPlan::LateralJoin { .. } => plan.clone(),
+        LogicalPlan::Union {
+            ref inputs,
+            ref schema,
+        } => Rc::new(LogicalPlan::Union {
+            inputs: inputs
+                .iter()
+                .map(|input| push_down_projection(input, projection))
+                .collect(),
+            schema: schema.clone(),
+        }),
         LogicalPlan::EmptyRelation { .. } => plan.clone(),
+        LogicalPlan::MemTable { .. } => plan.clone(),
+        LogicalPlan::StreamScan { .. } => plan.clone(),
+        LogicalPlan::GenerateSeries { .. } => plan.clone(),
     }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use super::super::functions::regex::RegexpExtractAllFunction;
     use super::super::sqlparser::*;
     use super::*;
 
@@ -609,6 +2875,57 @@ mod tests {
         );
     }
 
+    #[test]
+    fn select_aggregate_with_having() {
+        quick_test(
+            "SELECT state, MAX(salary) FROM person GROUP BY state HAVING MAX(salary) > 1.5",
+            "Selection: #1 Gt Float64(1.5)\
+             \n  Aggregate: groupBy=[[#4]], aggr=[[MAX(#5)]]\
+             \n    TableScan: person projection=None",
+        );
+
+        // aggregate functions only used by HAVING are removed from the output
+        quick_test(
+            "SELECT state FROM person GROUP BY state HAVING MIN(salary) > 1.5 AND state = 'CO'",
+            "Projection: #0\
+             \n  Selection: #1 Gt Float64(1.5) And #0 Eq Utf8(\"CO\")\
+             \n    Aggregate: groupBy=[[#4]], aggr=[[MIN(#5)]]\
+             \n      TableScan: person projection=None",
+        );
+
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT state FROM person HAVING state = 'CO'",
+            "SELECT state, MAX(age) FROM person GROUP BY state HAVING salary > 1.5",
+        ] {
+            let ast = Parser::parse_sql(sql.to_string()).unwrap();
+            assert!(planner.sql_to_rel(&ast).is_err());
+        }
+    }
+
+    #[test]
+    fn select_distinct() {
+        quick_test(
+            "SELECT DISTINCT state FROM person ORDER BY state",
+            "Sort: #0 ASC\
+             \n  Deduplicate: keys=[#0], keep=First, orderBy=[]\
+             \n    Projection: #4\
+             \n      TableScan: person projection=None",
+        );
+    }
+
+    #[test]
+    fn select_count_distinct_with_groupby() {
+        quick_test(
+            "SELECT state, COUNT(DISTINCT id) FROM person GROUP BY state",
+            "Aggregate: groupBy=[[#0]], aggr=[[COUNT(#1)]]\
+             \n  Deduplicate: keys=[#0, #1], keep=First, orderBy=[]\
+             \n    Projection: #4, #0\
+             \n      Selection: #0 IS NOT NULL\
+             \n        TableScan: person projection=None",
+        );
+    }
+
     #[test]
     fn select_count_one() {
         let sql = "SELECT COUNT(1) FROM person";
@@ -617,6 +2934,22 @@ mod tests {
         quick_test(sql, expected);
     }
 
+    #[test]
+    fn select_count_star_from_row_count() {
+        quick_test("SELECT COUNT(*), count(1) FROM orders", "MemTable: rows=1");
+        quick_test(
+            "SELECT COUNT(*) FROM orders WHERE amount > 1.5",
+            "Aggregate: groupBy=[[]], aggr=[[COUNT(#0)]]\
+             \n  Selection: #2 Gt Float64(1.5)\
+             \n    TableScan: orders projection=None",
+        );
+        quick_test(
+            "SELECT COUNT(person_id) FROM orders",
+            "Aggregate: groupBy=[[]], aggr=[[COUNT(#1)]]\
+             \n  TableScan: orders projection=None",
+        );
+    }
+
     #[test]
     fn select_scalar_func() {
         let sql = "SELECT sqrt(age) FROM person";
@@ -625,6 +2958,148 @@ mod tests {
         quick_test(sql, expected);
     }
 
+    #[test]
+    fn select_binary_expr_types() {
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let sql = "SELECT first_name LIKE 'J%', age > 21, age + salary FROM person";
+        let plan = planner
+            .sql_to_rel(&Parser::parse_sql(sql.to_string()).unwrap())
+            .unwrap();
+        let types: Vec<&DataType> = plan
+            .schema()
+            .columns()
+            .iter()
+            .map(|f| f.data_type())
+            .collect();
+        assert_eq!(
+            vec![&DataType::Boolean, &DataType::Boolean, &DataType::Float64],
+            types
+        );
+    }
+
+    #[test]
+    fn select_regexp_extract_all() {
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let sql = "SELECT regexp_extract_all(first_name, '(?P<initial>.)(.*)') FROM person";
+        let plan = planner
+            .sql_to_rel(&Parser::parse_sql(sql.to_string()).unwrap())
+            .unwrap();
+        assert_eq!(
+            &DataType::Struct(vec![
+                Field::new("initial", DataType::Utf8, true),
+                Field::new("c2", DataType::Utf8, true),
+            ]),
+            plan.schema().columns()[0].data_type()
+        );
+
+        let sql = "SELECT regexp_extract_all(first_name, last_name) FROM person";
+        assert!(planner
+            .sql_to_rel(&Parser::parse_sql(sql.to_string()).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn select_case_insensitive_identifiers() {
+        let sql = "SELECT FIRST_NAME FROM person WHERE Age > 18";
+        let ast = Parser::parse_sql(sql.to_string()).unwrap();
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        assert!(planner.sql_to_rel(&ast).is_err());
+
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}))
+            .with_identifier_casing(IdentifierCasing::CaseInsensitive);
+        assert_eq!(
+            "Projection: #1\
+             \n  Selection: CAST(#3 AS Int64) Gt Int64(18)\
+             \n    TableScan: person projection=None",
+            format!("{:?}", planner.sql_to_rel(&ast).unwrap())
+        );
+    }
+
+    #[test]
+    fn find_column_casing() {
+        let schema = Schema::new(vec![
+            Field::new("Id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("NAME", DataType::Utf8, false),
+        ]);
+        let sensitive = IdentifierCasing::CaseSensitive;
+        let insensitive = IdentifierCasing::CaseInsensitive;
+        assert_eq!(None, find_column(&schema, "id", sensitive).unwrap());
+        assert_eq!(Some(0), find_column(&schema, "id", insensitive).unwrap());
+        // an exact match is preferred over one that ignores case
+        assert_eq!(Some(2), find_column(&schema, "NAME", insensitive).unwrap());
+        assert!(find_column(&schema, "Name", insensitive).is_err());
+    }
+
+    #[test]
+    fn select_sql_func() {
+        let sql = "SELECT with_tax(age) FROM person";
+        let expected = "Projection: CAST(#3 AS Float64) Multiply Float64(1.5)\
+                        \n  TableScan: person projection=None";
+        quick_test(sql, expected);
+
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let ast = Parser::parse_sql("SELECT with_tax(age, 2) FROM person".to_string()).unwrap();
+        assert!(planner.sql_to_rel(&ast).is_err());
+    }
+
+    #[test]
+    fn select_logical_operators() {
+        quick_test(
+            "SELECT id FROM person WHERE NOT state = 'CO' AND age > 21 OR first_name = 'x'",
+            "Projection: #0\
+             \n  Selection: NOT #4 Eq Utf8(\"CO\") And CAST(#3 AS Int64) Gt Int64(21) \
+             Or #1 Eq Utf8(\"x\")\
+             \n    TableScan: person projection=None",
+        );
+    }
+
+    #[test]
+    fn select_nested_and_unary() {
+        quick_test(
+            "SELECT -age, (age + 1) * -2, -salary FROM person",
+            "Projection: CAST(Int8(0) AS Int32) Minus #3, \
+             CAST(#3 AS Int64) Plus Int64(1) Multiply Int64(-2), \
+             CAST(Int8(0) AS Float64) Minus #5\
+             \n  TableScan: person projection=None",
+        );
+
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let ast = Parser::parse_sql("SELECT -id FROM person".to_string()).unwrap();
+        assert!(planner.sql_to_rel(&ast).is_err());
+    }
+
+    #[test]
+    fn select_from_table_function() {
+        quick_test(
+            "SELECT generate_series FROM generate_series(1, 10, 3)",
+            "Projection: #0\
+             \n  GenerateSeries: start=1, stop=10, step=3, inclusive=true",
+        );
+        quick_test(
+            "SELECT \"range\" * 2 FROM range(5, -5, -1)",
+            "Projection: #0 Multiply Int64(2)\
+             \n  GenerateSeries: start=5, stop=-5, step=-1, inclusive=false",
+        );
+        quick_test(
+            "SELECT generate_series FROM generate_series(TIMESTAMP '1970-01-01', \
+             TIMESTAMP '1970-01-03', INTERVAL '1' DAY)",
+            "Projection: #0\
+             \n  GenerateSeries: start=0, stop=172800000, step=86400000, inclusive=true",
+        );
+
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT x FROM generate_series(1, 10, 0)",
+            "SELECT x FROM generate_series(1)",
+            "SELECT x FROM generate_series(1, 'a')",
+            "SELECT x FROM unnest(1, 2)",
+        ] {
+            let ast = Parser::parse_sql(sql.to_string()).unwrap();
+            assert!(planner.sql_to_rel(&ast).is_err());
+        }
+    }
+
     #[test]
     fn select_order_by() {
         let sql = "SELECT id FROM person ORDER BY id";
@@ -634,6 +3109,144 @@ mod tests {
         quick_test(sql, expected);
     }
 
+    #[test]
+    fn select_window_function() {
+        let sql = "SELECT id, AVG(salary) OVER (PARTITION BY state ORDER BY age \
+                   RANGE BETWEEN 5 PRECEDING AND 5 FOLLOWING EXCLUDE TIES) FROM person";
+        let expected = "Projection: #0, #6\
+                        \n  Window: AVG(#5) OVER (PARTITION BY [#4] ORDER BY [#3 ASC] \
+                        RANGE BETWEEN 5 PRECEDING AND 5 FOLLOWING EXCLUDE TIES)\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+
+        // the default frame depends on whether the window is ordered
+        let sql = "SELECT COUNT(id) OVER (ORDER BY age) FROM person";
+        let expected = "Projection: #6\
+                        \n  Window: COUNT(#0) OVER (ORDER BY [#3 ASC] \
+                        RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW)\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+
+        let sql = "SELECT NTH_VALUE(first_name, 2) IGNORE NULLS OVER (ORDER BY age), \
+                   NTILE(4) OVER (ORDER BY age) FROM person";
+        let expected = "Projection: #6, #7\
+                        \n  Window: NTH_VALUE(#1, Int64(2)) IGNORE NULLS OVER (ORDER BY [#3 ASC] \
+                        RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW), \
+                        NTILE(Int64(4)) OVER (ORDER BY [#3 ASC] \
+                        RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW)\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_window_function_invalid_frame() {
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT SUM(age) OVER (ORDER BY state RANGE 1 PRECEDING) FROM person",
+            "SELECT SUM(age) OVER (ORDER BY id ROWS 1 FOLLOWING) FROM person",
+            "SELECT SUM(age) OVER (GROUPS 1 PRECEDING) FROM person",
+            "SELECT SUM(age) OVER (ORDER BY id ROWS '1 day' PRECEDING) FROM person",
+            "SELECT SQRT(age) OVER (ORDER BY id) FROM person",
+            "SELECT NTILE(0) OVER (ORDER BY id) FROM person",
+            "SELECT NTH_VALUE(age, id) OVER (ORDER BY id) FROM person",
+            "SELECT SUM(age) IGNORE NULLS OVER (ORDER BY id) FROM person",
+        ] {
+            let ast = Parser::parse_sql(sql.to_string()).unwrap();
+            match planner.sql_to_rel(&ast) {
+                Err(DataFusionError::Plan(_)) => {}
+                other => panic!("expected a planning error for {} but got {:?}", sql, other),
+            }
+        }
+    }
+
+    #[test]
+    fn select_qualify_row_number_deduplicates() {
+        let sql = "SELECT id, first_name FROM person WHERE age > 18 \
+                   QUALIFY ROW_NUMBER() OVER (PARTITION BY state ORDER BY salary DESC) = 1";
+        let expected = "Projection: #0, #1\
+                        \n  Deduplicate: keys=[#4], keep=First, orderBy=[#5 DESC]\
+                        \n    Selection: CAST(#3 AS Int64) Gt Int64(18)\
+                        \n      TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_qualify_invalid() {
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT id FROM person QUALIFY age > 18",
+            "SELECT COUNT(id) FROM person QUALIFY RANK() OVER (ORDER BY age) = 1",
+            "SELECT RANK() OVER (ORDER BY age) FROM person WHERE RANK() OVER (ORDER BY age) = 1",
+        ] {
+            let ast = Parser::parse_sql(sql.to_string()).unwrap();
+            match planner.sql_to_rel(&ast) {
+                Err(DataFusionError::Plan(_)) => {}
+                other => panic!("expected a planning error for {} but got {:?}", sql, other),
+            }
+        }
+    }
+
+    #[test]
+    fn select_lateral_join() {
+        let sql = "SELECT first_name, amount FROM person CROSS JOIN LATERAL \
+                   (SELECT amount FROM orders WHERE person_id = id ORDER BY amount DESC LIMIT 1)";
+        let expected = "Projection: #1, #6\
+                        \n  LateralJoin: left_outer=false, correlated=true\
+                        \n    TableScan: person projection=None\
+                        \n    Limit: 1\
+                        \n      Sort: #0 DESC\
+                        \n        Projection: #2\
+                        \n          Selection: #1 Eq CAST(outer#0 AS Int64)\
+                        \n            TableScan: orders projection=None";
+        quick_test(sql, expected);
+
+        // the join condition becomes a filter on the subquery
+        let sql = "SELECT first_name, amount FROM person LEFT JOIN LATERAL \
+                   (SELECT amount FROM orders) ON amount > salary";
+        let expected = "Projection: #1, #6\
+                        \n  LateralJoin: left_outer=true, correlated=true\
+                        \n    TableScan: person projection=None\
+                        \n    Selection: #0 Gt outer#5\
+                        \n      Projection: #2\
+                        \n        TableScan: orders projection=None";
+        quick_test(sql, expected);
+    }
+
+    #[test]
+    fn select_quantified_comparison() {
+        let sql = "SELECT id FROM person WHERE salary > ALL \
+                   (SELECT amount FROM orders WHERE person_id > 2)";
+        let expected = "Projection: #0\
+                        \n  Selection: #5 Gt ALL (Projection: #2; Selection: #1 Gt Int64(2); \
+                        TableScan: orders projection=None)\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+
+        // both sides are cast to a common type
+        let sql = "SELECT id FROM person WHERE age < ANY (SELECT person_id FROM orders)";
+        let expected = "Projection: #0\
+                        \n  Selection: CAST(#3 AS Int64) Lt ANY (Projection: #1; \
+                        TableScan: orders projection=None)\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+
+        // a column that equals any row of an uncorrelated subquery becomes a semi join
+        let sql = "SELECT amount FROM orders WHERE order_id = ANY \
+                   (SELECT person_id FROM orders) AND person_id > 1";
+        let expected = "Projection: #2\
+                        \n  Selection: #1 Gt Int64(1)\
+                        \n    Join: type=LeftSemi, on=[#0 = #0]\
+                        \n      TableScan: orders projection=None\
+                        \n      Projection: #1\
+                        \n        TableScan: orders projection=None";
+        quick_test(sql, expected);
+
+        let sql = "SELECT id FROM person WHERE id = ANY (SELECT order_id, amount FROM orders)";
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let ast = Parser::parse_sql(sql.to_string()).unwrap();
+        assert!(planner.sql_to_rel(&ast).is_err());
+    }
+
     #[test]
     fn select_order_by_desc() {
         let sql = "SELECT id FROM person ORDER BY id DESC";
@@ -643,6 +3256,15 @@ mod tests {
         quick_test(sql, expected);
     }
 
+    #[test]
+    fn select_order_by_nulls() {
+        let sql = "SELECT id, age FROM person ORDER BY id NULLS LAST, age DESC NULLS LAST";
+        let expected = "Sort: #0 ASC NULLS LAST, #1 DESC\
+                        \n  Projection: #0, #3\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
     #[test]
     fn select_order_limit() {
         let sql = "SELECT id FROM person ORDER BY id DESC LIMIT 10";
@@ -662,6 +3284,41 @@ mod tests {
         quick_test(sql, expected);
     }
 
+    #[test]
+    fn select_ifnull() {
+        quick_test(
+            "SELECT IFNULL(first_name, 'unknown') FROM person",
+            "Projection: CASE WHEN #1 IS NOT NULL THEN #1 ELSE Utf8(\"unknown\") END\
+             \n  TableScan: person projection=None",
+        );
+    }
+
+    #[test]
+    fn select_decode() {
+        quick_test(
+            "SELECT DECODE(state, 'CO', 1, 'NY', 2, 0) FROM person",
+            "Projection: CASE WHEN #4 Eq Utf8(\"CO\") THEN Int64(1) \
+             WHEN #4 Eq Utf8(\"NY\") THEN Int64(2) ELSE Int64(0) END\
+             \n  TableScan: person projection=None",
+        );
+    }
+
+    #[test]
+    fn select_if_requires_boolean_condition() {
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        let ast = Parser::parse_sql("SELECT IF(age, 1, 2) FROM person".to_string()).unwrap();
+        assert!(planner.sql_to_rel(&ast).is_err());
+    }
+
+    #[test]
+    fn select_table_sample() {
+        let sql = "SELECT id FROM person TABLESAMPLE BERNOULLI (25) REPEATABLE (7)";
+        let expected = "Projection: #0\
+                        \n  Sample: method=Bernoulli, fraction=0.25, seed=7\
+                        \n    TableScan: person projection=None";
+        quick_test(sql, expected);
+    }
+
     #[test]
     fn test_collect_expr() {
         let mut accum: HashSet<usize> = HashSet::new();
@@ -726,6 +3383,120 @@ mod tests {
     //
     //    }
 
+    #[test]
+    fn test_expr_volatility() {
+        let provider = MockSchemaProvider {};
+        let sqrt = Expr::ScalarFunction {
+            name: "sqrt".to_string(),
+            args: vec![Expr::Column(5)],
+            return_type: DataType::Float64,
+        };
+        assert_eq!(Volatility::Immutable, expr_volatility(&sqrt, &provider));
+
+        let random = Expr::ScalarFunction {
+            name: "random".to_string(),
+            args: vec![],
+            return_type: DataType::Float64,
+        };
+        let expr = Expr::BinaryExpr {
+            left: Rc::new(sqrt),
+            op: Operator::Plus,
+            right: Rc::new(random),
+        };
+        assert_eq!(Volatility::Volatile, expr_volatility(&expr, &provider));
+        let random_meta = provider.get_function_meta("random").unwrap();
+        assert!(!random_meta.volatility().is_deterministic());
+        assert!(provider.get_function_meta("sqrt").unwrap().null_propagating());
+    }
+
+    #[test]
+    fn select_join() {
+        quick_test(
+            "SELECT first_name, amount FROM person JOIN orders ON id = person_id",
+            "Projection: #1, #8\
+             \n  Join: type=Inner, on=[#0 = #1]\
+             \n    TableScan: person projection=None\
+             \n    TableScan: orders projection=None",
+        );
+    }
+
+    #[test]
+    fn select_join_null_safe_qualified() {
+        quick_test(
+            "SELECT amount FROM person INNER JOIN orders \
+             ON orders.person_id IS NOT DISTINCT FROM person.id",
+            "Projection: #8\
+             \n  Join: type=Inner, on=[#0 IS NOT DISTINCT FROM #1]\
+             \n    TableScan: person projection=None\
+             \n    TableScan: orders projection=None",
+        );
+    }
+
+    #[test]
+    fn select_join_mixed_null_safety() {
+        quick_test(
+            "SELECT amount FROM person JOIN orders ON id = person_id \
+             AND age IS NOT DISTINCT FROM order_id",
+            "Projection: #8\
+             \n  Join: type=Inner, on=[#0 = #1, #3 IS NOT DISTINCT FROM #0]\
+             \n    TableScan: person projection=None\
+             \n    TableScan: orders projection=None",
+        );
+    }
+
+    #[test]
+    fn select_anti_join() {
+        quick_test(
+            "SELECT first_name FROM person LEFT ANTI JOIN orders ON id = person_id",
+            "Projection: #1\
+             \n  Join: type=LeftAnti, on=[#0 = #1]\
+             \n    TableScan: person projection=None\
+             \n    TableScan: orders projection=None",
+        );
+    }
+
+    #[test]
+    fn select_qualified_by_alias() {
+        quick_test(
+            "SELECT p.first_name, o.amount FROM person AS p JOIN orders o \
+             ON o.person_id = p.id WHERE p.state = 'CO'",
+            "Projection: #1, #8\
+             \n  Selection: #4 Eq Utf8(\"CO\")\
+             \n    Join: type=Inner, on=[#0 = #1]\
+             \n      TableScan: person projection=None\
+             \n      TableScan: orders projection=None",
+        );
+        quick_test(
+            "SELECT b.first_name FROM person a JOIN person b ON a.id = b.id",
+            "Projection: #7\
+             \n  Join: type=Inner, on=[#0 = #0]\
+             \n    TableScan: person projection=None\
+             \n    TableScan: person projection=None",
+        );
+
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT x.id FROM person p",
+            "SELECT person.id FROM person p",
+            "SELECT p.amount FROM person p JOIN orders o ON o.person_id = p.id",
+        ] {
+            let ast = Parser::parse_sql(sql.to_string()).unwrap();
+            assert!(planner.sql_to_rel(&ast).is_err());
+        }
+    }
+
+    #[test]
+    fn select_join_invalid_conditions() {
+        let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
+        for sql in &[
+            "SELECT amount FROM person JOIN orders ON id > person_id",
+            "SELECT amount FROM person JOIN orders ON id = age",
+        ] {
+            let ast = Parser::parse_sql(sql.to_string()).unwrap();
+            assert!(planner.sql_to_rel(&ast).is_err());
+        }
+    }
+
     /// Create logical plan, write with formatter, compare to expected output
     fn quick_test(sql: &str, expected: &str) {
         let planner = SqlToRel::new(Rc::new(MockSchemaProvider {}));
@@ -747,18 +3518,68 @@ mod tests {
                     Field::new("state", DataType::Utf8, false),
                     Field::new("salary", DataType::Float64, false),
                 ]))),
+                "orders" => Some(Rc::new(Schema::new(vec![
+                    Field::new("order_id", DataType::Int64, false),
+                    Field::new("person_id", DataType::Int64, true),
+                    Field::new("amount", DataType::Float64, false),
+                ]))),
                 _ => None,
             }
         }
 
         fn get_function_meta(&self, name: &str) -> Option<Rc<FunctionMeta>> {
             match name {
-                "sqrt" => Some(Rc::new(FunctionMeta::new(
-                    "sqrt".to_string(),
-                    vec![Field::new("n", DataType::Float64, false)],
-                    DataType::Float64,
-                    FunctionType::Scalar,
-                ))),
+                "sqrt" => Some(Rc::new(
+                    FunctionMeta::new(
+                        "sqrt".to_string(),
+                        vec![Field::new("n", DataType::Float64, false)],
+                        DataType::Float64,
+                        FunctionType::Scalar,
+                    )
+                    .with_null_propagating(true),
+                )),
+                "random" => Some(Rc::new(
+                    FunctionMeta::new(
+                        "random".to_string(),
+                        vec![],
+                        DataType::Float64,
+                        FunctionType::Scalar,
+                    )
+                    .with_volatility(Volatility::Volatile),
+                )),
+                "regexp_extract_all" => Some(Rc::new(
+                    FunctionMeta::new(
+                        "regexp_extract_all".to_string(),
+                        vec![
+                            Field::new("text", DataType::Utf8, false),
+                            Field::new("pattern", DataType::Utf8, false),
+                        ],
+                        DataType::Struct(vec![]),
+                        FunctionType::Scalar,
+                    )
+                    .with_return_type_fn(Rc::new(|literals: &[Option<ScalarValue>]| {
+                        RegexpExtractAllFunction {}.return_type_for(literals)
+                    })),
+                )),
+                _ => None,
+            }
+        }
+
+        fn get_sql_function(&self, name: &str) -> Option<Rc<SqlFunction>> {
+            match name {
+                "with_tax" => Some(Rc::new(SqlFunction {
+                    name: "with_tax".to_string(),
+                    args: vec![("amount".to_string(), SQLType::Double64)],
+                    return_type: SQLType::Double64,
+                    body: Parser::parse_sql("amount * 1.5".to_string()).unwrap(),
+                })),
+                _ => None,
+            }
+        }
+
+        fn get_table_row_count(&self, name: &str) -> Option<usize> {
+            match name {
+                "orders" => Some(3),
                 _ => None,
             }
         }
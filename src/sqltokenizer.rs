@@ -14,26 +14,50 @@
 
 //! SQL Tokenizer
 
-use std::iter::Peekable;
-use std::str::Chars;
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::{Deref, Range};
+use std::str;
 
-use fnv::FnvHashSet;
+use fnv::FnvHashMap;
 
-/// SQL Token enumeration
+use super::errors::DataFusionError;
+
+/// SQL Token enumeration. The text of a token is borrowed from the statement it was read from
+/// wherever it is written the same way in the statement.
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token {
+pub enum Token<'a> {
     /// SQL identifier e.g. table or column name
-    Identifier(String),
+    Identifier(Cow<'a, str>),
     /// SQL keyword  e.g. Keyword("SELECT")
-    Keyword(String),
-    /// Numeric literal
-    Number(String),
+    Keyword(Keyword<'a>),
+    /// Integer literal, digits without a decimal point or exponent
+    Integer(Cow<'a, str>),
+    /// Exact numeric literal with a decimal point, e.g. `3.14`
+    Decimal(Cow<'a, str>),
+    /// Approximate numeric literal with an exponent, e.g. `2.5e-2`
+    Float(Cow<'a, str>),
     /// String literal
-    String(String),
+    String(Cow<'a, str>),
+    /// Binary literal `X'DEADBEEF'` or `0x1F`
+    Binary(Vec<u8>),
+    /// Boolean literal `TRUE` or `FALSE`
+    Boolean(bool),
+    /// The `NULL` literal, which is also the keyword of `IS NULL` and `NOT NULL`
+    Null,
+    /// Parameter placeholder, `$n` for a numbered parameter or `?` for the next one
+    Placeholder(Option<usize>),
     /// Comma
     Comma,
+    /// Semicolon, which ends a statement
+    Semicolon,
     /// Whitespace (space, tab, etc)
     Whitespace,
+    /// The text of a `-- line comment` or a `/* block comment */`, without the delimiters
+    Comment(&'a str),
+    /// The text of an optimizer hint comment `/*+ BROADCAST(t) */`, without the delimiters and
+    /// the plus sign
+    Hint(&'a str),
     /// Equality operator `=`
     Eq,
     /// Not Equals operator `!=` or `<>`
@@ -56,271 +80,1009 @@ pub enum Token {
     Div,
     /// Modulo Operator `%`
     Mod,
+    /// String concatenation operator `||`
+    StringConcat,
+    /// Bitwise AND operator `&`
+    BitwiseAnd,
+    /// Bitwise OR operator `|`
+    BitwiseOr,
+    /// Bitwise XOR operator `^`, as in MySQL
+    BitwiseXor,
+    /// Left shift operator `<<`
+    ShiftLeft,
+    /// Right shift operator `>>`
+    ShiftRight,
+    /// Postgres-style cast operator `::`
+    DoubleColon,
     /// Left parenthesis `(`
     LParen,
     /// Right parenthesis `)`
     RParen,
+    /// JSON extraction operator `->`
+    Arrow,
+    /// JSON extraction as text operator `->>`
+    LongArrow,
+    /// Named argument operator `=>`
+    FatArrow,
     /// Period (used for compound identifiers or projections into nested types)
     Period,
 }
 
+/// A keyword, which is matched in upper case but keeps the text it was written as so that
+/// statements can be reproduced with the user's casing
+#[derive(Clone)]
+pub struct Keyword<'a> {
+    /// The keyword in upper case
+    value: Cow<'a, str>,
+    /// The keyword as it was written
+    text: &'a str,
+}
+
+impl<'a> Keyword<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let value = if text.chars().any(char::is_lowercase) {
+            Cow::Owned(text.to_uppercase())
+        } else {
+            Cow::Borrowed(text)
+        };
+        Keyword { value, text }
+    }
+
+    /// Get the keyword in upper case, which is how it is compared
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Get the keyword as it was written
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+}
+
+impl<'a> Deref for Keyword<'a> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.value
+    }
+}
+
+/// Keywords are equal when they are the same keyword, regardless of how they were written
+impl<'a, 'b> PartialEq<Keyword<'b>> for Keyword<'a> {
+    fn eq(&self, other: &Keyword<'b>) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<'a> PartialEq<str> for Keyword<'a> {
+    fn eq(&self, other: &str) -> bool {
+        self.value == other
+    }
+}
+
+impl<'a, 'b> PartialEq<&'b str> for Keyword<'a> {
+    fn eq(&self, other: &&'b str) -> bool {
+        self.value == *other
+    }
+}
+
+impl<'a> fmt::Debug for Keyword<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.value)
+    }
+}
+
+impl<'a> fmt::Display for Keyword<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Tokens are written as SQL text, with keywords in the case they were written in. The text of
+/// a statement is reproduced from its tokens apart from the kind of whitespace and the quoting
+/// of identifiers that don't need it.
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Identifier(ref id) => {
+                let plain = id.chars().enumerate().all(|(i, c)| {
+                    c.is_alphabetic() || c == '_' || c == '@' || (i > 0 && c.is_alphanumeric())
+                });
+                if plain && !id.is_empty() && !is_keyword(id) {
+                    write!(f, "{}", id)
+                } else {
+                    write!(f, "\"{}\"", id.replace('"', "\"\""))
+                }
+            }
+            Token::Keyword(ref k) => write!(f, "{}", k),
+            Token::Integer(ref n) | Token::Decimal(ref n) | Token::Float(ref n) => {
+                write!(f, "{}", n)
+            }
+            Token::String(ref s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Token::Binary(ref bytes) => {
+                write!(f, "X'")?;
+                for b in bytes {
+                    write!(f, "{:02X}", b)?;
+                }
+                write!(f, "'")
+            }
+            Token::Boolean(b) => write!(f, "{}", if b { "TRUE" } else { "FALSE" }),
+            Token::Null => write!(f, "NULL"),
+            Token::Placeholder(Some(n)) => write!(f, "${}", n),
+            Token::Placeholder(None) => write!(f, "?"),
+            Token::Comma => write!(f, ","),
+            Token::Semicolon => write!(f, ";"),
+            Token::Whitespace => write!(f, " "),
+            Token::Comment(ref c) => write!(f, "/*{}*/", c),
+            Token::Hint(ref h) => write!(f, "/*+{}*/", h),
+            Token::Eq => write!(f, "="),
+            Token::Neq => write!(f, "<>"),
+            Token::Lt => write!(f, "<"),
+            Token::Gt => write!(f, ">"),
+            Token::LtEq => write!(f, "<="),
+            Token::GtEq => write!(f, ">="),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::Mult => write!(f, "*"),
+            Token::Div => write!(f, "/"),
+            Token::Mod => write!(f, "%"),
+            Token::StringConcat => write!(f, "||"),
+            Token::BitwiseAnd => write!(f, "&"),
+            Token::BitwiseOr => write!(f, "|"),
+            Token::BitwiseXor => write!(f, "^"),
+            Token::ShiftLeft => write!(f, "<<"),
+            Token::ShiftRight => write!(f, ">>"),
+            Token::DoubleColon => write!(f, "::"),
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::Arrow => write!(f, "->"),
+            Token::LongArrow => write!(f, "->>"),
+            Token::FatArrow => write!(f, "=>"),
+            Token::Period => write!(f, "."),
+        }
+    }
+}
+
+/// How unquoted identifiers are matched against the names of columns. Quoted identifiers keep
+/// their case unless matching is case insensitive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdentifierCasing {
+    /// Identifiers only match names with the same case
+    CaseSensitive,
+    /// Unquoted identifiers are folded to lower case, as in Postgres
+    FoldLower,
+    /// Unquoted identifiers are folded to upper case, as in Snowflake
+    FoldUpper,
+    /// Identifiers match names in any case. An exact match is preferred, and an identifier that
+    /// matches several names only by ignoring case is ambiguous.
+    CaseInsensitive,
+}
+
+/// Apply the casing of unquoted identifiers to a word that is used as a name
+pub fn fold_identifier(word: &str, casing: IdentifierCasing) -> Cow<str> {
+    match casing {
+        IdentifierCasing::FoldLower if word.chars().any(char::is_uppercase) => {
+            Cow::Owned(word.to_lowercase())
+        }
+        IdentifierCasing::FoldUpper if word.chars().any(char::is_lowercase) => {
+            Cow::Owned(word.to_uppercase())
+        }
+        _ => Cow::Borrowed(word),
+    }
+}
+
+/// The SQL dialect a statement is written in, which decides which quoting styles the tokenizer
+/// accepts beyond ANSI SQL
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    /// Identifiers can only be quoted with double quotes
+    Ansi,
+    /// Identifiers can also be quoted with backticks, e.g. `` `first name` ``, and backslash
+    /// escapes such as `\n` and `\'` are decoded in string literals
+    MySql,
+    /// Identifiers can also be delimited by square brackets, e.g. `[Order Details]`, as in
+    /// SQL Server
+    MsSql,
+}
+
+/// Where a token was read from in the text of a statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    /// Line of the first character, counting from 1
+    pub line: usize,
+    /// Column of the first character within its line, in characters counting from 1
+    pub column: usize,
+    /// Byte offset of the first character
+    pub start: usize,
+    /// Byte offset just past the last character
+    pub end: usize,
+}
+
+impl Span {
+    /// The byte range of the token's text
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 /// Tokenizer error
 #[derive(Debug)]
 pub struct TokenizerError(String);
 
-lazy_static! {
-    static ref KEYWORDS: FnvHashSet<&'static str> = {
-        let mut m = FnvHashSet::default();
-
-        m.insert("SELECT");
-        m.insert("FROM");
-        m.insert("WHERE");
-        m.insert("LIMIT");
-        m.insert("ORDER");
-        m.insert("GROUP");
-        m.insert("BY");
-        m.insert("HAVING");
-        m.insert("UNION");
-        m.insert("ALL");
-        m.insert("INSERT");
-        m.insert("UPDATE");
-        m.insert("DELETE");
-        m.insert("IN");
-        m.insert("IS");
-        m.insert("NULL");
-        m.insert("SET");
-        m.insert("CREATE");
-        m.insert("EXTERNAL");
-        m.insert("TABLE");
-        m.insert("ASC");
-        m.insert("DESC");
-        m.insert("AND");
-        m.insert("OR");
-        m.insert("NOT");
-        m.insert("AS");
-        m.insert("STORED");
-        m.insert("CSV");
-        m.insert("PARQUET");
-        m.insert("LOCATION");
-        m.insert("WITH");
-        m.insert("WITHOUT");
-        m.insert("HEADER");
-        m.insert("ROW");
-
-        // SQL types
-        m.insert("STRING");
-        m.insert("VARCHAR");
-        m.insert("FLOAT");
-        m.insert("DOUBLE");
-        m.insert("INT");
-        m.insert("INTEGER");
-        m.insert("LONG");
-
-        // Arrow native types
-        m.insert("BOOLEAN");
-        m.insert("UINT8");
-        m.insert("UINT16");
-        m.insert("UINT32");
-        m.insert("UINT64");
-        m.insert("INT8");
-        m.insert("INT16");
-        m.insert("INT32");
-        m.insert("INT64");
-        m.insert("FLOAT32");
-        m.insert("FLOAT64");
-        m.insert("UTF8");
+/// An error found by `Tokenizer::tokenize_with_recovery`, with the span of the text that could
+/// not be tokenized
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenizerDiagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl From<TokenizerError> for DataFusionError {
+    fn from(e: TokenizerError) -> Self {
+        DataFusionError::Tokenizer(e.0)
+    }
+}
+
+/// Whether a keyword can also be used as the name of a table, column or function
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeywordKind {
+    /// Only usable as a name when quoted
+    Reserved,
+    /// Read as a name wherever a keyword isn't expected, e.g. a column named `format`
+    NonReserved,
+}
+
+/// ANSI SQL keywords that can't be used as names without quoting
+const RESERVED_KEYWORDS: &'static [&'static str] = &[
+    "ALL", "ALTER", "AND", "ANY", "AS", "ASC", "AT", "BETWEEN", "BY", "CASE", "CAST", "CHECK",
+    "COLLATE", "CONSTRAINT", "COPY", "CREATE", "CROSS", "CURRENT", "DEFAULT", "DELETE", "DESC",
+    "DESCRIBE", "DISTINCT", "DROP", "ELSE", "END", "ESCAPE", "EXCEPT", "EXISTS", "EXPLAIN",
+    "FALSE", "FETCH", "FOR", "FOREIGN", "FROM", "FULL", "GRANT", "GROUP", "GROUPS", "HAVING",
+    "ILIKE", "IN", "INNER", "INSERT", "INTERSECT", "INTERVAL", "INTO", "IS", "JOIN", "LATERAL",
+    "LEFT", "LIKE", "LIMIT", "NATURAL", "NOT", "NULL", "OF", "OFFSET", "ON", "OR", "ORDER",
+    "OUTER", "OVER", "PARTITION", "PRIMARY", "QUALIFY", "RANGE", "RECURSIVE", "REFERENCES",
+    "REVOKE", "RIGHT", "ROW", "ROWS", "SELECT", "SET", "SIMILAR", "SOME", "SYSTEM_TIME",
+    "TABLE", "TABLESAMPLE", "THEN", "TO", "TRUE", "UNION", "UNIQUE", "UPDATE", "USING", "VALUES",
+    "WHEN", "WHERE", "WINDOW", "WITH", "WITHOUT",
+];
+
+/// ANSI SQL keywords that can also be used as names
+const NON_RESERVED_KEYWORDS: &'static [&'static str] = &[
+    "ANALYZE", "ANTI", "BERNOULLI", "CSV", "DATABASE", "DATE", "DAY", "EXCLUDE", "EXTERNAL",
+    "FIRST", "FOLLOWING", "FORMAT", "FUNCTION", "HEADER", "HOUR", "IF", "IGNORE", "INDEX", "KEY",
+    "LAST", "LOCATION", "MINUTE", "MONTH", "NDJSON", "NEXT", "NO", "NULLS", "ONLY", "OPTIONS",
+    "OTHERS", "PARQUET", "PRECEDING", "RENAME", "REPEATABLE", "REPLACE", "RESPECT", "RETURNS",
+    "SCHEMA", "SECOND", "SEMI", "SHOW", "STORED", "SYSTEM", "TEMPORARY", "TIES", "TIME",
+    "TIMESTAMP", "TRUNCATE", "UNBOUNDED", "VIEW", "YEAR", "ZONE",
+    // SQL types
+    "BINARY", "BYTEA", "DOUBLE", "FLOAT", "INT", "INTEGER", "LONG", "STRING", "TEXT",
+    "VARBINARY", "VARCHAR",
+    // Arrow native types
+    "BOOLEAN", "FLOAT32", "FLOAT64", "INT16", "INT32", "INT64", "INT8", "UINT16", "UINT32",
+    "UINT64", "UINT8", "UTF8",
+];
 
+/// Keywords of MySQL beyond those of ANSI SQL
+const MYSQL_KEYWORDS: &'static [(&'static str, KeywordKind)] = &[
+    ("AUTO_INCREMENT", KeywordKind::NonReserved),
+    ("DIV", KeywordKind::Reserved),
+    ("ENGINE", KeywordKind::NonReserved),
+    ("REGEXP", KeywordKind::Reserved),
+    ("RLIKE", KeywordKind::Reserved),
+    ("UNSIGNED", KeywordKind::Reserved),
+    ("XOR", KeywordKind::Reserved),
+];
+
+/// Keywords of SQL Server beyond those of ANSI SQL
+const MSSQL_KEYWORDS: &'static [(&'static str, KeywordKind)] = &[
+    ("IDENTITY", KeywordKind::Reserved),
+    ("NOLOCK", KeywordKind::NonReserved),
+    ("NVARCHAR", KeywordKind::NonReserved),
+    ("TOP", KeywordKind::Reserved),
+];
+
+lazy_static! {
+    static ref KEYWORDS: FnvHashMap<&'static str, KeywordKind> = {
+        let mut m = FnvHashMap::default();
+        for k in RESERVED_KEYWORDS {
+            m.insert(*k, KeywordKind::Reserved);
+        }
+        for k in NON_RESERVED_KEYWORDS {
+            m.insert(*k, KeywordKind::NonReserved);
+        }
         m
     };
 }
 
-/// SQL Tokenizer
-pub struct Tokenizer {
-    pub query: String,
+impl Dialect {
+    /// Keywords of the dialect beyond those of ANSI SQL, none of which are ANSI keywords
+    fn extra_keywords(&self) -> &'static [(&'static str, KeywordKind)] {
+        match *self {
+            Dialect::Ansi => &[],
+            Dialect::MySql => MYSQL_KEYWORDS,
+            Dialect::MsSql => MSSQL_KEYWORDS,
+        }
+    }
+
+    /// Find a keyword of the dialect from a word in upper case, returning the keyword's entry
+    /// in the keyword lists so that tokens don't need their own copy of it
+    fn find_keyword(&self, upper: &str) -> Option<&'static str> {
+        KEYWORDS.get_key_value(upper).map(|(k, _)| *k).or_else(|| {
+            self.extra_keywords()
+                .iter()
+                .find(|&&(k, _)| k == upper)
+                .map(|&(k, _)| k)
+        })
+    }
+
+    /// Whether a word is a keyword of the dialect, ignoring case, and if so whether it is
+    /// reserved
+    pub fn keyword_kind(&self, word: &str) -> Option<KeywordKind> {
+        let upper = word.to_uppercase();
+        KEYWORDS.get(upper.as_str()).cloned().or_else(|| {
+            self.extra_keywords()
+                .iter()
+                .find(|&&(k, _)| k == upper)
+                .map(|&(_, kind)| kind)
+        })
+    }
+}
+
+/// Get the ANSI SQL keywords in alphabetical order
+pub fn keywords() -> Vec<&'static str> {
+    let mut keywords: Vec<&'static str> = KEYWORDS.keys().cloned().collect();
+    keywords.sort();
+    keywords
+}
+
+/// Reads the characters of a statement while keeping the byte offset reached, so that tokens
+/// can borrow their text from the statement
+struct Cursor<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.position += ch.len_utf8();
+        Some(ch)
+    }
+
+    /// Consume the next character if it is the expected one
+    fn next_if_eq(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume characters for as long as they match, returning the text that was consumed
+    fn take_while<F: Fn(char) -> bool>(&mut self, matches: F) -> &'a str {
+        let start = self.position;
+        while let Some(ch) = self.peek() {
+            if !matches(ch) {
+                break;
+            }
+            self.position += ch.len_utf8();
+        }
+        &self.text[start..self.position]
+    }
+
+    /// Get the text from a byte offset up to the current position
+    fn since(&self, start: usize) -> &'a str {
+        &self.text[start..self.position]
+    }
+}
+
+fn is_digit(ch: char) -> bool {
+    ch >= '0' && ch <= '9'
+}
+
+/// Consume the rest of an `X'DEADBEEF'` literal, after the opening quote
+fn consume_hex_string(chars: &mut Cursor) -> Result<Vec<u8>, TokenizerError> {
+    let digits = chars.take_while(|ch| ch != '\'');
+    if !chars.next_if_eq('\'') {
+        return Err(TokenizerError(format!(
+            "Unterminated binary literal X'{}",
+            digits
+        )));
+    }
+    if digits.len() % 2 != 0 {
+        return Err(TokenizerError(format!(
+            "Binary literal X'{}' has an odd number of digits",
+            digits
+        )));
+    }
+    decode_hex(digits)
+        .ok_or_else(|| TokenizerError(format!("Invalid binary literal X'{}'", digits)))
+}
+
+/// Decode hexadecimal digits into bytes, where an odd number of digits has a leading zero
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(digits.len() / 2 + 1);
+    let mut chars = digits.chars();
+    if digits.len() % 2 != 0 {
+        bytes.push(chars.next()?.to_digit(16)? as u8);
+    }
+    while let Some(high) = chars.next() {
+        let low = chars.next()?;
+        bytes.push((high.to_digit(16)? * 16 + low.to_digit(16)?) as u8);
+    }
+    Some(bytes)
+}
+
+/// Consume the exponent of a number, e.g. `e-3`, if there is one, and return whether there was.
+/// `start` is the offset of the number, for reporting an exponent without digits.
+fn consume_exponent(chars: &mut Cursor, start: usize) -> Result<bool, TokenizerError> {
+    if !chars.next_if_eq('e') && !chars.next_if_eq('E') {
+        return Ok(false);
+    }
+    if !chars.next_if_eq('+') {
+        chars.next_if_eq('-');
+    }
+    if chars.take_while(is_digit).is_empty() {
+        return Err(TokenizerError(format!(
+            "Invalid number '{}'",
+            chars.since(start)
+        )));
+    }
+    Ok(true)
+}
+
+/// Get the text that a number is kept as in its token. It is borrowed from the statement unless
+/// it is written differently, with `0` before a leading decimal point and the exponent marker
+/// in lower case.
+fn number_text<'a>(text: &'a str) -> Cow<'a, str> {
+    if text.starts_with('.') {
+        Cow::Owned(format!("0{}", text.replace('E', "e")))
+    } else if text.contains('E') {
+        Cow::Owned(text.replace('E', "e"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Consume the rest of a block comment after its opening `/*`, returning the text before the
+/// closing `*/`. Block comments can be nested.
+fn consume_block_comment<'a>(chars: &mut Cursor<'a>) -> Result<&'a str, TokenizerError> {
+    let start = chars.position;
+    let mut depth = 1;
+    while let Some(ch) = chars.next() {
+        if ch == '*' && chars.next_if_eq('/') {
+            depth -= 1;
+            if depth == 0 {
+                return Ok(&chars.text[start..chars.position - 2]);
+            }
+        } else if ch == '/' && chars.next_if_eq('*') {
+            depth += 1;
+        }
+    }
+    Err(TokenizerError(format!(
+        "Unterminated block comment '/*{}'",
+        chars.since(start)
+    )))
 }
 
-impl Tokenizer {
+/// Consume the rest of a quoted identifier after its opening quote, returning the text before
+/// the closing quote. A doubled closing quote is a closing quote inside the identifier, and is
+/// the only case where the text has to be copied.
+fn consume_quoted_identifier<'a>(
+    chars: &mut Cursor<'a>,
+    open: char,
+    close: char,
+) -> Result<Cow<'a, str>, TokenizerError> {
+    let start = chars.position;
+    let mut doubled = false;
+    loop {
+        match chars.next() {
+            Some(ch) if ch == close => {
+                if chars.next_if_eq(close) {
+                    doubled = true;
+                    continue;
+                }
+                let text = &chars.text[start..chars.position - close.len_utf8()];
+                if !doubled {
+                    return Ok(Cow::Borrowed(text));
+                }
+                let pair: String = [close, close].iter().collect();
+                return Ok(Cow::Owned(text.replace(pair.as_str(), &close.to_string())));
+            }
+            Some(_) => {}
+            None => {
+                return Err(TokenizerError(format!(
+                    "Unterminated quoted identifier {}{}",
+                    open,
+                    chars.since(start)
+                )))
+            }
+        }
+    }
+}
+
+/// Consume the rest of a string literal after its opening quote, returning its decoded value.
+/// A doubled quote is a quote inside the string, and with `backslash_escapes` a backslash
+/// escapes the next character as in MySQL. The text is only copied when it has escapes.
+fn consume_string<'a>(
+    chars: &mut Cursor<'a>,
+    backslash_escapes: bool,
+) -> Result<Cow<'a, str>, TokenizerError> {
+    let start = chars.position;
+    let mut escaped = false;
+    loop {
+        match chars.next() {
+            Some('\'') if chars.next_if_eq('\'') => escaped = true,
+            Some('\'') => {
+                let text = &chars.text[start..chars.position - 1];
+                return Ok(if escaped {
+                    Cow::Owned(unescape_string(text, backslash_escapes))
+                } else {
+                    Cow::Borrowed(text)
+                });
+            }
+            Some('\\') if backslash_escapes => {
+                escaped = true;
+                if chars.next().is_none() {
+                    break;
+                }
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    Err(TokenizerError(format!(
+        "Unterminated string literal '{}",
+        unescape_string(chars.since(start), backslash_escapes)
+    )))
+}
+
+/// Decode the doubled quotes, and with `backslash_escapes` the backslash escapes, of the text
+/// of a string literal
+fn unescape_string(text: &str, backslash_escapes: bool) -> String {
+    let mut s = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' => {
+                chars.next();
+                s.push('\'');
+            }
+            '\\' if backslash_escapes => match chars.next() {
+                Some('0') => s.push('\0'),
+                Some('b') => s.push('\u{8}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('Z') => s.push('\u{1a}'),
+                // kept so that they still match literally in LIKE patterns
+                Some(ch) if ch == '%' || ch == '_' => {
+                    s.push('\\');
+                    s.push(ch);
+                }
+                Some(ch) => s.push(ch),
+                None => break,
+            },
+            _ => s.push(ch),
+        }
+    }
+    s
+}
+
+/// Longest keyword of any dialect, so that longer words are never looked up
+const MAX_KEYWORD_LEN: usize = 16;
+
+/// Write a word in upper case into a buffer, if it is short enough to be a keyword. Keywords
+/// are only ASCII, so other words are never keywords.
+fn keyword_upper<'b>(word: &str, buffer: &'b mut [u8; MAX_KEYWORD_LEN]) -> Option<&'b str> {
+    if word.len() > MAX_KEYWORD_LEN || !word.is_ascii() {
+        return None;
+    }
+    let upper = &mut buffer[..word.len()];
+    upper.copy_from_slice(word.as_bytes());
+    upper.make_ascii_uppercase();
+    str::from_utf8(upper).ok()
+}
+
+/// Check whether a word is an ANSI SQL keyword, ignoring case
+pub fn is_keyword(word: &str) -> bool {
+    KEYWORDS.contains_key(word.to_uppercase().as_str())
+}
+
+/// Check whether a keyword can't be read as a name. A word is only tokenized as a keyword in
+/// the dialects it is a keyword of, so the keywords of every dialect are checked.
+pub fn is_reserved_keyword(word: &str) -> bool {
+    [Dialect::Ansi, Dialect::MySql, Dialect::MsSql]
+        .iter()
+        .any(|d| d.keyword_kind(word) == Some(KeywordKind::Reserved))
+}
+
+/// SQL Tokenizer. Tokens borrow their text from the statement, and only copy it when it has to
+/// be decoded or its case folded.
+pub struct Tokenizer<'a> {
+    pub query: &'a str,
+    /// Whether `tokenize` returns comments rather than skipping them
+    keep_comments: bool,
+    identifier_casing: IdentifierCasing,
+    dialect: Dialect,
+}
+
+impl<'a> Tokenizer<'a> {
     /// Create a new SQL tokenizer for the specified SQL statement
-    pub fn new(query: &str) -> Self {
+    pub fn new(query: &'a str) -> Self {
         Self {
-            query: query.to_string(),
+            query,
+            keep_comments: false,
+            identifier_casing: IdentifierCasing::CaseSensitive,
+            dialect: Dialect::Ansi,
         }
     }
 
-    /// Tokenize the statement and produce a vector of tokens
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, TokenizerError> {
-        let mut peekable = self.query.chars().peekable();
+    /// Accept the quoting styles of `dialect` as well as those of ANSI SQL
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
 
-        let mut tokens: Vec<Token> = vec![];
+    /// Fold the case of unquoted identifiers as required by `casing`
+    pub fn with_identifier_casing(mut self, casing: IdentifierCasing) -> Self {
+        self.identifier_casing = casing;
+        self
+    }
 
-        while let Some(token) = self.next_token(&mut peekable)? {
-            tokens.push(token);
-        }
+    /// Return comments from `tokenize` as `Token::Comment` rather than skipping them
+    pub fn with_comments(mut self) -> Self {
+        self.keep_comments = true;
+        self
+    }
 
-        Ok(tokens
+    /// Tokenize the statement and produce a vector of tokens, without whitespace or comments.
+    /// Hints are kept, since they are comments that the parser reads.
+    pub fn tokenize(&mut self) -> Result<Vec<Token<'a>>, TokenizerError> {
+        let keep_comments = self.keep_comments;
+        Ok(self
+            .tokenize_with_spans()?
             .into_iter()
+            .map(|(t, _)| t)
             .filter(|t| match t {
                 Token::Whitespace => false,
+                Token::Comment(_) => keep_comments,
                 _ => true,
             })
             .collect())
     }
 
-    /// Get the next token or return None
-    fn next_token(&self, chars: &mut Peekable<Chars>) -> Result<Option<Token>, TokenizerError> {
-        //println!("next_token: {:?}", chars.peek());
-        match chars.peek() {
-            Some(&ch) => match ch {
-                // whitespace
-                ' ' | '\t' | '\n' => {
-                    chars.next(); // consume
-                    Ok(Some(Token::Whitespace))
-                }
-                // identifier or keyword
-                'a'...'z' | 'A'...'Z' | '_' | '@' => {
-                    let mut s = String::new();
-                    while let Some(&ch) = chars.peek() {
-                        match ch {
-                            'a'...'z' | 'A'...'Z' | '_' | '0'...'9' | '@' => {
-                                chars.next(); // consume
-                                s.push(ch);
-                            }
-                            _ => break,
-                        }
-                    }
-                    let upper_str = s.to_uppercase();
-                    if KEYWORDS.contains(upper_str.as_str()) {
-                        Ok(Some(Token::Keyword(upper_str)))
-                    } else {
-                        Ok(Some(Token::Identifier(s)))
+    /// Tokenize the statement, keeping whitespace and comments, and return each token with the
+    /// span of the text it was read from. Errors report the line and column of the token that
+    /// could not be read.
+    pub fn tokenize_with_spans(&mut self) -> Result<Vec<(Token<'a>, Span)>, TokenizerError> {
+        let (tokens, diagnostics) = self.scan(false);
+        match diagnostics.into_iter().next() {
+            Some(d) => Err(TokenizerError(format!("{} at {}", d.message, d.span))),
+            None => Ok(tokens),
+        }
+    }
+
+    /// Tokenize the statement like `tokenize_with_spans`, but rather than stopping at the first
+    /// error, skip the text that could not be tokenized and carry on. Returns the tokens that
+    /// were read along with every error found, for tools that show all the problems in a
+    /// statement at once.
+    pub fn tokenize_with_recovery(
+        &mut self,
+    ) -> (Vec<(Token<'a>, Span)>, Vec<TokenizerDiagnostic>) {
+        self.scan(true)
+    }
+
+    /// Read tokens with their spans, stopping at the first error unless `recover` is set
+    fn scan(&self, recover: bool) -> (Vec<(Token<'a>, Span)>, Vec<TokenizerDiagnostic>) {
+        let mut chars = Cursor {
+            text: self.query,
+            position: 0,
+        };
+        let mut tokens = vec![];
+        let mut diagnostics = vec![];
+        let mut line = 1;
+        let mut column = 1;
+        loop {
+            let start = chars.position;
+            let result = self.next_token(&mut chars);
+            if chars.position == start && result.is_err() {
+                // skip the character that no token can start with
+                chars.next();
+            }
+            let span = Span {
+                line,
+                column,
+                start,
+                end: chars.position,
+            };
+            match result {
+                Ok(Some(token)) => tokens.push((token, span)),
+                Ok(None) => break,
+                Err(TokenizerError(message)) => {
+                    diagnostics.push(TokenizerDiagnostic { message, span });
+                    if !recover {
+                        break;
                     }
                 }
-                // string
-                '\'' => {
-                    //TODO: handle escaped quotes in string
-                    //TODO: handle EOF before terminating quote
-                    let mut s = String::new();
-                    chars.next(); // consume
-                    while let Some(&ch) = chars.peek() {
-                        match ch {
-                            '\'' => {
-                                chars.next(); // consume
-                                break;
-                            }
-                            _ => {
-                                chars.next(); // consume
-                                s.push(ch);
-                            }
-                        }
-                    }
-                    Ok(Some(Token::String(s)))
+            }
+            for ch in chars.since(start).chars() {
+                if ch == '\n' {
+                    line += 1;
+                    column = 1;
+                } else {
+                    column += 1;
                 }
-                // numbers
-                '0'...'9' => {
-                    let mut s = String::new();
-                    while let Some(&ch) = chars.peek() {
-                        match ch {
-                            '0'...'9' | '.' => {
-                                chars.next(); // consume
-                                s.push(ch);
-                            }
-                            _ => break,
-                        }
+            }
+        }
+        (tokens, diagnostics)
+    }
+
+    /// Read an unquoted word, which is a keyword, a literal or an identifier
+    fn next_word(&self, chars: &mut Cursor<'a>) -> Result<Token<'a>, TokenizerError> {
+        let word = chars.take_while(|ch| ch.is_alphanumeric() || ch == '_' || ch == '@');
+        let mut buffer = [0; MAX_KEYWORD_LEN];
+        if let Some(upper) = keyword_upper(word, &mut buffer) {
+            if upper == "X" && chars.next_if_eq('\'') {
+                return consume_hex_string(chars).map(Token::Binary);
+            }
+            match upper {
+                "TRUE" => return Ok(Token::Boolean(true)),
+                "FALSE" => return Ok(Token::Boolean(false)),
+                "NULL" => return Ok(Token::Null),
+                _ => {}
+            }
+            if let Some(keyword) = self.dialect.find_keyword(upper) {
+                return Ok(Token::Keyword(Keyword {
+                    value: Cow::Borrowed(keyword),
+                    text: word,
+                }));
+            }
+        }
+        Ok(Token::Identifier(fold_identifier(word, self.identifier_casing)))
+    }
+
+    /// Read a number, starting from its first digit
+    fn next_number(&self, chars: &mut Cursor<'a>) -> Result<Token<'a>, TokenizerError> {
+        let start = chars.position;
+        let digits = chars.take_while(is_digit);
+        if digits == "0" && (chars.next_if_eq('x') || chars.next_if_eq('X')) {
+            let hex = chars.take_while(|ch| ch.is_digit(16));
+            if hex.is_empty() {
+                return Err(TokenizerError("Invalid binary literal 0x".to_string()));
+            }
+            return decode_hex(hex)
+                .map(Token::Binary)
+                .ok_or_else(|| TokenizerError(format!("Invalid binary literal 0x{}", hex)));
+        }
+        let decimal = chars.next_if_eq('.');
+        if decimal {
+            chars.take_while(is_digit);
+        }
+        self.finish_number(chars, start, decimal)
+    }
+
+    /// Read the exponent of a number whose digits have been read, and make its token
+    fn finish_number(
+        &self,
+        chars: &mut Cursor<'a>,
+        start: usize,
+        decimal: bool,
+    ) -> Result<Token<'a>, TokenizerError> {
+        let float = consume_exponent(chars, start)?;
+        let text = number_text(chars.since(start));
+        if float {
+            Ok(Token::Float(text))
+        } else if decimal {
+            Ok(Token::Decimal(text))
+        } else {
+            Ok(Token::Integer(text))
+        }
+    }
+
+    /// Get the next token or return None
+    fn next_token(&self, chars: &mut Cursor<'a>) -> Result<Option<Token<'a>>, TokenizerError> {
+        let start = chars.position;
+        let ch = match chars.peek() {
+            Some(ch) => ch,
+            None => return Ok(None),
+        };
+        let token = match ch {
+            // whitespace
+            ' ' | '\t' | '\n' => {
+                chars.next(); // consume
+                Token::Whitespace
+            }
+            // identifier or keyword, which can contain letters and digits of any script
+            ch if ch.is_alphabetic() || ch == '_' || ch == '@' => self.next_word(chars)?,
+            // quoted identifier, which is never a keyword and keeps its case
+            '"' => {
+                chars.next(); // consume
+                Token::Identifier(consume_quoted_identifier(chars, '"', '"')?)
+            }
+            '`' if self.dialect == Dialect::MySql => {
+                chars.next(); // consume
+                Token::Identifier(consume_quoted_identifier(chars, '`', '`')?)
+            }
+            '[' if self.dialect == Dialect::MsSql => {
+                chars.next(); // consume
+                Token::Identifier(consume_quoted_identifier(chars, '[', ']')?)
+            }
+            // string
+            '\'' => {
+                chars.next(); // consume
+                Token::String(consume_string(chars, self.dialect == Dialect::MySql)?)
+            }
+            // numbers
+            '0'...'9' => self.next_number(chars)?,
+            // punctuation
+            ',' => {
+                chars.next();
+                Token::Comma
+            }
+            ';' => {
+                chars.next();
+                Token::Semicolon
+            }
+            '(' => {
+                chars.next();
+                Token::LParen
+            }
+            ')' => {
+                chars.next();
+                Token::RParen
+            }
+            // parameter placeholders
+            '?' => {
+                chars.next();
+                Token::Placeholder(None)
+            }
+            '$' => {
+                chars.next();
+                let digits = chars.take_while(is_digit);
+                match digits.parse::<usize>() {
+                    Ok(n) if n > 0 => Token::Placeholder(Some(n)),
+                    _ => {
+                        return Err(TokenizerError(format!(
+                            "Expected a parameter number from 1 after '$', found '${}'",
+                            digits
+                        )))
                     }
-                    Ok(Some(Token::Number(s)))
                 }
-                // punctuation
-                ',' => {
-                    chars.next();
-                    Ok(Some(Token::Comma))
-                }
-                '(' => {
-                    chars.next();
-                    Ok(Some(Token::LParen))
-                }
-                ')' => {
-                    chars.next();
-                    Ok(Some(Token::RParen))
-                }
-                // operators
-                '+' => {
-                    chars.next();
-                    Ok(Some(Token::Plus))
+            }
+            // operators
+            '+' => {
+                chars.next();
+                Token::Plus
+            }
+            '-' => {
+                chars.next(); // consume
+                if chars.next_if_eq('>') {
+                    if chars.next_if_eq('>') {
+                        Token::LongArrow
+                    } else {
+                        Token::Arrow
+                    }
+                } else if chars.next_if_eq('-') {
+                    // the comment runs to the end of the line, which is left as whitespace
+                    Token::Comment(chars.take_while(|ch| ch != '\n'))
+                } else {
+                    Token::Minus
                 }
-                '-' => {
-                    chars.next();
-                    Ok(Some(Token::Minus))
+            }
+            '*' => {
+                chars.next();
+                Token::Mult
+            }
+            '/' => {
+                chars.next();
+                if chars.next_if_eq('*') {
+                    let text = consume_block_comment(chars)?;
+                    if text.starts_with('+') {
+                        Token::Hint(&text[1..])
+                    } else {
+                        Token::Comment(text)
+                    }
+                } else {
+                    Token::Div
                 }
-                '*' => {
-                    chars.next();
-                    Ok(Some(Token::Mult))
+            }
+            '%' => {
+                chars.next();
+                Token::Mod
+            }
+            '=' => {
+                chars.next();
+                if chars.next_if_eq('>') {
+                    Token::FatArrow
+                } else {
+                    Token::Eq
                 }
-                '/' => {
-                    chars.next();
-                    Ok(Some(Token::Div))
+            }
+            '.' => {
+                chars.next();
+                match chars.peek() {
+                    // a number without an integer part, e.g. `.5`
+                    Some('0'...'9') => {
+                        chars.take_while(is_digit);
+                        self.finish_number(chars, start, true)?
+                    }
+                    _ => Token::Period,
                 }
-                '%' => {
-                    chars.next();
-                    Ok(Some(Token::Mod))
+            }
+            '!' => {
+                chars.next(); // consume
+                if chars.next_if_eq('=') {
+                    Token::Neq
+                } else {
+                    return Err(TokenizerError("Expected '=' after '!'".to_string()));
                 }
-                '=' => {
-                    chars.next();
-                    Ok(Some(Token::Eq))
+            }
+            '|' => {
+                chars.next(); // consume
+                if chars.next_if_eq('|') {
+                    Token::StringConcat
+                } else {
+                    Token::BitwiseOr
                 }
-                '.' => {
-                    chars.next();
-                    Ok(Some(Token::Period))
+            }
+            '&' => {
+                chars.next();
+                Token::BitwiseAnd
+            }
+            '^' => {
+                chars.next();
+                Token::BitwiseXor
+            }
+            ':' => {
+                chars.next(); // consume
+                if chars.next_if_eq(':') {
+                    Token::DoubleColon
+                } else {
+                    return Err(TokenizerError("Expected ':' after ':'".to_string()));
                 }
-                '!' => {
-                    chars.next(); // consume
-                    match chars.peek() {
-                        Some(&ch) => match ch {
-                            '=' => {
-                                chars.next();
-                                Ok(Some(Token::Neq))
-                            }
-                            _ => Err(TokenizerError(format!("TBD"))),
-                        },
-                        None => Err(TokenizerError(format!("TBD"))),
-                    }
+            }
+            '<' => {
+                chars.next(); // consume
+                if chars.next_if_eq('=') {
+                    Token::LtEq
+                } else if chars.next_if_eq('>') {
+                    Token::Neq
+                } else if chars.next_if_eq('<') {
+                    Token::ShiftLeft
+                } else {
+                    Token::Lt
                 }
-                '<' => {
-                    chars.next(); // consume
-                    match chars.peek() {
-                        Some(&ch) => match ch {
-                            '=' => {
-                                chars.next();
-                                Ok(Some(Token::LtEq))
-                            }
-                            '>' => {
-                                chars.next();
-                                Ok(Some(Token::Neq))
-                            }
-                            _ => Ok(Some(Token::Lt)),
-                        },
-                        None => Ok(Some(Token::Lt)),
-                    }
+            }
+            '>' => {
+                chars.next(); // consume
+                if chars.next_if_eq('=') {
+                    Token::GtEq
+                } else if chars.next_if_eq('>') {
+                    Token::ShiftRight
+                } else {
+                    Token::Gt
                 }
-                '>' => {
-                    chars.next(); // consume
-                    match chars.peek() {
-                        Some(&ch) => match ch {
-                            '=' => {
-                                chars.next();
-                                Ok(Some(Token::GtEq))
-                            }
-                            _ => Ok(Some(Token::Gt)),
-                        },
-                        None => Ok(Some(Token::Gt)),
-                    }
-                }
-                _ => Err(TokenizerError(format!(
+            }
+            _ => {
+                return Err(TokenizerError(format!(
                     "unhandled char '{}' in tokenizer",
                     ch
-                ))),
-            },
-            None => Ok(None),
-        }
+                )))
+            }
+        };
+        Ok(Some(token))
     }
 }
 
@@ -335,8 +1097,8 @@ mod tests {
         let tokens = tokenizer.tokenize().unwrap();
 
         let expected = vec![
-            Token::Keyword(String::from("SELECT")),
-            Token::Number(String::from("1")),
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Integer("1".into()),
         ];
 
         compare(expected, tokens);
@@ -349,10 +1111,10 @@ mod tests {
         let tokens = tokenizer.tokenize().unwrap();
 
         let expected = vec![
-            Token::Keyword(String::from("SELECT")),
-            Token::Identifier(String::from("sqrt")),
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Identifier("sqrt".into()),
             Token::LParen,
-            Token::Number(String::from("1")),
+            Token::Integer("1".into()),
             Token::RParen,
         ];
 
@@ -366,16 +1128,16 @@ mod tests {
         let tokens = tokenizer.tokenize().unwrap();
 
         let expected = vec![
-            Token::Keyword(String::from("SELECT")),
+            Token::Keyword(Keyword::new("SELECT")),
             Token::Mult,
-            Token::Keyword(String::from("FROM")),
-            Token::Identifier(String::from("customer")),
-            Token::Keyword(String::from("WHERE")),
-            Token::Identifier(String::from("id")),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Identifier("customer".into()),
+            Token::Keyword(Keyword::new("WHERE")),
+            Token::Identifier("id".into()),
             Token::Eq,
-            Token::Number(String::from("1")),
-            Token::Keyword(String::from("LIMIT")),
-            Token::Number(String::from("5")),
+            Token::Integer("1".into()),
+            Token::Keyword(Keyword::new("LIMIT")),
+            Token::Integer("5".into()),
         ];
 
         compare(expected, tokens);
@@ -388,14 +1150,270 @@ mod tests {
         let tokens = tokenizer.tokenize().unwrap();
 
         let expected = vec![
-            Token::Keyword(String::from("SELECT")),
+            Token::Keyword(Keyword::new("SELECT")),
             Token::Mult,
-            Token::Keyword(String::from("FROM")),
-            Token::Identifier(String::from("customer")),
-            Token::Keyword(String::from("WHERE")),
-            Token::Identifier(String::from("salary")),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Identifier("customer".into()),
+            Token::Keyword(Keyword::new("WHERE")),
+            Token::Identifier("salary".into()),
             Token::Neq,
-            Token::String(String::from("Not Provided")),
+            Token::String("Not Provided".into()),
+        ];
+
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_unterminated_string() {
+        let sql = String::from("SELECT * FROM customer WHERE name = 'Smith");
+        let mut tokenizer = Tokenizer::new(&sql);
+        match tokenizer.tokenize() {
+            Err(TokenizerError(msg)) => assert_eq!(
+                "Unterminated string literal 'Smith at line 1, column 37",
+                msg
+            ),
+            other => panic!("Expected an error but got {:?}", other),
+        }
+
+        let sql = String::from("SELECT ''");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+        compare(
+            vec![Token::Keyword(Keyword::new("SELECT")), Token::String("".into())],
+            tokens,
+        );
+    }
+
+    #[test]
+    fn tokenize_quoted_identifier() {
+        let sql = String::from("SELECT \"order\", \"first name\", \"a\"\"b\" FROM t");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+
+        let expected = vec![
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Identifier("order".into()),
+            Token::Comma,
+            Token::Identifier("first name".into()),
+            Token::Comma,
+            Token::Identifier("a\"b".into()),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Identifier("t".into()),
+        ];
+
+        compare(expected, tokens);
+
+        let sql = String::from("SELECT \"order");
+        assert!(Tokenizer::new(&sql).tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_dialect_keywords() {
+        assert_eq!(Some(KeywordKind::Reserved), Dialect::Ansi.keyword_kind("join"));
+        assert_eq!(Some(KeywordKind::NonReserved), Dialect::Ansi.keyword_kind("Format"));
+        assert_eq!(None, Dialect::Ansi.keyword_kind("top"));
+        assert_eq!(Some(KeywordKind::Reserved), Dialect::MsSql.keyword_kind("top"));
+        assert_eq!(None, Dialect::MySql.keyword_kind("top"));
+        assert!(is_reserved_keyword("CAST"));
+        assert!(!is_reserved_keyword("TIMESTAMP"));
+
+        let sql = String::from("SELECT TOP 10 a FROM t");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+        assert_eq!(Token::Identifier("TOP".into()), tokens[1]);
+        let tokens = Tokenizer::new(&sql)
+            .with_dialect(Dialect::MsSql)
+            .tokenize()
+            .unwrap();
+        assert_eq!(Token::Keyword(Keyword::new("TOP")), tokens[1]);
+    }
+
+    #[test]
+    fn tokenize_backtick_identifier() {
+        let sql = String::from("SELECT `order`, `first name`, `a``b` FROM t");
+        let tokens = Tokenizer::new(&sql)
+            .with_dialect(Dialect::MySql)
+            .tokenize()
+            .unwrap();
+
+        let expected = vec![
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Identifier("order".into()),
+            Token::Comma,
+            Token::Identifier("first name".into()),
+            Token::Comma,
+            Token::Identifier("a`b".into()),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Identifier("t".into()),
+        ];
+
+        compare(expected, tokens);
+
+        // backticks are only accepted in the MySQL dialect
+        assert!(Tokenizer::new(&sql).tokenize().is_err());
+        assert!(
+            Tokenizer::new("SELECT `order")
+                .with_dialect(Dialect::MySql)
+                .tokenize()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn tokenize_square_bracket_identifier() {
+        let sql = String::from("SELECT [Order ID], [a]]b] FROM [Order Details]");
+        let tokens = Tokenizer::new(&sql)
+            .with_dialect(Dialect::MsSql)
+            .tokenize()
+            .unwrap();
+
+        let expected = vec![
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Identifier("Order ID".into()),
+            Token::Comma,
+            Token::Identifier("a]b".into()),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Identifier("Order Details".into()),
+        ];
+
+        compare(expected, tokens);
+
+        // square brackets are only accepted in the SQL Server dialect
+        assert!(Tokenizer::new(&sql).tokenize().is_err());
+        assert!(
+            Tokenizer::new("SELECT `a`")
+                .with_dialect(Dialect::MsSql)
+                .tokenize()
+                .is_err()
+        );
+        match Tokenizer::new("SELECT [Order")
+            .with_dialect(Dialect::MsSql)
+            .tokenize()
+        {
+            Err(TokenizerError(msg)) => assert_eq!(
+                "Unterminated quoted identifier [Order at line 1, column 8",
+                msg
+            ),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn tokenize_decimal_numbers() {
+        let sql = String::from("SELECT 3.14, 10., .5, 1e3, 2.5E-2 FROM t WHERE t.x > 1.5");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+
+        let expected = vec![
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Decimal("3.14".into()),
+            Token::Comma,
+            Token::Decimal("10.".into()),
+            Token::Comma,
+            Token::Decimal("0.5".into()),
+            Token::Comma,
+            Token::Float("1e3".into()),
+            Token::Comma,
+            Token::Float("2.5e-2".into()),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Identifier("t".into()),
+            Token::Keyword(Keyword::new("WHERE")),
+            Token::Identifier("t".into()),
+            Token::Period,
+            Token::Identifier("x".into()),
+            Token::Gt,
+            Token::Decimal("1.5".into()),
+        ];
+
+        compare(expected, tokens);
+
+        // a second decimal point starts a new token rather than extending the number
+        let tokens = Tokenizer::new("1.2.3").tokenize().unwrap();
+        compare(
+            vec![
+                Token::Decimal("1.2".into()),
+                Token::Decimal("0.3".into()),
+            ],
+            tokens,
+        );
+        assert!(Tokenizer::new("SELECT 1e").tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_numeric_kinds() {
+        let tokens = Tokenizer::new("42 42.0 42e0 .5 .5e1").tokenize().unwrap();
+        compare(
+            vec![
+                Token::Integer("42".into()),
+                Token::Decimal("42.0".into()),
+                Token::Float("42e0".into()),
+                Token::Decimal("0.5".into()),
+                Token::Float("0.5e1".into()),
+            ],
+            tokens,
+        );
+    }
+
+    #[test]
+    fn tokenize_fat_arrow() {
+        let sql = String::from("AT (VERSION => 2) WHERE a = b");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+
+        let expected = vec![
+            Token::Keyword(Keyword::new("AT")),
+            Token::LParen,
+            Token::Identifier("VERSION".into()),
+            Token::FatArrow,
+            Token::Integer("2".into()),
+            Token::RParen,
+            Token::Keyword(Keyword::new("WHERE")),
+            Token::Identifier("a".into()),
+            Token::Eq,
+            Token::Identifier("b".into()),
+        ];
+
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_multi_char_operators() {
+        let sql = String::from("a != b || 'x' % 2 <> c::INT");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+
+        let expected = vec![
+            Token::Identifier("a".into()),
+            Token::Neq,
+            Token::Identifier("b".into()),
+            Token::StringConcat,
+            Token::String("x".into()),
+            Token::Mod,
+            Token::Integer("2".into()),
+            Token::Neq,
+            Token::Identifier("c".into()),
+            Token::DoubleColon,
+            Token::Keyword(Keyword::new("INT")),
+        ];
+
+        compare(expected, tokens);
+
+        assert!(Tokenizer::new("a : b").tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_bitwise_operators() {
+        let sql = String::from("a & b | c ^ 1 << 2 >> d||e");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+
+        let expected = vec![
+            Token::Identifier("a".into()),
+            Token::BitwiseAnd,
+            Token::Identifier("b".into()),
+            Token::BitwiseOr,
+            Token::Identifier("c".into()),
+            Token::BitwiseXor,
+            Token::Integer("1".into()),
+            Token::ShiftLeft,
+            Token::Integer("2".into()),
+            Token::ShiftRight,
+            Token::Identifier("d".into()),
+            Token::StringConcat,
+            Token::Identifier("e".into()),
         ];
 
         compare(expected, tokens);
@@ -408,14 +1426,343 @@ mod tests {
         let tokens = tokenizer.tokenize().unwrap();
 
         let expected = vec![
-            Token::Identifier(String::from("a")),
-            Token::Keyword("IS".to_string()),
-            Token::Keyword("NULL".to_string()),
+            Token::Identifier("a".into()),
+            Token::Keyword(Keyword::new("IS")),
+            Token::Null,
         ];
 
         compare(expected, tokens);
     }
 
+    #[test]
+    fn tokenize_boolean_and_null_literals() {
+        let sql = String::from("true AND False OR null");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize().unwrap();
+
+        let expected = vec![
+            Token::Boolean(true),
+            Token::Keyword(Keyword::new("AND")),
+            Token::Boolean(false),
+            Token::Keyword(Keyword::new("OR")),
+            Token::Null,
+        ];
+
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_string_escapes() {
+        let sql = String::from(r"SELECT 'it''s', 'a\nb', '50\%'");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+        compare(
+            vec![
+                Token::Keyword(Keyword::new("SELECT")),
+                Token::String("it's".into()),
+                Token::Comma,
+                Token::String(r"a\nb".into()),
+                Token::Comma,
+                Token::String(r"50\%".into()),
+            ],
+            tokens,
+        );
+
+        // backslashes only escape characters in MySQL
+        let tokens = Tokenizer::new(&sql)
+            .with_dialect(Dialect::MySql)
+            .tokenize()
+            .unwrap();
+        compare(
+            vec![
+                Token::Keyword(Keyword::new("SELECT")),
+                Token::String("it's".into()),
+                Token::Comma,
+                Token::String("a\nb".into()),
+                Token::Comma,
+                Token::String(r"50\%".into()),
+            ],
+            tokens,
+        );
+        let tokens = Tokenizer::new(r"'don\'t'")
+            .with_dialect(Dialect::MySql)
+            .tokenize()
+            .unwrap();
+        compare(vec![Token::String("don't".into())], tokens);
+        assert!(
+            Tokenizer::new(r"'abc\'")
+                .with_dialect(Dialect::MySql)
+                .tokenize()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn tokenize_binary_literals() {
+        let sql = String::from("x'DEADbeef' = 0x1F0 AND X'' = xs");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize().unwrap();
+
+        let expected = vec![
+            Token::Binary(vec![0xde, 0xad, 0xbe, 0xef]),
+            Token::Eq,
+            Token::Binary(vec![0x01, 0xf0]),
+            Token::Keyword(Keyword::new("AND")),
+            Token::Binary(vec![]),
+            Token::Eq,
+            Token::Identifier("xs".into()),
+        ];
+
+        compare(expected, tokens);
+
+        assert!(Tokenizer::new("X'ABC'").tokenize().is_err());
+        assert!(Tokenizer::new("X'GG'").tokenize().is_err());
+        assert!(Tokenizer::new("X'AB").tokenize().is_err());
+        assert!(Tokenizer::new("0x").tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_json_operators() {
+        let sql = String::from("a->'b'->>0 - 1");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize().unwrap();
+
+        let expected = vec![
+            Token::Identifier("a".into()),
+            Token::Arrow,
+            Token::String("b".into()),
+            Token::LongArrow,
+            Token::Integer("0".into()),
+            Token::Minus,
+            Token::Integer("1".into()),
+        ];
+
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_placeholders() {
+        let sql = String::from("SELECT a FROM t WHERE b = ? AND c = $12");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize().unwrap();
+        assert_eq!(Token::Placeholder(None), tokens[7]);
+        assert_eq!(Token::Placeholder(Some(12)), tokens[11]);
+
+        assert!(Tokenizer::new("SELECT $0").tokenize().is_err());
+        assert!(Tokenizer::new("SELECT $a").tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_folded_identifiers() {
+        let sql = String::from("SELECT UserId, \"UserId\" FROM t");
+        let tokens = Tokenizer::new(&sql)
+            .with_identifier_casing(IdentifierCasing::FoldLower)
+            .tokenize()
+            .unwrap();
+        assert_eq!(Token::Identifier("userid".into()), tokens[1]);
+        assert_eq!(Token::Identifier("UserId".into()), tokens[3]);
+
+        let tokens = Tokenizer::new(&sql)
+            .with_identifier_casing(IdentifierCasing::FoldUpper)
+            .tokenize()
+            .unwrap();
+        assert_eq!(Token::Identifier("USERID".into()), tokens[1]);
+        assert_eq!(Token::Identifier("T".into()), tokens[5]);
+    }
+
+    #[test]
+    fn tokenize_unicode_identifiers() {
+        let sql = String::from("SELECT país, 名前2 FROM 顧客 WHERE ciudad = 'München'");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+        assert_eq!(
+            vec![
+                Token::Keyword(Keyword::new("SELECT")),
+                Token::Identifier("país".into()),
+                Token::Comma,
+                Token::Identifier("名前2".into()),
+                Token::Keyword(Keyword::new("FROM")),
+                Token::Identifier("顧客".into()),
+                Token::Keyword(Keyword::new("WHERE")),
+                Token::Identifier("ciudad".into()),
+                Token::Eq,
+                Token::String("München".into()),
+            ],
+            tokens
+        );
+
+        let tokens = Tokenizer::new("SELECT País")
+            .with_identifier_casing(IdentifierCasing::FoldUpper)
+            .tokenize()
+            .unwrap();
+        assert_eq!(Token::Identifier("PAÍS".into()), tokens[1]);
+    }
+
+    #[test]
+    fn tokenize_with_spans() {
+        let sql = String::from("select a<>'é' FROM t");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize_with_spans().unwrap();
+        let texts: Vec<&str> = tokens.iter().map(|(_, span)| &sql[span.range()]).collect();
+        assert_eq!(
+            vec!["select", " ", "a", "<>", "'é'", " ", "FROM", " ", "t"],
+            texts
+        );
+        assert_eq!(Token::Keyword(Keyword::new("SELECT")), tokens[0].0);
+        assert_eq!(Token::String("é".into()), tokens[4].0);
+    }
+
+    #[test]
+    fn tokenize_with_recovery() {
+        let sql = String::from("SELECT a { b #\nFROM 'open");
+        let (tokens, diagnostics) = Tokenizer::new(&sql).tokenize_with_recovery();
+        let tokens: Vec<Token> = tokens
+            .into_iter()
+            .map(|(t, _)| t)
+            .filter(|t| *t != Token::Whitespace)
+            .collect();
+        assert_eq!(
+            vec![
+                Token::Keyword(Keyword::new("SELECT")),
+                Token::Identifier("a".into()),
+                Token::Identifier("b".into()),
+                Token::Keyword(Keyword::new("FROM")),
+            ],
+            tokens
+        );
+        let errors: Vec<(&str, usize, usize, &str)> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.message.as_str(),
+                    d.span.line,
+                    d.span.column,
+                    &sql[d.span.range()],
+                )
+            }).collect();
+        assert_eq!(
+            vec![
+                ("unhandled char '{' in tokenizer", 1, 10, "{"),
+                ("unhandled char '#' in tokenizer", 1, 14, "#"),
+                ("Unterminated string literal 'open", 2, 6, "'open"),
+            ],
+            errors
+        );
+
+        let (tokens, diagnostics) = Tokenizer::new("SELECT 1").tokenize_with_recovery();
+        assert_eq!(3, tokens.len());
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn tokenize_line_and_column() {
+        let sql = String::from("SELECT a,\n  'é' b\nFROM t");
+        let mut tokenizer = Tokenizer::new(&sql);
+        let tokens = tokenizer.tokenize_with_spans().unwrap();
+        let positions: Vec<(usize, usize)> = tokens
+            .iter()
+            .filter(|(t, _)| *t != Token::Whitespace)
+            .map(|(_, span)| (span.line, span.column))
+            .collect();
+        assert_eq!(
+            vec![(1, 1), (1, 8), (1, 9), (2, 3), (2, 7), (3, 1), (3, 6)],
+            positions
+        );
+
+        let sql = String::from("SELECT a\nFROM t WHERE b { 1");
+        let mut tokenizer = Tokenizer::new(&sql);
+        match tokenizer.tokenize() {
+            Err(TokenizerError(msg)) => {
+                assert_eq!("unhandled char '{' in tokenizer at line 2, column 16", msg)
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn tokenize_comments() {
+        let sql = String::from(
+            "SELECT a -- the first column\n\
+             FROM /* a /* nested */ comment */ t--trailing",
+        );
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+        let expected = vec![
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Identifier("a".into()),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Identifier("t".into()),
+        ];
+        compare(expected, tokens);
+
+        let tokens = Tokenizer::new(&sql).with_comments().tokenize().unwrap();
+        let expected = vec![
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Identifier("a".into()),
+            Token::Comment(" the first column"),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Comment(" a /* nested */ comment "),
+            Token::Identifier("t".into()),
+            Token::Comment("trailing"),
+        ];
+        compare(expected, tokens);
+
+        // a single minus or slash is still an operator
+        let tokens = Tokenizer::new("a - b / c").tokenize().unwrap();
+        assert_eq!(Token::Minus, tokens[1]);
+        assert_eq!(Token::Div, tokens[3]);
+
+        assert!(Tokenizer::new("SELECT /* a /* b */").tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_keyword_casing() {
+        let sql = "select a, 'it''s' From t Where a <> X'0F' -- done";
+        let tokens = Tokenizer::new(sql).tokenize_with_spans().unwrap();
+        match tokens[0].0 {
+            Token::Keyword(ref k) => {
+                assert_eq!("SELECT", k.value());
+                assert_eq!("select", k.text());
+            }
+            ref other => panic!("expected a keyword, found {:?}", other),
+        }
+        assert_eq!(Token::Keyword(Keyword::new("FROM")), tokens[7].0);
+        assert_eq!("From", tokens[7].0.to_string());
+
+        let text: String = tokens.iter().map(|&(ref t, _)| t.to_string()).collect();
+        assert_eq!("select a, 'it''s' From t Where a <> X'0F' /* done*/", text);
+    }
+
+    #[test]
+    fn tokenize_hints() {
+        let sql = String::from("SELECT /*+ BROADCAST(t) */ a /* +1 */ FROM t");
+        let tokens = Tokenizer::new(&sql).tokenize().unwrap();
+        let expected = vec![
+            Token::Keyword(Keyword::new("SELECT")),
+            Token::Hint(" BROADCAST(t) "),
+            Token::Identifier("a".into()),
+            Token::Keyword(Keyword::new("FROM")),
+            Token::Identifier("t".into()),
+        ];
+        compare(expected, tokens);
+    }
+
+    #[test]
+    fn tokenize_borrows_text() {
+        let sql = "SELECT name, 'plain', 'it''s', \"a\"\"b\", 1.5, .5 FROM t";
+        let tokens = Tokenizer::new(sql).tokenize().unwrap();
+        let borrowed: Vec<bool> = tokens
+            .iter()
+            .filter_map(|t| match *t {
+                Token::Identifier(ref s) | Token::String(ref s) | Token::Decimal(ref s) => {
+                    Some(match *s {
+                        Cow::Borrowed(_) => true,
+                        Cow::Owned(_) => false,
+                    })
+                }
+                _ => None,
+            }).collect();
+        // only text that is decoded or rewritten is copied
+        assert_eq!(vec![true, true, false, false, true, false, true], borrowed);
+    }
+
     fn compare(expected: Vec<Token>, actual: Vec<Token>) {
         //println!("------------------------------");
         //println!("tokens   = {:?}", actual);
@@ -212,7 +212,7 @@ impl From<Vec<Rc<Array>>> for Array {
 }
 
 /// ScalarValue enumeration
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum ScalarValue {
     Null,
     Boolean(bool),
@@ -307,7 +307,7 @@ macro_rules! primitive_accessor {
         pub fn $NAME(&self) -> Result<$TY> {
             match self {
                 ScalarValue::$VARIANT(v) => Ok(*v),
-                other => Err(ExecutionError::General(format!("Cannot access scalar value {:?} as {}", other, stringify!($VARIANT))))
+                other => Err(DataFusionError::Execution(format!("Cannot access scalar value {:?} as {}", other, stringify!($VARIANT))))
             }
         }
     }
@@ -518,12 +518,46 @@ fn _get_supertype(l: &DataType, r: &DataType) -> Option<DataType> {
     }
 }
 
+/// Describes whether a function always returns the same result for the same arguments, which
+/// determines whether the optimizer may constant fold, deduplicate or push down calls to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Volatility {
+    /// Always returns the same result for the same arguments e.g. `sqrt`
+    Immutable,
+    /// Returns the same result for the same arguments within a single query e.g. `now()`
+    Stable,
+    /// May return a different result on every call e.g. `random()`
+    Volatile,
+}
+
+impl Volatility {
+    /// Returns true if calls can be evaluated once at planning time
+    pub fn is_deterministic(&self) -> bool {
+        *self == Volatility::Immutable
+    }
+}
+
 /// Scalar function
 pub trait ScalarFunction {
     fn name(&self) -> String;
     fn args(&self) -> Vec<Field>;
     fn return_type(&self) -> DataType;
     fn execute(&self, args: &[Value]) -> Result<Value>;
+
+    /// The return type of a call, given the values of the arguments that are literals. This is
+    /// for functions whose return type depends on an argument, such as the fields of a struct.
+    fn return_type_for(&self, _literals: &[Option<ScalarValue>]) -> Result<DataType> {
+        Ok(self.return_type())
+    }
+
+    fn volatility(&self) -> Volatility {
+        Volatility::Immutable
+    }
+
+    /// Returns true if the function returns null whenever any argument is null
+    fn null_propagating(&self) -> bool {
+        false
+    }
 }
 
 /// Aggregate function